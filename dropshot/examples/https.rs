@@ -66,6 +66,7 @@ async fn main() -> Result<(), String> {
         tls: Some(ConfigTls::AsFile {
             cert_file: cert_file.path().to_path_buf(),
             key_file: key_file.path().to_path_buf(),
+            client_auth: None,
         }),
         ..Default::default()
     };
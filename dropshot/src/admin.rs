@@ -0,0 +1,157 @@
+// Copyright 2023 Oxide Computer Company
+//! Support for a separate listener hosting operator-only endpoints
+//!
+//! [`HttpServerStarter::admin_listener()`](crate::HttpServerStarter::admin_listener)
+//! binds a second listener, with its own [`ConfigDropshot`](crate::ConfigDropshot),
+//! that serves a separate `ApiDescription<AdminContext<C>>` alongside the
+//! server's public API.  The two listeners share nothing but the process:
+//! each has its own address, TLS configuration, routes, and server-wide hooks
+//! (e.g., authentication configured through the `ApiDescription`), so
+//! endpoints for health checks, statistics, runtime reconfiguration, and the
+//! like can be kept off the public listener entirely.
+//!
+//! Handlers on the admin listener get an [`AdminContext`], through which they
+//! can inspect and control the public server.
+
+use crate::maintenance::MaintenanceMode;
+use crate::reuseport::AcceptorStats;
+use crate::server::DropshotState;
+use crate::server::ServerContext;
+use crate::settings::ServerSettingsHandle;
+use crate::strict_http::StrictHttpRejections;
+use crate::ConfigLoggingLevel;
+
+use futures::future::BoxFuture;
+use std::fmt;
+use std::net::SocketAddr;
+use std::sync::Arc;
+
+/// Context for requests to an admin listener, providing access to the public
+/// server that the listener was started with
+pub struct AdminContext<C: ServerContext> {
+    server: Arc<DropshotState<C>>,
+}
+
+impl<C: ServerContext> AdminContext<C> {
+    pub(crate) fn new(server: Arc<DropshotState<C>>) -> AdminContext<C> {
+        AdminContext { server }
+    }
+
+    /// Returns the public server's private context.
+    pub fn app_private(&self) -> &C {
+        &self.server.private
+    }
+
+    /// Returns the address of the public server's listener.
+    pub fn local_addr(&self) -> SocketAddr {
+        self.server.local_addr
+    }
+
+    /// Returns true if the public server has begun a graceful shutdown.
+    pub fn is_draining(&self) -> bool {
+        self.server.is_draining()
+    }
+
+    /// Returns the method and path of each endpoint registered with the
+    /// public server.
+    pub fn routes(&self) -> Vec<(http::Method, String)> {
+        self.server
+            .router()
+            .endpoints()
+            .map(|endpoint| (endpoint.method.clone(), endpoint.path.clone()))
+            .collect()
+    }
+
+    /// See [`HttpServer::set_endpoint_log_level()`](crate::HttpServer::set_endpoint_log_level).
+    pub fn set_endpoint_log_level(
+        &self,
+        method: &http::Method,
+        path: &str,
+        level: Option<ConfigLoggingLevel>,
+    ) -> Result<(), String> {
+        self.server.set_endpoint_log_level(method, path, level)
+    }
+
+    /// See [`HttpServer::pause_accepting()`](crate::HttpServer::pause_accepting).
+    pub fn pause_accepting(&self) {
+        self.server.set_accepting(false)
+    }
+
+    /// See [`HttpServer::resume_accepting()`](crate::HttpServer::resume_accepting).
+    pub fn resume_accepting(&self) {
+        self.server.set_accepting(true)
+    }
+
+    /// See [`HttpServer::is_accepting()`](crate::HttpServer::is_accepting).
+    pub fn is_accepting(&self) -> bool {
+        *self.server.accepting.borrow()
+    }
+
+    /// See [`HttpServer::set_maintenance_mode()`](crate::HttpServer::set_maintenance_mode).
+    pub fn set_maintenance_mode(&self, mode: Option<MaintenanceMode>) {
+        self.server.set_maintenance_mode(mode)
+    }
+
+    /// See [`HttpServer::maintenance_mode()`](crate::HttpServer::maintenance_mode).
+    pub fn maintenance_mode(&self) -> Option<MaintenanceMode> {
+        self.server.maintenance.get()
+    }
+
+    /// See [`HttpServer::strict_http_rejections()`](crate::HttpServer::strict_http_rejections).
+    pub fn strict_http_rejections(&self) -> StrictHttpRejections {
+        self.server.strict_http.rejections()
+    }
+
+    /// See [`HttpServer::settings()`](crate::HttpServer::settings).
+    pub fn settings(&self) -> ServerSettingsHandle {
+        self.server.settings.clone()
+    }
+
+    /// See [`HttpServer::acceptor_stats()`](crate::HttpServer::acceptor_stats).
+    pub fn acceptor_stats(&self) -> Vec<AcceptorStats> {
+        self.server.acceptors.stats()
+    }
+
+    /// See [`HttpServer::set_connection_bandwidth_limit()`](crate::HttpServer::set_connection_bandwidth_limit).
+    pub fn set_connection_bandwidth_limit(&self, bytes_per_sec: Option<u64>) {
+        self.server.connection_throttle.set_rate(bytes_per_sec);
+    }
+
+    /// See [`HttpServer::connection_bandwidth_limit()`](crate::HttpServer::connection_bandwidth_limit).
+    pub fn connection_bandwidth_limit(&self) -> Option<u64> {
+        self.server.connection_throttle.rate()
+    }
+
+    /// See [`HttpServer::set_endpoint_bandwidth_limit()`](crate::HttpServer::set_endpoint_bandwidth_limit).
+    pub fn set_endpoint_bandwidth_limit(
+        &self,
+        method: &http::Method,
+        path: &str,
+        bytes_per_sec: Option<u64>,
+    ) -> Result<(), String> {
+        self.server.set_endpoint_bandwidth_limit(method, path, bytes_per_sec)
+    }
+}
+
+impl<C: ServerContext> fmt::Debug for AdminContext<C> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("AdminContext")
+            .field("local_addr", &self.server.local_addr)
+            .finish_non_exhaustive()
+    }
+}
+
+// The admin listener's starter and server are held by the public server's
+// starter and server behind these traits: their concrete types (parameterized
+// by `AdminContext<C>`) would otherwise have to contain themselves.
+
+/// An admin listener that has been bound but not started
+pub(crate) trait AdminStarter: Send {
+    fn start(self: Box<Self>) -> Box<dyn AdminServer>;
+}
+
+/// A running admin listener
+pub(crate) trait AdminServer: Send + Sync {
+    fn local_addr(&self) -> SocketAddr;
+    fn close(self: Box<Self>) -> BoxFuture<'static, Result<(), String>>;
+}
@@ -1,22 +1,48 @@
 // Copyright 2023 Oxide Computer Company
 //! Describes the endpoints and handler functions in your API
 
+use crate::decompress::ContentDecoder;
+use crate::error::HttpResponseError;
+use crate::error_responses::ErrorFormat;
+use crate::error_responses::ErrorResponseCustomizer;
+use crate::expect_continue::ContinueCheck;
 use crate::extractor::RequestExtractor;
+use crate::extractor::SchemaValidators;
+use crate::fallback::FallbackHandler;
+use crate::fallback::FallbackRoute;
 use crate::handler::HttpHandlerFunc;
 use crate::handler::HttpResponse;
 use crate::handler::HttpRouteHandler;
 use crate::handler::RouteHandler;
+use crate::hooks::HookTarget;
+use crate::hooks::RequestHook;
+use crate::idempotency::IdempotencyMode;
+use crate::idempotency::IdempotencyStore;
 use crate::router::route_path_to_segments;
 use crate::router::HttpRouter;
+use crate::router::PathDecoding;
 use crate::router::PathSegment;
-use crate::schema_util::j2oas_schema;
+use crate::router::RouteConflict;
+use crate::router::TrailingSlash;
 use crate::server::ServerContext;
+use crate::tenancy::TenantResolver;
+use crate::type_util::type_is_object_of_scalars;
 use crate::type_util::type_is_scalar;
+use crate::type_util::type_is_scalar_or_array;
+use crate::type_util::type_is_string;
 use crate::type_util::type_is_string_enum;
-use crate::HttpErrorResponseBody;
+use crate::type_util::type_object_properties;
+use crate::ConfigLoggingLevel;
+use crate::CONTENT_TYPE_CBOR;
 use crate::CONTENT_TYPE_JSON;
+use crate::CONTENT_TYPE_MSGPACK;
+use crate::CONTENT_TYPE_MULTIPART_FORM_DATA;
+use crate::CONTENT_TYPE_NDJSON;
 use crate::CONTENT_TYPE_OCTET_STREAM;
+use crate::CONTENT_TYPE_PROTOBUF;
 use crate::CONTENT_TYPE_URL_ENCODED;
+use crate::CONTENT_TYPE_XML;
+use crate::CONTENT_TYPE_YAML;
 
 use http::Method;
 use http::StatusCode;
@@ -25,6 +51,8 @@ use serde::Serialize;
 use std::collections::BTreeMap;
 use std::collections::HashMap;
 use std::collections::HashSet;
+use std::sync::Arc;
+use std::time::Duration;
 
 /// ApiEndpoint represents a single API endpoint associated with an
 /// ApiDescription. It has a handler, HTTP method (e.g. GET, POST), and a path--
@@ -33,11 +61,14 @@ use std::collections::HashSet;
 #[derive(Debug)]
 pub struct ApiEndpoint<Context: ServerContext> {
     pub operation_id: String,
-    pub handler: Box<dyn RouteHandler<Context>>,
+    pub handler: Arc<dyn RouteHandler<Context>>,
     pub method: Method,
     pub path: String,
     pub parameters: Vec<ApiEndpointParameter>,
     pub body_content_type: ApiEndpointBodyContentType,
+    /// Media types accepted for the request body in addition to
+    /// `body_content_type`.  See [`ApiEndpoint::additional_content_types()`].
+    pub additional_body_content_types: Vec<ApiEndpointBodyContentType>,
     pub response: ApiEndpointResponse,
     pub summary: Option<String>,
     pub description: Option<String>,
@@ -45,6 +76,37 @@ pub struct ApiEndpoint<Context: ServerContext> {
     pub extension_mode: ExtensionMode,
     pub visible: bool,
     pub deprecated: bool,
+    /// If present, overrides the level used for request-scoped log entries
+    /// for this endpoint.  See [`crate::HttpServer::set_endpoint_log_level`].
+    pub log_level: Option<ConfigLoggingLevel>,
+    /// Whether requests to this endpoint may carry an idempotency key.  See
+    /// [`crate::idempotency`].
+    pub idempotency: Option<IdempotencyMode>,
+    /// If present, concurrent identical requests to this endpoint are
+    /// coalesced, treating requests as identical if they have the same path,
+    /// query string, and values for these headers.  See
+    /// [`ApiEndpoint::coalesce()`].
+    pub coalesce: Option<Vec<String>>,
+    /// If present, limits the combined rate (in bytes per second) at which
+    /// this endpoint's response bodies are sent.  See
+    /// [`crate::HttpServer::set_endpoint_bandwidth_limit`].
+    pub bandwidth_limit: Option<u64>,
+    /// If present, overrides the server's `request_body_max_bytes` for this
+    /// endpoint
+    pub request_body_max_bytes: Option<usize>,
+    /// Whether this endpoint's responses may be compressed, if the server
+    /// compresses responses (see `ConfigDropshot::compression`)
+    pub compression: bool,
+    /// If present, requests whose handlers take longer than this fail with a
+    /// 503 ("Service Unavailable").  See [`ApiEndpoint::timeout()`].
+    pub timeout: Option<Duration>,
+    /// Where the endpoint was defined (`"file:line"`), if known, for
+    /// describing conflicts between routes.  The `endpoint` macro fills this
+    /// in.
+    pub source_location: Option<String>,
+    /// validators for the types that the handler's extractors deserialize,
+    /// used when the server's `schema_validation` is enabled
+    pub(crate) schema_validators: Arc<SchemaValidators>,
 }
 
 impl<'a, Context: ServerContext> ApiEndpoint<Context> {
@@ -64,7 +126,22 @@ impl<'a, Context: ServerContext> ApiEndpoint<Context> {
             ApiEndpointBodyContentType::from_mime_type(content_type)
                 .expect("unsupported mime type");
         let func_parameters = FuncParams::metadata(body_content_type.clone());
-        let response = ResponseType::response_metadata();
+        let mut schema_validators = SchemaValidators::default();
+        FuncParams::schema_validators(&mut schema_validators);
+        let mut response = ResponseType::response_metadata();
+        response.additional_responses.extend(
+            <<HandlerType as HttpHandlerFunc<
+                Context,
+                FuncParams,
+                ResponseType,
+            >>::Error as HttpResponseError>::error_responses(),
+        );
+        response.error_schema = <<HandlerType as HttpHandlerFunc<
+            Context,
+            FuncParams,
+            ResponseType,
+        >>::Error as HttpResponseError>::error_schema(
+        );
         ApiEndpoint {
             operation_id,
             handler: HttpRouteHandler::new(handler),
@@ -72,6 +149,7 @@ impl<'a, Context: ServerContext> ApiEndpoint<Context> {
             path: path.to_string(),
             parameters: func_parameters.parameters,
             body_content_type,
+            additional_body_content_types: vec![],
             response,
             summary: None,
             description: None,
@@ -79,6 +157,15 @@ impl<'a, Context: ServerContext> ApiEndpoint<Context> {
             extension_mode: func_parameters.extension_mode,
             visible: true,
             deprecated: false,
+            log_level: None,
+            idempotency: None,
+            coalesce: None,
+            bandwidth_limit: None,
+            request_body_max_bytes: None,
+            compression: true,
+            timeout: None,
+            source_location: None,
+            schema_validators: Arc::new(schema_validators),
         }
     }
 
@@ -106,6 +193,84 @@ impl<'a, Context: ServerContext> ApiEndpoint<Context> {
         self.deprecated = deprecated;
         self
     }
+
+    pub fn log_level(mut self, log_level: ConfigLoggingLevel) -> Self {
+        self.log_level = Some(log_level);
+        self
+    }
+
+    pub fn idempotency(mut self, idempotency: IdempotencyMode) -> Self {
+        self.idempotency = Some(idempotency);
+        self
+    }
+
+    /// Coalesces concurrent identical GET requests to this endpoint: while a
+    /// request is being handled, identical requests wait for it to complete
+    /// and receive a copy of its response rather than invoking the handler
+    /// again.  Requests are identical if they have the same path, query
+    /// string, and values for each of the given headers, which should include
+    /// any header that the response depends on (e.g., `authorization`).
+    ///
+    /// Responses to coalesced requests are buffered in memory, so this is only
+    /// appropriate for endpoints whose responses are reasonably small.
+    pub fn coalesce(mut self, headers: &[&str]) -> Self {
+        self.coalesce =
+            Some(headers.iter().map(|h| h.to_ascii_lowercase()).collect());
+        self
+    }
+
+    pub fn bandwidth_limit(mut self, bytes_per_sec: u64) -> Self {
+        self.bandwidth_limit = Some(bytes_per_sec);
+        self
+    }
+
+    /// Accepts request bodies of each of the given media types as well as the
+    /// one the endpoint was created with.  [`TypedBody`](crate::TypedBody)
+    /// parses each request's body according to its `Content-Type`, and the
+    /// OpenAPI document lists every accepted type for the body.
+    ///
+    /// Panics if any of the media types is not supported.
+    pub fn additional_content_types(mut self, content_types: &[&str]) -> Self {
+        self.additional_body_content_types.extend(content_types.iter().map(
+            |content_type| {
+                ApiEndpointBodyContentType::from_mime_type(content_type)
+                    .expect("unsupported mime type")
+            },
+        ));
+        self
+    }
+
+    /// Limits the size of this endpoint's request bodies to `max_bytes`
+    /// instead of the server's `request_body_max_bytes`.
+    pub fn request_body_max_bytes(mut self, max_bytes: usize) -> Self {
+        self.request_body_max_bytes = Some(max_bytes);
+        self
+    }
+
+    /// Determines whether this endpoint's responses may be compressed (if the
+    /// server is configured to compress responses at all).  Endpoints whose
+    /// responses are already compressed, or that mix secrets with
+    /// attacker-controlled data in their responses, should opt out.
+    pub fn compression(mut self, compression: bool) -> Self {
+        self.compression = compression;
+        self
+    }
+
+    /// Limits how long this endpoint's handler may run (including extracting
+    /// its arguments).  A handler that's still running when `timeout` elapses
+    /// is cancelled (dropped) and the request fails with a 503 ("Service
+    /// Unavailable").
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    /// Records where this endpoint was defined (e.g., `"src/api.rs:42"`), to
+    /// be reported if its route conflicts with another's.
+    pub fn source_location(mut self, source_location: &str) -> Self {
+        self.source_location = Some(source_location.to_string());
+        self
+    }
 }
 
 /// ApiEndpointParameter represents the discrete path and query parameters for a
@@ -137,6 +302,9 @@ impl ApiEndpointParameter {
                 ApiEndpointParameterLocation::Query => {
                     ApiEndpointParameterMetadata::Query(name)
                 }
+                ApiEndpointParameterLocation::Header => {
+                    ApiEndpointParameterMetadata::Header(name)
+                }
             },
             description,
             required,
@@ -165,16 +333,18 @@ impl ApiEndpointParameter {
 pub enum ApiEndpointParameterLocation {
     Path,
     Query,
+    Header,
 }
 
 #[derive(Debug, Clone)]
 pub enum ApiEndpointParameterMetadata {
     Path(String),
     Query(String),
+    Header(String),
     Body(ApiEndpointBodyContentType),
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub enum ApiEndpointBodyContentType {
     /// application/octet-stream
     Bytes,
@@ -182,6 +352,20 @@ pub enum ApiEndpointBodyContentType {
     Json,
     /// application/x-www-form-urlencoded
     UrlEncoded,
+    /// multipart/form-data
+    MultipartFormData,
+    /// application/msgpack
+    MsgPack,
+    /// application/cbor
+    Cbor,
+    /// application/x-protobuf
+    Protobuf,
+    /// application/xml
+    Xml,
+    /// application/yaml
+    Yaml,
+    /// application/x-ndjson
+    Ndjson,
 }
 
 impl Default for ApiEndpointBodyContentType {
@@ -196,6 +380,13 @@ impl ApiEndpointBodyContentType {
             Self::Bytes => CONTENT_TYPE_OCTET_STREAM,
             Self::Json => CONTENT_TYPE_JSON,
             Self::UrlEncoded => CONTENT_TYPE_URL_ENCODED,
+            Self::MultipartFormData => CONTENT_TYPE_MULTIPART_FORM_DATA,
+            Self::MsgPack => CONTENT_TYPE_MSGPACK,
+            Self::Cbor => CONTENT_TYPE_CBOR,
+            Self::Protobuf => CONTENT_TYPE_PROTOBUF,
+            Self::Xml => CONTENT_TYPE_XML,
+            Self::Yaml => CONTENT_TYPE_YAML,
+            Self::Ndjson => CONTENT_TYPE_NDJSON,
         }
     }
 
@@ -204,6 +395,20 @@ impl ApiEndpointBodyContentType {
             CONTENT_TYPE_OCTET_STREAM => Ok(Self::Bytes),
             CONTENT_TYPE_JSON => Ok(Self::Json),
             CONTENT_TYPE_URL_ENCODED => Ok(Self::UrlEncoded),
+            CONTENT_TYPE_MULTIPART_FORM_DATA => Ok(Self::MultipartFormData),
+            // "application/x-msgpack" predates the registered media type but
+            // is still widely used.
+            CONTENT_TYPE_MSGPACK | "application/x-msgpack" => Ok(Self::MsgPack),
+            CONTENT_TYPE_CBOR => Ok(Self::Cbor),
+            CONTENT_TYPE_PROTOBUF => Ok(Self::Protobuf),
+            // Older clients often use "text/xml" for the same documents.
+            CONTENT_TYPE_XML | "text/xml" => Ok(Self::Xml),
+            // "application/yaml" was only registered in 2024, so clients use a
+            // variety of unofficial types as well.
+            CONTENT_TYPE_YAML | "application/x-yaml" | "text/yaml" => {
+                Ok(Self::Yaml)
+            }
+            CONTENT_TYPE_NDJSON => Ok(Self::Ndjson),
             _ => Err(mime_type.to_string()),
         }
     }
@@ -221,9 +426,22 @@ pub struct ApiEndpointHeader {
 #[derive(Debug, Default)]
 pub struct ApiEndpointResponse {
     pub schema: Option<ApiSchemaGenerator>,
+    /// media type of the response body (JSON, if not specified)
+    pub content_type: Option<String>,
+    /// other media types in which the response body may be sent
+    pub additional_content_types: Vec<String>,
     pub headers: Vec<ApiEndpointHeader>,
     pub success: Option<StatusCode>,
     pub description: Option<String>,
+    /// other status codes (and their descriptions) with which the endpoint
+    /// may respond, such as 304 ("Not Modified") for conditional requests
+    pub additional_responses: Vec<(StatusCode, String)>,
+    /// other status codes (and their descriptions) with which the endpoint
+    /// may send the same body and headers as with `success`
+    pub alternate_statuses: Vec<(StatusCode, String)>,
+    /// schema of the bodies of error responses, if not the usual error body
+    /// (see [`crate::HttpResponseError::error_schema`])
+    pub error_schema: Option<ApiSchemaGenerator>,
 }
 
 /// Wrapper for both dynamically generated and pre-generated schemas.
@@ -255,8 +473,23 @@ impl std::fmt::Debug for ApiSchemaGenerator {
 /// generate an OpenAPI spec or to run an HTTP server implementing the API.
 pub struct ApiDescription<Context: ServerContext> {
     /// In practice, all the information we need is encoded in the router.
-    router: HttpRouter<Context>,
-    tag_config: TagConfig,
+    pub(crate) router: HttpRouter<Context>,
+    pub(crate) tag_config: TagConfig,
+    /// Records the outcomes of requests with idempotency keys
+    pub(crate) idempotency_store: Option<Arc<dyn IdempotencyStore>>,
+    /// Identifies the tenant of each request before it's routed
+    pub(crate) tenant_resolver: Option<Arc<dyn TenantResolver>>,
+    /// Supplies the bodies of error responses that Dropshot generates itself
+    pub(crate) error_response_customizer:
+        Option<Arc<dyn ErrorResponseCustomizer>>,
+    /// Format of the bodies of error responses
+    pub(crate) error_format: ErrorFormat,
+    /// Decides whether requests that ask to continue may send their bodies
+    pub(crate) continue_check: Option<Arc<dyn ContinueCheck<Context>>>,
+    /// Decoders for request bodies' content codings, beyond Dropshot's own
+    pub(crate) content_decoders: Vec<(String, Arc<dyn ContentDecoder>)>,
+    /// Run before and after the handlers of the endpoints they're attached to
+    pub(crate) hooks: Vec<(HookTarget, Arc<dyn RequestHook<Context>>)>,
 }
 
 impl<Context: ServerContext> ApiDescription<Context> {
@@ -264,6 +497,13 @@ impl<Context: ServerContext> ApiDescription<Context> {
         ApiDescription {
             router: HttpRouter::new(),
             tag_config: TagConfig::default(),
+            idempotency_store: None,
+            tenant_resolver: None,
+            error_response_customizer: None,
+            error_format: ErrorFormat::default(),
+            continue_check: None,
+            content_decoders: Vec::new(),
+            hooks: Vec::new(),
         }
     }
 
@@ -272,22 +512,291 @@ impl<Context: ServerContext> ApiDescription<Context> {
         self
     }
 
+    /// Sets the store used to record the outcomes of requests to endpoints
+    /// that accept idempotency keys.  This must be called before registering
+    /// any such endpoints.  See [`crate::idempotency`].
+    pub fn idempotency_store(
+        mut self,
+        idempotency_store: Arc<dyn IdempotencyStore>,
+    ) -> Self {
+        self.idempotency_store = Some(idempotency_store);
+        self
+    }
+
+    /// Sets the resolver used to identify the tenant on whose behalf each
+    /// request is made.  See [`TenantResolver`].
+    pub fn tenant_resolver(
+        mut self,
+        tenant_resolver: Arc<dyn TenantResolver>,
+    ) -> Self {
+        self.tenant_resolver = Some(tenant_resolver);
+        self
+    }
+
+    /// Sets how requests whose paths end with a "/" are routed.  By default,
+    /// the trailing "/" is ignored.  See [`TrailingSlash`].
+    pub fn trailing_slash(mut self, trailing_slash: TrailingSlash) -> Self {
+        self.router.set_trailing_slash(trailing_slash);
+        self
+    }
+
+    /// Sets how the segments of request paths are percent-decoded before
+    /// they're routed (e.g., whether `+` decodes to a space).  See
+    /// [`PathDecoding`].
+    pub fn path_decoding(mut self, path_decoding: PathDecoding) -> Self {
+        self.router.set_path_decoding(path_decoding);
+        self
+    }
+
+    /// Sets whether the literal segments of request paths match those of
+    /// registered paths regardless of case (e.g., so that `"/Projects/p1"`
+    /// matches `"/projects/{id}"`).  By default, they must match exactly.  The
+    /// values of path variables and the OpenAPI document are unaffected.
+    pub fn case_insensitive_paths(mut self, case_insensitive: bool) -> Self {
+        self.router.set_case_insensitive(case_insensitive);
+        self
+    }
+
+    /// Sets the handler for requests whose paths match no route, which
+    /// otherwise fail with a 404 ("Not Found") error.  See
+    /// [`FallbackHandler`].  (The fallback handler of an API that's added to
+    /// another with [`ApiDescription::register_prefix()`] is ignored.)
+    pub fn fallback_handler(
+        mut self,
+        fallback_handler: Arc<dyn FallbackHandler<Context>>,
+    ) -> Self {
+        self.router
+            .set_fallback(Arc::new(FallbackRoute::new(fallback_handler)));
+        self
+    }
+
+    /// Sets the customizer used to supply the bodies of the error responses
+    /// that Dropshot generates itself (e.g., for requests that match no
+    /// route).  See [`ErrorResponseCustomizer`].
+    pub fn error_response_customizer(
+        mut self,
+        error_response_customizer: Arc<dyn ErrorResponseCustomizer>,
+    ) -> Self {
+        self.error_response_customizer = Some(error_response_customizer);
+        self
+    }
+
+    /// Sets the format of the bodies of error responses, both those that the
+    /// server sends and those described in the OpenAPI document.  See
+    /// [`ErrorFormat`].
+    pub fn error_format(mut self, error_format: ErrorFormat) -> Self {
+        self.error_format = error_format;
+        self
+    }
+
+    /// Sets the check used to decide whether requests that ask to continue
+    /// (`Expect: 100-continue`) may send their bodies.  See
+    /// [`ContinueCheck`].
+    pub fn continue_check(
+        mut self,
+        continue_check: Arc<dyn ContinueCheck<Context>>,
+    ) -> Self {
+        self.continue_check = Some(continue_check);
+        self
+    }
+
+    /// Registers the decoder used for request bodies that use the content
+    /// coding `coding` (e.g., `"zstd"`), replacing Dropshot's own decoder for
+    /// that coding, if it has one.  See [`ContentDecoder`].
+    pub fn content_decoder(
+        mut self,
+        coding: &str,
+        decoder: Arc<dyn ContentDecoder>,
+    ) -> Self {
+        self.content_decoders.push((coding.to_string(), decoder));
+        self
+    }
+
+    /// Attaches `hook` to the endpoint registered (now or later) with `method`
+    /// and the path template `path` (e.g., `"/projects/{id}"`).  See
+    /// [`RequestHook`].
+    pub fn route_hook(
+        mut self,
+        method: Method,
+        path: &str,
+        hook: Arc<dyn RequestHook<Context>>,
+    ) -> Self {
+        self.hooks.push((HookTarget::Route(method, path.to_string()), hook));
+        self
+    }
+
+    /// Attaches `hook` to all endpoints with the tag `tag`, whether they're
+    /// registered before or after this call.  See [`RequestHook`].
+    pub fn tag_hook(
+        mut self,
+        tag: &str,
+        hook: Arc<dyn RequestHook<Context>>,
+    ) -> Self {
+        self.hooks.push((HookTarget::Tag(tag.to_string()), hook));
+        self
+    }
+
     /// Register a new API endpoint.
     pub fn register<T>(&mut self, endpoint: T) -> Result<(), String>
+    where
+        T: Into<ApiEndpoint<Context>>,
+    {
+        self.try_register(endpoint).map_err(|error| error.to_string())
+    }
+
+    /// Registers a new API endpoint like [`ApiDescription::register()`], but
+    /// describes a failure with a [`RegisterError`], which identifies both
+    /// endpoints involved in a conflict between routes.
+    pub fn try_register<T>(&mut self, endpoint: T) -> Result<(), RegisterError>
     where
         T: Into<ApiEndpoint<Context>>,
     {
         let e = endpoint.into();
 
-        self.validate_tags(&e)?;
-        self.validate_path_parameters(&e)?;
-        self.validate_named_parameters(&e)?;
+        let invalid = |message| RegisterError::Invalid {
+            operation_id: e.operation_id.clone(),
+            message,
+        };
+        self.validate_tags(&e).map_err(invalid)?;
+        self.validate_path_parameters(&e).map_err(invalid)?;
+        self.validate_named_parameters(&e).map_err(invalid)?;
+        self.validate_idempotency(&e).map_err(invalid)?;
+        self.validate_coalesce(&e).map_err(invalid)?;
+        self.validate_schemas(&e).map_err(invalid)?;
+
+        self.router.try_insert(e).map_err(RegisterError::Conflict)
+    }
+
+    /// Registers each of `endpoints`, as with
+    /// [`ApiDescription::try_register()`], and reports all of the failures
+    /// rather than just the first.  The endpoints that can be registered are,
+    /// even if others can't.
+    pub fn register_all<I, T>(
+        &mut self,
+        endpoints: I,
+    ) -> Result<(), Vec<RegisterError>>
+    where
+        I: IntoIterator<Item = T>,
+        T: Into<ApiEndpoint<Context>>,
+    {
+        let errors = endpoints
+            .into_iter()
+            .filter_map(|endpoint| self.try_register(endpoint).err())
+            .collect::<Vec<_>>();
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(errors)
+        }
+    }
+
+    /// Registers all of the endpoints of `api` under the path `prefix` (e.g.,
+    /// `"/v1"`), so that a large API can be composed from separately defined
+    /// parts.  The endpoints are validated as though they were registered
+    /// individually with their prefixed paths.  `prefix` must begin with a
+    /// '/' and consist of literal segments only.
+    ///
+    /// The definitions of `api`'s tags are added to those of this API (which
+    /// take precedence), and its hooks are attached to its endpoints at their
+    /// new paths.  Its other server-wide settings (such as its error format)
+    /// are ignored.
+    pub fn register_prefix(
+        &mut self,
+        prefix: &str,
+        api: ApiDescription<Context>,
+    ) -> Result<(), String> {
+        if !prefix.starts_with('/') {
+            return Err(format!(
+                "path prefix \"{}\" must begin with a '/'",
+                prefix
+            ));
+        }
+        let prefix = prefix.trim_end_matches('/');
+        let segments = match prefix {
+            "" => Vec::new(),
+            _ => route_path_to_segments(prefix),
+        };
+        for segment in segments {
+            if !matches!(PathSegment::from(segment), PathSegment::Literal(_)) {
+                return Err(format!(
+                    "path prefix \"{}\" may not contain variables",
+                    prefix
+                ));
+            }
+        }
+
+        for (name, details) in api.tag_config.tag_definitions {
+            self.tag_config.tag_definitions.entry(name).or_insert(details);
+        }
+
+        let prefixed = |path: &str| match path {
+            "/" if prefix.is_empty() => String::from("/"),
+            "/" => prefix.to_string(),
+            path => format!("{}{}", prefix, path),
+        };
+        for (target, hook) in api.hooks {
+            let target = match target {
+                HookTarget::Route(method, path) => {
+                    HookTarget::Route(method, prefixed(&path))
+                }
+                HookTarget::Tag(tag) => HookTarget::Tag(tag),
+            };
+            self.hooks.push((target, hook));
+        }
+
+        let mut endpoints = api.router.into_endpoints();
+        endpoints.sort_by(|a, b| {
+            (&a.path, a.method.as_str()).cmp(&(&b.path, b.method.as_str()))
+        });
+        for mut endpoint in endpoints {
+            endpoint.path = prefixed(&endpoint.path);
+            self.register(endpoint)?;
+        }
 
-        self.router.insert(e);
+        Ok(())
+    }
+
+    /// Validate that there's somewhere to record the outcomes of requests with
+    /// idempotency keys.
+    fn validate_idempotency(
+        &self,
+        e: &ApiEndpoint<Context>,
+    ) -> Result<(), String> {
+        if e.idempotency.is_some() && self.idempotency_store.is_none() {
+            return Err(format!(
+                "endpoint {} accepts idempotency keys, but no idempotency \
+                 store has been configured",
+                e.operation_id
+            ));
+        }
+        Ok(())
+    }
 
+    /// Validate that only GET requests are coalesced.
+    fn validate_coalesce(
+        &self,
+        e: &ApiEndpoint<Context>,
+    ) -> Result<(), String> {
+        if e.coalesce.is_some() && e.method != Method::GET {
+            return Err(format!(
+                "endpoint {} coalesces requests, but only GET requests may \
+                 be coalesced",
+                e.operation_id
+            ));
+        }
         Ok(())
     }
 
+    /// Validate that the schemas of the types that the endpoint's extractors
+    /// deserialize can be used to validate requests (e.g., that their
+    /// patterns are regular expressions that we support).
+    fn validate_schemas(&self, e: &ApiEndpoint<Context>) -> Result<(), String> {
+        match e.schema_validators.errors() {
+            [] => Ok(()),
+            errors => Err(errors.join("; ")),
+        }
+    }
+
     /// Validate that the tags conform to the tags policy.
     fn validate_tags(&self, e: &ApiEndpoint<Context>) -> Result<(), String> {
         // Don't care about endpoints that don't appear in the OpenAPI
@@ -328,6 +837,7 @@ impl<Context: ServerContext> ApiDescription<Context> {
             .iter()
             .filter_map(|segment| match PathSegment::from(segment) {
                 PathSegment::VarnameSegment(v) => Some(v),
+                PathSegment::VarnamePattern(v, _) => Some(v),
                 PathSegment::VarnameWildcard(v) => Some(v),
                 PathSegment::Literal(_) => None,
             })
@@ -376,7 +886,10 @@ impl<Context: ServerContext> ApiDescription<Context> {
 
     /// Validate that named parameters have appropriate types and there are no
     /// duplicates. Parameters must have scalar types except in the case of the
-    /// received for a wildcard path which must be an array of String.
+    /// received for a wildcard path which must be an array of String (its
+    /// segments) or a String (the raw remainder of the path), and of query
+    /// parameters, which may also be arrays of scalars or objects whose
+    /// properties are scalars (or arrays of them).
     fn validate_named_parameters(
         &self,
         e: &ApiEndpoint<Context>,
@@ -390,7 +903,8 @@ impl<Context: ServerContext> ApiDescription<Context> {
             .filter_map(|segment| {
                 let seg = PathSegment::from(segment);
                 match seg {
-                    PathSegment::VarnameSegment(v) => {
+                    PathSegment::VarnameSegment(v)
+                    | PathSegment::VarnamePattern(v, _) => {
                         Some((v, SegmentOrWildcard::Segment))
                     }
                     PathSegment::VarnameWildcard(v) => {
@@ -402,10 +916,12 @@ impl<Context: ServerContext> ApiDescription<Context> {
             .collect::<BTreeMap<_, _>>();
 
         for param in &e.parameters {
-            // Skip anything that's not a path or query parameter (i.e. body)
+            // Skip anything that's not a path, query, or header parameter
+            // (i.e. body)
             match &param.metadata {
                 ApiEndpointParameterMetadata::Path(_)
-                | ApiEndpointParameterMetadata::Query(_) => (),
+                | ApiEndpointParameterMetadata::Query(_)
+                | ApiEndpointParameterMetadata::Header(_) => (),
                 _ => continue,
             }
             // Only body parameters should have unresolved schemas
@@ -423,7 +939,19 @@ impl<Context: ServerContext> ApiDescription<Context> {
                             type_is_scalar(name, schema, dependencies)?;
                         }
                         Some(SegmentOrWildcard::Wildcard) => {
-                            type_is_string_enum(name, schema, dependencies)?;
+                            // A wildcard may be consumed as its segments or as
+                            // the raw remainder of the path.
+                            type_is_string_enum(name, schema, dependencies)
+                                .or_else(|_| {
+                                    type_is_string(name, schema, dependencies)
+                                })
+                                .map_err(|_| {
+                                    format!(
+                                        "the parameter '{}' must be an array \
+                                         of strings or a string",
+                                        name
+                                    )
+                                })?;
                         }
                         None => {
                             panic!("all path variables should be accounted for")
@@ -438,6 +966,13 @@ impl<Context: ServerContext> ApiDescription<Context> {
                             name
                         ));
                     }
+                    if type_object_properties(schema, dependencies).is_some() {
+                        type_is_object_of_scalars(name, schema, dependencies)?;
+                    } else {
+                        type_is_scalar_or_array(name, schema, dependencies)?;
+                    }
+                }
+                ApiEndpointParameterMetadata::Header(ref name) => {
                     type_is_scalar(name, schema, dependencies)?;
                 }
                 _ => (),
@@ -447,395 +982,15 @@ impl<Context: ServerContext> ApiDescription<Context> {
         Ok(())
     }
 
-    /// Build the OpenAPI definition describing this API.  Returns an
-    /// [`OpenApiDefinition`] which can be used to specify the contents of the
-    /// definition and select an output format.
-    ///
-    /// The arguments to this function will be used for the mandatory `title` and
-    /// `version` properties that the `Info` object in an OpenAPI definition must
-    /// contain.
-    pub fn openapi<S1, S2>(
+    /// Returns an iterator over the registered endpoints, ordered by path and
+    /// then by method.  Each [`ApiEndpoint`] describes its method, path
+    /// template, operation id, tags, and parameters, among other things, so
+    /// that consumers can list an API's routes (e.g., when the server starts)
+    /// without generating its OpenAPI document.
+    pub fn endpoints(
         &self,
-        title: S1,
-        version: S2,
-    ) -> OpenApiDefinition<Context>
-    where
-        S1: AsRef<str>,
-        S2: AsRef<str>,
-    {
-        OpenApiDefinition::new(self, title.as_ref(), version.as_ref())
-    }
-
-    /// Internal routine for constructing the OpenAPI definition describing this
-    /// API in its JSON form.
-    fn gen_openapi(&self, info: openapiv3::Info) -> openapiv3::OpenAPI {
-        let mut openapi = openapiv3::OpenAPI::default();
-
-        openapi.openapi = "3.0.3".to_string();
-        openapi.info = info;
-
-        // Gather up the ad hoc tags from endpoints
-        let endpoint_tags = (&self.router)
-            .into_iter()
-            .flat_map(|(_, _, endpoint)| {
-                endpoint.tags.iter().filter(|tag| {
-                    !self.tag_config.tag_definitions.contains_key(*tag)
-                })
-            })
-            .cloned()
-            .collect::<HashSet<_>>()
-            .into_iter()
-            .map(|tag| openapiv3::Tag { name: tag, ..Default::default() });
-
-        // Bundle those with the explicit tags provided by the consumer
-        openapi.tags = self
-            .tag_config
-            .tag_definitions
-            .iter()
-            .map(|(name, details)| openapiv3::Tag {
-                name: name.clone(),
-                description: details.description.clone(),
-                external_docs: details.external_docs.as_ref().map(|e| {
-                    openapiv3::ExternalDocumentation {
-                        description: e.description.clone(),
-                        url: e.url.clone(),
-                        ..Default::default()
-                    }
-                }),
-                ..Default::default()
-            })
-            .chain(endpoint_tags)
-            .collect();
-
-        // Sort the tags for stability
-        openapi.tags.sort_by(|a, b| a.name.cmp(&b.name));
-
-        let settings = schemars::gen::SchemaSettings::openapi3();
-        let mut generator = schemars::gen::SchemaGenerator::new(settings);
-        let mut definitions =
-            indexmap::IndexMap::<String, schemars::schema::Schema>::new();
-
-        for (path, method, endpoint) in &self.router {
-            if !endpoint.visible {
-                continue;
-            }
-            let path = openapi.paths.paths.entry(path).or_insert(
-                openapiv3::ReferenceOr::Item(openapiv3::PathItem::default()),
-            );
-
-            let pathitem = match path {
-                openapiv3::ReferenceOr::Item(ref mut item) => item,
-                _ => panic!("reference not expected"),
-            };
-
-            let method_ref = match &method[..] {
-                "GET" => &mut pathitem.get,
-                "PUT" => &mut pathitem.put,
-                "POST" => &mut pathitem.post,
-                "DELETE" => &mut pathitem.delete,
-                "OPTIONS" => &mut pathitem.options,
-                "HEAD" => &mut pathitem.head,
-                "PATCH" => &mut pathitem.patch,
-                "TRACE" => &mut pathitem.trace,
-                other => panic!("unexpected method `{}`", other),
-            };
-            let mut operation = openapiv3::Operation::default();
-            operation.operation_id = Some(endpoint.operation_id.clone());
-            operation.summary = endpoint.summary.clone();
-            operation.description = endpoint.description.clone();
-            operation.tags = endpoint.tags.clone();
-            operation.deprecated = endpoint.deprecated;
-
-            operation.parameters = endpoint
-                .parameters
-                .iter()
-                .filter_map(|param| {
-                    let (name, location) = match &param.metadata {
-                        ApiEndpointParameterMetadata::Body(_) => return None,
-                        ApiEndpointParameterMetadata::Path(name) => {
-                            (name, ApiEndpointParameterLocation::Path)
-                        }
-                        ApiEndpointParameterMetadata::Query(name) => {
-                            (name, ApiEndpointParameterLocation::Query)
-                        }
-                    };
-
-                    let schema = match &param.schema {
-                        ApiSchemaGenerator::Static { schema, dependencies } => {
-                            definitions.extend(dependencies.clone());
-                            j2oas_schema(None, schema)
-                        }
-                        _ => {
-                            unimplemented!("this may happen for complex types")
-                        }
-                    };
-
-                    let parameter_data = openapiv3::ParameterData {
-                        name: name.clone(),
-                        description: param.description.clone(),
-                        required: param.required,
-                        deprecated: None,
-                        format: openapiv3::ParameterSchemaOrContent::Schema(
-                            schema,
-                        ),
-                        example: None,
-                        examples: indexmap::IndexMap::new(),
-                        extensions: indexmap::IndexMap::new(),
-                        explode: None,
-                    };
-                    match location {
-                        ApiEndpointParameterLocation::Query => {
-                            Some(openapiv3::ReferenceOr::Item(
-                                openapiv3::Parameter::Query {
-                                    parameter_data: parameter_data,
-                                    allow_reserved: false,
-                                    style: openapiv3::QueryStyle::Form,
-                                    allow_empty_value: None,
-                                },
-                            ))
-                        }
-                        ApiEndpointParameterLocation::Path => {
-                            Some(openapiv3::ReferenceOr::Item(
-                                openapiv3::Parameter::Path {
-                                    parameter_data: parameter_data,
-                                    style: openapiv3::PathStyle::Simple,
-                                },
-                            ))
-                        }
-                    }
-                })
-                .collect::<Vec<_>>();
-
-            operation.request_body = endpoint
-                .parameters
-                .iter()
-                .filter_map(|param| {
-                    let mime_type = match &param.metadata {
-                        ApiEndpointParameterMetadata::Body(ct) => {
-                            ct.mime_type()
-                        }
-                        _ => return None,
-                    };
-
-                    let (name, js) = match &param.schema {
-                        ApiSchemaGenerator::Gen { name, schema } => {
-                            (Some(name()), schema(&mut generator))
-                        }
-                        ApiSchemaGenerator::Static { schema, dependencies } => {
-                            definitions.extend(dependencies.clone());
-                            (None, schema.as_ref().clone())
-                        }
-                    };
-                    let schema = j2oas_schema(name.as_ref(), &js);
-
-                    let mut content = indexmap::IndexMap::new();
-                    content.insert(
-                        mime_type.to_string(),
-                        openapiv3::MediaType {
-                            schema: Some(schema),
-                            ..Default::default()
-                        },
-                    );
-
-                    Some(openapiv3::ReferenceOr::Item(openapiv3::RequestBody {
-                        content: content,
-                        required: true,
-                        ..Default::default()
-                    }))
-                })
-                .next();
-
-            match endpoint.extension_mode {
-                ExtensionMode::None => {}
-                ExtensionMode::Paginated => {
-                    operation.extensions.insert(
-                        crate::pagination::PAGINATION_EXTENSION.to_string(),
-                        serde_json::json! {true},
-                    );
-                }
-                ExtensionMode::Websocket => {
-                    operation.extensions.insert(
-                        crate::websocket::WEBSOCKET_EXTENSION.to_string(),
-                        serde_json::json!({}),
-                    );
-                }
-            }
-
-            let response = if let Some(schema) = &endpoint.response.schema {
-                let (name, js) = match schema {
-                    ApiSchemaGenerator::Gen { name, schema } => {
-                        (Some(name()), schema(&mut generator))
-                    }
-                    ApiSchemaGenerator::Static { schema, dependencies } => {
-                        definitions.extend(dependencies.clone());
-                        (None, schema.as_ref().clone())
-                    }
-                };
-                let mut content = indexmap::IndexMap::new();
-                if !is_empty(&js) {
-                    content.insert(
-                        CONTENT_TYPE_JSON.to_string(),
-                        openapiv3::MediaType {
-                            schema: Some(j2oas_schema(name.as_ref(), &js)),
-                            ..Default::default()
-                        },
-                    );
-                }
-
-                let headers = endpoint
-                    .response
-                    .headers
-                    .iter()
-                    .map(|header| {
-                        let schema = match &header.schema {
-                            ApiSchemaGenerator::Static {
-                                schema,
-                                dependencies,
-                            } => {
-                                definitions.extend(dependencies.clone());
-                                j2oas_schema(None, schema)
-                            }
-                            _ => {
-                                unimplemented!(
-                                    "this may happen for complex types"
-                                )
-                            }
-                        };
-
-                        (
-                            header.name.clone(),
-                            openapiv3::ReferenceOr::Item(openapiv3::Header {
-                                description: header.description.clone(),
-                                style: openapiv3::HeaderStyle::Simple,
-                                required: header.required,
-                                deprecated: None,
-                                format:
-                                    openapiv3::ParameterSchemaOrContent::Schema(
-                                        schema,
-                                    ),
-                                example: None,
-                                examples: indexmap::IndexMap::new(),
-                                extensions: indexmap::IndexMap::new(),
-                            }),
-                        )
-                    })
-                    .collect();
-
-                let response = openapiv3::Response {
-                    description: if let Some(description) =
-                        &endpoint.response.description
-                    {
-                        description.clone()
-                    } else {
-                        // TODO: perhaps we should require even free-form
-                        // responses to have a description since it's required
-                        // by OpenAPI.
-                        "".to_string()
-                    },
-                    content,
-                    headers,
-                    ..Default::default()
-                };
-                response
-            } else {
-                // If no schema was specified, the response is hand-rolled. In
-                // this case we'll fall back to the default response type which
-                // we assume to be inclusive of errors. The media type and
-                // and schema will similarly be maximally permissive.
-                let mut content = indexmap::IndexMap::new();
-                content.insert(
-                    "*/*".to_string(),
-                    openapiv3::MediaType {
-                        schema: Some(openapiv3::ReferenceOr::Item(
-                            openapiv3::Schema {
-                                schema_data: openapiv3::SchemaData::default(),
-                                schema_kind: openapiv3::SchemaKind::Any(
-                                    openapiv3::AnySchema::default(),
-                                ),
-                            },
-                        )),
-                        ..Default::default()
-                    },
-                );
-                openapiv3::Response {
-                    // TODO: perhaps we should require even free-form
-                    // responses to have a description since it's required
-                    // by OpenAPI.
-                    description: "".to_string(),
-                    content,
-                    ..Default::default()
-                }
-            };
-
-            if let Some(code) = &endpoint.response.success {
-                operation.responses.responses.insert(
-                    openapiv3::StatusCode::Code(code.as_u16()),
-                    openapiv3::ReferenceOr::Item(response),
-                );
-
-                // 4xx and 5xx responses all use the same error information
-                let err_ref = openapiv3::ReferenceOr::ref_(
-                    "#/components/responses/Error",
-                );
-                operation
-                    .responses
-                    .responses
-                    .insert(openapiv3::StatusCode::Range(4), err_ref.clone());
-                operation
-                    .responses
-                    .responses
-                    .insert(openapiv3::StatusCode::Range(5), err_ref);
-            } else {
-                operation.responses.default =
-                    Some(openapiv3::ReferenceOr::Item(response))
-            }
-
-            // Drop in the operation.
-            method_ref.replace(operation);
-        }
-
-        let components = &mut openapi
-            .components
-            .get_or_insert_with(openapiv3::Components::default);
-
-        // All endpoints share an error response
-        let responses = &mut components.responses;
-        let mut content = indexmap::IndexMap::new();
-        content.insert(
-            CONTENT_TYPE_JSON.to_string(),
-            openapiv3::MediaType {
-                schema: Some(j2oas_schema(
-                    None,
-                    &generator.subschema_for::<HttpErrorResponseBody>(),
-                )),
-                ..Default::default()
-            },
-        );
-
-        responses.insert(
-            "Error".to_string(),
-            openapiv3::ReferenceOr::Item(openapiv3::Response {
-                description: "Error".to_string(),
-                content: content,
-                ..Default::default()
-            }),
-        );
-
-        // Add the schemas for which we generated references.
-        let schemas = &mut components.schemas;
-
-        let root_schema = generator.into_root_schema_for::<()>();
-        root_schema.definitions.iter().for_each(|(key, schema)| {
-            schemas.insert(key.clone(), j2oas_schema(None, schema));
-        });
-
-        definitions.into_iter().for_each(|(key, schema)| {
-            if !schemas.contains_key(&key) {
-                schemas.insert(key, j2oas_schema(None, &schema));
-            }
-        });
-
-        openapi
+    ) -> impl Iterator<Item = &ApiEndpoint<Context>> + '_ {
+        self.router.endpoints()
     }
 
     // TODO-cleanup is there a way to make this available only within this
@@ -846,190 +1001,24 @@ impl<Context: ServerContext> ApiDescription<Context> {
     }
 }
 
-/// Returns true iff the schema represents the void schema that matches no data.
-fn is_empty(schema: &schemars::schema::Schema) -> bool {
-    if let schemars::schema::Schema::Bool(false) = schema {
-        return true;
-    }
-    if let schemars::schema::Schema::Object(schemars::schema::SchemaObject {
-        metadata: _,
-        instance_type: None,
-        format: None,
-        enum_values: None,
-        const_value: None,
-        subschemas: Some(subschemas),
-        number: None,
-        string: None,
-        array: None,
-        object: None,
-        reference: None,
-        extensions: _,
-    }) = schema
-    {
-        if let schemars::schema::SubschemaValidation {
-            all_of: None,
-            any_of: None,
-            one_of: None,
-            not: Some(not),
-            if_schema: None,
-            then_schema: None,
-            else_schema: None,
-        } = subschemas.as_ref()
-        {
-            match not.as_ref() {
-                schemars::schema::Schema::Bool(true) => return true,
-                schemars::schema::Schema::Object(
-                    schemars::schema::SchemaObject {
-                        metadata: _,
-                        instance_type: None,
-                        format: None,
-                        enum_values: None,
-                        const_value: None,
-                        subschemas: None,
-                        number: None,
-                        string: None,
-                        array: None,
-                        object: None,
-                        reference: None,
-                        extensions: _,
-                    },
-                ) => return true,
-                _ => {}
-            }
-        }
-    }
-
-    false
-}
-
-/// This object is used to specify configuration for building an OpenAPI
-/// definition document.  It is constructed using [`ApiDescription::openapi()`].
-/// Additional optional properties may be added and then the OpenAPI definition
-/// document may be generated via [`write()`](`OpenApiDefinition::write`) or
-/// [`json()`](`OpenApiDefinition::json`).
-pub struct OpenApiDefinition<'a, Context: ServerContext> {
-    api: &'a ApiDescription<Context>,
-    info: openapiv3::Info,
+/// Describes why an endpoint couldn't be registered (see
+/// [`ApiDescription::try_register()`])
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum RegisterError {
+    /// The endpoint's route conflicts with that of an endpoint that's already
+    /// registered.
+    Conflict(RouteConflict),
+    /// The endpoint is invalid on its own (e.g., its path parameters don't
+    /// match its path) or violates the API's policies (e.g., for tags).
+    Invalid { operation_id: String, message: String },
 }
 
-impl<'a, Context: ServerContext> OpenApiDefinition<'a, Context> {
-    fn new(
-        api: &'a ApiDescription<Context>,
-        title: &str,
-        version: &str,
-    ) -> OpenApiDefinition<'a, Context> {
-        let info = openapiv3::Info {
-            title: title.to_string(),
-            version: version.to_string(),
-            ..Default::default()
-        };
-        OpenApiDefinition { api, info }
-    }
-
-    /// Provide a short description of the API.  CommonMark syntax may be
-    /// used for rich text representation.
-    ///
-    /// This routine will set the `description` field of the `Info` object in the
-    /// OpenAPI definition.
-    pub fn description<S: AsRef<str>>(&mut self, description: S) -> &mut Self {
-        self.info.description = Some(description.as_ref().to_string());
-        self
-    }
-
-    /// Include a Terms of Service URL for the API.  Must be in the format of a
-    /// URL.
-    ///
-    /// This routine will set the `termsOfService` field of the `Info` object in
-    /// the OpenAPI definition.
-    pub fn terms_of_service<S: AsRef<str>>(&mut self, url: S) -> &mut Self {
-        self.info.terms_of_service = Some(url.as_ref().to_string());
-        self
-    }
-
-    fn contact_mut(&mut self) -> &mut openapiv3::Contact {
-        if self.info.contact.is_none() {
-            self.info.contact = Some(openapiv3::Contact::default());
-        }
-        self.info.contact.as_mut().unwrap()
-    }
-
-    /// Set the identifying name of the contact person or organisation
-    /// responsible for the API.
-    ///
-    /// This routine will set the `name` property of the `Contact` object within
-    /// the `Info` object in the OpenAPI definition.
-    pub fn contact_name<S: AsRef<str>>(&mut self, name: S) -> &mut Self {
-        self.contact_mut().name = Some(name.as_ref().to_string());
-        self
-    }
-
-    /// Set a contact URL for the API.  Must be in the format of a URL.
-    ///
-    /// This routine will set the `url` property of the `Contact` object within
-    /// the `Info` object in the OpenAPI definition.
-    pub fn contact_url<S: AsRef<str>>(&mut self, url: S) -> &mut Self {
-        self.contact_mut().url = Some(url.as_ref().to_string());
-        self
-    }
-
-    /// Set the email address of the contact person or organisation responsible
-    /// for the API.  Must be in the format of an email address.
-    ///
-    /// This routine will set the `email` property of the `Contact` object within
-    /// the `Info` object in the OpenAPI definition.
-    pub fn contact_email<S: AsRef<str>>(&mut self, email: S) -> &mut Self {
-        self.contact_mut().email = Some(email.as_ref().to_string());
-        self
-    }
-
-    fn license_mut(&mut self, name: &str) -> &mut openapiv3::License {
-        if self.info.license.is_none() {
-            self.info.license = Some(openapiv3::License {
-                name: name.to_string(),
-                ..Default::default()
-            })
+impl std::fmt::Display for RegisterError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            RegisterError::Conflict(conflict) => conflict.fmt(f),
+            RegisterError::Invalid { message, .. } => f.write_str(message),
         }
-        self.info.license.as_mut().unwrap()
-    }
-
-    /// Provide the name of the licence used for the API, and a URL (must be in
-    /// URL format) displaying the licence text.
-    ///
-    /// This routine will set the `name` and optional `url` properties of the
-    /// `License` object within the `Info` object in the OpenAPI definition.
-    pub fn license<S1, S2>(&mut self, name: S1, url: S2) -> &mut Self
-    where
-        S1: AsRef<str>,
-        S2: AsRef<str>,
-    {
-        self.license_mut(name.as_ref()).url = Some(url.as_ref().to_string());
-        self
-    }
-
-    /// Provide the name of the licence used for the API.
-    ///
-    /// This routine will set the `name` property of the License object within
-    /// the `Info` object in the OpenAPI definition.
-    pub fn license_name<S: AsRef<str>>(&mut self, name: S) -> &mut Self {
-        self.license_mut(name.as_ref());
-        self
-    }
-
-    /// Build a JSON object containing the OpenAPI definition for this API.
-    pub fn json(&self) -> serde_json::Result<serde_json::Value> {
-        serde_json::to_value(&self.api.gen_openapi(self.info.clone()))
-    }
-
-    /// Build a JSON object containing the OpenAPI definition for this API and
-    /// write it to the provided stream.
-    pub fn write(
-        &self,
-        out: &mut dyn std::io::Write,
-    ) -> serde_json::Result<()> {
-        serde_json::to_writer_pretty(
-            out,
-            &self.api.gen_openapi(self.info.clone()),
-        )
     }
 }
 
@@ -1079,6 +1068,10 @@ pub struct TagExternalDocs {
     pub url: String,
 }
 
+pub(crate) const PAGINATION_PARAM_SENTINEL: &str =
+    "x-dropshot-pagination-param";
+pub(crate) const WEBSOCKET_PARAM_SENTINEL: &str = "x-dropshot-websocket-param";
+
 /// Dropshot/Progenitor features used by endpoints which are not a part of the base OpenAPI spec.
 #[derive(Copy, Clone, Debug, Eq, PartialEq)]
 pub enum ExtensionMode {
@@ -1100,14 +1093,15 @@ mod test {
     use crate::handler::RequestContext;
     use crate::ApiDescription;
     use crate::ApiEndpoint;
+    use crate::Body;
     use crate::EndpointTagPolicy;
     use crate::Path;
     use crate::Query;
+    use crate::RegisterError;
     use crate::TagConfig;
     use crate::TagDetails;
     use crate::CONTENT_TYPE_JSON;
     use http::Method;
-    use hyper::Body;
     use hyper::Response;
     use openapiv3::OpenAPI;
     use schemars::JsonSchema;
@@ -1350,4 +1344,142 @@ mod test {
                 .collect::<HashSet<_>>()
         )
     }
+
+    #[test]
+    fn test_register_prefix() {
+        let mut team_api = ApiDescription::new().tag_config(TagConfig {
+            tag_definitions: vec![("team".to_string(), TagDetails::default())]
+                .into_iter()
+                .collect(),
+            ..Default::default()
+        });
+        for path in ["/xx/{a}/{b}", "/yy/{a}/{b}"] {
+            team_api
+                .register(
+                    ApiEndpoint::new(
+                        "test_badpath_handler".to_string(),
+                        test_badpath_handler,
+                        Method::GET,
+                        CONTENT_TYPE_JSON,
+                        path,
+                    )
+                    .tag("team"),
+                )
+                .unwrap();
+        }
+
+        let mut api = ApiDescription::new().tag_config(TagConfig {
+            allow_other_tags: false,
+            ..Default::default()
+        });
+        api.register_prefix("/v1/", team_api).unwrap();
+
+        let paths = api
+            .endpoints()
+            .map(|endpoint| format!("{} {}", endpoint.method, endpoint.path))
+            .collect::<Vec<_>>();
+        assert_eq!(paths, vec!["GET /v1/xx/{a}/{b}", "GET /v1/yy/{a}/{b}"]);
+        assert!(api.tag_config.tag_definitions.contains_key("team"));
+
+        assert_eq!(
+            api.register_prefix("/v1/{version}", ApiDescription::new()),
+            Err("path prefix \"/v1/{version}\" may not contain variables"
+                .to_string())
+        );
+    }
+
+    #[test]
+    fn test_register_all() {
+        let mut api = ApiDescription::new();
+        let errors = api
+            .register_all(
+                [
+                    ("/things/{a}/{b}", Method::GET),
+                    ("/things/{a}/{b}", Method::GET),
+                    ("/things/x/{a}/{b}", Method::PUT),
+                    ("/stuff/{a}/{b}", Method::GET),
+                    ("/stuff/{a}", Method::PUT),
+                ]
+                .into_iter()
+                .map(|(path, method)| {
+                    ApiEndpoint::new(
+                        format!("{} {}", method, path),
+                        test_badpath_handler,
+                        method,
+                        CONTENT_TYPE_JSON,
+                        path,
+                    )
+                }),
+            )
+            .unwrap_err();
+        assert_eq!(errors.len(), 3);
+        match &errors[0] {
+            RegisterError::Conflict(conflict) => {
+                assert_eq!(conflict.endpoint.path, "/things/{a}/{b}");
+                assert_eq!(conflict.existing.path, "/things/{a}/{b}");
+                assert_eq!(conflict.segment, None);
+            }
+            error => panic!("unexpected error: {:?}", error),
+        }
+        match &errors[1] {
+            RegisterError::Conflict(conflict) => {
+                assert_eq!(conflict.endpoint.path, "/things/x/{a}/{b}");
+                assert_eq!(conflict.existing.path, "/things/{a}/{b}");
+                assert_eq!(conflict.segment.as_deref(), Some("x"));
+            }
+            error => panic!("unexpected error: {:?}", error),
+        }
+        assert!(matches!(
+            &errors[2],
+            RegisterError::Invalid { operation_id, .. }
+                if operation_id == "PUT /stuff/{a}"
+        ));
+
+        // The other endpoints were registered.
+        assert_eq!(api.endpoints().count(), 2);
+    }
+
+    #[test]
+    fn test_endpoints() {
+        let mut api = ApiDescription::new();
+        for (method, path, tag) in [
+            (Method::PUT, "/things/{a}/{b}", "things"),
+            (Method::GET, "/things/{a}/{b}", "things"),
+            (Method::GET, "/stuff/{a}/{b}", "stuff"),
+        ] {
+            api.register(
+                ApiEndpoint::new(
+                    format!("{}_{}", method.as_str().to_lowercase(), tag),
+                    test_badpath_handler,
+                    method,
+                    CONTENT_TYPE_JSON,
+                    path,
+                )
+                .tag(tag),
+            )
+            .unwrap();
+        }
+
+        let endpoints = api
+            .endpoints()
+            .map(|endpoint| {
+                format!(
+                    "{} {} {} {:?} ({} parameters)",
+                    endpoint.method,
+                    endpoint.path,
+                    endpoint.operation_id,
+                    endpoint.tags,
+                    endpoint.parameters.len()
+                )
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            endpoints,
+            vec![
+                "GET /stuff/{a}/{b} get_stuff ["stuff"] (2 parameters)",
+                "GET /things/{a}/{b} get_things ["things"] (2 parameters)",
+                "PUT /things/{a}/{b} put_things ["things"] (2 parameters)",
+            ]
+        );
+    }
 }
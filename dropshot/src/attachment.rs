@@ -0,0 +1,239 @@
+// Copyright 2023 Oxide Computer Company
+
+//! Responses that download a file (`Content-Disposition: attachment`)
+
+use crate::api_description::ApiEndpointHeader;
+use crate::api_description::ApiEndpointResponse;
+use crate::api_description::ApiSchemaGenerator;
+use crate::handler::HttpHandlerResult;
+use crate::handler::HttpResponse;
+use crate::Body;
+
+use async_stream::try_stream;
+use bytes::BytesMut;
+use http::header;
+use http::Response;
+use http::StatusCode;
+use percent_encoding::utf8_percent_encode;
+use percent_encoding::AsciiSet;
+use percent_encoding::NON_ALPHANUMERIC;
+use schemars::schema::InstanceType;
+use schemars::schema::SchemaObject;
+use std::io;
+use tokio::io::AsyncRead;
+use tokio::io::AsyncReadExt;
+
+/// Size of the chunks in which attachments are read and sent
+const CHUNK_SIZE: usize = 64 * 1024;
+
+/// Characters that may appear unencoded in an RFC 8187 extended parameter
+/// value (`attr-char`)
+const ATTR_CHAR: &AsciiSet = &NON_ALPHANUMERIC
+    .remove(b'!')
+    .remove(b'#')
+    .remove(b'$')
+    .remove(b'&')
+    .remove(b'+')
+    .remove(b'-')
+    .remove(b'.')
+    .remove(b'^')
+    .remove(b'_')
+    .remove(b'`')
+    .remove(b'|')
+    .remove(b'~');
+
+/// `HttpResponseAttachment` is a 200 response that downloads a file: its body
+/// is streamed from an `AsyncRead` (such as a `tokio::fs::File`), and its
+/// `Content-Disposition` header tells the client to save it (rather than
+/// display it) under a given file name.  The caller chooses the
+/// `Content-Type`.  The response has a `Content-Length` if the size of the
+/// contents is known (see [`HttpResponseAttachment::content_length()`] and
+/// [`HttpResponseAttachment::from_file()`]).
+///
+/// The file name may contain any characters.  As RFC 6266 recommends, it's
+/// sent both as a plain `filename` parameter, in which characters other than
+/// printable ASCII (and quotes and backslashes) are replaced by `_`, and, if
+/// that changed it, as a UTF-8 `filename*` parameter, which clients prefer.
+///
+/// In the OpenAPI document, the body is a binary string of any media type.
+///
+/// ```
+/// use dropshot::endpoint;
+/// use dropshot::HttpError;
+/// use dropshot::HttpResponseAttachment;
+/// use dropshot::RequestContext;
+///
+/// #[endpoint {
+///     method = GET,
+///     path = "/report",
+/// }]
+/// async fn report_get(
+///     _rqctx: RequestContext<()>,
+/// ) -> Result<HttpResponseAttachment, HttpError> {
+///     let file = tokio::fs::File::open("/var/reports/latest.csv")
+///         .await
+///         .map_err(|e| HttpError::for_internal_error(e.to_string()))?;
+///     HttpResponseAttachment::from_file(file, "report.csv", "text/csv")
+///         .await
+///         .map_err(|e| HttpError::for_internal_error(e.to_string()))
+/// }
+/// ```
+pub struct HttpResponseAttachment {
+    body: Body,
+    filename: String,
+    content_type: String,
+    content_length: Option<u64>,
+}
+
+impl HttpResponseAttachment {
+    /// Returns a response whose contents are read from `reader`, to be saved
+    /// as `filename` and sent with the given `Content-Type`.
+    pub fn new<R, F, C>(
+        reader: R,
+        filename: F,
+        content_type: C,
+    ) -> HttpResponseAttachment
+    where
+        R: AsyncRead + Send + 'static,
+        F: Into<String>,
+        C: Into<String>,
+    {
+        HttpResponseAttachment {
+            body: reader_body(reader),
+            filename: filename.into(),
+            content_type: content_type.into(),
+            content_length: None,
+        }
+    }
+
+    /// Returns a response whose contents are those of `file` (from its current
+    /// position), to be saved as `filename` and sent with the given
+    /// `Content-Type`.  The response's `Content-Length` is the size of the
+    /// file.
+    pub async fn from_file<F, C>(
+        file: tokio::fs::File,
+        filename: F,
+        content_type: C,
+    ) -> io::Result<HttpResponseAttachment>
+    where
+        F: Into<String>,
+        C: Into<String>,
+    {
+        let len = file.metadata().await?.len();
+        Ok(HttpResponseAttachment::new(file, filename, content_type)
+            .content_length(len))
+    }
+
+    /// Sets the size of the contents, which is sent as the `Content-Length`.
+    pub fn content_length(mut self, len: u64) -> Self {
+        self.content_length = Some(len);
+        self
+    }
+}
+
+/// Returns a body that streams the contents of `reader`.
+fn reader_body<R: AsyncRead + Send + 'static>(reader: R) -> Body {
+    let chunks = try_stream! {
+        tokio::pin!(reader);
+        loop {
+            let mut chunk = BytesMut::with_capacity(CHUNK_SIZE);
+            if reader.read_buf(&mut chunk).await? == 0 {
+                break;
+            }
+            yield chunk.freeze();
+        }
+    };
+    Body::wrap_stream::<_, _, io::Error>(chunks)
+}
+
+/// Returns the value of the `Content-Disposition` header for downloading a
+/// file named `filename`.
+fn content_disposition(filename: &str) -> String {
+    let fallback = filename
+        .chars()
+        .map(|c| match c {
+            ' '..='~' if c != '"' && c != '\\' => c,
+            _ => '_',
+        })
+        .collect::<String>();
+    if fallback == filename {
+        format!("attachment; filename=\"{}\"", filename)
+    } else {
+        format!(
+            "attachment; filename=\"{}\"; filename*=UTF-8''{}",
+            fallback,
+            utf8_percent_encode(filename, ATTR_CHAR)
+        )
+    }
+}
+
+impl HttpResponse for HttpResponseAttachment {
+    fn to_result(self) -> HttpHandlerResult {
+        let mut builder = Response::builder()
+            .status(StatusCode::OK)
+            .header(header::CONTENT_TYPE, self.content_type)
+            .header(
+                header::CONTENT_DISPOSITION,
+                content_disposition(&self.filename),
+            );
+        if let Some(len) = self.content_length {
+            builder = builder.header(header::CONTENT_LENGTH, len);
+        }
+        Ok(builder.body(self.body)?)
+    }
+
+    fn response_metadata() -> ApiEndpointResponse {
+        let string = |format: Option<&str>| -> schemars::schema::Schema {
+            SchemaObject {
+                instance_type: Some(InstanceType::String.into()),
+                format: format.map(String::from),
+                ..Default::default()
+            }
+            .into()
+        };
+        ApiEndpointResponse {
+            schema: Some(ApiSchemaGenerator::Static {
+                schema: Box::new(string(Some("binary"))),
+                dependencies: indexmap::IndexMap::new(),
+            }),
+            content_type: Some(String::from("*/*")),
+            headers: vec![ApiEndpointHeader {
+                name: String::from("Content-Disposition"),
+                description: Some(String::from(
+                    "name under which to save the file",
+                )),
+                schema: ApiSchemaGenerator::Static {
+                    schema: Box::new(string(None)),
+                    dependencies: indexmap::IndexMap::new(),
+                },
+                required: true,
+            }],
+            success: Some(StatusCode::OK),
+            description: Some(String::from("file download")),
+            ..Default::default()
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::content_disposition;
+
+    #[test]
+    fn test_content_disposition() {
+        assert_eq!(
+            content_disposition("report.csv"),
+            "attachment; filename=\"report.csv\""
+        );
+        assert_eq!(
+            content_disposition("my \"best\" report.csv"),
+            "attachment; filename=\"my _best_ report.csv\"; \
+             filename*=UTF-8''my%20%22best%22%20report.csv"
+        );
+        assert_eq!(
+            content_disposition("résumé €.pdf"),
+            "attachment; filename=\"r_sum_ _.pdf\"; \
+             filename*=UTF-8''r%C3%A9sum%C3%A9%20%E2%82%AC.pdf"
+        );
+    }
+}
@@ -0,0 +1,209 @@
+// Copyright 2023 Oxide Computer Company
+//! Throttling of response bandwidth
+
+use crate::router::HttpRouter;
+use crate::server::ServerContext;
+
+use crate::Body;
+use crate::BoxError;
+use http::Method;
+use http_body::Frame;
+use http_body_util::BodyExt;
+use http_body_util::StreamBody;
+use std::collections::BTreeMap;
+use std::sync::atomic::AtomicU64;
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
+use std::sync::Mutex;
+use std::time::Duration;
+use std::time::Instant;
+
+/// Largest piece of a response body that's written at once when the response
+/// is being throttled.  Larger chunks are split so that the body is sent at an
+/// even pace rather than in bursts.
+const THROTTLE_CHUNK_BYTES: usize = 16 * 1024;
+
+/// Token bucket that limits the rate at which response bytes are sent
+///
+/// The rate (in bytes per second) may be changed at any time, and the change
+/// applies to everything sent afterwards.  A throttle can be shared by many
+/// responses (e.g., all responses on one connection), which then share the
+/// bandwidth.  The bucket holds at most one second's worth of bytes, which
+/// bounds the burst that can be sent after a period of inactivity.
+#[derive(Clone, Debug)]
+pub(crate) struct Throttle {
+    /// bytes per second, or 0 for no limit
+    rate: Arc<AtomicU64>,
+    /// available bytes (which may be negative if bytes have been reserved
+    /// ahead of time) and when that was last computed
+    bucket: Arc<Mutex<Option<(f64, Instant)>>>,
+}
+
+impl Throttle {
+    pub fn new(rate: Option<u64>) -> Throttle {
+        Throttle {
+            rate: Arc::new(AtomicU64::new(rate.unwrap_or(0))),
+            bucket: Arc::new(Mutex::new(None)),
+        }
+    }
+
+    /// Returns a throttle that always has the same rate as this one, but
+    /// tracks the bytes sent through it separately.
+    pub fn with_new_bucket(&self) -> Throttle {
+        Throttle {
+            rate: Arc::clone(&self.rate),
+            bucket: Arc::new(Mutex::new(None)),
+        }
+    }
+
+    pub fn rate(&self) -> Option<u64> {
+        match self.rate.load(Ordering::Relaxed) {
+            0 => None,
+            rate => Some(rate),
+        }
+    }
+
+    pub fn set_rate(&self, rate: Option<u64>) {
+        self.rate.store(rate.unwrap_or(0), Ordering::Relaxed);
+    }
+
+    /// Reserves `nbytes` from the bucket, returning how long the caller must
+    /// wait before sending them.
+    fn reserve(&self, nbytes: usize) -> Duration {
+        let rate = match self.rate() {
+            None => return Duration::ZERO,
+            Some(rate) => rate as f64,
+        };
+
+        let now = Instant::now();
+        let mut bucket = self.bucket.lock().unwrap();
+        let available = match *bucket {
+            None => rate,
+            Some((available, when)) => (available
+                + now.saturating_duration_since(when).as_secs_f64() * rate)
+                .min(rate),
+        };
+        let available = available - nbytes as f64;
+        *bucket = Some((available, now));
+        if available >= 0.0 {
+            Duration::ZERO
+        } else {
+            Duration::from_secs_f64(-available / rate)
+        }
+    }
+
+    /// Returns whether this throttle currently limits anything.
+    pub fn is_limited(&self) -> bool {
+        self.rate().is_some()
+    }
+}
+
+/// Returns a body that yields the contents of `body` no faster than any of
+/// `throttles` allows.  Its trailers, if any, are passed along as they are.
+pub(crate) fn throttle_body(mut body: Body, throttles: Vec<Throttle>) -> Body {
+    Body::wrap(StreamBody::new(async_stream::stream! {
+        while let Some(frame) = body.frame().await {
+            let frame = match frame {
+                Ok(frame) => frame,
+                Err(error) => {
+                    yield Err(error);
+                    break;
+                }
+            };
+            let mut chunk = match frame.into_data() {
+                Ok(chunk) => chunk,
+                Err(frame) => {
+                    yield Ok(frame);
+                    continue;
+                }
+            };
+            while !chunk.is_empty() {
+                let piece =
+                    chunk.split_to(chunk.len().min(THROTTLE_CHUNK_BYTES));
+                let delay = throttles
+                    .iter()
+                    .map(|throttle| throttle.reserve(piece.len()))
+                    .max()
+                    .unwrap_or(Duration::ZERO);
+                if delay > Duration::ZERO {
+                    tokio::time::sleep(delay).await;
+                }
+                yield Ok::<_, BoxError>(Frame::data(piece));
+            }
+        }
+    }))
+}
+
+/// Per-endpoint bandwidth limits.  Each endpoint has its own throttle, shared
+/// by all of its responses.  Initial limits come from the endpoints'
+/// `bandwidth_limit`, but they can be changed while the server is running.
+#[derive(Debug)]
+pub(crate) struct EndpointThrottles {
+    /// path template -> HTTP method -> throttle
+    throttles: BTreeMap<String, BTreeMap<String, Throttle>>,
+}
+
+impl EndpointThrottles {
+    pub fn new<C: ServerContext>(router: &HttpRouter<C>) -> EndpointThrottles {
+        let mut throttles = BTreeMap::new();
+        for (_, _, endpoint) in router {
+            throttles
+                .entry(endpoint.path.clone())
+                .or_insert_with(BTreeMap::new)
+                .insert(
+                    endpoint.method.to_string(),
+                    Throttle::new(endpoint.bandwidth_limit),
+                );
+        }
+        EndpointThrottles { throttles }
+    }
+
+    /// Returns the throttle for the given endpoint, if it's registered.
+    pub fn get(&self, method: &Method, path: &str) -> Option<&Throttle> {
+        self.throttles.get(path).and_then(|m| m.get(method.as_str()))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::throttle_body;
+    use super::Throttle;
+    use crate::Body;
+    use std::time::Duration;
+    use std::time::Instant;
+
+    #[test]
+    fn test_reserve() {
+        let throttle = Throttle::new(None);
+        assert!(!throttle.is_limited());
+        assert_eq!(throttle.reserve(1_000_000), Duration::ZERO);
+
+        // The bucket starts with one second's worth of bytes, after which
+        // callers must wait for it to refill.
+        throttle.set_rate(Some(1000));
+        assert_eq!(throttle.reserve(1000), Duration::ZERO);
+        let delay = throttle.reserve(500);
+        assert!(delay > Duration::from_millis(450));
+        assert!(delay <= Duration::from_millis(500));
+
+        // A throttle with a new bucket shares the rate, but not the bucket.
+        let other = throttle.with_new_bucket();
+        assert_eq!(other.reserve(1000), Duration::ZERO);
+        throttle.set_rate(None);
+        assert!(!other.is_limited());
+    }
+
+    #[tokio::test]
+    async fn test_throttle_body() {
+        let throttle = Throttle::new(Some(64 * 1024));
+        let start = Instant::now();
+        let body = throttle_body(
+            Body::from(vec![0u8; 128 * 1024]),
+            vec![throttle.clone(), Throttle::new(None)],
+        );
+        let bytes = body.to_bytes().await.unwrap();
+        assert_eq!(bytes.len(), 128 * 1024);
+        // The first 64 KiB are sent immediately, and the rest take a second.
+        assert!(start.elapsed() >= Duration::from_millis(900));
+    }
+}
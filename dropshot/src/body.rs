@@ -0,0 +1,539 @@
+// Copyright 2023 Oxide Computer Company
+//! Body type for HTTP requests and responses
+
+use bytes::Bytes;
+use futures::channel::mpsc;
+use futures::future::BoxFuture;
+use futures::Future;
+use futures::FutureExt;
+use futures::Stream;
+use http::HeaderMap;
+use http_body::Frame;
+use http_body::SizeHint;
+use http_body_util::combinators::UnsyncBoxBody;
+use http_body_util::BodyExt;
+use http_body_util::Empty;
+use http_body_util::Full;
+use http_body_util::StreamBody;
+use std::borrow::Cow;
+use std::fmt;
+use std::io;
+use std::pin::Pin;
+use std::sync::atomic::AtomicBool;
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
+use std::task::Context;
+use std::task::Poll;
+use sync_wrapper::SyncWrapper;
+use tokio::io::AsyncWrite;
+
+/// Error type for [`Body`]
+pub type BoxError = Box<dyn std::error::Error + Send + Sync + 'static>;
+
+/// The body of an HTTP request or response.
+///
+/// This is what extractors like [`RawRequest`](crate::RawRequest) and response
+/// types like [`HttpResponse`](crate::HttpResponse) use instead of the body
+/// types of the underlying HTTP implementation.  A `Body` can be made from the
+/// usual in-memory types (e.g., `Bytes`, `String`, and `Vec<u8>`), from a
+/// [`Stream`] of chunks (see [`Body::wrap_stream()`]), from bytes written with
+/// `AsyncWrite` (see [`Body::channel()`]), or from any other
+/// [`http_body::Body`] (see [`Body::wrap()`]), and trailers can be added to
+/// any of these (see [`Body::with_trailers()`]).  In turn, it implements both
+/// `http_body::Body` and `Stream` (yielding the data chunks and skipping any
+/// trailers).
+///
+/// A `Body` is `Sync` even if what it wraps is not.
+pub struct Body {
+    inner: SyncWrapper<UnsyncBoxBody<Bytes, BoxError>>,
+    // `SyncWrapper` only provides mutable access to what it wraps, so these
+    // are cached from the wrapped body whenever it's polled.
+    size_hint: SizeHint,
+    is_end_stream: bool,
+}
+
+impl Body {
+    /// Returns a body with no contents.
+    pub fn empty() -> Body {
+        Body::wrap(Empty::<Bytes>::new())
+    }
+
+    /// Returns a body with the given contents.
+    pub fn with_content(data: impl Into<Bytes>) -> Body {
+        Body::wrap(Full::new(data.into()))
+    }
+
+    /// Wraps any other [`http_body::Body`].
+    pub fn wrap<B>(body: B) -> Body
+    where
+        B: http_body::Body + Send + 'static,
+        B::Data: Into<Bytes>,
+        B::Error: Into<BoxError>,
+    {
+        let size_hint = body.size_hint();
+        let is_end_stream = body.is_end_stream();
+        let inner = body
+            .map_frame(|frame| frame.map_data(Into::into))
+            .map_err(Into::into);
+        Body {
+            inner: SyncWrapper::new(UnsyncBoxBody::new(inner)),
+            size_hint,
+            is_end_stream,
+        }
+    }
+
+    /// Returns a body whose contents are the chunks yielded by `stream`.
+    pub fn wrap_stream<S, D, E>(stream: S) -> Body
+    where
+        S: Stream<Item = Result<D, E>> + Send + 'static,
+        D: Into<Bytes> + 'static,
+        E: Into<BoxError> + 'static,
+    {
+        use futures::TryStreamExt;
+        Body::wrap(StreamBody::new(
+            stream.map_ok(|data| Frame::data(data.into())).map_err(Into::into),
+        ))
+    }
+
+    /// Returns a body with the contents of this one followed by the trailers
+    /// (header fields sent after the contents) produced by `trailers`, which
+    /// is awaited once the contents have all been read.  This is useful for
+    /// metadata computed while the contents are streamed, like a checksum:
+    /// the stream can send it to `trailers` through a channel.  If `trailers`
+    /// fails, the body fails.
+    ///
+    /// The response must declare the names of its trailers in its `Trailer`
+    /// header: HTTP/1.1 clients are only sent the trailers declared there, and
+    /// only if they asked for trailers (with `TE: trailers`).  Since trailers
+    /// are sent at the end of a chunked response, the body never has a known
+    /// length (so the response won't have a `Content-Length`).
+    ///
+    /// ```
+    /// use dropshot::Body;
+    /// use http::HeaderMap;
+    /// use http::Response;
+    ///
+    /// let (checksum_tx, checksum_rx) = tokio::sync::oneshot::channel();
+    /// let body = Body::wrap_stream(async_stream::stream! {
+    ///     let mut checksum = 0u32;
+    ///     for chunk in ["hello, ", "world"] {
+    ///         for byte in chunk.bytes() {
+    ///             checksum = checksum.wrapping_add(u32::from(byte));
+    ///         }
+    ///         yield Ok::<_, std::io::Error>(chunk);
+    ///     }
+    ///     let _ = checksum_tx.send(checksum);
+    /// })
+    /// .with_trailers(async move {
+    ///     let checksum = checksum_rx.await?;
+    ///     let mut trailers = HeaderMap::new();
+    ///     trailers.insert("x-checksum", checksum.into());
+    ///     Ok::<_, tokio::sync::oneshot::error::RecvError>(trailers)
+    /// });
+    /// let response = Response::builder()
+    ///     .header(http::header::TRAILER, "x-checksum")
+    ///     .body(body)
+    ///     .unwrap();
+    /// ```
+    pub fn with_trailers<F, E>(self, trailers: F) -> Body
+    where
+        F: Future<Output = Result<HeaderMap, E>> + Send + 'static,
+        E: Into<BoxError> + 'static,
+    {
+        Body::wrap(WithTrailers {
+            body: self,
+            body_done: false,
+            body_trailers: None,
+            trailers: Some(trailers.map(|r| r.map_err(Into::into)).boxed()),
+        })
+    }
+
+    /// Returns a body whose contents are written with the returned
+    /// [`ResponseBodyWriter`], which implements `AsyncWrite`.  This is often
+    /// simpler than building a [`Stream`] for [`Body::wrap_stream()`], e.g.,
+    /// for copying from an `AsyncRead` or writing with an encoder that
+    /// produces its output through `AsyncWrite`.
+    ///
+    /// The writer holds at most one chunk that the body hasn't yet taken, so
+    /// writes wait while the client is slow to receive the response.  The body
+    /// ends when the writer is shut down (with
+    /// `tokio::io::AsyncWriteExt::shutdown()`).  If the writer is instead
+    /// dropped, the body fails, so that the client can tell that the response
+    /// is incomplete.  Once the body has been dropped (e.g., because the
+    /// client has gone away), writes fail with `BrokenPipe`.
+    ///
+    /// ```
+    /// use dropshot::Body;
+    /// use http::Response;
+    /// use tokio::io::AsyncWriteExt;
+    ///
+    /// # async fn example() {
+    /// let (mut writer, body) = Body::channel();
+    /// tokio::spawn(async move {
+    ///     for i in 0..1000 {
+    ///         writer.write_all(format!("line {}\n", i).as_bytes()).await?;
+    ///     }
+    ///     writer.shutdown().await
+    /// });
+    /// let response = Response::builder()
+    ///     .header(http::header::CONTENT_TYPE, "text/plain")
+    ///     .body(body)
+    ///     .unwrap();
+    /// # }
+    /// ```
+    pub fn channel() -> (ResponseBodyWriter, Body) {
+        use futures::StreamExt;
+        let (tx, rx) = mpsc::channel(0);
+        let finished = Arc::new(AtomicBool::new(false));
+        let writer = ResponseBodyWriter {
+            tx: Some(tx),
+            finished: Arc::clone(&finished),
+        };
+        let end = futures::stream::once(async move {
+            if finished.load(Ordering::SeqCst) {
+                None
+            } else {
+                Some(Err(BoxError::from(
+                    "response body writer dropped before it was shut down",
+                )))
+            }
+        })
+        .filter_map(futures::future::ready);
+        (writer, Body::wrap_stream(rx.map(Ok).chain(end)))
+    }
+
+    /// Reads the whole body into memory, however large it is.
+    pub async fn to_bytes(self) -> Result<Bytes, BoxError> {
+        Ok(self.collect().await?.to_bytes())
+    }
+}
+
+impl Default for Body {
+    fn default() -> Body {
+        Body::empty()
+    }
+}
+
+impl fmt::Debug for Body {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Body").finish_non_exhaustive()
+    }
+}
+
+impl http_body::Body for Body {
+    type Data = Bytes;
+    type Error = BoxError;
+
+    fn poll_frame(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Frame<Bytes>, BoxError>>> {
+        let this = self.get_mut();
+        let inner = this.inner.get_mut();
+        let result = Pin::new(&mut *inner).poll_frame(cx);
+        this.size_hint = inner.size_hint();
+        this.is_end_stream = inner.is_end_stream();
+        result
+    }
+
+    fn is_end_stream(&self) -> bool {
+        self.is_end_stream
+    }
+
+    fn size_hint(&self) -> SizeHint {
+        self.size_hint
+    }
+}
+
+/// A body followed by trailers produced by a future (see
+/// [`Body::with_trailers()`])
+struct WithTrailers {
+    body: Body,
+    /// whether `body` has ended
+    body_done: bool,
+    /// trailers of `body` itself, which are merged with those produced by
+    /// `trailers`
+    body_trailers: Option<HeaderMap>,
+    /// produces the trailers; `None` once they've been sent
+    trailers: Option<BoxFuture<'static, Result<HeaderMap, BoxError>>>,
+}
+
+impl http_body::Body for WithTrailers {
+    type Data = Bytes;
+    type Error = BoxError;
+
+    fn poll_frame(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Frame<Bytes>, BoxError>>> {
+        let this = self.get_mut();
+        if this.trailers.is_none() {
+            return Poll::Ready(None);
+        }
+
+        while !this.body_done && !http_body::Body::is_end_stream(&this.body) {
+            match futures::ready!(http_body::Body::poll_frame(
+                Pin::new(&mut this.body),
+                cx
+            )) {
+                None => this.body_done = true,
+                Some(Err(error)) => {
+                    this.trailers = None;
+                    return Poll::Ready(Some(Err(error)));
+                }
+                Some(Ok(frame)) => match frame.into_trailers() {
+                    Ok(trailers) => this
+                        .body_trailers
+                        .get_or_insert_with(HeaderMap::new)
+                        .extend(trailers),
+                    Err(frame) => return Poll::Ready(Some(Ok(frame))),
+                },
+            }
+        }
+
+        let future = match &mut this.trailers {
+            Some(future) => future,
+            None => return Poll::Ready(None),
+        };
+        let result = futures::ready!(future.as_mut().poll(cx));
+        this.trailers = None;
+        Poll::Ready(Some(result.map(|trailers| {
+            let mut all = this.body_trailers.take().unwrap_or_default();
+            all.extend(trailers);
+            Frame::trailers(all)
+        })))
+    }
+
+    fn is_end_stream(&self) -> bool {
+        self.trailers.is_none()
+    }
+
+    fn size_hint(&self) -> SizeHint {
+        // The length is never exact, so that the body is sent chunked (which
+        // is necessary to send trailers with HTTP/1.1).
+        let mut size_hint = SizeHint::new();
+        size_hint.set_lower(http_body::Body::size_hint(&self.body).lower());
+        size_hint
+    }
+}
+
+impl Stream for Body {
+    type Item = Result<Bytes, BoxError>;
+
+    fn poll_next(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Self::Item>> {
+        loop {
+            match futures::ready!(http_body::Body::poll_frame(
+                self.as_mut(),
+                cx
+            )) {
+                None => return Poll::Ready(None),
+                Some(Err(error)) => return Poll::Ready(Some(Err(error))),
+                Some(Ok(frame)) => {
+                    if let Ok(data) = frame.into_data() {
+                        return Poll::Ready(Some(Ok(data)));
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Writes the contents of a [`Body`] made with [`Body::channel()`]
+#[derive(Debug)]
+pub struct ResponseBodyWriter {
+    /// sends chunks to the body, until the writer is shut down
+    tx: Option<mpsc::Sender<Bytes>>,
+    /// set when the writer is shut down, so that the body ends normally
+    finished: Arc<AtomicBool>,
+}
+
+impl ResponseBodyWriter {
+    /// Waits until the body has taken the last chunk written, so that another
+    /// can be sent.
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        let tx = self.tx.as_mut().ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::BrokenPipe,
+                "response body writer has been shut down",
+            )
+        })?;
+        tx.poll_ready(cx).map_err(|_| {
+            io::Error::new(
+                io::ErrorKind::BrokenPipe,
+                "response body has been dropped",
+            )
+        })
+    }
+}
+
+impl AsyncWrite for ResponseBodyWriter {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        let this = self.get_mut();
+        if buf.is_empty() {
+            return Poll::Ready(Ok(0));
+        }
+        futures::ready!(this.poll_ready(cx))?;
+        this.tx
+            .as_mut()
+            .unwrap()
+            .start_send(Bytes::copy_from_slice(buf))
+            .map_err(|_| {
+                io::Error::new(
+                    io::ErrorKind::BrokenPipe,
+                    "response body has been dropped",
+                )
+            })?;
+        Poll::Ready(Ok(buf.len()))
+    }
+
+    /// Completes once the body has taken everything that's been written.
+    fn poll_flush(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        if this.tx.is_none() {
+            return Poll::Ready(Ok(()));
+        }
+        this.poll_ready(cx)
+    }
+
+    fn poll_shutdown(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        if this.tx.is_some() {
+            futures::ready!(this.poll_ready(cx))?;
+            this.finished.store(true, Ordering::SeqCst);
+            this.tx = None;
+        }
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl From<Bytes> for Body {
+    fn from(data: Bytes) -> Body {
+        Body::with_content(data)
+    }
+}
+
+impl From<Vec<u8>> for Body {
+    fn from(data: Vec<u8>) -> Body {
+        Body::with_content(data)
+    }
+}
+
+impl From<&'static [u8]> for Body {
+    fn from(data: &'static [u8]) -> Body {
+        Body::with_content(data)
+    }
+}
+
+impl From<String> for Body {
+    fn from(data: String) -> Body {
+        Body::with_content(data)
+    }
+}
+
+impl From<&'static str> for Body {
+    fn from(data: &'static str) -> Body {
+        Body::with_content(data)
+    }
+}
+
+impl From<Cow<'static, str>> for Body {
+    fn from(data: Cow<'static, str>) -> Body {
+        match data {
+            Cow::Borrowed(data) => Body::from(data),
+            Cow::Owned(data) => Body::from(data),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::Body;
+    use bytes::Bytes;
+    use futures::StreamExt;
+    use http::HeaderMap;
+    use http_body::Body as _;
+    use http_body_util::BodyExt;
+    use tokio::io::AsyncWriteExt;
+
+    #[tokio::test]
+    async fn test_body() {
+        let body = Body::from("hello");
+        assert_eq!(body.size_hint().exact(), Some(5));
+        assert!(!body.is_end_stream());
+        assert_eq!(body.to_bytes().await.unwrap(), "hello");
+
+        let body = Body::empty();
+        assert_eq!(body.size_hint().exact(), Some(0));
+        assert!(body.is_end_stream());
+
+        let stream =
+            futures::stream::iter(vec![Ok::<_, std::io::Error>("a"), Ok("bc")]);
+        let mut body = Body::wrap_stream(stream);
+        assert_eq!(body.size_hint().exact(), None);
+        assert_eq!(body.next().await.unwrap().unwrap(), Bytes::from("a"));
+        assert_eq!(body.to_bytes().await.unwrap(), "bc");
+    }
+
+    #[tokio::test]
+    async fn test_body_trailers() {
+        let body = Body::from("hello").with_trailers(async {
+            let mut trailers = HeaderMap::new();
+            trailers.insert("x-checksum", "abc".parse().unwrap());
+            Ok::<_, std::io::Error>(trailers)
+        });
+        assert_eq!(body.size_hint().exact(), None);
+        assert_eq!(body.size_hint().lower(), 5);
+
+        let collected = BodyExt::collect(body).await.unwrap();
+        assert_eq!(collected.trailers().unwrap()["x-checksum"], "abc");
+        assert_eq!(collected.to_bytes(), "hello");
+
+        let body = Body::empty().with_trailers(async {
+            Err::<HeaderMap, _>(std::io::Error::new(
+                std::io::ErrorKind::Other,
+                "no checksum",
+            ))
+        });
+        assert_eq!(
+            body.to_bytes().await.unwrap_err().to_string(),
+            "no checksum"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_body_channel() {
+        let (mut writer, body) = Body::channel();
+        let write = tokio::spawn(async move {
+            writer.write_all(b"hello, ").await?;
+            writer.write_all(b"world").await?;
+            writer.shutdown().await
+        });
+        assert_eq!(body.to_bytes().await.unwrap(), "hello, world");
+        write.await.unwrap().unwrap();
+
+        // A writer that's dropped without being shut down fails the body.
+        let (mut writer, body) = Body::channel();
+        let write = tokio::spawn(async move { writer.write_all(b"hel").await });
+        assert!(body.to_bytes().await.is_err());
+        write.await.unwrap().unwrap();
+
+        // Writes fail once the body is gone.
+        let (mut writer, body) = Body::channel();
+        drop(body);
+        let error = writer.write_all(b"hello").await.unwrap_err();
+        assert_eq!(error.kind(), std::io::ErrorKind::BrokenPipe);
+    }
+}
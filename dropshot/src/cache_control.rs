@@ -0,0 +1,233 @@
+// Copyright 2023 Oxide Computer Company
+
+//! Typed `Cache-Control` policies for responses
+
+use crate::api_description::ApiEndpointHeader;
+use crate::api_description::ApiEndpointResponse;
+use crate::api_description::ApiSchemaGenerator;
+use crate::error::HttpError;
+use crate::handler::HttpHandlerResult;
+use crate::handler::HttpResponse;
+
+use http::header;
+use http::HeaderValue;
+use std::convert::TryFrom;
+use std::fmt;
+use std::time::Duration;
+
+/// A caching policy for a response, sent as its `Cache-Control` header.
+///
+/// Policies are built up from [`CachePolicy::new()`] (which has no directives)
+/// and attached to a response with [`HttpResponseCached`].
+///
+/// ```
+/// use dropshot::CachePolicy;
+/// use std::time::Duration;
+///
+/// let policy = CachePolicy::new()
+///     .public()
+///     .max_age(Duration::from_secs(60))
+///     .stale_while_revalidate(Duration::from_secs(30));
+/// assert_eq!(
+///     policy.to_string(),
+///     "public, max-age=60, stale-while-revalidate=30"
+/// );
+/// ```
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct CachePolicy {
+    public: bool,
+    private: bool,
+    no_cache: bool,
+    no_store: bool,
+    max_age: Option<Duration>,
+    s_maxage: Option<Duration>,
+    stale_while_revalidate: Option<Duration>,
+    immutable: bool,
+}
+
+impl CachePolicy {
+    /// Returns a policy with no directives.
+    pub fn new() -> CachePolicy {
+        CachePolicy::default()
+    }
+
+    /// Allows shared caches (e.g., CDNs and proxies) to store the response,
+    /// even if they normally wouldn't (`public`).
+    pub fn public(mut self) -> Self {
+        self.public = true;
+        self
+    }
+
+    /// Allows only the client's own cache, not shared caches, to store the
+    /// response (`private`).
+    pub fn private(mut self) -> Self {
+        self.private = true;
+        self
+    }
+
+    /// Requires caches to revalidate the response with the server before each
+    /// use (`no-cache`).
+    pub fn no_cache(mut self) -> Self {
+        self.no_cache = true;
+        self
+    }
+
+    /// Forbids caches from storing the response at all (`no-store`).
+    pub fn no_store(mut self) -> Self {
+        self.no_store = true;
+        self
+    }
+
+    /// Sets how long the response stays fresh (`max-age`), in whole seconds.
+    pub fn max_age(mut self, max_age: Duration) -> Self {
+        self.max_age = Some(max_age);
+        self
+    }
+
+    /// Sets how long the response stays fresh in shared caches (`s-maxage`),
+    /// overriding `max_age` for them, in whole seconds.
+    pub fn s_maxage(mut self, s_maxage: Duration) -> Self {
+        self.s_maxage = Some(s_maxage);
+        self
+    }
+
+    /// Allows caches to use the response for this long after it becomes stale
+    /// while they revalidate it in the background
+    /// (`stale-while-revalidate`), in whole seconds.
+    pub fn stale_while_revalidate(mut self, window: Duration) -> Self {
+        self.stale_while_revalidate = Some(window);
+        self
+    }
+
+    /// Tells caches that the response will never change while it's fresh, so
+    /// that they needn't revalidate it (`immutable`).
+    pub fn immutable(mut self) -> Self {
+        self.immutable = true;
+        self
+    }
+}
+
+impl fmt::Display for CachePolicy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let flags = [
+            (self.public, "public"),
+            (self.private, "private"),
+            (self.no_cache, "no-cache"),
+            (self.no_store, "no-store"),
+        ];
+        let durations = [
+            (self.max_age, "max-age"),
+            (self.s_maxage, "s-maxage"),
+            (self.stale_while_revalidate, "stale-while-revalidate"),
+        ];
+        let mut directives = flags
+            .iter()
+            .filter(|(set, _)| *set)
+            .map(|(_, name)| name.to_string())
+            .chain(durations.iter().filter_map(|(duration, name)| {
+                duration.map(|d| format!("{}={}", name, d.as_secs()))
+            }))
+            .collect::<Vec<_>>();
+        if self.immutable {
+            directives.push(String::from("immutable"));
+        }
+        f.write_str(&directives.join(", "))
+    }
+}
+
+/// `HttpResponseCached<T>` is the response `T` with a `Cache-Control` header
+/// given by a [`CachePolicy`] (replacing any that `T` has).  The header is
+/// described in the OpenAPI document along with `T`'s.
+///
+/// ```
+/// use dropshot::endpoint;
+/// use dropshot::CachePolicy;
+/// use dropshot::HttpError;
+/// use dropshot::HttpResponseCached;
+/// use dropshot::HttpResponseOk;
+/// use dropshot::RequestContext;
+/// use std::time::Duration;
+///
+/// #[endpoint {
+///     method = GET,
+///     path = "/widget",
+/// }]
+/// async fn widget_get(
+///     _rqctx: RequestContext<()>,
+/// ) -> Result<HttpResponseCached<HttpResponseOk<String>>, HttpError> {
+///     let policy =
+///         CachePolicy::new().private().max_age(Duration::from_secs(60));
+///     Ok(HttpResponseCached::new(
+///         HttpResponseOk(String::from("sprocket")),
+///         policy,
+///     ))
+/// }
+/// ```
+pub struct HttpResponseCached<T: HttpResponse + Send + Sync + 'static> {
+    response: T,
+    policy: CachePolicy,
+}
+
+impl<T: HttpResponse + Send + Sync + 'static> HttpResponseCached<T> {
+    /// Returns `response` with the caching policy `policy`.
+    pub fn new(response: T, policy: CachePolicy) -> HttpResponseCached<T> {
+        HttpResponseCached { response, policy }
+    }
+}
+
+impl<T: HttpResponse + Send + Sync + 'static> HttpResponse
+    for HttpResponseCached<T>
+{
+    fn to_result(self) -> HttpHandlerResult {
+        let mut response = self.response.to_result()?;
+        let value = HeaderValue::try_from(self.policy.to_string())
+            .map_err(|e| HttpError::for_internal_error(e.to_string()))?;
+        response.headers_mut().insert(header::CACHE_CONTROL, value);
+        Ok(response)
+    }
+
+    fn response_metadata() -> ApiEndpointResponse {
+        let mut metadata = T::response_metadata();
+        metadata.headers.push(ApiEndpointHeader {
+            name: String::from("Cache-Control"),
+            description: Some(String::from("caching policy for the response")),
+            schema: ApiSchemaGenerator::Static {
+                schema: Box::new(
+                    schemars::gen::SchemaGenerator::default()
+                        .subschema_for::<String>(),
+                ),
+                dependencies: indexmap::IndexMap::new(),
+            },
+            required: true,
+        });
+        metadata
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::CachePolicy;
+    use std::time::Duration;
+
+    #[test]
+    fn test_cache_policy() {
+        assert_eq!(CachePolicy::new().to_string(), "");
+        assert_eq!(CachePolicy::new().no_store().to_string(), "no-store");
+        assert_eq!(
+            CachePolicy::new()
+                .immutable()
+                .max_age(Duration::from_secs(31536000))
+                .public()
+                .to_string(),
+            "public, max-age=31536000, immutable"
+        );
+        assert_eq!(
+            CachePolicy::new()
+                .private()
+                .no_cache()
+                .s_maxage(Duration::from_millis(1500))
+                .to_string(),
+            "private, no-cache, s-maxage=1"
+        );
+    }
+}
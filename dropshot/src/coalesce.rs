@@ -0,0 +1,312 @@
+// Copyright 2023 Oxide Computer Company
+//! Coalescing of identical concurrent GET requests
+//!
+//! When an endpoint opts in (with the `coalesce` attribute of the `endpoint`
+//! macro or [`ApiEndpoint::coalesce()`](crate::ApiEndpoint::coalesce)), a GET
+//! request that arrives while an identical one is already being handled does
+//! not invoke the handler again.  Instead, it waits for the first request to
+//! finish and gets a copy of its response.  Requests are identical if they have
+//! the same path, query string, and values for the headers that the endpoint
+//! lists (e.g., `Accept` or `Authorization`, if the response depends on them).
+//!
+//! Responses to coalesced requests are buffered in their entirety, so this is
+//! not suitable for endpoints that stream large responses.
+
+use crate::error::HttpError;
+use crate::Body;
+
+use bytes::Bytes;
+use http::HeaderMap;
+use http::HeaderValue;
+use http::StatusCode;
+use hyper::Response;
+use std::collections::HashMap;
+use std::future::Future;
+use std::sync::Arc;
+use std::sync::Mutex;
+use tokio::sync::watch;
+
+/// Identifies requests that can share a response
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+pub(crate) struct CoalesceKey {
+    tenant: Option<String>,
+    uri: String,
+    headers: Vec<Option<HeaderValue>>,
+}
+
+impl CoalesceKey {
+    pub fn new(
+        uri: &http::Uri,
+        request_headers: &HeaderMap,
+        key_headers: &[String],
+        tenant: Option<&str>,
+    ) -> CoalesceKey {
+        CoalesceKey {
+            tenant: tenant.map(str::to_string),
+            uri: uri
+                .path_and_query()
+                .map(|p| p.as_str())
+                .unwrap_or("/")
+                .to_string(),
+            headers: key_headers
+                .iter()
+                .map(|name| request_headers.get(name.as_str()).cloned())
+                .collect(),
+        }
+    }
+}
+
+/// Result of the handler for the request that was actually executed, in a
+/// form that can be copied for each of the requests that waited on it
+#[derive(Clone, Debug)]
+enum Outcome {
+    Response {
+        status: StatusCode,
+        headers: HeaderMap,
+        body: Bytes,
+    },
+    Error {
+        status_code: StatusCode,
+        error_code: Option<String>,
+        external_message: String,
+        internal_message: String,
+    },
+}
+
+impl Outcome {
+    fn into_result(self) -> Result<Response<Body>, HttpError> {
+        match self {
+            Outcome::Response { status, headers, body } => {
+                let mut response = Response::new(Body::from(body));
+                *response.status_mut() = status;
+                *response.headers_mut() = headers;
+                Ok(response)
+            }
+            Outcome::Error {
+                status_code,
+                error_code,
+                external_message,
+                internal_message,
+            } => Err(HttpError {
+                status_code,
+                error_code,
+                external_message,
+                internal_message,
+            }),
+        }
+    }
+}
+
+type InFlight = HashMap<CoalesceKey, watch::Receiver<Option<Outcome>>>;
+
+/// Tracks the coalescable requests currently being handled
+#[derive(Debug, Default)]
+pub(crate) struct RequestCoalescer {
+    in_flight: Arc<Mutex<InFlight>>,
+}
+
+/// Removes a request from the in-flight set when the request that's executing
+/// it completes or is cancelled.  In the latter case, the waiters see the
+/// channel close and execute the request themselves.
+struct InFlightGuard {
+    in_flight: Arc<Mutex<InFlight>>,
+    key: CoalesceKey,
+}
+
+impl Drop for InFlightGuard {
+    fn drop(&mut self) {
+        self.in_flight.lock().unwrap().remove(&self.key);
+    }
+}
+
+impl RequestCoalescer {
+    /// Runs the handler future produced by `handler`, unless an identical
+    /// request is already in flight, in which case this returns a copy of that
+    /// request's result.
+    pub async fn run<F, Fut>(
+        &self,
+        key: CoalesceKey,
+        handler: F,
+    ) -> Result<Response<Body>, HttpError>
+    where
+        F: FnOnce() -> Fut,
+        Fut: Future<Output = Result<Response<Body>, HttpError>>,
+    {
+        let existing = {
+            let mut in_flight = self.in_flight.lock().unwrap();
+            match in_flight.get(&key) {
+                Some(rx) => Err(rx.clone()),
+                None => {
+                    let (tx, rx) = watch::channel(None);
+                    in_flight.insert(key.clone(), rx);
+                    Ok(tx)
+                }
+            }
+        };
+
+        let tx = match existing {
+            Ok(tx) => tx,
+            Err(mut rx) => loop {
+                let outcome = rx.borrow().clone();
+                if let Some(outcome) = outcome {
+                    return outcome.into_result();
+                }
+                if rx.changed().await.is_err() {
+                    // The request we were waiting on was cancelled.
+                    return handler().await;
+                }
+            },
+        };
+
+        let _guard =
+            InFlightGuard { in_flight: Arc::clone(&self.in_flight), key };
+        let outcome = match handler().await {
+            Ok(response) => {
+                let (parts, body) = response.into_parts();
+                let body = body.to_bytes().await.map_err(|e| {
+                    HttpError::for_internal_error(format!(
+                        "reading response body: {}",
+                        e
+                    ))
+                })?;
+                Outcome::Response {
+                    status: parts.status,
+                    headers: parts.headers,
+                    body,
+                }
+            }
+            Err(error) => Outcome::Error {
+                status_code: error.status_code,
+                error_code: error.error_code,
+                external_message: error.external_message,
+                internal_message: error.internal_message,
+            },
+        };
+        // There may be no waiters, in which case there's nobody to tell.
+        let _ = tx.send(Some(outcome.clone()));
+        outcome.into_result()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::CoalesceKey;
+    use super::RequestCoalescer;
+    use crate::Body;
+    use http::HeaderMap;
+    use http::StatusCode;
+    use hyper::Response;
+    use std::sync::atomic::AtomicUsize;
+    use std::sync::atomic::Ordering;
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    #[test]
+    fn test_coalesce_key() {
+        let uri: http::Uri = "/things?limit=10".parse().unwrap();
+        let mut headers = HeaderMap::new();
+        headers.insert("accept", "application/json".parse().unwrap());
+        headers.insert("x-trace", "abc".parse().unwrap());
+        let key_headers = vec![String::from("accept")];
+
+        let key1 = CoalesceKey::new(&uri, &headers, &key_headers, None);
+        headers.insert("x-trace", "def".parse().unwrap());
+        let key2 = CoalesceKey::new(&uri, &headers, &key_headers, None);
+        assert_eq!(key1, key2);
+
+        headers.insert("accept", "text/plain".parse().unwrap());
+        let key3 = CoalesceKey::new(&uri, &headers, &key_headers, None);
+        assert_ne!(key1, key3);
+
+        let uri: http::Uri = "/things?limit=20".parse().unwrap();
+        let key4 = CoalesceKey::new(&uri, &headers, &key_headers, None);
+        assert_ne!(key3, key4);
+
+        let key5 = CoalesceKey::new(&uri, &headers, &key_headers, Some("acme"));
+        assert_ne!(key4, key5);
+    }
+
+    #[tokio::test]
+    async fn test_coalesce() {
+        let coalescer = Arc::new(RequestCoalescer::default());
+        let nexecuted = Arc::new(AtomicUsize::new(0));
+        let key = CoalesceKey::new(
+            &"/things".parse().unwrap(),
+            &HeaderMap::new(),
+            &[],
+            None,
+        );
+
+        let tasks = (0..8)
+            .map(|_| {
+                let coalescer = Arc::clone(&coalescer);
+                let nexecuted = Arc::clone(&nexecuted);
+                let key = key.clone();
+                tokio::spawn(async move {
+                    let response = coalescer
+                        .run(key, || async move {
+                            nexecuted.fetch_add(1, Ordering::SeqCst);
+                            tokio::time::sleep(Duration::from_millis(200))
+                                .await;
+                            Ok(Response::new(Body::from("things")))
+                        })
+                        .await
+                        .unwrap();
+                    response.into_body().to_bytes().await.unwrap()
+                })
+            })
+            .collect::<Vec<_>>();
+        for task in tasks {
+            assert_eq!(&task.await.unwrap()[..], b"things");
+        }
+        assert_eq!(nexecuted.load(Ordering::SeqCst), 1);
+        assert!(coalescer.in_flight.lock().unwrap().is_empty());
+
+        // Once that request has completed, the next one is executed again.
+        let error = coalescer
+            .run(key, || async {
+                Err(crate::HttpError::for_status(None, StatusCode::NOT_FOUND))
+            })
+            .await
+            .unwrap_err();
+        assert_eq!(error.status_code, StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn test_coalesce_cancelled() {
+        let coalescer = Arc::new(RequestCoalescer::default());
+        let key = CoalesceKey::new(
+            &"/things".parse().unwrap(),
+            &HeaderMap::new(),
+            &[],
+            None,
+        );
+
+        // Start a request that never finishes, then cancel it while another
+        // request is waiting on it.  The waiter should run its own handler.
+        let leader = {
+            let coalescer = Arc::clone(&coalescer);
+            let key = key.clone();
+            tokio::spawn(async move {
+                coalescer.run(key, || futures::future::pending()).await.unwrap()
+            })
+        };
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        let waiter = {
+            let coalescer = Arc::clone(&coalescer);
+            tokio::spawn(async move {
+                coalescer
+                    .run(key, || async {
+                        Ok(Response::new(Body::from("fallback")))
+                    })
+                    .await
+                    .unwrap()
+            })
+        };
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        leader.abort();
+        let response = waiter.await.unwrap();
+        let body = response.into_body().to_bytes().await.unwrap();
+        assert_eq!(&body[..], b"fallback");
+    }
+}
@@ -0,0 +1,271 @@
+// Copyright 2023 Oxide Computer Company
+//! Compression of response bodies (`Accept-Encoding`)
+//!
+//! With the `compression` feature enabled and `ConfigDropshot::compression`
+//! set, response bodies are compressed with `zstd`, `br` (Brotli), or `gzip`,
+//! whichever the client prefers according to its `Accept-Encoding` header
+//! (the server's order of preference breaks ties).  A response is only
+//! compressed if:
+//!
+//! * its endpoint doesn't opt out (with `compression = false` in the
+//!   `endpoint` macro, or [`ApiEndpoint::compression()`](
+//!   crate::ApiEndpoint::compression)),
+//! * it has a body (it's not a response to a `HEAD` request, and its status
+//!   isn't 204 or 304) that isn't already encoded (it has no
+//!   `Content-Encoding`) and isn't a partial range (206),
+//! * it doesn't declare trailers (in a `Trailer` header), which compression
+//!   would lose,
+//! * its `Content-Type` is in the configured allowlist,
+//! * its size is known to be at least the configured minimum (bodies whose
+//!   size isn't known ahead of time, like streams, are always compressed), and
+//! * it doesn't have `Cache-Control: no-transform`.
+//!
+//! Every response that would be compressed for some client carries
+//! `Vary: Accept-Encoding`.  Compressed responses lose their `Content-Length`
+//! (they're sent chunked), and a strong `ETag` becomes weak, since the
+//! compressed bytes differ from the uncompressed ones.
+
+use crate::config::CompressionCoding;
+use crate::config::ConfigCompression;
+use crate::http_util::negotiate_content_coding;
+use crate::Body;
+
+use async_compression::tokio::bufread::BrotliEncoder;
+use async_compression::tokio::bufread::GzipEncoder;
+use async_compression::tokio::bufread::ZstdEncoder;
+use futures::TryStreamExt;
+use http::header;
+use http::HeaderMap;
+use http::HeaderValue;
+use http::Method;
+use http::Response;
+use http::StatusCode;
+use http_body::Body as HttpBody;
+use std::io;
+use tokio_util::io::ReaderStream;
+use tokio_util::io::StreamReader;
+
+/// Compresses response bodies according to a server's `ConfigCompression`
+#[derive(Debug)]
+pub(crate) struct ResponseCompressor {
+    /// codings the server may use, in its order of preference
+    codings: Vec<CompressionCoding>,
+    min_size_bytes: u64,
+    /// lowercase media types (or `type/*` wildcards) that may be compressed
+    content_types: Vec<String>,
+}
+
+impl ResponseCompressor {
+    pub(crate) fn new(config: &ConfigCompression) -> ResponseCompressor {
+        ResponseCompressor {
+            codings: config.codings.clone(),
+            min_size_bytes: config.min_size_bytes,
+            content_types: config
+                .content_types
+                .iter()
+                .map(|t| t.to_ascii_lowercase())
+                .collect(),
+        }
+    }
+
+    /// Chooses the coding for the response to a request with the given
+    /// headers, if the client accepts any of the server's codings.
+    pub(crate) fn negotiate(
+        &self,
+        headers: &HeaderMap,
+    ) -> Option<CompressionCoding> {
+        negotiate_content_coding(headers, &self.codings)
+    }
+
+    /// Compresses the body of `response` with `coding` (if it's present and
+    /// the response is eligible), and notes the response's dependence on the
+    /// request's `Accept-Encoding` header.
+    pub(crate) fn compress(
+        &self,
+        method: &Method,
+        response: Response<Body>,
+        coding: Option<CompressionCoding>,
+    ) -> Response<Body> {
+        if !self.is_eligible(method, &response) {
+            return response;
+        }
+
+        let (mut parts, body) = response.into_parts();
+        // The response may already say this (e.g., if it's a static file that
+        // may have been served pre-compressed).
+        let varies = parts
+            .headers
+            .get_all(header::VARY)
+            .iter()
+            .filter_map(|v| v.to_str().ok())
+            .flat_map(|v| v.split(','))
+            .any(|name| name.trim().eq_ignore_ascii_case("accept-encoding"));
+        if !varies {
+            parts.headers.append(
+                header::VARY,
+                HeaderValue::from_static("accept-encoding"),
+            );
+        }
+        let too_small = HttpBody::size_hint(&body)
+            .exact()
+            .map_or(false, |len| len < self.min_size_bytes);
+        let coding = match coding {
+            Some(coding) if !too_small => coding,
+            _ => return Response::from_parts(parts, body),
+        };
+
+        parts.headers.remove(header::CONTENT_LENGTH);
+        parts.headers.insert(
+            header::CONTENT_ENCODING,
+            HeaderValue::from_static(coding.name()),
+        );
+        if let Some(etag) = parts.headers.get(header::ETAG) {
+            if !etag.as_bytes().starts_with(b"W/") {
+                let mut weak = b"W/".to_vec();
+                weak.extend_from_slice(etag.as_bytes());
+                if let Ok(weak) = HeaderValue::from_bytes(&weak) {
+                    parts.headers.insert(header::ETAG, weak);
+                }
+            }
+        }
+        Response::from_parts(parts, compress_body(body, coding))
+    }
+
+    /// Returns whether `response` (to a request with the given method) may be
+    /// compressed, regardless of what the client accepts.
+    fn is_eligible(&self, method: &Method, response: &Response<Body>) -> bool {
+        let status = response.status();
+        let headers = response.headers();
+        if *method == Method::HEAD
+            || status.is_informational()
+            || status == StatusCode::NO_CONTENT
+            || status == StatusCode::NOT_MODIFIED
+            || status == StatusCode::PARTIAL_CONTENT
+            || headers.contains_key(header::CONTENT_ENCODING)
+            || headers.contains_key(header::TRAILER)
+        {
+            return false;
+        }
+
+        let no_transform = headers
+            .get_all(header::CACHE_CONTROL)
+            .iter()
+            .filter_map(|v| v.to_str().ok())
+            .flat_map(|v| v.split(','))
+            .any(|directive| {
+                directive.trim().eq_ignore_ascii_case("no-transform")
+            });
+        if no_transform {
+            return false;
+        }
+
+        let media_type = match headers
+            .get(header::CONTENT_TYPE)
+            .and_then(|v| v.to_str().ok())
+        {
+            Some(value) => {
+                value.split(';').next().unwrap().trim().to_ascii_lowercase()
+            }
+            None => return false,
+        };
+        self.content_types.iter().any(|allowed| {
+            match allowed.strip_suffix("/*") {
+                Some(prefix) => media_type
+                    .split_once('/')
+                    .map_or(false, |(t, _)| t == prefix),
+                None => *allowed == media_type,
+            }
+        })
+    }
+}
+
+/// Returns a body whose contents are those of `body` compressed with `coding`.
+/// The compressed body is produced as `body` is read, without buffering it.
+fn compress_body(body: Body, coding: CompressionCoding) -> Body {
+    let reader = StreamReader::new(
+        body.map_err(|error| io::Error::new(io::ErrorKind::Other, error)),
+    );
+    match coding {
+        CompressionCoding::Gzip => {
+            Body::wrap_stream(ReaderStream::new(GzipEncoder::new(reader)))
+        }
+        CompressionCoding::Br => {
+            Body::wrap_stream(ReaderStream::new(BrotliEncoder::new(reader)))
+        }
+        CompressionCoding::Zstd => {
+            Body::wrap_stream(ReaderStream::new(ZstdEncoder::new(reader)))
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::ResponseCompressor;
+    use crate::config::CompressionCoding;
+    use crate::config::ConfigCompression;
+    use crate::Body;
+    use http::HeaderMap;
+    use http::Method;
+    use http::Response;
+    use http::StatusCode;
+
+    fn negotiate(accept_encoding: &str) -> Option<CompressionCoding> {
+        let compressor = ResponseCompressor::new(&ConfigCompression::default());
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            http::header::ACCEPT_ENCODING,
+            accept_encoding.parse().unwrap(),
+        );
+        compressor.negotiate(&headers)
+    }
+
+    #[test]
+    fn test_negotiate() {
+        let compressor = ResponseCompressor::new(&ConfigCompression::default());
+        assert_eq!(compressor.negotiate(&HeaderMap::new()), None);
+        assert_eq!(negotiate("identity"), None);
+        assert_eq!(negotiate("gzip"), Some(CompressionCoding::Gzip));
+        assert_eq!(negotiate("x-gzip"), Some(CompressionCoding::Gzip));
+        assert_eq!(negotiate("gzip, br"), Some(CompressionCoding::Br));
+        assert_eq!(
+            negotiate("gzip;q=1.0, br;q=0.5"),
+            Some(CompressionCoding::Gzip)
+        );
+        assert_eq!(negotiate("*"), Some(CompressionCoding::Zstd));
+        assert_eq!(negotiate("*, zstd;q=0"), Some(CompressionCoding::Br));
+        assert_eq!(negotiate("gzip;q=0"), None);
+    }
+
+    #[test]
+    fn test_eligible() {
+        let compressor = ResponseCompressor::new(&ConfigCompression::default());
+        let response = |status: StatusCode, content_type: &str| {
+            Response::builder()
+                .status(status)
+                .header(http::header::CONTENT_TYPE, content_type)
+                .body(Body::empty())
+                .unwrap()
+        };
+
+        let json = response(StatusCode::OK, "application/json");
+        assert!(compressor.is_eligible(&Method::GET, &json));
+        assert!(!compressor.is_eligible(&Method::HEAD, &json));
+        assert!(compressor.is_eligible(
+            &Method::GET,
+            &response(StatusCode::OK, "text/html; charset=utf-8")
+        ));
+        assert!(!compressor
+            .is_eligible(&Method::GET, &response(StatusCode::OK, "image/png")));
+        assert!(!compressor.is_eligible(
+            &Method::GET,
+            &response(StatusCode::NOT_MODIFIED, "application/json")
+        ));
+
+        let mut no_transform = response(StatusCode::OK, "application/json");
+        no_transform.headers_mut().insert(
+            http::header::CACHE_CONTROL,
+            "max-age=60, no-transform".parse().unwrap(),
+        );
+        assert!(!compressor.is_eligible(&Method::GET, &no_transform));
+    }
+}
@@ -0,0 +1,382 @@
+// Copyright 2023 Oxide Computer Company
+
+//! Entity tags and responses to conditional requests
+
+use crate::api_description::ApiEndpointHeader;
+use crate::api_description::ApiEndpointResponse;
+use crate::api_description::ApiSchemaGenerator;
+use crate::error::HttpError;
+use crate::handler::HttpHandlerResult;
+use crate::handler::HttpResponse;
+use crate::Body;
+
+use chrono::DateTime;
+use chrono::NaiveDateTime;
+use chrono::TimeZone;
+use chrono::Utc;
+use http::header;
+use http::HeaderMap;
+use http::Response;
+use http::StatusCode;
+use std::fmt;
+
+/// An entity tag (the value of an `ETag` header), which identifies a particular
+/// version of a resource.
+///
+/// A strong entity tag changes whenever the representation of the resource
+/// changes at all; a weak one only when it changes meaningfully.  See
+/// [`EntityTag::for_content()`] for computing a strong tag from the bytes of
+/// the representation.  The `Display` implementation produces the tag as it
+/// appears in headers, quoted and (if weak) prefixed with `W/`.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct EntityTag {
+    tag: String,
+    weak: bool,
+}
+
+impl EntityTag {
+    /// Returns a strong entity tag with the given opaque value (without
+    /// quotes).
+    ///
+    /// # Panics
+    ///
+    /// Panics if `tag` contains a double quote or a character that can't
+    /// appear in a header.
+    pub fn strong<S: Into<String>>(tag: S) -> EntityTag {
+        EntityTag::new(tag.into(), false)
+    }
+
+    /// Returns a weak entity tag with the given opaque value (without quotes).
+    ///
+    /// # Panics
+    ///
+    /// Panics if `tag` contains a double quote or a character that can't
+    /// appear in a header.
+    pub fn weak<S: Into<String>>(tag: S) -> EntityTag {
+        EntityTag::new(tag.into(), true)
+    }
+
+    fn new(tag: String, weak: bool) -> EntityTag {
+        assert!(
+            is_valid_tag(&tag),
+            "invalid character in entity tag: {:?}",
+            tag
+        );
+        EntityTag { tag, weak }
+    }
+
+    /// Returns an entity tag without checking that `tag` is valid, for
+    /// comparing against tags supplied by the handler as plain strings.
+    pub(crate) fn new_unchecked(tag: &str, weak: bool) -> EntityTag {
+        EntityTag { tag: tag.to_string(), weak }
+    }
+
+    /// Returns a strong entity tag derived from a (non-cryptographic) hash of
+    /// `content`, the representation of the resource.  The same content
+    /// always has the same tag, even across different builds of the server.
+    pub fn for_content(content: &[u8]) -> EntityTag {
+        // 64-bit FNV-1a
+        let hash = content.iter().fold(0xcbf29ce484222325u64, |hash, byte| {
+            (hash ^ u64::from(*byte)).wrapping_mul(0x100000001b3)
+        });
+        EntityTag { tag: format!("{:016x}", hash), weak: false }
+    }
+
+    /// Returns the opaque value of the tag (without quotes).
+    pub fn tag(&self) -> &str {
+        &self.tag
+    }
+
+    /// Returns true if this is a weak entity tag.
+    pub fn is_weak(&self) -> bool {
+        self.weak
+    }
+
+    /// Compares two entity tags using the strong comparison function of RFC
+    /// 9110: both must be strong, with the same opaque value.
+    pub fn strong_eq(&self, other: &EntityTag) -> bool {
+        !self.weak && !other.weak && self.tag == other.tag
+    }
+
+    /// Compares two entity tags using the weak comparison function of RFC
+    /// 9110: their opaque values must be the same, whether or not either is
+    /// weak.
+    pub fn weak_eq(&self, other: &EntityTag) -> bool {
+        self.tag == other.tag
+    }
+
+    /// Parses one entity tag as it appears in a header (e.g., `"abc"` or
+    /// `W/"abc"`).
+    pub(crate) fn parse(value: &str) -> Option<EntityTag> {
+        let (weak, quoted) = match value.strip_prefix("W/") {
+            Some(rest) => (true, rest),
+            None => (false, value),
+        };
+        quoted
+            .strip_prefix('"')
+            .and_then(|t| t.strip_suffix('"'))
+            .filter(|t| is_valid_tag(t))
+            .map(|t| EntityTag { tag: t.to_string(), weak })
+    }
+}
+
+/// Returns whether `tag` may be the opaque value of an entity tag (RFC 9110's
+/// `etagc`: visible ASCII other than the double quote, or obs-text).
+fn is_valid_tag(tag: &str) -> bool {
+    tag.bytes().all(|b| b == 0x21 || (0x23..=0x7e).contains(&b) || b >= 0x80)
+}
+
+impl fmt::Display for EntityTag {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.weak {
+            write!(f, "W/\"{}\"", self.tag)
+        } else {
+            write!(f, "\"{}\"", self.tag)
+        }
+    }
+}
+
+/// The result of evaluating a request's preconditions with
+/// [`Preconditions::evaluate()`](crate::Preconditions::evaluate).
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Precondition {
+    /// The preconditions hold (or there are none): the request should be
+    /// handled normally.
+    Proceed,
+    /// The client already has the current version of the resource, so the
+    /// server should send a 304 ("Not Modified") response.  This only
+    /// happens for `GET` and `HEAD` requests.
+    NotModified,
+    /// The preconditions don't hold, so the server should send a 412
+    /// ("Precondition Failed") response.
+    Failed,
+}
+
+/// Returns the 412 ("Precondition Failed") error sent when a request's
+/// preconditions don't hold.
+pub(crate) fn precondition_failed() -> HttpError {
+    HttpError::for_client_error(
+        Some(String::from("PreconditionFailed")),
+        StatusCode::PRECONDITION_FAILED,
+        String::from("the resource has been modified"),
+    )
+}
+
+/// `HttpResponseConditional<T>` is the response to a request that may be
+/// conditional.  Depending on the request's preconditions, it's either the
+/// response `T`, a 304 ("Not Modified") response without a body, or a 412
+/// ("Precondition Failed") error.  In the first two cases, the response
+/// carries the resource's `ETag` and `Last-Modified` headers (whichever are
+/// known).  All three responses, and both headers, are described in the
+/// OpenAPI document.
+///
+/// These are constructed with [`Preconditions::respond()`](
+/// crate::Preconditions::respond), which only produces the response `T` if it
+/// will be sent.
+///
+/// ```
+/// use dropshot::endpoint;
+/// use dropshot::EntityTag;
+/// use dropshot::HttpError;
+/// use dropshot::HttpResponseConditional;
+/// use dropshot::HttpResponseOk;
+/// use dropshot::Preconditions;
+/// use dropshot::RequestContext;
+///
+/// #[endpoint {
+///     method = GET,
+///     path = "/widget",
+/// }]
+/// async fn widget_get(
+///     _rqctx: RequestContext<()>,
+///     preconditions: Preconditions,
+/// ) -> Result<HttpResponseConditional<HttpResponseOk<String>>, HttpError> {
+///     let widget = String::from("sprocket"); // e.g., from the database
+///     let etag = EntityTag::for_content(widget.as_bytes());
+///     Ok(preconditions.respond(Some(etag), None, || HttpResponseOk(widget)))
+/// }
+/// ```
+pub struct HttpResponseConditional<T: HttpResponse + Send + Sync + 'static> {
+    outcome: ConditionalOutcome<T>,
+    etag: Option<EntityTag>,
+    last_modified: Option<DateTime<Utc>>,
+}
+
+enum ConditionalOutcome<T> {
+    Proceed(T),
+    NotModified,
+    Failed,
+}
+
+impl<T: HttpResponse + Send + Sync + 'static> HttpResponseConditional<T> {
+    pub(crate) fn new<F>(
+        precondition: Precondition,
+        etag: Option<EntityTag>,
+        last_modified: Option<DateTime<Utc>>,
+        response: F,
+    ) -> HttpResponseConditional<T>
+    where
+        F: FnOnce() -> T,
+    {
+        let outcome = match precondition {
+            Precondition::Proceed => ConditionalOutcome::Proceed(response()),
+            Precondition::NotModified => ConditionalOutcome::NotModified,
+            Precondition::Failed => ConditionalOutcome::Failed,
+        };
+        HttpResponseConditional { outcome, etag, last_modified }
+    }
+}
+
+impl<T: HttpResponse + Send + Sync + 'static> HttpResponse
+    for HttpResponseConditional<T>
+{
+    fn to_result(self) -> HttpHandlerResult {
+        let HttpResponseConditional { outcome, etag, last_modified } = self;
+        let mut response = match outcome {
+            ConditionalOutcome::Proceed(inner) => inner.to_result()?,
+            ConditionalOutcome::NotModified => Response::builder()
+                .status(StatusCode::NOT_MODIFIED)
+                .body(Body::empty())?,
+            ConditionalOutcome::Failed => return Err(precondition_failed()),
+        };
+        insert_validators(response.headers_mut(), etag, last_modified)?;
+        Ok(response)
+    }
+
+    fn response_metadata() -> ApiEndpointResponse {
+        let mut metadata = T::response_metadata();
+        let header = |name: &str, description: &str| ApiEndpointHeader {
+            name: name.to_string(),
+            description: Some(description.to_string()),
+            schema: ApiSchemaGenerator::Static {
+                schema: Box::new(
+                    schemars::gen::SchemaGenerator::default()
+                        .subschema_for::<String>(),
+                ),
+                dependencies: indexmap::IndexMap::new(),
+            },
+            required: false,
+        };
+        metadata.headers.push(header(
+            "ETag",
+            "entity tag of the current version of the resource",
+        ));
+        metadata.headers.push(header(
+            "Last-Modified",
+            "time at which the resource was last modified",
+        ));
+        metadata.additional_responses.push((
+            StatusCode::NOT_MODIFIED,
+            String::from("resource not modified"),
+        ));
+        metadata.additional_responses.push((
+            StatusCode::PRECONDITION_FAILED,
+            String::from("precondition failed"),
+        ));
+        metadata
+    }
+}
+
+/// Adds the `ETag` and `Last-Modified` headers (whichever are known) to a
+/// response.
+fn insert_validators(
+    headers: &mut HeaderMap,
+    etag: Option<EntityTag>,
+    last_modified: Option<DateTime<Utc>>,
+) -> Result<(), HttpError> {
+    let invalid = |e: header::InvalidHeaderValue| {
+        HttpError::for_internal_error(e.to_string())
+    };
+    if let Some(etag) = etag {
+        headers
+            .insert(header::ETAG, etag.to_string().parse().map_err(invalid)?);
+    }
+    if let Some(last_modified) = last_modified {
+        headers.insert(
+            header::LAST_MODIFIED,
+            http_date(last_modified).parse().map_err(invalid)?,
+        );
+    }
+    Ok(())
+}
+
+/// Formats `time` as an HTTP date (e.g., `Wed, 21 Oct 2015 07:28:00 GMT`).
+pub(crate) fn http_date(time: DateTime<Utc>) -> String {
+    time.format("%a, %d %b %Y %H:%M:%S GMT").to_string()
+}
+
+/// Parses an HTTP date in any of the three formats that RFC 9110 requires
+/// recipients to accept: the preferred format produced by [`http_date()`]
+/// (e.g., `Sun, 06 Nov 1994 08:49:37 GMT`) and the obsolete RFC 850 (e.g.,
+/// `Sunday, 06-Nov-94 08:49:37 GMT`) and asctime (e.g.,
+/// `Sun Nov  6 08:49:37 1994`) formats.  Two-digit years are in 1969 through
+/// 2068.
+pub(crate) fn parse_http_date(value: &str) -> Option<DateTime<Utc>> {
+    [
+        "%a, %d %b %Y %H:%M:%S GMT",
+        "%A, %d-%b-%y %H:%M:%S GMT",
+        "%a %b %e %H:%M:%S %Y",
+    ]
+    .iter()
+    .find_map(|format| NaiveDateTime::parse_from_str(value, format).ok())
+    .map(|time| Utc.from_utc_datetime(&time))
+}
+
+#[cfg(test)]
+mod test {
+    use super::http_date;
+    use super::parse_http_date;
+    use super::EntityTag;
+    use chrono::TimeZone;
+    use chrono::Utc;
+
+    #[test]
+    fn test_http_date() {
+        let time = Utc.with_ymd_and_hms(1994, 11, 6, 8, 49, 37).unwrap();
+        assert_eq!(http_date(time), "Sun, 06 Nov 1994 08:49:37 GMT");
+        for value in [
+            "Sun, 06 Nov 1994 08:49:37 GMT",
+            "Sunday, 06-Nov-94 08:49:37 GMT",
+            "Sun Nov  6 08:49:37 1994",
+        ] {
+            assert_eq!(parse_http_date(value), Some(time), "{}", value);
+        }
+        assert_eq!(
+            parse_http_date("Wed Oct 21 07:28:00 2015"),
+            Some(Utc.with_ymd_and_hms(2015, 10, 21, 7, 28, 0).unwrap())
+        );
+
+        for value in [
+            "yesterday",
+            "Sun, 06 Nov 1994 08:49:37",
+            // the wrong day of the week
+            "Mon, 06 Nov 1994 08:49:37 GMT",
+            "Sun, 06 Nov 1994 08:49:37 GMT trailing",
+        ] {
+            assert_eq!(parse_http_date(value), None, "{}", value);
+        }
+    }
+
+    #[test]
+    fn test_entity_tag() {
+        let strong = EntityTag::strong("abc");
+        let weak = EntityTag::weak("abc");
+        assert_eq!(strong.to_string(), "\"abc\"");
+        assert_eq!(weak.to_string(), "W/\"abc\"");
+        assert_eq!(EntityTag::parse("\"abc\""), Some(strong.clone()));
+        assert_eq!(EntityTag::parse("W/\"abc\""), Some(weak.clone()));
+        assert_eq!(EntityTag::parse("abc"), None);
+        assert_eq!(EntityTag::parse("\"a\"c\""), None);
+
+        assert!(strong.strong_eq(&strong));
+        assert!(!strong.strong_eq(&weak));
+        assert!(strong.weak_eq(&weak));
+        assert!(!strong.weak_eq(&EntityTag::strong("abd")));
+
+        let tag = EntityTag::for_content(b"hello");
+        assert_eq!(tag, EntityTag::for_content(b"hello"));
+        assert_ne!(tag, EntityTag::for_content(b"hellp"));
+        assert_eq!(tag.tag(), "a430d84680aabd0b");
+        assert!(!tag.is_weak());
+    }
+}
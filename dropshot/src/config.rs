@@ -55,6 +55,730 @@ pub struct ConfigDropshot {
 
     /// If present, enables TLS with the given configuration
     pub tls: Option<ConfigTls>,
+
+    /// Sampling rules for the "request completed" log entries of successful
+    /// requests.  Requests that fail are always logged.
+    pub request_log_sampling: Vec<ConfigRequestLogSampling>,
+
+    /// Parts of requests that are masked before they're logged
+    pub log_redaction: ConfigLogRedaction,
+
+    /// Latency thresholds above which requests to particular routes are
+    /// reported with a "slow request" warning
+    pub slow_request_logging: Vec<ConfigSlowRequestLogging>,
+
+    /// If present, limits the rate (in bytes per second) at which response
+    /// bodies are sent on each connection.  This can be changed while the
+    /// server is running with `HttpServer::set_connection_bandwidth_limit()`.
+    pub connection_bandwidth_limit: Option<u64>,
+
+    /// If true, request bodies and query and path parameters are validated
+    /// against their JSON schemas after they're deserialized, so that the
+    /// constraints expressed there (e.g., with `#[schemars(...)]` attributes
+    /// for formats, patterns, lengths, and ranges) are enforced.  Requests that
+    /// violate them fail with a 400 error listing each violation.
+    pub schema_validation: bool,
+
+    /// If true, requests whose query string includes parameters that the
+    /// endpoint's `Query` type doesn't declare fail with a 400 error naming
+    /// them.  By default, such parameters are ignored.
+    pub strict_query_params: bool,
+
+    /// Maximum allowed size of each part of a `multipart/form-data` request
+    /// body read with `MultipartBody`.  The body as a whole is still limited
+    /// by `request_body_max_bytes`.
+    pub multipart_part_max_bytes: Option<usize>,
+
+    /// Maximum allowed size of a compressed request body (one with a
+    /// `Content-Encoding` header) once it has been decompressed.  If this is
+    /// not set, the decompressed body is limited to the same size as the
+    /// compressed one.
+    pub request_body_decompressed_max_bytes: Option<usize>,
+
+    /// What happens to a request's handler when the client disconnects before
+    /// the response has been sent
+    pub handler_task_mode: HandlerTaskMode,
+
+    /// If present, HTTP/1 requests are inspected before they're parsed and
+    /// those that are ambiguously framed or malformed in ways that proxies
+    /// may interpret differently are rejected.  See [`ConfigStrictHttp`].
+    pub strict_http: Option<ConfigStrictHttp>,
+
+    /// If present, response bodies are compressed according to the request's
+    /// `Accept-Encoding` header.  This requires the `compression` feature.
+    /// See [`ConfigCompression`].
+    pub compression: Option<ConfigCompression>,
+
+    /// If true, the messages of error responses with 500-level status codes
+    /// are replaced with the status code's reason (e.g., "Internal Server
+    /// Error") and the request's id, so that no details of server errors reach
+    /// clients.  The original messages and error code are logged along with
+    /// the request id, so that operators can find them from a client's report.
+    pub redact_server_errors: bool,
+
+    /// If true, requests for a path that has endpoints, but none for the
+    /// request's method, fail with `404 Not Found` (as they did in earlier
+    /// versions of Dropshot) rather than `405 Method Not Allowed` with an
+    /// `Allow` header listing the path's methods.
+    pub method_not_allowed_as_not_found: bool,
+
+    /// If present, `POST` requests with an `X-HTTP-Method-Override` header
+    /// are handled as though they'd been made with the method that the header
+    /// names, for clients behind proxies that only pass `GET` and `POST`.  See
+    /// [`ConfigMethodOverride`].
+    pub method_override: Option<ConfigMethodOverride>,
+
+    /// If present, how long (in milliseconds) `HttpServer::close()` waits for
+    /// requests in progress to complete before it closes their connections.
+    /// By default, it waits indefinitely.  See
+    /// `HttpServer::close_with_report()`.
+    pub drain_timeout_ms: Option<u64>,
+
+    /// If present, how long (in milliseconds) a connection may stay open
+    /// without a request in progress before the server closes it.  By
+    /// default, connections stay open until the client closes them.  Behind a
+    /// proxy or load balancer, this should be longer than the proxy's own
+    /// idle timeout for its connections to the server, so that the server
+    /// doesn't close a connection just as the proxy sends a request on it.
+    pub keep_alive_timeout_ms: Option<u64>,
+
+    /// If present, the number of requests after which the server closes a
+    /// connection (asking HTTP/1 clients to close it with `Connection: close`
+    /// on the response to the last request).  This spreads long-lived clients
+    /// across the instances behind a load balancer.  By default, there's no
+    /// limit.
+    pub max_requests_per_connection: Option<u64>,
+
+    /// If present, how long (in milliseconds) a connection may go without
+    /// anything being read from or written to it, even with a request in
+    /// progress, before the server closes it.  This includes time spent by
+    /// request handlers, so it should be longer than the slowest handler.
+    /// Connections that have been upgraded (e.g., to WebSockets) are exempt
+    /// from this and the other limits on a connection's lifetime.  These
+    /// limits apply to connections over TCP and Unix domain sockets, not
+    /// HTTP/3.
+    pub idle_timeout_ms: Option<u64>,
+
+    /// If present, how long (in milliseconds) a client has to send the whole
+    /// head (request line and headers) of an HTTP/1 request once it has begun
+    /// sending it.  Connections whose clients take longer, such as clients
+    /// that trickle the head a few bytes at a time to tie up the server
+    /// ("slow loris" attacks), are closed.  By default, there's no limit.
+    pub header_read_timeout_ms: Option<u64>,
+
+    /// If present, the maximum size (in bytes) of a request's head.  Larger
+    /// HTTP/1 requests are rejected with `431 Request Header Fields Too
+    /// Large`, and the connection is closed; HTTP/2 requests whose header list
+    /// is larger are refused.  For HTTP/1, this bounds the buffer into which
+    /// requests are read, so it must be at least 8192, and it's approximate
+    /// when requests are pipelined.  By default, hyper's limit of roughly
+    /// 400 KiB applies.
+    pub max_header_bytes: Option<usize>,
+
+    /// If present, the maximum number of headers in a request.  Requests with
+    /// more are rejected with `431 Request Header Fields Too Large`.  HTTP/1
+    /// requests with more than 100 headers are rejected regardless.
+    pub max_header_count: Option<usize>,
+
+    /// Addresses (e.g., `"10.0.0.1"`) or CIDR blocks (e.g., `"10.0.0.0/8"`)
+    /// of the proxies in front of the server, whose `Forwarded`,
+    /// `X-Forwarded-For`, and `X-Forwarded-Proto` headers identify the client
+    /// on whose behalf a request was made.  The effective client is available
+    /// from `RequestContext::client()` and logged as `client_addr`.  By
+    /// default, no proxies are trusted, and the client is always the peer on
+    /// the other end of the connection.
+    pub trusted_proxies: Vec<String>,
+
+    /// If present (and `tls` is configured), how often (in milliseconds) the
+    /// certificate, key, and CA files named by `tls` are checked for changes.
+    /// When any of them changes, the server's certificates are reloaded from
+    /// them without affecting established connections.  See also
+    /// `HttpServer::reload_tls()`.
+    pub tls_reload_interval_ms: Option<u64>,
+
+    /// Limits on the connections and requests that the server handles at
+    /// once, beyond which it sheds load with `503 Service Unavailable`
+    /// responses.  By default, there are no limits.  See [`ConfigLoadLimits`].
+    pub load_limits: ConfigLoadLimits,
+
+    /// If present (and `tls` is configured), the server also serves its API
+    /// over HTTP/3, and advertises that to clients of its TCP listener.  This
+    /// requires the `http3` feature.  See [`ConfigHttp3`].
+    pub http3: Option<ConfigHttp3>,
+
+    /// If present, the server listens on this Unix domain socket instead of
+    /// on `bind_address`, which is then ignored.  This isn't compatible with
+    /// `tls`.  See [`ConfigUnixSocket`].
+    pub unix_socket: Option<ConfigUnixSocket>,
+
+    /// If present, the server accepts connections on the listening TCP
+    /// socket that was passed to the process at this index (starting at 0)
+    /// using the systemd socket activation protocol (`LISTEN_FDS`), rather
+    /// than binding to `bind_address`, which is then ignored.  This isn't
+    /// compatible with `unix_socket`.  See also
+    /// `HttpServerStarter::new_with_tcp_listener()`.
+    pub listen_fd: Option<usize>,
+
+    /// Further addresses on which the server accepts connections, each with
+    /// its own TLS configuration.  Connections on all of the server's
+    /// listeners are served the same API, with the same private context, and
+    /// the listeners are shut down together.  See [`ConfigListener`].
+    pub additional_listeners: Vec<ConfigListener>,
+
+    /// If present, the server binds several sockets to `bind_address` with
+    /// `SO_REUSEPORT` and accepts connections on each of them with its own
+    /// task.  See [`ConfigReusePort`].
+    pub reuseport: Option<ConfigReusePort>,
+
+    /// Options for the server's TCP sockets: those it listens on and the
+    /// connections it accepts.  By default, the operating system's defaults
+    /// apply.  See [`ConfigSocketOptions`].
+    pub socket_options: ConfigSocketOptions,
+}
+
+/// Configuration for accepting connections on several sockets bound to the
+/// same address with `SO_REUSEPORT`, which the kernel spreads connections
+/// among.  This removes the bottleneck of a single task accepting every
+/// connection when clients connect and disconnect at high rates.
+///
+/// This isn't compatible with `unix_socket`, `listen_fd`, or
+/// `HttpServerStarter::new_with_tcp_listener()`, and it's not supported on
+/// illumos, Solaris, or Windows.  `HttpServer::acceptor_stats()` reports the
+/// number of connections accepted by each socket.
+///
+/// ```
+/// use dropshot::ConfigDropshot;
+///
+/// let config: ConfigDropshot = toml::from_str(
+///     r##"
+///         bind_address = "0.0.0.0:8080"
+///
+///         [reuseport]
+///         acceptors = 4
+///     "##
+/// ).unwrap();
+/// assert_eq!(config.reuseport.unwrap().acceptors, Some(4));
+/// ```
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize)]
+#[serde(default)]
+pub struct ConfigReusePort {
+    /// number of sockets to bind (at least 1).  By default, one per CPU
+    /// available to the process.
+    pub acceptors: Option<usize>,
+}
+
+/// Options for a server's TCP sockets (see `ConfigDropshot::socket_options`)
+///
+/// The buffer sizes and `ipv6_only` are set on the listening sockets, whose
+/// connections inherit the buffer sizes; the rest are set on each connection
+/// as it's accepted.  `ipv6_only` can only be set on listeners that the server
+/// binds itself (not those passed with `listen_fd` or
+/// `HttpServerStarter::new_with_tcp_listener()`).
+///
+/// ```
+/// use dropshot::ConfigDropshot;
+///
+/// let config: ConfigDropshot = toml::from_str(
+///     r##"
+///         bind_address = "[::]:8080"
+///
+///         [socket_options]
+///         tcp_nodelay = true
+///         tcp_keepalive_secs = 60
+///         ipv6_only = false
+///     "##
+/// ).unwrap();
+/// assert!(config.socket_options.tcp_nodelay);
+/// ```
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize)]
+#[serde(default)]
+pub struct ConfigSocketOptions {
+    /// whether to disable Nagle's algorithm on connections (`TCP_NODELAY`),
+    /// so that small writes are sent without waiting to be coalesced
+    pub tcp_nodelay: bool,
+    /// If present, TCP keepalive (`SO_KEEPALIVE`) is enabled on connections,
+    /// with probes sent after they've been idle for this long (in seconds).
+    /// This detects clients that vanished without closing their connections.
+    pub tcp_keepalive_secs: Option<u64>,
+    /// If present, the size (in bytes) of each socket's receive buffer
+    /// (`SO_RCVBUF`)
+    pub recv_buffer_size: Option<usize>,
+    /// If present, the size (in bytes) of each socket's send buffer
+    /// (`SO_SNDBUF`)
+    pub send_buffer_size: Option<usize>,
+    /// If present, whether a listener bound to an IPv6 address accepts only
+    /// IPv6 connections (`IPV6_V6ONLY`) or IPv4 connections too.
+    pub ipv6_only: Option<bool>,
+}
+
+/// Configuration for one of a server's additional listeners (see
+/// `ConfigDropshot::additional_listeners`), as for a server that listens on
+/// both an IPv4 and an IPv6 address, or on one port with TLS and another
+/// without.
+///
+/// Other than the address and TLS configuration, these listeners behave like
+/// the server's main one: for example, its load limits count connections on
+/// all of them.  `HttpServer::refresh_tls()` and the server's
+/// `tls_reload_interval_ms` only affect the main listener's certificates, and
+/// HTTP/3 is only served alongside the main listener.
+///
+/// ```
+/// use dropshot::ConfigDropshot;
+///
+/// let config: ConfigDropshot = toml::from_str(
+///     r##"
+///         bind_address = "0.0.0.0:443"
+///
+///         [tls]
+///         type = "AsFile"
+///         cert_file = "/path/to/certs.pem"
+///         key_file = "/path/to/key.pem"
+///
+///         [[additional_listeners]]
+///         bind_address = "[::]:443"
+///
+///         [additional_listeners.tls]
+///         type = "AsFile"
+///         cert_file = "/path/to/certs.pem"
+///         key_file = "/path/to/key.pem"
+///
+///         [[additional_listeners]]
+///         bind_address = "127.0.0.1:8080"
+///     "##
+/// ).unwrap();
+/// assert_eq!(config.additional_listeners.len(), 2);
+/// assert!(config.additional_listeners[1].tls.is_none());
+/// ```
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+pub struct ConfigListener {
+    /// IP address and TCP port to which to bind for accepting connections
+    pub bind_address: SocketAddr,
+    /// If present, connections on this listener use TLS with this
+    /// configuration (whether or not the server's main listener does).
+    pub tls: Option<ConfigTls>,
+}
+
+/// Configuration for listening on a Unix domain socket, as for local
+/// control-plane APIs that shouldn't be reachable over the network.
+///
+/// The socket file is created when the server is created and removed when it
+/// shuts down.  Creating the server fails if the file already exists (e.g.,
+/// because another server is listening on it, or a previous one didn't shut
+/// down cleanly).  Handlers can identify the process on the other end of a
+/// connection with [`crate::ConnectionInfo::peer_credentials()`].
+///
+/// Unix domain sockets have no IP addresses, so the remote and local
+/// addresses of their connections (and the server's local address) are
+/// reported as the unspecified address `0.0.0.0:0`.
+///
+/// ```
+/// use dropshot::ConfigDropshot;
+///
+/// let config: ConfigDropshot = toml::from_str(
+///     r##"
+///         [unix_socket]
+///         path = "/var/run/myapp/control.sock"
+///         mode = 0o660
+///         gid = 1000
+///     "##
+/// ).unwrap();
+/// assert_eq!(config.unix_socket.unwrap().mode, Some(0o660));
+/// ```
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+pub struct ConfigUnixSocket {
+    /// path of the socket file
+    pub path: PathBuf,
+    /// permissions with which to create the socket file (e.g., `0o660`).  By
+    /// default, these are determined by the process's umask.
+    pub mode: Option<u32>,
+    /// user to own the socket file.  By default, the process's user.
+    pub uid: Option<u32>,
+    /// group to own the socket file.  By default, the process's group.
+    pub gid: Option<u32>,
+}
+
+/// Experimental support for serving HTTP/3 (over QUIC) alongside HTTP/1 and
+/// HTTP/2.
+///
+/// The server accepts QUIC connections on a UDP socket, using the
+/// certificate chain from its `tls` configuration, and serves the same API on
+/// them.  Responses on its TCP listener include an `Alt-Svc` header telling
+/// clients that they can switch to HTTP/3.  `ConfigTls::Sni`, TLS client
+/// authentication, and certificate reloading aren't yet supported for HTTP/3.
+///
+/// ```
+/// use dropshot::ConfigDropshot;
+///
+/// let config: ConfigDropshot = toml::from_str(
+///     r##"
+///         bind_address = "[::]:443"
+///
+///         [tls]
+///         type = "AsFile"
+///         cert_file = "/path/to/certs.pem"
+///         key_file = "/path/to/key.pem"
+///
+///         [http3]
+///         alt_svc_max_age_secs = 3600
+///     "##
+/// ).unwrap();
+/// assert_eq!(config.http3.unwrap().alt_svc_max_age_secs, 3600);
+/// ```
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+#[serde(default)]
+pub struct ConfigHttp3 {
+    /// UDP address on which to accept QUIC connections.  By default, the same
+    /// IP address and port as the TCP listener.
+    pub bind_address: Option<SocketAddr>,
+    /// How long (in seconds) clients may remember that the server supports
+    /// HTTP/3 (the `ma` parameter of the `Alt-Svc` header).  Defaults to one
+    /// day.
+    pub alt_svc_max_age_secs: u64,
+}
+
+impl Default for ConfigHttp3 {
+    fn default() -> Self {
+        ConfigHttp3 { bind_address: None, alt_svc_max_age_secs: 86400 }
+    }
+}
+
+/// Limits on the load that a server takes on at once, so that a spike in
+/// traffic degrades service rather than exhausting the server's file
+/// descriptors or memory.
+///
+/// A connection accepted while `max_connections` connections are already open
+/// gets a `503 Service Unavailable` response to its first request, after which
+/// it's closed.  A request that arrives while `max_in_flight_requests` requests
+/// are already in progress gets a `503 Service Unavailable` response without
+/// being routed.  Either response has a `Retry-After` header of
+/// `retry_after_secs` and the error code `"Overloaded"`.
+///
+/// ```
+/// use dropshot::ConfigDropshot;
+///
+/// let config: ConfigDropshot = toml::from_str(
+///     r##"
+///         [load_limits]
+///         max_connections = 1000
+///         max_in_flight_requests = 200
+///         retry_after_secs = 5
+///     "##
+/// ).unwrap();
+/// assert_eq!(config.load_limits.max_in_flight_requests, Some(200));
+/// ```
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+#[serde(default)]
+pub struct ConfigLoadLimits {
+    /// If present, the maximum number of connections that may be open at once
+    pub max_connections: Option<usize>,
+    /// If present, the maximum number of requests that may be in progress at
+    /// once
+    pub max_in_flight_requests: Option<usize>,
+    /// Value of the `Retry-After` header (in seconds) of responses to shed
+    /// connections and requests.  Defaults to 1.
+    pub retry_after_secs: u64,
+}
+
+impl Default for ConfigLoadLimits {
+    fn default() -> Self {
+        ConfigLoadLimits {
+            max_connections: None,
+            max_in_flight_requests: None,
+            retry_after_secs: 1,
+        }
+    }
+}
+
+/// Overriding the method of `POST` requests with the `X-HTTP-Method-Override`
+/// header.  A request that names a method that may not be overridden fails
+/// with a 400 ("Bad Request") response.  The header is ignored on requests
+/// made with other methods.  Overridden requests are logged, and their log
+/// entries include the method as `method_override`.
+///
+/// ```
+/// use dropshot::ConfigDropshot;
+///
+/// let config: ConfigDropshot = toml::from_str(
+///     r##"
+///         [method_override]
+///         methods = [ "PUT", "DELETE" ]
+///     "##
+/// ).unwrap();
+/// assert_eq!(config.method_override.unwrap().methods, ["PUT", "DELETE"]);
+/// ```
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+#[serde(default)]
+pub struct ConfigMethodOverride {
+    /// Methods that requests may be overridden to use (compared
+    /// case-insensitively).  By default, `PUT`, `PATCH`, and `DELETE`.
+    pub methods: Vec<String>,
+}
+
+impl Default for ConfigMethodOverride {
+    fn default() -> Self {
+        ConfigMethodOverride {
+            methods: ["PUT", "PATCH", "DELETE"]
+                .iter()
+                .map(|m| m.to_string())
+                .collect(),
+        }
+    }
+}
+
+/// Compression of response bodies, negotiated with each client according to
+/// its `Accept-Encoding` header.  Endpoints can opt out of compression with
+/// `ApiEndpoint::compression()` (or `compression = false` in the `endpoint`
+/// macro).
+///
+/// ```
+/// use dropshot::ConfigDropshot;
+///
+/// let config: ConfigDropshot = toml::from_str(
+///     r##"
+///         [compression]
+///         codings = [ "br", "gzip" ]
+///         min_size_bytes = 512
+///         content_types = [ "application/json", "text/*" ]
+///     "##
+/// ).unwrap();
+/// assert_eq!(config.compression.unwrap().min_size_bytes, 512);
+/// ```
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+#[serde(default)]
+pub struct ConfigCompression {
+    /// Codings that may be used, in the server's order of preference (which
+    /// decides between codings the client finds equally acceptable).  By
+    /// default, `zstd`, `br`, and `gzip`.
+    pub codings: Vec<CompressionCoding>,
+    /// Bodies smaller than this are sent uncompressed.  Bodies whose size
+    /// isn't known ahead of time (e.g., streams) are always compressed.
+    pub min_size_bytes: u64,
+    /// Media types of the bodies that may be compressed (compared
+    /// case-insensitively, ignoring parameters like `charset`).  A type of the
+    /// form `text/*` matches any subtype.  By default, this includes JSON,
+    /// XML, YAML, JavaScript, SVG, and `text/*`.  Streaming types like
+    /// `text/event-stream` should not be listed, since compression delays
+    /// each part of the stream until enough data has accumulated.
+    pub content_types: Vec<String>,
+}
+
+impl Default for ConfigCompression {
+    fn default() -> Self {
+        ConfigCompression {
+            codings: vec![
+                CompressionCoding::Zstd,
+                CompressionCoding::Br,
+                CompressionCoding::Gzip,
+            ],
+            min_size_bytes: 1024,
+            content_types: [
+                "application/json",
+                "application/xml",
+                "application/yaml",
+                "application/javascript",
+                "image/svg+xml",
+                "text/css",
+                "text/csv",
+                "text/html",
+                "text/javascript",
+                "text/plain",
+                "text/xml",
+            ]
+            .iter()
+            .map(|t| t.to_string())
+            .collect(),
+        }
+    }
+}
+
+/// A content coding with which response bodies may be compressed
+#[derive(Clone, Copy, Debug, Deserialize, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CompressionCoding {
+    /// gzip (RFC 1952)
+    Gzip,
+    /// Brotli (RFC 7932)
+    Br,
+    /// Zstandard (RFC 8878)
+    Zstd,
+}
+
+impl CompressionCoding {
+    /// Returns the name of the coding, as used in the `Accept-Encoding` and
+    /// `Content-Encoding` headers.
+    pub fn name(&self) -> &'static str {
+        match self {
+            CompressionCoding::Gzip => "gzip",
+            CompressionCoding::Br => "br",
+            CompressionCoding::Zstd => "zstd",
+        }
+    }
+}
+
+/// Stricter parsing of HTTP/1 requests, for servers that sit behind proxies
+/// which may frame requests differently than Dropshot does (the root of
+/// "request smuggling" attacks).
+///
+/// A request that violates any of the enabled checks fails with a 400 ("Bad
+/// Request") response and the connection is closed.  Rejections are counted
+/// by reason; see `HttpServer::strict_http_rejections()`.  Requests on
+/// connections that have been upgraded (e.g., to a websocket) and HTTP/2
+/// requests are not inspected.
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+#[serde(default)]
+pub struct ConfigStrictHttp {
+    /// Reject requests with both `Content-Length` and `Transfer-Encoding`
+    /// headers.  Otherwise, `Content-Length` is ignored for such requests.
+    pub reject_content_length_with_transfer_encoding: bool,
+    /// Reject requests whose headers use obsolete line folding (a line that
+    /// begins with whitespace, continuing the previous header's value)
+    pub reject_obs_fold: bool,
+    /// Reject requests whose head contains a CR that isn't followed by LF
+    pub reject_bare_cr: bool,
+    /// If present, the maximum total size of the chunk extensions in a
+    /// chunked request body
+    pub chunk_extensions_max_bytes: Option<usize>,
+}
+
+impl Default for ConfigStrictHttp {
+    fn default() -> Self {
+        ConfigStrictHttp {
+            reject_content_length_with_transfer_encoding: true,
+            reject_obs_fold: true,
+            reject_bare_cr: true,
+            chunk_extensions_max_bytes: Some(1024),
+        }
+    }
+}
+
+/// Determines what happens to a request's handler when the client disconnects
+/// (or, for HTTP/2, resets the stream) before the response has been sent.  In
+/// either case, handlers can find out that the client has gone away with
+/// `RequestContext::is_disconnected()` and `RequestContext::cancelled()`.
+#[derive(
+    Clone, Copy, Debug, Default, Deserialize, Eq, PartialEq, Serialize,
+)]
+#[serde(rename_all = "snake_case")]
+pub enum HandlerTaskMode {
+    /// The handler runs to completion in its own task, and its response is
+    /// discarded.  This is the default, since handlers that modify state may
+    /// behave badly if they're stopped partway through.
+    #[default]
+    Detached,
+    /// The handler's future is dropped, which stops it at its next `await`
+    /// point.  This avoids wasted work, but handlers must be written to
+    /// tolerate being stopped at any `await` point.
+    CancelOnDisconnect,
+}
+
+/// Controls how often the "request completed" log entry is emitted for
+/// successful requests to matching routes.  This is intended for high-volume,
+/// low-value routes like health checks and metrics scrapes.
+///
+/// Requests whose response has a 400- or 500-level status code are always
+/// logged, regardless of any sampling rule.
+///
+/// ```
+/// use dropshot::ConfigDropshot;
+///
+/// let config: ConfigDropshot = toml::from_str(
+///     r##"
+///         [[request_log_sampling]]
+///         path = "/healthz"
+///         sample_rate = 0
+///
+///         [[request_log_sampling]]
+///         path = "/metrics/*"
+///         sample_rate = 100
+///         max_per_second = 1
+///     "##
+/// ).unwrap();
+/// assert_eq!(config.request_log_sampling.len(), 2);
+/// ```
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+pub struct ConfigRequestLogSampling {
+    /// Route path, as registered with the `ApiDescription` (e.g., `"/healthz"`
+    /// or `"/metrics/{kind}"`).  A trailing `*` matches any route that begins
+    /// with the preceding prefix.  The first matching rule applies.
+    pub path: String,
+    /// Log one out of every `sample_rate` successful requests.  A value of 0
+    /// suppresses logging of successful requests entirely.
+    #[serde(default = "ConfigRequestLogSampling::default_sample_rate")]
+    pub sample_rate: u32,
+    /// If present, log at most this many successful requests per second, after
+    /// sampling.
+    #[serde(default)]
+    pub max_per_second: Option<u32>,
+}
+
+impl ConfigRequestLogSampling {
+    fn default_sample_rate() -> u32 {
+        1
+    }
+}
+
+/// Reports requests to matching routes that take longer than a threshold with a
+/// "slow request" log entry at the "warn" level.  The entry breaks down the
+/// request's latency into the time spent before the handler's arguments were
+/// extracted ("queueing"), extracting them, running the handler, converting its
+/// result into a response ("serialization"), and sending the response body
+/// ("write").  The entry is emitted once the response has been sent.
+///
+/// ```
+/// use dropshot::ConfigDropshot;
+///
+/// let config: ConfigDropshot = toml::from_str(
+///     r##"
+///         [[slow_request_logging]]
+///         path = "/projects/{project}"
+///         threshold_ms = 250
+///
+///         [[slow_request_logging]]
+///         path = "*"
+///         threshold_ms = 2000
+///     "##
+/// ).unwrap();
+/// assert_eq!(config.slow_request_logging.len(), 2);
+/// ```
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+pub struct ConfigSlowRequestLogging {
+    /// Route path, as registered with the `ApiDescription`.  A trailing `*`
+    /// matches any route that begins with the preceding prefix.  The first
+    /// matching rule applies.
+    pub path: String,
+    /// Requests that take longer than this many milliseconds are reported
+    pub threshold_ms: u64,
+}
+
+/// Describes sensitive parts of requests whose values are masked before
+/// they're written to the log.  This applies to the request URI (which Dropshot
+/// logs for every request), the request headers (logged at the "trace" level),
+/// and any request body logged via `RequestContext::redact_for_log()`.
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+#[serde(default)]
+pub struct ConfigLogRedaction {
+    /// Names of request headers whose values are masked.  These are compared
+    /// case-insensitively.  By default, this includes `Authorization` and
+    /// `Cookie`.
+    pub headers: Vec<String>,
+    /// Names of query parameters whose values are masked.
+    pub query_params: Vec<String>,
+    /// Names of JSON object fields whose values are masked, at any depth
+    /// within the body.
+    pub body_fields: Vec<String>,
+}
+
+impl Default for ConfigLogRedaction {
+    fn default() -> Self {
+        ConfigLogRedaction {
+            headers: vec![
+                http::header::AUTHORIZATION.to_string(),
+                http::header::COOKIE.to_string(),
+            ],
+            query_params: Vec::new(),
+            body_fields: Vec::new(),
+        }
+    }
 }
 
 #[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
@@ -69,13 +793,145 @@ pub enum ConfigTls {
         /// Path to a PEM-encoded PKCS #8 file containing the private key the
         ///  server will use.
         key_file: PathBuf,
+        /// If present, clients are asked for certificates, which are verified
+        ///  against the given CAs.  See [`ConfigTlsClientAuth`].
+        client_auth: Option<ConfigTlsClientAuth>,
     },
     AsBytes {
         certs: Vec<u8>,
         key: Vec<u8>,
+        /// If present, clients are asked for certificates, which are verified
+        ///  against the given CAs.  See [`ConfigTlsClientAuth`].
+        client_auth: Option<ConfigTlsClientAuth>,
+    },
+    /// Several certificate chains, one of which is chosen for each connection
+    ///  by the hostname that the client names with Server Name Indication
+    ///  (SNI).  See [`ConfigTlsSniCertificate`].
+    Sni {
+        /// Certificate chains and the hostnames they're used for.  Clients
+        ///  that don't send a hostname, or that send one for which no chain
+        ///  is configured, get the first one.
+        certificates: Vec<ConfigTlsSniCertificate>,
+        /// If present, clients are asked for certificates, which are verified
+        ///  against the given CAs.  See [`ConfigTlsClientAuth`].
+        client_auth: Option<ConfigTlsClientAuth>,
+    },
+}
+
+/// A certificate chain used for connections to particular hostnames (see
+/// `ConfigTls::Sni`).
+///
+/// ```
+/// use dropshot::ConfigDropshot;
+/// use dropshot::ConfigTls;
+///
+/// let config: ConfigDropshot = toml::from_str(
+///     r##"
+///         [tls]
+///         type = "Sni"
+///
+///         [[tls.certificates]]
+///         hostnames = [ "example.com", "*.example.com" ]
+///         cert_file = "/path/to/example-com-certs.pem"
+///         key_file = "/path/to/example-com-key.pem"
+///
+///         [[tls.certificates]]
+///         hostnames = [ "example.net" ]
+///         cert_file = "/path/to/example-net-certs.pem"
+///         key_file = "/path/to/example-net-key.pem"
+///     "##
+/// ).unwrap();
+/// match config.tls.unwrap() {
+///     ConfigTls::Sni { certificates, .. } => {
+///         assert_eq!(certificates[1].hostnames, ["example.net"])
+///     }
+///     _ => panic!("unexpected TLS config"),
+/// }
+/// ```
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+pub struct ConfigTlsSniCertificate {
+    /// Hostnames (compared case-insensitively) for which this chain is used.
+    /// A name whose first label is `*` (e.g., `*.example.com`) matches any
+    /// single label in its place.
+    pub hostnames: Vec<String>,
+    /// Path to a PEM file containing the certificate chain, with the
+    /// end-entity certificate first
+    pub cert_file: PathBuf,
+    /// Path to a PEM-encoded PKCS #8 file containing the chain's private key
+    pub key_file: PathBuf,
+}
+
+/// Authentication of clients with TLS client certificates ("mutual TLS").
+///
+/// A client's certificate is verified against the configured CA certificates
+/// during the TLS handshake, so a connection whose client presents a
+/// certificate that doesn't verify is never established.  The verified
+/// certificate is available to handlers from
+/// `RequestContext::connection().peer_certificate()` for authorization
+/// decisions.
+///
+/// ```
+/// use dropshot::ClientAuthMode;
+/// use dropshot::ConfigDropshot;
+/// use dropshot::ConfigTls;
+/// use dropshot::ConfigTlsClientAuth;
+///
+/// let config: ConfigDropshot = toml::from_str(
+///     r##"
+///         [tls]
+///         type = "AsFile"
+///         cert_file = "/path/to/certs.pem"
+///         key_file = "/path/to/key.pem"
+///
+///         [tls.client_auth]
+///         type = "AsFile"
+///         ca_file = "/path/to/client-ca.pem"
+///         mode = "request"
+///     "##
+/// ).unwrap();
+/// match config.tls.unwrap() {
+///     ConfigTls::AsFile {
+///         client_auth: Some(ConfigTlsClientAuth::AsFile { mode, .. }),
+///         ..
+///     } => assert_eq!(mode, ClientAuthMode::Request),
+///     _ => panic!("unexpected TLS config"),
+/// }
+/// ```
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+#[serde(tag = "type")]
+pub enum ConfigTlsClientAuth {
+    AsFile {
+        /// Path to a PEM file containing the certificates of the CAs that
+        ///  client certificates must chain to
+        ca_file: PathBuf,
+        /// Whether clients must present a certificate
+        #[serde(default)]
+        mode: ClientAuthMode,
     },
+    AsBytes {
+        ca_certs: Vec<u8>,
+        #[serde(default)]
+        mode: ClientAuthMode,
+    },
+}
+
+/// Determines whether clients must present a TLS client certificate (see
+/// [`ConfigTlsClientAuth`])
+#[derive(
+    Clone, Copy, Debug, Default, Deserialize, Eq, PartialEq, Serialize,
+)]
+#[serde(rename_all = "snake_case")]
+pub enum ClientAuthMode {
+    /// Clients that don't present a valid certificate can't connect.
+    #[default]
+    Require,
+    /// Clients may connect without a certificate, in which case
+    /// `ConnectionInfo::peer_certificate()` returns `None`, but a certificate
+    /// that's presented must be valid.
+    Request,
 }
 
+#[cfg(feature = "tls")]
 impl ConfigTls {
     pub(crate) fn cert_reader(
         &self,
@@ -97,6 +953,10 @@ impl ConfigTls {
             ConfigTls::AsBytes { certs, .. } => {
                 Ok(Box::new(std::io::BufReader::new(certs.as_slice())))
             }
+            ConfigTls::Sni { .. } => Err(std::io::Error::new(
+                std::io::ErrorKind::Other,
+                "certificates are configured for each hostname",
+            )),
         }
     }
 
@@ -116,6 +976,75 @@ impl ConfigTls {
             ConfigTls::AsBytes { key, .. } => {
                 Ok(Box::new(std::io::BufReader::new(key.as_slice())))
             }
+            ConfigTls::Sni { .. } => Err(std::io::Error::new(
+                std::io::ErrorKind::Other,
+                "keys are configured for each hostname",
+            )),
+        }
+    }
+
+    pub(crate) fn client_auth(&self) -> Option<&ConfigTlsClientAuth> {
+        match self {
+            ConfigTls::AsFile { client_auth, .. } => client_auth.as_ref(),
+            ConfigTls::AsBytes { client_auth, .. } => client_auth.as_ref(),
+            ConfigTls::Sni { client_auth, .. } => client_auth.as_ref(),
+        }
+    }
+}
+
+#[cfg(feature = "tls")]
+impl ConfigTlsSniCertificate {
+    pub(crate) fn cert_reader(
+        &self,
+    ) -> std::io::Result<Box<dyn std::io::BufRead + '_>> {
+        open_pem_file(&self.cert_file)
+    }
+
+    pub(crate) fn key_reader(
+        &self,
+    ) -> std::io::Result<Box<dyn std::io::BufRead + '_>> {
+        open_pem_file(&self.key_file)
+    }
+}
+
+#[cfg(feature = "tls")]
+fn open_pem_file(
+    path: &std::path::Path,
+) -> std::io::Result<Box<dyn std::io::BufRead>> {
+    let file = std::fs::File::open(path).map_err(|e| {
+        std::io::Error::new(
+            std::io::ErrorKind::Other,
+            format!("failed to open {}: {}", path.display(), e),
+        )
+    })?;
+    Ok(Box::new(std::io::BufReader::new(file)))
+}
+
+#[cfg(feature = "tls")]
+impl ConfigTlsClientAuth {
+    pub(crate) fn mode(&self) -> ClientAuthMode {
+        match self {
+            ConfigTlsClientAuth::AsFile { mode, .. } => *mode,
+            ConfigTlsClientAuth::AsBytes { mode, .. } => *mode,
+        }
+    }
+
+    pub(crate) fn ca_reader(
+        &self,
+    ) -> std::io::Result<Box<dyn std::io::BufRead + '_>> {
+        match self {
+            ConfigTlsClientAuth::AsFile { ca_file, .. } => {
+                let cafile = std::fs::File::open(ca_file).map_err(|e| {
+                    std::io::Error::new(
+                        std::io::ErrorKind::Other,
+                        format!("failed to open {}: {}", ca_file.display(), e),
+                    )
+                })?;
+                Ok(Box::new(std::io::BufReader::new(cafile)))
+            }
+            ConfigTlsClientAuth::AsBytes { ca_certs, .. } => {
+                Ok(Box::new(std::io::BufReader::new(ca_certs.as_slice())))
+            }
         }
     }
 }
@@ -126,6 +1055,36 @@ impl Default for ConfigDropshot {
             bind_address: "127.0.0.1:0".parse().unwrap(),
             request_body_max_bytes: 1024,
             tls: None,
+            request_log_sampling: Vec::new(),
+            log_redaction: ConfigLogRedaction::default(),
+            slow_request_logging: Vec::new(),
+            connection_bandwidth_limit: None,
+            schema_validation: false,
+            strict_query_params: false,
+            multipart_part_max_bytes: None,
+            request_body_decompressed_max_bytes: None,
+            handler_task_mode: HandlerTaskMode::default(),
+            strict_http: None,
+            compression: None,
+            redact_server_errors: false,
+            method_not_allowed_as_not_found: false,
+            method_override: None,
+            drain_timeout_ms: None,
+            keep_alive_timeout_ms: None,
+            max_requests_per_connection: None,
+            idle_timeout_ms: None,
+            header_read_timeout_ms: None,
+            max_header_bytes: None,
+            max_header_count: None,
+            trusted_proxies: Vec::new(),
+            tls_reload_interval_ms: None,
+            load_limits: ConfigLoadLimits::default(),
+            http3: None,
+            unix_socket: None,
+            listen_fd: None,
+            additional_listeners: Vec::new(),
+            reuseport: None,
+            socket_options: ConfigSocketOptions::default(),
         }
     }
 }
@@ -0,0 +1,199 @@
+// Copyright 2023 Oxide Computer Company
+
+//! Information about the connections on which requests arrive
+
+use std::net::SocketAddr;
+use tokio::net::TcpStream;
+
+/// `ConnectionInfo` describes the connection on which a request arrived.  It's
+/// available to handlers from [`crate::RequestContext::connection()`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ConnectionInfo {
+    remote_addr: SocketAddr,
+    local_addr: SocketAddr,
+    tls: bool,
+    alpn_protocol: Option<Vec<u8>>,
+    peer_certificate: Option<PeerCertificate>,
+    peer_credentials: Option<PeerCredentials>,
+}
+
+impl ConnectionInfo {
+    pub(crate) fn new(
+        remote_addr: SocketAddr,
+        local_addr: SocketAddr,
+        tls: bool,
+        alpn_protocol: Option<Vec<u8>>,
+    ) -> ConnectionInfo {
+        ConnectionInfo {
+            remote_addr,
+            local_addr,
+            tls,
+            alpn_protocol,
+            peer_certificate: None,
+            peer_credentials: None,
+        }
+    }
+
+    #[cfg(feature = "tls")]
+    pub(crate) fn with_peer_certificate(
+        mut self,
+        peer_certificate: Option<PeerCertificate>,
+    ) -> ConnectionInfo {
+        self.peer_certificate = peer_certificate;
+        self
+    }
+
+    #[cfg(unix)]
+    pub(crate) fn with_peer_credentials(
+        mut self,
+        peer_credentials: Option<PeerCredentials>,
+    ) -> ConnectionInfo {
+        self.peer_credentials = peer_credentials;
+        self
+    }
+
+    /// Returns the address of the client on the other end of the connection.
+    pub fn remote_addr(&self) -> SocketAddr {
+        self.remote_addr
+    }
+
+    /// Returns the server's address for the connection.  This is the address
+    /// that the client connected to, which is more specific than the server's
+    /// bind address if that's a wildcard address (e.g., `0.0.0.0`).
+    pub fn local_addr(&self) -> SocketAddr {
+        self.local_addr
+    }
+
+    /// Returns whether the connection uses TLS.
+    pub fn is_tls(&self) -> bool {
+        self.tls
+    }
+
+    /// Returns the protocol that the client and server agreed on using ALPN
+    /// (e.g., `b"h2"`) during the TLS handshake, if any.
+    pub fn alpn_protocol(&self) -> Option<&[u8]> {
+        self.alpn_protocol.as_deref()
+    }
+
+    /// Returns the certificate that the client presented during the TLS
+    /// handshake, which has been verified against the CAs configured with
+    /// `ConfigTls`'s `client_auth`.  This is `None` if the connection doesn't
+    /// use TLS, if client authentication isn't configured, or if the client
+    /// didn't present a certificate (which is only allowed with
+    /// `ClientAuthMode::Request`).
+    pub fn peer_certificate(&self) -> Option<&PeerCertificate> {
+        self.peer_certificate.as_ref()
+    }
+
+    /// Returns the credentials of the process on the other end of the
+    /// connection, as reported by the operating system.  These are only
+    /// available for connections on a Unix domain socket (see
+    /// `ConfigDropshot::unix_socket`).
+    pub fn peer_credentials(&self) -> Option<&PeerCredentials> {
+        self.peer_credentials.as_ref()
+    }
+}
+
+/// `PeerCredentials` identifies the process that connected to a server's Unix
+/// domain socket (see [`ConnectionInfo::peer_credentials()`]).  These are the
+/// credentials that the process had when it connected.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PeerCredentials {
+    uid: u32,
+    gid: u32,
+    pid: Option<i32>,
+}
+
+impl PeerCredentials {
+    /// Returns the process's effective user ID.
+    pub fn uid(&self) -> u32 {
+        self.uid
+    }
+
+    /// Returns the process's effective group ID.
+    pub fn gid(&self) -> u32 {
+        self.gid
+    }
+
+    /// Returns the process's ID, on platforms that report it.
+    pub fn pid(&self) -> Option<i32> {
+        self.pid
+    }
+}
+
+/// `PeerCertificate` describes a client's verified TLS certificate (see
+/// [`ConnectionInfo::peer_certificate()`]).
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PeerCertificate {
+    der: Vec<u8>,
+    subject: String,
+    subject_alt_names: Vec<String>,
+    fingerprint_sha256: String,
+}
+
+impl PeerCertificate {
+    pub(crate) fn new(
+        der: Vec<u8>,
+        subject: String,
+        subject_alt_names: Vec<String>,
+        fingerprint_sha256: String,
+    ) -> PeerCertificate {
+        PeerCertificate { der, subject, subject_alt_names, fingerprint_sha256 }
+    }
+
+    /// Returns the DER encoding of the certificate.
+    pub fn der(&self) -> &[u8] {
+        &self.der
+    }
+
+    /// Returns the certificate's subject as a distinguished name (e.g.,
+    /// `"CN=client.example.com, O=Example"`).
+    pub fn subject(&self) -> &str {
+        &self.subject
+    }
+
+    /// Returns the DNS names, email addresses, URIs, and IP addresses in the
+    /// certificate's subject alternative names extension, if it has one.
+    pub fn subject_alt_names(&self) -> &[String] {
+        &self.subject_alt_names
+    }
+
+    /// Returns the SHA-256 digest of the certificate's DER encoding, as
+    /// lowercase hexadecimal.
+    pub fn fingerprint_sha256(&self) -> &str {
+        &self.fingerprint_sha256
+    }
+}
+
+/// An accepted connection that can describe itself.
+pub(crate) trait AcceptedConnection {
+    /// Returns the `ConnectionInfo` for this connection with a client at
+    /// `remote_addr`.
+    fn connection_info(&self, remote_addr: SocketAddr) -> ConnectionInfo;
+}
+
+impl AcceptedConnection for TcpStream {
+    fn connection_info(&self, remote_addr: SocketAddr) -> ConnectionInfo {
+        // The local address of a connected socket is always available, but if
+        // it somehow weren't, the remote address is a better placeholder than
+        // failing the connection.
+        let local_addr = self.local_addr().unwrap_or(remote_addr);
+        ConnectionInfo::new(remote_addr, local_addr, false, None)
+    }
+}
+
+#[cfg(unix)]
+impl AcceptedConnection for tokio::net::UnixStream {
+    fn connection_info(&self, remote_addr: SocketAddr) -> ConnectionInfo {
+        // Unix domain sockets have no IP addresses, so the placeholder remote
+        // address serves as the local address too.
+        let peer_credentials =
+            self.peer_cred().ok().map(|cred| PeerCredentials {
+                uid: cred.uid(),
+                gid: cred.gid(),
+                pid: cred.pid(),
+            });
+        ConnectionInfo::new(remote_addr, remote_addr, false, None)
+            .with_peer_credentials(peer_credentials)
+    }
+}
@@ -0,0 +1,249 @@
+// Copyright 2023 Oxide Computer Company
+//! Decompression of request bodies (`Content-Encoding`)
+//!
+//! Bodies read with [`TypedBody`](crate::TypedBody) are decompressed according
+//! to the request's `Content-Encoding` header before they're deserialized.
+//! Dropshot itself decodes `gzip` (or `x-gzip`), `deflate`, and `br`; other
+//! codings can be supported by registering a [`ContentDecoder`] with
+//! [`ApiDescription::content_decoder()`](crate::ApiDescription::content_decoder).
+//! Requests using a coding that the server doesn't support fail with a 415
+//! ("Unsupported Media Type") error.
+//!
+//! To guard against "decompression bombs", a decompressed body is limited to
+//! `ConfigDropshot::request_body_decompressed_max_bytes` (or, if that's not
+//! set, to the same limit as the compressed body).
+
+use crate::error::HttpError;
+
+use bytes::Bytes;
+use flate2::Decompress;
+use flate2::FlushDecompress;
+use flate2::Status;
+use http::HeaderMap;
+use http::StatusCode;
+use std::collections::HashMap;
+use std::fmt::Debug;
+use std::io::Read;
+use std::sync::Arc;
+
+/// Decodes request bodies that use a particular content coding.
+pub trait ContentDecoder: Debug + Send + Sync {
+    /// Returns the decoded form of `encoded`.  Implementations must fail
+    /// (typically with a 400 error) rather than produce more than `max_bytes`
+    /// bytes of output.
+    fn decode(
+        &self,
+        encoded: &[u8],
+        max_bytes: usize,
+    ) -> Result<Vec<u8>, HttpError>;
+}
+
+/// The content decoders available to a server, by (lowercase) coding name
+#[derive(Debug)]
+pub(crate) struct ContentDecoders {
+    decoders: HashMap<String, Arc<dyn ContentDecoder>>,
+}
+
+impl ContentDecoders {
+    /// Returns Dropshot's own decoders along with those in `custom`, which
+    /// take precedence.
+    pub(crate) fn new(
+        custom: &[(String, Arc<dyn ContentDecoder>)],
+    ) -> ContentDecoders {
+        let mut decoders: HashMap<String, Arc<dyn ContentDecoder>> =
+            HashMap::new();
+        decoders.insert(String::from("gzip"), Arc::new(GzipDecoder));
+        decoders.insert(String::from("x-gzip"), Arc::new(GzipDecoder));
+        decoders.insert(String::from("deflate"), Arc::new(DeflateDecoder));
+        decoders.insert(String::from("br"), Arc::new(BrotliDecoder));
+        for (coding, decoder) in custom {
+            decoders.insert(coding.to_ascii_lowercase(), Arc::clone(decoder));
+        }
+        ContentDecoders { decoders }
+    }
+
+    /// Undoes the content codings listed in `headers` (in the reverse of the
+    /// order in which they were applied), limiting each decoded form of the
+    /// body to `max_bytes`.
+    pub(crate) fn decode(
+        &self,
+        headers: &HeaderMap,
+        body: Bytes,
+        max_bytes: usize,
+    ) -> Result<Bytes, HttpError> {
+        let mut codings = Vec::new();
+        for value in headers.get_all(http::header::CONTENT_ENCODING) {
+            let value = value.to_str().map_err(|_| {
+                HttpError::for_bad_request(
+                    None,
+                    String::from("invalid content encoding"),
+                )
+            })?;
+            codings.extend(
+                value
+                    .split(',')
+                    .map(|coding| coding.trim().to_ascii_lowercase())
+                    .filter(|coding| {
+                        !coding.is_empty() && coding != "identity"
+                    }),
+            );
+        }
+
+        let mut body = body;
+        for coding in codings.iter().rev() {
+            let decoder = self.decoders.get(coding).ok_or_else(|| {
+                HttpError::for_client_error(
+                    None,
+                    StatusCode::UNSUPPORTED_MEDIA_TYPE,
+                    format!("unsupported content encoding \"{}\"", coding),
+                )
+            })?;
+            body = Bytes::from(decoder.decode(&body, max_bytes)?);
+        }
+        Ok(body)
+    }
+}
+
+impl Default for ContentDecoders {
+    fn default() -> Self {
+        ContentDecoders::new(&[])
+    }
+}
+
+/// Decoder for the `gzip` coding (RFC 1952)
+#[derive(Debug)]
+struct GzipDecoder;
+
+impl ContentDecoder for GzipDecoder {
+    fn decode(
+        &self,
+        encoded: &[u8],
+        max_bytes: usize,
+    ) -> Result<Vec<u8>, HttpError> {
+        // A gzip file may consist of several members, which are decompressed
+        // one after the other.
+        read_limited(flate2::read::MultiGzDecoder::new(encoded), max_bytes)
+    }
+}
+
+/// Decoder for the `deflate` coding, which is the "zlib" format (RFC 1950).
+/// Some clients send a raw DEFLATE stream (RFC 1951) instead, so that's
+/// accepted as well.
+#[derive(Debug)]
+struct DeflateDecoder;
+
+impl ContentDecoder for DeflateDecoder {
+    fn decode(
+        &self,
+        encoded: &[u8],
+        max_bytes: usize,
+    ) -> Result<Vec<u8>, HttpError> {
+        inflate(encoded, has_zlib_header(encoded), max_bytes)
+    }
+}
+
+/// Decoder for the `br` coding (RFC 7932)
+#[derive(Debug)]
+struct BrotliDecoder;
+
+impl ContentDecoder for BrotliDecoder {
+    fn decode(
+        &self,
+        encoded: &[u8],
+        max_bytes: usize,
+    ) -> Result<Vec<u8>, HttpError> {
+        read_limited(brotli::Decompressor::new(encoded, 4096), max_bytes)
+    }
+}
+
+/// Reads all of `decoder`'s output, failing if there's more than `max_bytes`
+/// of it.
+fn read_limited<R: Read>(
+    decoder: R,
+    max_bytes: usize,
+) -> Result<Vec<u8>, HttpError> {
+    let mut out = Vec::new();
+    decoder
+        .take((max_bytes as u64).saturating_add(1))
+        .read_to_end(&mut out)
+        .map_err(|e| invalid(e.to_string()))?;
+    if out.len() > max_bytes {
+        return Err(too_large(max_bytes));
+    }
+    Ok(out)
+}
+
+/// Returns whether `data` starts with a zlib header (RFC 1950, section 2.2)
+/// for a DEFLATE stream.
+fn has_zlib_header(data: &[u8]) -> bool {
+    match data {
+        [cmf, flg, ..] => {
+            cmf & 0x0f == 8
+                && cmf >> 4 <= 7
+                && ((u16::from(*cmf) << 8) | u16::from(*flg)) % 31 == 0
+        }
+        _ => false,
+    }
+}
+
+/// Decompresses a zlib stream (if `zlib_header` is set) or a raw DEFLATE
+/// stream, which must be complete.
+fn inflate(
+    data: &[u8],
+    zlib_header: bool,
+    max_bytes: usize,
+) -> Result<Vec<u8>, HttpError> {
+    let mut inflater = Decompress::new(zlib_header);
+    let mut out =
+        Vec::with_capacity(max_bytes.min(data.len() * 4).saturating_add(1));
+    loop {
+        if out.len() > max_bytes {
+            return Err(too_large(max_bytes));
+        }
+        if out.len() == out.capacity() {
+            out.reserve(
+                out.capacity().min((max_bytes - out.len()).saturating_add(1)),
+            );
+        }
+        let consumed = inflater.total_in() as usize;
+        let produced = out.len();
+        let status = inflater
+            .decompress_vec(&data[consumed..], &mut out, FlushDecompress::None)
+            .map_err(|e| invalid(e.to_string()))?;
+        match status {
+            Status::StreamEnd if out.len() > max_bytes => {
+                return Err(too_large(max_bytes));
+            }
+            Status::StreamEnd if inflater.total_in() as usize != data.len() => {
+                return Err(invalid("unexpected data after end of stream"));
+            }
+            Status::StreamEnd => return Ok(out),
+            Status::Ok | Status::BufError => {
+                // With room left for output, the only reason not to make
+                // progress is that the stream was cut short.
+                if inflater.total_in() as usize == consumed
+                    && out.len() == produced
+                {
+                    return Err(invalid("unexpected end of data"));
+                }
+            }
+        }
+    }
+}
+
+fn invalid<S: std::fmt::Display>(reason: S) -> HttpError {
+    HttpError::for_bad_request(
+        None,
+        format!("unable to decompress request body: {}", reason),
+    )
+}
+
+fn too_large(max_bytes: usize) -> HttpError {
+    HttpError::for_bad_request(
+        None,
+        format!(
+            "decompressed request body exceeded maximum size of {} bytes",
+            max_bytes
+        ),
+    )
+}
@@ -0,0 +1,80 @@
+// Copyright 2023 Oxide Computer Company
+//! Detection of clients that disconnect before their response is sent
+
+use tokio::sync::watch;
+
+/// Fires the associated [`DisconnectSignal`] when it's dropped, unless it's
+/// been disarmed first.  This is held by the future that hyper polls for the
+/// response, which hyper drops if the client closes the connection (or, for
+/// HTTP/2, resets the stream) before the response is ready.
+#[derive(Debug)]
+pub(crate) struct DisconnectGuard {
+    tx: Option<watch::Sender<bool>>,
+}
+
+impl DisconnectGuard {
+    pub fn new() -> (DisconnectGuard, DisconnectSignal) {
+        let (tx, rx) = watch::channel(false);
+        (DisconnectGuard { tx: Some(tx) }, DisconnectSignal { rx })
+    }
+
+    /// Indicates that the response is ready, so that dropping the guard no
+    /// longer means that the client went away.
+    pub fn disarm(mut self) {
+        self.tx = None;
+    }
+}
+
+impl Drop for DisconnectGuard {
+    fn drop(&mut self) {
+        if let Some(tx) = self.tx.take() {
+            // This only fails if nobody's listening.
+            let _ = tx.send(true);
+        }
+    }
+}
+
+/// Indicates whether the client that made a request has disconnected
+#[derive(Clone, Debug)]
+pub(crate) struct DisconnectSignal {
+    rx: watch::Receiver<bool>,
+}
+
+impl DisconnectSignal {
+    pub fn is_disconnected(&self) -> bool {
+        *self.rx.borrow()
+    }
+
+    /// Completes when the client disconnects.  If the response is sent
+    /// first, this never completes.
+    pub async fn disconnected(&self) {
+        let mut rx = self.rx.clone();
+        while !*rx.borrow() {
+            if rx.changed().await.is_err() {
+                // The guard was disarmed.
+                futures::future::pending::<()>().await;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::DisconnectGuard;
+    use futures::FutureExt;
+
+    #[tokio::test]
+    async fn test_disconnect_signal() {
+        let (guard, signal) = DisconnectGuard::new();
+        assert!(!signal.is_disconnected());
+        assert!(signal.disconnected().now_or_never().is_none());
+        drop(guard);
+        assert!(signal.is_disconnected());
+        signal.disconnected().await;
+
+        let (guard, signal) = DisconnectGuard::new();
+        guard.disarm();
+        assert!(!signal.is_disconnected());
+        assert!(signal.disconnected().now_or_never().is_none());
+    }
+}
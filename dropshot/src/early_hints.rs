@@ -0,0 +1,238 @@
+// Copyright 2023 Oxide Computer Company
+//! `103 Early Hints` informational responses
+//!
+//! hyper has no way for a server to send informational responses other than
+//! `100 Continue`, so early hints are written to HTTP/1.1 connections directly,
+//! underneath hyper.  Each connection's stream is wrapped so that hints queued
+//! by a handler are written only when hyper has no output of its own in flight:
+//! after hyper has flushed everything it wrote since the request arrived, or
+//! just before hyper begins writing the response.  Hints that can't be written
+//! before the response is ready are dropped.
+
+use bytes::Buf;
+use bytes::BytesMut;
+use http::HeaderMap;
+use std::io;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::sync::Mutex;
+use std::task::Context;
+use std::task::Poll;
+use std::task::Waker;
+use tokio::io::AsyncRead;
+use tokio::io::AsyncWrite;
+use tokio::io::ReadBuf;
+
+/// State shared between a connection's stream and the requests received on it
+#[derive(Debug, Default)]
+struct Shared {
+    /// encoded hints that haven't been written yet
+    pending: BytesMut,
+    /// whether some of `pending` has been written (so the rest must be written
+    /// before anything else)
+    started: bool,
+    /// whether hyper has flushed everything it wrote since the current request
+    /// arrived
+    idle: bool,
+    /// number of requests received on the connection, identifying the current
+    /// one
+    request: u64,
+    /// whether the response to the current request is ready
+    responded: bool,
+    /// wakes the task that drives the connection, to write queued hints
+    waker: Option<Waker>,
+}
+
+/// Wraps the stream for a new connection so that early hints can be sent on
+/// it with the returned sender.
+pub(crate) fn wrap<I>(stream: I) -> (EarlyHintsStream<I>, EarlyHintsSender) {
+    let shared = Arc::new(Mutex::new(Shared::default()));
+    let sender = EarlyHintsSender { shared: Arc::clone(&shared) };
+    (EarlyHintsStream { inner: stream, shared }, sender)
+}
+
+/// Sends early hints on a particular connection
+#[derive(Clone, Debug)]
+pub(crate) struct EarlyHintsSender {
+    shared: Arc<Mutex<Shared>>,
+}
+
+impl EarlyHintsSender {
+    /// Notes that a new request has arrived on the connection, returning the
+    /// handle with which hints may be sent in response to it.
+    pub fn begin(&self) -> EarlyHints {
+        let mut shared = self.shared.lock().unwrap();
+        shared.request += 1;
+        shared.responded = false;
+        // hyper may still be writing the response to the previous request.
+        shared.idle = false;
+        EarlyHints { shared: Arc::clone(&self.shared), request: shared.request }
+    }
+}
+
+/// Sends early hints in response to a particular request
+#[derive(Clone, Debug)]
+pub(crate) struct EarlyHints {
+    shared: Arc<Mutex<Shared>>,
+    request: u64,
+}
+
+impl EarlyHints {
+    /// Queues a `103 Early Hints` response with the given headers, returning
+    /// false if the response to the request is already ready.
+    pub fn send(&self, headers: &HeaderMap) -> bool {
+        let mut shared = self.shared.lock().unwrap();
+        if shared.request != self.request || shared.responded {
+            return false;
+        }
+        shared.pending.extend_from_slice(b"HTTP/1.1 103 Early Hints\r\n");
+        for (name, value) in headers {
+            shared.pending.extend_from_slice(name.as_str().as_bytes());
+            shared.pending.extend_from_slice(b": ");
+            shared.pending.extend_from_slice(value.as_bytes());
+            shared.pending.extend_from_slice(b"\r\n");
+        }
+        shared.pending.extend_from_slice(b"\r\n");
+        if let Some(waker) = shared.waker.take() {
+            waker.wake();
+        }
+        true
+    }
+
+    /// Notes that the response to the request is ready, so that no more hints
+    /// may be sent.  Hints that would now be written after the response has
+    /// begun are dropped.
+    pub fn finish(&self) {
+        let mut shared = self.shared.lock().unwrap();
+        if shared.request != self.request {
+            return;
+        }
+        shared.responded = true;
+        if !shared.started && !shared.idle {
+            shared.pending.clear();
+        }
+    }
+}
+
+/// Connection stream on which early hints are written between hyper's own
+/// output
+pub(crate) struct EarlyHintsStream<I> {
+    inner: I,
+    shared: Arc<Mutex<Shared>>,
+}
+
+impl<I: AsyncWrite + Unpin> EarlyHintsStream<I> {
+    /// Writes all pending hints.
+    fn poll_write_hints(
+        inner: &mut I,
+        shared: &mut Shared,
+        cx: &mut Context<'_>,
+    ) -> Poll<io::Result<()>> {
+        while !shared.pending.is_empty() {
+            shared.started = true;
+            let n = match Pin::new(&mut *inner).poll_write(cx, &shared.pending)
+            {
+                Poll::Ready(Ok(n)) => n,
+                other => return other.map_ok(|_| ()),
+            };
+            if n == 0 {
+                return Poll::Ready(Err(io::ErrorKind::WriteZero.into()));
+            }
+            shared.pending.advance(n);
+        }
+        shared.started = false;
+        Poll::Ready(Ok(()))
+    }
+
+    /// Writes pending hints if hyper is about to write while none of its
+    /// output is in flight (or if a hint has been partly written), and notes
+    /// that hyper's output is in flight.
+    fn poll_before_write(
+        &mut self,
+        cx: &mut Context<'_>,
+    ) -> Poll<io::Result<()>> {
+        let mut shared = self.shared.lock().unwrap();
+        if shared.started || (shared.idle && !shared.pending.is_empty()) {
+            match Self::poll_write_hints(&mut self.inner, &mut shared, cx) {
+                Poll::Ready(Ok(())) => (),
+                other => return other,
+            }
+        }
+        shared.idle = false;
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl<I: AsyncRead + Unpin> AsyncRead for EarlyHintsStream<I> {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.get_mut().inner).poll_read(cx, buf)
+    }
+}
+
+impl<I: AsyncWrite + Unpin> AsyncWrite for EarlyHintsStream<I> {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        let this = self.get_mut();
+        match this.poll_before_write(cx) {
+            Poll::Ready(Ok(())) => (),
+            other => return other.map_ok(|_| 0),
+        }
+        Pin::new(&mut this.inner).poll_write(cx, buf)
+    }
+
+    fn poll_write_vectored(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        bufs: &[io::IoSlice<'_>],
+    ) -> Poll<io::Result<usize>> {
+        let this = self.get_mut();
+        match this.poll_before_write(cx) {
+            Poll::Ready(Ok(())) => (),
+            other => return other.map_ok(|_| 0),
+        }
+        Pin::new(&mut this.inner).poll_write_vectored(cx, bufs)
+    }
+
+    fn is_write_vectored(&self) -> bool {
+        self.inner.is_write_vectored()
+    }
+
+    fn poll_flush(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<io::Result<()>> {
+        // hyper only flushes once it's written all of its buffered output, so
+        // once the flush completes, none of its output is in flight.
+        let this = self.get_mut();
+        this.shared.lock().unwrap().waker = Some(cx.waker().clone());
+        match Pin::new(&mut this.inner).poll_flush(cx) {
+            Poll::Ready(Ok(())) => (),
+            other => return other,
+        }
+        let mut shared = this.shared.lock().unwrap();
+        shared.idle = true;
+        if shared.pending.is_empty() {
+            return Poll::Ready(Ok(()));
+        }
+        match Self::poll_write_hints(&mut this.inner, &mut shared, cx) {
+            Poll::Ready(Ok(())) => (),
+            other => return other,
+        }
+        drop(shared);
+        Pin::new(&mut this.inner).poll_flush(cx)
+    }
+
+    fn poll_shutdown(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.get_mut().inner).poll_shutdown(cx)
+    }
+}
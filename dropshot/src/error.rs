@@ -42,6 +42,8 @@
 //! way.  Consumers can provide a `From` implementation that converts these
 //! errors into HttpErrors.
 
+use crate::api_description::ApiSchemaGenerator;
+use crate::schema_util::make_subschema_for;
 use hyper::Error as HyperError;
 use schemars::JsonSchema;
 use serde::Deserialize;
@@ -237,7 +239,7 @@ impl HttpError {
     pub fn into_response(
         self,
         request_id: &str,
-    ) -> hyper::Response<hyper::Body> {
+    ) -> http::Response<crate::Body> {
         // TODO-hardening: consider handling the operational errors that the
         // Serde serialization fails or the response construction fails.  In
         // those cases, we should probably try to report this as a serious
@@ -246,7 +248,7 @@ impl HttpError {
         // there's only one possible set of input and we can test it.  We'll
         // probably have to use unwrap() there and make sure we've tested that
         // code at least once!)
-        hyper::Response::builder()
+        http::Response::builder()
             .status(self.status_code)
             .header(
                 http::header::CONTENT_TYPE,
@@ -266,6 +268,116 @@ impl HttpError {
     }
 }
 
+/// An error type that endpoint handlers may return in place of [`HttpError`],
+/// which declares the error responses it can produce so that they appear in
+/// the OpenAPI document.
+///
+/// Handlers return `Result<R, E>` for any `E: HttpResponseError`; Dropshot
+/// converts `E` into an `HttpError` to send it.  Each status code listed by
+/// [`HttpResponseError::error_responses()`] is described in the endpoint's
+/// OpenAPI operation, with its description and the usual error body schema.
+/// The generic `4XX` and `5XX` responses remain, since Dropshot itself may
+/// still fail the request (e.g., if its body can't be parsed).
+///
+/// ```
+/// use dropshot::endpoint;
+/// use dropshot::HttpError;
+/// use dropshot::HttpResponseError;
+/// use dropshot::HttpResponseOk;
+/// use dropshot::Path;
+/// use dropshot::RequestContext;
+/// use http::StatusCode;
+///
+/// enum WidgetError {
+///     NotFound(String),
+///     Locked,
+/// }
+///
+/// impl From<WidgetError> for HttpError {
+///     fn from(error: WidgetError) -> HttpError {
+///         match error {
+///             WidgetError::NotFound(name) => HttpError::for_not_found(
+///                 None,
+///                 format!("no widget named {:?}", name),
+///             ),
+///             WidgetError::Locked => HttpError::for_client_error(
+///                 Some(String::from("Locked")),
+///                 StatusCode::CONFLICT,
+///                 String::from("widget is locked"),
+///             ),
+///         }
+///     }
+/// }
+///
+/// impl HttpResponseError for WidgetError {
+///     fn error_responses() -> Vec<(StatusCode, String)> {
+///         vec![
+///             (StatusCode::NOT_FOUND, String::from("no such widget")),
+///             (StatusCode::CONFLICT, String::from("widget is locked")),
+///         ]
+///     }
+/// }
+///
+/// #[derive(serde::Deserialize, schemars::JsonSchema)]
+/// struct WidgetPath {
+///     name: String,
+/// }
+///
+/// #[endpoint {
+///     method = GET,
+///     path = "/widgets/{name}",
+/// }]
+/// async fn widget_get(
+///     _rqctx: RequestContext<()>,
+///     path: Path<WidgetPath>,
+/// ) -> Result<HttpResponseOk<String>, WidgetError> {
+///     Err(WidgetError::NotFound(path.into_inner().name))
+/// }
+/// ```
+///
+/// Any type that implements both `Into<HttpError>` and `JsonSchema`
+/// implements this trait automatically.  It declares no specific status codes,
+/// but its schema describes the bodies of the endpoint's generic `4XX` and
+/// `5XX` error responses in place of the usual error body schema.
+pub trait HttpResponseError: Into<HttpError> {
+    /// Returns the status codes (with their descriptions) of the error
+    /// responses that values of this type may be converted into.
+    fn error_responses() -> Vec<(http::StatusCode, String)>;
+
+    /// Returns the schema that describes the bodies of the error responses,
+    /// if not the usual error body schema.
+    fn error_schema() -> Option<ApiSchemaGenerator> {
+        None
+    }
+}
+
+/// `HttpError` can produce any error response, so it declares none
+/// specifically: the OpenAPI document describes them with the generic `4XX`
+/// and `5XX` responses.
+impl HttpResponseError for HttpError {
+    fn error_responses() -> Vec<(http::StatusCode, String)> {
+        Vec::new()
+    }
+}
+
+/// Application error types that can be converted into an `HttpError` may be
+/// returned by handlers directly, and document their errors with their schema.
+impl<E> HttpResponseError for E
+where
+    E: Into<HttpError> + JsonSchema,
+{
+    fn error_responses() -> Vec<(http::StatusCode, String)> {
+        Vec::new()
+    }
+
+    fn error_schema() -> Option<ApiSchemaGenerator> {
+        Some(ApiSchemaGenerator::Gen {
+            name: E::schema_name,
+            schema: make_subschema_for::<E>,
+        })
+    }
+}
+
 impl fmt::Display for HttpError {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         write!(f, "HttpError({}): {}", self.status_code, self.external_message)
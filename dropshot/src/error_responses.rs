@@ -0,0 +1,236 @@
+// Copyright 2023 Oxide Computer Company
+//! Formats of error responses, and customization of the error responses that
+//! Dropshot generates itself
+
+use crate::error::HttpError;
+use crate::handler::RequestContext;
+use crate::http_util::CONTENT_TYPE_PROBLEM_JSON;
+use crate::http_util::HEADER_REQUEST_ID;
+use crate::server::ServerContext;
+use crate::Body;
+
+use http::HeaderValue;
+use hyper::Response;
+use schemars::JsonSchema;
+use serde::Deserialize;
+use serde::Serialize;
+use std::convert::TryFrom;
+use std::fmt::Debug;
+
+/// Format of the bodies of error responses, which is chosen for a server with
+/// [`ApiDescription::error_format()`](crate::ApiDescription::error_format).
+/// The OpenAPI document describes the error responses in the same format.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub enum ErrorFormat {
+    /// An [`HttpErrorResponseBody`](crate::HttpErrorResponseBody) serialized
+    /// as JSON (`application/json`)
+    #[default]
+    Dropshot,
+    /// RFC 7807 problem details (`application/problem+json`): a
+    /// [`ProblemDetails`] serialized as JSON
+    ProblemJson,
+}
+
+/// Body of an error response in the RFC 7807 `application/problem+json`
+/// format (see [`ErrorFormat::ProblemJson`]).
+///
+/// Problems have no specific type (`type` is `about:blank`), so `title` is
+/// the reason phrase of the status code, while `detail` is the error's
+/// message.  The request id and error code (if any) are sent as the extension
+/// members `request_id` and `error_code`.  Other extension members that a
+/// client receives are kept in `extensions`.
+#[derive(Debug, Deserialize, Serialize, JsonSchema)]
+#[schemars(description = "Problem details of an error response (RFC 7807).")]
+pub struct ProblemDetails {
+    /// URI reference that identifies the type of problem
+    #[serde(rename = "type")]
+    pub problem_type: String,
+    /// short summary of the type of problem
+    pub title: String,
+    /// HTTP status code of the response
+    pub status: u16,
+    /// explanation of this occurrence of the problem
+    pub detail: String,
+    /// URI reference that identifies this occurrence of the problem (the path
+    /// of the request)
+    pub instance: String,
+    /// unique id assigned to the request
+    pub request_id: String,
+    /// application-specific error code, if any
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error_code: Option<String>,
+    /// other extension members
+    #[serde(flatten)]
+    pub extensions: serde_json::Map<String, serde_json::Value>,
+}
+
+/// Converts `error` into a response in the given format.  `instance` is the
+/// path of the request.
+pub(crate) fn format_error(
+    error: HttpError,
+    format: ErrorFormat,
+    request_id: &str,
+    instance: &str,
+) -> Response<Body> {
+    if format == ErrorFormat::Dropshot {
+        return error.into_response(request_id);
+    }
+
+    let problem = ProblemDetails {
+        problem_type: String::from("about:blank"),
+        title: error
+            .status_code
+            .canonical_reason()
+            .unwrap_or_default()
+            .to_string(),
+        status: error.status_code.as_u16(),
+        detail: error.external_message,
+        instance: instance.to_string(),
+        request_id: request_id.to_string(),
+        error_code: error.error_code,
+        extensions: serde_json::Map::new(),
+    };
+    Response::builder()
+        .status(error.status_code)
+        .header(http::header::CONTENT_TYPE, CONTENT_TYPE_PROBLEM_JSON)
+        .header(HEADER_REQUEST_ID, request_id)
+        .body(serde_json::to_string_pretty(&problem).unwrap().into())
+        .unwrap()
+}
+
+/// Kinds of error responses that Dropshot generates on its own, rather than
+/// returning from a handler
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum GeneratedErrorKind {
+    /// No endpoint is registered for the request's path (a 404)
+    NotFound,
+    /// Endpoints are registered for the request's path, but not for its
+    /// method (a 405)
+    MethodNotAllowed,
+    /// The endpoint's arguments could not be extracted from the request
+    /// (e.g., its body or query string failed to parse)
+    ExtractorFailure,
+}
+
+impl GeneratedErrorKind {
+    /// Returns the kind of error for a failure to route a request.
+    pub(crate) fn for_routing_error(
+        error: &HttpError,
+    ) -> Option<GeneratedErrorKind> {
+        match error.status_code {
+            http::StatusCode::NOT_FOUND => Some(GeneratedErrorKind::NotFound),
+            http::StatusCode::METHOD_NOT_ALLOWED => {
+                Some(GeneratedErrorKind::MethodNotAllowed)
+            }
+            _ => None,
+        }
+    }
+}
+
+/// Replacement body for an error response generated by Dropshot
+#[derive(Debug)]
+pub struct CustomErrorBody {
+    /// value of the response's `Content-Type` header
+    pub content_type: String,
+    pub body: Body,
+}
+
+impl CustomErrorBody {
+    pub fn new<T: ToString, B: Into<Body>>(
+        content_type: T,
+        body: B,
+    ) -> CustomErrorBody {
+        CustomErrorBody {
+            content_type: content_type.to_string(),
+            body: body.into(),
+        }
+    }
+}
+
+/// Replaces the bodies of the error responses that Dropshot generates itself
+/// (see [`GeneratedErrorKind`]) so that they can match an application's own
+/// error format.  A server is configured with a customizer using
+/// [`ApiDescription::error_response_customizer()`](crate::ApiDescription::error_response_customizer).
+///
+/// Only the body and its content type can be changed: the response keeps its
+/// status code and `x-request-id` header.  Errors returned by handlers
+/// themselves are not affected.
+pub trait ErrorResponseCustomizer: Debug + Send + Sync {
+    /// Returns the body to send for `error`, or `None` to send the server's
+    /// usual body (by default, an
+    /// [`HttpErrorResponseBody`](crate::HttpErrorResponseBody) serialized as
+    /// JSON; see [`ErrorFormat`]).
+    fn customize(
+        &self,
+        kind: GeneratedErrorKind,
+        error: &HttpError,
+        request_id: &str,
+    ) -> Option<CustomErrorBody>;
+}
+
+/// Inspects, and may rewrite, every `HttpError` that a server sends before
+/// it's converted into a response (e.g., to add a support URL to messages,
+/// strip internal details in production, or translate messages).  A server is
+/// configured with a mapper using
+/// [`HttpServerStarter::error_mapper()`](crate::HttpServerStarter::error_mapper).
+///
+/// This applies both to errors returned by handlers and to those that Dropshot
+/// generates itself, before any [`ErrorResponseCustomizer`] supplies the body.
+/// The response is logged with the rewritten error.
+pub trait ErrorMapper<Context: ServerContext>: Debug + Send + Sync {
+    /// Returns the error to send in place of `error`.  `rqctx` is the context
+    /// of the request, unless the request failed before it was routed (e.g.,
+    /// because no endpoint matched it).
+    fn map_error(
+        &self,
+        error: HttpError,
+        rqctx: Option<&RequestContext<Context>>,
+    ) -> HttpError;
+}
+
+/// Returns `error` (a server error) with its error code removed and its
+/// external message replaced by the reason for its status code and
+/// `request_id`.
+pub(crate) fn redact_server_error(
+    error: HttpError,
+    request_id: &str,
+) -> HttpError {
+    let reason = error.status_code.canonical_reason().unwrap_or("Server Error");
+    HttpError {
+        status_code: error.status_code,
+        error_code: None,
+        external_message: format!("{} (request id: {})", reason, request_id),
+        internal_message: error.internal_message,
+    }
+}
+
+/// Converts `error` into a response in the given format, letting `customizer`
+/// (if there is one) supply the body if Dropshot generated the error itself.
+pub(crate) fn error_response(
+    customizer: Option<&dyn ErrorResponseCustomizer>,
+    kind: Option<GeneratedErrorKind>,
+    error: HttpError,
+    format: ErrorFormat,
+    request_id: &str,
+    instance: &str,
+) -> Response<Body> {
+    let custom = match (customizer, kind) {
+        (Some(customizer), Some(kind)) => {
+            customizer.customize(kind, &error, request_id)
+        }
+        _ => None,
+    };
+    let custom = custom.and_then(|custom| {
+        let content_type = HeaderValue::try_from(custom.content_type).ok()?;
+        Some((content_type, custom.body))
+    });
+    match custom {
+        None => format_error(error, format, request_id, instance),
+        Some((content_type, body)) => Response::builder()
+            .status(error.status_code)
+            .header(http::header::CONTENT_TYPE, content_type)
+            .header(HEADER_REQUEST_ID, request_id)
+            .body(body)
+            .unwrap(),
+    }
+}
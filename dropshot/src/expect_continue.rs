@@ -0,0 +1,78 @@
+// Copyright 2023 Oxide Computer Company
+//! Handling of requests that ask to continue (`Expect: 100-continue`)
+//!
+//! A client that sends `Expect: 100-continue` waits for a `100 Continue`
+//! response before sending the request's body, so that a request that's going
+//! to be rejected anyway doesn't waste a full upload.  The `100 Continue`
+//! response is sent only when the body is first read, so a request that fails
+//! before then is rejected without the client ever sending its body.  Dropshot
+//! takes advantage of this in two ways:
+//!
+//! * The body extractors ([`TypedBody`](crate::TypedBody),
+//!   [`UntypedBody`](crate::UntypedBody), and
+//!   [`MultipartBody`](crate::MultipartBody)) reject such a request with a 413
+//!   ("Payload Too Large") error if its `Content-Length` exceeds the endpoint's
+//!   body limit (see `ApiEndpoint::request_body_max_bytes()`).
+//! * A [`ContinueCheck`] configured with
+//!   [`ApiDescription::continue_check()`](crate::ApiDescription::continue_check)
+//!   can reject such a request (e.g., with a 401 if it lacks valid
+//!   credentials) before its arguments are extracted.
+
+use crate::error::HttpError;
+use crate::handler::RequestContext;
+use crate::server::ServerContext;
+
+use async_trait::async_trait;
+use http::HeaderMap;
+use http::StatusCode;
+use std::fmt::Debug;
+
+/// Decides whether a request that asks to continue (`Expect: 100-continue`)
+/// may send its body.  This is invoked after the request has been routed and
+/// before its handler's arguments are extracted; requests that don't ask to
+/// continue are not checked.
+#[async_trait]
+pub trait ContinueCheck<Context: ServerContext>: Debug + Send + Sync {
+    /// Returns an error (e.g., a 401 for a request without valid credentials)
+    /// to reject the request without reading its body.
+    async fn check(
+        &self,
+        rqctx: &RequestContext<Context>,
+    ) -> Result<(), HttpError>;
+}
+
+/// Returns true if a request with these headers asks to continue.
+pub(crate) fn expects_continue(headers: &HeaderMap) -> bool {
+    headers.get(http::header::EXPECT).map_or(false, |value| {
+        value.as_bytes().eq_ignore_ascii_case(b"100-continue")
+    })
+}
+
+/// Fails with a 413 ("Payload Too Large") error if a request that asks to
+/// continue declares a body larger than `max_bytes`.  Requests that don't ask
+/// to continue have their bodies limited as they're read instead.
+pub(crate) fn check_declared_length(
+    headers: &HeaderMap,
+    max_bytes: usize,
+) -> Result<(), HttpError> {
+    if !expects_continue(headers) {
+        return Ok(());
+    }
+    let declared = headers
+        .get(http::header::CONTENT_LENGTH)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.parse::<u64>().ok());
+    match declared {
+        Some(len) if len > max_bytes as u64 => {
+            Err(HttpError::for_client_error(
+                None,
+                StatusCode::PAYLOAD_TOO_LARGE,
+                format!(
+                    "request body exceeds maximum size of {} bytes",
+                    max_bytes
+                ),
+            ))
+        }
+        _ => Ok(()),
+    }
+}
@@ -0,0 +1,83 @@
+// Copyright 2023 Oxide Computer Company
+
+//! Typed values attached to a request
+
+use std::sync::Mutex;
+
+/// `Extensions` holds values attached to a request, at most one of each type,
+/// and is available from [`crate::RequestContext::extensions()`].  Code that
+/// runs before the handler (e.g., a [`crate::ContinueCheck`] that
+/// authenticates the request) can insert values, such as the authenticated
+/// principal, that the handler later reads directly or with the
+/// [`crate::Extension`] extractor.
+///
+/// Values are inserted through a shared reference, so they're returned by
+/// cloning them; wrap values that are expensive to clone in an `Arc`.
+#[derive(Debug, Default)]
+pub struct Extensions {
+    map: Mutex<http::Extensions>,
+}
+
+impl Extensions {
+    /// Attaches `value` to the request, returning the value of the same type
+    /// that was previously attached, if any.
+    pub fn insert<T>(&self, value: T) -> Option<T>
+    where
+        T: Clone + Send + Sync + 'static,
+    {
+        self.map.lock().unwrap().insert(value)
+    }
+
+    /// Returns (a clone of) the value of type `T` attached to the request, if
+    /// any.
+    pub fn get<T>(&self) -> Option<T>
+    where
+        T: Clone + Send + Sync + 'static,
+    {
+        self.map.lock().unwrap().get::<T>().cloned()
+    }
+
+    /// Returns whether a value of type `T` is attached to the request.
+    pub fn contains<T>(&self) -> bool
+    where
+        T: Clone + Send + Sync + 'static,
+    {
+        self.map.lock().unwrap().get::<T>().is_some()
+    }
+
+    /// Detaches the value of type `T` from the request, returning it if there
+    /// was one.
+    pub fn remove<T>(&self) -> Option<T>
+    where
+        T: Clone + Send + Sync + 'static,
+    {
+        self.map.lock().unwrap().remove::<T>()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::Extensions;
+
+    #[derive(Clone, Debug, PartialEq)]
+    struct Principal(String);
+
+    #[test]
+    fn test_extensions() {
+        let extensions = Extensions::default();
+        assert!(!extensions.contains::<Principal>());
+        assert_eq!(extensions.get::<Principal>(), None);
+
+        let alice = Principal(String::from("alice"));
+        let bob = Principal(String::from("bob"));
+        assert_eq!(extensions.insert(alice.clone()), None);
+        assert_eq!(extensions.insert(7u32), None);
+        assert_eq!(extensions.get::<Principal>(), Some(alice.clone()));
+        assert_eq!(extensions.insert(bob.clone()), Some(alice));
+        assert_eq!(extensions.get::<u32>(), Some(7));
+
+        assert_eq!(extensions.remove::<Principal>(), Some(bob));
+        assert!(!extensions.contains::<Principal>());
+        assert!(extensions.contains::<u32>());
+    }
+}
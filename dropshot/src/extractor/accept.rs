@@ -0,0 +1,364 @@
+// Copyright 2023 Oxide Computer Company
+
+//! Accept header (content negotiation) extractor
+
+use crate::api_description::ApiEndpointBodyContentType;
+use crate::api_description::ExtensionMode;
+use crate::error::HttpError;
+use crate::server::ServerContext;
+use crate::ExtractorMetadata;
+use crate::RequestContext;
+use crate::SharedExtractor;
+use async_trait::async_trait;
+use http::HeaderMap;
+use http::StatusCode;
+
+/// `Accept` is an extractor for the `Accept` request header, with which a
+/// client says which media types it's willing to receive in the response.
+///
+/// The header's media ranges (e.g., `application/json`, `text/*`, or `*/*`)
+/// are available from [`Accept::ranges()`], most preferred first.  Handlers
+/// that can produce several representations usually call
+/// [`Accept::negotiate()`] with the media types they support to choose one,
+/// or return a [`crate::Negotiated`] response, which does that for JSON and
+/// CBOR and describes both in the OpenAPI document.
+///
+/// A request without an `Accept` header accepts any media type, as though it
+/// had sent `Accept: */*`.  A header that can't be parsed fails the request
+/// with a 400 error.  Parameters of media ranges other than `q` are ignored.
+///
+/// ```
+/// use dropshot::endpoint;
+/// use dropshot::Accept;
+/// use dropshot::Body;
+/// use dropshot::HttpError;
+/// use dropshot::RequestContext;
+/// use http::Response;
+///
+/// #[endpoint {
+///     method = GET,
+///     path = "/report",
+/// }]
+/// async fn report_get(
+///     _rqctx: RequestContext<()>,
+///     accept: Accept,
+/// ) -> Result<Response<Body>, HttpError> {
+///     let content_type = accept.negotiate(&["text/csv", "text/plain"])?;
+///     Ok(Response::builder()
+///         .header(http::header::CONTENT_TYPE, content_type)
+///         .header(http::header::VARY, "Accept")
+///         .body(Body::from("a,b,c\n"))?)
+/// }
+/// ```
+#[derive(Clone, Debug)]
+pub struct Accept {
+    /// media ranges in order of preference
+    ranges: Vec<MediaRange>,
+}
+
+/// A media range from an `Accept` header, such as `text/html;q=0.8`
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct MediaRange {
+    /// lowercase `type/subtype`, either of which may be `*`
+    range: String,
+    /// quality, in thousandths
+    quality: u16,
+}
+
+impl MediaRange {
+    /// Returns the media range (without parameters), such as `text/html`,
+    /// `text/*`, or `*/*`, in lowercase.
+    pub fn media_type(&self) -> &str {
+        &self.range
+    }
+
+    /// Returns the range's quality (its `q` parameter), from 0 to 1.
+    pub fn quality(&self) -> f32 {
+        f32::from(self.quality) / 1000.0
+    }
+
+    /// Returns whether the range includes `media_type` (e.g., `text/*`
+    /// includes `text/plain`).  Parameters of `media_type` are ignored.
+    pub fn matches(&self, media_type: &str) -> bool {
+        let media_type = media_type.split(';').next().unwrap_or("").trim();
+        let (range_type, range_subtype) =
+            self.range.split_once('/').expect("media range has a slash");
+        match media_type.split_once('/') {
+            Some((type_, subtype)) => {
+                range_type == "*"
+                    || (range_type.eq_ignore_ascii_case(type_)
+                        && (range_subtype == "*"
+                            || range_subtype.eq_ignore_ascii_case(subtype)))
+            }
+            None => false,
+        }
+    }
+
+    /// Ranks how specific the range is: `*/*` is least specific, and a range
+    /// without wildcards is most specific.
+    fn specificity(&self) -> u8 {
+        if self.range == "*/*" {
+            0
+        } else if self.range.ends_with("/*") {
+            1
+        } else {
+            2
+        }
+    }
+}
+
+impl Accept {
+    /// Parses the `Accept` headers in `headers`.
+    pub(crate) fn from_headers(headers: &HeaderMap) -> Result<Accept, String> {
+        let mut ranges = Vec::new();
+        for value in headers.get_all(http::header::ACCEPT) {
+            let value = value.to_str().map_err(|e| e.to_string())?;
+            for element in value.split(',').map(str::trim) {
+                if !element.is_empty() {
+                    ranges.push(parse_media_range(element)?);
+                }
+            }
+        }
+        if ranges.is_empty() {
+            let range = String::from("*/*");
+            ranges.push(MediaRange { range, quality: 1000 });
+        }
+        // More specific ranges are preferred over less specific ones of the
+        // same quality.  The sort is stable, so the client's own order breaks
+        // any remaining ties.
+        ranges.sort_by(|a, b| {
+            b.quality
+                .cmp(&a.quality)
+                .then_with(|| b.specificity().cmp(&a.specificity()))
+        });
+        Ok(Accept { ranges })
+    }
+
+    /// Returns the media ranges of the header, most preferred first.
+    pub fn ranges(&self) -> &[MediaRange] {
+        &self.ranges
+    }
+
+    /// Returns how acceptable `media_type` is to the client, from 0 (not at
+    /// all) to 1.  This is the quality of the most specific range that matches
+    /// it, as specified by RFC 9110.
+    pub fn quality(&self, media_type: &str) -> f32 {
+        self.ranges
+            .iter()
+            .filter(|range| range.matches(media_type))
+            .fold(None, |best: Option<&MediaRange>, range| match best {
+                Some(best) if best.specificity() >= range.specificity() => {
+                    Some(best)
+                }
+                _ => Some(range),
+            })
+            .map_or(0.0, MediaRange::quality)
+    }
+
+    /// Chooses the media type that the client finds most acceptable among
+    /// `supported`, which are listed in the server's order of preference (so
+    /// the first wins among equally acceptable ones).  Fails with a 406 ("Not
+    /// Acceptable") error if the client accepts none of them.
+    pub fn negotiate<'a>(
+        &self,
+        supported: &[&'a str],
+    ) -> Result<&'a str, HttpError> {
+        let mut best: Option<(&'a str, f32)> = None;
+        for media_type in supported {
+            let quality = self.quality(media_type);
+            match best {
+                Some((_, best_quality)) if best_quality >= quality => (),
+                _ if quality > 0.0 => best = Some((*media_type, quality)),
+                _ => (),
+            }
+        }
+        best.map(|(media_type, _)| media_type).ok_or_else(|| {
+            HttpError::for_client_error(
+                None,
+                StatusCode::NOT_ACCEPTABLE,
+                format!(
+                    "none of the available media types is acceptable: {}",
+                    supported.join(", ")
+                ),
+            )
+        })
+    }
+}
+
+/// Parses one element of an `Accept` header, such as `text/html;q=0.8`.
+fn parse_media_range(element: &str) -> Result<MediaRange, String> {
+    let mut parts = element.split(';').map(str::trim);
+    let range = parts.next().unwrap_or("").to_ascii_lowercase();
+    let valid = match range.split_once('/') {
+        Some(("*", subtype)) => subtype == "*",
+        Some((type_, subtype)) => is_token(type_) && is_token(subtype),
+        None => false,
+    };
+    if !valid {
+        return Err(format!("invalid media range {:?}", element));
+    }
+
+    let mut quality = 1000;
+    for parameter in parts {
+        let (name, value) = parameter
+            .split_once('=')
+            .ok_or_else(|| format!("invalid parameter {:?}", parameter))?;
+        if name.trim().eq_ignore_ascii_case("q") {
+            quality = parse_quality(value.trim())
+                .ok_or_else(|| format!("invalid quality {:?}", value))?;
+            // Anything after the quality is an "accept-ext", which we ignore.
+            break;
+        }
+    }
+    Ok(MediaRange { range, quality })
+}
+
+/// Parses a quality value (`qvalue` in RFC 9110), in thousandths.
+fn parse_quality(value: &str) -> Option<u16> {
+    let (whole, fraction) = value.split_once('.').unwrap_or((value, ""));
+    if fraction.len() > 3 || !fraction.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    let thousandths = format!("{:0<3}", fraction).parse::<u16>().ok()?;
+    match whole {
+        "0" => Some(thousandths),
+        "1" if thousandths == 0 => Some(1000),
+        _ => None,
+    }
+}
+
+fn is_token(s: &str) -> bool {
+    !s.is_empty()
+        && s.bytes().all(|b| {
+            b.is_ascii_alphanumeric() || b"!#$%&'*+-.^_`|~".contains(&b)
+        })
+}
+
+#[async_trait]
+impl SharedExtractor for Accept {
+    async fn from_request<Context: ServerContext>(
+        rqctx: &RequestContext<Context>,
+    ) -> Result<Accept, HttpError> {
+        Accept::from_headers(rqctx.request.headers()).map_err(|message| {
+            HttpError::for_bad_request(
+                None,
+                format!("invalid Accept header: {}", message),
+            )
+        })
+    }
+
+    // OpenAPI ignores header parameters named "Accept", since the media types
+    // of the responses already describe what the client may ask for.
+    fn metadata(
+        _content_type: ApiEndpointBodyContentType,
+    ) -> ExtractorMetadata {
+        ExtractorMetadata {
+            parameters: vec![],
+            extension_mode: ExtensionMode::None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::parse_quality;
+    use super::Accept;
+    use http::HeaderMap;
+    use http::StatusCode;
+
+    fn accept(values: &[&str]) -> Accept {
+        let mut headers = HeaderMap::new();
+        for value in values {
+            headers.append(http::header::ACCEPT, value.parse().unwrap());
+        }
+        Accept::from_headers(&headers).unwrap()
+    }
+
+    fn ranges(accept: &Accept) -> Vec<(&str, f32)> {
+        accept.ranges().iter().map(|r| (r.media_type(), r.quality())).collect()
+    }
+
+    #[test]
+    fn test_accept_parse() {
+        assert_eq!(ranges(&accept(&[])), vec![("*/*", 1.0)]);
+        assert_eq!(ranges(&accept(&[""])), vec![("*/*", 1.0)]);
+
+        let accept = accept(&[
+            "text/*;q=0.5, */*;q=0.1, Text/HTML;level=1",
+            "application/json;q=0.5;charset=utf-8",
+        ]);
+        assert_eq!(
+            ranges(&accept),
+            vec![
+                ("text/html", 1.0),
+                ("application/json", 0.5),
+                ("text/*", 0.5),
+                ("*/*", 0.1),
+            ]
+        );
+
+        let mut headers = HeaderMap::new();
+        for bad in [
+            "json",
+            "*/json",
+            "text/",
+            "text/html;q",
+            "text/html;q=2",
+            "text/html;q=0.1234",
+            "text/html;q=1.5",
+            "text/html;q=abc",
+        ] {
+            headers.insert(http::header::ACCEPT, bad.parse().unwrap());
+            assert!(Accept::from_headers(&headers).is_err(), "{}", bad);
+        }
+    }
+
+    #[test]
+    fn test_accept_quality() {
+        assert_eq!(parse_quality("0"), Some(0));
+        assert_eq!(parse_quality("0.25"), Some(250));
+        assert_eq!(parse_quality("1.000"), Some(1000));
+        assert_eq!(parse_quality("1.001"), None);
+
+        let accept = accept(&["text/*;q=0.3, text/html;q=0.7, */*;q=0.5"]);
+        assert_eq!(accept.quality("text/html"), 0.7);
+        assert_eq!(accept.quality("text/html; charset=utf-8"), 0.7);
+        assert_eq!(accept.quality("text/plain"), 0.3);
+        assert_eq!(accept.quality("image/png"), 0.5);
+        assert_eq!(accept.quality("bogus"), 0.0);
+    }
+
+    #[test]
+    fn test_accept_negotiate() {
+        let supported = ["application/json", "application/cbor"];
+        assert_eq!(accept(&[]).negotiate(&supported).unwrap(), supported[0]);
+        assert_eq!(
+            accept(&["application/cbor"]).negotiate(&supported).unwrap(),
+            supported[1]
+        );
+        assert_eq!(
+            accept(&["application/*;q=0.5, application/cbor;q=0.9"])
+                .negotiate(&supported)
+                .unwrap(),
+            supported[1]
+        );
+        assert_eq!(
+            accept(&["application/*"]).negotiate(&supported).unwrap(),
+            supported[0]
+        );
+        assert_eq!(
+            accept(&["*/*, application/json;q=0"])
+                .negotiate(&supported)
+                .unwrap(),
+            supported[1]
+        );
+
+        let error = accept(&["text/html"]).negotiate(&supported).unwrap_err();
+        assert_eq!(error.status_code, StatusCode::NOT_ACCEPTABLE);
+        assert_eq!(
+            error.external_message,
+            "none of the available media types is acceptable: \
+             application/json, application/cbor"
+        );
+    }
+}
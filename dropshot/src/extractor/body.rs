@@ -2,12 +2,17 @@
 
 //! Body-related extractor(s)
 
+use super::charset::decode_charset;
+use super::sniff::verify_content_type;
+use super::validate::SchemaValidators;
 use crate::api_description::ApiEndpointParameter;
 use crate::api_description::ApiSchemaGenerator;
 use crate::api_description::{ApiEndpointBodyContentType, ExtensionMode};
 use crate::error::HttpError;
+use crate::expect_continue::check_declared_length;
 use crate::http_util::http_read_body;
 use crate::http_util::CONTENT_TYPE_JSON;
+use crate::http_util::CONTENT_TYPE_MULTIPART_FORM_DATA;
 use crate::schema_util::make_subschema_for;
 use crate::server::ServerContext;
 use crate::ExclusiveExtractor;
@@ -15,14 +20,20 @@ use crate::ExtractorMetadata;
 use crate::RequestContext;
 use async_trait::async_trait;
 use bytes::Bytes;
+use futures::Stream;
 use schemars::schema::InstanceType;
 use schemars::schema::SchemaObject;
 use schemars::JsonSchema;
 use serde::de::DeserializeOwned;
+use serde::Deserialize;
 use std::fmt::Debug;
+use std::ops::Deref;
+use std::ops::DerefMut;
+use std::pin::Pin;
+use std::task::Poll;
 
 // TypedBody: body extractor for formats that can be deserialized to a specific
-// type.  Only JSON is currently supported.
+// type: JSON, form/url-encoded data, MessagePack, CBOR, XML, and YAML.
 
 /// `TypedBody<BodyType>` is an extractor used to deserialize an instance of
 /// `BodyType` from an HTTP request body.  `BodyType` is any structure of yours
@@ -36,31 +47,65 @@ pub struct TypedBody<BodyType: JsonSchema + DeserializeOwned + Send + Sync> {
 impl<BodyType: JsonSchema + DeserializeOwned + Send + Sync>
     TypedBody<BodyType>
 {
-    // TODO drop this in favor of Deref?  + Display and Debug for convenience?
+    /// Returns the deserialized body.  (The body can also be used in place
+    /// through `Deref`.)
     pub fn into_inner(self) -> BodyType {
         self.inner
     }
 }
 
+impl<BodyType: JsonSchema + DeserializeOwned + Send + Sync> Deref
+    for TypedBody<BodyType>
+{
+    type Target = BodyType;
+
+    fn deref(&self) -> &BodyType {
+        &self.inner
+    }
+}
+
+impl<BodyType: JsonSchema + DeserializeOwned + Send + Sync> DerefMut
+    for TypedBody<BodyType>
+{
+    fn deref_mut(&mut self) -> &mut BodyType {
+        &mut self.inner
+    }
+}
+
+impl<BodyType: JsonSchema + DeserializeOwned + Send + Sync> AsRef<BodyType>
+    for TypedBody<BodyType>
+{
+    fn as_ref(&self) -> &BodyType {
+        &self.inner
+    }
+}
+
 /// Given an HTTP request, attempt to read the body, parse it according
 /// to the content type, and deserialize it to an instance of `BodyType`.
 async fn http_request_load_body<Context: ServerContext, BodyType>(
     rqctx: &RequestContext<Context>,
-    mut request: hyper::Request<hyper::Body>,
+    mut request: hyper::Request<crate::Body>,
 ) -> Result<TypedBody<BodyType>, HttpError>
 where
-    BodyType: JsonSchema + DeserializeOwned + Send + Sync,
+    BodyType: JsonSchema + DeserializeOwned + Send + Sync + 'static,
 {
     let server = &rqctx.server;
-    let body = http_read_body(
-        request.body_mut(),
-        server.config.request_body_max_bytes,
-    )
-    .await?;
+    check_declared_length(request.headers(), rqctx.request_body_max_bytes())?;
+    let body =
+        http_read_body(request.body_mut(), rqctx.request_body_max_bytes())
+            .await?;
+    let decompressed_max_bytes = server
+        .config
+        .request_body_decompressed_max_bytes
+        .unwrap_or_else(|| rqctx.request_body_max_bytes());
+    let body = server.content_decoders.decode(
+        request.headers(),
+        body,
+        decompressed_max_bytes,
+    )?;
 
     // RFC 7231 §3.1.1.1: media types are case insensitive and may
-    // be followed by whitespace and/or a parameter (e.g., charset),
-    // which we currently ignore.
+    // be followed by whitespace and/or a parameter (e.g., charset).
     let content_type = request
         .headers()
         .get(http::header::CONTENT_TYPE)
@@ -78,37 +123,214 @@ where
     let body_content_type =
         ApiEndpointBodyContentType::from_mime_type(&mime_type)
             .map_err(|e| HttpError::for_bad_request(None, e))?;
-    let expected_content_type = rqctx.body_content_type.clone();
+    // If the endpoint accepts several content types, the body is parsed as
+    // whichever one the request uses.
+    let expected_content_type =
+        if rqctx.additional_body_content_types.contains(&body_content_type) {
+            body_content_type.clone()
+        } else {
+            rqctx.body_content_type.clone()
+        };
+
+    // YAML is decoded into a JSON value, which is kept for schema validation
+    // below.
+    let mut decoded_value = None;
 
     use ApiEndpointBodyContentType::*;
-    let content: BodyType = match (expected_content_type, body_content_type) {
+
+    // Text formats are parsed as UTF-8, so bodies in other character sets
+    // are converted first.
+    let body = match expected_content_type {
+        Json | UrlEncoded | Xml | Yaml => decode_charset(content_type, body)?,
+        _ => body,
+    };
+
+    let content: BodyType = match (&expected_content_type, &body_content_type) {
         (Json, Json) => serde_json::from_slice(&body).map_err(|e| {
             HttpError::for_bad_request(
                 None,
                 format!("unable to parse JSON body: {}", e),
             )
         })?,
-        (UrlEncoded, UrlEncoded) => serde_urlencoded::from_bytes(&body)
+        (UrlEncoded, UrlEncoded) => urlencoded_from_bytes(&body)?,
+        (MsgPack, MsgPack) => msgpack_from_slice(&body).map_err(|e| {
+            HttpError::for_bad_request(
+                None,
+                format!("unable to parse MessagePack body: {}", e),
+            )
+        })?,
+        (Cbor, Cbor) => cbor_from_slice(&body).map_err(|e| {
+            HttpError::for_bad_request(
+                None,
+                format!("unable to parse CBOR body: {}", e),
+            )
+        })?,
+        (Yaml, Yaml) => {
+            let value =
+                serde_yaml::from_slice(&body).map_err(|e| e.to_string());
+            from_decoded_value(value, "YAML", &mut decoded_value)?
+        }
+        (Xml, Xml) => std::str::from_utf8(&body)
+            .map_err(|e| e.to_string())
+            .and_then(|xml| {
+                quick_xml::de::from_str(xml).map_err(|e| e.to_string())
+            })
             .map_err(|e| {
                 HttpError::for_bad_request(
                     None,
-                    format!("unable to parse URL-encoded body: {}", e),
+                    format!("unable to parse XML body: {}", e),
                 )
             })?,
         (expected, requested) => {
+            let expected = std::iter::once(expected)
+                .chain(&rqctx.additional_body_content_types)
+                .map(|content_type| format!("\"{}\"", content_type.mime_type()))
+                .collect::<Vec<_>>()
+                .join(" or ");
             return Err(HttpError::for_bad_request(
                 None,
                 format!(
-                    "expected content type \"{}\", got \"{}\"",
-                    expected.mime_type(),
+                    "expected content type {}, got \"{}\"",
+                    expected,
                     requested.mime_type()
                 ),
-            ))
+            ));
         }
     };
+
+    if server.config.schema_validation {
+        let validator = rqctx.schema_validators.get::<BodyType>()?;
+        match expected_content_type {
+            Json => {
+                // This can't fail because the body was already parsed above.
+                let value = serde_json::from_slice(&body).map_err(|e| {
+                    HttpError::for_bad_request(
+                        None,
+                        format!("unable to parse JSON body: {}", e),
+                    )
+                })?;
+                validator.check("body", &value)?;
+            }
+            UrlEncoded => {
+                let form = urlencoded_from_bytes(&body)?;
+                validator.check("body", &validator.form_to_value(form))?;
+            }
+            // Binary data, MessagePack extension types, and CBOR tags have no
+            // JSON form, so the schema can't be checked for bodies that
+            // contain them (beyond what deserializing the body already
+            // checked).
+            MsgPack => {
+                if let Ok(value) = msgpack_from_slice(&body) {
+                    validator.check("body", &value)?;
+                }
+            }
+            Cbor => {
+                if let Ok(value) = cbor_from_slice(&body) {
+                    validator.check("body", &value)?;
+                }
+            }
+            Yaml => {
+                if let Some(value) = &decoded_value {
+                    validator.check("body", value)?;
+                }
+            }
+            _ => (),
+        }
+    }
+
     Ok(TypedBody { inner: content })
 }
 
+/// Parses a URL-encoded body.  Keys may describe nested structures with
+/// brackets (e.g., `filter[name]=x&ids[]=1&ids[]=2`).  Browsers
+/// percent-encode the brackets when they submit forms, so encoded brackets are
+/// accepted, too.  A key without a value (e.g., `b` in `a=1&b`) is rejected,
+/// as it always has been, rather than being treated as an empty value.
+fn urlencoded_from_bytes<T: DeserializeOwned>(
+    body: &[u8],
+) -> Result<T, HttpError> {
+    let parse_error = |message: String| {
+        HttpError::for_bad_request(
+            None,
+            format!("unable to parse URL-encoded body: {}", message),
+        )
+    };
+    if let Some(key) = body
+        .split(|b| *b == b'&')
+        .find(|pair| !pair.is_empty() && !pair.contains(&b'='))
+    {
+        return Err(parse_error(format!(
+            "missing value for key \"{}\"",
+            String::from_utf8_lossy(key)
+        )));
+    }
+    serde_qs::Config::new(5, false)
+        .deserialize_bytes(body)
+        .map_err(|e| parse_error(e.to_string()))
+}
+
+/// Deserializes a MessagePack body, which must consist of exactly one value.
+/// Types that serialize differently for human-readable formats (such as UUIDs
+/// and timestamps) are expected in the same form they'd have in JSON.
+fn msgpack_from_slice<T: DeserializeOwned>(body: &[u8]) -> Result<T, String> {
+    let mut deserializer =
+        rmp_serde::Deserializer::new(std::io::Cursor::new(body))
+            .with_human_readable();
+    let value = T::deserialize(&mut deserializer).map_err(|e| e.to_string())?;
+    let end = deserializer.get_ref().position();
+    if end != body.len() as u64 {
+        return Err(format!("trailing data at offset {}", end));
+    }
+    Ok(value)
+}
+
+/// Deserializes a CBOR body, which must consist of exactly one data item.
+fn cbor_from_slice<T: DeserializeOwned>(body: &[u8]) -> Result<T, String> {
+    let mut rest = body;
+    let value = ciborium::de::from_reader(&mut rest).map_err(|e| match e {
+        ciborium::de::Error::Io(e)
+            if e.kind() == std::io::ErrorKind::UnexpectedEof =>
+        {
+            String::from("unexpected end of data")
+        }
+        ciborium::de::Error::Io(e) => e.to_string(),
+        ciborium::de::Error::Syntax(offset) => {
+            format!("invalid data at offset {}", offset)
+        }
+        ciborium::de::Error::Semantic(_, message) => message,
+        ciborium::de::Error::RecursionLimitExceeded => {
+            String::from("nested too deeply")
+        }
+    })?;
+    if !rest.is_empty() {
+        return Err(format!(
+            "trailing data at offset {}",
+            body.len() - rest.len()
+        ));
+    }
+    Ok(value)
+}
+
+/// Deserializes a body that was decoded (from the format named `format`) into
+/// a JSON value, saving the value in `saved`.
+fn from_decoded_value<BodyType: DeserializeOwned>(
+    value: Result<serde_json::Value, String>,
+    format: &str,
+    saved: &mut Option<serde_json::Value>,
+) -> Result<BodyType, HttpError> {
+    let parse_error = |e: String| {
+        HttpError::for_bad_request(
+            None,
+            format!("unable to parse {} body: {}", format, e),
+        )
+    };
+    let value = value.map_err(parse_error)?;
+    let content = serde_json::from_value(value.clone())
+        .map_err(|e| parse_error(e.to_string()))?;
+    *saved = Some(value);
+    Ok(content)
+}
+
 // The `ExclusiveExtractor` implementation for TypedBody<BodyType> describes how
 // to construct an instance of `TypedBody<BodyType>` from an HTTP request:
 // namely, by reading the request body and parsing it as JSON into type
@@ -122,7 +344,7 @@ where
 {
     async fn from_request<Context: ServerContext>(
         rqctx: &RequestContext<Context>,
-        request: hyper::Request<hyper::Body>,
+        request: hyper::Request<crate::Body>,
     ) -> Result<TypedBody<BodyType>, HttpError> {
         http_request_load_body(rqctx, request).await
     }
@@ -142,15 +364,24 @@ where
             parameters: vec![body],
         }
     }
+
+    fn schema_validators(validators: &mut SchemaValidators) {
+        validators.add::<BodyType>();
+    }
 }
 
 // UntypedBody: body extractor for a plain array of bytes of a body.
 
 /// `UntypedBody` is an extractor for reading in the contents of the HTTP request
 /// body and making the raw bytes directly available to the consumer.
+///
+/// Handlers that accept uploads of particular types should use
+/// [`UntypedBody::verify_content_type()`] to check that the body really is of
+/// the type it claims to be before storing it.
 #[derive(Debug)]
 pub struct UntypedBody {
     content: Bytes,
+    content_type: Option<String>,
 }
 
 impl UntypedBody {
@@ -170,21 +401,50 @@ impl UntypedBody {
             )
         })
     }
+
+    /// Returns the value of the request's `Content-Type` header, if any.
+    pub fn content_type(&self) -> Option<&str> {
+        self.content_type.as_deref()
+    }
+
+    /// Verifies that the declared media type of the body (from the
+    /// `Content-Type` header) is one of `allowed` and that the body's contents
+    /// actually match it, returning the declared media type (lowercased,
+    /// without parameters).  Entries of `allowed` may be media types (e.g.,
+    /// `"image/png"`) or wildcards (e.g., `"image/*"`).
+    ///
+    /// The contents are checked by looking for the "magic bytes" of common
+    /// formats (PNG, JPEG, GIF, WebP, BMP, TIFF, ICO, PDF, ZIP, gzip, and MP4)
+    /// and for markup that browsers would render as HTML.  A body declared as
+    /// one of these formats must start with its signature, and a body declared
+    /// as any other type must not look like a different one of them.  (A body
+    /// declared as `application/octet-stream` may be anything but HTML.)
+    ///
+    /// Fails with a 415 ("Unsupported Media Type") error if the content type
+    /// is missing or not allowed, or if the contents don't match it.
+    pub fn verify_content_type(
+        &self,
+        allowed: &[&str],
+    ) -> Result<String, HttpError> {
+        verify_content_type(self.content_type(), &self.content, allowed)
+    }
 }
 
 #[async_trait]
 impl ExclusiveExtractor for UntypedBody {
     async fn from_request<Context: ServerContext>(
         rqctx: &RequestContext<Context>,
-        mut request: hyper::Request<hyper::Body>,
+        mut request: hyper::Request<crate::Body>,
     ) -> Result<UntypedBody, HttpError> {
-        let server = &rqctx.server;
-        let body_bytes = http_read_body(
-            request.body_mut(),
-            server.config.request_body_max_bytes,
-        )
-        .await?;
-        Ok(UntypedBody { content: body_bytes })
+        let max_bytes = rqctx.request_body_max_bytes();
+        check_declared_length(request.headers(), max_bytes)?;
+        let body_bytes = http_read_body(request.body_mut(), max_bytes).await?;
+        let content_type = request
+            .headers()
+            .get(http::header::CONTENT_TYPE)
+            .and_then(|value| value.to_str().ok())
+            .map(str::to_string);
+        Ok(UntypedBody { content: body_bytes, content_type })
     }
 
     fn metadata(
@@ -211,3 +471,321 @@ impl ExclusiveExtractor for UntypedBody {
         }
     }
 }
+
+// RawBody: body extractor for the unprocessed body, including any trailers.
+
+/// `RawBody` is an extractor that provides the request body as it arrives,
+/// without buffering or decoding it, along with any trailers that follow it
+/// (as in upload protocols that send a checksum of the body in a trailer).
+///
+/// The body's data can be read with [`RawBody::data()`] or by using the body as
+/// a [`Stream`] of chunks, and is limited to the endpoint's request body limit
+/// (see `RequestContext::request_body_max_bytes()`).  Once the data has been
+/// read, [`RawBody::trailers()`] provides the trailers.  Alternatively,
+/// [`RawBody::into_inner()`] provides the underlying [`Body`](crate::Body)
+/// itself, to which no limit applies.
+#[derive(Debug)]
+pub struct RawBody {
+    body: crate::Body,
+    trailers: Option<http::HeaderMap>,
+    nbytesread: usize,
+    max_bytes: usize,
+}
+
+impl RawBody {
+    /// Returns the next chunk of the body's data, or `None` if there is no
+    /// more.
+    pub async fn data(&mut self) -> Option<Result<Bytes, HttpError>> {
+        futures::future::poll_fn(|cx| Pin::new(&mut *self).poll_next(cx)).await
+    }
+
+    /// Returns the trailers that followed the body, if there were any.  Any
+    /// data that hasn't been read yet is read (but not kept) first.
+    pub async fn trailers(
+        mut self,
+    ) -> Result<Option<http::HeaderMap>, HttpError> {
+        while let Some(data) = self.data().await {
+            data?;
+        }
+        Ok(self.trailers)
+    }
+
+    /// Returns the underlying body, none of which has been read yet unless
+    /// [`RawBody::data()`] was used.
+    pub fn into_inner(self) -> crate::Body {
+        self.body
+    }
+}
+
+impl Stream for RawBody {
+    type Item = Result<Bytes, HttpError>;
+
+    fn poll_next(
+        self: Pin<&mut Self>,
+        cx: &mut std::task::Context<'_>,
+    ) -> Poll<Option<Self::Item>> {
+        let this = self.get_mut();
+        loop {
+            let frame = match futures::ready!(http_body::Body::poll_frame(
+                Pin::new(&mut this.body),
+                cx
+            )) {
+                None => return Poll::Ready(None),
+                Some(Err(error)) => {
+                    return Poll::Ready(Some(Err(HttpError::for_bad_request(
+                        None,
+                        format!("error processing request: {}", error),
+                    ))))
+                }
+                Some(Ok(frame)) => frame,
+            };
+            let frame = match frame.into_data() {
+                Ok(data) => {
+                    this.nbytesread += data.len();
+                    if this.nbytesread > this.max_bytes {
+                        return Poll::Ready(Some(Err(
+                            HttpError::for_bad_request(
+                                None,
+                                format!(
+                                    "request body exceeded maximum size of {} \
+                                     bytes",
+                                    this.max_bytes
+                                ),
+                            ),
+                        )));
+                    }
+                    return Poll::Ready(Some(Ok(data)));
+                }
+                Err(frame) => frame,
+            };
+            if let Ok(trailers) = frame.into_trailers() {
+                this.trailers
+                    .get_or_insert_with(Default::default)
+                    .extend(trailers);
+            }
+        }
+    }
+}
+
+#[async_trait]
+impl ExclusiveExtractor for RawBody {
+    async fn from_request<Context: ServerContext>(
+        rqctx: &RequestContext<Context>,
+        request: hyper::Request<crate::Body>,
+    ) -> Result<RawBody, HttpError> {
+        let max_bytes = rqctx.request_body_max_bytes();
+        check_declared_length(request.headers(), max_bytes)?;
+        Ok(RawBody {
+            body: request.into_body(),
+            trailers: None,
+            nbytesread: 0,
+            max_bytes,
+        })
+    }
+
+    fn metadata(content_type: ApiEndpointBodyContentType) -> ExtractorMetadata {
+        // The body is described just as it would be for `UntypedBody`.
+        UntypedBody::metadata(content_type)
+    }
+}
+
+// MultipartBody: body extractor for multipart/form-data requests, whose parts
+// are read incrementally.
+
+/// `MultipartBody` is an extractor for `multipart/form-data` request bodies,
+/// like those of HTML forms that upload files.  Rather than reading the whole
+/// body up front, it provides the parts of the body one at a time as they
+/// arrive (see [`MultipartBody::next_part()`]), and each part's contents are
+/// themselves streamed (see [`MultipartPart::chunk()`]).  This way, a request
+/// that mixes small fields with large files can be processed without ever
+/// buffering a file in memory.
+///
+/// The body as a whole is limited to the endpoint's request body limit (see
+/// `RequestContext::request_body_max_bytes()`), and each part to the server's
+/// `multipart_part_max_bytes` (if set).  Exceeding either limit fails the read
+/// that exceeded it with a 400 error.
+#[derive(Debug)]
+pub struct MultipartBody {
+    multipart: multer::Multipart<'static>,
+}
+
+impl MultipartBody {
+    /// Returns the next part of the body, or `None` if there are no more.
+    ///
+    /// Any unread contents of the previous part are skipped, so the previous
+    /// part should be dropped before calling this.
+    pub async fn next_part(
+        &mut self,
+    ) -> Result<Option<MultipartPart>, HttpError> {
+        let field =
+            self.multipart.next_field().await.map_err(multipart_error)?;
+        Ok(field.map(|field| MultipartPart { field }))
+    }
+
+    /// Converts this body into a stream of its parts.  As with
+    /// [`MultipartBody::next_part()`], each part should be dropped before the
+    /// next one is requested.
+    pub fn into_stream(
+        self,
+    ) -> impl Stream<Item = Result<MultipartPart, HttpError>> + Send {
+        futures::stream::unfold(self, |mut body| async move {
+            body.next_part().await.transpose().map(|part| (part, body))
+        })
+    }
+}
+
+#[async_trait]
+impl ExclusiveExtractor for MultipartBody {
+    async fn from_request<Context: ServerContext>(
+        rqctx: &RequestContext<Context>,
+        request: hyper::Request<crate::Body>,
+    ) -> Result<MultipartBody, HttpError> {
+        let content_type = request
+            .headers()
+            .get(http::header::CONTENT_TYPE)
+            .and_then(|value| value.to_str().ok())
+            .unwrap_or("");
+        let boundary = multer::parse_boundary(content_type).map_err(|_| {
+            HttpError::for_bad_request(
+                None,
+                format!(
+                    "expected content type \"{}\" with a boundary, got \"{}\"",
+                    CONTENT_TYPE_MULTIPART_FORM_DATA, content_type
+                ),
+            )
+        })?;
+
+        let max_bytes = rqctx.request_body_max_bytes();
+        check_declared_length(request.headers(), max_bytes)?;
+        let config = &rqctx.server.config;
+        let mut size_limit =
+            multer::SizeLimit::new().whole_stream(max_bytes as u64);
+        if let Some(max) = config.multipart_part_max_bytes {
+            size_limit = size_limit.per_field(max as u64);
+        }
+        let multipart = multer::Multipart::with_constraints(
+            request.into_body(),
+            boundary,
+            multer::Constraints::new().size_limit(size_limit),
+        );
+        Ok(MultipartBody { multipart })
+    }
+
+    fn metadata(
+        _content_type: ApiEndpointBodyContentType,
+    ) -> ExtractorMetadata {
+        ExtractorMetadata {
+            parameters: vec![ApiEndpointParameter::new_body(
+                ApiEndpointBodyContentType::MultipartFormData,
+                true,
+                ApiSchemaGenerator::Static {
+                    schema: Box::new(
+                        SchemaObject {
+                            instance_type: Some(InstanceType::Object.into()),
+                            ..Default::default()
+                        }
+                        .into(),
+                    ),
+                    dependencies: indexmap::IndexMap::default(),
+                },
+                vec![],
+            )],
+            extension_mode: ExtensionMode::None,
+        }
+    }
+}
+
+/// One part of a [`MultipartBody`], typically a form field or an uploaded
+/// file.  The part's contents can be read incrementally with
+/// [`MultipartPart::chunk()`] or by using the part as a [`Stream`] of chunks,
+/// or all at once (for small parts, like most form fields) with
+/// [`MultipartPart::bytes()`] or [`MultipartPart::text()`].
+#[derive(Debug)]
+pub struct MultipartPart {
+    field: multer::Field<'static>,
+}
+
+impl MultipartPart {
+    /// Returns the name of the form field, if any.
+    pub fn name(&self) -> Option<&str> {
+        self.field.name()
+    }
+
+    /// Returns the name of the uploaded file, if this part is a file.
+    pub fn file_name(&self) -> Option<&str> {
+        self.field.file_name()
+    }
+
+    /// Returns the value of the part's `Content-Type` header, if any.
+    pub fn content_type(&self) -> Option<&str> {
+        self.field.content_type().map(|mime| mime.as_ref())
+    }
+
+    /// Returns the part's headers.
+    pub fn headers(&self) -> &http::HeaderMap {
+        self.field.headers()
+    }
+
+    /// Returns the next chunk of the part's contents, or `None` if there are
+    /// no more.
+    pub async fn chunk(&mut self) -> Result<Option<Bytes>, HttpError> {
+        self.field.chunk().await.map_err(multipart_error)
+    }
+
+    /// Reads the rest of the part's contents into memory.
+    pub async fn bytes(self) -> Result<Bytes, HttpError> {
+        self.field.bytes().await.map_err(multipart_error)
+    }
+
+    /// Reads the rest of the part's contents into memory as a string, failing
+    /// with a 400 error if they are not valid UTF-8.
+    pub async fn text(self) -> Result<String, HttpError> {
+        let name = self.name().unwrap_or("").to_string();
+        let bytes = self.bytes().await?;
+        String::from_utf8(bytes.to_vec()).map_err(|_| {
+            HttpError::for_bad_request(
+                None,
+                format!("part \"{}\" is not valid UTF-8", name),
+            )
+        })
+    }
+}
+
+impl Stream for MultipartPart {
+    type Item = Result<Bytes, HttpError>;
+
+    fn poll_next(
+        mut self: Pin<&mut Self>,
+        cx: &mut std::task::Context<'_>,
+    ) -> Poll<Option<Self::Item>> {
+        Pin::new(&mut self.field)
+            .poll_next(cx)
+            .map(|item| item.map(|result| result.map_err(multipart_error)))
+    }
+}
+
+fn multipart_error(error: multer::Error) -> HttpError {
+    // Errors encountered while reading a part are reported as failures to
+    // read the underlying stream, so look inside those for the actual error.
+    let error = match error {
+        multer::Error::StreamReadFailed(inner) => {
+            match inner.downcast::<multer::Error>() {
+                Ok(inner) => *inner,
+                Err(inner) => multer::Error::StreamReadFailed(inner),
+            }
+        }
+        error => error,
+    };
+    let message = match error {
+        multer::Error::FieldSizeExceeded { limit, field_name } => format!(
+            "part {}exceeded maximum size of {} bytes",
+            field_name.map(|name| format!("\"{}\" ", name)).unwrap_or_default(),
+            limit
+        ),
+        multer::Error::StreamSizeExceeded { limit } => {
+            format!("request body exceeded maximum size of {} bytes", limit)
+        }
+        error => format!("unable to parse multipart body: {}", error),
+    };
+    HttpError::for_bad_request(None, message)
+}
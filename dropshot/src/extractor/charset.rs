@@ -0,0 +1,93 @@
+// Copyright 2023 Oxide Computer Company
+
+//! Decoding of text bodies according to the `charset` of their content type
+
+use crate::error::HttpError;
+use bytes::Bytes;
+use http::StatusCode;
+
+/// Returns the value of the `charset` parameter of a `Content-Type` header
+/// value, lowercased and without quotes, if there is one.
+fn charset(content_type: &str) -> Option<String> {
+    content_type.split(';').skip(1).find_map(|param| {
+        let (name, value) = param.split_once('=')?;
+        if !name.trim().eq_ignore_ascii_case("charset") {
+            return None;
+        }
+        let value = value.trim();
+        let value = value
+            .strip_prefix('"')
+            .and_then(|v| v.strip_suffix('"'))
+            .unwrap_or(value);
+        Some(value.to_ascii_lowercase())
+    })
+}
+
+/// Converts a text body whose `Content-Type` header value is `content_type` to
+/// UTF-8.  Bodies in UTF-8 (or ASCII, a subset of it), or without a `charset`,
+/// are returned as-is, and ISO-8859-1 (Latin-1) bodies are transcoded.  Bodies
+/// in any other character set fail with a 415 ("Unsupported Media Type")
+/// error.
+pub(super) fn decode_charset(
+    content_type: &str,
+    body: Bytes,
+) -> Result<Bytes, HttpError> {
+    let charset = match charset(content_type) {
+        None => return Ok(body),
+        Some(charset) => charset,
+    };
+    match charset.as_str() {
+        "utf-8" | "utf8" | "us-ascii" | "ascii" => Ok(body),
+        "iso-8859-1" | "iso8859-1" | "iso_8859-1" | "latin1" | "l1" => {
+            // Each byte of ISO-8859-1 is the code point of the same value.
+            let text = body.iter().map(|b| char::from(*b)).collect::<String>();
+            Ok(Bytes::from(text))
+        }
+        _ => Err(HttpError::for_client_error(
+            None,
+            StatusCode::UNSUPPORTED_MEDIA_TYPE,
+            format!("unsupported charset \"{}\"", charset),
+        )),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::charset;
+    use super::decode_charset;
+    use bytes::Bytes;
+    use http::StatusCode;
+
+    #[test]
+    fn test_charset() {
+        assert_eq!(charset("application/json"), None);
+        assert_eq!(
+            charset("application/json; charset=UTF-8"),
+            Some(String::from("utf-8"))
+        );
+        assert_eq!(
+            charset("text/xml;foo=bar; Charset=\"ISO-8859-1\""),
+            Some(String::from("iso-8859-1"))
+        );
+        assert_eq!(charset("text/plain; charsets=utf-8"), None);
+    }
+
+    #[test]
+    fn test_decode_charset() {
+        let body = Bytes::from_static(b"\"caf\xe9\"");
+        assert_eq!(
+            decode_charset("application/json", body.clone()).unwrap(),
+            body
+        );
+        assert_eq!(
+            decode_charset("application/json; charset=latin1", body.clone())
+                .unwrap(),
+            Bytes::from("\"café\"")
+        );
+        let error =
+            decode_charset("application/json; charset=utf-16", body.clone())
+                .unwrap_err();
+        assert_eq!(error.status_code, StatusCode::UNSUPPORTED_MEDIA_TYPE);
+        assert_eq!(error.external_message, "unsupported charset \"utf-16\"");
+    }
+}
@@ -1,5 +1,6 @@
 // Copyright 2023 Oxide Computer Company
 
+use super::validate::SchemaValidators;
 use crate::api_description::ApiEndpointParameter;
 use crate::api_description::{ApiEndpointBodyContentType, ExtensionMode};
 use crate::error::HttpError;
@@ -25,12 +26,18 @@ pub trait ExclusiveExtractor: Send + Sync + Sized {
     /// Construct an instance of this type from a `RequestContext`.
     async fn from_request<Context: ServerContext>(
         rqctx: &RequestContext<Context>,
-        request: hyper::Request<hyper::Body>,
+        request: hyper::Request<crate::Body>,
     ) -> Result<Self, HttpError>;
 
     fn metadata(
         body_content_type: ApiEndpointBodyContentType,
     ) -> ExtractorMetadata;
+
+    /// Adds validators for the types that this extractor deserializes, so
+    /// that they're built once, when the endpoint is registered.  Only
+    /// dropshot's own extractors need this.
+    #[doc(hidden)]
+    fn schema_validators(_validators: &mut SchemaValidators) {}
 }
 
 /// Extractors that do _not_ require exclusive access to the underyling
@@ -49,6 +56,12 @@ pub trait SharedExtractor: Send + Sync + Sized {
     fn metadata(
         body_content_type: ApiEndpointBodyContentType,
     ) -> ExtractorMetadata;
+
+    /// Adds validators for the types that this extractor deserializes, so
+    /// that they're built once, when the endpoint is registered.  Only
+    /// dropshot's own extractors need this.
+    #[doc(hidden)]
+    fn schema_validators(_validators: &mut SchemaValidators) {}
 }
 
 // A `SharedExtractor` can always be treated like an `ExclusiveExtractor`.
@@ -56,7 +69,7 @@ pub trait SharedExtractor: Send + Sync + Sized {
 impl<S: SharedExtractor> ExclusiveExtractor for S {
     async fn from_request<Context: ServerContext>(
         rqctx: &RequestContext<Context>,
-        _request: hyper::Request<hyper::Body>,
+        _request: hyper::Request<crate::Body>,
     ) -> Result<Self, HttpError> {
         <S as SharedExtractor>::from_request(rqctx).await
     }
@@ -66,6 +79,10 @@ impl<S: SharedExtractor> ExclusiveExtractor for S {
     ) -> ExtractorMetadata {
         <S as SharedExtractor>::metadata(body_content_type)
     }
+
+    fn schema_validators(validators: &mut SchemaValidators) {
+        <S as SharedExtractor>::schema_validators(validators)
+    }
 }
 
 /// Top-level extractor for a given request
@@ -96,12 +113,18 @@ pub trait RequestExtractor: Send + Sync + Sized {
     /// Construct an instance of this type from a `RequestContext`.
     async fn from_request<Context: ServerContext>(
         rqctx: &RequestContext<Context>,
-        request: hyper::Request<hyper::Body>,
+        request: hyper::Request<crate::Body>,
     ) -> Result<Self, HttpError>;
 
     fn metadata(
         body_content_type: ApiEndpointBodyContentType,
     ) -> ExtractorMetadata;
+
+    /// Adds validators for the types that this extractor deserializes, so
+    /// that they're built once, when the endpoint is registered.  Only
+    /// dropshot's own extractors need this.
+    #[doc(hidden)]
+    fn schema_validators(_validators: &mut SchemaValidators) {}
 }
 
 // Impl for zero-element tuple (used for request handlers with no extractors)
@@ -109,7 +132,7 @@ pub trait RequestExtractor: Send + Sync + Sized {
 impl RequestExtractor for () {
     async fn from_request<Context: ServerContext>(
         _rqctx: &RequestContext<Context>,
-        _request: hyper::Request<hyper::Body>,
+        _request: hyper::Request<crate::Body>,
     ) -> Result<Self, HttpError> {
         Ok(())
     }
@@ -129,7 +152,7 @@ impl RequestExtractor for () {
 impl<X: ExclusiveExtractor + 'static> RequestExtractor for (X,) {
     async fn from_request<Context: ServerContext>(
         rqctx: &RequestContext<Context>,
-        request: hyper::Request<hyper::Body>,
+        request: hyper::Request<crate::Body>,
     ) -> Result<Self, HttpError> {
         Ok((X::from_request(rqctx, request).await?,))
     }
@@ -139,6 +162,10 @@ impl<X: ExclusiveExtractor + 'static> RequestExtractor for (X,) {
     ) -> ExtractorMetadata {
         X::metadata(body_content_type)
     }
+
+    fn schema_validators(validators: &mut SchemaValidators) {
+        X::schema_validators(validators)
+    }
 }
 
 /// Defines implementations of `RequestExtractor` for tuples of one or more
@@ -162,7 +189,7 @@ macro_rules! impl_rqextractor_for_tuple {
     {
         async fn from_request<Context: ServerContext>(
             rqctx: &RequestContext<Context>,
-            request: hyper::Request<hyper::Body>
+            request: hyper::Request<crate::Body>
         ) -> Result<( $($S,)+ X ), HttpError>
         {
             futures::try_join!(
@@ -200,6 +227,11 @@ macro_rules! impl_rqextractor_for_tuple {
 
             ExtractorMetadata { extension_mode, parameters }
         }
+
+        fn schema_validators(validators: &mut SchemaValidators) {
+            $( $S::schema_validators(validators); )+
+            X::schema_validators(validators);
+        }
     }
 }}
 
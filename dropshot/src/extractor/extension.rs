@@ -0,0 +1,78 @@
+// Copyright 2023 Oxide Computer Company
+
+//! Request extension extractor
+
+use crate::api_description::ApiEndpointBodyContentType;
+use crate::api_description::ExtensionMode;
+use crate::error::HttpError;
+use crate::server::ServerContext;
+use crate::ExtractorMetadata;
+use crate::RequestContext;
+use crate::SharedExtractor;
+use async_trait::async_trait;
+use std::fmt::Debug;
+use std::ops::Deref;
+use std::ops::DerefMut;
+
+/// `Extension<T>` is an extractor for the value of type `T` attached to the
+/// request's [`crate::Extensions`] (e.g., by a [`crate::ContinueCheck`] that
+/// authenticates the request).  If there's no such value, the request fails
+/// with a 500 error, since that reflects a problem with the server rather
+/// than the request; handlers for which the value is optional can use
+/// [`crate::RequestContext::extensions()`] instead.
+///
+/// Extensions aren't part of the request as the client sees it, so they
+/// don't appear in the OpenAPI document.
+#[derive(Debug)]
+pub struct Extension<T: Clone + Send + Sync + 'static> {
+    inner: T,
+}
+
+impl<T: Clone + Send + Sync + 'static> Extension<T> {
+    /// Returns the extracted value.  (It can also be used in place through
+    /// `Deref`.)
+    pub fn into_inner(self) -> T {
+        self.inner
+    }
+}
+
+impl<T: Clone + Send + Sync + 'static> Deref for Extension<T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.inner
+    }
+}
+
+impl<T: Clone + Send + Sync + 'static> DerefMut for Extension<T> {
+    fn deref_mut(&mut self) -> &mut T {
+        &mut self.inner
+    }
+}
+
+#[async_trait]
+impl<T> SharedExtractor for Extension<T>
+where
+    T: Clone + Send + Sync + 'static,
+{
+    async fn from_request<Context: ServerContext>(
+        rqctx: &RequestContext<Context>,
+    ) -> Result<Extension<T>, HttpError> {
+        match rqctx.extensions().get::<T>() {
+            Some(inner) => Ok(Extension { inner }),
+            None => Err(HttpError::for_internal_error(format!(
+                "request has no extension of type {}",
+                std::any::type_name::<T>()
+            ))),
+        }
+    }
+
+    fn metadata(
+        _content_type: ApiEndpointBodyContentType,
+    ) -> ExtractorMetadata {
+        ExtractorMetadata {
+            parameters: vec![],
+            extension_mode: ExtensionMode::None,
+        }
+    }
+}
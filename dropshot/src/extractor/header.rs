@@ -0,0 +1,189 @@
+// Copyright 2023 Oxide Computer Company
+
+//! Header-related extractor(s)
+
+use super::metadata::get_metadata;
+use super::validate::SchemaValidators;
+use crate::api_description::ApiEndpointBodyContentType;
+use crate::api_description::ApiEndpointParameterLocation;
+use crate::api_description::ApiEndpointParameterMetadata;
+use crate::error::HttpError;
+use crate::from_map::from_map_keyed;
+use crate::server::ServerContext;
+use crate::ExtractorMetadata;
+use crate::RequestContext;
+use crate::SharedExtractor;
+use async_trait::async_trait;
+use http::HeaderMap;
+use schemars::JsonSchema;
+use serde::de::DeserializeOwned;
+use std::collections::BTreeMap;
+use std::fmt::Debug;
+use std::ops::Deref;
+use std::ops::DerefMut;
+
+/// `Header<HeaderType>` is an extractor used to deserialize an instance of
+/// `HeaderType` from an HTTP request's headers.  `HeaderType` is any structure
+/// of yours that implements `serde::Deserialize`; each of its fields is a
+/// header, named by the field's (serde) name with underscores replaced by
+/// hyphens (so a field `x_request_id` is the header `x-request-id`).  Header
+/// names are matched case-insensitively.  Headers that appear more than once
+/// are combined into one comma-separated value.
+///
+/// Each header is documented as a header parameter in the OpenAPI document,
+/// described by its field's doc comment.
+///
+/// ```
+/// use dropshot::Header;
+/// use schemars::JsonSchema;
+/// use serde::Deserialize;
+///
+/// #[derive(Deserialize, JsonSchema)]
+/// struct ClientHeaders {
+///     /// Identifies the client's request (header `x-request-id`)
+///     x_request_id: String,
+///     /// Number of times the client has retried (header `x-retry-count`)
+///     x_retry_count: Option<u32>,
+/// }
+/// ```
+#[derive(Debug)]
+pub struct Header<HeaderType: DeserializeOwned + JsonSchema + Send + Sync> {
+    inner: HeaderType,
+}
+
+impl<HeaderType: DeserializeOwned + JsonSchema + Send + Sync>
+    Header<HeaderType>
+{
+    /// Returns the deserialized headers.  (They can also be used in place
+    /// through `Deref`.)
+    pub fn into_inner(self) -> HeaderType {
+        self.inner
+    }
+}
+
+impl<HeaderType: DeserializeOwned + JsonSchema + Send + Sync> Deref
+    for Header<HeaderType>
+{
+    type Target = HeaderType;
+
+    fn deref(&self) -> &HeaderType {
+        &self.inner
+    }
+}
+
+impl<HeaderType: DeserializeOwned + JsonSchema + Send + Sync> DerefMut
+    for Header<HeaderType>
+{
+    fn deref_mut(&mut self) -> &mut HeaderType {
+        &mut self.inner
+    }
+}
+
+impl<HeaderType: DeserializeOwned + JsonSchema + Send + Sync> AsRef<HeaderType>
+    for Header<HeaderType>
+{
+    fn as_ref(&self) -> &HeaderType {
+        &self.inner
+    }
+}
+
+/// Returns the name of the header for the field (with serde name) `field`.
+pub(crate) fn header_name(field: &str) -> String {
+    field.replace('_', "-")
+}
+
+/// Collects the values of the headers that `HeaderType` declares, keyed by the
+/// (serde) names of their fields, failing if a required one is missing or if
+/// any of them isn't valid text.
+fn collect_headers<HeaderType: JsonSchema>(
+    headers: &HeaderMap,
+) -> Result<BTreeMap<String, String>, HttpError> {
+    let mut values = BTreeMap::new();
+    let parameters =
+        get_metadata::<HeaderType>(&ApiEndpointParameterLocation::Header)
+            .parameters;
+    for parameter in parameters {
+        let field = match parameter.metadata {
+            ApiEndpointParameterMetadata::Header(field) => field,
+            _ => continue,
+        };
+        let name = header_name(&field);
+        let mut header_values =
+            headers.get_all(name.as_str()).iter().peekable();
+        if header_values.peek().is_none() {
+            if parameter.required {
+                return Err(HttpError::for_bad_request(
+                    None,
+                    format!("missing required header \"{}\"", name),
+                ));
+            }
+            continue;
+        }
+        let value = header_values
+            .map(|value| value.to_str())
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|e| {
+                HttpError::for_bad_request(
+                    None,
+                    format!("invalid value for header \"{}\": {}", name, e),
+                )
+            })?
+            .join(", ");
+        values.insert(field, value);
+    }
+    Ok(values)
+}
+
+// The `SharedExtractor` implementation for Header<HeaderType> describes how to
+// construct an instance of `Header<HeaderType>` from an HTTP request: namely,
+// by deserializing the headers that `HeaderType` declares.
+#[async_trait]
+impl<HeaderType> SharedExtractor for Header<HeaderType>
+where
+    HeaderType: DeserializeOwned + JsonSchema + Send + Sync + 'static,
+{
+    async fn from_request<Context: ServerContext>(
+        rqctx: &RequestContext<Context>,
+    ) -> Result<Header<HeaderType>, HttpError> {
+        let values = collect_headers::<HeaderType>(rqctx.request.headers())?;
+        let inner = from_map_keyed(&values).map_err(|e| {
+            let message = match &e.key {
+                Some(field) => format!(
+                    "invalid value for header \"{}\": {}",
+                    header_name(field),
+                    e
+                ),
+                None => format!("unable to parse headers: {}", e),
+            };
+            HttpError::for_bad_request(None, message)
+        })?;
+        if rqctx.server.config.schema_validation {
+            let validator = rqctx.schema_validators.get::<HeaderType>()?;
+            let value =
+                validator.params_to_value(values.iter().map(
+                    |(name, value)| (name.as_str(), vec![value.as_str()]),
+                ));
+            validator.check("headers", &value)?;
+        }
+        Ok(Header { inner })
+    }
+
+    fn metadata(
+        _body_content_type: ApiEndpointBodyContentType,
+    ) -> ExtractorMetadata {
+        let mut metadata =
+            get_metadata::<HeaderType>(&ApiEndpointParameterLocation::Header);
+        for parameter in &mut metadata.parameters {
+            if let ApiEndpointParameterMetadata::Header(name) =
+                &mut parameter.metadata
+            {
+                *name = header_name(name);
+            }
+        }
+        metadata
+    }
+
+    fn schema_validators(validators: &mut SchemaValidators) {
+        validators.add::<HeaderType>();
+    }
+}
@@ -1,11 +1,11 @@
 // Copyright 2023 Oxide Computer Company
 
 use crate::api_description::ApiSchemaGenerator;
-use crate::pagination::PAGINATION_PARAM_SENTINEL;
+use crate::api_description::PAGINATION_PARAM_SENTINEL;
+use crate::api_description::WEBSOCKET_PARAM_SENTINEL;
 use crate::schema_util::schema2struct;
 use crate::schema_util::schema_extensions;
 use crate::schema_util::ReferenceVisitor;
-use crate::websocket::WEBSOCKET_PARAM_SENTINEL;
 use crate::ApiEndpointParameter;
 use crate::ApiEndpointParameterLocation;
 use crate::ExtensionMode;
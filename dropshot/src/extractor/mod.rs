@@ -10,17 +10,44 @@ pub use common::ExtractorMetadata;
 pub use common::RequestExtractor;
 pub use common::SharedExtractor;
 
+mod accept;
+pub use accept::Accept;
+pub use accept::MediaRange;
+
 mod body;
+pub use body::MultipartBody;
+pub use body::MultipartPart;
+pub use body::RawBody;
 pub use body::TypedBody;
 pub use body::UntypedBody;
 
+mod charset;
+
+mod extension;
+pub use extension::Extension;
+
+mod header;
+pub(crate) use header::header_name;
+pub use header::Header;
+
 mod metadata;
 
+mod ndjson;
+pub use ndjson::TypedStream;
+
 mod path;
 pub use path::Path;
 
+mod precondition;
+pub use precondition::Preconditions;
+
 mod query;
 pub use query::Query;
 
 mod raw_request;
 pub use raw_request::RawRequest;
+
+mod sniff;
+
+mod validate;
+pub(crate) use validate::SchemaValidators;
@@ -0,0 +1,242 @@
+// Copyright 2023 Oxide Computer Company
+
+//! Streaming extractor for newline-delimited JSON request bodies
+
+use super::validate::SchemaValidator;
+use super::validate::SchemaValidators;
+use crate::api_description::ApiEndpointBodyContentType;
+use crate::api_description::ApiEndpointParameter;
+use crate::api_description::ApiSchemaGenerator;
+use crate::api_description::ExtensionMode;
+use crate::error::HttpError;
+use crate::expect_continue::check_declared_length;
+use crate::http_util::CONTENT_TYPE_NDJSON;
+use crate::schema_util::make_subschema_for;
+use crate::server::ServerContext;
+use crate::ExclusiveExtractor;
+use crate::ExtractorMetadata;
+use crate::RequestContext;
+use async_trait::async_trait;
+use bytes::BytesMut;
+use futures::Stream;
+use schemars::JsonSchema;
+use serde::de::DeserializeOwned;
+use std::fmt::Debug;
+use std::marker::PhantomData;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::Poll;
+
+/// `TypedStream<T>` is an extractor for `application/x-ndjson` request bodies:
+/// sequences of JSON values, one per line.  Rather than reading the whole body
+/// up front, it's a [`Stream`] that parses each line into an instance of `T` as
+/// soon as the line has arrived, so that (for example) a bulk-ingest endpoint
+/// can process millions of records without buffering them all in memory.
+///
+/// Blank lines are skipped.  The first line that can't be parsed (or that
+/// fails schema validation, if the server's `schema_validation` is set) is
+/// reported as a 400 error identifying the line, after which the stream ends.
+/// The body as a whole is limited to the endpoint's request body limit (see
+/// `RequestContext::request_body_max_bytes()`).  Compressed bodies are not
+/// supported.
+pub struct TypedStream<T> {
+    body: crate::Body,
+    buffer: BytesMut,
+    /// how much of `buffer` is known not to contain a newline
+    scanned: usize,
+    /// number of lines read so far
+    line: usize,
+    nbytesread: usize,
+    max_bytes: usize,
+    validator: Option<Arc<SchemaValidator>>,
+    body_done: bool,
+    failed: bool,
+    phantom: PhantomData<fn() -> T>,
+}
+
+impl<T> Debug for TypedStream<T> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("TypedStream")
+            .field("line", &self.line)
+            .field("nbytesread", &self.nbytesread)
+            .finish_non_exhaustive()
+    }
+}
+
+impl<T: DeserializeOwned> TypedStream<T> {
+    /// Removes the next complete line from the buffer, if there is one.  Once
+    /// the body has ended, whatever remains is the last line.
+    fn next_line(&mut self) -> Option<BytesMut> {
+        match self.buffer[self.scanned..].iter().position(|b| *b == b'\n') {
+            Some(offset) => {
+                let line = self.buffer.split_to(self.scanned + offset + 1);
+                self.scanned = 0;
+                Some(line)
+            }
+            None if self.body_done && !self.buffer.is_empty() => {
+                Some(self.buffer.split())
+            }
+            None => {
+                self.scanned = self.buffer.len();
+                None
+            }
+        }
+    }
+
+    fn parse_line(&self, line: &[u8]) -> Result<T, HttpError> {
+        let parse_error = |e: serde_json::Error| {
+            HttpError::for_bad_request(
+                None,
+                format!(
+                    "unable to parse NDJSON body at line {}: {}",
+                    self.line, e
+                ),
+            )
+        };
+        match &self.validator {
+            None => serde_json::from_slice(line).map_err(parse_error),
+            Some(validator) => {
+                let value =
+                    serde_json::from_slice(line).map_err(parse_error)?;
+                let item = serde_json::from_value(value.clone())
+                    .map_err(parse_error)?;
+                validator.check(&format!("line {}", self.line), &value)?;
+                Ok(item)
+            }
+        }
+    }
+}
+
+impl<T: DeserializeOwned> Stream for TypedStream<T> {
+    type Item = Result<T, HttpError>;
+
+    fn poll_next(
+        self: Pin<&mut Self>,
+        cx: &mut std::task::Context<'_>,
+    ) -> Poll<Option<Self::Item>> {
+        let this = self.get_mut();
+        if this.failed {
+            return Poll::Ready(None);
+        }
+        loop {
+            while let Some(line) = this.next_line() {
+                this.line += 1;
+                if line.iter().all(u8::is_ascii_whitespace) {
+                    continue;
+                }
+                let result = this.parse_line(&line);
+                this.failed = result.is_err();
+                return Poll::Ready(Some(result));
+            }
+            if this.body_done {
+                return Poll::Ready(None);
+            }
+
+            match futures::ready!(Pin::new(&mut this.body).poll_next(cx)) {
+                None => this.body_done = true,
+                Some(Err(error)) => {
+                    this.failed = true;
+                    return Poll::Ready(Some(Err(HttpError::for_bad_request(
+                        None,
+                        format!("error processing request: {}", error),
+                    ))));
+                }
+                Some(Ok(chunk)) => {
+                    this.nbytesread += chunk.len();
+                    if this.nbytesread > this.max_bytes {
+                        this.failed = true;
+                        return Poll::Ready(Some(Err(
+                            HttpError::for_bad_request(
+                                None,
+                                format!(
+                                    "request body exceeded maximum size of {} \
+                                     bytes",
+                                    this.max_bytes
+                                ),
+                            ),
+                        )));
+                    }
+                    this.buffer.extend_from_slice(&chunk);
+                }
+            }
+        }
+    }
+}
+
+#[async_trait]
+impl<T> ExclusiveExtractor for TypedStream<T>
+where
+    T: JsonSchema + DeserializeOwned + Send + Sync + 'static,
+{
+    async fn from_request<Context: ServerContext>(
+        rqctx: &RequestContext<Context>,
+        request: hyper::Request<crate::Body>,
+    ) -> Result<TypedStream<T>, HttpError> {
+        let content_type = request
+            .headers()
+            .get(http::header::CONTENT_TYPE)
+            .map(|hv| {
+                hv.to_str().map_err(|e| {
+                    HttpError::for_bad_request(
+                        None,
+                        format!("invalid content type: {}", e),
+                    )
+                })
+            })
+            .unwrap_or(Ok(CONTENT_TYPE_NDJSON))?;
+        let end = content_type.find(';').unwrap_or_else(|| content_type.len());
+        let mime_type = content_type[..end].trim_end().to_lowercase();
+        if mime_type != CONTENT_TYPE_NDJSON {
+            return Err(HttpError::for_bad_request(
+                None,
+                format!(
+                    "expected content type \"{}\", got \"{}\"",
+                    CONTENT_TYPE_NDJSON, mime_type
+                ),
+            ));
+        }
+
+        let max_bytes = rqctx.request_body_max_bytes();
+        check_declared_length(request.headers(), max_bytes)?;
+        let validator = if rqctx.server.config.schema_validation {
+            Some(rqctx.schema_validators.get::<T>()?)
+        } else {
+            None
+        };
+        Ok(TypedStream {
+            body: request.into_body(),
+            buffer: BytesMut::new(),
+            scanned: 0,
+            line: 0,
+            nbytesread: 0,
+            max_bytes,
+            validator,
+            body_done: false,
+            failed: false,
+            phantom: PhantomData,
+        })
+    }
+
+    fn metadata(
+        _content_type: ApiEndpointBodyContentType,
+    ) -> ExtractorMetadata {
+        // The schema describes each line of the body.
+        let body = ApiEndpointParameter::new_body(
+            ApiEndpointBodyContentType::Ndjson,
+            true,
+            ApiSchemaGenerator::Gen {
+                name: T::schema_name,
+                schema: make_subschema_for::<T>,
+            },
+            vec![],
+        );
+        ExtractorMetadata {
+            extension_mode: ExtensionMode::None,
+            parameters: vec![body],
+        }
+    }
+
+    fn schema_validators(validators: &mut SchemaValidators) {
+        validators.add::<T>();
+    }
+}
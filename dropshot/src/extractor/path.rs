@@ -3,10 +3,12 @@
 //! URL-related extractor(s)
 
 use super::metadata::get_metadata;
+use super::validate::SchemaValidators;
 use crate::api_description::ApiEndpointBodyContentType;
 use crate::api_description::ApiEndpointParameterLocation;
 use crate::error::HttpError;
 use crate::http_util::http_extract_path_params;
+use crate::router::VariableValue;
 use crate::server::ServerContext;
 use crate::ExtractorMetadata;
 use crate::RequestContext;
@@ -15,6 +17,8 @@ use async_trait::async_trait;
 use schemars::JsonSchema;
 use serde::de::DeserializeOwned;
 use std::fmt::Debug;
+use std::ops::Deref;
+use std::ops::DerefMut;
 
 /// `Path<PathType>` is an extractor used to deserialize an instance of
 /// `PathType` from an HTTP request's path parameters.  `PathType` is any
@@ -26,12 +30,33 @@ pub struct Path<PathType: JsonSchema + Send + Sync> {
 }
 
 impl<PathType: JsonSchema + Send + Sync> Path<PathType> {
-    // TODO drop this in favor of Deref?  + Display and Debug for convenience?
+    /// Returns the deserialized path parameters.  (They can also be used in
+    /// place through `Deref`.)
     pub fn into_inner(self) -> PathType {
         self.inner
     }
 }
 
+impl<PathType: JsonSchema + Send + Sync> Deref for Path<PathType> {
+    type Target = PathType;
+
+    fn deref(&self) -> &PathType {
+        &self.inner
+    }
+}
+
+impl<PathType: JsonSchema + Send + Sync> DerefMut for Path<PathType> {
+    fn deref_mut(&mut self) -> &mut PathType {
+        &mut self.inner
+    }
+}
+
+impl<PathType: JsonSchema + Send + Sync> AsRef<PathType> for Path<PathType> {
+    fn as_ref(&self) -> &PathType {
+        &self.inner
+    }
+}
+
 // The `SharedExtractor` implementation for Path<PathType> describes how to
 // construct an instance of `Path<QueryType>` from an HTTP request: namely, by
 // extracting parameters from the query string.
@@ -44,6 +69,24 @@ where
         rqctx: &RequestContext<Context>,
     ) -> Result<Path<PathType>, HttpError> {
         let params: PathType = http_extract_path_params(&rqctx.path_variables)?;
+        if rqctx.server.config.schema_validation {
+            let validator = rqctx.schema_validators.get::<PathType>()?;
+            let value = validator.params_to_value(
+                rqctx.path_variables.iter().map(|(name, value)| {
+                    let values = match value {
+                        VariableValue::String(s) => vec![s.as_str()],
+                        VariableValue::Components(c, _)
+                            if validator.expects_array(name) =>
+                        {
+                            c.iter().map(String::as_str).collect()
+                        }
+                        VariableValue::Components(_, raw) => vec![raw.as_str()],
+                    };
+                    (name.as_str(), values)
+                }),
+            );
+            validator.check("path parameters", &value)?;
+        }
         Ok(Path { inner: params })
     }
 
@@ -52,4 +95,8 @@ where
     ) -> ExtractorMetadata {
         get_metadata::<PathType>(&ApiEndpointParameterLocation::Path)
     }
+
+    fn schema_validators(validators: &mut SchemaValidators) {
+        validators.add::<PathType>();
+    }
 }
@@ -0,0 +1,557 @@
+// Copyright 2023 Oxide Computer Company
+
+//! Conditional request (precondition) extractor
+
+use crate::api_description::ApiEndpointBodyContentType;
+use crate::api_description::ApiEndpointParameter;
+use crate::api_description::ApiEndpointParameterLocation;
+use crate::api_description::ApiSchemaGenerator;
+use crate::api_description::ExtensionMode;
+use crate::conditional::parse_http_date;
+use crate::conditional::precondition_failed;
+use crate::conditional::EntityTag;
+use crate::conditional::HttpResponseConditional;
+use crate::conditional::Precondition;
+use crate::error::HttpError;
+use crate::handler::HttpResponse;
+use crate::server::ServerContext;
+use crate::ExtractorMetadata;
+use crate::RequestContext;
+use crate::SharedExtractor;
+use async_trait::async_trait;
+use chrono::DateTime;
+use chrono::Utc;
+use http::header;
+use http::HeaderMap;
+use http::HeaderName;
+use http::Method;
+use http::StatusCode;
+
+/// `Preconditions` is an extractor for the headers that make a request
+/// conditional: `If-Match`, `If-None-Match`, `If-Modified-Since`, and
+/// `If-Unmodified-Since`.
+///
+/// For writes, clients use `If-Match` and `If-Unmodified-Since` to make the
+/// write conditional on the resource not having changed since they last
+/// fetched it (optimistic concurrency control).  The handler looks up the
+/// current version of the resource and passes its entity tag and/or
+/// modification time to [`Preconditions::check()`], which fails with a 412
+/// ("Precondition Failed") error if the client's precondition doesn't hold.
+/// Endpoints that don't want to allow unconditional writes can use
+/// [`Preconditions::require()`] to reject requests that have neither header
+/// with a 428 ("Precondition Required") error.
+///
+/// For reads, clients use `If-None-Match` and `If-Modified-Since` to avoid
+/// fetching a resource they already have.  Handlers that return
+/// [`HttpResponseConditional`] can use [`Preconditions::respond()`] to evaluate
+/// all four headers and send either their response or a 304 ("Not Modified")
+/// or 412 response.  [`Preconditions::evaluate()`] does the same evaluation
+/// for handlers that want to act on the result themselves.
+///
+/// All four headers are documented as optional header parameters in the
+/// OpenAPI document.
+///
+/// ```
+/// use dropshot::endpoint;
+/// use dropshot::HttpError;
+/// use dropshot::HttpResponseUpdatedNoContent;
+/// use dropshot::Preconditions;
+/// use dropshot::RequestContext;
+///
+/// #[endpoint {
+///     method = PUT,
+///     path = "/widget",
+/// }]
+/// async fn widget_put(
+///     _rqctx: RequestContext<()>,
+///     preconditions: Preconditions,
+/// ) -> Result<HttpResponseUpdatedNoContent, HttpError> {
+///     let current_etag = "v3"; // e.g., from the database
+///     preconditions.require()?;
+///     preconditions.check(Some(current_etag), None)?;
+///     // ... perform the update ...
+///     Ok(HttpResponseUpdatedNoContent())
+/// }
+/// ```
+#[derive(Debug)]
+pub struct Preconditions {
+    if_match: Option<EntityTags>,
+    if_none_match: Option<EntityTags>,
+    if_modified_since: Option<DateTime<Utc>>,
+    if_unmodified_since: Option<DateTime<Utc>>,
+    /// whether the request is a `GET` or `HEAD`, for which a 304 ("Not
+    /// Modified") response may be sent
+    is_read: bool,
+}
+
+/// Parsed value of an `If-Match` or `If-None-Match` header
+#[derive(Debug, Eq, PartialEq)]
+enum EntityTags {
+    /// `*`: matches any current representation
+    Any,
+    /// list of entity tags
+    Tags(Vec<EntityTag>),
+}
+
+impl EntityTags {
+    /// Returns whether any of the tags match `etag`, the current entity tag of
+    /// the resource (if it exists), using the strong or weak comparison
+    /// function.
+    fn matches(&self, etag: Option<&EntityTag>, strong: bool) -> bool {
+        match (self, etag) {
+            (_, None) => false,
+            (EntityTags::Any, Some(_)) => true,
+            (EntityTags::Tags(tags), Some(etag)) => tags.iter().any(|tag| {
+                if strong {
+                    tag.strong_eq(etag)
+                } else {
+                    tag.weak_eq(etag)
+                }
+            }),
+        }
+    }
+}
+
+impl Preconditions {
+    /// Returns true if the request has an `If-Match` or `If-Unmodified-Since`
+    /// header.
+    pub fn is_conditional(&self) -> bool {
+        self.if_match.is_some() || self.if_unmodified_since.is_some()
+    }
+
+    /// Returns a 428 ("Precondition Required") error if the request has
+    /// neither an `If-Match` nor an `If-Unmodified-Since` header.
+    pub fn require(&self) -> Result<(), HttpError> {
+        if self.is_conditional() {
+            Ok(())
+        } else {
+            Err(HttpError::for_client_error(
+                Some(String::from("PreconditionRequired")),
+                StatusCode::PRECONDITION_REQUIRED,
+                String::from(
+                    "this request requires an If-Match or \
+                     If-Unmodified-Since header",
+                ),
+            ))
+        }
+    }
+
+    /// Evaluates the request's `If-Match` and `If-Unmodified-Since`
+    /// preconditions against the current version of the resource: its entity
+    /// tag (without the surrounding quotes), and the
+    /// time it was last modified.  Either may be `None` if the resource does
+    /// not exist or the handler doesn't track it.  Returns a 412
+    /// ("Precondition Failed") error if the preconditions are not met.
+    ///
+    /// As specified by RFC 9110, `If-Unmodified-Since` is ignored if `If-Match`
+    /// is present, and `If-Match` uses the strong comparison function, so weak
+    /// entity tags never match.
+    pub fn check(
+        &self,
+        etag: Option<&str>,
+        last_modified: Option<DateTime<Utc>>,
+    ) -> Result<(), HttpError> {
+        let etag = etag.map(|tag| EntityTag::new_unchecked(tag, false));
+        if self.check_writes(etag.as_ref(), last_modified) {
+            Ok(())
+        } else {
+            Err(precondition_failed())
+        }
+    }
+
+    /// Evaluates all of the request's preconditions against the current
+    /// version of the resource, in the order specified by RFC 9110: first
+    /// `If-Match` (or else `If-Unmodified-Since`), then `If-None-Match` (or
+    /// else, for `GET` and `HEAD` requests, `If-Modified-Since`).  `etag` and
+    /// `last_modified` may be `None` if the resource does not exist or the
+    /// handler doesn't track them.
+    ///
+    /// If `If-None-Match` matches, the result is
+    /// [`Precondition::NotModified`] for `GET` and `HEAD` requests and
+    /// [`Precondition::Failed`] for others (so `If-None-Match: *` can be used
+    /// to create a resource only if it doesn't already exist).
+    pub fn evaluate(
+        &self,
+        etag: Option<&EntityTag>,
+        last_modified: Option<DateTime<Utc>>,
+    ) -> Precondition {
+        if !self.check_writes(etag, last_modified) {
+            return Precondition::Failed;
+        }
+
+        let not_modified = match (&self.if_none_match, self.if_modified_since) {
+            (Some(tags), _) => tags.matches(etag, false),
+            (None, Some(since)) if self.is_read => last_modified
+                .map_or(false, |m| m.timestamp() <= since.timestamp()),
+            _ => false,
+        };
+
+        match (not_modified, self.is_read) {
+            (false, _) => Precondition::Proceed,
+            (true, true) => Precondition::NotModified,
+            (true, false) => Precondition::Failed,
+        }
+    }
+
+    /// Evaluates the request's preconditions with [`Preconditions::evaluate()`]
+    /// and returns the corresponding [`HttpResponseConditional`].  `response`
+    /// is only invoked if the request should be handled normally.  The
+    /// response carries the resource's `ETag` and `Last-Modified` headers
+    /// (whichever are known) unless the preconditions failed.
+    pub fn respond<T, F>(
+        &self,
+        etag: Option<EntityTag>,
+        last_modified: Option<DateTime<Utc>>,
+        response: F,
+    ) -> HttpResponseConditional<T>
+    where
+        T: HttpResponse + Send + Sync + 'static,
+        F: FnOnce() -> T,
+    {
+        let precondition = self.evaluate(etag.as_ref(), last_modified);
+        HttpResponseConditional::new(
+            precondition,
+            etag,
+            last_modified,
+            response,
+        )
+    }
+
+    /// Evaluates the `If-Match` and `If-Unmodified-Since` headers.  As
+    /// specified by RFC 9110, `If-Unmodified-Since` is ignored if `If-Match`
+    /// is present, and `If-Match` uses the strong comparison function.
+    fn check_writes(
+        &self,
+        etag: Option<&EntityTag>,
+        last_modified: Option<DateTime<Utc>>,
+    ) -> bool {
+        match (&self.if_match, self.if_unmodified_since) {
+            (Some(tags), _) => tags.matches(etag, true),
+            (None, Some(since)) => {
+                // HTTP dates only have one-second precision.
+                last_modified
+                    .map_or(true, |m| m.timestamp() <= since.timestamp())
+            }
+            (None, None) => true,
+        }
+    }
+}
+
+fn parse_entity_tags(
+    headers: &HeaderMap,
+    name: HeaderName,
+) -> Result<Option<EntityTags>, String> {
+    let mut values = headers.get_all(&name).iter().peekable();
+    if values.peek().is_none() {
+        return Ok(None);
+    }
+
+    let mut tags = Vec::new();
+    for value in values {
+        let value = value
+            .to_str()
+            .map_err(|_| format!("invalid {} header", canonical_name(&name)))?;
+        for item in split_list(value) {
+            if item == "*" {
+                return Ok(Some(EntityTags::Any));
+            }
+            let tag = EntityTag::parse(item).ok_or_else(|| {
+                format!(
+                    "invalid entity tag in {} header: {}",
+                    canonical_name(&name),
+                    item
+                )
+            })?;
+            tags.push(tag);
+        }
+    }
+
+    Ok(Some(EntityTags::Tags(tags)))
+}
+
+/// Splits a comma-separated header value into its (trimmed, non-empty) items.
+/// Commas inside quoted strings, which may appear in entity tags, don't
+/// separate items.
+fn split_list(value: &str) -> impl Iterator<Item = &str> {
+    let mut quoted = false;
+    value
+        .split(move |c| {
+            if c == '"' {
+                quoted = !quoted;
+            }
+            c == ',' && !quoted
+        })
+        .map(str::trim)
+        .filter(|item| !item.is_empty())
+}
+
+/// Returns the conventional capitalization of the conditional request headers,
+/// for error messages.
+fn canonical_name(name: &HeaderName) -> &'static str {
+    if *name == header::IF_MATCH {
+        "If-Match"
+    } else if *name == header::IF_NONE_MATCH {
+        "If-None-Match"
+    } else if *name == header::IF_MODIFIED_SINCE {
+        "If-Modified-Since"
+    } else {
+        "If-Unmodified-Since"
+    }
+}
+
+fn parse_date_header(
+    headers: &HeaderMap,
+    name: HeaderName,
+) -> Result<Option<DateTime<Utc>>, String> {
+    headers
+        .get(&name)
+        .map(|value| {
+            value.to_str().ok().and_then(parse_http_date).ok_or_else(|| {
+                format!("invalid {} header", canonical_name(&name))
+            })
+        })
+        .transpose()
+}
+
+#[async_trait]
+impl SharedExtractor for Preconditions {
+    async fn from_request<Context: ServerContext>(
+        rqctx: &RequestContext<Context>,
+    ) -> Result<Preconditions, HttpError> {
+        let headers = rqctx.request.headers();
+        let bad_request = |message| HttpError::for_bad_request(None, message);
+        let method = rqctx.request.method();
+        Ok(Preconditions {
+            if_match: parse_entity_tags(headers, header::IF_MATCH)
+                .map_err(bad_request)?,
+            if_none_match: parse_entity_tags(headers, header::IF_NONE_MATCH)
+                .map_err(bad_request)?,
+            // As specified by RFC 9110, an invalid If-Modified-Since header is
+            // ignored rather than rejected.
+            if_modified_since: parse_date_header(
+                headers,
+                header::IF_MODIFIED_SINCE,
+            )
+            .unwrap_or(None),
+            if_unmodified_since: parse_date_header(
+                headers,
+                header::IF_UNMODIFIED_SINCE,
+            )
+            .map_err(bad_request)?,
+            is_read: *method == Method::GET || *method == Method::HEAD,
+        })
+    }
+
+    fn metadata(
+        _body_content_type: ApiEndpointBodyContentType,
+    ) -> ExtractorMetadata {
+        let header_param = |name: &str, description: &str| {
+            ApiEndpointParameter::new_named(
+                &ApiEndpointParameterLocation::Header,
+                name.to_string(),
+                Some(description.to_string()),
+                false,
+                ApiSchemaGenerator::Static {
+                    schema: Box::new(
+                        schemars::gen::SchemaGenerator::default()
+                            .subschema_for::<String>(),
+                    ),
+                    dependencies: indexmap::IndexMap::new(),
+                },
+                vec![],
+            )
+        };
+        ExtractorMetadata {
+            extension_mode: ExtensionMode::None,
+            parameters: vec![
+                header_param(
+                    "If-Match",
+                    "Only perform the request if the resource's current \
+                     entity tag matches one of these",
+                ),
+                header_param(
+                    "If-None-Match",
+                    "Only perform the request if the resource's current \
+                     entity tag matches none of these",
+                ),
+                header_param(
+                    "If-Modified-Since",
+                    "Only send the resource if it has been modified since \
+                     this time",
+                ),
+                header_param(
+                    "If-Unmodified-Since",
+                    "Only perform the request if the resource has not been \
+                     modified since this time",
+                ),
+            ],
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::parse_date_header;
+    use super::parse_entity_tags;
+    use super::EntityTags;
+    use super::Preconditions;
+    use crate::conditional::EntityTag;
+    use crate::conditional::Precondition;
+    use chrono::TimeZone;
+    use chrono::Utc;
+    use http::header;
+    use http::HeaderMap;
+    use http::StatusCode;
+
+    fn if_match(value: &str) -> Result<Option<EntityTags>, String> {
+        let mut headers = HeaderMap::new();
+        headers.insert(header::IF_MATCH, value.parse().unwrap());
+        parse_entity_tags(&headers, header::IF_MATCH)
+    }
+
+    fn preconditions(
+        if_match: Option<EntityTags>,
+        if_unmodified_since: Option<chrono::DateTime<Utc>>,
+    ) -> Preconditions {
+        Preconditions {
+            if_match,
+            if_none_match: None,
+            if_modified_since: None,
+            if_unmodified_since,
+            is_read: false,
+        }
+    }
+
+    #[test]
+    fn test_parse_if_match() {
+        assert_eq!(
+            parse_entity_tags(&HeaderMap::new(), header::IF_MATCH).unwrap(),
+            None
+        );
+        assert_eq!(if_match("*").unwrap(), Some(EntityTags::Any));
+        assert_eq!(
+            if_match(r#""abc", W/"def""#).unwrap(),
+            Some(EntityTags::Tags(vec![
+                EntityTag::strong("abc"),
+                EntityTag::weak("def"),
+            ]))
+        );
+        // Entity tags may contain commas.
+        assert_eq!(
+            if_match(r#""a,b",,"c""#).unwrap(),
+            Some(EntityTags::Tags(vec![
+                EntityTag::strong("a,b"),
+                EntityTag::strong("c"),
+            ]))
+        );
+        assert_eq!(
+            if_match("abc").unwrap_err(),
+            "invalid entity tag in If-Match header: abc"
+        );
+
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            header::IF_UNMODIFIED_SINCE,
+            "Wed, 21 Oct 2015 07:28:00 GMT".parse().unwrap(),
+        );
+        assert_eq!(
+            parse_date_header(&headers, header::IF_UNMODIFIED_SINCE).unwrap(),
+            Some(Utc.with_ymd_and_hms(2015, 10, 21, 7, 28, 0).unwrap())
+        );
+        headers
+            .insert(header::IF_UNMODIFIED_SINCE, "yesterday".parse().unwrap());
+        assert_eq!(
+            parse_date_header(&headers, header::IF_UNMODIFIED_SINCE)
+                .unwrap_err(),
+            "invalid If-Unmodified-Since header"
+        );
+    }
+
+    #[test]
+    fn test_check_preconditions() {
+        let none = preconditions(None, None);
+        assert!(none.check(Some("v1"), None).is_ok());
+        assert_eq!(
+            none.require().unwrap_err().status_code,
+            StatusCode::PRECONDITION_REQUIRED
+        );
+
+        let any = preconditions(Some(EntityTags::Any), None);
+        assert!(any.require().is_ok());
+        assert!(any.check(Some("v1"), None).is_ok());
+        assert_eq!(
+            any.check(None, None).unwrap_err().status_code,
+            StatusCode::PRECONDITION_FAILED
+        );
+
+        let tags = preconditions(
+            Some(EntityTags::Tags(vec![
+                EntityTag::strong("v1"),
+                EntityTag::weak("v2"),
+            ])),
+            None,
+        );
+        assert!(tags.check(Some("v1"), None).is_ok());
+        assert!(tags.check(Some("v2"), None).is_err());
+        assert!(tags.check(Some("v3"), None).is_err());
+
+        let since = Utc.with_ymd_and_hms(2015, 10, 21, 7, 28, 0).unwrap();
+        let unmodified = preconditions(None, Some(since));
+        assert!(unmodified.check(None, Some(since)).is_ok());
+        assert!(unmodified
+            .check(None, Some(since + chrono::Duration::seconds(1)))
+            .is_err());
+        assert!(unmodified.check(None, None).is_ok());
+
+        // If-Match takes precedence over If-Unmodified-Since.
+        let both = preconditions(Some(EntityTags::Any), Some(since));
+        assert!(both
+            .check(Some("v1"), Some(since + chrono::Duration::seconds(1)))
+            .is_ok());
+    }
+
+    #[test]
+    fn test_evaluate_preconditions() {
+        let v1 = EntityTag::strong("v1");
+        let v2 = EntityTag::strong("v2");
+        let since = Utc.with_ymd_and_hms(2015, 10, 21, 7, 28, 0).unwrap();
+        let later = since + chrono::Duration::seconds(1);
+
+        // If-None-Match uses the weak comparison function.
+        let mut read = preconditions(None, None);
+        read.is_read = true;
+        read.if_none_match =
+            Some(EntityTags::Tags(vec![EntityTag::weak("v1")]));
+        assert_eq!(read.evaluate(Some(&v1), None), Precondition::NotModified);
+        assert_eq!(read.evaluate(Some(&v2), None), Precondition::Proceed);
+        assert_eq!(read.evaluate(None, None), Precondition::Proceed);
+
+        // If-Modified-Since is ignored if If-None-Match is present.
+        read.if_modified_since = Some(since);
+        assert_eq!(
+            read.evaluate(Some(&v2), Some(since)),
+            Precondition::Proceed
+        );
+        read.if_none_match = None;
+        assert_eq!(
+            read.evaluate(Some(&v2), Some(since)),
+            Precondition::NotModified
+        );
+        assert_eq!(
+            read.evaluate(Some(&v2), Some(later)),
+            Precondition::Proceed
+        );
+
+        // If-Match is evaluated first.
+        read.if_match = Some(EntityTags::Tags(vec![v2.clone()]));
+        assert_eq!(read.evaluate(Some(&v1), Some(since)), Precondition::Failed);
+
+        // For writes, a matching If-None-Match fails, and If-Modified-Since is
+        // ignored.
+        let mut write = preconditions(None, None);
+        write.if_none_match = Some(EntityTags::Any);
+        write.if_modified_since = Some(since);
+        assert_eq!(write.evaluate(Some(&v1), None), Precondition::Failed);
+        assert_eq!(write.evaluate(None, Some(since)), Precondition::Proceed);
+    }
+}
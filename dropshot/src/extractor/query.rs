@@ -3,18 +3,31 @@
 //! Querystring-related extractor(s)
 
 use super::metadata::get_metadata;
+use super::validate::SchemaValidator;
+use super::validate::SchemaValidators;
 use crate::api_description::ApiEndpointBodyContentType;
 use crate::api_description::ApiEndpointParameterLocation;
+use crate::api_description::ApiEndpointParameterMetadata;
+use crate::api_description::ApiSchemaGenerator;
 use crate::error::HttpError;
+use crate::server::ServerConfig;
 use crate::server::ServerContext;
+use crate::type_util::type_object_properties;
 use crate::ExtractorMetadata;
 use crate::RequestContext;
 use crate::RequestInfo;
 use crate::SharedExtractor;
 use async_trait::async_trait;
+use schemars::schema::InstanceType;
+use schemars::schema::Schema;
+use schemars::schema::SchemaObject;
+use schemars::schema::SingleOrVec;
 use schemars::JsonSchema;
 use serde::de::DeserializeOwned;
+use std::collections::BTreeMap;
 use std::fmt::Debug;
+use std::ops::Deref;
+use std::ops::DerefMut;
 
 /// `Query<QueryType>` is an extractor used to deserialize an instance of
 /// `QueryType` from an HTTP request's query string.  `QueryType` is any
@@ -26,29 +39,244 @@ pub struct Query<QueryType: DeserializeOwned + JsonSchema + Send + Sync> {
 }
 
 impl<QueryType: DeserializeOwned + JsonSchema + Send + Sync> Query<QueryType> {
-    // TODO drop this in favor of Deref?  + Display and Debug for convenience?
+    /// Returns the deserialized query parameters.  (They can also be used in
+    /// place through `Deref`.)
     pub fn into_inner(self) -> QueryType {
         self.inner
     }
 }
 
+impl<QueryType: DeserializeOwned + JsonSchema + Send + Sync> Deref
+    for Query<QueryType>
+{
+    type Target = QueryType;
+
+    fn deref(&self) -> &QueryType {
+        &self.inner
+    }
+}
+
+impl<QueryType: DeserializeOwned + JsonSchema + Send + Sync> DerefMut
+    for Query<QueryType>
+{
+    fn deref_mut(&mut self) -> &mut QueryType {
+        &mut self.inner
+    }
+}
+
+impl<QueryType: DeserializeOwned + JsonSchema + Send + Sync> AsRef<QueryType>
+    for Query<QueryType>
+{
+    fn as_ref(&self) -> &QueryType {
+        &self.inner
+    }
+}
+
 /// Given an HTTP request, pull out the query string and attempt to deserialize
-/// it as an instance of `QueryType`.
+/// it as an instance of `QueryType`.  Depending on the server's configuration,
+/// the query parameters may also be checked for parameters that `QueryType`
+/// doesn't declare and validated against `QueryType`'s schema.
 fn http_request_load_query<QueryType>(
     request: &RequestInfo,
+    config: &ServerConfig,
+    validators: &SchemaValidators,
 ) -> Result<Query<QueryType>, HttpError>
 where
-    QueryType: DeserializeOwned + JsonSchema + Send + Sync,
+    QueryType: DeserializeOwned + JsonSchema + Send + Sync + 'static,
 {
     let raw_query_string = request.uri().query().unwrap_or("");
+    if config.strict_query_params {
+        check_unknown_params::<QueryType>(raw_query_string)?;
+    }
     // TODO-correctness: are query strings defined to be urlencoded in this way?
-    match serde_urlencoded::from_str(raw_query_string) {
-        Ok(q) => Ok(Query { inner: q }),
-        Err(e) => Err(HttpError::for_bad_request(
+    let structured = StructuredParams::of::<QueryType>();
+    if structured.is_empty() {
+        let inner =
+            serde_urlencoded::from_str(raw_query_string).map_err(|e| {
+                HttpError::for_bad_request(
+                    None,
+                    format!("unable to parse query string: {}", e),
+                )
+            })?;
+        if config.schema_validation {
+            validate_urlencoded(
+                &validators.get::<QueryType>()?,
+                "query parameters",
+                raw_query_string,
+            )?;
+        }
+        return Ok(Query { inner });
+    }
+
+    let parse_error = |e: String| {
+        HttpError::for_bad_request(
             None,
             format!("unable to parse query string: {}", e),
-        )),
+        )
+    };
+    let query_string =
+        structured.number_sequences(raw_query_string).map_err(parse_error)?;
+    let config_qs = serde_qs::Config::new(5, false);
+    let inner = config_qs
+        .deserialize_str(&query_string)
+        .map_err(|e| parse_error(e.to_string()))?;
+    if config.schema_validation {
+        let form = config_qs
+            .deserialize_str(&query_string)
+            .map_err(|e| parse_error(e.to_string()))?;
+        let validator = validators.get::<QueryType>()?;
+        validator.check("query parameters", &validator.form_to_value(form))?;
     }
+    Ok(Query { inner })
+}
+
+/// The parameters of a `Query` type that `serde_urlencoded` can't deserialize.
+/// When there are any, the query string is deserialized with `serde_qs`
+/// instead.
+struct StructuredParams {
+    /// sequences, whose values are given by repeating the parameter (e.g.,
+    /// `?id=1&id=2`)
+    sequences: Vec<String>,
+    /// objects, whose properties are given in the `deepObject` style (e.g.,
+    /// `?filter[name]=foo&filter[state]=running`)
+    objects: Vec<String>,
+}
+
+impl StructuredParams {
+    fn of<QueryType: JsonSchema>() -> StructuredParams {
+        let mut structured =
+            StructuredParams { sequences: Vec::new(), objects: Vec::new() };
+        let parameters =
+            get_metadata::<QueryType>(&ApiEndpointParameterLocation::Query)
+                .parameters;
+        for parameter in parameters {
+            let (name, schema, dependencies) =
+                match (parameter.metadata, parameter.schema) {
+                    (
+                        ApiEndpointParameterMetadata::Query(name),
+                        ApiSchemaGenerator::Static { schema, dependencies },
+                    ) => (name, schema, dependencies),
+                    _ => continue,
+                };
+            if type_object_properties(&schema, &dependencies).is_some() {
+                structured.objects.push(name);
+                continue;
+            }
+            match *schema {
+                Schema::Object(SchemaObject {
+                    instance_type: Some(SingleOrVec::Single(instance_type)),
+                    ..
+                }) if *instance_type == InstanceType::Array => {
+                    structured.sequences.push(name)
+                }
+                _ => (),
+            }
+        }
+        structured
+    }
+
+    fn is_empty(&self) -> bool {
+        self.sequences.is_empty() && self.objects.is_empty()
+    }
+
+    /// Numbers the values of sequence parameters (e.g., `id=1&id=2` becomes
+    /// `id[0]=1&id[1]=2`) so that `serde_qs` can deserialize them.
+    fn number_sequences(
+        &self,
+        raw_query_string: &str,
+    ) -> Result<String, String> {
+        let pairs: Vec<(String, String)> =
+            serde_urlencoded::from_str(raw_query_string)
+                .map_err(|e| e.to_string())?;
+        let mut counts: BTreeMap<String, usize> = BTreeMap::new();
+        let pairs = pairs
+            .into_iter()
+            .map(|(name, value)| {
+                if !self.sequences.contains(&name) {
+                    return (name, value);
+                }
+                let count = counts.entry(name.clone()).or_insert(0);
+                let numbered = format!("{}[{}]", name, count);
+                *count += 1;
+                (numbered, value)
+            })
+            .collect::<Vec<_>>();
+        serde_urlencoded::to_string(&pairs).map_err(|e| e.to_string())
+    }
+}
+
+/// Fails if the query string includes parameters that `QueryType` doesn't
+/// declare, naming each of them.
+fn check_unknown_params<QueryType: JsonSchema>(
+    raw_query_string: &str,
+) -> Result<(), HttpError> {
+    let pairs: Vec<(String, String)> =
+        serde_urlencoded::from_str(raw_query_string).map_err(|e| {
+            HttpError::for_bad_request(
+                None,
+                format!("unable to parse query string: {}", e),
+            )
+        })?;
+    let declared =
+        get_metadata::<QueryType>(&ApiEndpointParameterLocation::Query)
+            .parameters
+            .into_iter()
+            .filter_map(|parameter| match parameter.metadata {
+                ApiEndpointParameterMetadata::Query(name) => Some(name),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+
+    let mut unknown: Vec<String> = Vec::new();
+    for (name, _) in pairs {
+        // The properties of `deepObject` parameters are named after them
+        // (e.g., `filter[name]`).
+        let base_name = match name.find('[') {
+            Some(i) if i > 0 => &name[..i],
+            _ => name.as_str(),
+        };
+        if !declared.iter().any(|d| d == base_name) && !unknown.contains(&name)
+        {
+            unknown.push(name);
+        }
+    }
+    if unknown.is_empty() {
+        return Ok(());
+    }
+
+    let names = unknown
+        .iter()
+        .map(|name| format!("\"{}\"", name))
+        .collect::<Vec<_>>()
+        .join(", ");
+    Err(HttpError::for_bad_request(
+        Some(String::from("UnknownQueryParameters")),
+        format!("unknown query parameters: {}", names),
+    ))
+}
+
+/// Validates urlencoded data (which has already been successfully deserialized)
+/// with `validator`.
+pub(super) fn validate_urlencoded(
+    validator: &SchemaValidator,
+    what: &str,
+    raw: &str,
+) -> Result<(), HttpError> {
+    let pairs: Vec<(String, String)> = serde_urlencoded::from_str(raw)
+        .map_err(|e| {
+            HttpError::for_bad_request(
+                None,
+                format!("unable to parse {}: {}", what, e),
+            )
+        })?;
+    let mut params: Vec<(&str, Vec<&str>)> = Vec::new();
+    for (name, value) in &pairs {
+        match params.iter_mut().find(|(n, _)| n == name) {
+            Some((_, values)) => values.push(value),
+            None => params.push((name, vec![value])),
+        }
+    }
+    validator.check(what, &validator.params_to_value(params))
 }
 
 // The `SharedExtractor` implementation for Query<QueryType> describes how to
@@ -65,7 +293,11 @@ where
     async fn from_request<Context: ServerContext>(
         rqctx: &RequestContext<Context>,
     ) -> Result<Query<QueryType>, HttpError> {
-        http_request_load_query(&rqctx.request)
+        http_request_load_query(
+            &rqctx.request,
+            &rqctx.server.config,
+            &rqctx.schema_validators,
+        )
     }
 
     fn metadata(
@@ -73,4 +305,8 @@ where
     ) -> ExtractorMetadata {
         get_metadata::<QueryType>(&ApiEndpointParameterLocation::Query)
     }
+
+    fn schema_validators(validators: &mut SchemaValidators) {
+        validators.add::<QueryType>();
+    }
 }
@@ -0,0 +1,207 @@
+// Copyright 2023 Oxide Computer Company
+
+//! Content sniffing: identifying the type of a body from its leading bytes
+
+use crate::error::HttpError;
+use http::StatusCode;
+
+/// Signatures ("magic bytes") of the formats we can identify.  Each entry is
+/// the media type, the offset of the signature, and the signature itself.  A
+/// format may have several entries.
+const SIGNATURES: &[(&str, usize, &[u8])] = &[
+    ("image/png", 0, b"\x89PNG\r\n\x1a\n"),
+    ("image/jpeg", 0, b"\xff\xd8\xff"),
+    ("image/gif", 0, b"GIF87a"),
+    ("image/gif", 0, b"GIF89a"),
+    ("image/webp", 8, b"WEBP"),
+    ("image/bmp", 0, b"BM"),
+    ("image/tiff", 0, b"II*\x00"),
+    ("image/tiff", 0, b"MM\x00*"),
+    ("image/x-icon", 0, b"\x00\x00\x01\x00"),
+    ("application/pdf", 0, b"%PDF-"),
+    ("application/zip", 0, b"PK\x03\x04"),
+    ("application/gzip", 0, b"\x1f\x8b"),
+    ("video/mp4", 4, b"ftyp"),
+];
+
+/// Prefixes that identify a body as HTML, compared case-insensitively after
+/// any leading whitespace (per the WHATWG MIME Sniffing standard).
+const HTML_PREFIXES: &[&[u8]] = &[
+    b"<!doctype html",
+    b"<html",
+    b"<head",
+    b"<body",
+    b"<script",
+    b"<iframe",
+    b"<style",
+    b"<title",
+    b"<div",
+    b"<a ",
+    b"<img",
+    b"<table",
+    b"<!--",
+];
+
+/// Returns the media type of `content`, if its leading bytes identify it.
+pub(crate) fn sniff(content: &[u8]) -> Option<&'static str> {
+    let signature_match =
+        SIGNATURES.iter().find(|(media_type, offset, sig)| {
+            content.get(*offset..offset + sig.len()) == Some(*sig)
+            // WebP is a RIFF container.
+            && (*media_type != "image/webp" || content.starts_with(b"RIFF"))
+        });
+    if let Some((media_type, _, _)) = signature_match {
+        return Some(media_type);
+    }
+
+    let text = content
+        .strip_prefix(b"\xef\xbb\xbf")
+        .unwrap_or(content)
+        .iter()
+        .skip_while(|b| b.is_ascii_whitespace())
+        .take(16)
+        .map(u8::to_ascii_lowercase)
+        .collect::<Vec<_>>();
+    if HTML_PREFIXES.iter().any(|prefix| text.starts_with(prefix)) {
+        return Some("text/html");
+    }
+
+    None
+}
+
+/// Returns whether `media_type` is one that we can identify by sniffing.
+fn is_sniffable(media_type: &str) -> bool {
+    media_type == "text/html"
+        || SIGNATURES.iter().any(|(sniffable, _, _)| *sniffable == media_type)
+}
+
+/// Returns whether `media_type` matches `pattern`, which is either a media type
+/// or a wildcard like `image/*`.
+fn media_type_matches(pattern: &str, media_type: &str) -> bool {
+    match pattern.strip_suffix("/*") {
+        Some(top_level) => media_type
+            .split_once('/')
+            .map_or(false, |(t, _)| t.eq_ignore_ascii_case(top_level)),
+        None => pattern.eq_ignore_ascii_case(media_type),
+    }
+}
+
+fn unsupported_media_type(message: String) -> HttpError {
+    HttpError::for_client_error(
+        Some(String::from("UnsupportedMediaType")),
+        StatusCode::UNSUPPORTED_MEDIA_TYPE,
+        message,
+    )
+}
+
+/// Verifies that a body with declared content type `content_type` (the value
+/// of the `Content-Type` header, if any) is one of the `allowed` media types
+/// and that its contents are consistent with that type.  Returns the declared
+/// media type (lowercased, without parameters).
+pub(crate) fn verify_content_type(
+    content_type: Option<&str>,
+    content: &[u8],
+    allowed: &[&str],
+) -> Result<String, HttpError> {
+    let declared = content_type
+        .map(|c| c.split(';').next().unwrap().trim().to_ascii_lowercase())
+        .filter(|c| !c.is_empty())
+        .ok_or_else(|| {
+            unsupported_media_type(String::from("missing content type"))
+        })?;
+    if !allowed.iter().any(|pattern| media_type_matches(pattern, &declared)) {
+        return Err(unsupported_media_type(format!(
+            "content type \"{}\" is not allowed",
+            declared
+        )));
+    }
+
+    let consistent = match sniff(content) {
+        Some(detected) if detected == declared => true,
+        // Octet streams can be anything except HTML, which browsers may
+        // render anyway.
+        Some(detected) => {
+            declared == "application/octet-stream" && detected != "text/html"
+        }
+        None => !is_sniffable(&declared),
+    };
+    if !consistent {
+        return Err(unsupported_media_type(format!(
+            "body does not match content type \"{}\"",
+            declared
+        )));
+    }
+
+    Ok(declared)
+}
+
+#[cfg(test)]
+mod test {
+    use super::sniff;
+    use super::verify_content_type;
+    use http::StatusCode;
+
+    const PNG: &[u8] = b"\x89PNG\r\n\x1a\n\x00\x00\x00\rIHDR";
+
+    #[test]
+    fn test_sniff() {
+        assert_eq!(sniff(PNG), Some("image/png"));
+        assert_eq!(sniff(b"\xff\xd8\xff\xe0\x00\x10JFIF"), Some("image/jpeg"));
+        assert_eq!(sniff(b"RIFF\x00\x00\x00\x00WEBPVP8 "), Some("image/webp"));
+        assert_eq!(sniff(b"RIFF\x00\x00\x00\x00WAVEfmt "), None);
+        assert_eq!(sniff(b"%PDF-1.7"), Some("application/pdf"));
+        assert_eq!(sniff(b"  \n<!DOCTYPE HTML><html>"), Some("text/html"));
+        assert_eq!(sniff(b"\xef\xbb\xbf<script>alert(1)"), Some("text/html"));
+        assert_eq!(sniff(b"{\"hello\": \"world\"}"), None);
+        assert_eq!(sniff(b""), None);
+    }
+
+    #[test]
+    fn test_verify_content_type() {
+        let images = &["image/*"];
+        assert_eq!(
+            verify_content_type(Some("image/PNG; q=1"), PNG, images).unwrap(),
+            "image/png"
+        );
+
+        // HTML masquerading as an image
+        let error = verify_content_type(
+            Some("image/png"),
+            b"<html><script>alert(1)</script></html>",
+            images,
+        )
+        .unwrap_err();
+        assert_eq!(error.status_code, StatusCode::UNSUPPORTED_MEDIA_TYPE);
+        assert_eq!(
+            error.external_message,
+            "body does not match content type \"image/png\""
+        );
+
+        // a PNG declared as a JPEG
+        assert!(verify_content_type(Some("image/jpeg"), PNG, images).is_err());
+
+        // not in the allowlist, or not declared at all
+        assert!(
+            verify_content_type(Some("text/html"), b"<html>", images).is_err()
+        );
+        assert!(verify_content_type(None, PNG, images).is_err());
+
+        // types we can't sniff are accepted unless the body looks like
+        // something else
+        let text = &["text/plain", "application/octet-stream"];
+        assert!(verify_content_type(Some("text/plain"), b"hello", text).is_ok());
+        assert!(verify_content_type(Some("text/plain"), PNG, text).is_err());
+        assert!(verify_content_type(
+            Some("application/octet-stream"),
+            PNG,
+            text
+        )
+        .is_ok());
+        assert!(verify_content_type(
+            Some("application/octet-stream"),
+            b"<html>",
+            text
+        )
+        .is_err());
+    }
+}
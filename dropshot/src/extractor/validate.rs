@@ -0,0 +1,1074 @@
+// Copyright 2023 Oxide Computer Company
+
+//! Validation of request parameters and bodies against their JSON schemas
+//!
+//! Serde enforces the shape of the types into which requests are deserialized,
+//! but not the other constraints that can be expressed in their schemas
+//! (formats, patterns, lengths, ranges, and so on).  When the server is
+//! configured with `schema_validation`, extractors use a [`SchemaValidator`] to
+//! enforce those constraints, too, after the request has been deserialized.
+//!
+//! Generating a schema and compiling its patterns is much more expensive than
+//! validating a value against it, so each endpoint builds the validators for
+//! its extractors' types once, when it's registered (see [`SchemaValidators`]).
+
+use crate::error::HttpError;
+use http::StatusCode;
+use regex::Regex;
+use schemars::gen::SchemaSettings;
+use schemars::schema::ArrayValidation;
+use schemars::schema::InstanceType;
+use schemars::schema::NumberValidation;
+use schemars::schema::ObjectValidation;
+use schemars::schema::RootSchema;
+use schemars::schema::Schema;
+use schemars::schema::SchemaObject;
+use schemars::schema::SingleOrVec;
+use schemars::schema::StringValidation;
+use schemars::schema::SubschemaValidation;
+use schemars::JsonSchema;
+use serde_json::Map;
+use serde_json::Value;
+use std::any::TypeId;
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::net::Ipv4Addr;
+use std::net::Ipv6Addr;
+use std::sync::Arc;
+
+/// A way in which a value fails to conform to its schema
+#[derive(Debug, Eq, PartialEq)]
+pub(crate) struct SchemaViolation {
+    /// JSON Pointer to the offending part of the value (empty for the value as
+    /// a whole)
+    pub path: String,
+    pub message: String,
+}
+
+fn violation<S: ToString>(path: &str, message: S) -> SchemaViolation {
+    SchemaViolation { path: path.to_string(), message: message.to_string() }
+}
+
+/// Appends `token` to the JSON Pointer `path`.
+fn pointer_join(path: &str, token: &str) -> String {
+    format!("{}/{}", path, token.replace('~', "~0").replace('/', "~1"))
+}
+
+/// The schema validators for the types that an endpoint's extractors
+/// deserialize, keyed by type
+///
+/// Extractors add their types with [`SchemaValidators::add()`] when the
+/// endpoint is registered, and look up the validators with
+/// [`SchemaValidators::get()`] when they handle a request.
+#[derive(Default)]
+pub struct SchemaValidators {
+    validators: HashMap<TypeId, Arc<SchemaValidator>>,
+    /// problems with the schemas (e.g., patterns that aren't valid regular
+    /// expressions), which prevent the endpoint from being registered
+    errors: Vec<String>,
+}
+
+impl std::fmt::Debug for SchemaValidators {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("SchemaValidators")
+            .field("count", &self.validators.len())
+            .field("errors", &self.errors)
+            .finish()
+    }
+}
+
+impl SchemaValidators {
+    /// Builds the validator for `T`, unless there's one already.
+    pub(crate) fn add<T: JsonSchema + 'static>(&mut self) {
+        if self.validators.contains_key(&TypeId::of::<T>()) {
+            return;
+        }
+        match SchemaValidator::new::<T>() {
+            Ok(validator) => {
+                self.validators.insert(TypeId::of::<T>(), Arc::new(validator));
+            }
+            Err(error) => self.errors.push(error),
+        }
+    }
+
+    /// Returns the validator for `T`.  If `T` wasn't added when the endpoint
+    /// was registered (as for an extractor used outside of an endpoint's
+    /// arguments), the validator is built now.
+    pub(crate) fn get<T: JsonSchema + 'static>(
+        &self,
+    ) -> Result<Arc<SchemaValidator>, HttpError> {
+        match self.validators.get(&TypeId::of::<T>()) {
+            Some(validator) => Ok(Arc::clone(validator)),
+            None => SchemaValidator::new::<T>()
+                .map(Arc::new)
+                .map_err(HttpError::for_internal_error),
+        }
+    }
+
+    /// Returns the problems found in the schemas that were added.
+    pub(crate) fn errors(&self) -> &[String] {
+        &self.errors
+    }
+}
+
+/// Validates values against the (JSON Schema draft 7) schema of a type
+pub(crate) struct SchemaValidator {
+    root: RootSchema,
+    /// the compiled `pattern`s and `patternProperties` in the schema
+    patterns: HashMap<String, Regex>,
+}
+
+impl SchemaValidator {
+    /// Generates the schema for `T` and compiles the patterns in it, failing
+    /// if any of them isn't a regular expression that we can evaluate.
+    pub fn new<T: JsonSchema>() -> Result<SchemaValidator, String> {
+        let root = SchemaSettings::draft07()
+            .into_generator()
+            .into_root_schema_for::<T>();
+        let mut patterns = HashMap::new();
+        let schemas = std::iter::once(&root.schema).chain(
+            root.definitions.values().filter_map(|schema| match schema {
+                Schema::Object(object) => Some(object),
+                Schema::Bool(_) => None,
+            }),
+        );
+        for schema in schemas {
+            compile_patterns(schema, &mut patterns).map_err(|message| {
+                format!("invalid schema for {}: {}", T::schema_name(), message)
+            })?;
+        }
+        Ok(SchemaValidator { root, patterns })
+    }
+
+    /// Validates `value`, which came from the part of the request described
+    /// by `what` (e.g., "body"), returning a 400 error listing every violation
+    /// if it doesn't conform to the schema.
+    pub fn check(&self, what: &str, value: &Value) -> Result<(), HttpError> {
+        let violations = self.violations(value);
+        if violations.is_empty() {
+            return Ok(());
+        }
+
+        let details = violations
+            .iter()
+            .map(|v| {
+                if v.path.is_empty() {
+                    v.message.clone()
+                } else {
+                    format!("{}: {}", v.path, v.message)
+                }
+            })
+            .collect::<Vec<_>>()
+            .join("; ");
+        Err(HttpError::for_client_error(
+            Some(String::from("ValidationFailed")),
+            StatusCode::BAD_REQUEST,
+            format!("invalid {}: {}", what, details),
+        ))
+    }
+
+    /// Returns the ways in which `value` fails to conform to the schema.
+    pub fn violations(&self, value: &Value) -> Vec<SchemaViolation> {
+        let mut violations = Vec::new();
+        self.validate_object(&self.root.schema, value, "", &mut violations);
+        violations
+    }
+
+    /// Converts request parameters (e.g., from the query string), whose values
+    /// are all strings, into an object whose values have the types that the
+    /// schema expects, so that it can be validated.  Parameters whose values
+    /// don't parse as the expected type are left as strings.
+    pub fn params_to_value<'a, I>(&self, params: I) -> Value
+    where
+        I: IntoIterator<Item = (&'a str, Vec<&'a str>)>,
+    {
+        let object = params
+            .into_iter()
+            .map(|(name, values)| {
+                let schema = self.property_schema(&self.root.schema, name);
+                let value = match schema {
+                    Some(s) if self.allows_type(s, &InstanceType::Array) => {
+                        let items = self.array_items(s);
+                        Value::Array(
+                            values
+                                .into_iter()
+                                .map(|v| self.coerce(items, v))
+                                .collect(),
+                        )
+                    }
+                    _ => match values.first() {
+                        Some(v) => self.coerce(schema, v),
+                        None => Value::Null,
+                    },
+                };
+                (name.to_string(), value)
+            })
+            .collect();
+        Value::Object(object)
+    }
+
+    /// Returns whether the schema expects the parameter `name` to be an array
+    /// (rather than a single value).
+    pub fn expects_array(&self, name: &str) -> bool {
+        self.property_schema(&self.root.schema, name)
+            .map_or(false, |s| self.allows_type(s, &InstanceType::Array))
+    }
+
+    /// Converts a form (e.g., a URL-encoded body), which may contain nested
+    /// objects and arrays but whose values are all strings, into an object
+    /// whose values have the types that the schema expects, so that it can be
+    /// validated.  As with `params_to_value()`, values that don't parse as the
+    /// expected type are left as strings.
+    pub fn form_to_value(&self, form: Map<String, Value>) -> Value {
+        let object = form
+            .into_iter()
+            .map(|(name, value)| {
+                let schema = self.property_schema(&self.root.schema, &name);
+                (name, self.coerce_form_value(schema, value))
+            })
+            .collect();
+        Value::Object(object)
+    }
+
+    fn coerce_form_value(
+        &self,
+        schema: Option<&Schema>,
+        value: Value,
+    ) -> Value {
+        match value {
+            Value::String(s) => self.coerce(schema, &s),
+            Value::Array(items) => {
+                let items_schema = schema.and_then(|s| self.array_items(s));
+                Value::Array(
+                    items
+                        .into_iter()
+                        .map(|item| self.coerce_form_value(items_schema, item))
+                        .collect(),
+                )
+            }
+            Value::Object(properties) => {
+                let object = properties
+                    .into_iter()
+                    .map(|(name, property)| {
+                        let property_schema = match schema {
+                            Some(Schema::Object(o)) => {
+                                self.property_schema(o, &name)
+                            }
+                            _ => None,
+                        };
+                        let property =
+                            self.coerce_form_value(property_schema, property);
+                        (name, property)
+                    })
+                    .collect();
+                Value::Object(object)
+            }
+            value => value,
+        }
+    }
+
+    /// Returns the schema referenced by `schema`, if it's a reference.
+    fn resolve<'a>(&'a self, schema: &'a SchemaObject) -> &'a SchemaObject {
+        match schema
+            .reference
+            .as_deref()
+            .and_then(|r| r.strip_prefix("#/definitions/"))
+            .and_then(|name| self.root.definitions.get(name))
+        {
+            Some(Schema::Object(target)) => self.resolve(target),
+            _ => schema,
+        }
+    }
+
+    /// Returns the subschemas that a value must (or may) also conform to.
+    fn subschemas<'a>(
+        &self,
+        schema: &'a SchemaObject,
+    ) -> impl Iterator<Item = &'a Schema> {
+        schema.subschemas.iter().flat_map(|s| {
+            s.all_of
+                .iter()
+                .chain(s.any_of.iter())
+                .chain(s.one_of.iter())
+                .flatten()
+        })
+    }
+
+    fn property_schema<'a>(
+        &'a self,
+        schema: &'a SchemaObject,
+        name: &str,
+    ) -> Option<&'a Schema> {
+        let schema = self.resolve(schema);
+        schema.object.as_ref().and_then(|o| o.properties.get(name)).or_else(
+            || {
+                self.subschemas(schema).find_map(|s| match s {
+                    Schema::Object(o) => self.property_schema(o, name),
+                    Schema::Bool(_) => None,
+                })
+            },
+        )
+    }
+
+    fn allows_type(
+        &self,
+        schema: &Schema,
+        instance_type: &InstanceType,
+    ) -> bool {
+        let schema = match schema {
+            Schema::Object(o) => self.resolve(o),
+            Schema::Bool(_) => return false,
+        };
+        let direct = match &schema.instance_type {
+            Some(SingleOrVec::Single(t)) => **t == *instance_type,
+            Some(SingleOrVec::Vec(ts)) => ts.contains(instance_type),
+            None => false,
+        };
+        direct
+            || self
+                .subschemas(schema)
+                .any(|s| self.allows_type(s, instance_type))
+    }
+
+    fn array_items<'a>(&'a self, schema: &'a Schema) -> Option<&'a Schema> {
+        let schema = match schema {
+            Schema::Object(o) => self.resolve(o),
+            Schema::Bool(_) => return None,
+        };
+        match schema.array.as_ref().and_then(|a| a.items.as_ref()) {
+            Some(SingleOrVec::Single(items)) => Some(items),
+            _ => self.subschemas(schema).find_map(|s| self.array_items(s)),
+        }
+    }
+
+    fn coerce(&self, schema: Option<&Schema>, raw: &str) -> Value {
+        if let Some(schema) = schema {
+            if self.allows_type(schema, &InstanceType::Integer)
+                || self.allows_type(schema, &InstanceType::Number)
+            {
+                if let Ok(n) = raw.parse::<i64>() {
+                    return Value::from(n);
+                }
+                if let Ok(n) = raw.parse::<u64>() {
+                    return Value::from(n);
+                }
+                if let Some(n) = raw
+                    .parse::<f64>()
+                    .ok()
+                    .and_then(serde_json::Number::from_f64)
+                {
+                    return Value::Number(n);
+                }
+            }
+            if self.allows_type(schema, &InstanceType::Boolean) {
+                if let Ok(b) = raw.parse::<bool>() {
+                    return Value::Bool(b);
+                }
+            }
+        }
+        Value::String(raw.to_string())
+    }
+
+    /// Returns whether `value` conforms to `schema`.
+    fn is_valid(&self, schema: &Schema, value: &Value) -> bool {
+        let mut violations = Vec::new();
+        self.validate(schema, value, "", &mut violations);
+        violations.is_empty()
+    }
+
+    fn validate(
+        &self,
+        schema: &Schema,
+        value: &Value,
+        path: &str,
+        out: &mut Vec<SchemaViolation>,
+    ) {
+        match schema {
+            Schema::Bool(true) => (),
+            Schema::Bool(false) => out.push(violation(path, "is not allowed")),
+            Schema::Object(object) => {
+                self.validate_object(object, value, path, out)
+            }
+        }
+    }
+
+    fn validate_object(
+        &self,
+        schema: &SchemaObject,
+        value: &Value,
+        path: &str,
+        out: &mut Vec<SchemaViolation>,
+    ) {
+        let schema = self.resolve(schema);
+
+        if let Some(types) = &schema.instance_type {
+            let matches = match types {
+                SingleOrVec::Single(t) => has_type(value, t),
+                SingleOrVec::Vec(ts) => ts.iter().any(|t| has_type(value, t)),
+            };
+            if !matches {
+                let expected = match types {
+                    SingleOrVec::Single(t) => vec![type_name(t)],
+                    SingleOrVec::Vec(ts) => ts.iter().map(type_name).collect(),
+                };
+                out.push(violation(
+                    path,
+                    format!("expected {}", expected.join(" or ")),
+                ));
+                // Nothing else about the value can be meaningfully checked.
+                return;
+            }
+        }
+
+        if let Some(values) = &schema.enum_values {
+            if !values.contains(value) {
+                let allowed = values
+                    .iter()
+                    .map(Value::to_string)
+                    .collect::<Vec<_>>()
+                    .join(", ");
+                out.push(violation(
+                    path,
+                    format!("must be one of {}", allowed),
+                ));
+            }
+        }
+        if let Some(constant) = &schema.const_value {
+            if constant != value {
+                out.push(violation(path, format!("must be {}", constant)));
+            }
+        }
+        if let Some(format) = &schema.format {
+            if !is_valid_format(format, value) {
+                out.push(violation(
+                    path,
+                    format!("must be a valid \"{}\"", format),
+                ));
+            }
+        }
+        if let Some(number) = &schema.number {
+            validate_number(number, value, path, out);
+        }
+        if let Some(string) = &schema.string {
+            self.validate_string(string, value, path, out);
+        }
+        if let Some(array) = &schema.array {
+            self.validate_array(array, value, path, out);
+        }
+        if let Some(object) = &schema.object {
+            self.validate_properties(object, value, path, out);
+        }
+        if let Some(subschemas) = &schema.subschemas {
+            self.validate_subschemas(subschemas, value, path, out);
+        }
+    }
+
+    fn validate_array(
+        &self,
+        array: &ArrayValidation,
+        value: &Value,
+        path: &str,
+        out: &mut Vec<SchemaViolation>,
+    ) {
+        let items = match value {
+            Value::Array(items) => items,
+            _ => return,
+        };
+
+        match &array.items {
+            Some(SingleOrVec::Single(schema)) => {
+                for (i, item) in items.iter().enumerate() {
+                    let item_path = pointer_join(path, &i.to_string());
+                    self.validate(schema, item, &item_path, out);
+                }
+            }
+            Some(SingleOrVec::Vec(schemas)) => {
+                for (i, item) in items.iter().enumerate() {
+                    let item_path = pointer_join(path, &i.to_string());
+                    match schemas.get(i).or(array.additional_items.as_deref()) {
+                        Some(schema) => {
+                            self.validate(schema, item, &item_path, out)
+                        }
+                        None => (),
+                    }
+                }
+            }
+            None => (),
+        }
+
+        if let Some(max) = array.max_items {
+            if items.len() > max as usize {
+                out.push(violation(
+                    path,
+                    format!("must have at most {} items", max),
+                ));
+            }
+        }
+        if let Some(min) = array.min_items {
+            if items.len() < min as usize {
+                out.push(violation(
+                    path,
+                    format!("must have at least {} items", min),
+                ));
+            }
+        }
+        if array.unique_items == Some(true) {
+            let duplicated = items
+                .iter()
+                .enumerate()
+                .any(|(i, item)| items[..i].contains(item));
+            if duplicated {
+                out.push(violation(path, "items must be unique"));
+            }
+        }
+        if let Some(contains) = &array.contains {
+            if !items.iter().any(|item| self.is_valid(contains, item)) {
+                out.push(violation(path, "must contain a matching item"));
+            }
+        }
+    }
+
+    fn validate_properties(
+        &self,
+        object: &ObjectValidation,
+        value: &Value,
+        path: &str,
+        out: &mut Vec<SchemaViolation>,
+    ) {
+        let properties = match value {
+            Value::Object(properties) => properties,
+            _ => return,
+        };
+
+        for name in &object.required {
+            if !properties.contains_key(name) {
+                out.push(violation(&pointer_join(path, name), "is required"));
+            }
+        }
+
+        for (name, property) in properties {
+            let property_path = pointer_join(path, name);
+            let mut matched = false;
+            if let Some(schema) = object.properties.get(name) {
+                matched = true;
+                self.validate(schema, property, &property_path, out);
+            }
+            for (pattern, schema) in &object.pattern_properties {
+                if self.patterns[pattern].is_match(name) {
+                    matched = true;
+                    self.validate(schema, property, &property_path, out);
+                }
+            }
+            if !matched {
+                if let Some(schema) = &object.additional_properties {
+                    match schema.as_ref() {
+                        Schema::Bool(false) => out.push(violation(
+                            &property_path,
+                            "is not a known property",
+                        )),
+                        schema => {
+                            self.validate(schema, property, &property_path, out)
+                        }
+                    }
+                }
+            }
+            if let Some(schema) = &object.property_names {
+                let name_value = Value::String(name.clone());
+                if !self.is_valid(schema, &name_value) {
+                    out.push(violation(&property_path, "is not a valid name"));
+                }
+            }
+        }
+
+        if let Some(max) = object.max_properties {
+            if properties.len() > max as usize {
+                out.push(violation(
+                    path,
+                    format!("must have at most {} properties", max),
+                ));
+            }
+        }
+        if let Some(min) = object.min_properties {
+            if properties.len() < min as usize {
+                out.push(violation(
+                    path,
+                    format!("must have at least {} properties", min),
+                ));
+            }
+        }
+    }
+
+    fn validate_subschemas(
+        &self,
+        subschemas: &SubschemaValidation,
+        value: &Value,
+        path: &str,
+        out: &mut Vec<SchemaViolation>,
+    ) {
+        for schema in subschemas.all_of.iter().flatten() {
+            self.validate(schema, value, path, out);
+        }
+        if let Some(schemas) = &subschemas.any_of {
+            if !schemas.iter().any(|s| self.is_valid(s, value)) {
+                self.report_alternatives(schemas, value, path, out);
+            }
+        }
+        if let Some(schemas) = &subschemas.one_of {
+            match schemas.iter().filter(|s| self.is_valid(s, value)).count() {
+                0 => self.report_alternatives(schemas, value, path, out),
+                1 => (),
+                _ => out.push(violation(
+                    path,
+                    "matches more than one of the allowed schemas",
+                )),
+            }
+        }
+        if let Some(schema) = &subschemas.not {
+            if self.is_valid(schema, value) {
+                out.push(violation(path, "matches a disallowed schema"));
+            }
+        }
+        if let Some(condition) = &subschemas.if_schema {
+            let branch = if self.is_valid(condition, value) {
+                &subschemas.then_schema
+            } else {
+                &subschemas.else_schema
+            };
+            if let Some(schema) = branch {
+                self.validate(schema, value, path, out);
+            }
+        }
+    }
+
+    fn validate_string(
+        &self,
+        string: &StringValidation,
+        value: &Value,
+        path: &str,
+        out: &mut Vec<SchemaViolation>,
+    ) {
+        let s = match value {
+            Value::String(s) => s,
+            _ => return,
+        };
+
+        let length = s.chars().count();
+        if let Some(max) = string.max_length {
+            if length > max as usize {
+                out.push(violation(
+                    path,
+                    format!("must be at most {} characters long", max),
+                ));
+            }
+        }
+        if let Some(min) = string.min_length {
+            if length < min as usize {
+                out.push(violation(
+                    path,
+                    format!("must be at least {} characters long", min),
+                ));
+            }
+        }
+        if let Some(pattern) = &string.pattern {
+            if !self.patterns[pattern].is_match(s) {
+                out.push(violation(
+                    path,
+                    format!("must match the pattern \"{}\"", pattern),
+                ));
+            }
+        }
+    }
+
+    /// Reports that `value` matches none of `schemas`.  When only one of them
+    /// is for a value of the right type (as for an optional struct, whose
+    /// schema is a choice between the struct and null), that one's violations
+    /// are much more useful than a generic message, so we report them instead.
+    fn report_alternatives(
+        &self,
+        schemas: &[Schema],
+        value: &Value,
+        path: &str,
+        out: &mut Vec<SchemaViolation>,
+    ) {
+        let candidates = schemas
+            .iter()
+            .filter(|s| self.type_matches(s, value))
+            .collect::<Vec<_>>();
+        if let [schema] = candidates[..] {
+            self.validate(schema, value, path, out);
+        } else {
+            out.push(violation(path, "does not match any allowed schema"));
+        }
+    }
+
+    /// Returns whether `schema` is (at the top level) for values of the same
+    /// type as `value`.
+    fn type_matches(&self, schema: &Schema, value: &Value) -> bool {
+        let schema = match schema {
+            Schema::Object(o) => self.resolve(o),
+            Schema::Bool(b) => return *b,
+        };
+        match &schema.instance_type {
+            Some(SingleOrVec::Single(t)) => has_type(value, t),
+            Some(SingleOrVec::Vec(ts)) => ts.iter().any(|t| has_type(value, t)),
+            None => true,
+        }
+    }
+}
+
+/// Compiles the patterns in `schema` and its subschemas into `patterns`.
+fn compile_patterns(
+    schema: &SchemaObject,
+    patterns: &mut HashMap<String, Regex>,
+) -> Result<(), String> {
+    let mut compile = |pattern: &String| {
+        if !patterns.contains_key(pattern) {
+            let re = Regex::new(pattern).map_err(|e| {
+                format!("invalid pattern \"{}\": {}", pattern, e)
+            })?;
+            patterns.insert(pattern.clone(), re);
+        }
+        Ok::<(), String>(())
+    };
+    if let Some(pattern) =
+        schema.string.as_ref().and_then(|s| s.pattern.as_ref())
+    {
+        compile(pattern)?;
+    }
+
+    let mut subschemas: Vec<&Schema> = Vec::new();
+    if let Some(object) = &schema.object {
+        for pattern in object.pattern_properties.keys() {
+            compile(pattern)?;
+        }
+        subschemas.extend(object.properties.values());
+        subschemas.extend(object.pattern_properties.values());
+        subschemas.extend(object.additional_properties.as_deref());
+        subschemas.extend(object.property_names.as_deref());
+    }
+    if let Some(array) = &schema.array {
+        match &array.items {
+            Some(SingleOrVec::Single(items)) => subschemas.push(items),
+            Some(SingleOrVec::Vec(items)) => subschemas.extend(items),
+            None => (),
+        }
+        subschemas.extend(array.additional_items.as_deref());
+        subschemas.extend(array.contains.as_deref());
+    }
+    if let Some(s) = &schema.subschemas {
+        subschemas.extend(s.all_of.iter().flatten());
+        subschemas.extend(s.any_of.iter().flatten());
+        subschemas.extend(s.one_of.iter().flatten());
+        subschemas.extend(s.not.as_deref());
+        subschemas.extend(s.if_schema.as_deref());
+        subschemas.extend(s.then_schema.as_deref());
+        subschemas.extend(s.else_schema.as_deref());
+    }
+    for subschema in subschemas {
+        if let Schema::Object(object) = subschema {
+            compile_patterns(object, patterns)?;
+        }
+    }
+    Ok(())
+}
+
+fn has_type(value: &Value, instance_type: &InstanceType) -> bool {
+    match (instance_type, value) {
+        (InstanceType::Null, Value::Null)
+        | (InstanceType::Boolean, Value::Bool(_))
+        | (InstanceType::Object, Value::Object(_))
+        | (InstanceType::Array, Value::Array(_))
+        | (InstanceType::Number, Value::Number(_))
+        | (InstanceType::String, Value::String(_)) => true,
+        (InstanceType::Integer, Value::Number(n)) => {
+            n.is_i64()
+                || n.is_u64()
+                || n.as_f64().map_or(false, |f| f.fract() == 0.0)
+        }
+        _ => false,
+    }
+}
+
+fn type_name(instance_type: &InstanceType) -> &'static str {
+    match instance_type {
+        InstanceType::Null => "null",
+        InstanceType::Boolean => "a boolean",
+        InstanceType::Object => "an object",
+        InstanceType::Array => "an array",
+        InstanceType::Number => "a number",
+        InstanceType::String => "a string",
+        InstanceType::Integer => "an integer",
+    }
+}
+
+fn validate_number(
+    number: &NumberValidation,
+    value: &Value,
+    path: &str,
+    out: &mut Vec<SchemaViolation>,
+) {
+    let n = match value.as_f64() {
+        Some(n) => n,
+        None => return,
+    };
+
+    if let Some(multiple) = number.multiple_of {
+        if multiple > 0.0 && (n / multiple).fract() != 0.0 {
+            out.push(violation(
+                path,
+                format!("must be a multiple of {}", multiple),
+            ));
+        }
+    }
+    if let Some(max) = number.maximum {
+        if n > max {
+            out.push(violation(path, format!("must be at most {}", max)));
+        }
+    }
+    if let Some(max) = number.exclusive_maximum {
+        if n >= max {
+            out.push(violation(path, format!("must be less than {}", max)));
+        }
+    }
+    if let Some(min) = number.minimum {
+        if n < min {
+            out.push(violation(path, format!("must be at least {}", min)));
+        }
+    }
+    if let Some(min) = number.exclusive_minimum {
+        if n <= min {
+            out.push(violation(path, format!("must be greater than {}", min)));
+        }
+    }
+}
+
+/// Returns whether `value` conforms to the named format.  Formats we don't know
+/// about are assumed to be satisfied.
+fn is_valid_format(format: &str, value: &Value) -> bool {
+    match value {
+        Value::String(s) => match format {
+            "date-time" => chrono::DateTime::parse_from_rfc3339(s).is_ok(),
+            "date" => chrono::NaiveDate::parse_from_str(s, "%Y-%m-%d").is_ok(),
+            "uuid" => uuid::Uuid::parse_str(s).is_ok(),
+            "ip" => s.parse::<IpAddr>().is_ok(),
+            "ipv4" => s.parse::<Ipv4Addr>().is_ok(),
+            "ipv6" => s.parse::<Ipv6Addr>().is_ok(),
+            "hostname" => is_hostname(s),
+            "email" => match s.rsplit_once('@') {
+                Some((local, domain)) => {
+                    !local.is_empty()
+                        && !local.chars().any(char::is_whitespace)
+                        && is_hostname(domain)
+                }
+                None => false,
+            },
+            "uri" => match s.split_once(':') {
+                Some((scheme, _)) => {
+                    scheme.starts_with(|c: char| c.is_ascii_alphabetic())
+                        && scheme.chars().all(|c| {
+                            c.is_ascii_alphanumeric() || "+-.".contains(c)
+                        })
+                        && !s.chars().any(char::is_whitespace)
+                }
+                None => false,
+            },
+            _ => true,
+        },
+        Value::Number(n) => {
+            let (min, max): (i128, i128) = match format {
+                "int8" => (i8::MIN.into(), i8::MAX.into()),
+                "int16" => (i16::MIN.into(), i16::MAX.into()),
+                "int32" => (i32::MIN.into(), i32::MAX.into()),
+                "uint8" => (0, u8::MAX.into()),
+                "uint16" => (0, u16::MAX.into()),
+                "uint32" => (0, u32::MAX.into()),
+                "uint" | "uint64" => (0, u64::MAX.into()),
+                _ => return true,
+            };
+            let n = n
+                .as_i64()
+                .map(i128::from)
+                .or_else(|| n.as_u64().map(i128::from));
+            n.map_or(false, |n| min <= n && n <= max)
+        }
+        _ => true,
+    }
+}
+
+fn is_hostname(s: &str) -> bool {
+    !s.is_empty()
+        && s.len() <= 253
+        && s.split('.').all(|label| {
+            !label.is_empty()
+                && label.len() <= 63
+                && !label.starts_with('-')
+                && !label.ends_with('-')
+                && label.chars().all(|c| c.is_ascii_alphanumeric() || c == '-')
+        })
+}
+
+#[cfg(test)]
+mod test {
+    use super::SchemaValidator;
+    use super::SchemaViolation;
+    use schemars::JsonSchema;
+    use serde_json::json;
+
+    #[allow(dead_code)]
+    #[derive(JsonSchema)]
+    struct Address {
+        #[schemars(length(min = 1, max = 8))]
+        city: String,
+        #[schemars(regex(pattern = r"^\d{5}$"))]
+        zip: String,
+    }
+
+    #[allow(dead_code)]
+    #[derive(JsonSchema)]
+    struct Person {
+        #[schemars(email)]
+        email: String,
+        #[schemars(range(min = 0, max = 150))]
+        age: u8,
+        address: Option<Address>,
+        #[schemars(length(max = 2))]
+        tags: Vec<String>,
+        id: Option<uuid::Uuid>,
+    }
+
+    fn violations(value: serde_json::Value) -> Vec<(String, String)> {
+        SchemaValidator::new::<Person>()
+            .unwrap()
+            .violations(&value)
+            .into_iter()
+            .map(|SchemaViolation { path, message }| (path, message))
+            .collect()
+    }
+
+    #[test]
+    fn test_valid() {
+        assert_eq!(
+            violations(json!({
+                "email": "ada@example.com",
+                "age": 36,
+                "address": { "city": "London", "zip": "12345" },
+                "tags": ["math"],
+                "id": "2c9e1bea-a2a8-4b3c-9a2f-8d5a0ad7c7a1",
+            })),
+            vec![]
+        );
+        assert_eq!(
+            violations(json!({
+                "email": "ada@example.com",
+                "age": 36,
+                "address": null,
+                "tags": [],
+            })),
+            vec![]
+        );
+    }
+
+    #[test]
+    fn test_violations() {
+        let v =
+            |path: &str, message: &str| (path.to_string(), message.to_string());
+        assert_eq!(
+            violations(json!({
+                "email": "ada",
+                "age": 151,
+                "address": { "city": "Londonderry", "zip": "1234" },
+                "tags": ["a", "b", "c"],
+                "id": "nope",
+            })),
+            vec![
+                v("/address/city", "must be at most 8 characters long"),
+                v("/address/zip", "must match the pattern \"^\\d{5}$\""),
+                v("/age", "must be at most 150"),
+                v("/email", "must be a valid \"email\""),
+                v("/id", "must be a valid \"uuid\""),
+                v("/tags", "must have at most 2 items"),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_invalid_pattern() {
+        #[allow(dead_code)]
+        #[derive(JsonSchema)]
+        struct Lookahead {
+            #[schemars(regex(pattern = r"^(?!admin)[a-z]+$"))]
+            username: String,
+        }
+
+        let error = SchemaValidator::new::<Lookahead>().err().unwrap();
+        assert!(
+            error.starts_with(
+                "invalid schema for Lookahead: invalid pattern \"^(?!admin)"
+            ),
+            "{}",
+            error
+        );
+    }
+
+    #[test]
+    fn test_params_to_value() {
+        #[allow(dead_code)]
+        #[derive(JsonSchema)]
+        struct Params {
+            limit: Option<u32>,
+            verbose: bool,
+            name: String,
+            ids: Vec<u64>,
+        }
+
+        let validator = SchemaValidator::new::<Params>().unwrap();
+        let value = validator.params_to_value(vec![
+            ("limit", vec!["10"]),
+            ("verbose", vec!["true"]),
+            ("name", vec!["17"]),
+            ("ids", vec!["1", "2"]),
+            ("other", vec!["x"]),
+        ]);
+        assert_eq!(
+            value,
+            json!({
+                "limit": 10,
+                "verbose": true,
+                "name": "17",
+                "ids": [1, 2],
+                "other": "x",
+            })
+        );
+    }
+
+    #[test]
+    fn test_form_to_value() {
+        #[allow(dead_code)]
+        #[derive(JsonSchema)]
+        struct Filter {
+            name: String,
+            min_age: Option<u8>,
+        }
+
+        #[allow(dead_code)]
+        #[derive(JsonSchema)]
+        struct Form {
+            filter: Option<Filter>,
+            ids: Vec<u64>,
+        }
+
+        let validator = SchemaValidator::new::<Form>().unwrap();
+        let form = json!({
+            "filter": { "name": "7", "min_age": "18" },
+            "ids": ["1", "two"],
+        });
+        let value = validator.form_to_value(form.as_object().unwrap().clone());
+        assert_eq!(
+            value,
+            json!({
+                "filter": { "name": "7", "min_age": 18 },
+                "ids": [1, "two"],
+            })
+        );
+    }
+}
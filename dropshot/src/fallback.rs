@@ -0,0 +1,110 @@
+// Copyright 2023 Oxide Computer Company
+//! Handling of requests that match no route
+//!
+//! By default, a request whose path matches no route fails with a 404 ("Not
+//! Found") error.  A [`FallbackHandler`] configured with
+//! [`ApiDescription::fallback_handler()`](crate::ApiDescription::fallback_handler)
+//! handles such requests instead, e.g., to serve a custom 404 page, the index
+//! page of a single-page application, or to proxy the request elsewhere.
+//!
+//! ```
+//! use async_trait::async_trait;
+//! use dropshot::ApiDescription;
+//! use dropshot::Body;
+//! use dropshot::FallbackHandler;
+//! use dropshot::HttpError;
+//! use dropshot::RequestContext;
+//! use http::Request;
+//! use http::Response;
+//! use http::StatusCode;
+//! use std::sync::Arc;
+//!
+//! /// Serves the application's index page for any unmatched `GET` request
+//! #[derive(Debug)]
+//! struct IndexFallback;
+//!
+//! #[async_trait]
+//! impl FallbackHandler<()> for IndexFallback {
+//!     async fn handle(
+//!         &self,
+//!         _rqctx: RequestContext<()>,
+//!         request: Request<Body>,
+//!     ) -> Result<Response<Body>, HttpError> {
+//!         if request.method() != http::Method::GET {
+//!             return Err(HttpError::for_not_found(
+//!                 None,
+//!                 String::from("no route found"),
+//!             ));
+//!         }
+//!         Ok(Response::builder()
+//!             .status(StatusCode::OK)
+//!             .header(http::header::CONTENT_TYPE, "text/html")
+//!             .body(Body::from("<html>...</html>"))?)
+//!     }
+//! }
+//!
+//! let api = ApiDescription::<()>::new()
+//!     .fallback_handler(Arc::new(IndexFallback));
+//! ```
+
+use crate::error::HttpError;
+use crate::handler::HttpHandlerResult;
+use crate::handler::RequestContext;
+use crate::handler::RouteHandler;
+use crate::server::ServerContext;
+use crate::Body;
+
+use async_trait::async_trait;
+use hyper::Request;
+use hyper::Response;
+use std::fmt::Debug;
+use std::sync::Arc;
+
+/// Handles requests whose paths match no route.  Requests whose paths match a
+/// route, but whose methods have no handler, still fail with a 405 ("Method
+/// Not Allowed") error.
+///
+/// The handler runs like an endpoint's handler (e.g., it's subject to the
+/// server's `handler_task_mode`), but it isn't in the OpenAPI document and no
+/// [`RequestHook`](crate::RequestHook)s run for it.  Its requests are logged
+/// with the route `"(fallback)"`.
+#[async_trait]
+pub trait FallbackHandler<Context: ServerContext>: Debug + Send + Sync {
+    /// Handles `request`, which matched no route.
+    async fn handle(
+        &self,
+        rqctx: RequestContext<Context>,
+        request: Request<Body>,
+    ) -> Result<Response<Body>, HttpError>;
+}
+
+/// Path template with which requests handled by a [`FallbackHandler`] are
+/// logged
+pub(crate) const FALLBACK_ROUTE: &str = "(fallback)";
+
+/// Routes requests to a [`FallbackHandler`]
+#[derive(Debug)]
+pub(crate) struct FallbackRoute<Context: ServerContext> {
+    handler: Arc<dyn FallbackHandler<Context>>,
+}
+
+impl<Context: ServerContext> FallbackRoute<Context> {
+    pub(crate) fn new(handler: Arc<dyn FallbackHandler<Context>>) -> Self {
+        FallbackRoute { handler }
+    }
+}
+
+#[async_trait]
+impl<Context: ServerContext> RouteHandler<Context> for FallbackRoute<Context> {
+    fn label(&self) -> &str {
+        FALLBACK_ROUTE
+    }
+
+    async fn handle_request(
+        &self,
+        rqctx: RequestContext<Context>,
+        request: Request<Body>,
+    ) -> HttpHandlerResult {
+        self.handler.handle(rqctx, request).await
+    }
+}
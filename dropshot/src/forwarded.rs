@@ -0,0 +1,401 @@
+// Copyright 2023 Oxide Computer Company
+//! Identifying the client behind trusted proxies (see
+//! `ConfigDropshot::trusted_proxies`)
+//!
+//! Proxies describe the requests they forward with the standard `Forwarded`
+//! header (RFC 7239) or the older `X-Forwarded-For` and `X-Forwarded-Proto`
+//! headers, each proxy appending the client it received the request from.
+//! Anyone can send these headers, so they're only believed as far back as the
+//! chain of proxies is trusted: starting from the peer on the other end of
+//! the connection, each hop is believed only if it was reported by a trusted
+//! proxy, and the first hop that isn't a trusted proxy is taken to be the
+//! client.
+
+use crate::connection::ConnectionInfo;
+
+use http::header::HeaderMap;
+use http::header::FORWARDED;
+use http::uri::Scheme;
+use std::net::IpAddr;
+use std::str::FromStr;
+
+const HEADER_X_FORWARDED_FOR: &str = "x-forwarded-for";
+const HEADER_X_FORWARDED_PROTO: &str = "x-forwarded-proto";
+
+/// The client on whose behalf a request was made, as identified through any
+/// trusted proxies that forwarded it (see `ConfigDropshot::trusted_proxies`).
+/// It's available to handlers from
+/// [`RequestContext::client()`](crate::RequestContext::client).
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct EffectiveClient {
+    addr: IpAddr,
+    scheme: Scheme,
+    forwarded: bool,
+}
+
+impl EffectiveClient {
+    /// Returns the peer on the other end of `connection` as the client.
+    pub(crate) fn for_connection(
+        connection: &ConnectionInfo,
+    ) -> EffectiveClient {
+        EffectiveClient {
+            addr: canonical(connection.remote_addr().ip()),
+            scheme: if connection.is_tls() {
+                Scheme::HTTPS
+            } else {
+                Scheme::HTTP
+            },
+            forwarded: false,
+        }
+    }
+
+    /// Returns the address of the client.
+    pub fn addr(&self) -> IpAddr {
+        self.addr
+    }
+
+    /// Returns the scheme (`http` or `https`) with which the client made the
+    /// request.
+    pub fn scheme(&self) -> &Scheme {
+        &self.scheme
+    }
+
+    /// Returns whether the client was identified from headers added by
+    /// trusted proxies, rather than being the peer on the other end of the
+    /// connection.
+    pub fn is_forwarded(&self) -> bool {
+        self.forwarded
+    }
+}
+
+/// A range of addresses, written as an address with an optional prefix length
+/// (e.g., `10.0.0.0/8` or `::1`)
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+struct IpNetwork {
+    addr: IpAddr,
+    prefix_len: u32,
+}
+
+impl FromStr for IpNetwork {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<IpNetwork, ()> {
+        let (addr, prefix_len) = match s.split_once('/') {
+            Some((addr, prefix_len)) => {
+                (addr, Some(prefix_len.parse::<u32>().map_err(|_| ())?))
+            }
+            None => (s, None),
+        };
+        let addr = addr.parse::<IpAddr>().map_err(|_| ())?;
+        let max_len = if addr.is_ipv4() { 32 } else { 128 };
+        let prefix_len = prefix_len.unwrap_or(max_len);
+        if prefix_len > max_len {
+            return Err(());
+        }
+        Ok(IpNetwork { addr, prefix_len })
+    }
+}
+
+impl IpNetwork {
+    fn contains(&self, addr: IpAddr) -> bool {
+        match (self.addr, canonical(addr)) {
+            (IpAddr::V4(network), IpAddr::V4(addr)) => {
+                prefix_eq(&network.octets(), &addr.octets(), self.prefix_len)
+            }
+            (IpAddr::V6(network), IpAddr::V6(addr)) => {
+                prefix_eq(&network.octets(), &addr.octets(), self.prefix_len)
+            }
+            _ => false,
+        }
+    }
+}
+
+/// Returns whether the first `prefix_len` bits of `a` and `b` are equal.
+fn prefix_eq(a: &[u8], b: &[u8], prefix_len: u32) -> bool {
+    let bytes = (prefix_len / 8) as usize;
+    let bits = prefix_len % 8;
+    if a[..bytes] != b[..bytes] {
+        return false;
+    }
+    bits == 0 || (a[bytes] ^ b[bytes]) >> (8 - bits) == 0
+}
+
+/// Returns `addr`, or the IPv4 address that it maps if it's an IPv4-mapped
+/// IPv6 address (as the peers of dual-stack sockets appear).
+fn canonical(addr: IpAddr) -> IpAddr {
+    match addr {
+        IpAddr::V6(v6) => v6.to_ipv4_mapped().map_or(addr, IpAddr::V4),
+        IpAddr::V4(_) => addr,
+    }
+}
+
+/// The proxies whose forwarding headers a server believes
+#[derive(Debug, Default)]
+pub(crate) struct TrustedProxies {
+    networks: Vec<IpNetwork>,
+}
+
+impl TrustedProxies {
+    pub fn new(proxies: &[String]) -> Result<TrustedProxies, String> {
+        let networks = proxies
+            .iter()
+            .map(|proxy| {
+                proxy.parse().map_err(|_| {
+                    format!(
+                        "invalid address for trusted_proxies: \"{}\"",
+                        proxy
+                    )
+                })
+            })
+            .collect::<Result<_, _>>()?;
+        Ok(TrustedProxies { networks })
+    }
+
+    fn trusts(&self, addr: IpAddr) -> bool {
+        self.networks.iter().any(|network| network.contains(addr))
+    }
+
+    /// Returns the client that made a request with `headers` on `connection`.
+    pub fn client(
+        &self,
+        connection: &ConnectionInfo,
+        headers: &HeaderMap,
+    ) -> EffectiveClient {
+        let mut client = EffectiveClient::for_connection(connection);
+        if !self.trusts(client.addr) {
+            return client;
+        }
+
+        // Walk back from the most recent hop for as long as each was reported
+        // by a trusted proxy.
+        for hop in forwarded_hops(headers).into_iter().rev() {
+            let addr = match hop.addr {
+                Some(addr) => canonical(addr),
+                // The proxy doesn't know (or won't say) where the request came
+                // from, so the proxy is as far back as the client is known.
+                None => break,
+            };
+            client.addr = addr;
+            if let Some(scheme) = hop.scheme {
+                client.scheme = scheme;
+            }
+            client.forwarded = true;
+            if !self.trusts(addr) {
+                break;
+            }
+        }
+        client
+    }
+}
+
+/// One hop described by forwarding headers: the client from which a proxy
+/// received the request, and the scheme with which it did
+#[derive(Debug, Eq, PartialEq)]
+struct Hop {
+    addr: Option<IpAddr>,
+    scheme: Option<Scheme>,
+}
+
+/// Returns the hops described by `headers`, earliest first.  The `Forwarded`
+/// header takes precedence over the `X-Forwarded-` headers if both are
+/// present.
+fn forwarded_hops(headers: &HeaderMap) -> Vec<Hop> {
+    let values = |name: &str| {
+        headers
+            .get_all(name)
+            .iter()
+            .flat_map(|value| {
+                value.to_str().unwrap_or("").split(',').map(str::trim)
+            })
+            .filter(|value| !value.is_empty())
+            .collect::<Vec<_>>()
+    };
+
+    let forwarded = values(FORWARDED.as_str());
+    if !forwarded.is_empty() {
+        return forwarded
+            .into_iter()
+            .map(|element| {
+                let mut hop = Hop { addr: None, scheme: None };
+                for pair in element.split(';') {
+                    let (key, value) = match pair.split_once('=') {
+                        Some((key, value)) => (key.trim(), unquote(value)),
+                        None => continue,
+                    };
+                    if key.eq_ignore_ascii_case("for") {
+                        hop.addr = parse_node(value);
+                    } else if key.eq_ignore_ascii_case("proto") {
+                        hop.scheme = parse_scheme(value);
+                    }
+                }
+                hop
+            })
+            .collect();
+    }
+
+    let addrs = values(HEADER_X_FORWARDED_FOR);
+    let schemes = values(HEADER_X_FORWARDED_PROTO);
+    // Proxies that set `X-Forwarded-Proto` don't always append to it, so
+    // schemes are matched to addresses from the most recent hop, and the
+    // earliest scheme applies to any earlier hops.
+    let offset = addrs.len().saturating_sub(schemes.len());
+    addrs
+        .iter()
+        .enumerate()
+        .map(|(i, addr)| Hop {
+            addr: parse_node(addr),
+            scheme: schemes
+                .get(i.saturating_sub(offset))
+                .and_then(|scheme| parse_scheme(scheme)),
+        })
+        .collect()
+}
+
+fn unquote(value: &str) -> &str {
+    let value = value.trim();
+    value
+        .strip_prefix('"')
+        .and_then(|value| value.strip_suffix('"'))
+        .unwrap_or(value)
+}
+
+/// Parses a node (an address with an optional port, e.g., `192.0.2.60`,
+/// `192.0.2.60:4711`, or `[2001:db8::17]:4711`), returning `None` for
+/// obfuscated or unknown nodes.
+fn parse_node(node: &str) -> Option<IpAddr> {
+    if let Some(rest) = node.strip_prefix('[') {
+        let (addr, _) = rest.split_once(']')?;
+        return addr.parse().ok();
+    }
+    node.parse().ok().or_else(|| {
+        let (addr, _port) = node.split_once(':')?;
+        addr.parse().ok()
+    })
+}
+
+fn parse_scheme(scheme: &str) -> Option<Scheme> {
+    if scheme.eq_ignore_ascii_case("https") {
+        Some(Scheme::HTTPS)
+    } else if scheme.eq_ignore_ascii_case("http") {
+        Some(Scheme::HTTP)
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::IpNetwork;
+    use super::TrustedProxies;
+    use crate::connection::ConnectionInfo;
+    use http::header::HeaderMap;
+    use http::uri::Scheme;
+    use std::net::IpAddr;
+
+    fn client(
+        proxies: &[&str],
+        peer: &str,
+        headers: &[(&'static str, &str)],
+    ) -> (IpAddr, Scheme, bool) {
+        let proxies = proxies.iter().map(|p| p.to_string()).collect::<Vec<_>>();
+        let proxies = TrustedProxies::new(&proxies).unwrap();
+        let peer = peer.parse().unwrap();
+        let connection = ConnectionInfo::new(peer, peer, false, None);
+        let mut map = HeaderMap::new();
+        for (name, value) in headers {
+            map.append(*name, value.parse().unwrap());
+        }
+        let client = proxies.client(&connection, &map);
+        (client.addr(), client.scheme().clone(), client.is_forwarded())
+    }
+
+    #[test]
+    fn test_ip_network() {
+        let network = "10.1.0.0/16".parse::<IpNetwork>().unwrap();
+        assert!(network.contains("10.1.2.3".parse().unwrap()));
+        assert!(network.contains("::ffff:10.1.2.3".parse().unwrap()));
+        assert!(!network.contains("10.2.0.1".parse().unwrap()));
+        let network = "fd00::/12".parse::<IpNetwork>().unwrap();
+        assert!(network.contains("fd0f::1".parse().unwrap()));
+        assert!(!network.contains("fd10::1".parse().unwrap()));
+        let network = "::1".parse::<IpNetwork>().unwrap();
+        assert!(network.contains("::1".parse().unwrap()));
+        assert!("10.0.0.0/33".parse::<IpNetwork>().is_err());
+        assert!("proxy".parse::<IpNetwork>().is_err());
+        assert!(TrustedProxies::new(&["10.0.0.0/8".to_string()]).is_ok());
+        assert_eq!(
+            TrustedProxies::new(&["10/8".to_string()]).unwrap_err(),
+            "invalid address for trusted_proxies: \"10/8\""
+        );
+    }
+
+    #[test]
+    fn test_effective_client() {
+        let ip = |s: &str| s.parse::<IpAddr>().unwrap();
+
+        // Without trusted proxies, forwarding headers are ignored.
+        assert_eq!(
+            client(&[], "10.0.0.1:80", &[("x-forwarded-for", "192.0.2.1")]),
+            (ip("10.0.0.1"), Scheme::HTTP, false)
+        );
+        assert_eq!(
+            client(
+                &["10.0.0.2"],
+                "10.0.0.1:80",
+                &[("x-forwarded-for", "192.0.2.1")]
+            ),
+            (ip("10.0.0.1"), Scheme::HTTP, false)
+        );
+
+        // Hops are believed back to the first one that isn't trusted, even if
+        // earlier ones claim otherwise.
+        let proxies = ["10.0.0.0/8"];
+        assert_eq!(
+            client(
+                &proxies,
+                "10.0.0.1:80",
+                &[
+                    ("x-forwarded-for", "203.0.113.9, 192.0.2.1"),
+                    ("x-forwarded-for", "10.0.0.5"),
+                    ("x-forwarded-proto", "https"),
+                ]
+            ),
+            (ip("192.0.2.1"), Scheme::HTTPS, true)
+        );
+        assert_eq!(
+            client(
+                &proxies,
+                "10.0.0.1:80",
+                &[("x-forwarded-for", "10.0.0.3, 10.0.0.5")]
+            ),
+            (ip("10.0.0.3"), Scheme::HTTP, true)
+        );
+
+        // `Forwarded` takes precedence, and its nodes may have ports.
+        assert_eq!(
+            client(
+                &proxies,
+                "10.0.0.1:80",
+                &[
+                    ("x-forwarded-for", "192.0.2.1"),
+                    (
+                        "forwarded",
+                        "for=\"[2001:db8::17]:4711\";proto=https, \
+                         For=10.0.0.7;proto=http"
+                    ),
+                ]
+            ),
+            (ip("2001:db8::17"), Scheme::HTTPS, true)
+        );
+
+        // An unknown node stops the search at the proxy that reported it.
+        assert_eq!(
+            client(
+                &proxies,
+                "10.0.0.1:80",
+                &[("forwarded", "for=192.0.2.1, for=unknown, for=10.0.0.9")]
+            ),
+            (ip("10.0.0.9"), Scheme::HTTP, true)
+        );
+    }
+}
@@ -20,12 +20,24 @@ use std::fmt::Display;
 pub(crate) fn from_map<'a, T, Z>(
     map: &'a BTreeMap<String, Z>,
 ) -> Result<T, String>
+where
+    T: Deserialize<'a>,
+    Z: MapValue + Debug + Clone + 'static,
+{
+    from_map_keyed(map).map_err(|e| e.message)
+}
+
+/// Like `from_map()`, but the error identifies the key whose value couldn't be
+/// deserialized, if the failure is specific to one value.
+pub(crate) fn from_map_keyed<'a, T, Z>(
+    map: &'a BTreeMap<String, Z>,
+) -> Result<T, MapError>
 where
     T: Deserialize<'a>,
     Z: MapValue + Debug + Clone + 'static,
 {
     let mut deserializer = MapDeserializer::from_map(map);
-    T::deserialize(&mut deserializer).map_err(|e| e.0)
+    T::deserialize(&mut deserializer)
 }
 
 pub(crate) trait MapValue {
@@ -39,7 +51,7 @@ impl MapValue for String {
     }
 
     fn as_seq(&self) -> Result<Box<dyn Iterator<Item = String>>, MapError> {
-        Err(MapError(
+        Err(MapError::new(
             "a string may not be used in place of a sequence of values"
                 .to_string(),
         ))
@@ -70,7 +82,7 @@ where
     {
         match self {
             MapDeserializer::Value(ref raw_value) => deserialize(raw_value),
-            MapDeserializer::Map(_) => Err(MapError(
+            MapDeserializer::Map(_) => Err(MapError::new(
                 "must be applied to a flattened struct rather than a raw type"
                     .to_string(),
             )),
@@ -79,11 +91,21 @@ where
 }
 
 #[derive(Clone, Debug)]
-pub(crate) struct MapError(pub String);
+pub(crate) struct MapError {
+    pub message: String,
+    /// key of the map entry whose value couldn't be deserialized, if any
+    pub key: Option<String>,
+}
+
+impl MapError {
+    pub fn new(message: String) -> MapError {
+        MapError { message, key: None }
+    }
+}
 
 impl Display for MapError {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        f.write_str(self.0.as_str())
+        f.write_str(self.message.as_str())
     }
 }
 
@@ -92,7 +114,7 @@ impl serde::de::Error for MapError {
     where
         T: std::fmt::Display,
     {
-        MapError(format!("{}", msg))
+        MapError::new(format!("{}", msg))
     }
 }
 
@@ -126,7 +148,7 @@ macro_rules! de_value {
             {
                 self.value(|raw_value| match raw_value.as_value()?.parse::<$i>() {
                     Ok(value) => visitor.[<visit_ $i>](value),
-                    Err(_) => Err(MapError(format!(
+                    Err(_) => Err(MapError::new(format!(
                         "unable to parse '{}' as {}",
                         raw_value.as_value()?,
                         type_name::<$i>()
@@ -199,10 +221,10 @@ where
             MapDeserializer::Map(map) => {
                 let xx = map.clone();
                 let x = Box::new(xx.into_iter());
-                let m = MapMapAccess::<Z> { iter: x, value: None };
+                let m = MapMapAccess::<Z> { iter: x, key: None, value: None };
                 visitor.visit_map(m)
             }
-            MapDeserializer::Value(_) => Err(MapError(
+            MapDeserializer::Value(_) => Err(MapError::new(
                 "destination struct must be fully flattened".to_string(),
             )),
         }
@@ -357,6 +379,8 @@ where
 struct MapMapAccess<Z> {
     /// Iterator through the Map
     iter: Box<dyn Iterator<Item = (String, Z)>>,
+    /// Key of the pending value
+    key: Option<String>,
     /// Pending value in a key-value pair
     value: Option<Z>,
 }
@@ -377,6 +401,7 @@ where
         match self.iter.next() {
             Some((key, value)) => {
                 // Save the value for later.
+                self.key.replace(key.clone());
                 self.value.replace(value);
                 // Create a Deserializer for that single value.
                 let mut deserializer = MapDeserializer::Value(key);
@@ -391,8 +416,10 @@ where
     {
         match self.value.take() {
             Some(value) => {
+                let key = self.key.take();
                 let mut deserializer = MapDeserializer::Value(value);
                 seed.deserialize(&mut deserializer)
+                    .map_err(|e| MapError { key: e.key.or(key), ..e })
             }
             // This means we were called without a corresponding call to
             // next_key_seed() which should not be possible.
@@ -0,0 +1,287 @@
+// Copyright 2023 Oxide Computer Company
+//! Serving a GraphQL API alongside a REST API
+//!
+//! With the `graphql` feature enabled,
+//! [`ApiDescription::register_graphql()`] mounts an [`async_graphql`] schema
+//! at a given path (e.g., `"/graphql"`).  This registers three endpoints:
+//!
+//! * `POST /graphql` executes a query (or a batch of queries) in the JSON body
+//!   of the request.
+//! * `GET /graphql` executes a query given by the query string (`query`,
+//!   `operationName`, and `variables`).
+//! * `GET /graphql/ws` accepts websocket connections for subscriptions, using
+//!   either the `graphql-transport-ws` or the older `graphql-ws` protocol, as
+//!   negotiated with `Sec-WebSocket-Protocol`.
+//!
+//! ```
+//! use async_graphql::EmptyMutation;
+//! use async_graphql::EmptySubscription;
+//! use async_graphql::Object;
+//! use async_graphql::Schema;
+//! use dropshot::ApiDescription;
+//!
+//! struct Query;
+//!
+//! #[Object]
+//! impl Query {
+//!     async fn answer(&self) -> u32 {
+//!         42
+//!     }
+//! }
+//!
+//! let schema = Schema::new(Query, EmptyMutation, EmptySubscription);
+//! let mut api = ApiDescription::<()>::new();
+//! api.register_graphql("/graphql", schema).unwrap();
+//! ```
+//!
+//! These endpoints are not included in the OpenAPI document.
+
+use crate::api_description::ApiDescription;
+use crate::api_description::ApiEndpoint;
+use crate::error::HttpError;
+use crate::extractor::UntypedBody;
+use crate::handler::RequestContext;
+use crate::http_util::CONTENT_TYPE_JSON;
+use crate::server::ServerContext;
+use crate::websocket::WebsocketConnection;
+use crate::websocket::WebsocketUpgrade;
+use crate::Body;
+
+use async_graphql::http::WebSocket;
+use async_graphql::http::WebSocketProtocols;
+use async_graphql::http::WsMessage;
+use async_graphql::BatchRequest;
+use async_graphql::ObjectType;
+use async_graphql::Schema;
+use async_graphql::SubscriptionType;
+use futures::SinkExt;
+use futures::StreamExt;
+use http::header;
+use http::HeaderValue;
+use http::Method;
+use http::StatusCode;
+use hyper::Response;
+use serde::Serialize;
+use std::str::FromStr;
+use tokio_tungstenite::tungstenite::protocol::frame::coding::CloseCode;
+use tokio_tungstenite::tungstenite::protocol::CloseFrame;
+use tokio_tungstenite::tungstenite::protocol::Role;
+use tokio_tungstenite::tungstenite::Message;
+use tokio_tungstenite::WebSocketStream;
+
+impl<Context: ServerContext> ApiDescription<Context> {
+    /// Registers endpoints that serve the GraphQL schema `schema` at `path`
+    /// (and subscriptions at `path` + `/ws`).  See the [`crate::graphql`]
+    /// module-level documentation for details.
+    pub fn register_graphql<Query, Mutation, Subscription>(
+        &mut self,
+        path: &str,
+        schema: Schema<Query, Mutation, Subscription>,
+    ) -> Result<(), String>
+    where
+        Query: ObjectType + 'static,
+        Mutation: ObjectType + 'static,
+        Subscription: SubscriptionType + 'static,
+    {
+        let post_schema = schema.clone();
+        self.register(
+            ApiEndpoint::new(
+                "graphql_post".to_string(),
+                move |_rqctx: RequestContext<Context>, body: UntypedBody| {
+                    let schema = post_schema.clone();
+                    async move {
+                        let batch: BatchRequest = serde_json::from_slice(
+                            body.as_bytes(),
+                        )
+                        .map_err(|e| {
+                            HttpError::for_bad_request(
+                                None,
+                                format!("invalid GraphQL request: {}", e),
+                            )
+                        })?;
+                        json_response(&schema.execute_batch(batch).await)
+                    }
+                },
+                Method::POST,
+                CONTENT_TYPE_JSON,
+                path,
+            )
+            .visible(false),
+        )?;
+
+        let get_schema = schema.clone();
+        self.register(
+            ApiEndpoint::new(
+                "graphql_get".to_string(),
+                move |rqctx: RequestContext<Context>| {
+                    let schema = get_schema.clone();
+                    async move {
+                        let query = rqctx.request.uri().query().unwrap_or("");
+                        let request =
+                            async_graphql::http::parse_query_string(query)
+                                .map_err(|e| {
+                                    HttpError::for_bad_request(
+                                        None,
+                                        format!(
+                                            "invalid GraphQL request: {}",
+                                            e
+                                        ),
+                                    )
+                                })?;
+                        json_response(&schema.execute(request).await)
+                    }
+                },
+                Method::GET,
+                CONTENT_TYPE_JSON,
+                path,
+            )
+            .visible(false),
+        )?;
+
+        let ws_path = format!("{}/ws", path.trim_end_matches('/'));
+        self.register(
+            ApiEndpoint::new(
+                "graphql_ws".to_string(),
+                move |rqctx: RequestContext<Context>,
+                      upgrade: WebsocketUpgrade| {
+                    let schema = schema.clone();
+                    async move {
+                        let protocol =
+                            negotiate_protocol(rqctx.request.headers())
+                                .ok_or_else(|| {
+                                    HttpError::for_bad_request(
+                                        None,
+                                        String::from(
+                                            "missing or unsupported GraphQL \
+                                     websocket subprotocol",
+                                        ),
+                                    )
+                                })?;
+                        // The server must echo the subprotocol it selected.
+                        let protocol_header = HeaderValue::from_static(
+                            protocol.sec_websocket_protocol(),
+                        );
+                        let mut response = upgrade.handle(move |conn| {
+                            serve_subscriptions(schema, protocol, conn)
+                        })?;
+                        response.headers_mut().insert(
+                            header::SEC_WEBSOCKET_PROTOCOL,
+                            protocol_header,
+                        );
+                        Ok(response)
+                    }
+                },
+                Method::GET,
+                CONTENT_TYPE_JSON,
+                &ws_path,
+            )
+            .visible(false),
+        )
+    }
+}
+
+fn json_response<T: Serialize>(body: &T) -> Result<Response<Body>, HttpError> {
+    let body = serde_json::to_string(body)
+        .map_err(|e| HttpError::for_internal_error(e.to_string()))?;
+    Ok(Response::builder()
+        .status(StatusCode::OK)
+        .header(header::CONTENT_TYPE, CONTENT_TYPE_JSON)
+        .body(body.into())?)
+}
+
+/// Picks the first GraphQL websocket protocol requested by the client that we
+/// support.
+fn negotiate_protocol(headers: &http::HeaderMap) -> Option<WebSocketProtocols> {
+    headers
+        .get_all(header::SEC_WEBSOCKET_PROTOCOL)
+        .iter()
+        .filter_map(|v| v.to_str().ok())
+        .flat_map(|v| v.split(','))
+        .find_map(|p| WebSocketProtocols::from_str(p.trim()).ok())
+}
+
+async fn serve_subscriptions<Query, Mutation, Subscription>(
+    schema: Schema<Query, Mutation, Subscription>,
+    protocol: WebSocketProtocols,
+    conn: WebsocketConnection,
+) -> crate::WebsocketChannelResult
+where
+    Query: ObjectType + 'static,
+    Mutation: ObjectType + 'static,
+    Subscription: SubscriptionType + 'static,
+{
+    let ws_stream =
+        WebSocketStream::from_raw_socket(conn.into_inner(), Role::Server, None)
+            .await;
+    let (mut sink, stream) = ws_stream.split();
+
+    // Feed text and binary messages from the client to async-graphql until the
+    // client closes the connection (or it fails).
+    let input = stream
+        .take_while(|message| {
+            futures::future::ready(matches!(
+                message,
+                Ok(m) if !m.is_close()
+            ))
+        })
+        .filter_map(|message| {
+            futures::future::ready(match message {
+                Ok(Message::Text(text)) => Some(text.into_bytes()),
+                Ok(Message::Binary(bytes)) => Some(bytes),
+                _ => None,
+            })
+        });
+
+    let mut output = WebSocket::new(schema, input, protocol);
+    while let Some(message) = output.next().await {
+        let message = match message {
+            WsMessage::Text(text) => Message::Text(text),
+            WsMessage::Close(code, reason) => {
+                Message::Close(Some(CloseFrame {
+                    code: CloseCode::from(code),
+                    reason: reason.into(),
+                }))
+            }
+        };
+        sink.send(message).await?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::negotiate_protocol;
+    use async_graphql::http::WebSocketProtocols;
+    use http::HeaderMap;
+
+    #[test]
+    fn test_negotiate_protocol() {
+        let mut headers = HeaderMap::new();
+        assert!(negotiate_protocol(&headers).is_none());
+
+        headers.insert(
+            http::header::SEC_WEBSOCKET_PROTOCOL,
+            "chat, graphql-transport-ws, graphql-ws".parse().unwrap(),
+        );
+        assert!(matches!(
+            negotiate_protocol(&headers),
+            Some(WebSocketProtocols::GraphQLWS)
+        ));
+
+        headers.insert(
+            http::header::SEC_WEBSOCKET_PROTOCOL,
+            "graphql-ws".parse().unwrap(),
+        );
+        assert!(matches!(
+            negotiate_protocol(&headers),
+            Some(WebSocketProtocols::SubscriptionsTransportWS)
+        ));
+
+        headers.insert(
+            http::header::SEC_WEBSOCKET_PROTOCOL,
+            "chat".parse().unwrap(),
+        );
+        assert!(negotiate_protocol(&headers).is_none());
+    }
+}
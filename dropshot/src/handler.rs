@@ -31,8 +31,17 @@
 //! facilities don't seem that valuable right now since they largely don't affect
 //! OpenAPI document generation.
 
+use super::connection::ConnectionInfo;
+use super::disconnect::DisconnectSignal;
+use super::early_hints::EarlyHints;
 use super::error::HttpError;
+use super::error::HttpResponseError;
+use super::extensions::Extensions;
+use super::extractor::Accept;
 use super::extractor::RequestExtractor;
+use super::extractor::SchemaValidators;
+use super::forwarded::EffectiveClient;
+use super::http_util::CONTENT_TYPE_CBOR;
 use super::http_util::CONTENT_TYPE_JSON;
 use super::http_util::CONTENT_TYPE_OCTET_STREAM;
 use super::server::DropshotState;
@@ -41,22 +50,28 @@ use crate::api_description::ApiEndpointBodyContentType;
 use crate::api_description::ApiEndpointHeader;
 use crate::api_description::ApiEndpointResponse;
 use crate::api_description::ApiSchemaGenerator;
+use crate::extractor::header_name;
+#[cfg(feature = "pagination")]
 use crate::pagination::PaginationParams;
 use crate::router::VariableSet;
 use crate::schema_util::make_subschema_for;
 use crate::schema_util::schema2struct;
 use crate::schema_util::ReferenceVisitor;
+use crate::slow_request::RequestPhase;
+use crate::slow_request::RequestTimings;
 use crate::to_map::to_map;
+use crate::Body;
 
 use async_trait::async_trait;
 use http::HeaderMap;
 use http::StatusCode;
-use hyper::Body;
 use hyper::Response;
 use schemars::JsonSchema;
+#[cfg(feature = "pagination")]
 use serde::de::DeserializeOwned;
 use serde::Serialize;
 use slog::Logger;
+#[cfg(feature = "pagination")]
 use std::cmp::min;
 use std::convert::TryFrom;
 use std::fmt::Debug;
@@ -64,6 +79,7 @@ use std::fmt::Formatter;
 use std::fmt::Result as FmtResult;
 use std::future::Future;
 use std::marker::PhantomData;
+#[cfg(feature = "pagination")]
 use std::num::NonZeroU32;
 use std::sync::Arc;
 
@@ -79,6 +95,8 @@ pub struct RequestContext<Context: ServerContext> {
     pub path_variables: VariableSet,
     /// expected request body mime type
     pub body_content_type: ApiEndpointBodyContentType,
+    /// other accepted request body mime types, if any
+    pub additional_body_content_types: Vec<ApiEndpointBodyContentType>,
     /// unique id assigned to this request
     pub request_id: String,
     /// logger for this specific request
@@ -86,11 +104,35 @@ pub struct RequestContext<Context: ServerContext> {
 
     /// basic request information (method, URI, etc.)
     pub request: RequestInfo,
+
+    /// the connection on which the request arrived
+    pub(crate) connection: Arc<ConnectionInfo>,
+
+    /// the client on whose behalf the request was made
+    pub(crate) client: EffectiveClient,
+
+    /// when the request entered each phase of its handling
+    pub(crate) timings: Arc<RequestTimings>,
+
+    /// values attached to the request before it's handled
+    pub(crate) extensions: Arc<Extensions>,
+
+    /// tenant on whose behalf the request is made, if any
+    pub(crate) tenant: Option<String>,
+
+    /// indicates whether the client has disconnected
+    pub(crate) disconnect: DisconnectSignal,
+    /// sends early hints in response to the request, if that's possible
+    pub(crate) early_hints: Option<EarlyHints>,
+    /// maximum allowed size of the request body
+    pub(crate) request_body_max_bytes: usize,
+    /// validators for the types that the endpoint's extractors deserialize
+    pub(crate) schema_validators: Arc<SchemaValidators>,
 }
 
 // This is deliberately as close to compatible with `hyper::Request` as
 // reasonable.
-#[derive(Debug)]
+#[derive(Clone, Debug)]
 pub struct RequestInfo {
     method: http::Method,
     uri: http::Uri,
@@ -171,12 +213,108 @@ impl<Context: ServerContext> RequestContext<Context> {
         &self.server.private
     }
 
+    /// Returns information about the connection on which the request arrived:
+    /// the addresses of its ends and whether (and how) it uses TLS.
+    pub fn connection(&self) -> &ConnectionInfo {
+        &self.connection
+    }
+
+    /// Returns the client on whose behalf the request was made: the peer on
+    /// the other end of the connection or, if that's one of the server's
+    /// trusted proxies (see `ConfigDropshot::trusted_proxies`), the client
+    /// that the proxies report.
+    pub fn client(&self) -> &EffectiveClient {
+        &self.client
+    }
+
+    /// Returns the values attached to the request (e.g., by a
+    /// [`crate::ContinueCheck`]), which can also be extracted with
+    /// [`crate::Extension`].
+    pub fn extensions(&self) -> &Extensions {
+        &self.extensions
+    }
+
+    /// Returns the tenant on whose behalf the request is made, as identified
+    /// by the server's [`crate::TenantResolver`] (if it has one).
+    pub fn tenant(&self) -> Option<&str> {
+        self.tenant.as_deref()
+    }
+
+    /// Returns the maximum allowed size of the request body: the endpoint's
+    /// own limit, if it has one, or else the server's `request_body_max_bytes`.
+    pub fn request_body_max_bytes(&self) -> usize {
+        self.request_body_max_bytes
+    }
+
+    /// Returns whether the client closed the connection (or, for HTTP/2,
+    /// reset the stream) before the response was sent.  No response can be
+    /// delivered once this happens, so long-running handlers may want to check
+    /// this periodically and stop early.
+    pub fn is_disconnected(&self) -> bool {
+        self.disconnect.is_disconnected()
+    }
+
+    /// Completes when the client closes the connection (or, for HTTP/2,
+    /// resets the stream) before the response is sent.  If the response is
+    /// sent first, this never completes.  Handlers can race this against
+    /// their work, e.g., with `tokio::select!`, to stop as soon as the client
+    /// goes away.
+    ///
+    /// See also [`crate::HandlerTaskMode`], which can cancel handlers
+    /// automatically.
+    pub async fn cancelled(&self) {
+        self.disconnect.disconnected().await
+    }
+
+    /// Sends a `103 Early Hints` informational response with the given
+    /// headers (typically `Link` headers naming resources that the client can
+    /// start loading) ahead of the final response.  This may be called more
+    /// than once.
+    ///
+    /// Returns false, without sending anything, if the request isn't an
+    /// HTTP/1.1 request (HTTP/1.0 clients don't expect informational
+    /// responses, and hyper can't send them over HTTP/2) or if the handler has
+    /// already produced its response.  Hints are best-effort: they may still be
+    /// dropped if the response becomes ready before they can be written.
+    pub fn send_early_hints(&self, headers: &http::HeaderMap) -> bool {
+        self.early_hints
+            .as_ref()
+            .map_or(false, |early_hints| early_hints.send(headers))
+    }
+
+    /// Returns a copy of this context that outlives the handler's own (which
+    /// the handler consumes), sharing its extensions but unable to send early
+    /// hints.
+    pub(crate) fn detached(&self) -> RequestContext<Context> {
+        RequestContext {
+            server: Arc::clone(&self.server),
+            path_variables: self.path_variables.clone(),
+            body_content_type: self.body_content_type.clone(),
+            additional_body_content_types: self
+                .additional_body_content_types
+                .clone(),
+            request_id: self.request_id.clone(),
+            log: self.log.clone(),
+            request: self.request.clone(),
+            connection: Arc::clone(&self.connection),
+            client: self.client.clone(),
+            timings: Arc::clone(&self.timings),
+            extensions: Arc::clone(&self.extensions),
+            tenant: self.tenant.clone(),
+            disconnect: self.disconnect.clone(),
+            early_hints: None,
+            request_body_max_bytes: self.request_body_max_bytes,
+            schema_validators: Arc::clone(&self.schema_validators),
+        }
+    }
+
     /// Returns the appropriate count of items to return for a paginated request
     ///
     /// This first looks at any client-requested limit and clamps it based on the
     /// server-configured maximum page size.  If the client did not request any
     /// particular limit, this function returns the server-configured default
     /// page size.
+    #[cfg(feature = "pagination")]
     pub fn page_limit<ScanParams, PageSelector>(
         &self,
         pag_params: &PaginationParams<ScanParams, PageSelector>,
@@ -196,6 +334,18 @@ impl<Context: ServerContext> RequestContext<Context> {
             // default.
             .unwrap_or(server_config.page_default_nitems))
     }
+
+    /// Returns a copy of `body` suitable for logging, with the values of any
+    /// fields configured in `ConfigDropshot::log_redaction` masked (at any
+    /// depth).
+    pub fn redact_for_log(
+        &self,
+        body: &serde_json::Value,
+    ) -> serde_json::Value {
+        let mut redacted = body.clone();
+        self.server.log_redactor.redact_json(&mut redacted);
+        redacted
+    }
 }
 
 /// Helper trait for extracting the underlying Context type from the
@@ -236,6 +386,9 @@ where
     FuncParams: RequestExtractor,
     ResponseType: HttpResponse + Send + Sync + 'static,
 {
+    /// type of the error with which the handler function may fail
+    type Error: HttpResponseError;
+
     async fn handle_request(
         &self,
         rqctx: RequestContext<Context>,
@@ -284,7 +437,9 @@ where
 // functions to return a variety of different return types that are ultimately
 // converted into `Result<Response<Body>, HttpError>`.  To do that, the trait
 // bounds below say that the function must produce a `Result<ResponseType,
-// HttpError>` where `ResponseType` is a type that implements `HttpResponse`.
+// ErrorType>` where `ResponseType` is a type that implements `HttpResponse`
+// and `ErrorType` is a type that implements `HttpResponseError` (usually
+// `HttpError` itself).
 // We provide a few implementations of the trait `HttpTypedResponse` that
 // includes a HTTP status code and structured output. In addition we allow for
 // functions to hand-craft a `Response<Body>`. For both we implement
@@ -294,9 +449,10 @@ where
 //            |
 //            | returns:
 //            v
-//      2. Result<ResponseType, HttpError>
+//      2. Result<ResponseType, ErrorType>
 //            |
-//            | This may fail with an HttpError which we return immediately.
+//            | This may fail with an ErrorType which we convert into an
+//            | HttpError and return immediately.
 //            | On success, this will be Ok(ResponseType) for some specific
 //            | ResponseType that implements HttpResponse.  We'll end up
 //            | invoking:
@@ -325,26 +481,32 @@ macro_rules! impl_HttpHandlerFunc_for_func_with_params {
     ($(($i:tt, $T:tt)),*) => {
 
     #[async_trait]
-    impl<Context, FuncType, FutureType, ResponseType, $($T,)*>
+    impl<Context, FuncType, FutureType, ResponseType, ErrorType, $($T,)*>
         HttpHandlerFunc<Context, ($($T,)*), ResponseType> for FuncType
     where
         Context: ServerContext,
         FuncType: Fn(RequestContext<Context>, $($T,)*)
             -> FutureType + Send + Sync + 'static,
-        FutureType: Future<Output = Result<ResponseType, HttpError>>
+        FutureType: Future<Output = Result<ResponseType, ErrorType>>
             + Send + 'static,
         ResponseType: HttpResponse + Send + Sync + 'static,
+        ErrorType: HttpResponseError + Send + 'static,
         ($($T,)*): RequestExtractor,
         $($T: Send + Sync + 'static,)*
     {
+        type Error = ErrorType;
+
         async fn handle_request(
             &self,
             rqctx: RequestContext<Context>,
             _param_tuple: ($($T,)*)
         ) -> HttpHandlerResult
         {
-            let response: ResponseType =
-                (self)(rqctx, $(_param_tuple.$i,)*).await?;
+            let timings = Arc::clone(&rqctx.timings);
+            let response: ResponseType = (self)(rqctx, $(_param_tuple.$i,)*)
+                .await
+                .map_err(Into::<HttpError>::into)?;
+            timings.mark(RequestPhase::Serialization);
             response.to_result()
         }
     }
@@ -371,7 +533,7 @@ pub trait RouteHandler<Context: ServerContext>: Debug + Send + Sync {
     async fn handle_request(
         &self,
         rqctx: RequestContext<Context>,
-        request: hyper::Request<hyper::Body>,
+        request: hyper::Request<crate::Body>,
     ) -> HttpHandlerResult;
 }
 
@@ -434,7 +596,7 @@ where
     async fn handle_request(
         &self,
         rqctx: RequestContext<Context>,
-        request: hyper::Request<hyper::Body>,
+        request: hyper::Request<crate::Body>,
     ) -> HttpHandlerResult {
         // This is where the magic happens: in the code below, `funcparams` has
         // type `FuncParams`, which is a tuple type describing the extractor
@@ -452,8 +614,10 @@ where
         // is resolved statically.makes them actual function arguments for the
         // actual handler function.  From this point down, all of this is
         // resolved statically.
+        rqctx.timings.mark(RequestPhase::Extraction);
         let funcparams =
             RequestExtractor::from_request(&rqctx, request).await?;
+        rqctx.timings.mark(RequestPhase::Handler);
         let future = self.handler.handle_request(rqctx, funcparams);
         future.await
     }
@@ -472,7 +636,7 @@ where
     /// Given a function matching one of the supported API handler function
     /// signatures, return a RouteHandler that can be used to respond to HTTP
     /// requests using this function.
-    pub fn new(handler: HandlerType) -> Box<dyn RouteHandler<Context>> {
+    pub fn new(handler: HandlerType) -> Arc<dyn RouteHandler<Context>> {
         HttpRouteHandler::new_with_name(handler, "<unlabeled handler>")
     }
 
@@ -482,8 +646,8 @@ where
     pub fn new_with_name(
         handler: HandlerType,
         label: &str,
-    ) -> Box<dyn RouteHandler<Context>> {
-        Box::new(HttpRouteHandler {
+    ) -> Arc<dyn RouteHandler<Context>> {
+        Arc::new(HttpRouteHandler {
             label: label.to_string(),
             handler,
             phantom: PhantomData,
@@ -519,7 +683,7 @@ impl HttpResponse for Response<Body> {
     }
 }
 
-/// Wraps a [hyper::Body] so that it can be used with coded response types such
+/// Wraps a [Body] so that it can be used with coded response types such
 /// as [HttpResponseOk].
 pub struct FreeformBody(pub Body);
 
@@ -529,6 +693,55 @@ impl From<Body> for FreeformBody {
     }
 }
 
+/// Wraps a serializable object so that it's sent as CBOR (`application/cbor`)
+/// rather than JSON when used with coded response types such as
+/// [HttpResponseOk].
+pub struct Cbor<T>(pub T);
+
+/// Wraps a serializable object so that it's sent as JSON or as CBOR
+/// (`application/cbor`), whichever the client prefers according to its
+/// `Accept` header.  Both are described in the OpenAPI document.  JSON is sent
+/// if the client finds both equally acceptable.
+///
+/// ```
+/// use dropshot::endpoint;
+/// use dropshot::Accept;
+/// use dropshot::HttpError;
+/// use dropshot::HttpResponseOk;
+/// use dropshot::Negotiated;
+/// use dropshot::RequestContext;
+///
+/// #[endpoint {
+///     method = GET,
+///     path = "/counter",
+/// }]
+/// async fn counter_get(
+///     _rqctx: RequestContext<()>,
+///     accept: Accept,
+/// ) -> Result<HttpResponseOk<Negotiated<u64>>, HttpError> {
+///     Ok(HttpResponseOk(Negotiated::new(&accept, 5)?))
+/// }
+/// ```
+pub struct Negotiated<T> {
+    value: T,
+    content_type: &'static str,
+}
+
+impl<T> Negotiated<T> {
+    /// Chooses how to send `value` according to `accept`, failing with a 406
+    /// ("Not Acceptable") error if the client accepts neither JSON nor CBOR.
+    pub fn new(accept: &Accept, value: T) -> Result<Negotiated<T>, HttpError> {
+        let content_type =
+            accept.negotiate(&[CONTENT_TYPE_JSON, CONTENT_TYPE_CBOR])?;
+        Ok(Negotiated { value, content_type })
+    }
+
+    /// Returns the media type in which the value will be sent.
+    pub fn content_type(&self) -> &'static str {
+        self.content_type
+    }
+}
+
 /// An "empty" type used to represent responses that have no associated data
 /// payload. This isn't intended for general use, but must be pub since it's
 /// used as the Body type for certain responses.
@@ -555,6 +768,19 @@ pub trait HttpResponseContent {
     // with multiple, explicitly enumerated mime types.
     // TODO the ApiSchemaGenerator type is particularly inelegant.
     fn content_metadata() -> Option<ApiSchemaGenerator>;
+
+    /// Returns the media type of the content, as described in the OpenAPI
+    /// document.
+    fn content_type() -> &'static str {
+        CONTENT_TYPE_JSON
+    }
+
+    /// Returns the other media types in which the content may be sent (e.g.,
+    /// depending on the request's `Accept` header), as described in the
+    /// OpenAPI document.
+    fn additional_content_types() -> Vec<&'static str> {
+        Vec::new()
+    }
 }
 
 impl HttpResponseContent for FreeformBody {
@@ -611,6 +837,58 @@ where
     }
 }
 
+impl<T> HttpResponseContent for Cbor<T>
+where
+    T: JsonSchema + Serialize + Send + Sync + 'static,
+{
+    fn to_response(
+        self,
+        builder: http::response::Builder,
+    ) -> HttpHandlerResult {
+        let mut serialized = Vec::new();
+        ciborium::ser::into_writer(&self.0, &mut serialized)
+            .map_err(|e| HttpError::for_internal_error(e.to_string()))?;
+        Ok(builder
+            .header(http::header::CONTENT_TYPE, CONTENT_TYPE_CBOR)
+            .body(serialized.into())?)
+    }
+
+    fn content_metadata() -> Option<ApiSchemaGenerator> {
+        T::content_metadata()
+    }
+
+    fn content_type() -> &'static str {
+        CONTENT_TYPE_CBOR
+    }
+}
+
+impl<T> HttpResponseContent for Negotiated<T>
+where
+    T: JsonSchema + Serialize + Send + Sync + 'static,
+{
+    fn to_response(
+        self,
+        builder: http::response::Builder,
+    ) -> HttpHandlerResult {
+        // The response depends on the request's `Accept` header, which caches
+        // need to know.
+        let builder = builder.header(http::header::VARY, "Accept");
+        if self.content_type == CONTENT_TYPE_CBOR {
+            Cbor(self.value).to_response(builder)
+        } else {
+            self.value.to_response(builder)
+        }
+    }
+
+    fn content_metadata() -> Option<ApiSchemaGenerator> {
+        T::content_metadata()
+    }
+
+    fn additional_content_types() -> Vec<&'static str> {
+        vec![CONTENT_TYPE_CBOR]
+    }
+}
+
 /// The `HttpCodedResponse` trait is used for all of the specific response types
 /// that we provide. We use it in particular to encode the success status code
 /// and the type information of the return value.
@@ -641,6 +919,11 @@ where
     fn response_metadata() -> ApiEndpointResponse {
         ApiEndpointResponse {
             schema: T::Body::content_metadata(),
+            content_type: Some(T::Body::content_type().to_string()),
+            additional_content_types: T::Body::additional_content_types()
+                .into_iter()
+                .map(String::from)
+                .collect(),
             success: Some(T::STATUS_CODE),
             description: Some(T::DESCRIPTION.to_string()),
             ..Default::default()
@@ -746,6 +1029,112 @@ impl From<HttpResponseUpdatedNoContent> for HttpHandlerResult {
     }
 }
 
+/// The set of status codes with which an [`HttpResponseStatus`] may respond.
+///
+/// Implement this for a type of your own (typically an empty struct or enum)
+/// that names the set.
+pub trait ResponseStatusSet: Send + Sync + 'static {
+    /// The status codes in the set, each with the description of its response
+    /// in the OpenAPI document.  The first is the endpoint's primary response.
+    const STATUSES: &'static [(StatusCode, &'static str)];
+}
+
+/// `HttpResponseStatus<S, T>` is a response whose status code is chosen by the
+/// handler at runtime, from the set of status codes declared by `S`, and whose
+/// body is generated from `T` (as for, e.g., [`HttpResponseOk<T>`]).  The
+/// OpenAPI document describes a response with the body of `T` for each status
+/// code in the set (without a body for 204 and 304).
+///
+/// ```
+/// use dropshot::endpoint;
+/// use dropshot::HttpError;
+/// use dropshot::HttpResponseStatus;
+/// use dropshot::RequestContext;
+/// use dropshot::ResponseStatusSet;
+/// use http::StatusCode;
+///
+/// /// A widget that's ready (200) or still being built (202)
+/// struct WidgetStatuses;
+/// impl ResponseStatusSet for WidgetStatuses {
+///     const STATUSES: &'static [(StatusCode, &'static str)] = &[
+///         (StatusCode::OK, "widget is ready"),
+///         (StatusCode::ACCEPTED, "widget is being built"),
+///     ];
+/// }
+///
+/// #[endpoint {
+///     method = GET,
+///     path = "/widget",
+/// }]
+/// async fn widget_get(
+///     _rqctx: RequestContext<()>,
+/// ) -> Result<HttpResponseStatus<WidgetStatuses, String>, HttpError> {
+///     let ready = false; // e.g., from the database
+///     let status = if ready { StatusCode::OK } else { StatusCode::ACCEPTED };
+///     HttpResponseStatus::new(status, String::from("sprocket"))
+/// }
+/// ```
+pub struct HttpResponseStatus<
+    S: ResponseStatusSet,
+    T: HttpResponseContent + Send + Sync + 'static,
+> {
+    status: StatusCode,
+    body: T,
+    statuses: PhantomData<S>,
+}
+
+impl<S: ResponseStatusSet, T: HttpResponseContent + Send + Sync + 'static>
+    HttpResponseStatus<S, T>
+{
+    /// Returns a response with status code `status` and a body generated from
+    /// `body`.  Fails with a 500 error if `status` isn't in the set `S`, since
+    /// the response wouldn't match the OpenAPI document.
+    pub fn new(status: StatusCode, body: T) -> Result<Self, HttpError> {
+        if !S::STATUSES.iter().any(|(code, _)| *code == status) {
+            return Err(HttpError::for_internal_error(format!(
+                "status code {} is not among the endpoint's declared statuses",
+                status.as_u16()
+            )));
+        }
+        Ok(HttpResponseStatus { status, body, statuses: PhantomData })
+    }
+
+    /// Returns the response's status code.
+    pub fn status(&self) -> StatusCode {
+        self.status
+    }
+}
+
+impl<S: ResponseStatusSet, T: HttpResponseContent + Send + Sync + 'static>
+    HttpResponse for HttpResponseStatus<S, T>
+{
+    fn to_result(self) -> HttpHandlerResult {
+        self.body.to_response(Response::builder().status(self.status))
+    }
+
+    fn response_metadata() -> ApiEndpointResponse {
+        let (primary, others) = match S::STATUSES.split_first() {
+            Some(split) => split,
+            None => panic!("ResponseStatusSet must have at least one status"),
+        };
+        ApiEndpointResponse {
+            schema: T::content_metadata(),
+            content_type: Some(T::content_type().to_string()),
+            additional_content_types: T::additional_content_types()
+                .into_iter()
+                .map(String::from)
+                .collect(),
+            success: Some(primary.0),
+            description: Some(primary.1.to_string()),
+            alternate_statuses: others
+                .iter()
+                .map(|(code, description)| (*code, description.to_string()))
+                .collect(),
+            ..Default::default()
+        }
+    }
+}
+
 /// Describes headers associated with a 300-level response.
 #[derive(JsonSchema, Serialize)]
 #[doc(hidden)]
@@ -909,6 +1298,49 @@ pub struct NoHeaders {}
 /// named headers, consumers may add additional headers via the `headers_mut`
 /// interface. Unnamed headers override named headers in the case of naming
 /// conflicts.
+///
+/// Each field of the struct of named headers is a header, named (as for the
+/// [`Header`](crate::Header) extractor) by the field's serde name with
+/// underscores replaced by hyphens.  A field's value may be a string, a
+/// boolean, a number, a unit variant of an enum, or a newtype around one of
+/// these; an `Option` field that's `None` omits its header.  The headers
+/// appear in the OpenAPI document under the response's `headers`, with their
+/// fields' schemas and doc comments.
+///
+/// ```
+/// use dropshot::endpoint;
+/// use dropshot::HttpError;
+/// use dropshot::HttpResponseHeaders;
+/// use dropshot::HttpResponseOk;
+/// use dropshot::RequestContext;
+/// use schemars::JsonSchema;
+/// use serde::Serialize;
+///
+/// #[derive(Serialize, JsonSchema)]
+/// struct RateLimitHeaders {
+///     /// Requests remaining in the current window (header
+///     /// `x-ratelimit-remaining`)
+///     x_ratelimit_remaining: u32,
+///     /// Seconds until the client may retry, if it's been throttled
+///     retry_after: Option<u64>,
+/// }
+///
+/// #[endpoint {
+///     method = GET,
+///     path = "/widget",
+/// }]
+/// async fn widget_get(
+///     _rqctx: RequestContext<()>,
+/// ) -> Result<
+///     HttpResponseHeaders<HttpResponseOk<String>, RateLimitHeaders>,
+///     HttpError,
+/// > {
+///     Ok(HttpResponseHeaders::new(
+///         HttpResponseOk(String::from("sprocket")),
+///         RateLimitHeaders { x_ratelimit_remaining: 41, retry_after: None },
+///     ))
+/// }
+/// ```
 pub struct HttpResponseHeaders<
     T: HttpCodedResponse,
     H: JsonSchema + Serialize + Send + Sync + 'static = NoHeaders,
@@ -918,6 +1350,7 @@ pub struct HttpResponseHeaders<
     other_headers: HeaderMap,
 }
 impl<T: HttpCodedResponse> HttpResponseHeaders<T, NoHeaders> {
+    /// Returns a response with no named headers.
     pub fn new_unnamed(body: T) -> Self {
         Self {
             body,
@@ -931,6 +1364,7 @@ impl<
         H: JsonSchema + Serialize + Send + Sync + 'static,
     > HttpResponseHeaders<T, H>
 {
+    /// Returns a response with the named headers in `headers`.
     pub fn new(body: T, headers: H) -> Self {
         Self {
             body,
@@ -939,6 +1373,7 @@ impl<
         }
     }
 
+    /// Returns the unnamed headers, to which more can be added.
     pub fn headers_mut(&mut self) -> &mut HeaderMap {
         &mut self.other_headers
     }
@@ -963,7 +1398,7 @@ impl<
         })?;
 
         for (key, value) in header_map {
-            let key = http::header::HeaderName::try_from(key)
+            let key = http::header::HeaderName::try_from(header_name(&key))
                 .map_err(|e| HttpError::for_internal_error(e.to_string()))?;
             let value = http::header::HeaderValue::try_from(value)
                 .map_err(|e| HttpError::for_internal_error(e.to_string()))?;
@@ -990,7 +1425,7 @@ impl<
                 let mut visitor = ReferenceVisitor::new(&generator);
                 schemars::visit::visit_schema(&mut visitor, &mut s);
                 ApiEndpointHeader {
-                    name: struct_member.name,
+                    name: header_name(&struct_member.name),
                     description: struct_member.description,
                     schema: ApiSchemaGenerator::Static {
                         schema: Box::new(s),
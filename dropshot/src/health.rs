@@ -0,0 +1,311 @@
+// Copyright 2023 Oxide Computer Company
+//! Built-in liveness and readiness endpoints
+//!
+//! [`ApiDescription::register_health_endpoints()`] registers two endpoints that
+//! run the checks in a [`HealthChecks`]:
+//!
+//! * `GET /healthz` (liveness) runs the liveness checks.  This should only fail
+//!   if the process is wedged badly enough that it ought to be restarted.
+//! * `GET /readyz` (readiness) runs the readiness checks.  This fails if the
+//!   server should not be sent new requests, e.g., because a dependency is
+//!   unavailable.  It also fails as soon as the server begins a graceful
+//!   shutdown (see [`HttpServer::close()`](crate::HttpServer::close)), so that
+//!   load balancers stop routing requests to it while it drains.
+//!
+//! Each endpoint runs its checks concurrently, giving each one its own timeout,
+//! and responds with 200 ("OK") if they all pass or 503 ("Service Unavailable")
+//! otherwise.  The body is a JSON [`HealthReport`] describing each check.
+//!
+//! ```
+//! use dropshot::ApiDescription;
+//! use dropshot::health::HealthChecks;
+//! use std::time::Duration;
+//!
+//! let checks = HealthChecks::new().readiness_check(
+//!     "database",
+//!     Duration::from_secs(1),
+//!     || async {
+//!         // ... ping the database ...
+//!         Ok(())
+//!     },
+//! );
+//! let mut api = ApiDescription::<()>::new();
+//! api.register_health_endpoints(checks).unwrap();
+//! ```
+//!
+//! These endpoints are not included in the OpenAPI document.
+
+use crate::api_description::ApiDescription;
+use crate::api_description::ApiEndpoint;
+use crate::error::HttpError;
+use crate::handler::RequestContext;
+use crate::http_util::CONTENT_TYPE_JSON;
+use crate::server::ServerContext;
+use crate::Body;
+
+use futures::future::BoxFuture;
+use futures::FutureExt;
+use http::Method;
+use http::StatusCode;
+use hyper::Response;
+use serde::Deserialize;
+use serde::Serialize;
+use std::fmt;
+use std::future::Future;
+use std::sync::Arc;
+use std::time::Duration;
+
+/// Path of the liveness endpoint
+pub const HEALTHZ_PATH: &str = "/healthz";
+/// Path of the readiness endpoint
+pub const READYZ_PATH: &str = "/readyz";
+
+type CheckFn =
+    dyn Fn() -> BoxFuture<'static, Result<(), String>> + Send + Sync + 'static;
+
+struct HealthCheck {
+    name: String,
+    timeout: Duration,
+    check: Arc<CheckFn>,
+}
+
+impl HealthCheck {
+    async fn run(&self) -> HealthCheckReport {
+        let result = tokio::time::timeout(self.timeout, (self.check)()).await;
+        let (status, message) = match result {
+            Ok(Ok(())) => (HealthStatus::Pass, None),
+            Ok(Err(message)) => (HealthStatus::Fail, Some(message)),
+            Err(_) => (
+                HealthStatus::Fail,
+                Some(format!("check timed out after {:?}", self.timeout)),
+            ),
+        };
+        HealthCheckReport { name: self.name.clone(), status, message }
+    }
+}
+
+/// Collection of checks run by the endpoints registered with
+/// [`ApiDescription::register_health_endpoints()`]
+///
+/// Each check is an async function that returns `Ok(())` if the check passed
+/// or an error message if it failed.  A check that doesn't complete within its
+/// timeout fails.
+#[derive(Default)]
+pub struct HealthChecks {
+    liveness: Vec<HealthCheck>,
+    readiness: Vec<HealthCheck>,
+}
+
+impl fmt::Debug for HealthChecks {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let names = |checks: &[HealthCheck]| {
+            checks.iter().map(|c| c.name.clone()).collect::<Vec<_>>()
+        };
+        f.debug_struct("HealthChecks")
+            .field("liveness", &names(&self.liveness))
+            .field("readiness", &names(&self.readiness))
+            .finish()
+    }
+}
+
+impl HealthChecks {
+    pub fn new() -> Self {
+        HealthChecks::default()
+    }
+
+    /// Adds a check run by the liveness endpoint (`/healthz`).
+    pub fn liveness_check<F, Fut>(
+        mut self,
+        name: &str,
+        timeout: Duration,
+        check: F,
+    ) -> Self
+    where
+        F: Fn() -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = Result<(), String>> + Send + 'static,
+    {
+        self.liveness.push(HealthCheck::new(name, timeout, check));
+        self
+    }
+
+    /// Adds a check run by the readiness endpoint (`/readyz`).
+    pub fn readiness_check<F, Fut>(
+        mut self,
+        name: &str,
+        timeout: Duration,
+        check: F,
+    ) -> Self
+    where
+        F: Fn() -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = Result<(), String>> + Send + 'static,
+    {
+        self.readiness.push(HealthCheck::new(name, timeout, check));
+        self
+    }
+}
+
+impl HealthCheck {
+    fn new<F, Fut>(name: &str, timeout: Duration, check: F) -> Self
+    where
+        F: Fn() -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = Result<(), String>> + Send + 'static,
+    {
+        HealthCheck {
+            name: name.to_string(),
+            timeout,
+            check: Arc::new(move || check().boxed()),
+        }
+    }
+}
+
+/// Outcome of a health check (or a set of them)
+#[derive(Clone, Copy, Debug, Deserialize, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum HealthStatus {
+    Pass,
+    Fail,
+}
+
+/// Body of the response from the liveness and readiness endpoints
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+pub struct HealthReport {
+    /// `pass` if the server is healthy and every check passed
+    pub status: HealthStatus,
+    /// Explains a failure that isn't attributable to a particular check
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub message: Option<String>,
+    /// Results of the individual checks, in the order they were added
+    pub checks: Vec<HealthCheckReport>,
+}
+
+/// Result of one health check
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+pub struct HealthCheckReport {
+    pub name: String,
+    pub status: HealthStatus,
+    /// Why the check failed
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub message: Option<String>,
+}
+
+async fn run_checks(checks: &[HealthCheck]) -> HealthReport {
+    let checks =
+        futures::future::join_all(checks.iter().map(HealthCheck::run)).await;
+    let status = if checks.iter().all(|c| c.status == HealthStatus::Pass) {
+        HealthStatus::Pass
+    } else {
+        HealthStatus::Fail
+    };
+    HealthReport { status, message: None, checks }
+}
+
+fn report_response(report: &HealthReport) -> Result<Response<Body>, HttpError> {
+    let status_code = match report.status {
+        HealthStatus::Pass => StatusCode::OK,
+        HealthStatus::Fail => StatusCode::SERVICE_UNAVAILABLE,
+    };
+    let body = serde_json::to_string(report)
+        .map_err(|e| HttpError::for_internal_error(e.to_string()))?;
+    Ok(Response::builder()
+        .status(status_code)
+        .header(http::header::CONTENT_TYPE, CONTENT_TYPE_JSON)
+        .body(body.into())?)
+}
+
+impl<Context: ServerContext> ApiDescription<Context> {
+    /// Registers the liveness (`/healthz`) and readiness (`/readyz`)
+    /// endpoints, which run the given checks.  See the [`crate::health`]
+    /// module-level documentation for details.
+    pub fn register_health_endpoints(
+        &mut self,
+        checks: HealthChecks,
+    ) -> Result<(), String> {
+        let liveness = Arc::new(checks.liveness);
+        let readiness = Arc::new(checks.readiness);
+
+        self.register(
+            ApiEndpoint::new(
+                "healthz".to_string(),
+                move |_rqctx: RequestContext<Context>| {
+                    let liveness = Arc::clone(&liveness);
+                    async move { report_response(&run_checks(&liveness).await) }
+                },
+                Method::GET,
+                CONTENT_TYPE_JSON,
+                HEALTHZ_PATH,
+            )
+            .visible(false),
+        )?;
+
+        self.register(
+            ApiEndpoint::new(
+                "readyz".to_string(),
+                move |rqctx: RequestContext<Context>| {
+                    let readiness = Arc::clone(&readiness);
+                    async move {
+                        let report = if rqctx.server.is_draining() {
+                            HealthReport {
+                                status: HealthStatus::Fail,
+                                message: Some(String::from(
+                                    "server is shutting down",
+                                )),
+                                checks: Vec::new(),
+                            }
+                        } else {
+                            run_checks(&readiness).await
+                        };
+                        report_response(&report)
+                    }
+                },
+                Method::GET,
+                CONTENT_TYPE_JSON,
+                READYZ_PATH,
+            )
+            .visible(false),
+        )
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::run_checks;
+    use super::HealthChecks;
+    use super::HealthStatus;
+    use std::time::Duration;
+
+    #[tokio::test]
+    async fn test_run_checks() {
+        let report = run_checks(&HealthChecks::new().liveness).await;
+        assert_eq!(report.status, HealthStatus::Pass);
+        assert!(report.checks.is_empty());
+
+        let checks = HealthChecks::new()
+            .readiness_check("ok", Duration::from_secs(10), || async { Ok(()) })
+            .readiness_check("broken", Duration::from_secs(10), || async {
+                Err(String::from("it's broken"))
+            })
+            .readiness_check("slow", Duration::from_millis(10), || async {
+                tokio::time::sleep(Duration::from_secs(60)).await;
+                Ok(())
+            });
+        let report = run_checks(&checks.readiness).await;
+        assert_eq!(report.status, HealthStatus::Fail);
+        let summary = report
+            .checks
+            .iter()
+            .map(|c| (c.name.as_str(), c.status, c.message.as_deref()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            summary,
+            vec![
+                ("ok", HealthStatus::Pass, None),
+                ("broken", HealthStatus::Fail, Some("it's broken")),
+                (
+                    "slow",
+                    HealthStatus::Fail,
+                    Some("check timed out after 10ms")
+                ),
+            ]
+        );
+    }
+}
@@ -0,0 +1,143 @@
+// Copyright 2023 Oxide Computer Company
+//! Hooks that run before and after the handlers of selected endpoints
+//!
+//! A [`RequestHook`] is attached to the endpoints registered with a particular
+//! method and path, with
+//! [`ApiDescription::route_hook()`](crate::ApiDescription::route_hook), or to
+//! all of the endpoints with a particular tag, with
+//! [`ApiDescription::tag_hook()`](crate::ApiDescription::tag_hook).  For
+//! example, a hook attached to the tag `admin` can check that every request
+//! to an administrative endpoint is authorized:
+//!
+//! ```
+//! use async_trait::async_trait;
+//! use dropshot::ApiDescription;
+//! use dropshot::ApiEndpoint;
+//! use dropshot::HttpError;
+//! use dropshot::RequestContext;
+//! use dropshot::RequestHook;
+//! use std::sync::Arc;
+//!
+//! #[derive(Debug)]
+//! struct RequireAdmin;
+//!
+//! #[async_trait]
+//! impl RequestHook<()> for RequireAdmin {
+//!     async fn before(
+//!         &self,
+//!         rqctx: &RequestContext<()>,
+//!         _endpoint: &ApiEndpoint<()>,
+//!     ) -> Result<(), HttpError> {
+//!         match rqctx.request.headers().get("x-role") {
+//!             Some(role) if role == "admin" => Ok(()),
+//!             _ => Err(HttpError::for_client_error(
+//!                 None,
+//!                 http::StatusCode::FORBIDDEN,
+//!                 String::from("administrators only"),
+//!             )),
+//!         }
+//!     }
+//! }
+//!
+//! let api =
+//!     ApiDescription::<()>::new().tag_hook("admin", Arc::new(RequireAdmin));
+//! ```
+//!
+//! Hooks run in the order in which they were attached.  They apply only to
+//! requests that are served by an endpoint's handler (not, for example, to
+//! `OPTIONS` requests that Dropshot answers itself).
+
+use crate::api_description::ApiEndpoint;
+use crate::error::HttpError;
+use crate::handler::HttpHandlerResult;
+use crate::handler::RequestContext;
+use crate::server::ServerContext;
+
+use async_trait::async_trait;
+use http::Method;
+use std::fmt::Debug;
+use std::sync::Arc;
+
+/// Runs before and after the handlers of the endpoints it's attached to (see
+/// the [module-level documentation](crate::hooks)).  Both methods do nothing
+/// by default.
+#[async_trait]
+pub trait RequestHook<Context: ServerContext>: Debug + Send + Sync {
+    /// Runs after the request has been routed to `endpoint` and before its
+    /// handler's arguments are extracted.  Returns an error to fail the
+    /// request without running the handler (or any later hooks).
+    async fn before(
+        &self,
+        _rqctx: &RequestContext<Context>,
+        _endpoint: &ApiEndpoint<Context>,
+    ) -> Result<(), HttpError> {
+        Ok(())
+    }
+
+    /// Runs after the handler of `endpoint` has finished with `result`, which
+    /// the hook may inspect or replace.  This isn't invoked for requests that
+    /// a hook's `before()` rejected.
+    async fn after(
+        &self,
+        _rqctx: &RequestContext<Context>,
+        _endpoint: &ApiEndpoint<Context>,
+        result: HttpHandlerResult,
+    ) -> HttpHandlerResult {
+        result
+    }
+}
+
+/// Identifies the endpoints that a [`RequestHook`] is attached to
+#[derive(Clone, Debug)]
+pub(crate) enum HookTarget {
+    /// the endpoint with this method and path template
+    Route(Method, String),
+    /// all endpoints with this tag
+    Tag(String),
+}
+
+impl HookTarget {
+    fn matches<Context: ServerContext>(
+        &self,
+        endpoint: &ApiEndpoint<Context>,
+    ) -> bool {
+        match self {
+            HookTarget::Route(method, path) => {
+                endpoint.method == *method && endpoint.path == *path
+            }
+            HookTarget::Tag(tag) => endpoint.tags.contains(tag),
+        }
+    }
+}
+
+/// The hooks of an API, along with the endpoints they're attached to
+#[derive(Debug)]
+pub(crate) struct RequestHooks<Context: ServerContext> {
+    hooks: Vec<(HookTarget, Arc<dyn RequestHook<Context>>)>,
+}
+
+impl<Context: ServerContext> Default for RequestHooks<Context> {
+    fn default() -> Self {
+        RequestHooks { hooks: Vec::new() }
+    }
+}
+
+impl<Context: ServerContext> RequestHooks<Context> {
+    pub(crate) fn new(
+        hooks: &[(HookTarget, Arc<dyn RequestHook<Context>>)],
+    ) -> Self {
+        RequestHooks { hooks: hooks.to_vec() }
+    }
+
+    /// Returns the hooks attached to `endpoint`, in order.
+    pub(crate) fn for_endpoint(
+        &self,
+        endpoint: &ApiEndpoint<Context>,
+    ) -> Vec<Arc<dyn RequestHook<Context>>> {
+        self.hooks
+            .iter()
+            .filter(|(target, _)| target.matches(endpoint))
+            .map(|(_, hook)| Arc::clone(hook))
+            .collect()
+    }
+}
@@ -0,0 +1,355 @@
+// Copyright 2023 Oxide Computer Company
+//! Experimental support for serving HTTP/3 over QUIC (see
+//! `ConfigDropshot::http3`)
+
+use crate::bandwidth::Throttle;
+use crate::config::ConfigTls;
+use crate::connection::ConnectionInfo;
+use crate::server::generate_connection_id;
+use crate::server::http_request_handle_wrap;
+use crate::server::DropshotState;
+use crate::server::ServerContext;
+use crate::server::ShutdownReport;
+use crate::tls::load_certs;
+use crate::tls::load_private_key;
+use crate::Body;
+
+use bytes::Buf;
+use bytes::Bytes;
+use http::HeaderValue;
+use http_body_util::BodyExt;
+use hyper::Request;
+use hyper::Response;
+use std::net::SocketAddr;
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
+use tokio::sync::oneshot;
+use tokio::sync::watch;
+use tokio::task::JoinSet;
+
+type GenericError = Box<dyn std::error::Error + Send + Sync>;
+
+type RequestStream =
+    h3::server::RequestStream<h3_quinn::BidiStream<Bytes>, Bytes>;
+
+/// HTTP/3 error code with which connections beyond the server's limit (see
+/// `ConfigLoadLimits::max_connections`) are closed (`H3_EXCESSIVE_LOAD`)
+const H3_EXCESSIVE_LOAD: u32 = 0x0107;
+
+/// A QUIC endpoint on which HTTP/3 connections are accepted
+pub(crate) struct Http3Listener {
+    endpoint: quinn::Endpoint,
+}
+
+impl Http3Listener {
+    /// Binds a QUIC endpoint to the UDP address `address`, which identifies
+    /// itself with the certificate chain in `config`.
+    pub fn bind(
+        config: &ConfigTls,
+        address: SocketAddr,
+    ) -> std::io::Result<Http3Listener> {
+        let tls_config = quic_tls_config(config)?;
+        let server_config =
+            quinn::ServerConfig::with_crypto(Arc::new(tls_config));
+        let endpoint = quinn::Endpoint::server(server_config, address)?;
+        Ok(Http3Listener { endpoint })
+    }
+
+    /// Returns the value of the `Alt-Svc` header with which responses on the
+    /// server's TCP listener advertise this endpoint.
+    pub fn alt_svc(&self, max_age_secs: u64) -> std::io::Result<HeaderValue> {
+        let port = self.endpoint.local_addr()?.port();
+        let value = format!("h3=\":{}\"; ma={}", port, max_age_secs);
+        Ok(HeaderValue::from_str(&value).unwrap())
+    }
+
+    /// Serves each connection accepted by the endpoint on its own task until
+    /// `close_signal` fires, then waits for requests that are already in
+    /// progress to complete (for up to the server's drain timeout, if it has
+    /// one), like the server's TCP listener.
+    pub async fn serve<C: ServerContext>(
+        self,
+        server: Arc<DropshotState<C>>,
+        close_signal: oneshot::Receiver<()>,
+    ) -> Result<ShutdownReport, GenericError> {
+        let endpoint = self.endpoint;
+        let local_addr = endpoint.local_addr()?;
+        let (closing_tx, closing_rx) = watch::channel(false);
+        let mut tasks = JoinSet::new();
+        let mut accepting = server.accepting.subscribe();
+        tokio::pin!(close_signal);
+
+        loop {
+            let accept = *accepting.borrow_and_update();
+            tokio::select! {
+                result = &mut close_signal => {
+                    result.expect(
+                        "dropshot server shutting down without invoking \
+                         close()",
+                    );
+                    break;
+                }
+                // Reap the tasks of connections that have been closed.
+                Some(_) = tasks.join_next() => {}
+                _ = accepting.changed() => {}
+                connecting = endpoint.accept(), if accept => {
+                    let connecting = match connecting {
+                        Some(connecting) => connecting,
+                        None => break,
+                    };
+                    tasks.spawn(serve_connection(
+                        Arc::clone(&server),
+                        local_addr,
+                        connecting,
+                        closing_rx.clone(),
+                    ));
+                }
+            }
+        }
+
+        // Stop accepting connections, and ask the clients of the open ones not
+        // to send more requests on them.
+        endpoint.set_server_config(None);
+        let _ = closing_tx.send(true);
+        let drain = async { while tasks.join_next().await.is_some() {} };
+        let drained = match server.settings.get().drain_timeout {
+            None => {
+                drain.await;
+                true
+            }
+            Some(timeout) => tokio::time::timeout(timeout, drain).await.is_ok(),
+        };
+        let mut report = ShutdownReport::default();
+        if !drained {
+            report.aborted_requests =
+                server.in_flight_requests.load(Ordering::SeqCst);
+            tasks.shutdown().await;
+        }
+        endpoint.close(0u32.into(), b"server shutting down");
+        endpoint.wait_idle().await;
+        Ok(report)
+    }
+}
+
+/// Returns the TLS configuration for QUIC connections, which use the same
+/// certificate chain as the server's TCP listener.
+fn quic_tls_config(
+    config: &ConfigTls,
+) -> std::io::Result<quic_rustls::ServerConfig> {
+    let unsupported = |what: &str| {
+        std::io::Error::new(
+            std::io::ErrorKind::Other,
+            format!("{} is not supported with HTTP/3", what),
+        )
+    };
+    if config.client_auth().is_some() {
+        return Err(unsupported("TLS client authentication"));
+    }
+    if let ConfigTls::Sni { .. } = config {
+        return Err(unsupported("choosing certificates by SNI hostname"));
+    }
+    let certs = load_certs(&mut config.cert_reader()?)?
+        .into_iter()
+        .map(|cert| quic_rustls::Certificate(cert.0))
+        .collect();
+    let private_key =
+        quic_rustls::PrivateKey(load_private_key(&mut config.key_reader()?)?.0);
+    let mut tls_config = quic_rustls::ServerConfig::builder()
+        .with_safe_default_cipher_suites()
+        .with_safe_default_kx_groups()
+        // QUIC requires TLS 1.3.
+        .with_protocol_versions(&[&quic_rustls::version::TLS13])
+        .unwrap()
+        .with_no_client_auth()
+        .with_single_cert(certs, private_key)
+        .map_err(|e| {
+            std::io::Error::new(
+                std::io::ErrorKind::Other,
+                format!("bad certificate/key: {}", e),
+            )
+        })?;
+    tls_config.alpn_protocols = vec![b"h3".to_vec()];
+    Ok(tls_config)
+}
+
+/// Serves the requests on a QUIC connection until it's closed (or, once
+/// `closing` is set, until the requests that have already arrived have been
+/// handled).
+async fn serve_connection<C: ServerContext>(
+    server: Arc<DropshotState<C>>,
+    local_addr: SocketAddr,
+    connecting: quinn::Connecting,
+    mut closing: watch::Receiver<bool>,
+) {
+    let open_connection = server
+        .load_limits
+        .accept_connection(server.settings.get().max_connections);
+    let conn = match connecting.await {
+        Ok(conn) => conn,
+        Err(e) => {
+            warn!(server.log, "quic accept err: {}", e);
+            return;
+        }
+    };
+    let remote_addr = conn.remote_address();
+    if open_connection.is_none() {
+        warn!(server.log, "shedding connection: too many open";
+            "remote_addr" => %remote_addr,
+        );
+        conn.close(H3_EXCESSIVE_LOAD.into(), b"");
+        return;
+    }
+
+    let connection_id = generate_connection_id();
+    info!(server.log, "accepted connection";
+        "remote_addr" => %remote_addr,
+        "local_addr" => %local_addr,
+        "tls" => true,
+        "http3" => true,
+        "conn_id" => &connection_id,
+    );
+    let connection = Arc::new(ConnectionInfo::new(
+        remote_addr,
+        local_addr,
+        true,
+        Some(b"h3".to_vec()),
+    ));
+    let throttle = server.connection_throttle.with_new_bucket();
+    let mut h3_conn = match h3::server::Connection::<_, Bytes>::new(
+        h3_quinn::Connection::new(conn),
+    )
+    .await
+    {
+        Ok(h3_conn) => h3_conn,
+        Err(e) => {
+            debug!(server.log, "connection error";
+                "remote_addr" => %remote_addr,
+                "error" => %e,
+            );
+            return;
+        }
+    };
+
+    let mut requests = JoinSet::new();
+    loop {
+        tokio::select! {
+            result = h3_conn.accept() => match result {
+                Ok(Some((request, stream))) => {
+                    requests.spawn(handle_request(
+                        Arc::clone(&server),
+                        Arc::clone(&connection),
+                        connection_id.clone(),
+                        throttle.clone(),
+                        request,
+                        stream,
+                    ));
+                }
+                Ok(None) => break,
+                Err(e) => {
+                    debug!(server.log, "connection error";
+                        "remote_addr" => %remote_addr,
+                        "error" => %e,
+                    );
+                    break;
+                }
+            },
+            // Reap the tasks of requests that have been handled.
+            Some(_) = requests.join_next() => {}
+            Ok(()) = closing.changed(), if !*closing.borrow() => {
+                // Sending GOAWAY lets the requests that have already arrived
+                // complete.
+                if let Err(e) = h3_conn.shutdown(0).await {
+                    debug!(server.log, "connection error";
+                        "remote_addr" => %remote_addr,
+                        "error" => %e,
+                    );
+                    break;
+                }
+            }
+        }
+    }
+    while requests.join_next().await.is_some() {}
+}
+
+/// Handles a request on an HTTP/3 connection with the same machinery as
+/// requests on the server's TCP listener.
+async fn handle_request<C: ServerContext>(
+    server: Arc<DropshotState<C>>,
+    connection: Arc<ConnectionInfo>,
+    connection_id: String,
+    throttle: Throttle,
+    request: Request<()>,
+    stream: RequestStream,
+) {
+    let log = server.log.clone();
+    if let Err(e) =
+        respond(server, connection, connection_id, throttle, request, stream)
+            .await
+    {
+        debug!(log, "HTTP/3 stream error"; "error" => %e);
+    }
+}
+
+async fn respond<C: ServerContext>(
+    server: Arc<DropshotState<C>>,
+    connection: Arc<ConnectionInfo>,
+    connection_id: String,
+    throttle: Throttle,
+    request: Request<()>,
+    stream: RequestStream,
+) -> Result<(), GenericError> {
+    let (mut send, recv) = stream.split();
+    let body = Body::wrap_stream(futures::stream::unfold(
+        Some(recv),
+        |recv| async move {
+            let mut recv = recv?;
+            match recv.recv_data().await {
+                Ok(Some(mut data)) => {
+                    let data = data.copy_to_bytes(data.remaining());
+                    Some((Ok(data), Some(recv)))
+                }
+                Ok(None) => None,
+                Err(e) => Some((Err(e), None)),
+            }
+        },
+    ));
+
+    let (mut parts, ()) = request.into_parts();
+    parts.version = http::Version::HTTP_3;
+    // HTTP/3 requests name the server in their URI's authority rather than a
+    // `Host` header, but handlers may expect the header.
+    if !parts.headers.contains_key(http::header::HOST) {
+        let host = parts
+            .uri
+            .authority()
+            .and_then(|a| HeaderValue::from_str(a.as_str()).ok());
+        if let Some(host) = host {
+            parts.headers.insert(http::header::HOST, host);
+        }
+    }
+
+    let response = http_request_handle_wrap(
+        server,
+        connection,
+        connection_id,
+        throttle,
+        Request::from_parts(parts, body),
+    )
+    .await?;
+
+    let (parts, mut body) = response.into_parts();
+    send.send_response(Response::from_parts(parts, ())).await?;
+    while let Some(frame) = body.frame().await {
+        match frame?.into_data() {
+            Ok(data) => send.send_data(data).await?,
+            Err(frame) => {
+                if let Ok(trailers) = frame.into_trailers() {
+                    send.send_trailers(trailers).await?;
+                    break;
+                }
+            }
+        }
+    }
+    send.finish().await?;
+    Ok(())
+}
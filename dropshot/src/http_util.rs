@@ -3,10 +3,15 @@
 
 use bytes::BufMut;
 use bytes::Bytes;
-use hyper::body::HttpBody;
+use http_body::Body as HttpBody;
+use http_body_util::BodyExt;
 use serde::de::DeserializeOwned;
+use std::fmt::Display;
+use std::str::FromStr;
 
 use super::error::HttpError;
+#[cfg(any(feature = "compression", feature = "static-files"))]
+use crate::config::CompressionCoding;
 use crate::from_map::from_map;
 use crate::router::VariableSet;
 
@@ -16,10 +21,26 @@ pub const HEADER_REQUEST_ID: &str = "x-request-id";
 pub const CONTENT_TYPE_OCTET_STREAM: &str = "application/octet-stream";
 /// MIME type for plain JSON data
 pub const CONTENT_TYPE_JSON: &str = "application/json";
+/// MIME type for RFC 7807 problem details
+pub const CONTENT_TYPE_PROBLEM_JSON: &str = "application/problem+json";
+/// MIME type for CBOR data
+pub const CONTENT_TYPE_CBOR: &str = "application/cbor";
+/// MIME type for MessagePack data
+pub const CONTENT_TYPE_MSGPACK: &str = "application/msgpack";
 /// MIME type for newline-delimited JSON data
 pub const CONTENT_TYPE_NDJSON: &str = "application/x-ndjson";
+/// MIME type for Protocol Buffers messages
+pub const CONTENT_TYPE_PROTOBUF: &str = "application/x-protobuf";
 /// MIME type for form/urlencoded data
 pub const CONTENT_TYPE_URL_ENCODED: &str = "application/x-www-form-urlencoded";
+/// MIME type for XML documents
+pub const CONTENT_TYPE_XML: &str = "application/xml";
+/// MIME type for YAML documents
+pub const CONTENT_TYPE_YAML: &str = "application/yaml";
+/// MIME type for multipart form data
+pub const CONTENT_TYPE_MULTIPART_FORM_DATA: &str = "multipart/form-data";
+/// MIME type for a stream of server-sent events
+pub const CONTENT_TYPE_EVENT_STREAM: &str = "text/event-stream";
 
 /// Reads the rest of the body from the request up to the given number of bytes.
 /// If the body fits within the specified cap, a buffer is returned with all the
@@ -29,26 +50,41 @@ pub async fn http_read_body<T>(
     cap: usize,
 ) -> Result<Bytes, HttpError>
 where
-    T: HttpBody<Data = Bytes, Error = hyper::Error> + std::marker::Unpin,
+    T: HttpBody<Data = Bytes> + std::marker::Unpin,
+    T::Error: std::fmt::Display,
 {
-    // This looks a lot like the implementation of hyper::body::to_bytes(), but
+    // This looks a lot like the implementation of `BodyExt::collect()`, but
     // applies the requested cap.  We've skipped the optimization for the
     // 1-buffer case for now, as it seems likely this implementation will change
     // anyway.
     // TODO should this use some Stream interface instead?
     // TODO why does this look so different in type signature (Data=Bytes,
     // std::marker::Unpin, &mut T)
-    // TODO Error type shouldn't have to be hyper Error -- Into<ApiError> should
-    // work too?
     // TODO do we need to use saturating_add() here?
     let mut parts = std::vec::Vec::new();
     let mut nbytesread: usize = 0;
-    while let Some(maybebuf) = body.data().await {
-        let buf = maybebuf?;
+    while let Some(maybeframe) = body.frame().await {
+        let frame = maybeframe.map_err(|error| {
+            HttpError::for_bad_request(
+                None,
+                format!("error processing request: {}", error),
+            )
+        })?;
+        // Trailers are read along with the data, but we don't do anything with
+        // them.
+        let buf = match frame.into_data() {
+            Ok(buf) => buf,
+            Err(_) => continue,
+        };
         let bufsize = buf.len();
 
         if nbytesread + bufsize > cap {
-            http_dump_body(body).await?;
+            http_dump_body(body).await.map_err(|error| {
+                HttpError::for_bad_request(
+                    None,
+                    format!("error processing request: {}", error),
+                )
+            })?;
             // TODO-correctness check status code
             return Err(HttpError::for_bad_request(
                 None,
@@ -60,14 +96,6 @@ where
         parts.put(buf);
     }
 
-    // Read the trailers as well, even though we're not going to do anything
-    // with them.
-    body.trailers().await?;
-    // TODO-correctness why does the is_end_stream() assertion fail and the next
-    // one panic?
-    // assert!(body.is_end_stream());
-    // assert!(body.data().await.is_none());
-    // assert!(body.trailers().await?.is_none());
     Ok(parts.into())
 }
 
@@ -84,9 +112,10 @@ where
     // TODO better understand pin_mut!()
     // TODO do we need to use saturating_add() here?
     let mut nbytesread: usize = 0;
-    while let Some(maybebuf) = body.data().await {
-        let buf = maybebuf?;
-        nbytesread += buf.len();
+    while let Some(maybeframe) = body.frame().await {
+        if let Some(buf) = maybeframe?.data_ref() {
+            nbytesread += buf.len();
+        }
     }
 
     // TODO-correctness why does the is_end_stream() assertion fail?
@@ -110,14 +139,12 @@ where
 ///
 /// TODO-cleanup: It would be better to fail to build when the struct's
 /// parameters don't match up precisely with the path parameters
-/// TODO-cleanup It would also be nice to know if the struct could not possibly
-/// be correctly constructed from path parameters because the struct contains
-/// values that could not be represented in path parameters (e.g., nested
-/// structs).  One approach to doing this would be to skip serde altogether here
-/// for `T` and instead define our own trait.  We could define a "derive" macro
-/// that would do something similar to serde, but only allows field values that
-/// implement FromStr.  Then we'd at least know at build time that the consumer
-/// gave us a type that could conceivably be represented by the path parameters.
+///
+/// Types that derive `PathParams` (rather than `Deserialize`) are only
+/// allowed to have fields whose types implement `FromStr` (or, for wildcards,
+/// are `Vec`s of such types), so we know at build time that they could
+/// conceivably be represented by the path parameters.  Their errors also name
+/// the offending parameter; see `parse_path_param()`.
 /// TODO-testing: Add automated tests.
 pub fn http_extract_path_params<T: DeserializeOwned>(
     path_params: &VariableSet,
@@ -132,9 +159,10 @@ pub fn http_extract_path_params<T: DeserializeOwned>(
         // even with our own deserializer, we'd also have to build our
         // own serde::de::Error impl in order to distinguish this particular
         // case.  For now, we resort to parsing the error message.
-        // TODO-correctness The error message produced in the type-error case
-        // (that end users will see) does not indicate which path parameter was
-        // invalid.  That's pretty bad for end users.
+        // TODO-correctness Unless T derives `PathParams`, the error message
+        // produced in the type-error case (that end users will see) does not
+        // indicate which path parameter was invalid.  That's pretty bad for
+        // end users.
         assert!(!message.starts_with("missing field: "));
         HttpError::for_bad_request(
             None,
@@ -142,3 +170,78 @@ pub fn http_extract_path_params<T: DeserializeOwned>(
         )
     })
 }
+
+/// Parses the value of the path parameter `name` for a field of a struct that
+/// derives `PathParams`, reporting a failure along with the parameter's name.
+pub fn parse_path_param<T, E>(name: &str, value: String) -> Result<T, E>
+where
+    T: FromStr,
+    T::Err: Display,
+    E: serde::de::Error,
+{
+    value.parse().map_err(|e| {
+        E::custom(format!(
+            "invalid value {:?} for parameter \"{}\": {}",
+            value, name, e
+        ))
+    })
+}
+
+/// Like `parse_path_param()`, but for the segments matched by a wildcard.
+pub fn parse_path_params<T, E>(
+    name: &str,
+    values: Vec<String>,
+) -> Result<Vec<T>, E>
+where
+    T: FromStr,
+    T::Err: Display,
+    E: serde::de::Error,
+{
+    values.into_iter().map(|value| parse_path_param(name, value)).collect()
+}
+
+/// Chooses the content coding from `codings` (in the server's order of
+/// preference, which breaks ties) that the client most prefers according to
+/// the `Accept-Encoding` header(s) in `headers`, if it accepts any of them.
+#[cfg(any(feature = "compression", feature = "static-files"))]
+pub(crate) fn negotiate_content_coding(
+    headers: &http::HeaderMap,
+    codings: &[CompressionCoding],
+) -> Option<CompressionCoding> {
+    let mut accepted = Vec::new();
+    for value in headers.get_all(http::header::ACCEPT_ENCODING) {
+        let value = value.to_str().ok()?;
+        for item in value.split(',').filter(|i| !i.trim().is_empty()) {
+            let mut parts = item.split(';');
+            let coding = parts.next().unwrap().trim().to_ascii_lowercase();
+            let mut quality = 1.0;
+            for param in parts {
+                if let Some((name, value)) = param.split_once('=') {
+                    if name.trim().eq_ignore_ascii_case("q") {
+                        quality = value.trim().parse().unwrap_or(0.0);
+                    }
+                }
+            }
+            accepted.push((coding, quality));
+        }
+    }
+
+    // An explicit entry for a coding overrides the `*` wildcard.
+    let quality = |coding: CompressionCoding| -> f32 {
+        let find = |name: &str| {
+            accepted.iter().find(|(c, _)| c == name).map(|(_, q)| *q)
+        };
+        let name = coding.name();
+        let alias = if name == "gzip" { find("x-gzip") } else { None };
+        find(name).or(alias).or_else(|| find("*")).unwrap_or(0.0)
+    };
+
+    let mut best = None;
+    for coding in codings {
+        let q = quality(*coding);
+        if q > 0.0 && best.map_or(true, |(_, best_q)| q > best_q) {
+            best = Some((*coding, q));
+        }
+    }
+    best.map(|(coding, _)| coding)
+}
@@ -0,0 +1,764 @@
+// Copyright 2023 Oxide Computer Company
+//! Support for idempotency keys
+//!
+//! Clients of endpoints with side effects (e.g., creating a payment) often need
+//! to retry requests whose outcome they don't know, as when the connection
+//! fails before the response arrives.  To make that safe, the client sends a
+//! unique `Idempotency-Key` header with the request, reusing it for any
+//! retries.  The server executes the request the first time it sees a given
+//! key, remembers the outcome, and responds to subsequent requests with the
+//! same key by replaying that outcome instead of executing them again.
+//!
+//! To use this, configure an [`IdempotencyStore`] with
+//! [`ApiDescription::idempotency_store()`](crate::ApiDescription::idempotency_store)
+//! and enable it for particular endpoints with the `idempotency` attribute of
+//! the `endpoint` macro (or [`ApiEndpoint::idempotency()`]):
+//!
+//! ```
+//! use dropshot::endpoint;
+//! use dropshot::ApiDescription;
+//! use dropshot::HttpError;
+//! use dropshot::HttpResponseCreated;
+//! use dropshot::InMemoryIdempotencyStore;
+//! use dropshot::RequestContext;
+//! use std::sync::Arc;
+//! use std::time::Duration;
+//!
+//! #[endpoint {
+//!     method = POST,
+//!     path = "/payments",
+//!     idempotency = "required",
+//! }]
+//! async fn payment_create(
+//!     _rqctx: RequestContext<()>,
+//! ) -> Result<HttpResponseCreated<()>, HttpError> {
+//!     // ... charge the customer exactly once ...
+//!     Ok(HttpResponseCreated(()))
+//! }
+//!
+//! let store = InMemoryIdempotencyStore::new(Duration::from_secs(86400));
+//! let mut api = ApiDescription::new().idempotency_store(Arc::new(store));
+//! api.register(payment_create).unwrap();
+//! ```
+//!
+//! Keys are scoped to the request's method and path, so the same key may be
+//! used with different resources.  A key is also bound to the body of the
+//! request that first used it: a request that reuses a key with a different
+//! body fails with a 422 ("Unprocessable Entity") error.  A request whose key
+//! matches one that's still being executed fails with a 409 ("Conflict")
+//! error.  Outcomes are recorded
+//! for successful responses and client errors (4xx), which would recur if the
+//! request were executed again, but not for server errors (5xx), so that those
+//! requests can be retried.  Replayed responses carry an
+//! `Idempotent-Replayed: true` header.
+//!
+//! The request body is read in its entirety (up to the usual limit) before the
+//! handler runs in order to compare it with that of earlier requests, and
+//! successful responses are buffered in their entirety in order to record them,
+//! so this is not suitable for endpoints that stream large requests or
+//! responses.
+//!
+//! If a request with a key is cancelled before its outcome is recorded (e.g.,
+//! because it exceeded its endpoint's timeout or, with
+//! [`HandlerTaskMode::CancelOnDisconnect`], because the client disconnected),
+//! its claim on the key is released so that it may be retried.
+//!
+//! [`ApiEndpoint::idempotency()`]: crate::ApiEndpoint::idempotency
+//! [`HandlerTaskMode::CancelOnDisconnect`]: crate::HandlerTaskMode::CancelOnDisconnect
+
+use crate::error::HttpError;
+use crate::Body;
+
+use async_trait::async_trait;
+use http::HeaderMap;
+use http::HeaderValue;
+use http::StatusCode;
+use hyper::Response;
+use serde::Deserialize;
+use serde::Serialize;
+use sha2::Digest;
+use sha2::Sha256;
+use std::collections::HashMap;
+use std::fmt::Debug;
+use std::sync::Arc;
+use std::sync::Mutex;
+use std::time::Duration;
+use std::time::Instant;
+
+/// Name of the request header carrying the idempotency key
+pub const HEADER_IDEMPOTENCY_KEY: &str = "idempotency-key";
+/// Name of the response header marking replayed responses
+pub const HEADER_IDEMPOTENT_REPLAYED: &str = "idempotent-replayed";
+
+/// Maximum length of an idempotency key
+const IDEMPOTENCY_KEY_MAX_LEN: usize = 255;
+
+/// Whether an endpoint accepts idempotency keys
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum IdempotencyMode {
+    /// Requests may include an `Idempotency-Key` header.  Those without one
+    /// are executed normally.
+    Optional,
+    /// Requests without an `Idempotency-Key` header fail with a 400 ("Bad
+    /// Request") error.
+    Required,
+}
+
+/// Identifies a request for the purpose of detecting replays: the client's
+/// idempotency key, scoped to the request's method, path, and tenant (see
+/// [`crate::TenantResolver`]).
+#[derive(Clone, Debug, Deserialize, Eq, Hash, PartialEq, Serialize)]
+pub struct IdempotencyKey {
+    pub key: String,
+    pub method: String,
+    pub path: String,
+    #[serde(default)]
+    pub tenant: Option<String>,
+}
+
+/// Recorded outcome of the first execution of a request
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case", tag = "type")]
+pub enum IdempotentOutcome {
+    /// The handler produced a response.
+    Response { status: u16, headers: Vec<(String, String)>, body: Vec<u8> },
+    /// The handler failed with a client error.
+    Error { status: u16, error_code: Option<String>, message: String },
+}
+
+/// State of a key, as reported by [`IdempotencyStore::begin()`]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum IdempotencyState {
+    /// The key had not been seen, and the caller has now claimed it.  The
+    /// caller will execute the request and then call either
+    /// [`IdempotencyStore::complete()`] or [`IdempotencyStore::abandon()`].
+    New,
+    /// Another request with this key is still being executed.
+    InProgress {
+        /// digest of the body of the request that claimed the key
+        request_digest: String,
+    },
+    /// A request with this key has already been executed.
+    Completed {
+        /// digest of the body of the request that claimed the key
+        request_digest: String,
+        outcome: IdempotentOutcome,
+    },
+}
+
+/// Storage for idempotency keys and the outcomes of the requests that used
+/// them
+///
+/// [`InMemoryIdempotencyStore`] is suitable for a single server.  Services
+/// that run several servers behind a load balancer should implement this trait
+/// on top of a database that they share.  Implementations are responsible for
+/// expiring keys after whatever period the service promises to honor them.
+#[async_trait]
+pub trait IdempotencyStore: Debug + Send + Sync {
+    /// Atomically looks up `key` and, if it's not present, claims it for the
+    /// caller, recording `request_digest` (see [`request_digest()`]) along
+    /// with it.
+    async fn begin(
+        &self,
+        key: &IdempotencyKey,
+        request_digest: &str,
+    ) -> Result<IdempotencyState, HttpError>;
+
+    /// Records the outcome of the request that claimed `key`.
+    async fn complete(
+        &self,
+        key: &IdempotencyKey,
+        outcome: IdempotentOutcome,
+    ) -> Result<(), HttpError>;
+
+    /// Releases the claim on `key` without recording an outcome (e.g., because
+    /// the request failed with a server error) so that it may be retried.
+    async fn abandon(&self, key: &IdempotencyKey) -> Result<(), HttpError>;
+}
+
+/// [`IdempotencyStore`] that keeps keys in memory for a fixed period after
+/// they're first used
+#[derive(Debug)]
+pub struct InMemoryIdempotencyStore {
+    ttl: Duration,
+    entries: Mutex<HashMap<IdempotencyKey, InMemoryEntry>>,
+}
+
+#[derive(Debug)]
+struct InMemoryEntry {
+    created: Instant,
+    request_digest: String,
+    outcome: Option<IdempotentOutcome>,
+}
+
+impl InMemoryIdempotencyStore {
+    /// Creates a store that forgets keys `ttl` after they're first used.
+    pub fn new(ttl: Duration) -> Self {
+        InMemoryIdempotencyStore { ttl, entries: Mutex::new(HashMap::new()) }
+    }
+}
+
+#[async_trait]
+impl IdempotencyStore for InMemoryIdempotencyStore {
+    async fn begin(
+        &self,
+        key: &IdempotencyKey,
+        request_digest: &str,
+    ) -> Result<IdempotencyState, HttpError> {
+        let now = Instant::now();
+        let mut entries = self.entries.lock().unwrap();
+        entries.retain(|_, entry| now.duration_since(entry.created) < self.ttl);
+        match entries.get(key) {
+            None => {
+                entries.insert(
+                    key.clone(),
+                    InMemoryEntry {
+                        created: now,
+                        request_digest: request_digest.to_string(),
+                        outcome: None,
+                    },
+                );
+                Ok(IdempotencyState::New)
+            }
+            Some(InMemoryEntry { request_digest, outcome: None, .. }) => {
+                Ok(IdempotencyState::InProgress {
+                    request_digest: request_digest.clone(),
+                })
+            }
+            Some(InMemoryEntry {
+                request_digest,
+                outcome: Some(outcome),
+                ..
+            }) => Ok(IdempotencyState::Completed {
+                request_digest: request_digest.clone(),
+                outcome: outcome.clone(),
+            }),
+        }
+    }
+
+    async fn complete(
+        &self,
+        key: &IdempotencyKey,
+        outcome: IdempotentOutcome,
+    ) -> Result<(), HttpError> {
+        if let Some(entry) = self.entries.lock().unwrap().get_mut(key) {
+            entry.outcome = Some(outcome);
+        }
+        Ok(())
+    }
+
+    async fn abandon(&self, key: &IdempotencyKey) -> Result<(), HttpError> {
+        self.entries.lock().unwrap().remove(key);
+        Ok(())
+    }
+}
+
+/// Returns the request's idempotency key, if it has one, or an error if it's
+/// invalid or missing when required.
+pub(crate) fn idempotency_key(
+    mode: IdempotencyMode,
+    method: &http::Method,
+    path: &str,
+    headers: &HeaderMap,
+    tenant: Option<&str>,
+) -> Result<Option<IdempotencyKey>, HttpError> {
+    let key = match headers.get(HEADER_IDEMPOTENCY_KEY) {
+        Some(value) => value
+            .to_str()
+            .ok()
+            .filter(|k| !k.is_empty() && k.len() <= IDEMPOTENCY_KEY_MAX_LEN),
+        None if mode == IdempotencyMode::Required => {
+            return Err(HttpError::for_bad_request(
+                Some(String::from("IdempotencyKeyRequired")),
+                format!("missing {} header", HEADER_IDEMPOTENCY_KEY),
+            ));
+        }
+        None => return Ok(None),
+    };
+    let key = key.ok_or_else(|| {
+        HttpError::for_bad_request(
+            None,
+            format!(
+                "invalid {} header (must be 1 to {} visible ASCII characters)",
+                HEADER_IDEMPOTENCY_KEY, IDEMPOTENCY_KEY_MAX_LEN,
+            ),
+        )
+    })?;
+    Ok(Some(IdempotencyKey {
+        key: key.to_string(),
+        method: method.to_string(),
+        path: path.to_string(),
+        tenant: tenant.map(str::to_string),
+    }))
+}
+
+/// Returns the digest of a request body that binds an idempotency key to it:
+/// the hex-encoded SHA-256 hash of the body.
+pub fn request_digest(body: &[u8]) -> String {
+    Sha256::digest(body).iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+/// Converts a recorded outcome back into the result of a handler.
+pub(crate) fn replay(
+    outcome: IdempotentOutcome,
+) -> Result<Response<Body>, HttpError> {
+    match outcome {
+        IdempotentOutcome::Response { status, headers, body } => {
+            let mut builder = Response::builder().status(status);
+            for (name, value) in headers {
+                builder = builder.header(name, value);
+            }
+            let mut response = builder.body(Body::from(body))?;
+            response.headers_mut().insert(
+                HEADER_IDEMPOTENT_REPLAYED,
+                HeaderValue::from_static("true"),
+            );
+            Ok(response)
+        }
+        IdempotentOutcome::Error { status, error_code, message } => {
+            Err(HttpError {
+                status_code: StatusCode::from_u16(status)
+                    .unwrap_or(StatusCode::INTERNAL_SERVER_ERROR),
+                error_code,
+                external_message: message,
+                internal_message: String::from(
+                    "replayed error for idempotency key",
+                ),
+            })
+        }
+    }
+}
+
+/// Releases the claim on a key if the request that made it is dropped (e.g.,
+/// because it timed out) before its outcome is recorded
+struct ClaimGuard {
+    store: Arc<dyn IdempotencyStore>,
+    key: Option<IdempotencyKey>,
+}
+
+impl ClaimGuard {
+    /// Releases the claim now.
+    async fn abandon(mut self) -> Result<(), HttpError> {
+        let result = match &self.key {
+            Some(key) => self.store.abandon(key).await,
+            None => Ok(()),
+        };
+        self.key = None;
+        result
+    }
+
+    /// Keeps the claim, now that the outcome has been recorded.
+    fn disarm(mut self) {
+        self.key = None;
+    }
+}
+
+impl Drop for ClaimGuard {
+    fn drop(&mut self) {
+        // `abandon()` is async, so it can't be awaited here.
+        if let (Some(key), Ok(runtime)) =
+            (self.key.take(), tokio::runtime::Handle::try_current())
+        {
+            let store = Arc::clone(&self.store);
+            runtime.spawn(async move {
+                let _ = store.abandon(&key).await;
+            });
+        }
+    }
+}
+
+/// Runs `handler` for the request identified by `key`, unless it has already
+/// been executed, recording its outcome in `store`.  `request_digest` is the
+/// [`request_digest()`] of the request's body.
+pub(crate) async fn run_idempotent<F>(
+    store: Arc<dyn IdempotencyStore>,
+    key: &IdempotencyKey,
+    request_digest: &str,
+    handler: F,
+) -> Result<Response<Body>, HttpError>
+where
+    F: std::future::Future<Output = Result<Response<Body>, HttpError>>,
+{
+    match store.begin(key, request_digest).await? {
+        IdempotencyState::New => (),
+        IdempotencyState::InProgress { request_digest: claimed }
+        | IdempotencyState::Completed { request_digest: claimed, .. }
+            if claimed != request_digest =>
+        {
+            return Err(HttpError::for_client_error(
+                Some(String::from("IdempotencyKeyMismatch")),
+                StatusCode::UNPROCESSABLE_ENTITY,
+                String::from(
+                    "this idempotency key was used with a different request \
+                     body",
+                ),
+            ));
+        }
+        IdempotencyState::InProgress { .. } => {
+            return Err(HttpError::for_client_error(
+                Some(String::from("IdempotencyKeyInUse")),
+                StatusCode::CONFLICT,
+                String::from(
+                    "a request with this idempotency key is already in \
+                     progress",
+                ),
+            ));
+        }
+        IdempotencyState::Completed { outcome, .. } => return replay(outcome),
+    }
+
+    // If this future is dropped while the handler runs, the guard releases
+    // the claim.
+    let guard =
+        ClaimGuard { store: Arc::clone(&store), key: Some(key.clone()) };
+    match handler.await {
+        Ok(response) if !response.status().is_server_error() => {
+            let (parts, body) = response.into_parts();
+            let body = match body.to_bytes().await {
+                Ok(body) => body,
+                Err(e) => {
+                    guard.abandon().await?;
+                    return Err(HttpError::for_internal_error(format!(
+                        "reading response body: {}",
+                        e
+                    )));
+                }
+            };
+            let headers = parts
+                .headers
+                .iter()
+                .filter_map(|(name, value)| {
+                    Some((name.to_string(), value.to_str().ok()?.to_string()))
+                })
+                .collect();
+            store
+                .complete(
+                    key,
+                    IdempotentOutcome::Response {
+                        status: parts.status.as_u16(),
+                        headers,
+                        body: body.to_vec(),
+                    },
+                )
+                .await?;
+            guard.disarm();
+            Ok(Response::from_parts(parts, Body::from(body)))
+        }
+        Err(error) if error.status_code.is_client_error() => {
+            store
+                .complete(
+                    key,
+                    IdempotentOutcome::Error {
+                        status: error.status_code.as_u16(),
+                        error_code: error.error_code.clone(),
+                        message: error.external_message.clone(),
+                    },
+                )
+                .await?;
+            guard.disarm();
+            Err(error)
+        }
+        result => {
+            guard.abandon().await?;
+            result
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::idempotency_key;
+    use super::request_digest;
+    use super::run_idempotent;
+    use super::IdempotencyKey;
+    use super::IdempotencyMode;
+    use super::InMemoryIdempotencyStore;
+    use super::HEADER_IDEMPOTENCY_KEY;
+    use super::HEADER_IDEMPOTENT_REPLAYED;
+    use crate::Body;
+    use crate::HttpError;
+    use http::HeaderMap;
+    use http::Method;
+    use http::StatusCode;
+    use hyper::Response;
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    #[test]
+    fn test_idempotency_key() {
+        let mut headers = HeaderMap::new();
+        assert!(idempotency_key(
+            IdempotencyMode::Optional,
+            &Method::POST,
+            "/p",
+            &headers,
+            None,
+        )
+        .unwrap()
+        .is_none());
+        let error = idempotency_key(
+            IdempotencyMode::Required,
+            &Method::POST,
+            "/p",
+            &headers,
+            None,
+        )
+        .unwrap_err();
+        assert_eq!(error.status_code, StatusCode::BAD_REQUEST);
+
+        headers.insert(HEADER_IDEMPOTENCY_KEY, "abc".parse().unwrap());
+        assert_eq!(
+            idempotency_key(
+                IdempotencyMode::Required,
+                &Method::POST,
+                "/p",
+                &headers,
+                None,
+            )
+            .unwrap()
+            .unwrap(),
+            IdempotencyKey {
+                key: String::from("abc"),
+                method: String::from("POST"),
+                path: String::from("/p"),
+                tenant: None,
+            }
+        );
+
+        headers
+            .insert(HEADER_IDEMPOTENCY_KEY, "a".repeat(256).parse().unwrap());
+        assert!(idempotency_key(
+            IdempotencyMode::Optional,
+            &Method::POST,
+            "/p",
+            &headers,
+            None,
+        )
+        .is_err());
+    }
+
+    #[tokio::test]
+    async fn test_run_idempotent() {
+        let store =
+            Arc::new(InMemoryIdempotencyStore::new(Duration::from_secs(60)));
+        let key = |k: &str| IdempotencyKey {
+            key: k.to_string(),
+            method: String::from("POST"),
+            path: String::from("/p"),
+            tenant: None,
+        };
+        let handler = |status: StatusCode| async move {
+            if status.is_success() {
+                Ok(Response::builder()
+                    .status(status)
+                    .header("content-type", "text/plain")
+                    .body(Body::from("done"))
+                    .unwrap())
+            } else if status.is_client_error() {
+                Err(HttpError::for_status(None, status))
+            } else {
+                Err(HttpError::for_unavail(None, String::from("try again")))
+            }
+        };
+
+        // The first execution runs the handler; the second replays it.
+        let response = run_idempotent(
+            store.clone(),
+            &key("a"),
+            "d",
+            handler(StatusCode::CREATED),
+        )
+        .await
+        .unwrap();
+        assert_eq!(response.status(), StatusCode::CREATED);
+        assert!(response.headers().get(HEADER_IDEMPOTENT_REPLAYED).is_none());
+        let response = run_idempotent(
+            store.clone(),
+            &key("a"),
+            "d",
+            handler(StatusCode::OK),
+        )
+        .await
+        .unwrap();
+        assert_eq!(response.status(), StatusCode::CREATED);
+        assert_eq!(
+            response.headers().get(HEADER_IDEMPOTENT_REPLAYED).unwrap(),
+            "true"
+        );
+        assert_eq!(
+            response.headers().get("content-type").unwrap(),
+            "text/plain"
+        );
+        let body = response.into_body().to_bytes().await.unwrap();
+        assert_eq!(&body[..], b"done");
+
+        // Client errors are recorded, too.
+        let error = run_idempotent(
+            store.clone(),
+            &key("b"),
+            "d",
+            handler(StatusCode::NOT_FOUND),
+        )
+        .await
+        .unwrap_err();
+        assert_eq!(error.status_code, StatusCode::NOT_FOUND);
+        let error = run_idempotent(
+            store.clone(),
+            &key("b"),
+            "d",
+            handler(StatusCode::OK),
+        )
+        .await
+        .unwrap_err();
+        assert_eq!(error.status_code, StatusCode::NOT_FOUND);
+
+        // Server errors are not, so the request can be retried.
+        let error = run_idempotent(
+            store.clone(),
+            &key("c"),
+            "d",
+            handler(StatusCode::SERVICE_UNAVAILABLE),
+        )
+        .await
+        .unwrap_err();
+        assert_eq!(error.status_code, StatusCode::SERVICE_UNAVAILABLE);
+        let response = run_idempotent(
+            store.clone(),
+            &key("c"),
+            "d",
+            handler(StatusCode::OK),
+        )
+        .await
+        .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(store.entries.lock().unwrap().len(), 3);
+    }
+
+    #[tokio::test]
+    async fn test_idempotency_in_progress() {
+        let store =
+            Arc::new(InMemoryIdempotencyStore::new(Duration::from_secs(60)));
+        let key = IdempotencyKey {
+            key: String::from("a"),
+            method: String::from("POST"),
+            path: String::from("/p"),
+            tenant: None,
+        };
+        let error = run_idempotent(store.clone(), &key, "d", async {
+            // While this request is running, a duplicate is rejected.
+            let error = run_idempotent(store.clone(), &key, "d", async {
+                Ok(Response::new(Body::from("duplicate")))
+            })
+            .await
+            .unwrap_err();
+            assert_eq!(error.status_code, StatusCode::CONFLICT);
+            Err(HttpError::for_bad_request(None, String::from("oops")))
+        })
+        .await
+        .unwrap_err();
+        assert_eq!(error.status_code, StatusCode::BAD_REQUEST);
+    }
+
+    #[tokio::test]
+    async fn test_idempotency_mismatch() {
+        let store =
+            Arc::new(InMemoryIdempotencyStore::new(Duration::from_secs(60)));
+        let key = IdempotencyKey {
+            key: String::from("a"),
+            method: String::from("POST"),
+            path: String::from("/p"),
+            tenant: None,
+        };
+        assert_ne!(request_digest(b"{}"), request_digest(b"{\"n\":1}"));
+        let first = request_digest(b"{}");
+        run_idempotent(store.clone(), &key, &first, async {
+            // While this request is running, one with a different body is
+            // rejected as a mismatch rather than a conflict.
+            let error = run_idempotent(store.clone(), &key, "other", async {
+                Ok(Response::new(Body::from("different")))
+            })
+            .await
+            .unwrap_err();
+            assert_eq!(error.status_code, StatusCode::UNPROCESSABLE_ENTITY);
+            Ok(Response::new(Body::from("first")))
+        })
+        .await
+        .unwrap();
+
+        // Likewise after it completes.
+        let error = run_idempotent(store.clone(), &key, "other", async {
+            Ok(Response::new(Body::from("different")))
+        })
+        .await
+        .unwrap_err();
+        assert_eq!(error.status_code, StatusCode::UNPROCESSABLE_ENTITY);
+        assert_eq!(error.error_code.as_deref(), Some("IdempotencyKeyMismatch"));
+        let response = run_idempotent(store.clone(), &key, &first, async {
+            Ok(Response::new(Body::from("second")))
+        })
+        .await
+        .unwrap();
+        let body = response.into_body().to_bytes().await.unwrap();
+        assert_eq!(&body[..], b"first");
+    }
+
+    #[tokio::test]
+    async fn test_idempotency_cancelled() {
+        let store =
+            Arc::new(InMemoryIdempotencyStore::new(Duration::from_secs(60)));
+        let key = IdempotencyKey {
+            key: String::from("a"),
+            method: String::from("POST"),
+            path: String::from("/p"),
+            tenant: None,
+        };
+        // Dropping the request while the handler runs (as when it times out)
+        // releases the claim on the key.
+        let result = tokio::time::timeout(
+            Duration::from_millis(10),
+            run_idempotent(
+                store.clone(),
+                &key,
+                "d",
+                futures::future::pending(),
+            ),
+        )
+        .await;
+        assert!(result.is_err());
+        // The claim is released by a task that the guard spawns.
+        tokio::time::sleep(Duration::from_millis(10)).await;
+        assert!(store.entries.lock().unwrap().is_empty());
+        let response = run_idempotent(store.clone(), &key, "d", async {
+            Ok(Response::new(Body::from("retried")))
+        })
+        .await
+        .unwrap();
+        let body = response.into_body().to_bytes().await.unwrap();
+        assert_eq!(&body[..], b"retried");
+    }
+
+    #[tokio::test]
+    async fn test_idempotency_expiration() {
+        let store =
+            Arc::new(InMemoryIdempotencyStore::new(Duration::from_millis(1)));
+        let key = IdempotencyKey {
+            key: String::from("a"),
+            method: String::from("POST"),
+            path: String::from("/p"),
+            tenant: None,
+        };
+        run_idempotent(store.clone(), &key, "d", async {
+            Ok(Response::new(Body::from("first")))
+        })
+        .await
+        .unwrap();
+        tokio::time::sleep(Duration::from_millis(10)).await;
+        let response = run_idempotent(store.clone(), &key, "d", async {
+            Ok(Response::new(Body::from("second")))
+        })
+        .await
+        .unwrap();
+        let body = response.into_body().to_bytes().await.unwrap();
+        assert_eq!(&body[..], b"second");
+    }
+}
@@ -0,0 +1,231 @@
+// Copyright 2023 Oxide Computer Company
+
+//! Response bodies serialized incrementally from a `Stream` of objects
+
+use crate::api_description::ApiEndpointResponse;
+use crate::api_description::ApiSchemaGenerator;
+use crate::body::BoxError;
+use crate::handler::HttpHandlerResult;
+use crate::handler::HttpResponse;
+use crate::handler::HttpResponseContent;
+use crate::handler::HttpResponseOk;
+use crate::http_util::CONTENT_TYPE_JSON;
+use crate::http_util::CONTENT_TYPE_NDJSON;
+use crate::schema_util::make_subschema_for;
+use crate::Body;
+
+use async_stream::try_stream;
+use bytes::Bytes;
+use futures::stream::BoxStream;
+use futures::Stream;
+use futures::StreamExt;
+use futures::TryStreamExt;
+use schemars::JsonSchema;
+use serde::Serialize;
+use sync_wrapper::SyncWrapper;
+
+type ItemStream<T> = BoxStream<'static, Result<T, BoxError>>;
+
+/// `JsonArrayStream<T>` is a response body that's a JSON array of the objects
+/// produced by a `Stream`, serialized as they're produced rather than collected
+/// into memory first (e.g., for a list endpoint backed by a database cursor).
+/// Use it with coded response types such as [`crate::HttpResponseOk`]; it's
+/// described in the OpenAPI document exactly as a `Vec<T>` would be.
+///
+/// If the stream fails (see [`JsonArrayStream::try_new()`]) or an object
+/// can't be serialized, the response ends abruptly, since the status has
+/// already been sent.  Clients will see an incomplete array.
+///
+/// ```
+/// use dropshot::endpoint;
+/// use dropshot::HttpError;
+/// use dropshot::HttpResponseOk;
+/// use dropshot::JsonArrayStream;
+/// use dropshot::RequestContext;
+///
+/// #[endpoint {
+///     method = GET,
+///     path = "/numbers",
+/// }]
+/// async fn numbers_get(
+///     _rqctx: RequestContext<()>,
+/// ) -> Result<HttpResponseOk<JsonArrayStream<u64>>, HttpError> {
+///     let numbers = futures::stream::iter(0..1000);
+///     Ok(HttpResponseOk(JsonArrayStream::new(numbers)))
+/// }
+/// ```
+pub struct JsonArrayStream<T> {
+    items: SyncWrapper<ItemStream<T>>,
+}
+
+/// `NdjsonStream<T>` is a response body of newline-delimited JSON
+/// (`application/x-ndjson`), with one line for each of the objects produced by
+/// a `Stream`, serialized as they're produced.  Use it with coded response
+/// types such as [`crate::HttpResponseOk`]; it's described in the OpenAPI
+/// document as `application/x-ndjson` content with the schema of `T` (that of
+/// each line).
+///
+/// As for [`JsonArrayStream`], a failure partway through ends the response
+/// abruptly.  Clients will see every line sent before it.
+pub struct NdjsonStream<T> {
+    items: SyncWrapper<ItemStream<T>>,
+}
+
+macro_rules! impl_constructors {
+    ($name:ident) => {
+        impl<T: Serialize + Send + 'static> $name<T> {
+            /// Returns a body made of the objects produced by `items`.
+            pub fn new<S>(items: S) -> $name<T>
+            where
+                S: Stream<Item = T> + Send + 'static,
+            {
+                $name::try_new(items.map(Ok::<T, BoxError>))
+            }
+
+            /// Returns a body made of the objects produced by `items`, which
+            /// ends (abruptly) at the first error.
+            pub fn try_new<S, E>(items: S) -> $name<T>
+            where
+                S: Stream<Item = Result<T, E>> + Send + 'static,
+                E: Into<BoxError> + 'static,
+            {
+                let items = items.map_err(Into::into).boxed();
+                $name { items: SyncWrapper::new(items) }
+            }
+        }
+    };
+}
+
+impl_constructors!(JsonArrayStream);
+impl_constructors!(NdjsonStream);
+
+fn serialize<T: Serialize>(item: &T) -> Result<Vec<u8>, BoxError> {
+    serde_json::to_vec(item).map_err(|e| {
+        BoxError::from(format!("failed to serialize response item: {}", e))
+    })
+}
+
+impl<T> HttpResponseContent for JsonArrayStream<T>
+where
+    T: JsonSchema + Serialize + Send + 'static,
+{
+    fn to_response(
+        self,
+        builder: http::response::Builder,
+    ) -> HttpHandlerResult {
+        let mut items = self.items.into_inner();
+        let chunks = try_stream! {
+            let mut first = true;
+            while let Some(item) = items.next().await {
+                let mut chunk = vec![if first { b'[' } else { b',' }];
+                chunk.extend(serialize(&item?)?);
+                yield Bytes::from(chunk);
+                first = false;
+            }
+            yield Bytes::from_static(if first { &b"[]"[..] } else { b"]" });
+        };
+        Ok(builder
+            .header(http::header::CONTENT_TYPE, CONTENT_TYPE_JSON)
+            .body(Body::wrap_stream::<_, _, BoxError>(chunks))?)
+    }
+
+    fn content_metadata() -> Option<ApiSchemaGenerator> {
+        Some(ApiSchemaGenerator::Gen {
+            name: <Vec<T>>::schema_name,
+            schema: make_subschema_for::<Vec<T>>,
+        })
+    }
+}
+
+impl<T> HttpResponseContent for NdjsonStream<T>
+where
+    T: JsonSchema + Serialize + Send + 'static,
+{
+    fn to_response(
+        self,
+        builder: http::response::Builder,
+    ) -> HttpHandlerResult {
+        let chunks = self.items.into_inner().and_then(|item| async move {
+            let mut line = serialize(&item)?;
+            line.push(b'\n');
+            Ok(Bytes::from(line))
+        });
+        Ok(builder
+            .header(http::header::CONTENT_TYPE, CONTENT_TYPE_NDJSON)
+            .body(Body::wrap_stream(chunks))?)
+    }
+
+    fn content_metadata() -> Option<ApiSchemaGenerator> {
+        Some(ApiSchemaGenerator::Gen {
+            name: T::schema_name,
+            schema: make_subschema_for::<T>,
+        })
+    }
+
+    fn content_type() -> &'static str {
+        CONTENT_TYPE_NDJSON
+    }
+}
+
+/// `HttpResponseNdjson<T>` is a 200 response whose body is newline-delimited
+/// JSON (`application/x-ndjson`): one line for each of the objects produced by
+/// a `Stream`.  It's shorthand for `HttpResponseOk<NdjsonStream<T>>` (and is
+/// described the same way in the OpenAPI document), except that each line is
+/// sent to the client as soon as it's serialized: the response asks reverse
+/// proxies not to buffer it, and it's never compressed (with the default
+/// `ConfigCompression`).
+///
+/// As for [`NdjsonStream`], an error from the stream ends the response
+/// abruptly.  Clients will see every line sent before it.
+///
+/// ```
+/// use dropshot::endpoint;
+/// use dropshot::HttpError;
+/// use dropshot::HttpResponseNdjson;
+/// use dropshot::RequestContext;
+///
+/// #[endpoint {
+///     method = GET,
+///     path = "/numbers",
+/// }]
+/// async fn numbers_get(
+///     _rqctx: RequestContext<()>,
+/// ) -> Result<HttpResponseNdjson<u64>, HttpError> {
+///     let numbers = futures::stream::iter((0..1000).map(Ok::<_, HttpError>));
+///     Ok(HttpResponseNdjson::new(numbers))
+/// }
+/// ```
+pub struct HttpResponseNdjson<T> {
+    items: NdjsonStream<T>,
+}
+
+impl<T: Serialize + Send + 'static> HttpResponseNdjson<T> {
+    /// Returns a response made of the objects produced by `items`, which ends
+    /// (abruptly) at the first error.
+    pub fn new<S, E>(items: S) -> HttpResponseNdjson<T>
+    where
+        S: Stream<Item = Result<T, E>> + Send + 'static,
+        E: Into<BoxError> + 'static,
+    {
+        HttpResponseNdjson { items: NdjsonStream::try_new(items) }
+    }
+}
+
+impl<T> HttpResponse for HttpResponseNdjson<T>
+where
+    T: JsonSchema + Serialize + Send + 'static,
+{
+    fn to_result(self) -> HttpHandlerResult {
+        let mut response = HttpResponseOk(self.items).to_result()?;
+        // Asks reverse proxies (e.g., nginx) to pass each line along as it's
+        // sent rather than buffering the response.
+        response
+            .headers_mut()
+            .insert("x-accel-buffering", http::HeaderValue::from_static("no"));
+        Ok(response)
+    }
+
+    fn response_metadata() -> ApiEndpointResponse {
+        HttpResponseOk::<NdjsonStream<T>>::response_metadata()
+    }
+}
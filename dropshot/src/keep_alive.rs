@@ -0,0 +1,424 @@
+// Copyright 2023 Oxide Computer Company
+//! Closing connections that have been idle too long or have served enough
+//! requests (see `ConfigDropshot::keep_alive_timeout_ms`,
+//! `ConfigDropshot::idle_timeout_ms`, and
+//! `ConfigDropshot::max_requests_per_connection`)
+//!
+//! hyper doesn't close connections for any of these reasons, so connections
+//! are closed through their streams instead: once a connection should be
+//! closed, reading from its stream yields end-of-file, which hyper takes to
+//! mean that the client has closed the connection.  For a connection without
+//! requests in progress, that's a clean close.  A connection on which nothing
+//! can be written for longer than the idle timeout fails instead.
+
+use crate::body::BoxError;
+use crate::Body;
+
+use bytes::Bytes;
+use http_body::Frame;
+use http_body::SizeHint;
+use std::future::Future;
+use std::io;
+use std::pin::Pin;
+use std::sync::atomic::AtomicBool;
+use std::sync::atomic::AtomicU64;
+use std::sync::atomic::AtomicUsize;
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
+use std::sync::Mutex;
+use std::task::Context;
+use std::task::Poll;
+use std::task::Waker;
+use std::time::Duration;
+use tokio::io::AsyncRead;
+use tokio::io::AsyncWrite;
+use tokio::io::ReadBuf;
+use tokio::time::Instant;
+use tokio::time::Sleep;
+
+/// Limits on how long and for how many requests a connection stays open
+#[derive(Clone, Copy, Debug, Default)]
+pub(crate) struct ConnectionLifetime {
+    pub keep_alive_timeout: Option<Duration>,
+    pub idle_timeout: Option<Duration>,
+    pub max_requests: Option<u64>,
+}
+
+/// State shared between a connection's stream and the requests received on it
+#[derive(Debug)]
+struct Shared {
+    lifetime: ConnectionLifetime,
+    /// requests whose responses haven't been completely sent
+    in_flight: AtomicUsize,
+    /// requests that have arrived on the connection
+    requests: AtomicU64,
+    /// set once the connection has been upgraded to another protocol, after
+    /// which it's left alone
+    upgraded: AtomicBool,
+    /// when data was last read or written, or a response was last completed
+    last_active: Mutex<Instant>,
+    /// wakes the task that drives the connection when its last request
+    /// completes
+    waker: Mutex<Option<Waker>>,
+}
+
+impl Shared {
+    fn touch(&self) {
+        *self.last_active.lock().unwrap() = Instant::now();
+    }
+
+    /// Returns when the connection should be closed for lack of activity or
+    /// for having served its last request, if it should be at all.
+    fn read_deadline(&self) -> Option<Instant> {
+        if self.upgraded.load(Ordering::SeqCst) {
+            return None;
+        }
+        let last_active = *self.last_active.lock().unwrap();
+        let idle = self.lifetime.idle_timeout.map(|t| last_active + t);
+        if self.in_flight.load(Ordering::SeqCst) > 0 {
+            return idle;
+        }
+        let exhausted = self
+            .lifetime
+            .max_requests
+            .map_or(false, |max| self.requests.load(Ordering::SeqCst) >= max);
+        if exhausted {
+            return Some(last_active);
+        }
+        let keep_alive =
+            self.lifetime.keep_alive_timeout.map(|t| last_active + t);
+        match (idle, keep_alive) {
+            (Some(idle), Some(keep_alive)) => Some(idle.min(keep_alive)),
+            (idle, keep_alive) => idle.or(keep_alive),
+        }
+    }
+
+    /// Returns when writing to the connection should give up.
+    fn write_deadline(&self) -> Option<Instant> {
+        if self.upgraded.load(Ordering::SeqCst) {
+            return None;
+        }
+        let last_active = *self.last_active.lock().unwrap();
+        self.lifetime.idle_timeout.map(|t| last_active + t)
+    }
+}
+
+/// Wraps the stream for a new connection so that it's closed according to
+/// `lifetime`, which is enforced with the help of the returned tracker.
+pub(crate) fn wrap<I>(
+    stream: I,
+    lifetime: ConnectionLifetime,
+) -> (KeepAliveStream<I>, ConnectionRequests) {
+    let shared = Arc::new(Shared {
+        lifetime,
+        in_flight: AtomicUsize::new(0),
+        requests: AtomicU64::new(0),
+        upgraded: AtomicBool::new(false),
+        last_active: Mutex::new(Instant::now()),
+        waker: Mutex::new(None),
+    });
+    let requests = ConnectionRequests { shared: Arc::clone(&shared) };
+    let stream = KeepAliveStream {
+        inner: stream,
+        shared,
+        read_sleep: None,
+        write_sleep: None,
+    };
+    (stream, requests)
+}
+
+/// Tracks the requests on a particular connection
+#[derive(Clone, Debug)]
+pub(crate) struct ConnectionRequests {
+    shared: Arc<Shared>,
+}
+
+impl ConnectionRequests {
+    /// Returns whether requests need to be tracked at all.
+    pub fn enabled(&self) -> bool {
+        let lifetime = &self.shared.lifetime;
+        lifetime.keep_alive_timeout.is_some() || lifetime.max_requests.is_some()
+    }
+
+    /// Counts a request that has arrived, until the returned guard is
+    /// dropped.
+    pub fn begin(&self) -> RequestInProgress {
+        self.shared.in_flight.fetch_add(1, Ordering::SeqCst);
+        let count = self.shared.requests.fetch_add(1, Ordering::SeqCst) + 1;
+        let last =
+            self.shared.lifetime.max_requests.map_or(false, |max| count >= max);
+        RequestInProgress { shared: Arc::clone(&self.shared), last }
+    }
+
+    /// Leaves the connection alone from now on, since it's been upgraded to
+    /// another protocol (e.g., WebSockets).
+    pub fn upgraded(&self) {
+        self.shared.upgraded.store(true, Ordering::SeqCst);
+    }
+}
+
+/// Counts a request as in progress until it's dropped (see
+/// [`ConnectionRequests::begin()`])
+#[derive(Debug)]
+pub(crate) struct RequestInProgress {
+    shared: Arc<Shared>,
+    last: bool,
+}
+
+impl RequestInProgress {
+    /// Returns whether this is the last request to be served on the
+    /// connection.
+    pub fn is_last(&self) -> bool {
+        self.last
+    }
+
+    /// Returns `body`, which keeps the request in progress until it's been
+    /// sent.
+    pub fn hold_until_sent(self, body: Body) -> Body {
+        Body::wrap(HeldBody { body, _request: self })
+    }
+}
+
+impl Drop for RequestInProgress {
+    fn drop(&mut self) {
+        self.shared.touch();
+        self.shared.in_flight.fetch_sub(1, Ordering::SeqCst);
+        if let Some(waker) = self.shared.waker.lock().unwrap().take() {
+            waker.wake();
+        }
+    }
+}
+
+/// A response body that keeps its request in progress until it's dropped
+struct HeldBody {
+    body: Body,
+    _request: RequestInProgress,
+}
+
+impl http_body::Body for HeldBody {
+    type Data = Bytes;
+    type Error = BoxError;
+
+    fn poll_frame(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Frame<Bytes>, BoxError>>> {
+        Pin::new(&mut self.get_mut().body).poll_frame(cx)
+    }
+
+    fn is_end_stream(&self) -> bool {
+        self.body.is_end_stream()
+    }
+
+    fn size_hint(&self) -> SizeHint {
+        self.body.size_hint()
+    }
+}
+
+/// Connection stream that ends once the connection should be closed
+pub(crate) struct KeepAliveStream<I> {
+    inner: I,
+    shared: Arc<Shared>,
+    read_sleep: Option<Pin<Box<Sleep>>>,
+    write_sleep: Option<Pin<Box<Sleep>>>,
+}
+
+/// Returns whether `deadline` has passed, arranging for the current task to be
+/// woken when it does if it hasn't.
+fn expired(
+    sleep: &mut Option<Pin<Box<Sleep>>>,
+    deadline: Instant,
+    cx: &mut Context<'_>,
+) -> bool {
+    let sleep = match sleep {
+        Some(sleep) => {
+            sleep.as_mut().reset(deadline);
+            sleep
+        }
+        None => sleep.insert(Box::pin(tokio::time::sleep_until(deadline))),
+    };
+    sleep.as_mut().poll(cx).is_ready()
+}
+
+impl<I: AsyncRead + Unpin> AsyncRead for KeepAliveStream<I> {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        let before = buf.filled().len();
+        match Pin::new(&mut this.inner).poll_read(cx, buf) {
+            Poll::Ready(Ok(())) => {
+                if buf.filled().len() > before {
+                    this.shared.touch();
+                }
+                return Poll::Ready(Ok(()));
+            }
+            Poll::Ready(Err(e)) => return Poll::Ready(Err(e)),
+            Poll::Pending => {}
+        }
+
+        // There's nothing to read, so this is when to decide whether to close
+        // the connection.
+        *this.shared.waker.lock().unwrap() = Some(cx.waker().clone());
+        match this.shared.read_deadline() {
+            // Reading nothing more signals the end of the stream.
+            Some(deadline) if expired(&mut this.read_sleep, deadline, cx) => {
+                Poll::Ready(Ok(()))
+            }
+            _ => Poll::Pending,
+        }
+    }
+}
+
+impl<I: AsyncWrite + Unpin> AsyncWrite for KeepAliveStream<I> {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        let this = self.get_mut();
+        let result = Pin::new(&mut this.inner).poll_write(cx, buf);
+        this.after_write(cx, result)
+    }
+
+    fn poll_write_vectored(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        bufs: &[io::IoSlice<'_>],
+    ) -> Poll<io::Result<usize>> {
+        let this = self.get_mut();
+        let result = Pin::new(&mut this.inner).poll_write_vectored(cx, bufs);
+        this.after_write(cx, result)
+    }
+
+    fn is_write_vectored(&self) -> bool {
+        self.inner.is_write_vectored()
+    }
+
+    fn poll_flush(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.get_mut().inner).poll_flush(cx)
+    }
+
+    fn poll_shutdown(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.get_mut().inner).poll_shutdown(cx)
+    }
+}
+
+impl<I> KeepAliveStream<I> {
+    /// Records the outcome of a write, failing one that has been blocked for
+    /// longer than the idle timeout.
+    fn after_write(
+        &mut self,
+        cx: &mut Context<'_>,
+        result: Poll<io::Result<usize>>,
+    ) -> Poll<io::Result<usize>> {
+        match result {
+            Poll::Ready(Ok(n)) => {
+                if n > 0 {
+                    self.shared.touch();
+                }
+                Poll::Ready(Ok(n))
+            }
+            Poll::Ready(Err(e)) => Poll::Ready(Err(e)),
+            Poll::Pending => match self.shared.write_deadline() {
+                Some(deadline)
+                    if expired(&mut self.write_sleep, deadline, cx) =>
+                {
+                    Poll::Ready(Err(io::Error::new(
+                        io::ErrorKind::TimedOut,
+                        "connection idle timeout",
+                    )))
+                }
+                _ => Poll::Pending,
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::wrap;
+    use super::ConnectionLifetime;
+    use std::time::Duration;
+    use tokio::io::AsyncReadExt;
+    use tokio::io::AsyncWriteExt;
+
+    #[tokio::test(start_paused = true)]
+    async fn test_keep_alive_timeout() {
+        let (client, server) = tokio::io::duplex(64);
+        let (mut stream, requests) = wrap(
+            server,
+            ConnectionLifetime {
+                keep_alive_timeout: Some(Duration::from_secs(5)),
+                ..Default::default()
+            },
+        );
+        let mut client = client;
+        client.write_all(b"hello").await.unwrap();
+        let mut buf = [0; 5];
+        stream.read_exact(&mut buf).await.unwrap();
+
+        // While a request is in progress, the connection stays open.
+        let request = requests.begin();
+        assert!(!request.is_last());
+        let read = tokio::time::timeout(
+            Duration::from_secs(60),
+            stream.read(&mut buf),
+        );
+        assert!(read.await.is_err());
+
+        // Once it's done, the connection is closed after the timeout.
+        drop(request);
+        let start = tokio::time::Instant::now();
+        assert_eq!(stream.read(&mut buf).await.unwrap(), 0);
+        assert_eq!(start.elapsed(), Duration::from_secs(5));
+    }
+
+    #[tokio::test]
+    async fn test_max_requests() {
+        let (_client, server) = tokio::io::duplex(64);
+        let (mut stream, requests) = wrap(
+            server,
+            ConnectionLifetime { max_requests: Some(2), ..Default::default() },
+        );
+        assert!(!requests.begin().is_last());
+        let request = requests.begin();
+        assert!(request.is_last());
+
+        // The connection is closed once its last request completes.
+        let mut buf = [0; 5];
+        let read = tokio::spawn(async move { stream.read(&mut buf).await });
+        tokio::task::yield_now().await;
+        drop(request);
+        assert_eq!(read.await.unwrap().unwrap(), 0);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_idle_timeout() {
+        let (_client, server) = tokio::io::duplex(4);
+        let (mut stream, requests) = wrap(
+            server,
+            ConnectionLifetime {
+                idle_timeout: Some(Duration::from_secs(5)),
+                ..Default::default()
+            },
+        );
+
+        // The idle timeout applies even with a request in progress.
+        let _request = requests.begin();
+        let mut buf = [0; 5];
+        assert_eq!(stream.read(&mut buf).await.unwrap(), 0);
+
+        // Writes that the client doesn't read time out, too.
+        let error = stream.write_all(b"too much data").await.unwrap_err();
+        assert_eq!(error.kind(), std::io::ErrorKind::TimedOut);
+    }
+}
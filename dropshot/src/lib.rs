@@ -69,7 +69,7 @@
 //!             &ConfigDropshot {
 //!                 bind_address: "127.0.0.1:0".parse().unwrap(),
 //!                 request_body_max_bytes: 1024,
-//!                 tls: None,
+//!                 ..Default::default()
 //!             },
 //!             api,
 //!             Arc::new(()),
@@ -191,6 +191,15 @@
 //!
 //!     // Optional fields
 //!     tags = [ "all", "your", "OpenAPI", "tags" ],
+//!     content_type = "application/json",
+//!     additional_content_types = [ "application/x-www-form-urlencoded" ],
+//!     log_level = "debug",
+//!     idempotency = "required",
+//!     coalesce = [ "authorization" ],
+//!     bandwidth_limit = 1048576,
+//!     request_body_max_bytes = 10485760,
+//!     compression = false,
+//!     timeout_secs = 30,
 //! }]
 //! ```
 //!
@@ -198,9 +207,66 @@
 //! for the API endpoint. These are used as part of endpoint registration and
 //! appear in the OpenAPI spec output.
 //!
+//! A path variable may be constrained by a pattern given after a colon: either
+//! the name of a format (currently only `uuid`, as in `/projects/{id:uuid}`)
+//! or a regular expression that the whole segment must match (as in
+//! `/items/{name:[a-z][a-z0-9-]*}`).  Requests whose segment doesn't match are
+//! rejected with a 404, as though there were no such route, and the pattern
+//! appears in the OpenAPI spec output as the `format` or `pattern` of the
+//! (string-typed) parameter.
+//!
+//! The pattern `.*` is special: it matches all remaining segments of the path
+//! (as in `/files/{path:.*}`).  The corresponding field of the path parameters
+//! struct may be a `Vec<String>` of the (percent-decoded) segments or a
+//! `String` holding the remainder of the path just as it appeared in the
+//! request, leaving the decoding to you.  Requests with segments that decode
+//! to `.` or `..`, or that contain `/` (as `%2F`) or NUL, are rejected with a
+//! 400, so that the segments can be safely joined back together.  For serving
+//! files, the field may be a [`SafePath`], which also rejects other segments
+//! that could escape a directory and resolves the path under one.
+//!
 //! The tags field is used to categorize API endpoints and only impacts the
 //! OpenAPI spec output.
 //!
+//! The content_type field specifies the media type of the request body
+//! accepted by [`TypedBody`] (JSON, by default), and additional_content_types
+//! lists other media types that it should accept as well.  Each request's body
+//! is parsed according to its `Content-Type`, and all of these types appear in
+//! the OpenAPI spec output.
+//!
+//! The log_level field overrides the level of log entries scoped to requests
+//! for this endpoint (including those emitted through `RequestContext::log`),
+//! making them either more or less verbose than the rest of the server's.  It
+//! can be changed while the server is running with
+//! [`HttpServer::set_endpoint_log_level`].
+//!
+//! The idempotency field (`"optional"` or `"required"`) lets clients safely
+//! retry requests by sending an `Idempotency-Key` header.  See
+//! [`idempotency`].
+//!
+//! The coalesce field (GET endpoints only) makes concurrent identical requests
+//! share a single invocation of the handler.  Requests are identical if they
+//! have the same path, query string, and values of the listed headers.  See
+//! [`ApiEndpoint::coalesce`].
+//!
+//! The bandwidth_limit field limits the combined rate, in bytes per second, at
+//! which the endpoint's response bodies are sent.  It can be changed while the
+//! server is running with [`HttpServer::set_endpoint_bandwidth_limit`].
+//!
+//! The request_body_max_bytes field overrides the server's
+//! `request_body_max_bytes` for the endpoint's request bodies.  A request that
+//! asks to continue (`Expect: 100-continue`) and declares a larger
+//! `Content-Length` is rejected with a 413 without its body being read.
+//!
+//! The compression field, if `false`, prevents the endpoint's responses from
+//! being compressed when the server is configured to compress responses (see
+//! `ConfigDropshot::compression`).
+//!
+//! The timeout_secs field limits how long the endpoint's handler may run.  A
+//! handler that's still running when the timeout elapses is cancelled (its
+//! future is dropped) and the request fails with a 503, so that a hung backend
+//! doesn't hold the client's connection indefinitely.
+//!
 //!
 //! ### Function parameters
 //!
@@ -226,22 +292,49 @@
 //!
 //! * [`Query`]`<Q>` extracts parameters from a query string, deserializing them
 //!   into an instance of type `Q`. `Q` must implement `serde::Deserialize` and
-//!   `schemars::JsonSchema`.
+//!   `schemars::JsonSchema`.  Besides scalars, its fields may be sequences,
+//!   given by repeating the parameter (`?id=1&id=2`), or structs of scalars,
+//!   given in the OpenAPI `deepObject` style (`?filter[name]=foo`).
 //! * [`Path`]`<P>` extracts parameters from HTTP path, deserializing them into
 //!   an instance of type `P`. `P` must implement `serde::Deserialize` and
-//!   `schemars::JsonSchema`.
+//!   `schemars::JsonSchema`.  Rather than deriving `serde::Deserialize`, `P`
+//!   may derive [`PathParams`], which parses each field with `FromStr`: fields
+//!   that couldn't come from a path segment fail to compile, and errors name
+//!   the offending parameter.
+//! * [`Header`]`<H>` extracts request headers, deserializing them into an
+//!   instance of type `H`, each of whose fields is a header named by the field
+//!   in kebab-case (e.g., `x_request_id` is `x-request-id`).  `H` must
+//!   implement `serde::Deserialize` and `schemars::JsonSchema`.
+//! * [`Accept`] parses the `Accept` header, ranking the media types that the
+//!   client will accept, so that the handler can choose among the
+//!   representations it supports (see [`Accept::negotiate()`] and
+//!   [`Negotiated`]).
+//! * [`Extension`]`<T>` extracts the value of type `T` that was attached to the
+//!   request's [`Extensions`] before it was handled (e.g., the authenticated
+//!   principal, attached by a [`ContinueCheck`]).
 //! * [`TypedBody`]`<J>` extracts content from the request body by parsing the
-//!   body as JSON (or form/url-encoded) and deserializing it into an instance
-//!   of type `J`. `J` must implement `serde::Deserialize` and `schemars::JsonSchema`.
+//!   body as JSON (or form/url-encoded data, MessagePack, CBOR, XML, or YAML,
+//!   according to the endpoint's `content_type`) and deserializing it into an
+//!   instance of type `J`. `J` must implement `serde::Deserialize` and `schemars::JsonSchema`.
+//!   A compressed body (e.g., `Content-Encoding: gzip`) is decompressed first;
+//!   see [`ContentDecoder`].  Form/url-encoded data may describe nested
+//!   structures and sequences using brackets in its keys, as in
+//!   `filter[name]=x&ids[]=1&ids[]=2`.
+//! * [`TypedStream`]`<J>` parses a newline-delimited JSON
+//!   (`application/x-ndjson`) request body incrementally, providing a `Stream`
+//!   of instances of type `J`, one per line.
 //! * [`UntypedBody`] extracts the raw bytes of the request body.
-//! * [`RawRequest`] provides access to the underlying [`hyper::Request`].  The
+//! * [`RawBody`] provides the request body as it arrives, followed by any
+//!   trailers.
+//! * [`RawRequest`] provides access to the underlying [`http::Request`].  The
 //!   hope is that this would generally not be needed.  It can be useful to
 //!   implement functionality not provided by Dropshot.
 //!
-//! `Query` and `Path` impl `SharedExtractor`.  `TypedBody`, `UntypedBody`, and
-//! `RawRequest` impl `ExclusiveExtractor`.  Your function may accept 0-3
-//! extractors, but only one can be `ExclusiveExtractor`, and it must be the
-//! last one.  Otherwise, the order of extractor arguments does not matter.
+//! `Query`, `Path`, `Header`, `Accept`, and `Extension` impl
+//! `SharedExtractor`.  `TypedBody`, `UntypedBody`, and `RawRequest` impl
+//! `ExclusiveExtractor`.  Your function may accept 0-3 extractors, but only one
+//! can be `ExclusiveExtractor`, and it must be the last one.  Otherwise, the
+//! order of extractor arguments does not matter.
 //!
 //! If the handler accepts any extractors and the corresponding extraction
 //! cannot be completed, the request fails with status code 400 and an error
@@ -258,8 +351,8 @@
 //! use dropshot::TypedBody;
 //! use dropshot::Query;
 //! use dropshot::RequestContext;
-//! use hyper::Body;
-//! use hyper::Response;
+//! use dropshot::Body;
+//! use http::Response;
 //! use schemars::JsonSchema;
 //! use serde::Deserialize;
 //! use std::sync::Arc;
@@ -287,6 +380,10 @@
 //! }
 //! ```
 //!
+//! `Query`, `Path`, and `TypedBody` also dereference to the value they
+//! extracted (and implement `AsRef` for it), so a handler that only needs to
+//! read it can write `query.limit` without calling `into_inner()` first.
+//!
 //! ### Endpoint function return types
 //!
 //! Endpoint handler functions are async, so they always return a `Future`.  When
@@ -320,6 +417,41 @@
 //! | [`HttpResponseDeleted`] | 204 |
 //! | [`HttpResponseUpdatedNoContent`] | 204 |
 //!
+//! The body is serialized as JSON unless it's wrapped in [`Cbor`], as in
+//! `HttpResponseOk<Cbor<Project>>`, in which case it's serialized as CBOR.
+//!
+//! To send a large collection without first collecting it into memory, wrap a
+//! `Stream` of its items in [`JsonArrayStream`] (for a JSON array) or
+//! [`NdjsonStream`] (for newline-delimited JSON), as in
+//! `HttpResponseOk<JsonArrayStream<Project>>`.  [`HttpResponseNdjson`] is a
+//! 200 response of newline-delimited JSON that also asks proxies to pass each
+//! line along as soon as it's sent.
+//!
+//! [`HttpResponseSse`] streams server-sent events (`text/event-stream`), each
+//! of which carries a serialized object, to the client.
+//!
+//! [`HttpResponseAttachment`] streams a file (or anything else that implements
+//! `AsyncRead`) for the client to save under a given name.
+//!
+//! Any of these can be wrapped in [`HttpResponseCached`] to send a
+//! `Cache-Control` header built from a [`CachePolicy`].
+//!
+//! When the status code depends on what the handler finds at runtime (e.g., 200
+//! or 202), return [`HttpResponseStatus`], which declares the possible status
+//! codes with a [`ResponseStatusSet`] so that each appears in the OpenAPI spec.
+//!
+//! Handlers may fail with their own error type rather than [`HttpError`], as
+//! in `Result<HttpResponseOk<Project>, ProjectError>`, if it implements
+//! [`HttpResponseError`], which converts it into an `HttpError` and declares
+//! the status codes of its error responses for the OpenAPI spec.  Any error
+//! type that implements both `Into<HttpError>` and `JsonSchema` qualifies
+//! without more work; its schema describes the endpoint's error responses.
+//!
+//! Error responses have a JSON [`HttpErrorResponseBody`] by default.  With
+//! `ApiDescription::error_format(ErrorFormat::ProblemJson)`, they are instead
+//! RFC 7807 problem details ([`ProblemDetails`]) of type
+//! `application/problem+json`, both on the wire and in the OpenAPI spec.
+//!
 //! In situations where the response schema is not fixed, the endpoint should
 //! return `Response<Body>`, which also implements `HttpResponse`. Note that
 //! the OpenAPI spec will not include any status code or type information in
@@ -543,6 +675,33 @@
 //! {"ok":{"id":"a53696af-543d-452f-81b6-5a045dd9921d","local_addr":"127.0.0.1:61028","remote_addr":"127.0.0.1:57376","method":"PUT","path":"/counter","query":null}}
 //! {"ok":{"id":"a53696af-543d-452f-81b6-5a045dd9921d","local_addr":"127.0.0.1:61028","remote_addr":"127.0.0.1:57376","status_code":204,"message":""}}
 //! ```
+//!
+//! ## Feature flags
+//!
+//! Parts of Dropshot that pull in substantial dependencies are behind cargo
+//! features.  These are enabled by default:
+//!
+//! * `logging`: creating loggers from a [`ConfigLogging`] with
+//!   [`ConfigLogging::to_logger()`].  The configuration types themselves are
+//!   always available.
+//! * `openapi`: generating OpenAPI definitions with
+//!   [`ApiDescription::openapi()`].
+//! * `pagination`: [`PaginationParams`], [`ResultsPage`], and
+//!   [`RequestContext::page_limit()`].
+//! * `test-util`: the [`test_util`] module (implies `logging` and
+//!   `pagination`).
+//! * `tls`: serving HTTPS (see [`ConfigTls`]).  Without this feature, starting
+//!   a server whose configuration includes `tls` fails.
+//! * `websocket`: the `channel` macro and [`WebsocketUpgrade`].
+//!
+//! A server that only needs to serve JSON over HTTP can depend on Dropshot
+//! with `default-features = false` (adding back whichever of these it uses) for
+//! a much smaller dependency tree.  Features that are not enabled by default
+//! are `graphql` (see `ApiDescription::register_graphql()`, implies
+//! `websocket`), `http3` (experimental HTTP/3 support, see [`ConfigHttp3`],
+//! implies `tls`), `protobuf` (`ProtobufBody` and `Protobuf`, for Protocol
+//! Buffers request and response bodies), `static-files` (see
+//! `ApiDescription::register_static_files()`), and `usdt-probes` (see above).
 
 // Clippy's style advice is definitely valuable, but not worth the trouble for
 // automated enforcement.
@@ -560,26 +719,79 @@
 mod dtrace;
 
 mod api_description;
+mod attachment;
+mod bandwidth;
+mod body;
+mod cache_control;
+mod coalesce;
+#[cfg(feature = "compression")]
+mod compression;
+mod conditional;
 mod config;
+mod connection;
+mod decompress;
+mod disconnect;
+mod early_hints;
 mod error;
+mod error_responses;
+mod expect_continue;
+mod extensions;
 mod extractor;
+mod fallback;
+mod forwarded;
 mod from_map;
 mod handler;
+#[cfg(feature = "http3")]
+mod http3;
 mod http_util;
+mod json_stream;
+mod keep_alive;
+mod load_limits;
 mod logging;
+mod maintenance;
+#[cfg(feature = "openapi")]
+mod openapi;
+#[cfg(feature = "pagination")]
 mod pagination;
+#[cfg(feature = "protobuf")]
+mod protobuf;
+mod request_log;
+mod reuseport;
+mod route_pattern;
 mod router;
+mod safe_path;
 mod schema_util;
 mod server;
+mod settings;
+mod slow_request;
+mod socket_activation;
+mod socket_options;
+mod sse;
+mod strict_http;
+mod tenancy;
+#[cfg(feature = "tls")]
+mod tls;
 mod to_map;
 mod type_util;
+#[cfg(feature = "websocket")]
 mod websocket;
 
+pub mod admin;
+#[cfg(feature = "graphql")]
+pub mod graphql;
+pub mod health;
+pub mod hooks;
+pub mod idempotency;
+pub mod proxy;
+#[cfg(feature = "static-files")]
+pub mod static_files;
+#[cfg(feature = "test-util")]
 pub mod test_util;
 
 #[macro_use]
 extern crate slog;
 
+pub use admin::AdminContext;
 pub use api_description::ApiDescription;
 pub use api_description::ApiEndpoint;
 pub use api_description::ApiEndpointBodyContentType;
@@ -588,26 +800,77 @@ pub use api_description::ApiEndpointParameterLocation;
 pub use api_description::ApiEndpointResponse;
 pub use api_description::EndpointTagPolicy;
 pub use api_description::ExtensionMode;
-pub use api_description::OpenApiDefinition;
+pub use api_description::RegisterError;
 pub use api_description::TagConfig;
 pub use api_description::TagDetails;
 pub use api_description::TagExternalDocs;
+pub use attachment::HttpResponseAttachment;
+pub use body::Body;
+pub use body::BoxError;
+pub use body::ResponseBodyWriter;
+pub use cache_control::CachePolicy;
+pub use cache_control::HttpResponseCached;
+pub use conditional::EntityTag;
+pub use conditional::HttpResponseConditional;
+pub use conditional::Precondition;
+pub use config::ClientAuthMode;
+pub use config::CompressionCoding;
+pub use config::ConfigCompression;
 pub use config::ConfigDropshot;
+pub use config::ConfigHttp3;
+pub use config::ConfigListener;
+pub use config::ConfigLoadLimits;
+pub use config::ConfigLogRedaction;
+pub use config::ConfigMethodOverride;
+pub use config::ConfigRequestLogSampling;
+pub use config::ConfigReusePort;
+pub use config::ConfigSlowRequestLogging;
+pub use config::ConfigSocketOptions;
+pub use config::ConfigStrictHttp;
 pub use config::ConfigTls;
+pub use config::ConfigTlsClientAuth;
+pub use config::ConfigTlsSniCertificate;
+pub use config::ConfigUnixSocket;
+pub use config::HandlerTaskMode;
+pub use connection::ConnectionInfo;
+pub use connection::PeerCertificate;
+pub use connection::PeerCredentials;
+pub use decompress::ContentDecoder;
 pub use dtrace::ProbeRegistration;
 pub use error::HttpError;
 pub use error::HttpErrorResponseBody;
+pub use error::HttpResponseError;
+pub use error_responses::CustomErrorBody;
+pub use error_responses::ErrorFormat;
+pub use error_responses::ErrorMapper;
+pub use error_responses::ErrorResponseCustomizer;
+pub use error_responses::GeneratedErrorKind;
+pub use error_responses::ProblemDetails;
+pub use expect_continue::ContinueCheck;
+pub use extensions::Extensions;
+pub use extractor::Accept;
 pub use extractor::ExclusiveExtractor;
+pub use extractor::Extension;
 pub use extractor::ExtractorMetadata;
+pub use extractor::Header;
+pub use extractor::MediaRange;
+pub use extractor::MultipartBody;
+pub use extractor::MultipartPart;
 pub use extractor::Path;
+pub use extractor::Preconditions;
 pub use extractor::Query;
+pub use extractor::RawBody;
 pub use extractor::RawRequest;
 pub use extractor::SharedExtractor;
 pub use extractor::TypedBody;
+pub use extractor::TypedStream;
 pub use extractor::UntypedBody;
+pub use fallback::FallbackHandler;
+pub use forwarded::EffectiveClient;
 pub use handler::http_response_found;
 pub use handler::http_response_see_other;
 pub use handler::http_response_temporary_redirect;
+pub use handler::Cbor;
 pub use handler::FreeformBody;
 pub use handler::HttpCodedResponse;
 pub use handler::HttpResponse;
@@ -618,31 +881,84 @@ pub use handler::HttpResponseFound;
 pub use handler::HttpResponseHeaders;
 pub use handler::HttpResponseOk;
 pub use handler::HttpResponseSeeOther;
+pub use handler::HttpResponseStatus;
 pub use handler::HttpResponseTemporaryRedirect;
 pub use handler::HttpResponseUpdatedNoContent;
+pub use handler::Negotiated;
 pub use handler::NoHeaders;
 pub use handler::RequestContext;
 pub use handler::RequestInfo;
+pub use handler::ResponseStatusSet;
+pub use hooks::RequestHook;
+pub use http_util::CONTENT_TYPE_CBOR;
+pub use http_util::CONTENT_TYPE_EVENT_STREAM;
 pub use http_util::CONTENT_TYPE_JSON;
+pub use http_util::CONTENT_TYPE_MSGPACK;
+pub use http_util::CONTENT_TYPE_MULTIPART_FORM_DATA;
 pub use http_util::CONTENT_TYPE_NDJSON;
 pub use http_util::CONTENT_TYPE_OCTET_STREAM;
+pub use http_util::CONTENT_TYPE_PROBLEM_JSON;
+pub use http_util::CONTENT_TYPE_PROTOBUF;
 pub use http_util::CONTENT_TYPE_URL_ENCODED;
+pub use http_util::CONTENT_TYPE_XML;
+pub use http_util::CONTENT_TYPE_YAML;
 pub use http_util::HEADER_REQUEST_ID;
+pub use idempotency::IdempotencyMode;
+pub use idempotency::IdempotencyStore;
+pub use idempotency::InMemoryIdempotencyStore;
+pub use json_stream::HttpResponseNdjson;
+pub use json_stream::JsonArrayStream;
+pub use json_stream::NdjsonStream;
 pub use logging::ConfigLogging;
 pub use logging::ConfigLoggingIfExists;
 pub use logging::ConfigLoggingLevel;
+pub use logging::ConfigSyslogTransport;
+pub use logging::RequestLevelFilter;
+pub use maintenance::MaintenanceMode;
+#[cfg(feature = "openapi")]
+pub use openapi::OpenApiDefinition;
+#[cfg(feature = "pagination")]
 pub use pagination::EmptyScanParams;
+#[cfg(feature = "pagination")]
 pub use pagination::PaginationOrder;
+#[cfg(feature = "pagination")]
 pub use pagination::PaginationParams;
+#[cfg(feature = "pagination")]
 pub use pagination::ResultsPage;
+#[cfg(feature = "pagination")]
 pub use pagination::WhichPage;
+#[cfg(feature = "protobuf")]
+pub use protobuf::Protobuf;
+#[cfg(feature = "protobuf")]
+pub use protobuf::ProtobufBody;
+pub use reuseport::AcceptorStats;
+pub use router::ConflictingEndpoint;
+pub use router::InvalidUtf8;
+pub use router::PathDecoding;
+pub use router::RouteConflict;
+pub use router::TrailingSlash;
+pub use safe_path::SafePath;
 pub use server::ServerContext;
+pub use server::ShutdownReport;
 pub use server::ShutdownWaitFuture;
 pub use server::{HttpServer, HttpServerStarter};
+pub use settings::ServerSettings;
+pub use settings::ServerSettingsHandle;
+pub use sse::HttpResponseSse;
+pub use sse::SseEvent;
+pub use strict_http::StrictHttpRejections;
+pub use tenancy::ResolvedTenant;
+pub use tenancy::TenantResolver;
+pub use tenancy::TenantSource;
+#[cfg(feature = "websocket")]
 pub use websocket::WebsocketChannelResult;
+#[cfg(feature = "websocket")]
 pub use websocket::WebsocketConnection;
+#[cfg(feature = "websocket")]
 pub use websocket::WebsocketConnectionRaw;
+#[cfg(feature = "websocket")]
 pub use websocket::WebsocketEndpointResult;
+#[cfg(feature = "websocket")]
 pub use websocket::WebsocketUpgrade;
 
 // Users of the `endpoint` macro need the following macros:
@@ -652,3 +968,12 @@ pub use http::Method;
 extern crate dropshot_endpoint;
 pub use dropshot_endpoint::channel;
 pub use dropshot_endpoint::endpoint;
+pub use dropshot_endpoint::PathParams;
+
+// Code generated by `#[derive(PathParams)]` uses these.
+#[doc(hidden)]
+pub mod __path_params {
+    pub use crate::http_util::parse_path_param as parse;
+    pub use crate::http_util::parse_path_params as parse_all;
+    pub use serde;
+}
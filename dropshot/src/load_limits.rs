@@ -0,0 +1,147 @@
+// Copyright 2023 Oxide Computer Company
+//! Shedding of connections and requests beyond a server's limits (see
+//! `ConfigDropshot::load_limits`)
+
+use crate::config::ConfigLoadLimits;
+use crate::error::HttpError;
+use crate::error_responses::format_error;
+use crate::error_responses::ErrorFormat;
+use crate::Body;
+
+use http::HeaderValue;
+use http::StatusCode;
+use hyper::Response;
+use std::sync::atomic::AtomicUsize;
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
+
+/// Tracks a server's open connections and decides which connections and
+/// requests to shed.  The limits themselves are among the server's settings,
+/// which can change while it runs (see `ServerSettings`).
+#[derive(Debug)]
+pub(crate) struct LoadLimits {
+    retry_after_secs: u64,
+    /// number of connections that are open (other than those being shed)
+    connections: Arc<AtomicUsize>,
+}
+
+impl LoadLimits {
+    pub fn new(config: &ConfigLoadLimits) -> LoadLimits {
+        LoadLimits {
+            retry_after_secs: config.retry_after_secs,
+            connections: Arc::new(AtomicUsize::new(0)),
+        }
+    }
+
+    /// Counts a newly accepted connection as open until the returned guard is
+    /// dropped.  Returns `None` if the server already has `max_connections`
+    /// open, in which case the connection should be shed.
+    pub fn accept_connection(
+        &self,
+        max_connections: Option<usize>,
+    ) -> Option<OpenConnection> {
+        let open = self.connections.fetch_add(1, Ordering::SeqCst) + 1;
+        let connection =
+            OpenConnection { connections: Arc::clone(&self.connections) };
+        match max_connections {
+            Some(max) if open > max => None,
+            _ => Some(connection),
+        }
+    }
+
+    /// Returns the number of connections that are open (not counting those
+    /// being shed).
+    pub fn open_connections(&self) -> usize {
+        self.connections.load(Ordering::SeqCst)
+    }
+
+    /// Returns whether a request should be shed, given the number of requests
+    /// in flight (including it) and the most that may be.
+    pub fn shed_request(
+        &self,
+        in_flight: usize,
+        max_in_flight_requests: Option<usize>,
+    ) -> bool {
+        max_in_flight_requests.map_or(false, |max| in_flight > max)
+    }
+
+    /// Returns the response with which a connection or request is shed.
+    pub fn overloaded_response(
+        &self,
+        format: ErrorFormat,
+        request_id: &str,
+        instance: &str,
+    ) -> Response<Body> {
+        let error = HttpError {
+            status_code: StatusCode::SERVICE_UNAVAILABLE,
+            error_code: Some(String::from("Overloaded")),
+            external_message: String::from(
+                "The service is temporarily overloaded.",
+            ),
+            internal_message: String::from("server is at its load limits"),
+        };
+        let mut response = format_error(error, format, request_id, instance);
+        response.headers_mut().insert(
+            http::header::RETRY_AFTER,
+            HeaderValue::from(self.retry_after_secs),
+        );
+        response
+    }
+}
+
+/// Counts a connection as open until it's dropped (see
+/// [`LoadLimits::accept_connection()`])
+#[derive(Debug)]
+pub(crate) struct OpenConnection {
+    connections: Arc<AtomicUsize>,
+}
+
+impl Drop for OpenConnection {
+    fn drop(&mut self) {
+        self.connections.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::LoadLimits;
+    use crate::config::ConfigLoadLimits;
+    use crate::error_responses::ErrorFormat;
+    use http::StatusCode;
+
+    #[test]
+    fn test_load_limits() {
+        let limits = LoadLimits::new(&ConfigLoadLimits::default());
+        let connections = (0..100)
+            .map(|_| limits.accept_connection(None).unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(limits.open_connections(), 100);
+        drop(connections);
+        assert_eq!(limits.open_connections(), 0);
+        assert!(!limits.shed_request(1000, None));
+
+        let limits = LoadLimits::new(&ConfigLoadLimits {
+            retry_after_secs: 5,
+            ..Default::default()
+        });
+        let first = limits.accept_connection(Some(2)).unwrap();
+        let _second = limits.accept_connection(Some(2)).unwrap();
+        assert!(limits.accept_connection(Some(2)).is_none());
+        assert_eq!(limits.open_connections(), 2);
+        drop(first);
+        let _third = limits.accept_connection(Some(2)).unwrap();
+        assert!(limits.accept_connection(Some(2)).is_none());
+        // A new limit applies to the next connection.
+        assert!(limits.accept_connection(Some(3)).is_some());
+        assert!(!limits.shed_request(3, Some(3)));
+        assert!(limits.shed_request(4, Some(3)));
+
+        let response =
+            limits.overloaded_response(ErrorFormat::Dropshot, "req", "/");
+        assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(
+            response.headers().get(http::header::RETRY_AFTER).unwrap(),
+            "5"
+        );
+    }
+}
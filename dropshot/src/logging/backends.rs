@@ -1,70 +1,27 @@
-// Copyright 2020 Oxide Computer Company
-//! Provides basic facilities for configuring logging and creating loggers, all
-//! using Slog.  None of these facilities are required for this crate, but
-//! they're provided because they're commonly wanted by consumers of this crate.
-
-use camino::Utf8PathBuf;
-use serde::Deserialize;
-use serde::Serialize;
+// Copyright 2023 Oxide Computer Company
+//! Creating loggers from a [`ConfigLogging`]
+
+use super::ConfigLogging;
+use super::ConfigLoggingIfExists;
+use super::ConfigLoggingLevel;
+use super::ConfigSyslogTransport;
+use super::RequestLevelFilter;
+
+use chrono::DateTime;
+use chrono::SecondsFormat;
+use chrono::Utc;
 use slog::Drain;
 use slog::Level;
 use slog::Logger;
+use slog::OwnedKVList;
+use slog::Record;
+use slog::KV;
+use std::fmt;
 use std::fs::OpenOptions;
 use std::io::LineWriter;
+use std::net::SocketAddr;
 use std::{io, path::Path};
 
-/// Represents the logging configuration for a server.  This is expected to be a
-/// top-level block in a TOML config file, although that's not required.
-#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
-#[serde(rename_all = "kebab-case", tag = "mode")]
-pub enum ConfigLogging {
-    /// Pretty-printed output to stderr, assumed to support terminal escapes.
-    StderrTerminal { level: ConfigLoggingLevel },
-    /// Bunyan-formatted output to a specified file.
-    File {
-        level: ConfigLoggingLevel,
-        path: Utf8PathBuf,
-        if_exists: ConfigLoggingIfExists,
-    },
-}
-
-/// Log messages have a level that's used for filtering in the usual way.
-#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
-#[serde(rename_all = "lowercase")]
-pub enum ConfigLoggingLevel {
-    Trace,
-    Debug,
-    Info,
-    Warn,
-    Error,
-    Critical,
-}
-
-impl From<&ConfigLoggingLevel> for Level {
-    fn from(config_level: &ConfigLoggingLevel) -> Level {
-        match config_level {
-            ConfigLoggingLevel::Trace => Level::Trace,
-            ConfigLoggingLevel::Debug => Level::Debug,
-            ConfigLoggingLevel::Info => Level::Info,
-            ConfigLoggingLevel::Warn => Level::Warning,
-            ConfigLoggingLevel::Error => Level::Error,
-            ConfigLoggingLevel::Critical => Level::Critical,
-        }
-    }
-}
-
-/// Specifies the behavior when logging to a file that already exists.
-#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
-#[serde(rename_all = "lowercase")]
-pub enum ConfigLoggingIfExists {
-    /// Fail to create the log
-    Fail,
-    /// Truncate the existing file
-    Truncate,
-    /// Append to the existing file
-    Append,
-}
-
 impl ConfigLogging {
     /// Create a root logger based on the requested configuration.
     pub fn to_logger<S: AsRef<str>>(
@@ -103,6 +60,25 @@ impl ConfigLogging {
                 )?;
                 Ok(async_root_logger(level, drain))
             }
+
+            // For both syslog and journald, failing to send a message is not
+            // worth panicking over, so we discard those errors.
+            ConfigLogging::Syslog { level, transport } => {
+                let drain = SyslogDrain::new(transport, log_name.as_ref())?;
+                Ok(async_root_logger(level, drain.ignore_res()))
+            }
+
+            #[cfg(unix)]
+            ConfigLogging::Journald { level } => {
+                let drain = JournaldDrain::new(log_name.as_ref())?;
+                Ok(async_root_logger(level, drain.ignore_res()))
+            }
+
+            #[cfg(not(unix))]
+            ConfigLogging::Journald { .. } => Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                "journald logging is only supported on Unix systems",
+            )),
         }
     }
 }
@@ -117,9 +93,13 @@ where
     T: slog::Drain + Send + 'static,
     <T as slog::Drain>::Err: std::fmt::Debug,
 {
-    let level_drain = slog::LevelFilter(drain, Level::from(level)).fuse();
-    let async_drain = slog_async::Async::new(level_drain).build().fuse();
-    slog::Logger::root(async_drain, o!())
+    // Entries are filtered by level on the thread that logged them, where an
+    // endpoint's level override can let them through (see
+    // `RequestLevelFilter`).
+    let async_drain = slog_async::Async::new(drain.fuse()).build().fuse();
+    let level_drain =
+        RequestLevelFilter::new(async_drain, Level::from(level)).fuse();
+    slog::Logger::root(level_drain, o!())
 }
 
 fn log_drain_for_file(
@@ -148,6 +128,309 @@ fn log_drain_for_file(
     Ok(slog_bunyan::with_name(log_name_leaked, file).build().fuse())
 }
 
+/// Collects the key-value pairs associated with a log record as strings.
+struct KvCollector(Vec<(String, String)>);
+
+impl slog::Serializer for KvCollector {
+    fn emit_arguments(
+        &mut self,
+        key: slog::Key,
+        val: &fmt::Arguments,
+    ) -> slog::Result {
+        self.0.push((key.to_string(), val.to_string()));
+        Ok(())
+    }
+}
+
+fn record_kvs(record: &Record, values: &OwnedKVList) -> Vec<(String, String)> {
+    let mut collector = KvCollector(Vec::new());
+    // KvCollector itself never fails, so there's nothing to do with errors.
+    let _ = record.kv().serialize(record, &mut collector);
+    let _ = values.serialize(record, &mut collector);
+    collector.0
+}
+
+/// syslog facility used for all messages ("daemon")
+const SYSLOG_FACILITY: u8 = 3;
+
+/// SD-ID used for the structured data element carrying key-value pairs.  RFC
+/// 5424 requires enterprise-specific SD-IDs to include an IANA private
+/// enterprise number.  32473 is the number reserved for documentation (RFC
+/// 5612), which is the best we can do without one of our own.
+const SYSLOG_SD_ID: &str = "dropshot@32473";
+
+fn syslog_severity(level: Level) -> u8 {
+    match level {
+        Level::Critical => 2,
+        Level::Error => 3,
+        Level::Warning => 4,
+        Level::Info => 6,
+        Level::Debug | Level::Trace => 7,
+    }
+}
+
+/// Returns `s` with anything that's not printable US-ASCII replaced, truncated
+/// to `max_len` bytes, as RFC 5424 requires for header fields and SD-NAMEs.
+fn syslog_sanitize(s: &str, max_len: usize, also_bad: &[char]) -> String {
+    let rv: String = s
+        .chars()
+        .map(|c| {
+            if c.is_ascii_graphic() && !also_bad.contains(&c) {
+                c
+            } else {
+                '_'
+            }
+        })
+        .take(max_len)
+        .collect();
+    if rv.is_empty() {
+        String::from("-")
+    } else {
+        rv
+    }
+}
+
+/// Formats one RFC 5424 syslog message.
+fn syslog_format(
+    level: Level,
+    timestamp: &DateTime<Utc>,
+    hostname: &str,
+    app_name: &str,
+    pid: u32,
+    message: &str,
+    kvs: &[(String, String)],
+) -> String {
+    let pri = SYSLOG_FACILITY * 8 + syslog_severity(level);
+    let structured_data = if kvs.is_empty() {
+        String::from("-")
+    } else {
+        let params = kvs
+            .iter()
+            .map(|(key, value)| {
+                let mut escaped = String::with_capacity(value.len());
+                for c in value.chars() {
+                    if matches!(c, '"' | '\\' | ']') {
+                        escaped.push('\\');
+                    }
+                    escaped.push(c);
+                }
+                format!(
+                    "{}=\"{}\"",
+                    syslog_sanitize(key, 32, &['=', ']', '"']),
+                    escaped
+                )
+            })
+            .collect::<Vec<_>>()
+            .join(" ");
+        format!("[{} {}]", SYSLOG_SD_ID, params)
+    };
+
+    format!(
+        "<{}>1 {} {} {} {} - {} {}",
+        pri,
+        timestamp.to_rfc3339_opts(SecondsFormat::Micros, true),
+        syslog_sanitize(hostname, 255, &[]),
+        syslog_sanitize(app_name, 48, &[]),
+        pid,
+        structured_data,
+        message,
+    )
+}
+
+enum SyslogSocket {
+    #[cfg(unix)]
+    Unix(std::os::unix::net::UnixDatagram),
+    Udp(std::net::UdpSocket),
+}
+
+/// Drain that sends each record as an RFC 5424 syslog message
+struct SyslogDrain {
+    socket: SyslogSocket,
+    hostname: String,
+    app_name: String,
+    pid: u32,
+}
+
+impl SyslogDrain {
+    fn new(
+        transport: &ConfigSyslogTransport,
+        app_name: &str,
+    ) -> Result<SyslogDrain, io::Error> {
+        let socket = match transport {
+            #[cfg(unix)]
+            ConfigSyslogTransport::Unix { path } => {
+                let socket = std::os::unix::net::UnixDatagram::unbound()?;
+                socket.connect(path)?;
+                SyslogSocket::Unix(socket)
+            }
+            #[cfg(not(unix))]
+            ConfigSyslogTransport::Unix { .. } => {
+                return Err(io::Error::new(
+                    io::ErrorKind::Unsupported,
+                    "syslog over a Unix domain socket is only supported on \
+                     Unix systems",
+                ));
+            }
+            ConfigSyslogTransport::Udp { address } => {
+                let bind_address: SocketAddr = if address.is_ipv4() {
+                    (std::net::Ipv4Addr::UNSPECIFIED, 0).into()
+                } else {
+                    (std::net::Ipv6Addr::UNSPECIFIED, 0).into()
+                };
+                let socket = std::net::UdpSocket::bind(bind_address)?;
+                socket.connect(address)?;
+                SyslogSocket::Udp(socket)
+            }
+        };
+
+        Ok(SyslogDrain {
+            socket,
+            hostname: hostname::get()?.to_string_lossy().into_owned(),
+            app_name: app_name.to_string(),
+            pid: std::process::id(),
+        })
+    }
+}
+
+impl Drain for SyslogDrain {
+    type Ok = ();
+    type Err = io::Error;
+
+    fn log(
+        &self,
+        record: &Record,
+        values: &OwnedKVList,
+    ) -> Result<Self::Ok, Self::Err> {
+        let message = syslog_format(
+            record.level(),
+            &Utc::now(),
+            &self.hostname,
+            &self.app_name,
+            self.pid,
+            &record.msg().to_string(),
+            &record_kvs(record, values),
+        );
+        match &self.socket {
+            #[cfg(unix)]
+            SyslogSocket::Unix(socket) => socket.send(message.as_bytes()),
+            SyslogSocket::Udp(socket) => socket.send(message.as_bytes()),
+        }
+        .map(|_| ())
+    }
+}
+
+/// Path to the socket on which journald receives native protocol messages
+#[cfg(unix)]
+const JOURNALD_SOCKET_PATH: &str = "/run/systemd/journal/socket";
+
+/// Returns `name` converted to a valid journal field name: uppercase letters,
+/// digits, and underscores, not starting with a digit or underscore (which
+/// would denote a trusted field), and at most 64 characters.  Returns `None` if
+/// nothing is left.
+#[cfg(unix)]
+fn journald_field_name(name: &str) -> Option<String> {
+    let rv: String = name
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() {
+                c.to_ascii_uppercase()
+            } else {
+                '_'
+            }
+        })
+        .skip_while(|c| *c == '_' || c.is_ascii_digit())
+        .take(64)
+        .collect();
+    if rv.is_empty() {
+        None
+    } else {
+        Some(rv)
+    }
+}
+
+#[cfg(unix)]
+fn journald_append_field(buf: &mut Vec<u8>, name: &str, value: &str) {
+    buf.extend_from_slice(name.as_bytes());
+    if value.contains('\n') {
+        // Values containing newlines use the binary-safe form, with an
+        // explicit length.
+        buf.push(b'\n');
+        buf.extend_from_slice(&(value.len() as u64).to_le_bytes());
+    } else {
+        buf.push(b'=');
+    }
+    buf.extend_from_slice(value.as_bytes());
+    buf.push(b'\n');
+}
+
+/// Formats one journald native protocol message.
+#[cfg(unix)]
+fn journald_format(
+    level: Level,
+    identifier: &str,
+    message: &str,
+    file: &str,
+    line: u32,
+    kvs: &[(String, String)],
+) -> Vec<u8> {
+    let mut buf = Vec::new();
+    journald_append_field(&mut buf, "MESSAGE", message);
+    journald_append_field(
+        &mut buf,
+        "PRIORITY",
+        &syslog_severity(level).to_string(),
+    );
+    journald_append_field(&mut buf, "SYSLOG_IDENTIFIER", identifier);
+    journald_append_field(&mut buf, "CODE_FILE", file);
+    journald_append_field(&mut buf, "CODE_LINE", &line.to_string());
+    for (key, value) in kvs {
+        if let Some(name) = journald_field_name(key) {
+            journald_append_field(&mut buf, &name, value);
+        }
+    }
+    buf
+}
+
+/// Drain that sends each record to journald
+// TODO-robustness Messages too large for a single datagram need to be passed to
+// journald via a sealed memfd instead.  Those messages are currently dropped.
+#[cfg(unix)]
+struct JournaldDrain {
+    socket: std::os::unix::net::UnixDatagram,
+    identifier: String,
+}
+
+#[cfg(unix)]
+impl JournaldDrain {
+    fn new(identifier: &str) -> Result<JournaldDrain, io::Error> {
+        let socket = std::os::unix::net::UnixDatagram::unbound()?;
+        socket.connect(JOURNALD_SOCKET_PATH)?;
+        Ok(JournaldDrain { socket, identifier: identifier.to_string() })
+    }
+}
+
+#[cfg(unix)]
+impl Drain for JournaldDrain {
+    type Ok = ();
+    type Err = io::Error;
+
+    fn log(
+        &self,
+        record: &Record,
+        values: &OwnedKVList,
+    ) -> Result<Self::Ok, Self::Err> {
+        let message = journald_format(
+            record.level(),
+            &self.identifier,
+            &record.msg().to_string(),
+            record.file(),
+            record.line(),
+            &record_kvs(record, values),
+        );
+        self.socket.send(&message).map(|_| ())
+    }
+}
+
 #[cfg(test)]
 mod test {
     use crate::test_util::read_bunyan_log;
@@ -198,8 +481,8 @@ mod test {
             .unwrap_err()
             .to_string();
         assert!(error.starts_with(
-            "unknown variant `bonkers`, expected `stderr-terminal` or `file` \
-             for key `mode`"
+            "unknown variant `bonkers`, expected one of `stderr-terminal`, \
+             `file`, `syslog`, `journald` for key `mode`"
         ));
     }
 
@@ -545,4 +828,104 @@ mod test {
         assert_eq!(log_records[1].msg, "message3_warn");
         assert_eq!(log_records[2].msg, "message3_error");
     }
+
+    // "mode = syslog" configurations
+
+    #[test]
+    fn test_config_bad_syslog_transport() {
+        let bad_config = r##"
+            mode = "syslog"
+            level = "info"
+            transport = { type = "carrier-pigeon" }
+            "##;
+        let error =
+            read_config::<ConfigLogging>("bad_syslog_transport", bad_config)
+                .unwrap_err()
+                .to_string();
+        assert!(error.starts_with(
+            "unknown variant `carrier-pigeon`, expected `unix` or `udp`"
+        ));
+    }
+
+    #[test]
+    fn test_config_syslog_udp() {
+        let receiver = std::net::UdpSocket::bind("127.0.0.1:0").unwrap();
+        receiver
+            .set_read_timeout(Some(std::time::Duration::from_secs(10)))
+            .unwrap();
+        let config = format!(
+            r#"
+            mode = "syslog"
+            level = "info"
+            transport = {{ type = "udp", address = "{}" }}
+            "#,
+            receiver.local_addr().unwrap()
+        );
+
+        {
+            // Construct the logger in a block so that it's flushed by the time
+            // we proceed.
+            let log =
+                read_config_and_create_logger("syslog_udp", &config).unwrap();
+            let log = log.new(o!("component" => "test"));
+            debug!(log, "message_debug");
+            warn!(log, "message_warn"; "quoted" => "a \"b\" [c]");
+        }
+
+        let mut buf = [0u8; 2048];
+        let n = receiver.recv(&mut buf).unwrap();
+        let message = std::str::from_utf8(&buf[..n]).unwrap();
+        let expected_hostname = hostname::get().unwrap().into_string().unwrap();
+
+        // facility "daemon" (3) * 8 + severity "warning" (4)
+        assert!(message.starts_with("<28>1 "), "message: {}", message);
+        let prefix = format!(
+            " {} test-logger {} - [dropshot@32473 ",
+            expected_hostname,
+            std::process::id()
+        );
+        assert!(message.contains(&prefix), "message: {}", message);
+        assert!(
+            message.ends_with(
+                r#"quoted="a \"b\" [c\]" component="test"] message_warn"#
+            ),
+            "message: {}",
+            message
+        );
+    }
+
+    // "mode = journald" encoding
+
+    #[cfg(unix)]
+    #[test]
+    fn test_journald_format() {
+        use super::journald_field_name;
+        use super::journald_format;
+
+        assert_eq!(journald_field_name("req_id").unwrap(), "REQ_ID");
+        assert_eq!(journald_field_name("remote-addr").unwrap(), "REMOTE_ADDR");
+        assert_eq!(journald_field_name("_PID").unwrap(), "PID");
+        assert_eq!(journald_field_name("0x").unwrap(), "X");
+        assert_eq!(journald_field_name("__"), None);
+
+        let message = journald_format(
+            slog::Level::Error,
+            "test-logger",
+            "two\nlines",
+            "src/lib.rs",
+            12,
+            &[("req_id".to_string(), "abc".to_string())],
+        );
+        let mut expected = b"MESSAGE\n".to_vec();
+        expected.extend_from_slice(&9u64.to_le_bytes());
+        expected.extend_from_slice(
+            b"two\nlines\n\
+            PRIORITY=3\n\
+            SYSLOG_IDENTIFIER=test-logger\n\
+            CODE_FILE=src/lib.rs\n\
+            CODE_LINE=12\n\
+            REQ_ID=abc\n",
+        );
+        assert_eq!(message, expected);
+    }
 }
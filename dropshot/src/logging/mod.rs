@@ -0,0 +1,284 @@
+// Copyright 2020 Oxide Computer Company
+//! Provides basic facilities for configuring logging and creating loggers, all
+//! using Slog.  None of these facilities are required for this crate, but
+//! they're provided because they're commonly wanted by consumers of this crate.
+
+use camino::Utf8PathBuf;
+use serde::Deserialize;
+use serde::Serialize;
+use slog::Drain;
+use slog::Level;
+use slog::Logger;
+use slog::OwnedKVList;
+use slog::Record;
+use std::cell::Cell;
+use std::net::SocketAddr;
+
+#[cfg(feature = "logging")]
+mod backends;
+
+/// Represents the logging configuration for a server.  This is expected to be a
+/// top-level block in a TOML config file, although that's not required.
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+#[serde(rename_all = "kebab-case", tag = "mode")]
+pub enum ConfigLogging {
+    /// Pretty-printed output to stderr, assumed to support terminal escapes.
+    StderrTerminal { level: ConfigLoggingLevel },
+    /// Bunyan-formatted output to a specified file.
+    File {
+        level: ConfigLoggingLevel,
+        path: Utf8PathBuf,
+        if_exists: ConfigLoggingIfExists,
+    },
+    /// RFC 5424 syslog messages sent to a local or remote syslog daemon.
+    /// Key-value pairs are sent as structured data.
+    Syslog {
+        level: ConfigLoggingLevel,
+        #[serde(default)]
+        transport: ConfigSyslogTransport,
+    },
+    /// Messages sent to systemd-journald using its native protocol.  Key-value
+    /// pairs are sent as journal fields, with names converted to uppercase.
+    /// This is only supported on Unix systems.
+    Journald { level: ConfigLoggingLevel },
+}
+
+/// Specifies where syslog messages are sent.
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+#[serde(rename_all = "kebab-case", tag = "type")]
+pub enum ConfigSyslogTransport {
+    /// Datagrams sent to a Unix domain socket.  This is the default, using
+    /// `/dev/log`.  This is only supported on Unix systems.
+    Unix { path: Utf8PathBuf },
+    /// Datagrams sent to a UDP address
+    Udp { address: SocketAddr },
+}
+
+impl Default for ConfigSyslogTransport {
+    fn default() -> Self {
+        ConfigSyslogTransport::Unix { path: Utf8PathBuf::from("/dev/log") }
+    }
+}
+
+/// Log messages have a level that's used for filtering in the usual way.
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ConfigLoggingLevel {
+    Trace,
+    Debug,
+    Info,
+    Warn,
+    Error,
+    Critical,
+}
+
+impl From<&ConfigLoggingLevel> for Level {
+    fn from(config_level: &ConfigLoggingLevel) -> Level {
+        match config_level {
+            ConfigLoggingLevel::Trace => Level::Trace,
+            ConfigLoggingLevel::Debug => Level::Debug,
+            ConfigLoggingLevel::Info => Level::Info,
+            ConfigLoggingLevel::Warn => Level::Warning,
+            ConfigLoggingLevel::Error => Level::Error,
+            ConfigLoggingLevel::Critical => Level::Critical,
+        }
+    }
+}
+
+/// Specifies the behavior when logging to a file that already exists.
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ConfigLoggingIfExists {
+    /// Fail to create the log
+    Fail,
+    /// Truncate the existing file
+    Truncate,
+    /// Append to the existing file
+    Append,
+}
+
+/// Drain that discards entries less severe than a given level, unless they
+/// come from a logger with a per-endpoint level override (see the `log_level`
+/// field of the `endpoint` macro), which has already applied its own level.
+///
+/// Loggers created with [`ConfigLogging::to_logger()`] use this in place of
+/// `slog::LevelFilter`, so that an override can make an endpoint's logging
+/// more verbose than the configured level as well as less.  A logger built
+/// some other way discards entries below its own level regardless of any
+/// override, unless its drain is wrapped in one of these as well.  This must
+/// see entries on the thread that logged them, so it belongs outside of any
+/// asynchronous drain:
+///
+/// ```
+/// use dropshot::RequestLevelFilter;
+/// use slog::Drain;
+///
+/// let drain = RequestLevelFilter::new(slog::Discard, slog::Level::Info);
+/// let log = slog::Logger::root(drain.fuse(), slog::o!());
+/// ```
+#[derive(Debug)]
+pub struct RequestLevelFilter<D> {
+    drain: D,
+    level: Level,
+}
+
+impl<D> RequestLevelFilter<D> {
+    /// Returns a drain that passes entries at least as severe as `level` (or
+    /// from a logger with an override) on to `drain`.
+    pub fn new(drain: D, level: Level) -> RequestLevelFilter<D> {
+        RequestLevelFilter { drain, level }
+    }
+}
+
+impl<D: Drain> Drain for RequestLevelFilter<D> {
+    type Ok = Option<D::Ok>;
+    type Err = D::Err;
+
+    fn log(
+        &self,
+        record: &Record<'_>,
+        values: &OwnedKVList,
+    ) -> Result<Self::Ok, Self::Err> {
+        if is_overridden() || record.level().is_at_least(self.level) {
+            self.drain.log(record, values).map(Some)
+        } else {
+            Ok(None)
+        }
+    }
+
+    // Whether an override applies depends on the logger, which isn't known
+    // here, so this can only defer to the underlying drain.
+    fn is_enabled(&self, level: Level) -> bool {
+        self.drain.is_enabled(level)
+    }
+}
+
+thread_local! {
+    /// whether the entry being logged on this thread has passed through a
+    /// `LevelOverride`
+    static OVERRIDDEN: Cell<bool> = Cell::new(false);
+}
+
+/// Returns whether the entry being logged on this thread comes from a logger
+/// with a level override, which has already decided to emit it.
+pub(crate) fn is_overridden() -> bool {
+    OVERRIDDEN.with(Cell::get)
+}
+
+/// Drain of a logger with a per-endpoint level override, which filters entries
+/// by its own level before passing them on to the logger it was derived from
+///
+/// Loggers pass entries to their drains synchronously, so the entries that
+/// this passes on are marked as overridden (for [`RequestLevelFilter`] and
+/// the like) only for the duration of that call.
+pub(crate) struct LevelOverride {
+    log: Logger,
+    level: Level,
+}
+
+impl LevelOverride {
+    /// Returns a logger that emits what `log` would, but filtered at `level`
+    /// rather than the level of `log`.
+    pub(crate) fn logger(log: Logger, level: Level) -> Logger {
+        Logger::root(LevelOverride { log, level }, o!())
+    }
+}
+
+impl Drain for LevelOverride {
+    type Ok = ();
+    type Err = slog::Never;
+
+    fn log(
+        &self,
+        record: &Record<'_>,
+        values: &OwnedKVList,
+    ) -> Result<(), slog::Never> {
+        // If an override nearer to the logger that emitted the entry has
+        // already passed it, that one applies.
+        let overridden = is_overridden();
+        if !overridden && !record.level().is_at_least(self.level) {
+            return Ok(());
+        }
+
+        /// Restores the previous marking, even if logging panics.
+        struct Restore(bool);
+        impl Drop for Restore {
+            fn drop(&mut self) {
+                OVERRIDDEN.with(|o| o.set(self.0));
+            }
+        }
+        let _restore = Restore(overridden);
+        OVERRIDDEN.with(|o| o.set(true));
+        self.log.log(record, values)
+    }
+
+    fn is_enabled(&self, level: Level) -> bool {
+        level.is_at_least(self.level)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::LevelOverride;
+    use super::RequestLevelFilter;
+    use slog::Drain;
+    use slog::Level;
+    use slog::OwnedKVList;
+    use slog::Record;
+    use std::sync::Arc;
+    use std::sync::Mutex;
+
+    /// Drain that records the messages of the entries it receives
+    #[derive(Clone, Default)]
+    struct Collector(Arc<Mutex<Vec<String>>>);
+
+    impl Drain for Collector {
+        type Ok = ();
+        type Err = slog::Never;
+
+        fn log(
+            &self,
+            record: &Record<'_>,
+            _values: &OwnedKVList,
+        ) -> Result<(), slog::Never> {
+            self.0.lock().unwrap().push(record.msg().to_string());
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_request_level_filter() {
+        let collector = Collector::default();
+        let drain = RequestLevelFilter::new(collector.clone(), Level::Info);
+        let log = slog::Logger::root(drain.fuse(), o!("name" => "test"));
+        debug!(log, "server debug");
+        info!(log, "server info");
+
+        let verbose = LevelOverride::logger(log.clone(), Level::Debug);
+        debug!(verbose, "verbose debug");
+        let quiet = LevelOverride::logger(log.clone(), Level::Warning);
+        info!(quiet, "quiet info");
+        warn!(quiet, "quiet warning");
+
+        // The innermost override applies.
+        let nested = LevelOverride::logger(verbose.clone(), Level::Error);
+        warn!(nested, "nested warning");
+        let nested = LevelOverride::logger(quiet.clone(), Level::Debug);
+        debug!(nested, "nested debug");
+        debug!(verbose.new(o!("key" => "value")), "child debug");
+
+        // The marking doesn't outlast the entry.
+        debug!(log, "server debug");
+
+        assert_eq!(
+            *collector.0.lock().unwrap(),
+            vec![
+                "server info",
+                "verbose debug",
+                "quiet warning",
+                "nested debug",
+                "child debug"
+            ]
+        );
+    }
+}
@@ -0,0 +1,183 @@
+// Copyright 2023 Oxide Computer Company
+//! Runtime-toggleable maintenance mode
+
+use crate::error::HttpError;
+use crate::error_responses::format_error;
+use crate::error_responses::ErrorFormat;
+use crate::health::HEALTHZ_PATH;
+use crate::health::READYZ_PATH;
+use crate::route_pattern::RoutePattern;
+use crate::Body;
+
+use http::HeaderValue;
+use http::StatusCode;
+use hyper::Response;
+use std::sync::RwLock;
+use std::time::Duration;
+
+/// Describes how a server behaves while in maintenance mode.  See
+/// [`HttpServer::set_maintenance_mode()`](crate::HttpServer::set_maintenance_mode).
+///
+/// While in maintenance mode, requests to any endpoint other than the built-in
+/// health endpoints and those exempted with [`MaintenanceMode::exempt_path()`]
+/// fail with a 503 ("Service Unavailable") response whose body is a standard
+/// error body with error code `"Maintenance"`.
+#[derive(Clone, Debug)]
+pub struct MaintenanceMode {
+    message: String,
+    retry_after: Option<Duration>,
+    exempt_paths: Vec<RoutePattern>,
+}
+
+impl Default for MaintenanceMode {
+    fn default() -> Self {
+        MaintenanceMode {
+            message: String::from(
+                "The service is temporarily unavailable for maintenance.",
+            ),
+            retry_after: None,
+            exempt_paths: Vec::new(),
+        }
+    }
+}
+
+impl MaintenanceMode {
+    pub fn new() -> Self {
+        MaintenanceMode::default()
+    }
+
+    /// Sets the message included in the body of rejected requests.
+    pub fn message<T: ToString>(mut self, message: T) -> Self {
+        self.message = message.to_string();
+        self
+    }
+
+    /// Sets the value of the `Retry-After` header in rejected requests, which
+    /// tells clients how long to wait before trying again.
+    pub fn retry_after(mut self, retry_after: Duration) -> Self {
+        self.retry_after = Some(retry_after);
+        self
+    }
+
+    /// Exempts endpoints with the given path template (e.g., `"/admin/{id}"`)
+    /// from maintenance mode.  A trailing `*` matches any path template with
+    /// the given prefix (e.g., `"/admin/*"`).
+    pub fn exempt_path(mut self, path: &str) -> Self {
+        self.exempt_paths.push(RoutePattern::new(path));
+        self
+    }
+
+    fn is_exempt(&self, route: &str) -> bool {
+        route == HEALTHZ_PATH
+            || route == READYZ_PATH
+            || self.exempt_paths.iter().any(|path| path.matches(route))
+    }
+
+    fn response(
+        &self,
+        format: ErrorFormat,
+        request_id: &str,
+        instance: &str,
+    ) -> Response<Body> {
+        let error = HttpError {
+            status_code: StatusCode::SERVICE_UNAVAILABLE,
+            error_code: Some(String::from("Maintenance")),
+            external_message: self.message.clone(),
+            internal_message: String::from("server is in maintenance mode"),
+        };
+        let mut response = format_error(error, format, request_id, instance);
+        if let Some(retry_after) = self.retry_after {
+            response.headers_mut().insert(
+                http::header::RETRY_AFTER,
+                HeaderValue::from(retry_after.as_secs()),
+            );
+        }
+        response
+    }
+}
+
+/// Server-wide maintenance mode state
+#[derive(Debug, Default)]
+pub(crate) struct MaintenanceState(RwLock<Option<MaintenanceMode>>);
+
+impl MaintenanceState {
+    pub fn get(&self) -> Option<MaintenanceMode> {
+        self.0.read().unwrap().clone()
+    }
+
+    pub fn set(&self, mode: Option<MaintenanceMode>) {
+        *self.0.write().unwrap() = mode;
+    }
+
+    /// Returns the response to send instead of invoking the handler for the
+    /// endpoint with path template `route`, if the server is in maintenance
+    /// mode and that endpoint is not exempt.  The response's body has the
+    /// given format, and `instance` is the path of the request.
+    pub fn response_for(
+        &self,
+        route: &str,
+        format: ErrorFormat,
+        request_id: &str,
+        instance: &str,
+    ) -> Option<Response<Body>> {
+        self.0
+            .read()
+            .unwrap()
+            .as_ref()
+            .filter(|mode| !mode.is_exempt(route))
+            .map(|mode| mode.response(format, request_id, instance))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::MaintenanceMode;
+    use super::MaintenanceState;
+    use crate::error_responses::ErrorFormat;
+    use http::StatusCode;
+    use std::time::Duration;
+
+    #[test]
+    fn test_maintenance_state() {
+        let state = MaintenanceState::default();
+        assert!(state.get().is_none());
+        assert!(state
+            .response_for("/projects", ErrorFormat::Dropshot, "req", "/")
+            .is_none());
+
+        state.set(Some(
+            MaintenanceMode::new()
+                .retry_after(Duration::from_secs(120))
+                .exempt_path("/admin/*")
+                .exempt_path("/status"),
+        ));
+        let response = state
+            .response_for("/projects", ErrorFormat::Dropshot, "req", "/")
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(
+            response.headers().get(http::header::RETRY_AFTER).unwrap(),
+            "120"
+        );
+        assert!(state
+            .response_for("/status/{id}", ErrorFormat::Dropshot, "req", "/")
+            .is_some());
+        assert!(state
+            .response_for("/status", ErrorFormat::Dropshot, "req", "/")
+            .is_none());
+        assert!(state
+            .response_for("/admin/users", ErrorFormat::Dropshot, "req", "/")
+            .is_none());
+        assert!(state
+            .response_for("/healthz", ErrorFormat::Dropshot, "req", "/")
+            .is_none());
+        assert!(state
+            .response_for("/readyz", ErrorFormat::Dropshot, "req", "/")
+            .is_none());
+
+        state.set(None);
+        assert!(state
+            .response_for("/projects", ErrorFormat::Dropshot, "req", "/")
+            .is_none());
+    }
+}
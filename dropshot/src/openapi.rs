@@ -0,0 +1,1405 @@
+// Copyright 2023 Oxide Computer Company
+//! Generation of OpenAPI definitions describing an [`ApiDescription`]
+
+use crate::api_description::ApiEndpointParameterLocation;
+use crate::api_description::ApiEndpointParameterMetadata;
+use crate::api_description::ApiSchemaGenerator;
+use crate::api_description::ExtensionMode;
+use crate::router::route_path_to_segments;
+use crate::router::PathSegment;
+use crate::server::ServerContext;
+use crate::type_util::type_object_properties;
+use crate::ApiDescription;
+use crate::ErrorFormat;
+use crate::HttpErrorResponseBody;
+use crate::ProblemDetails;
+use crate::CONTENT_TYPE_JSON;
+use crate::CONTENT_TYPE_PROBLEM_JSON;
+
+use std::collections::HashSet;
+
+const PAGINATION_EXTENSION: &str = "x-dropshot-pagination";
+
+// To indicate websocket usage by the endpoint to code generators (i.e. Progenitor)
+const WEBSOCKET_EXTENSION: &str = "x-dropshot-websocket";
+
+impl<Context: ServerContext> ApiDescription<Context> {
+    /// Build the OpenAPI definition describing this API.  Returns an
+    /// [`OpenApiDefinition`] which can be used to specify the contents of the
+    /// definition and select an output format.
+    ///
+    /// The arguments to this function will be used for the mandatory `title` and
+    /// `version` properties that the `Info` object in an OpenAPI definition must
+    /// contain.
+    pub fn openapi<S1, S2>(
+        &self,
+        title: S1,
+        version: S2,
+    ) -> OpenApiDefinition<Context>
+    where
+        S1: AsRef<str>,
+        S2: AsRef<str>,
+    {
+        OpenApiDefinition::new(self, title.as_ref(), version.as_ref())
+    }
+
+    /// Internal routine for constructing the OpenAPI definition describing this
+    /// API in its JSON form.
+    fn gen_openapi(&self, info: openapiv3::Info) -> openapiv3::OpenAPI {
+        let mut openapi = openapiv3::OpenAPI::default();
+
+        openapi.openapi = "3.0.3".to_string();
+        openapi.info = info;
+
+        // Gather up the ad hoc tags from endpoints
+        let endpoint_tags = (&self.router)
+            .into_iter()
+            .flat_map(|(_, _, endpoint)| {
+                endpoint.tags.iter().filter(|tag| {
+                    !self.tag_config.tag_definitions.contains_key(*tag)
+                })
+            })
+            .cloned()
+            .collect::<HashSet<_>>()
+            .into_iter()
+            .map(|tag| openapiv3::Tag { name: tag, ..Default::default() });
+
+        // Bundle those with the explicit tags provided by the consumer
+        openapi.tags = self
+            .tag_config
+            .tag_definitions
+            .iter()
+            .map(|(name, details)| openapiv3::Tag {
+                name: name.clone(),
+                description: details.description.clone(),
+                external_docs: details.external_docs.as_ref().map(|e| {
+                    openapiv3::ExternalDocumentation {
+                        description: e.description.clone(),
+                        url: e.url.clone(),
+                        ..Default::default()
+                    }
+                }),
+                ..Default::default()
+            })
+            .chain(endpoint_tags)
+            .collect();
+
+        // Sort the tags for stability
+        openapi.tags.sort_by(|a, b| a.name.cmp(&b.name));
+
+        let settings = schemars::gen::SchemaSettings::openapi3();
+        let mut generator = schemars::gen::SchemaGenerator::new(settings);
+        let mut definitions =
+            indexmap::IndexMap::<String, schemars::schema::Schema>::new();
+
+        for (path, method, endpoint) in &self.router {
+            if !endpoint.visible {
+                continue;
+            }
+            let path = openapi.paths.paths.entry(path).or_insert(
+                openapiv3::ReferenceOr::Item(openapiv3::PathItem::default()),
+            );
+
+            let pathitem = match path {
+                openapiv3::ReferenceOr::Item(ref mut item) => item,
+                _ => panic!("reference not expected"),
+            };
+
+            let method_ref = match &method[..] {
+                "GET" => &mut pathitem.get,
+                "PUT" => &mut pathitem.put,
+                "POST" => &mut pathitem.post,
+                "DELETE" => &mut pathitem.delete,
+                "OPTIONS" => &mut pathitem.options,
+                "HEAD" => &mut pathitem.head,
+                "PATCH" => &mut pathitem.patch,
+                "TRACE" => &mut pathitem.trace,
+                other => panic!("unexpected method `{}`", other),
+            };
+            let mut operation = openapiv3::Operation::default();
+            operation.operation_id = Some(endpoint.operation_id.clone());
+            operation.summary = endpoint.summary.clone();
+            operation.description = endpoint.description.clone();
+            operation.tags = endpoint.tags.clone();
+            operation.deprecated = endpoint.deprecated;
+
+            operation.parameters = endpoint
+                .parameters
+                .iter()
+                .filter_map(|param| {
+                    let (name, location) = match &param.metadata {
+                        ApiEndpointParameterMetadata::Body(_) => return None,
+                        ApiEndpointParameterMetadata::Path(name) => {
+                            (name, ApiEndpointParameterLocation::Path)
+                        }
+                        ApiEndpointParameterMetadata::Query(name) => {
+                            (name, ApiEndpointParameterLocation::Query)
+                        }
+                        ApiEndpointParameterMetadata::Header(name) => {
+                            (name, ApiEndpointParameterLocation::Header)
+                        }
+                    };
+
+                    let schema = match &param.schema {
+                        ApiSchemaGenerator::Static { schema, dependencies } => {
+                            definitions.extend(dependencies.clone());
+                            match location {
+                                ApiEndpointParameterLocation::Path => {
+                                    j2oas_schema(
+                                        None,
+                                        &path_param_schema(
+                                            &endpoint.path,
+                                            name,
+                                            schema,
+                                        ),
+                                    )
+                                }
+                                _ => j2oas_schema(None, schema),
+                            }
+                        }
+                        _ => {
+                            unimplemented!("this may happen for complex types")
+                        }
+                    };
+
+                    // Arrays in the query string are given by repeating the
+                    // parameter (e.g., `?id=1&id=2`), and objects in the
+                    // `deepObject` style (e.g., `?filter[name]=foo`).
+                    let is_object = match &param.schema {
+                        ApiSchemaGenerator::Static { schema, dependencies } => {
+                            type_object_properties(schema, dependencies)
+                                .is_some()
+                        }
+                        _ => false,
+                    };
+                    let is_array = matches!(
+                        &schema,
+                        openapiv3::ReferenceOr::Item(openapiv3::Schema {
+                            schema_kind: openapiv3::SchemaKind::Type(
+                                openapiv3::Type::Array(_)
+                            ),
+                            ..
+                        })
+                    );
+                    let explode = match location {
+                        ApiEndpointParameterLocation::Query
+                            if is_array || is_object =>
+                        {
+                            Some(true)
+                        }
+                        _ => None,
+                    };
+
+                    let parameter_data = openapiv3::ParameterData {
+                        name: name.clone(),
+                        description: param.description.clone(),
+                        required: param.required,
+                        deprecated: None,
+                        format: openapiv3::ParameterSchemaOrContent::Schema(
+                            schema,
+                        ),
+                        example: None,
+                        examples: indexmap::IndexMap::new(),
+                        extensions: indexmap::IndexMap::new(),
+                        explode,
+                    };
+                    match location {
+                        ApiEndpointParameterLocation::Query => {
+                            Some(openapiv3::ReferenceOr::Item(
+                                openapiv3::Parameter::Query {
+                                    parameter_data: parameter_data,
+                                    allow_reserved: false,
+                                    style: if is_object {
+                                        openapiv3::QueryStyle::DeepObject
+                                    } else {
+                                        openapiv3::QueryStyle::Form
+                                    },
+                                    allow_empty_value: None,
+                                },
+                            ))
+                        }
+                        ApiEndpointParameterLocation::Path => {
+                            Some(openapiv3::ReferenceOr::Item(
+                                openapiv3::Parameter::Path {
+                                    parameter_data: parameter_data,
+                                    style: openapiv3::PathStyle::Simple,
+                                },
+                            ))
+                        }
+                        ApiEndpointParameterLocation::Header => {
+                            Some(openapiv3::ReferenceOr::Item(
+                                openapiv3::Parameter::Header {
+                                    parameter_data: parameter_data,
+                                    style: openapiv3::HeaderStyle::Simple,
+                                },
+                            ))
+                        }
+                    }
+                })
+                .collect::<Vec<_>>();
+
+            operation.request_body = endpoint
+                .parameters
+                .iter()
+                .filter_map(|param| {
+                    let content_type = match &param.metadata {
+                        ApiEndpointParameterMetadata::Body(ct) => ct,
+                        _ => return None,
+                    };
+                    // A body of the endpoint's content type (i.e., a
+                    // `TypedBody`) may also be of any of its additional ones.
+                    let mut content_types = vec![content_type];
+                    if *content_type == endpoint.body_content_type {
+                        content_types
+                            .extend(&endpoint.additional_body_content_types);
+                    }
+
+                    let (name, js) = match &param.schema {
+                        ApiSchemaGenerator::Gen { name, schema } => {
+                            (Some(name()), schema(&mut generator))
+                        }
+                        ApiSchemaGenerator::Static { schema, dependencies } => {
+                            definitions.extend(dependencies.clone());
+                            (None, schema.as_ref().clone())
+                        }
+                    };
+                    let schema = j2oas_schema(name.as_ref(), &js);
+
+                    let content = content_types
+                        .into_iter()
+                        .map(|content_type| {
+                            let media_type = openapiv3::MediaType {
+                                schema: Some(schema.clone()),
+                                ..Default::default()
+                            };
+                            (content_type.mime_type().to_string(), media_type)
+                        })
+                        .collect();
+
+                    Some(openapiv3::ReferenceOr::Item(openapiv3::RequestBody {
+                        content: content,
+                        required: true,
+                        ..Default::default()
+                    }))
+                })
+                .next();
+
+            match endpoint.extension_mode {
+                ExtensionMode::None => {}
+                ExtensionMode::Paginated => {
+                    operation.extensions.insert(
+                        PAGINATION_EXTENSION.to_string(),
+                        serde_json::json! {true},
+                    );
+                }
+                ExtensionMode::Websocket => {
+                    operation.extensions.insert(
+                        WEBSOCKET_EXTENSION.to_string(),
+                        serde_json::json!({}),
+                    );
+                }
+            }
+
+            let response = if let Some(schema) = &endpoint.response.schema {
+                let (name, js) = match schema {
+                    ApiSchemaGenerator::Gen { name, schema } => {
+                        (Some(name()), schema(&mut generator))
+                    }
+                    ApiSchemaGenerator::Static { schema, dependencies } => {
+                        definitions.extend(dependencies.clone());
+                        (None, schema.as_ref().clone())
+                    }
+                };
+                let mut content = indexmap::IndexMap::new();
+                if !is_empty(&js) {
+                    let content_type = endpoint
+                        .response
+                        .content_type
+                        .as_deref()
+                        .unwrap_or(CONTENT_TYPE_JSON);
+                    let content_types = std::iter::once(content_type).chain(
+                        endpoint
+                            .response
+                            .additional_content_types
+                            .iter()
+                            .map(String::as_str),
+                    );
+                    for content_type in content_types {
+                        content.insert(
+                            content_type.to_string(),
+                            openapiv3::MediaType {
+                                schema: Some(j2oas_schema(name.as_ref(), &js)),
+                                ..Default::default()
+                            },
+                        );
+                    }
+                }
+
+                let headers = endpoint
+                    .response
+                    .headers
+                    .iter()
+                    .map(|header| {
+                        let schema = match &header.schema {
+                            ApiSchemaGenerator::Static {
+                                schema,
+                                dependencies,
+                            } => {
+                                definitions.extend(dependencies.clone());
+                                j2oas_schema(None, schema)
+                            }
+                            _ => {
+                                unimplemented!(
+                                    "this may happen for complex types"
+                                )
+                            }
+                        };
+
+                        (
+                            header.name.clone(),
+                            openapiv3::ReferenceOr::Item(openapiv3::Header {
+                                description: header.description.clone(),
+                                style: openapiv3::HeaderStyle::Simple,
+                                required: header.required,
+                                deprecated: None,
+                                format:
+                                    openapiv3::ParameterSchemaOrContent::Schema(
+                                        schema,
+                                    ),
+                                example: None,
+                                examples: indexmap::IndexMap::new(),
+                                extensions: indexmap::IndexMap::new(),
+                            }),
+                        )
+                    })
+                    .collect();
+
+                let response = openapiv3::Response {
+                    description: if let Some(description) =
+                        &endpoint.response.description
+                    {
+                        description.clone()
+                    } else {
+                        // TODO: perhaps we should require even free-form
+                        // responses to have a description since it's required
+                        // by OpenAPI.
+                        "".to_string()
+                    },
+                    content,
+                    headers,
+                    ..Default::default()
+                };
+                response
+            } else {
+                // If no schema was specified, the response is hand-rolled. In
+                // this case we'll fall back to the default response type which
+                // we assume to be inclusive of errors. The media type and
+                // and schema will similarly be maximally permissive.
+                let mut content = indexmap::IndexMap::new();
+                content.insert(
+                    "*/*".to_string(),
+                    openapiv3::MediaType {
+                        schema: Some(openapiv3::ReferenceOr::Item(
+                            openapiv3::Schema {
+                                schema_data: openapiv3::SchemaData::default(),
+                                schema_kind: openapiv3::SchemaKind::Any(
+                                    openapiv3::AnySchema::default(),
+                                ),
+                            },
+                        )),
+                        ..Default::default()
+                    },
+                );
+                openapiv3::Response {
+                    // TODO: perhaps we should require even free-form
+                    // responses to have a description since it's required
+                    // by OpenAPI.
+                    description: "".to_string(),
+                    content,
+                    ..Default::default()
+                }
+            };
+
+            if let Some(code) = &endpoint.response.success {
+                // 4xx and 5xx responses all use the same error information,
+                // unless the handler's error type has its own schema.
+                let error_schema =
+                    endpoint.response.error_schema.as_ref().map(|schema| {
+                        let (name, js) = match schema {
+                            ApiSchemaGenerator::Gen { name, schema } => {
+                                (Some(name()), schema(&mut generator))
+                            }
+                            ApiSchemaGenerator::Static {
+                                schema,
+                                dependencies,
+                            } => {
+                                definitions.extend(dependencies.clone());
+                                (None, schema.as_ref().clone())
+                            }
+                        };
+                        j2oas_schema(name.as_ref(), &js)
+                    });
+                let err_ref = match &error_schema {
+                    None => openapiv3::ReferenceOr::ref_(
+                        "#/components/responses/Error",
+                    ),
+                    Some(schema) => {
+                        openapiv3::ReferenceOr::Item(openapiv3::Response {
+                            description: "Error".to_string(),
+                            content: error_content(
+                                self.error_format,
+                                &mut generator,
+                                Some(schema.clone()),
+                            ),
+                            ..Default::default()
+                        })
+                    }
+                };
+
+                // Other responses (e.g., 304 "Not Modified") have no body,
+                // but carry the same headers as the successful response.
+                // Specific error responses (e.g., those declared by the
+                // handler's error type) have the usual error body.
+                for (code, description) in
+                    &endpoint.response.additional_responses
+                {
+                    let other =
+                        if code.is_client_error() || code.is_server_error() {
+                            openapiv3::ReferenceOr::Item(openapiv3::Response {
+                                description: description.clone(),
+                                content: error_content(
+                                    self.error_format,
+                                    &mut generator,
+                                    error_schema.clone(),
+                                ),
+                                ..Default::default()
+                            })
+                        } else {
+                            openapiv3::ReferenceOr::Item(openapiv3::Response {
+                                description: description.clone(),
+                                headers: response.headers.clone(),
+                                ..Default::default()
+                            })
+                        };
+                    operation.responses.responses.insert(
+                        openapiv3::StatusCode::Code(code.as_u16()),
+                        other,
+                    );
+                }
+
+                // Alternate statuses are described just like the primary one
+                // (except for those that can't have a body).
+                for (code, description) in &endpoint.response.alternate_statuses
+                {
+                    let mut other = response.clone();
+                    other.description = description.clone();
+                    if *code == http::StatusCode::NO_CONTENT
+                        || *code == http::StatusCode::NOT_MODIFIED
+                    {
+                        other.content.clear();
+                    }
+                    operation.responses.responses.insert(
+                        openapiv3::StatusCode::Code(code.as_u16()),
+                        openapiv3::ReferenceOr::Item(other),
+                    );
+                }
+
+                operation.responses.responses.insert(
+                    openapiv3::StatusCode::Code(code.as_u16()),
+                    openapiv3::ReferenceOr::Item(response),
+                );
+
+                operation
+                    .responses
+                    .responses
+                    .insert(openapiv3::StatusCode::Range(4), err_ref.clone());
+                operation
+                    .responses
+                    .responses
+                    .insert(openapiv3::StatusCode::Range(5), err_ref);
+            } else {
+                operation.responses.default =
+                    Some(openapiv3::ReferenceOr::Item(response))
+            }
+
+            // Drop in the operation.
+            method_ref.replace(operation);
+        }
+
+        let components = &mut openapi
+            .components
+            .get_or_insert_with(openapiv3::Components::default);
+
+        // All endpoints share an error response
+        let responses = &mut components.responses;
+        responses.insert(
+            "Error".to_string(),
+            openapiv3::ReferenceOr::Item(openapiv3::Response {
+                description: "Error".to_string(),
+                content: error_content(self.error_format, &mut generator, None),
+                ..Default::default()
+            }),
+        );
+
+        // Add the schemas for which we generated references.
+        let schemas = &mut components.schemas;
+
+        let root_schema = generator.into_root_schema_for::<()>();
+        root_schema.definitions.iter().for_each(|(key, schema)| {
+            schemas.insert(key.clone(), j2oas_schema(None, schema));
+        });
+
+        definitions.into_iter().for_each(|(key, schema)| {
+            if !schemas.contains_key(&key) {
+                schemas.insert(key, j2oas_schema(None, &schema));
+            }
+        });
+
+        openapi
+    }
+}
+
+/// Returns the content of error responses in the given format, with the given
+/// schema (if any) in place of that of the format's error body.
+fn error_content(
+    format: ErrorFormat,
+    generator: &mut schemars::gen::SchemaGenerator,
+    schema: Option<openapiv3::ReferenceOr<openapiv3::Schema>>,
+) -> indexmap::IndexMap<String, openapiv3::MediaType> {
+    let (content_type, default_schema) = match format {
+        ErrorFormat::Dropshot => (
+            CONTENT_TYPE_JSON,
+            generator.subschema_for::<HttpErrorResponseBody>(),
+        ),
+        ErrorFormat::ProblemJson => (
+            CONTENT_TYPE_PROBLEM_JSON,
+            generator.subschema_for::<ProblemDetails>(),
+        ),
+    };
+    let schema = schema.unwrap_or_else(|| j2oas_schema(None, &default_schema));
+    let mut content = indexmap::IndexMap::new();
+    content.insert(
+        content_type.to_string(),
+        openapiv3::MediaType { schema: Some(schema), ..Default::default() },
+    );
+    content
+}
+
+/// Returns true iff the schema represents the void schema that matches no data.
+fn is_empty(schema: &schemars::schema::Schema) -> bool {
+    if let schemars::schema::Schema::Bool(false) = schema {
+        return true;
+    }
+    if let schemars::schema::Schema::Object(schemars::schema::SchemaObject {
+        metadata: _,
+        instance_type: None,
+        format: None,
+        enum_values: None,
+        const_value: None,
+        subschemas: Some(subschemas),
+        number: None,
+        string: None,
+        array: None,
+        object: None,
+        reference: None,
+        extensions: _,
+    }) = schema
+    {
+        if let schemars::schema::SubschemaValidation {
+            all_of: None,
+            any_of: None,
+            one_of: None,
+            not: Some(not),
+            if_schema: None,
+            then_schema: None,
+            else_schema: None,
+        } = subschemas.as_ref()
+        {
+            match not.as_ref() {
+                schemars::schema::Schema::Bool(true) => return true,
+                schemars::schema::Schema::Object(
+                    schemars::schema::SchemaObject {
+                        metadata: _,
+                        instance_type: None,
+                        format: None,
+                        enum_values: None,
+                        const_value: None,
+                        subschemas: None,
+                        number: None,
+                        string: None,
+                        array: None,
+                        object: None,
+                        reference: None,
+                        extensions: _,
+                    },
+                ) => return true,
+                _ => {}
+            }
+        }
+    }
+
+    false
+}
+
+/// This object is used to specify configuration for building an OpenAPI
+/// definition document.  It is constructed using [`ApiDescription::openapi()`].
+/// Additional optional properties may be added and then the OpenAPI definition
+/// document may be generated via [`write()`](`OpenApiDefinition::write`) or
+/// [`json()`](`OpenApiDefinition::json`).
+pub struct OpenApiDefinition<'a, Context: ServerContext> {
+    api: &'a ApiDescription<Context>,
+    info: openapiv3::Info,
+}
+
+impl<'a, Context: ServerContext> OpenApiDefinition<'a, Context> {
+    fn new(
+        api: &'a ApiDescription<Context>,
+        title: &str,
+        version: &str,
+    ) -> OpenApiDefinition<'a, Context> {
+        let info = openapiv3::Info {
+            title: title.to_string(),
+            version: version.to_string(),
+            ..Default::default()
+        };
+        OpenApiDefinition { api, info }
+    }
+
+    /// Provide a short description of the API.  CommonMark syntax may be
+    /// used for rich text representation.
+    ///
+    /// This routine will set the `description` field of the `Info` object in the
+    /// OpenAPI definition.
+    pub fn description<S: AsRef<str>>(&mut self, description: S) -> &mut Self {
+        self.info.description = Some(description.as_ref().to_string());
+        self
+    }
+
+    /// Include a Terms of Service URL for the API.  Must be in the format of a
+    /// URL.
+    ///
+    /// This routine will set the `termsOfService` field of the `Info` object in
+    /// the OpenAPI definition.
+    pub fn terms_of_service<S: AsRef<str>>(&mut self, url: S) -> &mut Self {
+        self.info.terms_of_service = Some(url.as_ref().to_string());
+        self
+    }
+
+    fn contact_mut(&mut self) -> &mut openapiv3::Contact {
+        if self.info.contact.is_none() {
+            self.info.contact = Some(openapiv3::Contact::default());
+        }
+        self.info.contact.as_mut().unwrap()
+    }
+
+    /// Set the identifying name of the contact person or organisation
+    /// responsible for the API.
+    ///
+    /// This routine will set the `name` property of the `Contact` object within
+    /// the `Info` object in the OpenAPI definition.
+    pub fn contact_name<S: AsRef<str>>(&mut self, name: S) -> &mut Self {
+        self.contact_mut().name = Some(name.as_ref().to_string());
+        self
+    }
+
+    /// Set a contact URL for the API.  Must be in the format of a URL.
+    ///
+    /// This routine will set the `url` property of the `Contact` object within
+    /// the `Info` object in the OpenAPI definition.
+    pub fn contact_url<S: AsRef<str>>(&mut self, url: S) -> &mut Self {
+        self.contact_mut().url = Some(url.as_ref().to_string());
+        self
+    }
+
+    /// Set the email address of the contact person or organisation responsible
+    /// for the API.  Must be in the format of an email address.
+    ///
+    /// This routine will set the `email` property of the `Contact` object within
+    /// the `Info` object in the OpenAPI definition.
+    pub fn contact_email<S: AsRef<str>>(&mut self, email: S) -> &mut Self {
+        self.contact_mut().email = Some(email.as_ref().to_string());
+        self
+    }
+
+    fn license_mut(&mut self, name: &str) -> &mut openapiv3::License {
+        if self.info.license.is_none() {
+            self.info.license = Some(openapiv3::License {
+                name: name.to_string(),
+                ..Default::default()
+            })
+        }
+        self.info.license.as_mut().unwrap()
+    }
+
+    /// Provide the name of the licence used for the API, and a URL (must be in
+    /// URL format) displaying the licence text.
+    ///
+    /// This routine will set the `name` and optional `url` properties of the
+    /// `License` object within the `Info` object in the OpenAPI definition.
+    pub fn license<S1, S2>(&mut self, name: S1, url: S2) -> &mut Self
+    where
+        S1: AsRef<str>,
+        S2: AsRef<str>,
+    {
+        self.license_mut(name.as_ref()).url = Some(url.as_ref().to_string());
+        self
+    }
+
+    /// Provide the name of the licence used for the API.
+    ///
+    /// This routine will set the `name` property of the License object within
+    /// the `Info` object in the OpenAPI definition.
+    pub fn license_name<S: AsRef<str>>(&mut self, name: S) -> &mut Self {
+        self.license_mut(name.as_ref());
+        self
+    }
+
+    /// Build a JSON object containing the OpenAPI definition for this API.
+    pub fn json(&self) -> serde_json::Result<serde_json::Value> {
+        serde_json::to_value(&self.api.gen_openapi(self.info.clone()))
+    }
+
+    /// Build a JSON object containing the OpenAPI definition for this API and
+    /// write it to the provided stream.
+    pub fn write(
+        &self,
+        out: &mut dyn std::io::Write,
+    ) -> serde_json::Result<()> {
+        serde_json::to_writer_pretty(
+            out,
+            &self.api.gen_openapi(self.info.clone()),
+        )
+    }
+}
+
+/// Convert from JSON Schema into OpenAPI.
+// TODO Initially this seemed like it was going to be a win, but the versions
+// of JSON Schema that the schemars and openapiv3 crates adhere to are just
+// different enough to make the conversion a real pain in the neck. A better
+// approach might be a derive(OpenAPI)-like thing, or even a generic
+// derive(schema) that we could then marshall into OpenAPI.
+// The schemars crate also seems a bit inflexible when it comes to how the
+// schema is generated wrt references vs. inline types.
+/// Returns the schema of the path parameter `name` of an endpoint registered
+/// at `path`, given the schema of its type: string-typed parameters whose path
+/// variable has a pattern (e.g., `{id:uuid}`) are given the corresponding
+/// `format` or `pattern`.
+fn path_param_schema(
+    path: &str,
+    name: &str,
+    schema: &schemars::schema::Schema,
+) -> schemars::schema::Schema {
+    let pattern =
+        route_path_to_segments(path).into_iter().find_map(|segment| {
+            match PathSegment::from(segment) {
+                PathSegment::VarnamePattern(varname, pattern)
+                    if varname == name =>
+                {
+                    Some(pattern)
+                }
+                _ => None,
+            }
+        });
+    let mut schema = schema.clone();
+    if let (Some(pattern), schemars::schema::Schema::Object(obj)) =
+        (pattern, &mut schema)
+    {
+        let is_string = obj.reference.is_none()
+            && obj.instance_type
+                == Some(schemars::schema::SingleOrVec::Single(Box::new(
+                    schemars::schema::InstanceType::String,
+                )));
+        if is_string {
+            if let Some(format) = pattern.format() {
+                obj.format.get_or_insert_with(|| format.to_string());
+            }
+            if let Some(regex) = pattern.regex() {
+                obj.string().pattern = Some(regex.to_string());
+            }
+        }
+    }
+    schema
+}
+
+pub(crate) fn j2oas_schema(
+    name: Option<&String>,
+    schema: &schemars::schema::Schema,
+) -> openapiv3::ReferenceOr<openapiv3::Schema> {
+    match schema {
+        // The permissive, "match anything" schema. We'll typically see this
+        // when consumers use a type such as serde_json::Value.
+        schemars::schema::Schema::Bool(true) => {
+            openapiv3::ReferenceOr::Item(openapiv3::Schema {
+                schema_data: openapiv3::SchemaData::default(),
+                schema_kind: openapiv3::SchemaKind::Any(
+                    openapiv3::AnySchema::default(),
+                ),
+            })
+        }
+        schemars::schema::Schema::Bool(false) => {
+            panic!("We don't expect to see a schema that matches the null set")
+        }
+        schemars::schema::Schema::Object(obj) => j2oas_schema_object(name, obj),
+    }
+}
+
+fn j2oas_schema_object(
+    name: Option<&String>,
+    obj: &schemars::schema::SchemaObject,
+) -> openapiv3::ReferenceOr<openapiv3::Schema> {
+    if let Some(reference) = &obj.reference {
+        return openapiv3::ReferenceOr::Reference {
+            reference: reference.clone(),
+        };
+    }
+
+    let ty = match &obj.instance_type {
+        Some(schemars::schema::SingleOrVec::Single(ty)) => Some(ty.as_ref()),
+        Some(schemars::schema::SingleOrVec::Vec(_)) => {
+            panic!(
+                "a type array is unsupported by openapiv3:\n{}",
+                serde_json::to_string_pretty(obj)
+                    .unwrap_or_else(|_| "<can't serialize>".to_string())
+            )
+        }
+        None => None,
+    };
+
+    let kind = match (ty, &obj.subschemas) {
+        (Some(schemars::schema::InstanceType::Null), None) => {
+            openapiv3::SchemaKind::Type(openapiv3::Type::String(
+                openapiv3::StringType {
+                    enumeration: vec![None],
+                    ..Default::default()
+                },
+            ))
+        }
+        (Some(schemars::schema::InstanceType::Boolean), None) => {
+            openapiv3::SchemaKind::Type(openapiv3::Type::Boolean {})
+        }
+        (Some(schemars::schema::InstanceType::Object), None) => {
+            j2oas_object(&obj.object)
+        }
+        (Some(schemars::schema::InstanceType::Array), None) => {
+            j2oas_array(&obj.array)
+        }
+        (Some(schemars::schema::InstanceType::Number), None) => {
+            j2oas_number(&obj.format, &obj.number, &obj.enum_values)
+        }
+        (Some(schemars::schema::InstanceType::String), None) => {
+            j2oas_string(&obj.format, &obj.string, &obj.enum_values)
+        }
+        (Some(schemars::schema::InstanceType::Integer), None) => {
+            j2oas_integer(&obj.format, &obj.number, &obj.enum_values)
+        }
+        (None, Some(subschema)) => j2oas_subschemas(subschema),
+        (None, None) => {
+            openapiv3::SchemaKind::Any(openapiv3::AnySchema::default())
+        }
+        (Some(_), Some(_)) => panic!(
+            "a schema can't have both a type and subschemas:\n{}",
+            serde_json::to_string_pretty(&obj)
+                .unwrap_or_else(|_| "<can't serialize>".to_string())
+        ),
+    };
+
+    let mut data = openapiv3::SchemaData::default();
+
+    if matches!(
+        &obj.extensions.get("nullable"),
+        Some(serde_json::Value::Bool(true))
+    ) {
+        data.nullable = true;
+    }
+
+    if let Some(metadata) = &obj.metadata {
+        data.title = metadata.title.clone();
+        data.description = metadata.description.clone();
+        data.default = metadata.default.clone();
+        data.deprecated = metadata.deprecated;
+        data.read_only = metadata.read_only;
+        data.write_only = metadata.write_only;
+    }
+
+    if let Some(name) = name {
+        data.title = Some(name.clone());
+    }
+    if let Some(example) = obj.extensions.get("example") {
+        data.example = Some(example.clone());
+    }
+    // Vendor extensions (e.g., the message name of a Protobuf body) are passed
+    // through as-is.
+    for (key, value) in &obj.extensions {
+        if key.starts_with("x-") {
+            data.extensions.insert(key.clone(), value.clone());
+        }
+    }
+
+    openapiv3::ReferenceOr::Item(openapiv3::Schema {
+        schema_data: data,
+        schema_kind: kind,
+    })
+}
+
+fn j2oas_subschemas(
+    subschemas: &schemars::schema::SubschemaValidation,
+) -> openapiv3::SchemaKind {
+    match (
+        &subschemas.all_of,
+        &subschemas.any_of,
+        &subschemas.one_of,
+        &subschemas.not,
+    ) {
+        (Some(all_of), None, None, None) => openapiv3::SchemaKind::AllOf {
+            all_of: all_of
+                .iter()
+                .map(|schema| j2oas_schema(None, schema))
+                .collect::<Vec<_>>(),
+        },
+        (None, Some(any_of), None, None) => openapiv3::SchemaKind::AnyOf {
+            any_of: any_of
+                .iter()
+                .map(|schema| j2oas_schema(None, schema))
+                .collect::<Vec<_>>(),
+        },
+        (None, None, Some(one_of), None) => openapiv3::SchemaKind::OneOf {
+            one_of: one_of
+                .iter()
+                .map(|schema| j2oas_schema(None, schema))
+                .collect::<Vec<_>>(),
+        },
+        (None, None, None, Some(not)) => openapiv3::SchemaKind::Not {
+            not: Box::new(j2oas_schema(None, not)),
+        },
+        _ => panic!("invalid subschema {:#?}", subschemas),
+    }
+}
+
+fn j2oas_integer(
+    format: &Option<String>,
+    number: &Option<Box<schemars::schema::NumberValidation>>,
+    enum_values: &Option<Vec<serde_json::value::Value>>,
+) -> openapiv3::SchemaKind {
+    let format = match format.as_ref().map(|s| s.as_str()) {
+        None => openapiv3::VariantOrUnknownOrEmpty::Empty,
+        Some("int32") => openapiv3::VariantOrUnknownOrEmpty::Item(
+            openapiv3::IntegerFormat::Int32,
+        ),
+        Some("int64") => openapiv3::VariantOrUnknownOrEmpty::Item(
+            openapiv3::IntegerFormat::Int64,
+        ),
+        Some(other) => {
+            openapiv3::VariantOrUnknownOrEmpty::Unknown(other.to_string())
+        }
+    };
+
+    let (multiple_of, minimum, exclusive_minimum, maximum, exclusive_maximum) =
+        match number {
+            None => (None, None, false, None, false),
+            Some(number) => {
+                let multiple_of = number.multiple_of.map(|f| f as i64);
+                let (minimum, exclusive_minimum) =
+                    match (number.minimum, number.exclusive_minimum) {
+                        (None, None) => (None, false),
+                        (Some(f), None) => (Some(f as i64), false),
+                        (None, Some(f)) => (Some(f as i64), true),
+                        _ => panic!("invalid"),
+                    };
+                let (maximum, exclusive_maximum) =
+                    match (number.maximum, number.exclusive_maximum) {
+                        (None, None) => (None, false),
+                        (Some(f), None) => (Some(f as i64), false),
+                        (None, Some(f)) => (Some(f as i64), true),
+                        _ => panic!("invalid"),
+                    };
+
+                (
+                    multiple_of,
+                    minimum,
+                    exclusive_minimum,
+                    maximum,
+                    exclusive_maximum,
+                )
+            }
+        };
+
+    let enumeration = enum_values
+        .iter()
+        .flat_map(|v| {
+            v.iter().map(|vv| match vv {
+                serde_json::Value::Null => None,
+                serde_json::Value::Number(value) => {
+                    Some(value.as_i64().unwrap())
+                }
+                _ => panic!("unexpected enumeration value {:?}", vv),
+            })
+        })
+        .collect::<Vec<_>>();
+
+    openapiv3::SchemaKind::Type(openapiv3::Type::Integer(
+        openapiv3::IntegerType {
+            format,
+            multiple_of,
+            exclusive_minimum,
+            exclusive_maximum,
+            minimum,
+            maximum,
+            enumeration,
+        },
+    ))
+}
+
+fn j2oas_number(
+    format: &Option<String>,
+    number: &Option<Box<schemars::schema::NumberValidation>>,
+    enum_values: &Option<Vec<serde_json::value::Value>>,
+) -> openapiv3::SchemaKind {
+    let format = match format.as_ref().map(|s| s.as_str()) {
+        None => openapiv3::VariantOrUnknownOrEmpty::Empty,
+        Some("float") => openapiv3::VariantOrUnknownOrEmpty::Item(
+            openapiv3::NumberFormat::Float,
+        ),
+        Some("double") => openapiv3::VariantOrUnknownOrEmpty::Item(
+            openapiv3::NumberFormat::Double,
+        ),
+        Some(other) => {
+            openapiv3::VariantOrUnknownOrEmpty::Unknown(other.to_string())
+        }
+    };
+
+    let (multiple_of, minimum, exclusive_minimum, maximum, exclusive_maximum) =
+        match number {
+            None => (None, None, false, None, false),
+            Some(number) => {
+                let multiple_of = number.multiple_of;
+                let (minimum, exclusive_minimum) =
+                    match (number.minimum, number.exclusive_minimum) {
+                        (None, None) => (None, false),
+                        (s @ Some(_), None) => (s, false),
+                        (None, s @ Some(_)) => (s, true),
+                        _ => panic!("invalid"),
+                    };
+                let (maximum, exclusive_maximum) =
+                    match (number.maximum, number.exclusive_maximum) {
+                        (None, None) => (None, false),
+                        (s @ Some(_), None) => (s, false),
+                        (None, s @ Some(_)) => (s, true),
+                        _ => panic!("invalid"),
+                    };
+
+                (
+                    multiple_of,
+                    minimum,
+                    exclusive_minimum,
+                    maximum,
+                    exclusive_maximum,
+                )
+            }
+        };
+
+    let enumeration = enum_values
+        .iter()
+        .flat_map(|v| {
+            v.iter().map(|vv| match vv {
+                serde_json::Value::Null => None,
+                serde_json::Value::Number(value) => {
+                    Some(value.as_f64().unwrap())
+                }
+                _ => panic!("unexpected enumeration value {:?}", vv),
+            })
+        })
+        .collect::<Vec<_>>();
+
+    openapiv3::SchemaKind::Type(openapiv3::Type::Number(
+        openapiv3::NumberType {
+            format,
+            multiple_of,
+            exclusive_minimum,
+            exclusive_maximum,
+            minimum,
+            maximum,
+            enumeration,
+        },
+    ))
+}
+
+fn j2oas_string(
+    format: &Option<String>,
+    string: &Option<Box<schemars::schema::StringValidation>>,
+    enum_values: &Option<Vec<serde_json::value::Value>>,
+) -> openapiv3::SchemaKind {
+    let format = match format.as_ref().map(|s| s.as_str()) {
+        None => openapiv3::VariantOrUnknownOrEmpty::Empty,
+        Some("date") => openapiv3::VariantOrUnknownOrEmpty::Item(
+            openapiv3::StringFormat::Date,
+        ),
+        Some("date-time") => openapiv3::VariantOrUnknownOrEmpty::Item(
+            openapiv3::StringFormat::DateTime,
+        ),
+        Some("password") => openapiv3::VariantOrUnknownOrEmpty::Item(
+            openapiv3::StringFormat::Password,
+        ),
+        Some("byte") => openapiv3::VariantOrUnknownOrEmpty::Item(
+            openapiv3::StringFormat::Byte,
+        ),
+        Some("binary") => openapiv3::VariantOrUnknownOrEmpty::Item(
+            openapiv3::StringFormat::Binary,
+        ),
+        Some(other) => {
+            openapiv3::VariantOrUnknownOrEmpty::Unknown(other.to_string())
+        }
+    };
+
+    let (max_length, min_length, pattern) = match string.as_ref() {
+        None => (None, None, None),
+        Some(string) => (
+            string.max_length.map(|n| n as usize),
+            string.min_length.map(|n| n as usize),
+            string.pattern.clone(),
+        ),
+    };
+
+    let enumeration = enum_values
+        .iter()
+        .flat_map(|v| {
+            v.iter().map(|vv| match vv {
+                serde_json::Value::Null => None,
+                serde_json::Value::String(s) => Some(s.clone()),
+                _ => panic!("unexpected enumeration value {:?}", vv),
+            })
+        })
+        .collect::<Vec<_>>();
+
+    openapiv3::SchemaKind::Type(openapiv3::Type::String(
+        openapiv3::StringType {
+            format,
+            pattern,
+            enumeration,
+            min_length,
+            max_length,
+        },
+    ))
+}
+
+fn j2oas_array(
+    array: &Option<Box<schemars::schema::ArrayValidation>>,
+) -> openapiv3::SchemaKind {
+    let arr = array.as_ref().unwrap();
+
+    openapiv3::SchemaKind::Type(openapiv3::Type::Array(openapiv3::ArrayType {
+        items: match &arr.items {
+            Some(schemars::schema::SingleOrVec::Single(schema)) => {
+                Some(box_reference_or(j2oas_schema(None, &schema)))
+            }
+            Some(schemars::schema::SingleOrVec::Vec(_)) => {
+                panic!("OpenAPI v3.0.x cannot support tuple-like arrays")
+            }
+            None => None,
+        },
+        min_items: arr.min_items.map(|n| n as usize),
+        max_items: arr.max_items.map(|n| n as usize),
+        unique_items: arr.unique_items.unwrap_or(false),
+    }))
+}
+
+fn box_reference_or<T>(
+    r: openapiv3::ReferenceOr<T>,
+) -> openapiv3::ReferenceOr<Box<T>> {
+    match r {
+        openapiv3::ReferenceOr::Item(schema) => {
+            openapiv3::ReferenceOr::boxed_item(schema)
+        }
+        openapiv3::ReferenceOr::Reference { reference } => {
+            openapiv3::ReferenceOr::Reference { reference }
+        }
+    }
+}
+
+fn j2oas_object(
+    object: &Option<Box<schemars::schema::ObjectValidation>>,
+) -> openapiv3::SchemaKind {
+    match object {
+        None => openapiv3::SchemaKind::Type(openapiv3::Type::Object(
+            openapiv3::ObjectType::default(),
+        )),
+        Some(obj) => openapiv3::SchemaKind::Type(openapiv3::Type::Object(
+            openapiv3::ObjectType {
+                properties: obj
+                    .properties
+                    .iter()
+                    .map(|(prop, schema)| {
+                        (
+                            prop.clone(),
+                            box_reference_or(j2oas_schema(None, schema)),
+                        )
+                    })
+                    .collect::<_>(),
+                required: obj.required.iter().cloned().collect::<_>(),
+                additional_properties: obj.additional_properties.as_ref().map(
+                    |schema| match schema.as_ref() {
+                        schemars::schema::Schema::Bool(b) => {
+                            openapiv3::AdditionalProperties::Any(*b)
+                        }
+                        schemars::schema::Schema::Object(obj) => {
+                            openapiv3::AdditionalProperties::Schema(Box::new(
+                                j2oas_schema_object(None, obj),
+                            ))
+                        }
+                    },
+                ),
+                min_properties: obj.min_properties.map(|n| n as usize),
+                max_properties: obj.max_properties.map(|n| n as usize),
+            },
+        )),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::j2oas_schema;
+    use super::j2oas_schema_object;
+    use schemars::JsonSchema;
+
+    #[test]
+    fn test_empty_struct() {
+        #[derive(JsonSchema)]
+        struct Empty {}
+
+        let settings = schemars::gen::SchemaSettings::openapi3();
+        let mut generator = schemars::gen::SchemaGenerator::new(settings);
+
+        let schema = Empty::json_schema(&mut generator);
+        let _ = j2oas_schema(None, &schema);
+    }
+
+    #[test]
+    fn test_garbage_barge_structure_conversion() {
+        #[allow(dead_code)]
+        #[derive(JsonSchema)]
+        struct SuperGarbage {
+            string: String,
+            strings: Vec<String>,
+            more_strings: [String; 3],
+            substruct: Substruct,
+            more: Option<Substruct>,
+            union: Union,
+            map: std::collections::BTreeMap<String, String>,
+        }
+
+        #[allow(dead_code)]
+        #[derive(JsonSchema)]
+        struct Substruct {
+            ii32: i32,
+            uu64: u64,
+            ff: f32,
+            dd: f64,
+            b: bool,
+        }
+
+        #[allow(dead_code)]
+        #[derive(JsonSchema)]
+        enum Union {
+            A { a: u32 },
+            B { b: f32 },
+        }
+
+        let settings = schemars::gen::SchemaSettings::openapi3();
+        let mut generator = schemars::gen::SchemaGenerator::new(settings);
+
+        let schema = SuperGarbage::json_schema(&mut generator);
+        let _ = j2oas_schema(None, &schema);
+        for (key, schema) in generator.definitions().iter() {
+            let _ = j2oas_schema(Some(key), schema);
+        }
+    }
+
+    #[test]
+    fn test_additional_properties() {
+        #[allow(dead_code)]
+        #[derive(JsonSchema)]
+        enum Union {
+            A { a: u32 },
+        }
+        let settings = schemars::gen::SchemaSettings::openapi3();
+        let mut generator = schemars::gen::SchemaGenerator::new(settings);
+        let schema = Union::json_schema(&mut generator);
+        let _ = j2oas_schema(None, &schema);
+        for (key, schema) in generator.definitions().iter() {
+            let _ = j2oas_schema(Some(key), schema);
+        }
+    }
+
+    #[test]
+    fn test_nullable() {
+        #[allow(dead_code)]
+        #[derive(JsonSchema)]
+        struct Foo {
+            bar: String,
+        }
+        let settings = schemars::gen::SchemaSettings::openapi3();
+        let generator = schemars::gen::SchemaGenerator::new(settings);
+        let root_schema = generator.into_root_schema_for::<Option<Foo>>();
+        let schema = root_schema.schema;
+        let os = j2oas_schema_object(None, &schema);
+
+        assert_eq!(
+            os,
+            openapiv3::ReferenceOr::Item(openapiv3::Schema {
+                schema_data: openapiv3::SchemaData {
+                    title: Some("Nullable_Foo".to_string()),
+                    nullable: true,
+                    ..Default::default()
+                },
+                schema_kind: openapiv3::SchemaKind::AllOf {
+                    all_of: vec![openapiv3::ReferenceOr::Reference {
+                        reference: "#/components/schemas/Foo".to_string()
+                    }],
+                },
+            })
+        );
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_bad_schema() {
+        #![allow(unused)]
+
+        #[derive(JsonSchema)]
+        #[schemars(tag = "which")]
+        enum Which {
+            This,
+            That,
+        }
+
+        #[derive(JsonSchema)]
+        struct BlackSheep {
+            #[schemars(flatten)]
+            you_can_get_with: Which,
+        }
+
+        let schema = schemars::schema_for!(BlackSheep).schema;
+
+        let _ = j2oas_schema_object(None, &schema);
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_two_types() {
+        #![allow(unused)]
+
+        #[derive(JsonSchema)]
+        enum One {
+            One,
+        }
+
+        #[derive(JsonSchema)]
+        struct Uno {
+            #[schemars(flatten)]
+            one: One,
+        }
+
+        let schema = schemars::schema_for!(Uno).schema;
+
+        let _ = j2oas_schema_object(None, &schema);
+    }
+}
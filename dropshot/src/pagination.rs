@@ -97,6 +97,7 @@
 //! [1]: https://cloud.google.com/apis/design/design_patterns#list_pagination
 //! [2]: https://www.citusdata.com/blog/2016/03/30/five-ways-to-paginate/
 
+use crate::api_description::PAGINATION_PARAM_SENTINEL;
 use crate::error::HttpError;
 use crate::from_map::from_map;
 use base64::engine::general_purpose::URL_SAFE;
@@ -227,10 +228,6 @@ where
     pub(crate) limit: Option<NonZeroU32>,
 }
 
-pub(crate) const PAGINATION_PARAM_SENTINEL: &str =
-    "x-dropshot-pagination-param";
-pub(crate) const PAGINATION_EXTENSION: &str = "x-dropshot-pagination";
-
 impl<ScanParams, PageSelector> JsonSchema
     for PaginationParams<ScanParams, PageSelector>
 where
@@ -471,7 +468,7 @@ mod test {
     use super::PaginationParams;
     use super::ResultsPage;
     use super::WhichPage;
-    use super::PAGINATION_PARAM_SENTINEL;
+    use crate::api_description::PAGINATION_PARAM_SENTINEL;
     use base64::engine::general_purpose::URL_SAFE;
     use base64::Engine;
     use schemars::JsonSchema;
@@ -0,0 +1,164 @@
+// Copyright 2023 Oxide Computer Company
+//! Protocol Buffers request and response bodies
+//!
+//! [`ProtobufBody`] is an extractor that decodes an `application/x-protobuf`
+//! request body into a [`prost::Message`], and [`Protobuf`] wraps a message
+//! so that it's sent as the body of a typed response (e.g.,
+//! `HttpResponseOk<Protobuf<T>>`).
+//!
+//! Protobuf messages don't have JSON schemas, so the OpenAPI document
+//! describes these bodies as binary strings and names the message type in the
+//! `x-protobuf-message` vendor extension of the schema.
+
+use crate::api_description::ApiEndpointBodyContentType;
+use crate::api_description::ApiEndpointParameter;
+use crate::api_description::ApiSchemaGenerator;
+use crate::api_description::ExtensionMode;
+use crate::error::HttpError;
+use crate::expect_continue::check_declared_length;
+use crate::handler::HttpHandlerResult;
+use crate::handler::HttpResponseContent;
+use crate::http_util::http_read_body;
+use crate::http_util::CONTENT_TYPE_PROTOBUF;
+use crate::server::ServerContext;
+use crate::ExclusiveExtractor;
+use crate::ExtractorMetadata;
+use crate::RequestContext;
+use async_trait::async_trait;
+use schemars::schema::InstanceType;
+use schemars::schema::SchemaObject;
+
+/// Name of the schema extension that identifies the Protobuf message type of a
+/// body
+const PROTOBUF_MESSAGE_EXTENSION: &str = "x-protobuf-message";
+
+/// `ProtobufBody<T>` is an extractor used to decode a Protobuf message of type
+/// `T` from an `application/x-protobuf` request body.  (A request without a
+/// `Content-Type` header is assumed to be Protobuf.)  As with
+/// [`TypedBody`](crate::TypedBody), a compressed body is decompressed first.
+#[derive(Debug)]
+pub struct ProtobufBody<T: prost::Message + prost::Name + Default> {
+    inner: T,
+}
+
+impl<T: prost::Message + prost::Name + Default> ProtobufBody<T> {
+    pub fn into_inner(self) -> T {
+        self.inner
+    }
+}
+
+#[async_trait]
+impl<T> ExclusiveExtractor for ProtobufBody<T>
+where
+    T: prost::Message + prost::Name + Default + 'static,
+{
+    async fn from_request<Context: ServerContext>(
+        rqctx: &RequestContext<Context>,
+        mut request: hyper::Request<crate::Body>,
+    ) -> Result<ProtobufBody<T>, HttpError> {
+        let content_type = request
+            .headers()
+            .get(http::header::CONTENT_TYPE)
+            .map(|hv| {
+                hv.to_str().map_err(|e| {
+                    HttpError::for_bad_request(
+                        None,
+                        format!("invalid content type: {}", e),
+                    )
+                })
+            })
+            .unwrap_or(Ok(CONTENT_TYPE_PROTOBUF))?;
+        let end = content_type.find(';').unwrap_or_else(|| content_type.len());
+        let mime_type = content_type[..end].trim_end().to_lowercase();
+        if mime_type != CONTENT_TYPE_PROTOBUF {
+            return Err(HttpError::for_bad_request(
+                None,
+                format!(
+                    "expected content type \"{}\", got \"{}\"",
+                    CONTENT_TYPE_PROTOBUF, mime_type
+                ),
+            ));
+        }
+
+        let max_bytes = rqctx.request_body_max_bytes();
+        check_declared_length(request.headers(), max_bytes)?;
+        let body = http_read_body(request.body_mut(), max_bytes).await?;
+        let decompressed_max_bytes = rqctx
+            .server
+            .config
+            .request_body_decompressed_max_bytes
+            .unwrap_or(max_bytes);
+        let body = rqctx.server.content_decoders.decode(
+            request.headers(),
+            body,
+            decompressed_max_bytes,
+        )?;
+
+        let inner = T::decode(body).map_err(|e| {
+            HttpError::for_bad_request(
+                None,
+                format!("unable to parse Protobuf body: {}", e),
+            )
+        })?;
+        Ok(ProtobufBody { inner })
+    }
+
+    fn metadata(
+        _content_type: ApiEndpointBodyContentType,
+    ) -> ExtractorMetadata {
+        ExtractorMetadata {
+            parameters: vec![ApiEndpointParameter::new_body(
+                ApiEndpointBodyContentType::Protobuf,
+                true,
+                message_schema::<T>(),
+                vec![],
+            )],
+            extension_mode: ExtensionMode::None,
+        }
+    }
+}
+
+/// Wraps a Protobuf message so that it's sent as `application/x-protobuf` when
+/// used with coded response types such as
+/// [`HttpResponseOk`](crate::HttpResponseOk).
+pub struct Protobuf<T>(pub T);
+
+impl<T> HttpResponseContent for Protobuf<T>
+where
+    T: prost::Message + prost::Name + Send + Sync + 'static,
+{
+    fn to_response(
+        self,
+        builder: http::response::Builder,
+    ) -> HttpHandlerResult {
+        Ok(builder
+            .header(http::header::CONTENT_TYPE, CONTENT_TYPE_PROTOBUF)
+            .body(self.0.encode_to_vec().into())?)
+    }
+
+    fn content_metadata() -> Option<ApiSchemaGenerator> {
+        Some(message_schema::<T>())
+    }
+
+    fn content_type() -> &'static str {
+        CONTENT_TYPE_PROTOBUF
+    }
+}
+
+/// Describes a body containing a message of type `T`: a binary string whose
+/// message type is named by a vendor extension.
+fn message_schema<T: prost::Name>() -> ApiSchemaGenerator {
+    let mut schema = SchemaObject {
+        instance_type: Some(InstanceType::String.into()),
+        format: Some(String::from("binary")),
+        ..Default::default()
+    };
+    schema.extensions.insert(
+        PROTOBUF_MESSAGE_EXTENSION.to_string(),
+        serde_json::Value::String(T::full_name()),
+    );
+    ApiSchemaGenerator::Static {
+        schema: Box::new(schema.into()),
+        dependencies: indexmap::IndexMap::default(),
+    }
+}
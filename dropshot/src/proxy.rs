@@ -0,0 +1,412 @@
+// Copyright 2023 Oxide Computer Company
+//! Facilities for forwarding requests to an upstream HTTP server
+//!
+//! This is intended for gateway-style services that implement some endpoints
+//! themselves and pass others along to another server.  A [`Proxy`] is
+//! typically stored in the server context and used from an endpoint that
+//! accepts the [`RawRequest`](crate::RawRequest):
+//!
+//! ```
+//! use dropshot::endpoint;
+//! use dropshot::proxy::Proxy;
+//! use dropshot::HttpError;
+//! use dropshot::Path;
+//! use dropshot::RawRequest;
+//! use dropshot::RequestContext;
+//! use dropshot::Body;
+//! use hyper::Response;
+//! use schemars::JsonSchema;
+//! use serde::Deserialize;
+//!
+//! #[derive(Deserialize, JsonSchema)]
+//! struct AllPath {
+//!     path: Vec<String>,
+//! }
+//!
+//! #[endpoint {
+//!     method = GET,
+//!     path = "/upstream/{path:.*}",
+//!     unpublished = true,
+//! }]
+//! async fn upstream_get(
+//!     rqctx: RequestContext<Proxy>,
+//!     path: Path<AllPath>,
+//!     raw_request: RawRequest,
+//! ) -> Result<Response<Body>, HttpError> {
+//!     let path = path.into_inner().path.join("/");
+//!     rqctx.context().forward(&rqctx, raw_request.into_inner(), &path).await
+//! }
+//! ```
+//!
+//! The request body and response body are streamed in both directions rather
+//! than buffered, so `request_body_max_bytes` does not apply to proxied
+//! requests.
+
+use crate::error::HttpError;
+use crate::handler::RequestContext;
+use crate::server::ServerContext;
+use crate::Body;
+
+use http::header;
+use http::header::HeaderName;
+use http::HeaderMap;
+use http::HeaderValue;
+use http::StatusCode;
+use http::Uri;
+use hyper::Request;
+use hyper::Response;
+use hyper_util::client::legacy::connect::HttpConnector;
+use hyper_util::client::legacy::Client;
+use hyper_util::rt::TokioExecutor;
+use percent_encoding::utf8_percent_encode;
+use percent_encoding::AsciiSet;
+use percent_encoding::CONTROLS;
+use std::time::Duration;
+
+/// Headers that are meaningful only for a single transport-level connection
+/// and must not be forwarded by proxies (RFC 9110 section 7.6.1).
+static HOP_BY_HOP_HEADERS: [HeaderName; 7] = [
+    header::CONNECTION,
+    header::PROXY_AUTHENTICATE,
+    header::PROXY_AUTHORIZATION,
+    header::TE,
+    header::TRAILER,
+    header::TRANSFER_ENCODING,
+    header::UPGRADE,
+];
+
+/// Characters that must be percent-encoded within a path segment (the WHATWG
+/// URL standard's "path percent-encode set", plus `/` and `%`)
+const PATH_SEGMENT: &AsciiSet = &CONTROLS
+    .add(b' ')
+    .add(b'"')
+    .add(b'#')
+    .add(b'<')
+    .add(b'>')
+    .add(b'?')
+    .add(b'`')
+    .add(b'{')
+    .add(b'}')
+    .add(b'/')
+    .add(b'%');
+
+const HEADER_KEEP_ALIVE: &str = "keep-alive";
+const HEADER_X_FORWARDED_FOR: &str = "x-forwarded-for";
+const HEADER_X_FORWARDED_HOST: &str = "x-forwarded-host";
+const HEADER_X_FORWARDED_PROTO: &str = "x-forwarded-proto";
+
+/// Forwards requests to an upstream HTTP server.
+///
+/// Connections to the upstream server are pooled and reused across requests,
+/// so a single `Proxy` should be shared by all requests to a given upstream.
+#[derive(Debug)]
+pub struct Proxy {
+    client: Client<HttpConnector, Body>,
+    upstream: Uri,
+    timeout: Option<Duration>,
+}
+
+impl Proxy {
+    /// Creates a `Proxy` for the upstream server at `upstream`, an `http` URL
+    /// (e.g., `"http://127.0.0.1:8080/api"`).  Paths passed to
+    /// [`Proxy::forward`] are appended to the path of this URL.
+    pub fn new(upstream: &str) -> Result<Proxy, String> {
+        let upstream: Uri = upstream
+            .parse()
+            .map_err(|e| format!("invalid upstream URL: {}", e))?;
+        if upstream.scheme() != Some(&http::uri::Scheme::HTTP) {
+            return Err(String::from(
+                "invalid upstream URL: only \"http\" is supported",
+            ));
+        }
+        if upstream.authority().is_none() {
+            return Err(String::from("invalid upstream URL: missing host"));
+        }
+        if upstream.query().is_some() {
+            return Err(String::from(
+                "invalid upstream URL: query string not allowed",
+            ));
+        }
+
+        let client = Client::builder(TokioExecutor::new()).build_http();
+        Ok(Proxy { client, upstream, timeout: None })
+    }
+
+    /// Sets how long to wait for the upstream server to begin responding to a
+    /// request.  If it hasn't sent the response headers by then, the request
+    /// fails with a 504 ("Gateway Timeout") error.  This does not limit how
+    /// long it takes to stream the response body.
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    /// Forwards `request` to the upstream server at `path` (relative to the
+    /// upstream URL), preserving the request's query string, and returns the
+    /// upstream server's response.
+    ///
+    /// `path` is the decoded path, such as the segments of a wildcard path
+    /// variable joined with `/`.  Each of its segments is percent-encoded for
+    /// the upstream request, so characters like `?` and `#` stay part of the
+    /// path.  Segments that are `.` or `..` are rejected with a 400 ("Bad
+    /// Request") error, so that the request can't escape the upstream URL's
+    /// path.
+    ///
+    /// Hop-by-hop headers are removed from both the request and the response.
+    /// `X-Forwarded-For`, `X-Forwarded-Host`, and `X-Forwarded-Proto` are added
+    /// to the request to describe the original client request.
+    ///
+    /// If the upstream server cannot be reached, this returns a 502 ("Bad
+    /// Gateway") error.
+    pub async fn forward<C: ServerContext>(
+        &self,
+        rqctx: &RequestContext<C>,
+        request: Request<Body>,
+        path: &str,
+    ) -> Result<Response<Body>, HttpError> {
+        let (mut parts, body) = request.into_parts();
+
+        let uri = self.upstream_uri(path, parts.uri.query())?;
+        let original_host = parts.headers.remove(header::HOST);
+        remove_hop_by_hop_headers(&mut parts.headers);
+        add_forwarded_headers(
+            &mut parts.headers,
+            rqctx,
+            original_host.as_ref(),
+        )?;
+        parts.uri = uri;
+        // The request may have arrived over any version of HTTP, but the
+        // connection to the upstream server always uses HTTP/1.1.
+        parts.version = http::Version::HTTP_11;
+
+        debug!(rqctx.log, "forwarding request to upstream";
+            "upstream_uri" => %parts.uri,
+        );
+
+        let response_fut =
+            self.client.request(Request::from_parts(parts, body));
+        let result = match self.timeout {
+            None => response_fut.await,
+            Some(timeout) => tokio::time::timeout(timeout, response_fut)
+                .await
+                .map_err(|_| HttpError {
+                    status_code: StatusCode::GATEWAY_TIMEOUT,
+                    error_code: None,
+                    external_message: StatusCode::GATEWAY_TIMEOUT
+                        .canonical_reason()
+                        .unwrap()
+                        .to_string(),
+                    internal_message: format!(
+                        "upstream server did not respond within {:?}",
+                        timeout
+                    ),
+                })?,
+        };
+
+        let mut response = result.map_err(|e| HttpError {
+            status_code: StatusCode::BAD_GATEWAY,
+            error_code: None,
+            external_message: StatusCode::BAD_GATEWAY
+                .canonical_reason()
+                .unwrap()
+                .to_string(),
+            internal_message: format!("upstream request failed: {}", e),
+        })?;
+
+        remove_hop_by_hop_headers(response.headers_mut());
+        Ok(response.map(Body::wrap))
+    }
+
+    fn upstream_uri(
+        &self,
+        path: &str,
+        query: Option<&str>,
+    ) -> Result<Uri, HttpError> {
+        let mut path_and_query =
+            self.upstream.path().trim_end_matches('/').to_string();
+        for segment in path.trim_start_matches('/').split('/') {
+            if segment == "." || segment == ".." {
+                return Err(HttpError::for_bad_request(
+                    None,
+                    format!("path segment {:?} is not permitted", segment),
+                ));
+            }
+            path_and_query.push('/');
+            path_and_query.extend(utf8_percent_encode(segment, PATH_SEGMENT));
+        }
+        if let Some(query) = query {
+            path_and_query.push('?');
+            path_and_query.push_str(query);
+        }
+
+        Uri::builder()
+            .scheme(http::uri::Scheme::HTTP)
+            .authority(self.upstream.authority().unwrap().clone())
+            .path_and_query(path_and_query)
+            .build()
+            .map_err(|e| {
+                HttpError::for_bad_request(
+                    None,
+                    format!("invalid path for upstream request: {}", e),
+                )
+            })
+    }
+}
+
+/// Removes hop-by-hop headers, including any named in the `Connection` header.
+fn remove_hop_by_hop_headers(headers: &mut HeaderMap) {
+    let connection_headers: Vec<HeaderName> = headers
+        .get_all(header::CONNECTION)
+        .iter()
+        .filter_map(|v| v.to_str().ok())
+        .flat_map(|v| v.split(','))
+        .filter_map(|name| HeaderName::from_bytes(name.trim().as_bytes()).ok())
+        .collect();
+    for name in connection_headers {
+        headers.remove(name);
+    }
+    for name in &HOP_BY_HOP_HEADERS {
+        headers.remove(name);
+    }
+    headers.remove(HEADER_KEEP_ALIVE);
+}
+
+fn add_forwarded_headers<C: ServerContext>(
+    headers: &mut HeaderMap,
+    rqctx: &RequestContext<C>,
+    original_host: Option<&HeaderValue>,
+) -> Result<(), HttpError> {
+    // Per convention, a proxy appends the address of its own client to any
+    // X-Forwarded-For value it received.
+    // The value may be split across several header lines, which together form
+    // a single comma-separated list.
+    let mut forwarded_for = headers
+        .get_all(HEADER_X_FORWARDED_FOR)
+        .iter()
+        .map(|value| {
+            value.to_str().map_err(|_| {
+                HttpError::for_bad_request(
+                    None,
+                    format!("invalid {} header", HEADER_X_FORWARDED_FOR),
+                )
+            })
+        })
+        .collect::<Result<Vec<_>, _>>()?
+        .join(", ");
+    if !forwarded_for.is_empty() {
+        forwarded_for.push_str(", ");
+    }
+    forwarded_for.push_str(&rqctx.connection().remote_addr().ip().to_string());
+    headers.insert(
+        HEADER_X_FORWARDED_FOR,
+        HeaderValue::from_str(&forwarded_for).unwrap(),
+    );
+
+    // The scheme is the one the client used, even if a trusted proxy in front
+    // of this one received the request.
+    let proto =
+        HeaderValue::from_str(rqctx.client().scheme().as_str()).unwrap();
+    headers.insert(HEADER_X_FORWARDED_PROTO, proto);
+
+    if let Some(host) = original_host {
+        headers.insert(HEADER_X_FORWARDED_HOST, host.clone());
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::remove_hop_by_hop_headers;
+    use super::Proxy;
+    use http::HeaderMap;
+
+    #[test]
+    fn test_proxy_bad_upstream() {
+        assert!(Proxy::new("not a url")
+            .unwrap_err()
+            .starts_with("invalid upstream URL: "));
+        assert_eq!(
+            Proxy::new("https://127.0.0.1").unwrap_err(),
+            "invalid upstream URL: only \"http\" is supported"
+        );
+        assert_eq!(
+            Proxy::new("/relative").unwrap_err(),
+            "invalid upstream URL: only \"http\" is supported"
+        );
+        assert_eq!(
+            Proxy::new("http://127.0.0.1/?a=b").unwrap_err(),
+            "invalid upstream URL: query string not allowed"
+        );
+    }
+
+    #[test]
+    fn test_proxy_upstream_uri() {
+        let proxy = Proxy::new("http://127.0.0.1:8080").unwrap();
+        assert_eq!(
+            proxy.upstream_uri("foo/bar", None).unwrap().to_string(),
+            "http://127.0.0.1:8080/foo/bar"
+        );
+        assert_eq!(
+            proxy.upstream_uri("/foo", Some("a=b")).unwrap().to_string(),
+            "http://127.0.0.1:8080/foo?a=b"
+        );
+
+        let proxy = Proxy::new("http://127.0.0.1:8080/api/").unwrap();
+        assert_eq!(
+            proxy.upstream_uri("foo", None).unwrap().to_string(),
+            "http://127.0.0.1:8080/api/foo"
+        );
+        assert_eq!(
+            proxy.upstream_uri("", None).unwrap().to_string(),
+            "http://127.0.0.1:8080/api/"
+        );
+    }
+
+    #[test]
+    fn test_proxy_upstream_uri_escaping() {
+        let proxy = Proxy::new("http://127.0.0.1:8080/api").unwrap();
+
+        // Characters that would start a query or fragment stay in the path.
+        assert_eq!(
+            proxy.upstream_uri("a?b=c#d", Some("q=1")).unwrap().to_string(),
+            "http://127.0.0.1:8080/api/a%3Fb=c%23d?q=1"
+        );
+        assert_eq!(
+            proxy.upstream_uri("with space/100%", None).unwrap().to_string(),
+            "http://127.0.0.1:8080/api/with%20space/100%25"
+        );
+        assert_eq!(
+            proxy.upstream_uri("dir/", None).unwrap().to_string(),
+            "http://127.0.0.1:8080/api/dir/"
+        );
+
+        // Segments that would escape the upstream path are rejected.
+        for path in ["..", "../secret", "a/../../secret", "./a", "a/."] {
+            let error = proxy.upstream_uri(path, None).unwrap_err();
+            assert_eq!(error.status_code, http::StatusCode::BAD_REQUEST);
+        }
+        assert_eq!(
+            proxy.upstream_uri("a..b/.c", None).unwrap().to_string(),
+            "http://127.0.0.1:8080/api/a..b/.c"
+        );
+    }
+
+    #[test]
+    fn test_remove_hop_by_hop_headers() {
+        let mut headers = HeaderMap::new();
+        headers.insert("connection", "keep-alive, x-custom".parse().unwrap());
+        headers.insert("keep-alive", "timeout=5".parse().unwrap());
+        headers.insert("x-custom", "1".parse().unwrap());
+        headers.insert("transfer-encoding", "chunked".parse().unwrap());
+        headers.insert("te", "trailers".parse().unwrap());
+        headers.insert("x-other", "2".parse().unwrap());
+        headers.insert("content-type", "text/plain".parse().unwrap());
+        remove_hop_by_hop_headers(&mut headers);
+
+        let mut remaining =
+            headers.keys().map(|k| k.as_str()).collect::<Vec<_>>();
+        remaining.sort();
+        assert_eq!(remaining, vec!["content-type", "x-other"]);
+    }
+}
@@ -0,0 +1,485 @@
+// Copyright 2023 Oxide Computer Company
+//! Sampling, filtering, and redaction of per-request log entries
+
+use crate::config::ConfigLogRedaction;
+use crate::config::ConfigRequestLogSampling;
+use crate::logging::ConfigLoggingLevel;
+use crate::logging::LevelOverride;
+use crate::route_pattern::RoutePattern;
+use crate::router::HttpRouter;
+use crate::server::ServerContext;
+use http::HeaderMap;
+use http::Method;
+use http::StatusCode;
+use http::Uri;
+use percent_encoding::percent_decode_str;
+use slog::Level;
+use slog::Logger;
+use std::collections::BTreeMap;
+use std::collections::BTreeSet;
+use std::sync::atomic::AtomicU32;
+use std::sync::atomic::AtomicU64;
+use std::sync::atomic::Ordering;
+use std::sync::RwLock;
+use std::time::Instant;
+
+/// Decides whether the "request completed" log entry for a given request should
+/// be emitted, based on the server's configured sampling rules.
+///
+/// The counters used here are shared by all requests to the server, so they're
+/// updated with atomics rather than under a lock.  Under contention, the rate
+/// limit may be exceeded by a small number of entries at the boundary between
+/// two one-second windows.  That's fine for this purpose.
+#[derive(Debug)]
+pub(crate) struct RequestLogSampler {
+    rules: Vec<SamplingRule>,
+    start_time: Instant,
+}
+
+#[derive(Debug)]
+struct SamplingRule {
+    config: ConfigRequestLogSampling,
+    /// `config.path`, parsed
+    pattern: RoutePattern,
+    /// number of successful requests seen that matched this rule
+    nseen: AtomicU64,
+    /// second (relative to `start_time`) of the current rate-limiting window
+    window: AtomicU64,
+    /// number of entries logged in the current rate-limiting window
+    window_nlogged: AtomicU32,
+}
+
+impl SamplingRule {
+    fn sample(&self, now_secs: u64) -> bool {
+        let sample_rate = u64::from(self.config.sample_rate);
+        if sample_rate == 0 {
+            return false;
+        }
+
+        let nseen = self.nseen.fetch_add(1, Ordering::Relaxed);
+        if nseen % sample_rate != 0 {
+            return false;
+        }
+
+        let max_per_second = match self.config.max_per_second {
+            None => return true,
+            Some(max) => max,
+        };
+
+        let window = self.window.load(Ordering::Relaxed);
+        if window != now_secs
+            && self
+                .window
+                .compare_exchange(
+                    window,
+                    now_secs,
+                    Ordering::Relaxed,
+                    Ordering::Relaxed,
+                )
+                .is_ok()
+        {
+            self.window_nlogged.store(0, Ordering::Relaxed);
+        }
+
+        self.window_nlogged.fetch_add(1, Ordering::Relaxed) < max_per_second
+    }
+}
+
+impl RequestLogSampler {
+    pub fn new(config: &[ConfigRequestLogSampling]) -> RequestLogSampler {
+        RequestLogSampler {
+            rules: config
+                .iter()
+                .map(|c| SamplingRule {
+                    config: c.clone(),
+                    pattern: RoutePattern::new(&c.path),
+                    nseen: AtomicU64::new(0),
+                    window: AtomicU64::new(0),
+                    window_nlogged: AtomicU32::new(0),
+                })
+                .collect(),
+            start_time: Instant::now(),
+        }
+    }
+
+    /// Returns whether a request to `route` (the path template of the matched
+    /// endpoint, if any) that completed with `status` should be logged.
+    ///
+    /// Requests that did not match any endpoint, requests that did not match
+    /// any sampling rule, and requests that failed are always logged.
+    pub fn should_log(&self, route: Option<&str>, status: StatusCode) -> bool {
+        if status.is_client_error() || status.is_server_error() {
+            return true;
+        }
+
+        let route = match route {
+            None => return true,
+            Some(route) => route,
+        };
+
+        match self.rules.iter().find(|rule| rule.pattern.matches(route)) {
+            None => true,
+            Some(rule) => rule.sample(self.start_time.elapsed().as_secs()),
+        }
+    }
+}
+
+/// Per-endpoint overrides of the level used for request-scoped log entries
+/// (both the ones Dropshot emits and those emitted by the handler via
+/// `RequestContext::log`).
+///
+/// Initial values come from the endpoints' `log_level`, but they can be changed
+/// while the server is running.
+#[derive(Debug)]
+pub(crate) struct EndpointLogLevels {
+    /// path template -> HTTP method -> level
+    levels: RwLock<BTreeMap<String, BTreeMap<String, ConfigLoggingLevel>>>,
+}
+
+impl EndpointLogLevels {
+    pub fn new<C: ServerContext>(router: &HttpRouter<C>) -> EndpointLogLevels {
+        let mut levels = BTreeMap::new();
+        for (_, _, endpoint) in router {
+            if let Some(level) = &endpoint.log_level {
+                levels
+                    .entry(endpoint.path.clone())
+                    .or_insert_with(BTreeMap::new)
+                    .insert(endpoint.method.to_string(), level.clone());
+            }
+        }
+        EndpointLogLevels { levels: RwLock::new(levels) }
+    }
+
+    /// Returns the override for the given endpoint, if any.
+    pub fn get(
+        &self,
+        method: &Method,
+        path: &str,
+    ) -> Option<ConfigLoggingLevel> {
+        let levels = self.levels.read().unwrap();
+        levels.get(path).and_then(|m| m.get(method.as_str())).cloned()
+    }
+
+    /// Sets (or, with `None`, removes) the override for the given endpoint.
+    pub fn set(
+        &self,
+        method: &Method,
+        path: &str,
+        level: Option<ConfigLoggingLevel>,
+    ) {
+        let mut levels = self.levels.write().unwrap();
+        match level {
+            Some(level) => {
+                levels
+                    .entry(path.to_string())
+                    .or_insert_with(BTreeMap::new)
+                    .insert(method.to_string(), level);
+            }
+            None => {
+                if let Some(methods) = levels.get_mut(path) {
+                    methods.remove(method.as_str());
+                    if methods.is_empty() {
+                        levels.remove(path);
+                    }
+                }
+            }
+        }
+    }
+
+    /// Returns a logger that emits whatever `log` would, but at the override
+    /// for the given endpoint, if any, rather than the server's level.  If
+    /// there's no override, this is just `log`.
+    ///
+    /// The returned logger filters entries by the override itself, and the
+    /// entries it passes on get past the server logger's filter by its
+    /// configured level (see [`RequestLevelFilter`](crate::RequestLevelFilter)),
+    /// so the override can make logging either more or less verbose.
+    pub fn filter_logger(
+        &self,
+        method: &Method,
+        path: &str,
+        log: Logger,
+    ) -> Logger {
+        match self.get(method, path) {
+            None => log,
+            Some(level) => LevelOverride::logger(log, Level::from(&level)),
+        }
+    }
+}
+
+/// Placeholder logged in place of redacted values
+const REDACTED: &str = "<redacted>";
+
+/// Masks sensitive values in request data before it's logged, as configured by
+/// [`ConfigLogRedaction`].
+#[derive(Debug)]
+pub(crate) struct LogRedactor {
+    /// header names, in lowercase
+    headers: BTreeSet<String>,
+    query_params: BTreeSet<String>,
+    body_fields: BTreeSet<String>,
+}
+
+impl LogRedactor {
+    pub fn new(config: &ConfigLogRedaction) -> LogRedactor {
+        LogRedactor {
+            headers: config.headers.iter().map(|h| h.to_lowercase()).collect(),
+            query_params: config.query_params.iter().cloned().collect(),
+            body_fields: config.body_fields.iter().cloned().collect(),
+        }
+    }
+
+    /// Returns the URI as it should appear in the log, with the values of any
+    /// redacted query parameters replaced.
+    pub fn redact_uri(&self, uri: &Uri) -> String {
+        let query = match uri.query() {
+            Some(query) if !self.query_params.is_empty() => query,
+            _ => return uri.to_string(),
+        };
+
+        let redacted = query
+            .split('&')
+            .map(|pair| {
+                let raw_name = pair.split('=').next().unwrap_or_default();
+                let name = percent_decode_str(&raw_name.replace('+', " "))
+                    .decode_utf8_lossy()
+                    .into_owned();
+                if self.query_params.contains(&name) {
+                    format!("{}={}", raw_name, REDACTED)
+                } else {
+                    pair.to_string()
+                }
+            })
+            .collect::<Vec<_>>()
+            .join("&");
+
+        let mut s = String::new();
+        if let Some(scheme) = uri.scheme_str() {
+            s.push_str(scheme);
+            s.push_str("://");
+        }
+        if let Some(authority) = uri.authority() {
+            s.push_str(authority.as_str());
+        }
+        s.push_str(uri.path());
+        s.push('?');
+        s.push_str(&redacted);
+        s
+    }
+
+    /// Returns the request headers as they should appear in the log.  Repeated
+    /// headers are combined into one comma-separated value.
+    pub fn redact_headers(
+        &self,
+        headers: &HeaderMap,
+    ) -> BTreeMap<String, String> {
+        let mut rv = BTreeMap::new();
+        for (name, value) in headers {
+            let value = if self.headers.contains(name.as_str()) {
+                REDACTED.to_string()
+            } else {
+                String::from_utf8_lossy(value.as_bytes()).into_owned()
+            };
+            rv.entry(name.as_str().to_string())
+                .and_modify(|v: &mut String| {
+                    v.push_str(", ");
+                    v.push_str(&value);
+                })
+                .or_insert(value);
+        }
+        rv
+    }
+
+    /// Replaces the values of redacted fields anywhere within `value`.
+    pub fn redact_json(&self, value: &mut serde_json::Value) {
+        match value {
+            serde_json::Value::Object(map) => {
+                for (name, field) in map.iter_mut() {
+                    if self.body_fields.contains(name) {
+                        *field = serde_json::Value::from(REDACTED);
+                    } else {
+                        self.redact_json(field);
+                    }
+                }
+            }
+            serde_json::Value::Array(items) => {
+                for item in items {
+                    self.redact_json(item);
+                }
+            }
+            _ => (),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::EndpointLogLevels;
+    use super::LogRedactor;
+    use super::RequestLogSampler;
+    use crate::config::ConfigLogRedaction;
+    use crate::config::ConfigRequestLogSampling;
+    use crate::logging::ConfigLoggingLevel;
+    use crate::router::HttpRouter;
+    use http::HeaderMap;
+    use http::Method;
+    use http::StatusCode;
+    use http::Uri;
+
+    fn rule(
+        path: &str,
+        sample_rate: u32,
+        max_per_second: Option<u32>,
+    ) -> ConfigRequestLogSampling {
+        ConfigRequestLogSampling {
+            path: path.to_string(),
+            sample_rate,
+            max_per_second,
+        }
+    }
+
+    #[test]
+    fn test_redact_uri() {
+        let redactor = LogRedactor::new(&ConfigLogRedaction {
+            query_params: vec!["token".to_string(), "api key".to_string()],
+            ..Default::default()
+        });
+        let uri = |s: &str| s.parse::<Uri>().unwrap();
+        assert_eq!(redactor.redact_uri(&uri("/projects")), "/projects");
+        assert_eq!(
+            redactor.redact_uri(&uri("/projects?limit=10")),
+            "/projects?limit=10"
+        );
+        assert_eq!(
+            redactor.redact_uri(&uri("/p?limit=10&token=abc&token=def")),
+            "/p?limit=10&token=<redacted>&token=<redacted>"
+        );
+        assert_eq!(
+            redactor.redact_uri(&uri("/p?api+key=abc&api%20key=def&tokens=1")),
+            "/p?api+key=<redacted>&api%20key=<redacted>&tokens=1"
+        );
+        assert_eq!(
+            redactor.redact_uri(&uri("http://localhost:8080/p?token")),
+            "http://localhost:8080/p?token=<redacted>"
+        );
+    }
+
+    #[test]
+    fn test_redact_headers() {
+        let redactor = LogRedactor::new(&ConfigLogRedaction {
+            headers: vec!["Authorization".to_string(), "X-Secret".to_string()],
+            ..Default::default()
+        });
+        let mut headers = HeaderMap::new();
+        headers.insert("authorization", "Bearer abc".parse().unwrap());
+        headers.append("x-secret", "1".parse().unwrap());
+        headers.append("x-secret", "2".parse().unwrap());
+        headers.append("accept", "text/plain".parse().unwrap());
+        headers.append("accept", "application/json".parse().unwrap());
+        let redacted = redactor.redact_headers(&headers);
+        assert_eq!(redacted["authorization"], "<redacted>");
+        assert_eq!(redacted["x-secret"], "<redacted>, <redacted>");
+        assert_eq!(redacted["accept"], "text/plain, application/json");
+    }
+
+    #[test]
+    fn test_redact_json() {
+        let redactor = LogRedactor::new(&ConfigLogRedaction {
+            body_fields: vec!["password".to_string()],
+            ..Default::default()
+        });
+        let mut body = serde_json::json!({
+            "name": "alice",
+            "password": "hunter2",
+            "keys": [
+                { "name": "key1", "password": { "nested": true } },
+                "password",
+            ],
+        });
+        redactor.redact_json(&mut body);
+        assert_eq!(
+            body,
+            serde_json::json!({
+                "name": "alice",
+                "password": "<redacted>",
+                "keys": [
+                    { "name": "key1", "password": "<redacted>" },
+                    "password",
+                ],
+            })
+        );
+    }
+
+    #[test]
+    fn test_endpoint_log_levels() {
+        let levels = EndpointLogLevels::new(&HttpRouter::<()>::new());
+        assert_eq!(levels.get(&Method::GET, "/projects/{id}"), None);
+
+        levels.set(
+            &Method::GET,
+            "/projects/{id}",
+            Some(ConfigLoggingLevel::Debug),
+        );
+        assert_eq!(
+            levels.get(&Method::GET, "/projects/{id}"),
+            Some(ConfigLoggingLevel::Debug)
+        );
+        assert_eq!(levels.get(&Method::PUT, "/projects/{id}"), None);
+        assert_eq!(levels.get(&Method::GET, "/projects"), None);
+
+        levels.set(&Method::GET, "/projects/{id}", None);
+        assert_eq!(levels.get(&Method::GET, "/projects/{id}"), None);
+    }
+
+    #[test]
+    fn test_sampling_unmatched() {
+        let sampler = RequestLogSampler::new(&[rule("/healthz", 0, None)]);
+        assert!(sampler.should_log(None, StatusCode::OK));
+        assert!(sampler.should_log(Some("/projects"), StatusCode::OK));
+        assert!(sampler.should_log(Some("/healthz/more"), StatusCode::OK));
+    }
+
+    #[test]
+    fn test_sampling_suppressed() {
+        let sampler = RequestLogSampler::new(&[rule("/healthz", 0, None)]);
+        assert!(!sampler.should_log(Some("/healthz"), StatusCode::OK));
+        assert!(!sampler.should_log(Some("/healthz"), StatusCode::NO_CONTENT));
+
+        // Failures are always logged.
+        assert!(sampler.should_log(Some("/healthz"), StatusCode::NOT_FOUND));
+        assert!(sampler
+            .should_log(Some("/healthz"), StatusCode::SERVICE_UNAVAILABLE));
+    }
+
+    #[test]
+    fn test_sampling_rate() {
+        let sampler = RequestLogSampler::new(&[rule("/metrics/*", 3, None)]);
+        let logged = (0..9)
+            .filter(|_| {
+                sampler.should_log(Some("/metrics/{kind}"), StatusCode::OK)
+            })
+            .count();
+        assert_eq!(logged, 3);
+    }
+
+    #[test]
+    fn test_sampling_first_match_wins() {
+        let sampler = RequestLogSampler::new(&[
+            rule("/metrics/raw", 1, None),
+            rule("/metrics/*", 0, None),
+        ]);
+        assert!(sampler.should_log(Some("/metrics/raw"), StatusCode::OK));
+        assert!(!sampler.should_log(Some("/metrics/{kind}"), StatusCode::OK));
+    }
+
+    #[test]
+    fn test_sampling_rate_limit() {
+        let sampler = RequestLogSampler::new(&[rule("/healthz", 1, Some(2))]);
+        let logged = (0..10)
+            .filter(|_| sampler.should_log(Some("/healthz"), StatusCode::OK))
+            .count();
+        // This could in principle straddle a window boundary, in which case
+        // we'd see up to twice the limit.
+        assert!((2..=4).contains(&logged), "logged {} entries", logged);
+    }
+}
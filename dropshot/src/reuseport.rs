@@ -0,0 +1,152 @@
+// Copyright 2023 Oxide Computer Company
+//! Accepting connections on several sockets bound to the same address (see
+//! `ConfigDropshot::reuseport`)
+//!
+//! When several sockets are bound to an address with `SO_REUSEPORT`, the
+//! kernel spreads the connections to that address among them.  Each socket
+//! gets its own accept loop on its own task, so that a single loop accepting
+//! every connection doesn't become a bottleneck when connections come and go
+//! quickly.
+
+use crate::config::ConfigReusePort;
+use crate::config::ConfigSocketOptions;
+#[cfg(all(unix, not(target_os = "solaris"), not(target_os = "illumos")))]
+use crate::socket_options::listen;
+#[cfg(all(unix, not(target_os = "solaris"), not(target_os = "illumos")))]
+use crate::socket_options::listener_socket;
+
+use futures::stream::Stream;
+use futures::stream::StreamExt;
+use std::io;
+use std::net::SocketAddr;
+use std::num::NonZeroUsize;
+use std::sync::atomic::AtomicU64;
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
+use tokio::net::TcpListener;
+
+/// Statistics for one of a server's acceptors (see
+/// `ConfigDropshot::reuseport`)
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct AcceptorStats {
+    /// connections that the acceptor has accepted (including any that were
+    /// shed)
+    pub accepted_connections: u64,
+}
+
+/// Counts of the connections accepted by each of a server's acceptors
+#[derive(Debug, Default)]
+pub(crate) struct Acceptors {
+    accepted: Vec<Arc<AtomicU64>>,
+}
+
+impl Acceptors {
+    pub fn new(config: Option<&ConfigReusePort>) -> Acceptors {
+        let count = config.map_or(0, acceptor_count);
+        Acceptors {
+            accepted: (0..count).map(|_| Arc::new(AtomicU64::new(0))).collect(),
+        }
+    }
+
+    /// Returns the number of acceptors (0 if `SO_REUSEPORT` isn't in use).
+    pub fn len(&self) -> usize {
+        self.accepted.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.accepted.is_empty()
+    }
+
+    /// Returns the counter of connections accepted by acceptor `index`.
+    pub fn counter(&self, index: usize) -> Arc<AtomicU64> {
+        Arc::clone(&self.accepted[index])
+    }
+
+    pub fn stats(&self) -> Vec<AcceptorStats> {
+        self.accepted
+            .iter()
+            .map(|accepted| AcceptorStats {
+                accepted_connections: accepted.load(Ordering::Relaxed),
+            })
+            .collect()
+    }
+}
+
+/// Returns the number of acceptors that `config` calls for: by default, one
+/// per CPU available to the process.
+fn acceptor_count(config: &ConfigReusePort) -> usize {
+    config.acceptors.unwrap_or_else(|| {
+        std::thread::available_parallelism().map_or(1, NonZeroUsize::get)
+    })
+}
+
+/// Passes along the connections from `connections`, counting those that were
+/// accepted in `accepted` (if given).
+pub(crate) fn count_accepted<S, T>(
+    connections: S,
+    accepted: Option<Arc<AtomicU64>>,
+) -> impl Stream<Item = io::Result<T>>
+where
+    S: Stream<Item = io::Result<T>>,
+{
+    connections.inspect(move |result| {
+        if let (Ok(_), Some(accepted)) = (result, &accepted) {
+            accepted.fetch_add(1, Ordering::Relaxed);
+        }
+    })
+}
+
+/// Binds a TCP listener to `address` with `SO_REUSEPORT` (and the given socket
+/// options), so that other listeners can be bound to the same address.
+#[cfg(all(unix, not(target_os = "solaris"), not(target_os = "illumos")))]
+pub(crate) fn bind_reuseport(
+    address: &SocketAddr,
+    options: &ConfigSocketOptions,
+) -> io::Result<TcpListener> {
+    let socket = listener_socket(address, options)?;
+    socket.set_reuse_port(true)?;
+    listen(socket, address)
+}
+
+#[cfg(not(all(unix, not(target_os = "solaris"), not(target_os = "illumos"))))]
+pub(crate) fn bind_reuseport(
+    _address: &SocketAddr,
+    _options: &ConfigSocketOptions,
+) -> io::Result<TcpListener> {
+    Err(io::Error::new(
+        io::ErrorKind::Other,
+        "SO_REUSEPORT is not supported on this platform",
+    ))
+}
+
+#[cfg(test)]
+mod test {
+    use super::count_accepted;
+    use super::Acceptors;
+    use crate::config::ConfigReusePort;
+    use futures::StreamExt;
+    use std::sync::atomic::Ordering;
+
+    #[tokio::test]
+    async fn test_acceptors() {
+        assert_eq!(Acceptors::new(None).len(), 0);
+        assert!(Acceptors::new(Some(&ConfigReusePort::default())).len() >= 1);
+
+        let acceptors =
+            Acceptors::new(Some(&ConfigReusePort { acceptors: Some(2) }));
+        assert_eq!(acceptors.len(), 2);
+        let connections = futures::stream::iter(vec![
+            Ok(1),
+            Err(std::io::Error::from(std::io::ErrorKind::Other)),
+            Ok(2),
+        ]);
+        let results = count_accepted(connections, Some(acceptors.counter(1)))
+            .collect::<Vec<_>>()
+            .await;
+        assert_eq!(results.len(), 3);
+        assert_eq!(acceptors.counter(1).load(Ordering::Relaxed), 2);
+        let stats = acceptors.stats();
+        assert_eq!(stats[0].accepted_connections, 0);
+        assert_eq!(stats[1].accepted_connections, 2);
+    }
+}
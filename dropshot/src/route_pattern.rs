@@ -0,0 +1,76 @@
+// Copyright 2023 Oxide Computer Company
+//! Patterns that select endpoints by their path templates
+
+/// A pattern matching the path templates of endpoints (e.g.,
+/// `"/projects/{id}"`), as used in configuration that applies to some routes
+/// but not others.  A pattern ending in `*` matches every path template that
+/// starts with the rest of it (so `"/admin/*"` matches `"/admin/users"`, and
+/// `"*"` matches everything).  Any other pattern matches only the path
+/// template that's identical to it.
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) enum RoutePattern {
+    Exact(String),
+    Prefix(String),
+}
+
+impl RoutePattern {
+    pub fn new(pattern: &str) -> RoutePattern {
+        match pattern.strip_suffix('*') {
+            Some(prefix) => RoutePattern::Prefix(prefix.to_string()),
+            None => RoutePattern::Exact(pattern.to_string()),
+        }
+    }
+
+    /// Returns whether `route`, the path template of an endpoint, matches this
+    /// pattern.
+    pub fn matches(&self, route: &str) -> bool {
+        match self {
+            RoutePattern::Exact(path) => route == path,
+            RoutePattern::Prefix(prefix) => route.starts_with(prefix.as_str()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::RoutePattern;
+
+    #[test]
+    fn test_exact() {
+        let pattern = RoutePattern::new("/projects/{id}");
+        assert!(pattern.matches("/projects/{id}"));
+        assert!(!pattern.matches("/projects"));
+        assert!(!pattern.matches("/projects/{id}/instances"));
+        assert!(!pattern.matches("/projects/{project_id}"));
+    }
+
+    #[test]
+    fn test_prefix() {
+        let pattern = RoutePattern::new("/admin/*");
+        assert!(pattern.matches("/admin/"));
+        assert!(pattern.matches("/admin/users"));
+        assert!(pattern.matches("/admin/users/{id}"));
+        assert!(!pattern.matches("/admin"));
+        assert!(!pattern.matches("/administrators"));
+
+        // Without a slash before the `*`, the prefix needn't end a segment.
+        let pattern = RoutePattern::new("/admin*");
+        assert!(pattern.matches("/admin"));
+        assert!(pattern.matches("/administrators"));
+    }
+
+    #[test]
+    fn test_everything() {
+        let pattern = RoutePattern::new("*");
+        assert!(pattern.matches("/"));
+        assert!(pattern.matches("/projects/{id}"));
+        assert!(pattern.matches(""));
+    }
+
+    #[test]
+    fn test_star_only_at_end() {
+        let pattern = RoutePattern::new("/a/*/b");
+        assert!(pattern.matches("/a/*/b"));
+        assert!(!pattern.matches("/a/x/b"));
+    }
+}
@@ -2,18 +2,29 @@
 //! Routes incoming HTTP requests to handler functions
 
 use super::error::HttpError;
+use super::handler::HttpHandlerResult;
+use super::handler::RequestContext;
 use super::handler::RouteHandler;
 
+use crate::fallback::FALLBACK_ROUTE;
 use crate::from_map::MapError;
 use crate::from_map::MapValue;
+use crate::idempotency::IdempotencyMode;
 use crate::server::ServerContext;
 use crate::ApiEndpoint;
 use crate::ApiEndpointBodyContentType;
+use crate::Body;
+use async_trait::async_trait;
 use http::Method;
 use http::StatusCode;
+use hyper::Response;
 use percent_encoding::percent_decode_str;
+use regex::Regex;
+use std::borrow::Cow;
 use std::collections::BTreeMap;
 use std::collections::BTreeSet;
+use std::sync::Arc;
+use std::time::Duration;
 
 /// `HttpRouter` is a simple data structure for routing incoming HTTP requests to
 /// specific handler functions based on the request method and URI path.  For
@@ -32,6 +43,12 @@ use std::collections::BTreeSet;
 /// `"bar456"`).  Only one segment is matched per variable, so `"/foo/{bar}"`
 /// will not match `"/foo/123/456"`.
 ///
+/// A variable may be constrained by a pattern given after a colon: either the
+/// name of a format (e.g., `"/foo/{bar:uuid}"`) or a regular expression that
+/// the whole segment must match (e.g., `"/foo/{bar:[0-9]+}"`).  Requests whose
+/// segment doesn't match are treated as though there were no such route.  The
+/// pattern `.*` is special: it matches all remaining segments of the path.
+///
 /// The implementation here is essentially a trie where edges represent segments
 /// of the URI path.  ("Segments" here are chunks of the path separated by one or
 /// more "/" characters.)  To register or look up the path `"/foo/bar/baz"`, we
@@ -49,13 +66,29 @@ use std::collections::BTreeSet;
 ///   `"/projects/default"`.
 ///
 /// * If a given resource has an edge with a variable name, all routes through
-///   this node must use the same name for that variable.  That is, you can't
-///   define routes for `"/projects/{id}"` and `"/projects/{project_id}/info"`.
+///   this node must use the same name (and pattern, if any) for that variable.
+///   That is, you can't define routes for `"/projects/{id}"` and
+///   `"/projects/{project_id}/info"`, nor for `"/projects/{id}"` and
+///   `"/projects/{id:uuid}/info"`.
 ///
 /// * A given path cannot use the same variable name twice.  For example, you
 ///   can't register path `"/projects/{id}/instances/{id}"`.
 ///
 /// * A given resource may have at most one handler for a given HTTP method.
+///   Resources without an `OPTIONS` handler answer `OPTIONS` requests with a
+///   204 ("No Content") response whose `Allow` header lists their methods.
+///   Those without a `HEAD` handler serve `HEAD` requests with their `GET`
+///   handler, if they have one.
+///   Requests with any other method for which there's no handler fail with a
+///   405 ("Method Not Allowed") error, whose message lists the same methods.
+///
+/// * By default, a trailing "/" in a request's path is ignored, so that
+///   `"/projects/"` matches the route for `"/projects"`.  See [`TrailingSlash`]
+///   for the alternatives.
+///
+/// * Literal path segments match case-sensitively unless the router is made
+///   case-insensitive, in which case `"/Projects"` also matches the route for
+///   `"/projects"`.  Either way, values of variables are matched as given.
 ///
 /// * The expectation is that during server initialization,
 ///   `HttpRouter::insert()` will be invoked to register a number of route
@@ -65,6 +98,73 @@ use std::collections::BTreeSet;
 pub struct HttpRouter<Context: ServerContext> {
     /// root of the trie
     root: Box<HttpRouterNode<Context>>,
+    /// how request paths with a trailing "/" are routed
+    trailing_slash: TrailingSlash,
+    /// whether literal path segments match regardless of case
+    case_insensitive: bool,
+    /// how the segments of request paths are percent-decoded
+    path_decoding: PathDecoding,
+    /// handles requests whose paths match no route, if there's such a handler
+    fallback: Option<Arc<dyn RouteHandler<Context>>>,
+}
+
+/// How a server routes requests whose paths end with a "/" (other than the
+/// root, `"/"`), as set with
+/// [`ApiDescription::trailing_slash()`](crate::ApiDescription::trailing_slash).
+/// Paths that end in a wildcard match (e.g., `"/files/{path:.*}"`) are exempt,
+/// since the wildcard matches the trailing "/" along with everything else.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub enum TrailingSlash {
+    /// The trailing "/" is ignored: `"/foo/"` is routed just like `"/foo"`.
+    #[default]
+    Merge,
+    /// Paths with a trailing "/" match no route, so that requests for them
+    /// fail with a 404 ("Not Found").
+    Strict,
+    /// Requests for a path with a trailing "/" that would otherwise match a
+    /// route are redirected with a 308 ("Permanent Redirect") to the path
+    /// without it (e.g., `"/foo/"` to `"/foo"`), keeping the query string.
+    Redirect,
+}
+
+/// How the segments of request paths are percent-decoded before they're
+/// matched against routes and assigned to path variables, as set with
+/// [`ApiDescription::path_decoding()`](crate::ApiDescription::path_decoding).
+/// Requests whose paths can't be decoded fail with a 400 ("Bad Request").
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct PathDecoding {
+    /// Whether a segment may contain an encoded "/" (`"%2F"`), which then
+    /// appears in the value of a single-segment variable (e.g., `"a%2Fb"`
+    /// matches `"/files/{name}"` with `name` set to `"a/b"`).  This is true by
+    /// default.  Wildcard variables (e.g., `"/files/{path:.*}"`) never accept
+    /// an encoded "/".
+    pub allow_encoded_slash: bool,
+    /// Whether a `+` decodes to a space, as it does in form-encoded data.  By
+    /// default, it's left as is.  (An encoded `+`, `"%2B"`, is always a `+`.)
+    pub plus_as_space: bool,
+    /// What's done with segments that don't decode to valid UTF-8
+    pub invalid_utf8: InvalidUtf8,
+}
+
+impl Default for PathDecoding {
+    fn default() -> Self {
+        PathDecoding {
+            allow_encoded_slash: true,
+            plus_as_space: false,
+            invalid_utf8: InvalidUtf8::default(),
+        }
+    }
+}
+
+/// What's done with path segments that don't decode to valid UTF-8 (see
+/// [`PathDecoding`])
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub enum InvalidUtf8 {
+    /// The request fails with a 400 ("Bad Request").
+    #[default]
+    Reject,
+    /// Invalid sequences are replaced with U+FFFD ("�").
+    Replace,
 }
 
 /// Each node in the tree represents a group of HTTP resources having the same
@@ -89,8 +189,13 @@ struct HttpRouterNode<Context: ServerContext> {
 enum HttpRouterEdges<Context: ServerContext> {
     /// Outgoing edges for literal paths.
     Literals(BTreeMap<String, Box<HttpRouterNode<Context>>>),
-    /// Outgoing edge for variable-named paths.
-    VariableSingle(String, Box<HttpRouterNode<Context>>),
+    /// Outgoing edge for variable-named paths, whose values may be constrained
+    /// by a pattern.
+    VariableSingle(
+        String,
+        Option<SegmentPattern>,
+        Box<HttpRouterNode<Context>>,
+    ),
     /// Outgoing edge that consumes all remaining components.
     VariableRest(String, Box<HttpRouterNode<Context>>),
 }
@@ -98,14 +203,16 @@ enum HttpRouterEdges<Context: ServerContext> {
 /// `PathSegment` represents a segment in a URI path when the router is being
 /// configured.  Each segment may be either a literal string or a variable (the
 /// latter indicated by being wrapped in braces). Variables may consume a single
-/// /-delimited segment or several as defined by a regex (currently only `.*` is
-/// supported).
+/// /-delimited segment, optionally constrained by a pattern, or all remaining
+/// segments (with the pattern `.*`).
 #[derive(Debug, PartialEq)]
 pub enum PathSegment {
     /// a path segment for a literal string
     Literal(String),
     /// a path segment for a variable
     VarnameSegment(String),
+    /// a path segment for a variable whose value must match a pattern
+    VarnamePattern(String, SegmentPattern),
     /// a path segment that matches all remaining components for a variable
     VarnameWildcard(String),
 }
@@ -145,14 +252,19 @@ impl PathSegment {
                 "HTTP URI path segment variable name must not be empty",
             );
 
-            if let Some(pat) = pat {
-                assert!(
-                    pat == ".*",
-                    "Only the pattern '.*' is currently supported"
-                );
-                PathSegment::VarnameWildcard(var.to_string())
-            } else {
-                PathSegment::VarnameSegment(var.to_string())
+            match pat {
+                Some(".*") => PathSegment::VarnameWildcard(var.to_string()),
+                Some(pat) => match SegmentPattern::new(pat) {
+                    Ok(pattern) => {
+                        PathSegment::VarnamePattern(var.to_string(), pattern)
+                    }
+                    Err(e) => panic!(
+                        "HTTP URI path segment variable \"{}\" has an \
+                         invalid pattern: {}",
+                        var, e
+                    ),
+                },
+                None => PathSegment::VarnameSegment(var.to_string()),
             }
         } else {
             PathSegment::Literal(segment.to_string())
@@ -160,6 +272,74 @@ impl PathSegment {
     }
 }
 
+/// Named formats that may be used as the pattern for a path variable, along
+/// with the regular expressions that values of each format match.
+const SEGMENT_FORMATS: &[(&str, &str)] = &[(
+    "uuid",
+    "[0-9a-fA-F]{8}-[0-9a-fA-F]{4}-[0-9a-fA-F]{4}-[0-9a-fA-F]{4}-\
+     [0-9a-fA-F]{12}",
+)];
+
+/// `SegmentPattern` constrains the value of a path variable that consumes a
+/// single segment.  It's either the name of a format (currently only `uuid`)
+/// or a regular expression, which must match the whole segment.
+#[derive(Clone, Debug)]
+pub struct SegmentPattern {
+    /// the pattern as written in the path
+    source: String,
+    /// the format named by the pattern, if any
+    format: Option<&'static str>,
+    /// the (anchored) regular expression that values must match
+    regex: Regex,
+}
+
+impl SegmentPattern {
+    fn new(source: &str) -> Result<SegmentPattern, regex::Error> {
+        let (format, regex) = match SEGMENT_FORMATS
+            .iter()
+            .find(|(format, _)| *format == source)
+        {
+            Some((format, regex)) => (Some(*format), *regex),
+            None => (None, source),
+        };
+        let regex = Regex::new(&format!("^(?:{})$", regex))?;
+        Ok(SegmentPattern { source: source.to_string(), format, regex })
+    }
+
+    /// Returns the name of the format that values must have, if the pattern
+    /// names one.
+    pub fn format(&self) -> Option<&str> {
+        self.format
+    }
+
+    /// Returns the regular expression that values must match, unless the
+    /// pattern names a format.
+    pub fn regex(&self) -> Option<&str> {
+        match self.format {
+            Some(_) => None,
+            None => Some(self.regex.as_str()),
+        }
+    }
+
+    /// Returns whether the (decoded) path segment `segment` satisfies the
+    /// pattern.
+    pub fn is_match(&self, segment: &str) -> bool {
+        self.regex.is_match(segment)
+    }
+}
+
+impl PartialEq for SegmentPattern {
+    fn eq(&self, other: &SegmentPattern) -> bool {
+        self.source == other.source
+    }
+}
+
+impl std::fmt::Display for SegmentPattern {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.source)
+    }
+}
+
 /// Wrapper for a path that's the result of user input i.e. an HTTP query.
 /// We use this type to avoid confusion with paths used to define routes.
 #[derive(Debug)]
@@ -176,7 +356,10 @@ impl<'a> From<&'a str> for InputPath<'a> {
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub enum VariableValue {
     String(String),
-    Components(Vec<String>),
+    /// the (percent-decoded) segments matched by a wildcard, along with the
+    /// remainder of the path as it appeared in the request (i.e., still
+    /// percent-encoded, with the segments joined by "/")
+    Components(Vec<String>, String),
 }
 
 pub type VariableSet = BTreeMap<String, VariableValue>;
@@ -185,18 +368,18 @@ impl MapValue for VariableValue {
     fn as_value(&self) -> Result<&str, MapError> {
         match self {
             VariableValue::String(s) => Ok(s.as_str()),
-            VariableValue::Components(_) => Err(MapError(
-                "cannot deserialize sequence as a single value".to_string(),
-            )),
+            VariableValue::Components(_, raw) => Ok(raw.as_str()),
         }
     }
 
     fn as_seq(&self) -> Result<Box<dyn Iterator<Item = String>>, MapError> {
         match self {
-            VariableValue::String(_) => Err(MapError(
+            VariableValue::String(_) => Err(MapError::new(
                 "cannot deserialize a single value as a sequence".to_string(),
             )),
-            VariableValue::Components(v) => Ok(Box::new(v.clone().into_iter())),
+            VariableValue::Components(v, _) => {
+                Ok(Box::new(v.clone().into_iter()))
+            }
         }
     }
 }
@@ -208,9 +391,123 @@ impl MapValue for VariableValue {
 /// content type.
 #[derive(Debug)]
 pub struct RouterLookupResult<'a, Context: ServerContext> {
-    pub handler: &'a dyn RouteHandler<Context>,
+    pub handler: Arc<dyn RouteHandler<Context>>,
+    /// path template with which the matched endpoint was registered
+    pub path: &'a str,
     pub variables: VariableSet,
+    /// whether the matched endpoint accepts idempotency keys
+    pub idempotency: Option<IdempotencyMode>,
+    /// headers that distinguish requests that may be coalesced, if the
+    /// matched endpoint coalesces requests
+    pub coalesce: Option<&'a [String]>,
     pub body_content_type: ApiEndpointBodyContentType,
+    pub additional_body_content_types: Vec<ApiEndpointBodyContentType>,
+    /// overrides the server's request body size limit, if the matched
+    /// endpoint has its own
+    pub request_body_max_bytes: Option<usize>,
+    /// whether the matched endpoint's responses may be compressed
+    pub compression: bool,
+    /// how long the matched endpoint's handler may run, if it's limited
+    pub timeout: Option<Duration>,
+    /// the matched endpoint, unless the router answers the request itself
+    /// (e.g., for an `OPTIONS` request without a handler)
+    pub endpoint: Option<&'a ApiEndpoint<Context>>,
+}
+
+/// An endpoint involved in a [`RouteConflict`]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct ConflictingEndpoint {
+    /// name of the endpoint's handler function (or its operation id)
+    pub operation_id: String,
+    pub method: Method,
+    /// path template with which the endpoint was (or was to be) registered
+    pub path: String,
+    /// where the endpoint was defined (`"file:line"`), if known
+    pub source_location: Option<String>,
+}
+
+impl ConflictingEndpoint {
+    fn new<Context: ServerContext>(endpoint: &ApiEndpoint<Context>) -> Self {
+        ConflictingEndpoint {
+            operation_id: endpoint.operation_id.clone(),
+            method: endpoint.method.clone(),
+            path: endpoint.path.clone(),
+            source_location: endpoint.source_location.clone(),
+        }
+    }
+}
+
+impl std::fmt::Display for ConflictingEndpoint {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} ({} {}", self.operation_id, self.method, self.path)?;
+        if let Some(source_location) = &self.source_location {
+            write!(f, ", defined at {}", source_location)?;
+        }
+        write!(f, ")")
+    }
+}
+
+/// Describes why an endpoint can't be registered: its route conflicts with
+/// that of an endpoint that already has been.  See
+/// [`ApiDescription::try_register()`](crate::ApiDescription::try_register).
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct RouteConflict {
+    /// the endpoint that couldn't be registered
+    pub endpoint: ConflictingEndpoint,
+    /// an endpoint already registered whose route conflicts with it
+    pub existing: ConflictingEndpoint,
+    /// the segment of `endpoint`'s path at which the routes conflict (e.g.,
+    /// `"{id}"`), or `None` if they have the same method and path
+    pub segment: Option<String>,
+    /// what the conflict is
+    pub message: String,
+}
+
+impl RouteConflict {
+    /// Describes a conflict between `endpoint` and the endpoints in the
+    /// subtree rooted at `existing` (one of which is named).
+    fn new<Context: ServerContext>(
+        endpoint: &ApiEndpoint<Context>,
+        existing: &HttpRouterNode<Context>,
+        segment: Option<String>,
+        message: String,
+    ) -> Self {
+        // Nodes are only created on the way to an endpoint, so every subtree
+        // has at least one.
+        let mut nodes = vec![existing];
+        let existing = loop {
+            let node = nodes.pop().expect("route subtree has no endpoints");
+            if let Some(existing) = node.methods.values().next() {
+                break existing;
+            }
+            match &node.edges {
+                None => {}
+                Some(HttpRouterEdges::Literals(children)) => {
+                    nodes.extend(children.values().map(|child| &**child))
+                }
+                Some(HttpRouterEdges::VariableSingle(_, _, child))
+                | Some(HttpRouterEdges::VariableRest(_, child)) => {
+                    nodes.push(&**child)
+                }
+            }
+        };
+        RouteConflict {
+            endpoint: ConflictingEndpoint::new(endpoint),
+            existing: ConflictingEndpoint::new(existing),
+            segment,
+            message,
+        }
+    }
+}
+
+impl std::fmt::Display for RouteConflict {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} (registering {}, which conflicts with {})",
+            self.message, self.endpoint, self.existing
+        )
+    }
 }
 
 impl<Context: ServerContext> HttpRouterNode<Context> {
@@ -222,13 +519,59 @@ impl<Context: ServerContext> HttpRouterNode<Context> {
 impl<Context: ServerContext> HttpRouter<Context> {
     /// Returns a new `HttpRouter` with no routes configured.
     pub fn new() -> Self {
-        HttpRouter { root: Box::new(HttpRouterNode::new()) }
+        HttpRouter {
+            root: Box::new(HttpRouterNode::new()),
+            trailing_slash: TrailingSlash::default(),
+            case_insensitive: false,
+            path_decoding: PathDecoding::default(),
+            fallback: None,
+        }
+    }
+
+    /// Sets how request paths with a trailing "/" are routed.
+    pub(crate) fn set_trailing_slash(&mut self, trailing_slash: TrailingSlash) {
+        self.trailing_slash = trailing_slash;
+    }
+
+    /// Sets whether literal path segments match regardless of case.
+    pub(crate) fn set_case_insensitive(&mut self, case_insensitive: bool) {
+        self.case_insensitive = case_insensitive;
+    }
+
+    /// Sets how the segments of request paths are percent-decoded.
+    pub(crate) fn set_path_decoding(&mut self, path_decoding: PathDecoding) {
+        self.path_decoding = path_decoding;
+    }
+
+    /// Sets the handler for requests whose paths match no route.
+    pub(crate) fn set_fallback(
+        &mut self,
+        fallback: Arc<dyn RouteHandler<Context>>,
+    ) {
+        self.fallback = Some(fallback);
     }
 
     /// Configure a route for HTTP requests based on the HTTP `method` and
     /// URI `path`.  See the `HttpRouter` docs for information about how `path`
     /// is processed.  Requests matching `path` will be resolved to `handler`.
+    ///
+    /// Panics if the route conflicts with one that's already configured.  See
+    /// [`HttpRouter::try_insert()`].
     pub fn insert(&mut self, endpoint: ApiEndpoint<Context>) {
+        if let Err(conflict) = self.try_insert(endpoint) {
+            panic!("{}", conflict);
+        }
+    }
+
+    /// Configure a route like [`HttpRouter::insert()`], but fail with a
+    /// description of the conflict if the route conflicts with one that's
+    /// already configured.  (Paths that are invalid on their own, such as
+    /// those that use a variable name twice, still cause a panic.)  The router
+    /// is unchanged if this fails.
+    pub fn try_insert(
+        &mut self,
+        endpoint: ApiEndpoint<Context>,
+    ) -> Result<(), RouteConflict> {
         let method = endpoint.method.clone();
         let path = endpoint.path.clone();
 
@@ -239,6 +582,14 @@ impl<Context: ServerContext> HttpRouter<Context> {
 
         let mut node: &mut Box<HttpRouterNode<Context>> = &mut self.root;
         while let Some(raw_segment) = all_segments.next() {
+            let conflict = |existing: &HttpRouterNode<Context>, message| {
+                RouteConflict::new(
+                    &endpoint,
+                    existing,
+                    Some(raw_segment.to_string()),
+                    message,
+                )
+            };
             let segment = PathSegment::from(raw_segment);
 
             node = match segment {
@@ -251,15 +602,22 @@ impl<Context: ServerContext> HttpRouter<Context> {
                         // the same node.  This could be supported (with some
                         // caveats about how matching would work), but it seems
                         // more likely to be a mistake.
-                        HttpRouterEdges::VariableSingle(varname, _)
-                        | HttpRouterEdges::VariableRest(varname, _) => {
-                            panic!(
-                                "URI path \"{}\": attempted to register route \
-                                 for literal path segment \"{}\" when a route \
-                                 exists for variable path segment (variable \
-                                 name: \"{}\")",
-                                path, lit, varname
-                            );
+                        HttpRouterEdges::VariableSingle(
+                            varname,
+                            _,
+                            existing,
+                        )
+                        | HttpRouterEdges::VariableRest(varname, existing) => {
+                            return Err(conflict(
+                                existing,
+                                format!(
+                                    "URI path \"{}\": attempted to register \
+                                     route for literal path segment \"{}\" \
+                                     when a route exists for variable path \
+                                     segment (variable name: \"{}\")",
+                                    path, lit, varname
+                                ),
+                            ));
                         }
                         HttpRouterEdges::Literals(ref mut literals) => literals
                             .entry(lit)
@@ -267,36 +625,58 @@ impl<Context: ServerContext> HttpRouter<Context> {
                     }
                 }
 
-                PathSegment::VarnameSegment(new_varname) => {
+                PathSegment::VarnameSegment(_)
+                | PathSegment::VarnamePattern(_, _) => {
+                    let (new_varname, new_pattern) = match segment {
+                        PathSegment::VarnamePattern(varname, pattern) => {
+                            (varname, Some(pattern))
+                        }
+                        PathSegment::VarnameSegment(varname) => (varname, None),
+                        _ => unreachable!(),
+                    };
                     insert_var(&path, &mut varnames, &new_varname);
 
                     let edges = node.edges.get_or_insert(
                         HttpRouterEdges::VariableSingle(
                             new_varname.clone(),
+                            new_pattern.clone(),
                             Box::new(HttpRouterNode::new()),
                         ),
                     );
                     match edges {
                         // See the analogous check above about combining literal
                         // and variable path segments from the same resource.
-                        HttpRouterEdges::Literals(_) => panic!(
-                            "URI path \"{}\": attempted to register route for \
-                             variable path segment (variable name: \"{}\") \
-                             when a route already exists for a literal path \
-                             segment",
-                            path, new_varname
-                        ),
+                        HttpRouterEdges::Literals(literals) => {
+                            return Err(conflict(
+                                literals.values().next().unwrap(),
+                                format!(
+                                    "URI path \"{}\": attempted to register \
+                                     route for variable path segment \
+                                     (variable name: \"{}\") when a route \
+                                     already exists for a literal path \
+                                     segment",
+                                    path, new_varname
+                                ),
+                            ))
+                        }
 
-                        HttpRouterEdges::VariableRest(varname, _) => panic!(
-                            "URI path \"{}\": attempted to register route for \
-                             variable path segment (variable name: \"{}\") \
-                             when a route already exists for the remainder of \
-                             the path as {}",
-                            path, new_varname, varname,
-                        ),
+                        HttpRouterEdges::VariableRest(varname, existing) => {
+                            return Err(conflict(
+                                existing,
+                                format!(
+                                    "URI path \"{}\": attempted to register \
+                                     route for variable path segment \
+                                     (variable name: \"{}\") when a route \
+                                     already exists for the remainder of the \
+                                     path as {}",
+                                    path, new_varname, varname,
+                                ),
+                            ))
+                        }
 
                         HttpRouterEdges::VariableSingle(
                             varname,
+                            pattern,
                             ref mut node,
                         ) => {
                             if *new_varname != *varname {
@@ -304,13 +684,34 @@ impl<Context: ServerContext> HttpRouter<Context> {
                                 // the same part of the path.  Again, this could
                                 // be supported, but it seems likely to be
                                 // confusing and probably a mistake.
-                                panic!(
-                                    "URI path \"{}\": attempted to use \
-                                     variable name \"{}\", but a different \
-                                     name (\"{}\") has already been used for \
-                                     this",
-                                    path, new_varname, varname
-                                );
+                                return Err(conflict(
+                                    node,
+                                    format!(
+                                        "URI path \"{}\": attempted to use \
+                                         variable name \"{}\", but a \
+                                         different name (\"{}\") has already \
+                                         been used for this",
+                                        path, new_varname, varname
+                                    ),
+                                ));
+                            }
+                            if new_pattern != *pattern {
+                                // Likewise, the values that reach a given
+                                // resource shouldn't depend on which of its
+                                // routes one has in mind.
+                                return Err(conflict(
+                                    node,
+                                    format!(
+                                        "URI path \"{}\": attempted to use \
+                                         pattern {} for variable \"{}\", but \
+                                         a different pattern ({}) has already \
+                                         been used for this",
+                                        path,
+                                        describe_pattern(new_pattern.as_ref()),
+                                        varname,
+                                        describe_pattern(pattern.as_ref()),
+                                    ),
+                                ));
                             }
 
                             node
@@ -342,19 +743,35 @@ impl<Context: ServerContext> HttpRouter<Context> {
                          * See the analogous check above about combining literal
                          * and variable path segments from the same resource.
                          */
-                        HttpRouterEdges::Literals(_) => panic!(
-                            "URI path \"{}\": attempted to register route for \
-                             variable path regex (variable name: \"{}\") when \
-                             a route already exists for a literal path segment",
-                            path, new_varname
-                        ),
+                        HttpRouterEdges::Literals(literals) => {
+                            return Err(conflict(
+                                literals.values().next().unwrap(),
+                                format!(
+                                    "URI path \"{}\": attempted to register \
+                                     route for variable path regex (variable \
+                                     name: \"{}\") when a route already exists \
+                                     for a literal path segment",
+                                    path, new_varname
+                                ),
+                            ))
+                        }
 
-                        HttpRouterEdges::VariableSingle(varname, _) => panic!(
-                            "URI path \"{}\": attempted to register route for \
-                             variable path regex (variable name: \"{}\") when \
-                             a route already exists for a segment {}",
-                            path, new_varname, varname,
-                        ),
+                        HttpRouterEdges::VariableSingle(
+                            varname,
+                            _,
+                            existing,
+                        ) => {
+                            return Err(conflict(
+                                existing,
+                                format!(
+                                    "URI path \"{}\": attempted to register \
+                                     route for variable path regex (variable \
+                                     name: \"{}\") when a route already exists \
+                                     for a segment {}",
+                                    path, new_varname, varname,
+                                ),
+                            ))
+                        }
 
                         HttpRouterEdges::VariableRest(
                             varname,
@@ -367,13 +784,16 @@ impl<Context: ServerContext> HttpRouter<Context> {
                                  * be supported, but it seems likely to be
                                  * confusing and probably a mistake.
                                  */
-                                panic!(
-                                    "URI path \"{}\": attempted to use \
-                                     variable name \"{}\", but a different \
-                                     name (\"{}\") has already been used for \
-                                     this",
-                                    path, new_varname, varname
-                                );
+                                return Err(conflict(
+                                    node,
+                                    format!(
+                                        "URI path \"{}\": attempted to use \
+                                         variable name \"{}\", but a \
+                                         different name (\"{}\") has already \
+                                         been used for this",
+                                        path, new_varname, varname
+                                    ),
+                                ));
                             }
 
                             node
@@ -385,14 +805,41 @@ impl<Context: ServerContext> HttpRouter<Context> {
 
         let methodname = method.as_str().to_uppercase();
         if node.methods.get(&methodname).is_some() {
-            panic!(
-                "URI path \"{}\": attempted to create duplicate route for \
-                 method \"{}\"",
-                path, method,
-            );
+            return Err(RouteConflict::new(
+                &endpoint,
+                node,
+                None,
+                format!(
+                    "URI path \"{}\": attempted to create duplicate route for \
+                     method \"{}\"",
+                    path, method,
+                ),
+            ));
         }
 
         node.methods.insert(methodname, endpoint);
+        Ok(())
+    }
+
+    /// Removes and returns all of the endpoints registered with the router.
+    pub(crate) fn into_endpoints(self) -> Vec<ApiEndpoint<Context>> {
+        let mut endpoints = Vec::new();
+        let mut nodes = vec![self.root];
+        while let Some(node) = nodes.pop() {
+            let node = *node;
+            endpoints.extend(node.methods.into_values());
+            match node.edges {
+                None => {}
+                Some(HttpRouterEdges::Literals(children)) => {
+                    nodes.extend(children.into_values())
+                }
+                Some(HttpRouterEdges::VariableSingle(_, _, child))
+                | Some(HttpRouterEdges::VariableRest(_, child)) => {
+                    nodes.push(child)
+                }
+            }
+        }
+        endpoints
     }
 
     /// Look up the route handler for an HTTP request having method `method` and
@@ -406,12 +853,137 @@ impl<Context: ServerContext> HttpRouter<Context> {
         method: &'b Method,
         path: InputPath<'b>,
     ) -> Result<RouterLookupResult<'a, Context>, HttpError> {
-        let all_segments = input_path_to_segments(&path).map_err(|_| {
-            HttpError::for_bad_request(
-                None,
-                String::from("invalid path encoding"),
-            )
-        })?;
+        let (node, variables) = match (self.lookup_node(&path), &self.fallback)
+        {
+            (Ok(found), _) => found,
+            (Err(error), Some(fallback))
+                if error.status_code == StatusCode::NOT_FOUND =>
+            {
+                return Ok(RouterLookupResult {
+                    handler: Arc::clone(fallback),
+                    path: FALLBACK_ROUTE,
+                    variables: VariableSet::new(),
+                    idempotency: None,
+                    coalesce: None,
+                    body_content_type: ApiEndpointBodyContentType::default(),
+                    additional_body_content_types: vec![],
+                    request_body_max_bytes: None,
+                    compression: true,
+                    timeout: None,
+                    endpoint: None,
+                });
+            }
+            (Err(error), _) => return Err(error),
+        };
+
+        if self.trailing_slash == TrailingSlash::Redirect
+            && has_trailing_slash(&path, &variables)
+        {
+            let endpoint = node.methods.values().next().unwrap();
+            return Ok(RouterLookupResult {
+                handler: Arc::new(TrailingSlashRedirect),
+                path: &endpoint.path,
+                variables,
+                idempotency: None,
+                coalesce: None,
+                body_content_type: ApiEndpointBodyContentType::default(),
+                additional_body_content_types: vec![],
+                request_body_max_bytes: None,
+                compression: false,
+                timeout: None,
+                endpoint: None,
+            });
+        }
+
+        let methodname = method.as_str().to_uppercase();
+        // Resources without a HEAD handler serve HEAD requests with their GET
+        // handler.  (The server discards the body of its response.)
+        let handler = node.methods.get(&methodname).or_else(|| {
+            if *method == Method::HEAD {
+                node.methods.get(Method::GET.as_str())
+            } else {
+                None
+            }
+        });
+        if let Some(handler) = handler {
+            return Ok(RouterLookupResult {
+                handler: Arc::clone(&handler.handler),
+                path: &handler.path,
+                variables,
+                idempotency: handler.idempotency,
+                coalesce: handler.coalesce.as_deref(),
+                body_content_type: handler.body_content_type.clone(),
+                additional_body_content_types: handler
+                    .additional_body_content_types
+                    .clone(),
+                request_body_max_bytes: handler.request_body_max_bytes,
+                compression: handler.compression,
+                timeout: handler.timeout,
+                endpoint: Some(handler),
+            });
+        }
+
+        // Resources answer OPTIONS requests on their own if there's no handler
+        // for them.  All of a node's endpoints share the same path template.
+        if *method == Method::OPTIONS {
+            let endpoint = node.methods.values().next().unwrap();
+            return Ok(RouterLookupResult {
+                handler: Arc::new(OptionsHandler {
+                    allow: allowed_methods(node),
+                }),
+                path: &endpoint.path,
+                variables,
+                idempotency: None,
+                coalesce: None,
+                body_content_type: ApiEndpointBodyContentType::default(),
+                additional_body_content_types: vec![],
+                request_body_max_bytes: None,
+                compression: false,
+                timeout: None,
+                endpoint: None,
+            });
+        }
+
+        Err(HttpError::for_client_error(
+            None,
+            StatusCode::METHOD_NOT_ALLOWED,
+            format!(
+                "Method Not Allowed (allowed methods: {})",
+                allowed_methods(node)
+            ),
+        ))
+    }
+
+    /// Returns an iterator over the endpoints in the router, ordered by path
+    /// and then by method.
+    pub fn endpoints(
+        &self,
+    ) -> impl Iterator<Item = &ApiEndpoint<Context>> + '_ {
+        self.into_iter().map(|(_, _, endpoint)| endpoint)
+    }
+
+    /// Returns the methods (as the value of an `Allow` header) for which
+    /// there are handlers for URI path `path`, if it matches any route.
+    pub fn lookup_allowed_methods(
+        &self,
+        path: InputPath<'_>,
+    ) -> Option<String> {
+        self.lookup_node(&path).ok().map(|(node, _)| allowed_methods(node))
+    }
+
+    /// Finds the node for URI path `path`, along with the values of the
+    /// variables in the path, if there are handlers for it.
+    fn lookup_node<'a>(
+        &'a self,
+        path: &InputPath<'_>,
+    ) -> Result<(&'a HttpRouterNode<Context>, VariableSet), HttpError> {
+        let all_segments = input_path_to_segments(path, &self.path_decoding)
+            .map_err(|_| {
+                HttpError::for_bad_request(
+                    None,
+                    String::from("invalid path encoding"),
+                )
+            })?;
         let mut all_segments = all_segments.into_iter();
         let mut node = &self.root;
         let mut variables = VariableSet::new();
@@ -423,23 +995,66 @@ impl<Context: ServerContext> HttpRouter<Context> {
                 None => None,
 
                 Some(HttpRouterEdges::Literals(edges)) => {
-                    edges.get(&segment_string)
-                }
-                Some(HttpRouterEdges::VariableSingle(varname, ref node)) => {
-                    variables.insert(
-                        varname.clone(),
-                        VariableValue::String(segment_string),
-                    );
-                    Some(node)
+                    edges.get(&segment_string).or_else(|| {
+                        // An exact match wins over any that differ in case.
+                        // Beyond that, the first in sorted order wins.
+                        if !self.case_insensitive {
+                            return None;
+                        }
+                        let segment = segment_string.to_lowercase();
+                        edges
+                            .iter()
+                            .find(|(literal, _)| {
+                                literal.to_lowercase() == segment
+                            })
+                            .map(|(_, node)| node)
+                    })
                 }
+                Some(HttpRouterEdges::VariableSingle(
+                    varname,
+                    pattern,
+                    ref node,
+                )) => match pattern {
+                    // A segment that doesn't match the variable's pattern
+                    // doesn't match any route through this node.
+                    Some(pattern) if !pattern.is_match(&segment) => None,
+                    _ => {
+                        variables.insert(
+                            varname.clone(),
+                            VariableValue::String(segment_string),
+                        );
+                        Some(node)
+                    }
+                },
                 Some(HttpRouterEdges::VariableRest(varname, node)) => {
                     let mut rest = vec![segment];
                     while let Some(segment) = all_segments.next() {
                         rest.push(segment);
                     }
+                    if let Some(segment) =
+                        rest.iter().find(|s| !is_safe_rest_segment(s))
+                    {
+                        return Err(HttpError::for_bad_request(
+                            None,
+                            format!(
+                                "path segment {:?} is not permitted here",
+                                segment
+                            ),
+                        ));
+                    }
+                    // The segments of the raw path correspond one-to-one with
+                    // the decoded ones, so the last of them are the ones that
+                    // the wildcard matched.
+                    let raw_segments = path
+                        .0
+                        .split('/')
+                        .filter(|segment| !segment.is_empty())
+                        .collect::<Vec<_>>();
+                    let raw = raw_segments[raw_segments.len() - rest.len()..]
+                        .join("/");
                     variables.insert(
                         varname.clone(),
-                        VariableValue::Components(rest),
+                        VariableValue::Components(rest, raw),
                     );
                     // There should be no outgoing edges since this is by
                     // definition a terminal node
@@ -458,8 +1073,10 @@ impl<Context: ServerContext> HttpRouter<Context> {
         // The wildcard match consumes the implicit, empty path segment
         match &node.edges {
             Some(HttpRouterEdges::VariableRest(varname, new_node)) => {
-                variables
-                    .insert(varname.clone(), VariableValue::Components(vec![]));
+                variables.insert(
+                    varname.clone(),
+                    VariableValue::Components(vec![], String::new()),
+                );
                 // There should be no outgoing edges
                 assert!(new_node.edges.is_none());
                 node = new_node;
@@ -476,17 +1093,127 @@ impl<Context: ServerContext> HttpRouter<Context> {
             ));
         }
 
-        let methodname = method.as_str().to_uppercase();
-        node.methods
-            .get(&methodname)
-            .map(|handler| RouterLookupResult {
-                handler: &*handler.handler,
-                variables,
-                body_content_type: handler.body_content_type.clone(),
-            })
-            .ok_or_else(|| {
-                HttpError::for_status(None, StatusCode::METHOD_NOT_ALLOWED)
-            })
+        if self.trailing_slash == TrailingSlash::Strict
+            && has_trailing_slash(path, &variables)
+        {
+            return Err(HttpError::for_not_found(
+                None,
+                String::from("no route found (trailing slash)"),
+            ));
+        }
+
+        Ok((&**node, variables))
+    }
+}
+
+/// Returns the value of the `Allow` header for the resource `node`: the
+/// methods for which it has handlers, along with `OPTIONS` (and `HEAD`, if it
+/// has a `GET` handler).
+fn allowed_methods<Context: ServerContext>(
+    node: &HttpRouterNode<Context>,
+) -> String {
+    let head = node
+        .methods
+        .contains_key(Method::GET.as_str())
+        .then(|| Method::HEAD.as_str());
+    node.methods
+        .keys()
+        .map(|method| method.as_str())
+        .chain(std::iter::once(Method::OPTIONS.as_str()))
+        .chain(head)
+        .collect::<BTreeSet<_>>()
+        .into_iter()
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+/// Answers `OPTIONS` requests for a resource that has no handler for them
+/// with a 204 ("No Content") response listing the resource's methods.
+#[derive(Debug)]
+struct OptionsHandler {
+    /// value of the `Allow` header
+    allow: String,
+}
+
+#[async_trait]
+impl<Context: ServerContext> RouteHandler<Context> for OptionsHandler {
+    fn label(&self) -> &str {
+        "(OPTIONS)"
+    }
+
+    async fn handle_request(
+        &self,
+        _rqctx: RequestContext<Context>,
+        _request: hyper::Request<Body>,
+    ) -> HttpHandlerResult {
+        Ok(Response::builder()
+            .status(StatusCode::NO_CONTENT)
+            .header(http::header::ALLOW, &self.allow)
+            .body(Body::empty())?)
+    }
+}
+
+/// Redirects requests for a path with a trailing "/" to the same path without
+/// it (see [`TrailingSlash::Redirect`])
+#[derive(Debug)]
+struct TrailingSlashRedirect;
+
+#[async_trait]
+impl<Context: ServerContext> RouteHandler<Context> for TrailingSlashRedirect {
+    fn label(&self) -> &str {
+        "(trailing slash redirect)"
+    }
+
+    async fn handle_request(
+        &self,
+        _rqctx: RequestContext<Context>,
+        request: hyper::Request<Body>,
+    ) -> HttpHandlerResult {
+        // Empty segments are dropped, as they are for routing, so that the
+        // location can't begin with "//" (which a client would take to name
+        // another host).
+        let uri = request.uri();
+        let segments = uri
+            .path()
+            .split('/')
+            .filter(|segment| !segment.is_empty())
+            .collect::<Vec<_>>();
+        let mut location = format!("/{}", segments.join("/"));
+        if let Some(query) = uri.query() {
+            location.push('?');
+            location.push_str(query);
+        }
+        Ok(Response::builder()
+            .status(StatusCode::PERMANENT_REDIRECT)
+            .header(http::header::LOCATION, location)
+            .body(Body::empty())?)
+    }
+}
+
+/// Returns whether `path` ends with a "/" that [`TrailingSlash`] applies to,
+/// given the `variables` with which it matched a route
+fn has_trailing_slash(path: &InputPath<'_>, variables: &VariableSet) -> bool {
+    path.0.len() > 1
+        && path.0.ends_with('/')
+        && !variables
+            .values()
+            .any(|value| matches!(value, VariableValue::Components(..)))
+}
+
+/// Returns whether the (decoded) path segment `segment` may be matched by a
+/// wildcard.  Wildcards are typically used to refer to files and the like, so
+/// we reject segments that would be interpreted differently once the segments
+/// are joined back together: those that decoded to a dot-segment, or that
+/// contain a "/" (from "%2F") or a NUL.
+fn is_safe_rest_segment(segment: &str) -> bool {
+    segment != "." && segment != ".." && !segment.contains(['/', '\0'])
+}
+
+/// Describes a variable's pattern (or lack of one) for error messages.
+fn describe_pattern(pattern: Option<&SegmentPattern>) -> String {
+    match pattern {
+        Some(pattern) => format!("\"{}\"", pattern),
+        None => String::from("none"),
     }
 }
 
@@ -555,7 +1282,7 @@ impl<'a, Context: ServerContext> HttpRouterIter<'a, Context> {
                 map.iter()
                     .map(|(s, node)| (PathSegment::Literal(s.clone()), node)),
             ),
-            Some(HttpRouterEdges::VariableSingle(varname, node)) => {
+            Some(HttpRouterEdges::VariableSingle(varname, _, node)) => {
                 Box::new(std::iter::once((
                     PathSegment::VarnameSegment(varname.clone()),
                     node,
@@ -579,6 +1306,7 @@ impl<'a, Context: ServerContext> HttpRouterIter<'a, Context> {
             .map(|(c, _)| match c {
                 PathSegment::Literal(s) => s.clone(),
                 PathSegment::VarnameSegment(s) => format!("{{{}}}", s),
+                PathSegment::VarnamePattern(s, p) => format!("{{{}:{}}}", s, p),
                 PathSegment::VarnameWildcard(s) => format!("{{{}:.*}}", s),
             })
             .collect();
@@ -650,7 +1378,10 @@ impl<'a, Context: ServerContext> Iterator for HttpRouterIter<'a, Context> {
 /// that consumers may be susceptible to other information leaks, for example
 /// if a client were able to follow a symlink to the root of the filesystem. As
 /// always, it is incumbent on the consumer and *critical* to validate input.
-fn input_path_to_segments(path: &InputPath) -> Result<Vec<String>, String> {
+fn input_path_to_segments(
+    path: &InputPath,
+    decoding: &PathDecoding,
+) -> Result<Vec<String>, String> {
     // We're given the "path" portion of a URI and we want to construct an
     // array of the segments of the path.   Relevant references:
     //
@@ -684,14 +1415,34 @@ fn input_path_to_segments(path: &InputPath) -> Result<Vec<String>, String> {
         .filter(|segment| !segment.is_empty())
         .map(|segment| match segment {
             "." | ".." => Err("dot-segments are not permitted".to_string()),
-            _ => Ok(percent_decode_str(segment)
-                .decode_utf8()
-                .map_err(|e| e.to_string())?
-                .to_string()),
+            _ => decode_segment(segment, decoding),
         })
         .collect()
 }
 
+/// Percent-decodes one segment of a request path as described by `decoding`.
+fn decode_segment(
+    segment: &str,
+    decoding: &PathDecoding,
+) -> Result<String, String> {
+    let segment = if decoding.plus_as_space {
+        Cow::Owned(segment.replace('+', " "))
+    } else {
+        Cow::Borrowed(segment)
+    };
+    let decoded = percent_decode_str(&segment);
+    let decoded = match decoding.invalid_utf8 {
+        InvalidUtf8::Reject => {
+            decoded.decode_utf8().map_err(|e| e.to_string())?.into_owned()
+        }
+        InvalidUtf8::Replace => decoded.decode_utf8_lossy().into_owned(),
+    };
+    if !decoding.allow_encoded_slash && decoded.contains('/') {
+        return Err(String::from("encoded \"/\" is not permitted"));
+    }
+    Ok(decoded)
+}
+
 /// Whereas in `input_path_to_segments()` we must accommodate any user input, when
 /// processing paths specified by the client program we can be more stringent and
 /// fail via a panic! rather than an error. We do not percent-decode the path
@@ -726,18 +1477,21 @@ mod test {
     use super::super::handler::RouteHandler;
     use super::input_path_to_segments;
     use super::HttpRouter;
+    use super::InvalidUtf8;
+    use super::PathDecoding;
     use super::PathSegment;
     use crate::api_description::ApiEndpointBodyContentType;
     use crate::from_map::from_map;
     use crate::router::VariableValue;
     use crate::ApiEndpoint;
     use crate::ApiEndpointResponse;
+    use crate::Body;
     use http::Method;
     use http::StatusCode;
-    use hyper::Body;
     use hyper::Response;
     use serde::Deserialize;
     use std::collections::BTreeMap;
+    use std::sync::Arc;
 
     async fn test_handler(
         _: RequestContext<()>,
@@ -745,16 +1499,16 @@ mod test {
         panic!("test handler is not supposed to run");
     }
 
-    fn new_handler() -> Box<dyn RouteHandler<()>> {
+    fn new_handler() -> Arc<dyn RouteHandler<()>> {
         HttpRouteHandler::new(test_handler)
     }
 
-    fn new_handler_named(name: &str) -> Box<dyn RouteHandler<()>> {
+    fn new_handler_named(name: &str) -> Arc<dyn RouteHandler<()>> {
         HttpRouteHandler::new_with_name(test_handler, name)
     }
 
     fn new_endpoint(
-        handler: Box<dyn RouteHandler<()>>,
+        handler: Arc<dyn RouteHandler<()>>,
         method: Method,
         path: &str,
     ) -> ApiEndpoint<()> {
@@ -765,6 +1519,7 @@ mod test {
             path: path.to_string(),
             parameters: vec![],
             body_content_type: ApiEndpointBodyContentType::default(),
+            additional_body_content_types: vec![],
             response: ApiEndpointResponse::default(),
             summary: None,
             description: None,
@@ -772,6 +1527,15 @@ mod test {
             extension_mode: Default::default(),
             visible: true,
             deprecated: false,
+            log_level: None,
+            idempotency: None,
+            coalesce: None,
+            bandwidth_limit: None,
+            request_body_max_bytes: None,
+            compression: true,
+            timeout: None,
+            source_location: None,
+            schema_validators: Default::default(),
         }
     }
 
@@ -925,13 +1689,32 @@ mod test {
     }
 
     #[test]
-    #[should_panic(expected = "Only the pattern '.*' is currently supported")]
+    #[should_panic(expected = "variable \"rest\" has an invalid pattern")]
     fn test_bogus_regex() {
         let mut router = HttpRouter::new();
         router.insert(new_endpoint(
             new_handler(),
             Method::GET,
-            "/word/{rest:[a-z]*}",
+            "/word/{rest:[a-z}",
+        ));
+    }
+
+    #[test]
+    #[should_panic(expected = "URI path \"/projects/{id:uuid}/info\": \
+                               attempted to use pattern \"uuid\" for \
+                               variable \"id\", but a different pattern \
+                               (none) has already been used for this")]
+    fn test_inconsistent_pattern() {
+        let mut router = HttpRouter::new();
+        router.insert(new_endpoint(
+            new_handler(),
+            Method::GET,
+            "/projects/{id}",
+        ));
+        router.insert(new_endpoint(
+            new_handler(),
+            Method::GET,
+            "/projects/{id:uuid}/info",
         ));
     }
 
@@ -1017,6 +1800,241 @@ mod test {
         assert_eq!(error.status_code, StatusCode::METHOD_NOT_ALLOWED);
     }
 
+    #[test]
+    fn test_options() {
+        let mut router = HttpRouter::new();
+        router.insert(new_endpoint(
+            new_handler_named("h1"),
+            Method::GET,
+            "/foo/{bar}",
+        ));
+        router.insert(new_endpoint(
+            new_handler_named("h2"),
+            Method::DELETE,
+            "/foo/{bar}",
+        ));
+        router.insert(new_endpoint(
+            new_handler_named("h3"),
+            Method::GET,
+            "/baz",
+        ));
+        router.insert(new_endpoint(
+            new_handler_named("h4"),
+            Method::OPTIONS,
+            "/baz",
+        ));
+
+        // Resources without an OPTIONS handler answer on their own.
+        let result =
+            router.lookup_route(&Method::OPTIONS, "/foo/x".into()).unwrap();
+        assert_eq!(result.handler.label(), "(OPTIONS)");
+        assert_eq!(result.path, "/foo/{bar}");
+
+        // Those with one use it.
+        let result =
+            router.lookup_route(&Method::OPTIONS, "/baz".into()).unwrap();
+        assert_eq!(result.handler.label(), "h4");
+
+        // Paths that don't match any resource are still not found.
+        let error =
+            router.lookup_route(&Method::OPTIONS, "/qux".into()).unwrap_err();
+        assert_eq!(error.status_code, StatusCode::NOT_FOUND);
+    }
+
+    #[test]
+    fn test_route_conflict() {
+        let mut router = HttpRouter::new();
+        let mut endpoint =
+            new_endpoint(new_handler(), Method::GET, "/projects/{id}/info");
+        endpoint.operation_id = String::from("project_info");
+        endpoint.source_location = Some(String::from("src/projects.rs:10"));
+        router.insert(endpoint);
+
+        let mut endpoint =
+            new_endpoint(new_handler(), Method::GET, "/projects/default");
+        endpoint.operation_id = String::from("default_project");
+        let conflict = router.try_insert(endpoint).unwrap_err();
+        assert_eq!(conflict.endpoint.operation_id, "default_project");
+        assert_eq!(conflict.endpoint.path, "/projects/default");
+        assert_eq!(conflict.existing.operation_id, "project_info");
+        assert_eq!(conflict.existing.path, "/projects/{id}/info");
+        assert_eq!(
+            conflict.existing.source_location.as_deref(),
+            Some("src/projects.rs:10")
+        );
+        assert_eq!(conflict.segment.as_deref(), Some("default"));
+        assert!(conflict.to_string().ends_with(
+            "(registering default_project (GET /projects/default), which \
+             conflicts with project_info (GET /projects/{id}/info, defined at \
+             src/projects.rs:10))"
+        ));
+
+        let conflict = router
+            .try_insert(new_endpoint(
+                new_handler(),
+                Method::GET,
+                "/projects/{id}/info",
+            ))
+            .unwrap_err();
+        assert_eq!(conflict.existing.operation_id, "project_info");
+        assert_eq!(conflict.segment, None);
+
+        // The router is unchanged.
+        assert!(router
+            .lookup_route(&Method::GET, "/projects/default".into())
+            .is_err());
+    }
+
+    #[test]
+    fn test_trailing_slash() {
+        fn router(trailing_slash: TrailingSlash) -> HttpRouter<()> {
+            let mut router = HttpRouter::new();
+            router.set_trailing_slash(trailing_slash);
+            router.insert(new_endpoint(
+                new_handler_named("h1"),
+                Method::GET,
+                "/foo/{bar}",
+            ));
+            router.insert(new_endpoint(
+                new_handler_named("h2"),
+                Method::GET,
+                "/files/{path:.*}",
+            ));
+            router.insert(new_endpoint(
+                new_handler_named("h3"),
+                Method::GET,
+                "/",
+            ));
+            router
+        }
+
+        fn label(
+            router: &HttpRouter<()>,
+            path: &str,
+        ) -> Result<String, StatusCode> {
+            router
+                .lookup_route(&Method::GET, path.into())
+                .map(|result| result.handler.label().to_string())
+                .map_err(|error| error.status_code)
+        }
+
+        let merge = router(TrailingSlash::Merge);
+        assert_eq!(label(&merge, "/foo/x"), Ok("h1".to_string()));
+        assert_eq!(label(&merge, "/foo/x/"), Ok("h1".to_string()));
+
+        let strict = router(TrailingSlash::Strict);
+        assert_eq!(label(&strict, "/foo/x"), Ok("h1".to_string()));
+        assert_eq!(label(&strict, "/foo/x/"), Err(StatusCode::NOT_FOUND));
+        assert!(strict.lookup_allowed_methods("/foo/x/".into()).is_none());
+
+        let redirect = router(TrailingSlash::Redirect);
+        assert_eq!(label(&redirect, "/foo/x"), Ok("h1".to_string()));
+        assert_eq!(
+            label(&redirect, "/foo/x/"),
+            Ok("(trailing slash redirect)".to_string())
+        );
+        // Paths that match no route aren't redirected.
+        assert_eq!(label(&redirect, "/bar/"), Err(StatusCode::NOT_FOUND));
+
+        // The root and wildcard matches are the same under every policy.
+        for router in [&merge, &strict, &redirect] {
+            assert_eq!(label(router, "/"), Ok("h3".to_string()));
+            assert_eq!(label(router, "/files/a/b/"), Ok("h2".to_string()));
+            assert_eq!(label(router, "/files/"), Ok("h2".to_string()));
+        }
+    }
+
+    #[test]
+    fn test_case_insensitive() {
+        let mut router = HttpRouter::new();
+        router.insert(new_endpoint(
+            new_handler_named("h1"),
+            Method::GET,
+            "/projects/{id}/Instances",
+        ));
+        router.insert(new_endpoint(
+            new_handler_named("h2"),
+            Method::GET,
+            "/projects/{id}/instances",
+        ));
+
+        assert_eq!(
+            router
+                .lookup_route(&Method::GET, "/Projects/p1/instances".into())
+                .unwrap_err()
+                .status_code,
+            StatusCode::NOT_FOUND
+        );
+
+        router.set_case_insensitive(true);
+        let result = router
+            .lookup_route(&Method::GET, "/PROJECTS/P1/INSTANCES".into())
+            .unwrap();
+        assert_eq!(result.handler.label(), "h1");
+        assert_eq!(result.path, "/projects/{id}/Instances");
+        assert_eq!(
+            *result.variables.get("id").unwrap(),
+            VariableValue::String("P1".to_string())
+        );
+
+        // Exact matches still win.
+        let result = router
+            .lookup_route(&Method::GET, "/projects/p1/instances".into())
+            .unwrap();
+        assert_eq!(result.handler.label(), "h2");
+    }
+
+    #[test]
+    fn test_head() {
+        let mut router = HttpRouter::new();
+        router.insert(new_endpoint(
+            new_handler_named("h1"),
+            Method::GET,
+            "/foo/{bar}",
+        ));
+        router.insert(new_endpoint(
+            new_handler_named("h2"),
+            Method::GET,
+            "/baz",
+        ));
+        router.insert(new_endpoint(
+            new_handler_named("h3"),
+            Method::HEAD,
+            "/baz",
+        ));
+        router.insert(new_endpoint(
+            new_handler_named("h4"),
+            Method::PUT,
+            "/qux",
+        ));
+
+        // Resources without a HEAD handler use their GET handler.
+        let result =
+            router.lookup_route(&Method::HEAD, "/foo/x".into()).unwrap();
+        assert_eq!(result.handler.label(), "h1");
+        assert_eq!(
+            *result.variables.get("bar").unwrap(),
+            VariableValue::String("x".to_string())
+        );
+
+        // Those with one use it.
+        let result = router.lookup_route(&Method::HEAD, "/baz".into()).unwrap();
+        assert_eq!(result.handler.label(), "h3");
+
+        // Those with neither don't support HEAD.
+        let error =
+            router.lookup_route(&Method::HEAD, "/qux".into()).unwrap_err();
+        assert_eq!(error.status_code, StatusCode::METHOD_NOT_ALLOWED);
+        assert_eq!(
+            router.lookup_allowed_methods("/foo/x".into()).unwrap(),
+            "GET, HEAD, OPTIONS"
+        );
+        assert_eq!(
+            router.lookup_allowed_methods("/qux".into()).unwrap(),
+            "OPTIONS, PUT"
+        );
+    }
+
     #[test]
     fn test_router_basic() {
         let mut router = HttpRouter::new();
@@ -1167,6 +2185,40 @@ mod test {
         );
     }
 
+    #[test]
+    fn test_variables_pattern() {
+        let mut router = HttpRouter::new();
+        router.insert(new_endpoint(
+            new_handler_named("h1"),
+            Method::GET,
+            "/projects/{project_id:uuid}/instances/{instance:[a-z][a-z0-9]*}",
+        ));
+        let result = router
+            .lookup_route(
+                &Method::GET,
+                "/projects/3d4b2b0e-9d5c-4f1a-8c6e-2b7f1a0c9e8d/instances/i1"
+                    .into(),
+            )
+            .unwrap();
+        assert_eq!(result.handler.label(), "h1");
+        assert_eq!(
+            *result.variables.get("instance").unwrap(),
+            VariableValue::String("i1".to_string())
+        );
+
+        // Segments that don't match a variable's pattern don't match the
+        // route at all.
+        for path in [
+            "/projects/p12345/instances/i1",
+            "/projects/3d4b2b0e-9d5c-4f1a-8c6e-2b7f1a0c9e8d/instances/1i",
+            "/projects/3d4b2b0e-9d5c-4f1a-8c6e-2b7f1a0c9e8d/instances/i1x%20",
+        ] {
+            let error =
+                router.lookup_route(&Method::GET, path.into()).unwrap_err();
+            assert_eq!(error.status_code, StatusCode::NOT_FOUND);
+        }
+    }
+
     #[test]
     fn test_variables_multi() {
         // Exercise a case with multiple variables.
@@ -1242,11 +2294,43 @@ mod test {
 
         assert_eq!(
             result.variables.get("path"),
-            Some(&VariableValue::Components(vec![
-                "missiles".to_string(),
-                "launch".to_string()
-            ]))
+            Some(&VariableValue::Components(
+                vec!["missiles".to_string(), "launch".to_string()],
+                "missiles/launch".to_string(),
+            ))
+        );
+
+        // Segments are decoded, but the raw path is preserved.
+        let result = router
+            .lookup_route(&Method::OPTIONS, "/console//a%20b/c%3F/".into())
+            .unwrap();
+        assert_eq!(
+            result.variables.get("path"),
+            Some(&VariableValue::Components(
+                vec!["a b".to_string(), "c?".to_string()],
+                "a%20b/c%3F".to_string(),
+            ))
+        );
+
+        let result =
+            router.lookup_route(&Method::OPTIONS, "/console".into()).unwrap();
+        assert_eq!(
+            result.variables.get("path"),
+            Some(&VariableValue::Components(vec![], String::new()))
         );
+
+        // Segments that would change the meaning of the path once joined
+        // together are rejected.
+        for path in [
+            "/console/%2e%2e/secrets",
+            "/console/a/%2E",
+            "/console/a%2Fb",
+            "/console/a%00b",
+        ] {
+            let error =
+                router.lookup_route(&Method::OPTIONS, path.into()).unwrap_err();
+            assert_eq!(error.status_code, StatusCode::BAD_REQUEST);
+        }
     }
 
     #[test]
@@ -1339,11 +2423,56 @@ mod test {
 
     #[test]
     fn test_segments() {
+        let decoding = PathDecoding::default();
         let segs =
-            input_path_to_segments(&"//foo/bar/baz%2fbuzz".into()).unwrap();
+            input_path_to_segments(&"//foo/bar/baz%2fbuzz".into(), &decoding)
+                .unwrap();
         assert_eq!(segs, vec!["foo", "bar", "baz/buzz"]);
     }
 
+    #[test]
+    fn test_path_decoding() {
+        fn segments(
+            path: &str,
+            decoding: PathDecoding,
+        ) -> Result<Vec<String>, String> {
+            input_path_to_segments(&path.into(), &decoding)
+        }
+
+        let default = PathDecoding::default();
+        assert_eq!(segments("/a+b%2Bc", default).unwrap(), ["a+b+c"]);
+        assert_eq!(segments("/a%2Fb", default).unwrap(), ["a/b"]);
+        assert!(segments("/a%FFb", default).is_err());
+
+        let decoding = PathDecoding {
+            allow_encoded_slash: false,
+            plus_as_space: true,
+            invalid_utf8: InvalidUtf8::Replace,
+        };
+        assert_eq!(segments("/a+b%2Bc", decoding).unwrap(), ["a b+c"]);
+        assert!(segments("/a%2Fb", decoding).is_err());
+        assert_eq!(segments("/a%FFb", decoding).unwrap(), ["a\u{fffd}b"]);
+
+        // The options apply to the values of path variables.
+        let mut router = HttpRouter::new();
+        router.set_path_decoding(decoding);
+        router.insert(new_endpoint(
+            new_handler(),
+            Method::GET,
+            "/files/{name}",
+        ));
+        let result =
+            router.lookup_route(&Method::GET, "/files/my+file".into()).unwrap();
+        assert_eq!(
+            *result.variables.get("name").unwrap(),
+            VariableValue::String("my file".to_string())
+        );
+        let error = router
+            .lookup_route(&Method::GET, "/files/my%2Ffile".into())
+            .unwrap_err();
+        assert_eq!(error.status_code, StatusCode::BAD_REQUEST);
+    }
+
     #[test]
     fn test_path_segment() {
         let seg = PathSegment::from("abc");
@@ -1354,6 +2483,33 @@ mod test {
 
         let seg = PathSegment::from("{rest:.*}");
         assert_eq!(seg, PathSegment::VarnameWildcard("rest".to_string()),);
+
+        let seg = PathSegment::from("{id:uuid}");
+        match seg {
+            PathSegment::VarnamePattern(varname, pattern) => {
+                assert_eq!(varname, "id");
+                assert_eq!(pattern.format(), Some("uuid"));
+                assert_eq!(pattern.regex(), None);
+                assert!(
+                    pattern.is_match("3D4B2B0E-9D5C-4F1A-8C6E-2B7F1A0C9E8D")
+                );
+                assert!(!pattern.is_match("3d4b2b0e"));
+            }
+            _ => panic!("unexpected segment: {:?}", seg),
+        }
+
+        let seg = PathSegment::from("{name:abc+}");
+        match seg {
+            PathSegment::VarnamePattern(varname, pattern) => {
+                assert_eq!(varname, "name");
+                assert_eq!(pattern.format(), None);
+                assert_eq!(pattern.regex(), Some("^(?:abc+)$"));
+                assert!(pattern.is_match("abccc"));
+                assert!(!pattern.is_match("xabc"));
+                assert!(!pattern.is_match("abcx"));
+            }
+            _ => panic!("unexpected segment: {:?}", seg),
+        }
     }
 
     #[test]
@@ -1377,7 +2533,7 @@ mod test {
     #[test]
     #[should_panic]
     fn test_bad_path_segment4() {
-        let _ = PathSegment::from("{varname:abc+}");
+        let _ = PathSegment::from("{varname:abc(}");
     }
 
     #[test]
@@ -1395,10 +2551,10 @@ mod test {
         );
         map.insert(
             "ccc".to_string(),
-            VariableValue::Components(vec![
-                "lizzie".to_string(),
-                "brickley".to_string(),
-            ]),
+            VariableValue::Components(
+                vec!["lizzie".to_string(), "brickley".to_string()],
+                "lizzie/brickley".to_string(),
+            ),
         );
 
         match from_map::<A, VariableValue>(&map) {
@@ -1411,8 +2567,7 @@ mod test {
     }
 
     #[test]
-    fn test_map_bad_value() {
-        #[allow(dead_code)]
+    fn test_map_raw_components() {
         #[derive(Deserialize)]
         struct A {
             bbb: String,
@@ -1421,17 +2576,15 @@ mod test {
         let mut map = BTreeMap::new();
         map.insert(
             "bbb".to_string(),
-            VariableValue::Components(vec![
-                "lizzie".to_string(),
-                "brickley".to_string(),
-            ]),
+            VariableValue::Components(
+                vec!["lizzie".to_string(), "bric kley".to_string()],
+                "lizzie/bric%20kley".to_string(),
+            ),
         );
 
         match from_map::<A, VariableValue>(&map) {
-            Ok(_) => panic!("unexpected success"),
-            Err(s) => {
-                assert_eq!(s, "cannot deserialize sequence as a single value")
-            }
+            Ok(a) => assert_eq!(a.bbb, "lizzie/bric%20kley"),
+            Err(s) => panic!("unexpected error: {}", s),
         }
     }
 
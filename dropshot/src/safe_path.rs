@@ -0,0 +1,156 @@
+// Copyright 2023 Oxide Computer Company
+
+//! Relative paths that are safe to resolve under a root directory
+
+use camino::Utf8Component;
+use camino::Utf8Path;
+use camino::Utf8PathBuf;
+use schemars::gen::SchemaGenerator;
+use schemars::schema::Schema;
+use schemars::JsonSchema;
+use serde::de::Error;
+use serde::Deserialize;
+use serde::Deserializer;
+use std::ops::Deref;
+
+/// `SafePath` is a relative path built from the segments matched by a wildcard
+/// path variable (e.g., `{path:.*}`), for handlers that serve files from disk.
+/// Use it as the type of the wildcard's field in a [`crate::Path`] struct:
+///
+/// ```
+/// use dropshot::SafePath;
+/// use schemars::JsonSchema;
+/// use serde::Deserialize;
+///
+/// #[derive(Deserialize, JsonSchema)]
+/// struct FilePath {
+///     path: SafePath,
+/// }
+/// ```
+///
+/// Every segment must be a plain file name: requests with segments that are
+/// empty, `.`, or `..`, or that contain a path separator (`/` or `\`) or a
+/// NUL, are rejected with a 400.  As a result, [`SafePath::under()`] never
+/// produces a path outside of the directory it's given.  (That's only true of
+/// the path itself, though: it doesn't protect against symbolic links within
+/// that directory.)
+#[derive(Clone, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct SafePath(Utf8PathBuf);
+
+impl SafePath {
+    /// Builds a `SafePath` from the segments of a path, failing if any of them
+    /// isn't a plain file name.  No segments at all make an empty path, which
+    /// refers to the root itself.
+    pub fn from_segments<I, S>(segments: I) -> Result<SafePath, String>
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<str>,
+    {
+        let mut path = Utf8PathBuf::new();
+        for segment in segments {
+            let segment = segment.as_ref();
+            check_segment(segment)?;
+            path.push(segment);
+        }
+        Ok(SafePath(path))
+    }
+
+    /// Returns the path (relative to the root).
+    pub fn as_path(&self) -> &Utf8Path {
+        &self.0
+    }
+
+    /// Returns the path (relative to the root).
+    pub fn into_inner(self) -> Utf8PathBuf {
+        self.0
+    }
+
+    /// Returns the path resolved under the directory `root`, which is
+    /// guaranteed to be `root` or one of its descendants.
+    pub fn under<P: AsRef<Utf8Path>>(&self, root: P) -> Utf8PathBuf {
+        let root = root.as_ref();
+        if self.0.as_str().is_empty() {
+            root.to_path_buf()
+        } else {
+            root.join(&self.0)
+        }
+    }
+}
+
+/// Checks that `segment` is a plain file name, which can't change the meaning
+/// of the path it's pushed onto.
+fn check_segment(segment: &str) -> Result<(), String> {
+    let mut components = Utf8Path::new(segment).components();
+    let is_normal = match (components.next(), components.next()) {
+        (Some(Utf8Component::Normal(name)), None) => name == segment,
+        _ => false,
+    };
+    if !is_normal || segment.contains(['/', '\\', '\0']) {
+        return Err(format!("path segment {:?} is not permitted", segment));
+    }
+    Ok(())
+}
+
+impl Deref for SafePath {
+    type Target = Utf8Path;
+
+    fn deref(&self) -> &Utf8Path {
+        &self.0
+    }
+}
+
+impl AsRef<Utf8Path> for SafePath {
+    fn as_ref(&self) -> &Utf8Path {
+        &self.0
+    }
+}
+
+impl<'de> Deserialize<'de> for SafePath {
+    fn deserialize<D>(deserializer: D) -> Result<SafePath, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let segments = Vec::<String>::deserialize(deserializer)?;
+        SafePath::from_segments(segments).map_err(D::Error::custom)
+    }
+}
+
+// A `SafePath` is deserialized from the segments of the path, so that's how
+// it's described.
+impl JsonSchema for SafePath {
+    fn schema_name() -> String {
+        "SafePath".to_string()
+    }
+
+    fn is_referenceable() -> bool {
+        false
+    }
+
+    fn json_schema(gen: &mut SchemaGenerator) -> Schema {
+        <Vec<String>>::json_schema(gen)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::SafePath;
+    use camino::Utf8Path;
+
+    #[test]
+    fn test_safe_path() {
+        let path = SafePath::from_segments(["docs", "a b.txt"]).unwrap();
+        assert_eq!(path.as_path(), "docs/a b.txt");
+        assert_eq!(path.under("/srv/files"), "/srv/files/docs/a b.txt");
+
+        let path = SafePath::from_segments(Vec::<String>::new()).unwrap();
+        assert_eq!(path.as_path(), "");
+        assert_eq!(path.under(Utf8Path::new("/srv/files")), "/srv/files");
+
+        for segment in ["", ".", "..", "a/b", "/etc", "a\\b", "..\\..", "a\0"] {
+            assert_eq!(
+                SafePath::from_segments(["docs", segment]).unwrap_err(),
+                format!("path segment {:?} is not permitted", segment)
+            );
+        }
+    }
+}
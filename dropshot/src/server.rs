@@ -1,43 +1,129 @@
 // Copyright 2023 Oxide Computer Company
 //! Generic server-wide state and facilities
 
+use super::admin::AdminContext;
+use super::admin::AdminServer;
+use super::admin::AdminStarter;
 use super::api_description::ApiDescription;
-use super::config::{ConfigDropshot, ConfigTls};
+use super::bandwidth::throttle_body;
+use super::bandwidth::EndpointThrottles;
+use super::bandwidth::Throttle;
+use super::coalesce::CoalesceKey;
+use super::coalesce::RequestCoalescer;
+#[cfg(feature = "compression")]
+use super::compression::ResponseCompressor;
+use super::config::ConfigListener;
+#[cfg(feature = "tls")]
+use super::config::ConfigTls;
+#[cfg(unix)]
+use super::config::ConfigUnixSocket;
+use super::config::{ConfigDropshot, ConfigSocketOptions, HandlerTaskMode};
+use super::connection::AcceptedConnection;
+use super::connection::ConnectionInfo;
+use super::decompress::ContentDecoders;
+use super::disconnect::DisconnectGuard;
 #[cfg(feature = "usdt-probes")]
 use super::dtrace::probes;
+use super::early_hints;
+use super::early_hints::EarlyHints;
+use super::early_hints::EarlyHintsSender;
 use super::error::HttpError;
+use super::error_responses::error_response;
+use super::error_responses::redact_server_error;
+use super::error_responses::ErrorFormat;
+use super::error_responses::ErrorMapper;
+use super::error_responses::ErrorResponseCustomizer;
+use super::error_responses::GeneratedErrorKind;
+use super::expect_continue::expects_continue;
+use super::expect_continue::ContinueCheck;
+use super::forwarded::EffectiveClient;
+use super::forwarded::TrustedProxies;
 use super::handler::RequestContext;
+use super::hooks::RequestHooks;
+#[cfg(feature = "http3")]
+use super::http3::Http3Listener;
+use super::http_util::http_read_body;
 use super::http_util::HEADER_REQUEST_ID;
+use super::idempotency::idempotency_key;
+use super::idempotency::request_digest;
+use super::idempotency::run_idempotent;
+use super::idempotency::IdempotencyStore;
+use super::keep_alive;
+use super::keep_alive::ConnectionLifetime;
+use super::keep_alive::ConnectionRequests;
+use super::load_limits::LoadLimits;
+use super::maintenance::MaintenanceMode;
+use super::maintenance::MaintenanceState;
+use super::request_log::EndpointLogLevels;
+use super::request_log::LogRedactor;
+use super::request_log::RequestLogSampler;
+use super::reuseport::bind_reuseport;
+use super::reuseport::count_accepted;
+use super::reuseport::AcceptorStats;
+use super::reuseport::Acceptors;
 use super::router::HttpRouter;
+use super::settings::ServerSettings;
+use super::settings::ServerSettingsHandle;
+use super::slow_request::RequestTimings;
+use super::slow_request::SlowRequestDetector;
+use super::slow_request::TimedBody;
+use super::socket_activation::take_listen_fd;
+use super::socket_options::configure_connection;
+use super::socket_options::configure_listener;
+use super::socket_options::listen;
+use super::socket_options::listener_socket;
+use super::strict_http::StrictHttp;
+use super::strict_http::StrictHttpRejections;
+use super::tenancy::TenantResolver;
+#[cfg(feature = "tls")]
+use super::tls::tls_connections;
+#[cfg(feature = "tls")]
+use super::tls::tls_file_times;
+use super::ConfigLoggingLevel;
 use super::ProbeRegistration;
 
 use async_stream::stream;
-use futures::future::{
-    BoxFuture, FusedFuture, FutureExt, Shared, TryFutureExt,
-};
+use futures::future::{BoxFuture, FusedFuture, FutureExt, Shared};
+#[cfg(feature = "tls")]
 use futures::lock::Mutex;
 use futures::stream::{Stream, StreamExt};
-use hyper::server::{
-    conn::{AddrIncoming, AddrStream},
-    Server,
-};
+use http::StatusCode;
+use http_body::Body as HttpBody;
+use hyper::body::Incoming;
 use hyper::service::Service;
-use hyper::Body;
 use hyper::Request;
 use hyper::Response;
+use hyper_util::rt::TokioExecutor;
+use hyper_util::rt::TokioIo;
+use hyper_util::rt::TokioTimer;
+use hyper_util::server::conn::auto;
+use hyper_util::server::graceful::GracefulShutdown;
+#[cfg(feature = "tls")]
 use rustls;
+use socket2::SockRef;
+#[cfg(feature = "tls")]
 use std::convert::TryFrom;
 use std::future::Future;
 use std::net::SocketAddr;
 use std::num::NonZeroU32;
 use std::pin::Pin;
+use std::sync::atomic::AtomicBool;
+use std::sync::atomic::AtomicU64;
+use std::sync::atomic::AtomicUsize;
+use std::sync::atomic::Ordering;
 use std::sync::Arc;
+use std::sync::RwLock;
 use std::task::{Context, Poll};
-use tokio::io::ReadBuf;
+use std::time::Duration;
+use std::time::Instant;
+use tokio::io::{AsyncRead, AsyncWrite};
 use tokio::net::{TcpListener, TcpStream};
-use tokio_rustls::{server::TlsStream, TlsAcceptor};
+use tokio::sync::watch;
+#[cfg(feature = "tls")]
+use tokio_rustls::TlsAcceptor;
 use uuid::Uuid;
 
+use crate::Body;
 use crate::RequestInfo;
 use slog::Logger;
 
@@ -58,23 +144,231 @@ pub struct DropshotState<C: ServerContext> {
     pub private: C,
     /// static server configuration parameters
     pub config: ServerConfig,
-    /// request router
-    pub router: HttpRouter<C>,
+    /// request router and per-endpoint state, replaced as a whole by
+    /// `HttpServer::replace_api()`
+    pub(crate) routes: RwLock<Arc<Routes<C>>>,
     /// server-wide log handle
     pub log: Logger,
     /// bound local address for the server.
     pub local_addr: SocketAddr,
     /// Identifies how to accept TLS connections
+    #[cfg(feature = "tls")]
     pub(crate) tls_acceptor: Option<Arc<Mutex<TlsAcceptor>>>,
+    /// Configuration from which the TLS acceptor was last loaded (see
+    /// `HttpServer::reload_tls()`)
+    #[cfg(feature = "tls")]
+    pub(crate) tls_config: std::sync::Mutex<Option<ConfigTls>>,
+    /// Decides which completed requests get logged
+    pub(crate) request_log_sampler: RequestLogSampler,
+    /// Response bandwidth limit for each connection.  Each connection has its
+    /// own bucket, but they all share this rate.
+    pub(crate) connection_throttle: Throttle,
+    /// Masks sensitive request data before it's logged
+    pub(crate) log_redactor: LogRedactor,
+    /// Set once the server has begun a graceful shutdown
+    pub(crate) draining: AtomicBool,
+    /// Whether the server's listeners are accepting new connections (see
+    /// `HttpServer::pause_accepting()`)
+    pub(crate) accepting: watch::Sender<bool>,
+    /// Number of requests whose responses haven't yet been produced
+    pub(crate) in_flight_requests: AtomicUsize,
+    /// Decides which connections and requests to shed under load
+    pub(crate) load_limits: LoadLimits,
+    /// Counts the connections accepted by each socket, when several are bound
+    /// with `SO_REUSEPORT`
+    pub(crate) acceptors: Acceptors,
+    /// `Alt-Svc` header with which responses on the TCP listener advertise
+    /// the server's HTTP/3 endpoint, if it has one
+    pub(crate) alt_svc: Option<http::HeaderValue>,
+    /// Whether (and how) the server is rejecting requests for maintenance
+    pub(crate) maintenance: MaintenanceState,
+    /// Records the outcomes of requests with idempotency keys
+    pub(crate) idempotency_store: Option<Arc<dyn IdempotencyStore>>,
+    /// Tracks in-flight requests to endpoints that coalesce requests
+    pub(crate) coalescer: RequestCoalescer,
+    /// Decides which requests to report as slow
+    pub(crate) slow_request_detector: SlowRequestDetector,
+    /// Identifies the tenant of each request before it's routed
+    pub(crate) tenant_resolver: Option<Arc<dyn TenantResolver>>,
+    /// Identifies the client of each request behind trusted proxies
+    pub(crate) trusted_proxies: TrustedProxies,
+    /// Settings that can be changed while the server runs
+    pub(crate) settings: ServerSettingsHandle,
+    /// Supplies the bodies of error responses that Dropshot generates itself
+    pub(crate) error_response_customizer:
+        Option<Arc<dyn ErrorResponseCustomizer>>,
+    /// Format of the bodies of error responses
+    pub(crate) error_format: ErrorFormat,
+    /// Rewrites errors before they're sent
+    pub(crate) error_mapper: RwLock<Option<Arc<dyn ErrorMapper<C>>>>,
+    /// Inspects incoming HTTP/1 requests, if strict parsing is enabled
+    pub(crate) strict_http: StrictHttp,
+    /// Decides whether requests that ask to continue may send their bodies
+    pub(crate) continue_check: Option<Arc<dyn ContinueCheck<C>>>,
+    /// decodes compressed request bodies
+    pub(crate) content_decoders: ContentDecoders,
+    /// Compresses response bodies, if compression is enabled
+    #[cfg(feature = "compression")]
+    pub(crate) compressor: Option<ResponseCompressor>,
 }
 
 impl<C: ServerContext> DropshotState<C> {
+    /// Returns whether the server's main listener uses TLS.  Connections on
+    /// its additional listeners (see `ConfigDropshot::additional_listeners`)
+    /// may not match; see [`ConnectionInfo::is_tls()`].
     pub fn using_tls(&self) -> bool {
-        self.tls_acceptor.is_some()
+        #[cfg(feature = "tls")]
+        return self.tls_acceptor.is_some();
+        #[cfg(not(feature = "tls"))]
+        return false;
+    }
+
+    /// Returns true if the server has begun a graceful shutdown and is waiting
+    /// for in-flight requests to complete.
+    pub fn is_draining(&self) -> bool {
+        self.draining.load(Ordering::SeqCst)
+    }
+
+    /// Replaces the server's TLS certificates with those loaded from
+    /// `config`.  Connections that have already been established keep using
+    /// the certificates they were established with.
+    #[cfg(feature = "tls")]
+    pub(crate) async fn refresh_tls(
+        &self,
+        config: &ConfigTls,
+    ) -> Result<(), String> {
+        let acceptor = self
+            .tls_acceptor
+            .as_ref()
+            .ok_or_else(|| "Not configured for TLS".to_string())?;
+        let tls_config = rustls::ServerConfig::try_from(config)
+            .map_err(|e| format!("loading TLS configuration: {}", e))?;
+        *acceptor.lock().await = TlsAcceptor::from(Arc::new(tls_config));
+        *self.tls_config.lock().unwrap() = Some(config.clone());
+        Ok(())
+    }
+
+    /// Reloads the server's TLS certificates from the files named by its
+    /// current TLS configuration.
+    #[cfg(feature = "tls")]
+    pub(crate) async fn reload_tls(&self) -> Result<(), String> {
+        let config = self
+            .tls_config
+            .lock()
+            .unwrap()
+            .clone()
+            .ok_or_else(|| "Not configured for TLS".to_string())?;
+        self.refresh_tls(&config).await
+    }
+
+    /// Returns the router with which requests are currently routed.
+    pub fn router(&self) -> Arc<HttpRouter<C>> {
+        Arc::clone(&self.routes().router)
+    }
+
+    /// Returns the current endpoints and their state.  Requests hold onto
+    /// these until they're complete, even if the API is replaced meanwhile.
+    pub(crate) fn routes(&self) -> Arc<Routes<C>> {
+        Arc::clone(&self.routes.read().unwrap())
+    }
+
+    pub(crate) fn replace_api(&self, api: ApiDescription<C>) {
+        let routes = Routes::new(api);
+        for (path, method, _) in &*routes.router {
+            debug!(self.log, "registered endpoint";
+                "method" => &method,
+                "path" => &path
+            );
+        }
+        *self.routes.write().unwrap() = Arc::new(routes);
+        info!(self.log, "replaced API");
+    }
+
+    pub(crate) fn set_endpoint_log_level(
+        &self,
+        method: &http::Method,
+        path: &str,
+        level: Option<ConfigLoggingLevel>,
+    ) -> Result<(), String> {
+        let routes = self.routes();
+        let registered = routes
+            .router
+            .endpoints()
+            .any(|e| e.method == *method && e.path == path);
+        if !registered {
+            return Err(format!(
+                "no endpoint registered for {} {}",
+                method, path
+            ));
+        }
+        routes.endpoint_log_levels.set(method, path, level);
+        Ok(())
+    }
+
+    pub(crate) fn set_accepting(&self, accepting: bool) {
+        if self.accepting.send_replace(accepting) != accepting {
+            if accepting {
+                info!(self.log, "resuming accepting connections");
+            } else {
+                info!(self.log, "pausing accepting connections");
+            }
+        }
+    }
+
+    pub(crate) fn set_maintenance_mode(&self, mode: Option<MaintenanceMode>) {
+        match &mode {
+            Some(_) => info!(self.log, "entering maintenance mode"),
+            None => info!(self.log, "leaving maintenance mode"),
+        }
+        self.maintenance.set(mode);
+    }
+
+    pub(crate) fn set_endpoint_bandwidth_limit(
+        &self,
+        method: &http::Method,
+        path: &str,
+        bytes_per_sec: Option<u64>,
+    ) -> Result<(), String> {
+        let routes = self.routes();
+        let throttle =
+            routes.endpoint_throttles.get(method, path).ok_or_else(|| {
+                format!("no endpoint registered for {} {}", method, path)
+            })?;
+        throttle.set_rate(bytes_per_sec);
+        Ok(())
     }
 }
 
-/// Stores static configuration associated with the server
+/// The endpoints that a server serves, along with the state that's kept for
+/// each of them
+#[derive(Debug)]
+pub(crate) struct Routes<C: ServerContext> {
+    /// request router
+    pub(crate) router: Arc<HttpRouter<C>>,
+    /// Per-endpoint log level overrides
+    pub(crate) endpoint_log_levels: EndpointLogLevels,
+    /// Per-endpoint response bandwidth limits
+    pub(crate) endpoint_throttles: EndpointThrottles,
+    /// Run before and after the handlers of the endpoints they're attached to
+    pub(crate) hooks: RequestHooks<C>,
+}
+
+impl<C: ServerContext> Routes<C> {
+    pub(crate) fn new(api: ApiDescription<C>) -> Self {
+        let hooks = RequestHooks::new(&api.hooks);
+        let router = api.into_router();
+        Routes {
+            endpoint_log_levels: EndpointLogLevels::new(&router),
+            endpoint_throttles: EndpointThrottles::new(&router),
+            hooks,
+            router: Arc::new(router),
+        }
+    }
+}
+
+/// Stores static configuration associated with the server.  For the settings
+/// that can change while the server runs (e.g., `request_body_max_bytes` and
+/// the timeouts), these are only the initial values: see `ServerSettings`.
 /// TODO-cleanup merge with ConfigDropshot
 #[derive(Debug)]
 pub struct ServerConfig {
@@ -84,6 +378,43 @@ pub struct ServerConfig {
     pub page_max_nitems: NonZeroU32,
     /// default size for a page of results
     pub page_default_nitems: NonZeroU32,
+    /// whether to validate requests against their JSON schemas
+    pub schema_validation: bool,
+    /// whether to reject undeclared query parameters
+    pub strict_query_params: bool,
+    /// maximum allowed size of each part of a multipart body
+    pub multipart_part_max_bytes: Option<usize>,
+    /// maximum allowed size of a request body once decompressed
+    pub request_body_decompressed_max_bytes: Option<usize>,
+    /// what happens to handlers when their clients disconnect
+    pub handler_task_mode: HandlerTaskMode,
+    /// whether to hide the messages of server errors from clients
+    pub redact_server_errors: bool,
+    /// whether to report a 404 rather than a 405 for unsupported methods
+    pub method_not_allowed_as_not_found: bool,
+    /// methods to which `POST` requests may be overridden, if overriding is
+    /// enabled
+    pub method_override: Option<Vec<http::Method>>,
+    /// how long to wait for requests in progress to complete when shutting
+    /// down, if not indefinitely
+    pub drain_timeout: Option<Duration>,
+    /// how long a connection may stay open without a request in progress, if
+    /// not indefinitely
+    pub keep_alive_timeout: Option<Duration>,
+    /// how long a connection may go without any reads or writes, if not
+    /// indefinitely
+    pub idle_timeout: Option<Duration>,
+    /// number of requests after which a connection is closed, if any
+    pub max_requests_per_connection: Option<u64>,
+    /// how long a client may take to send a request's head, if not
+    /// indefinitely
+    pub header_read_timeout: Option<Duration>,
+    /// maximum size of a request's head, if not hyper's default
+    pub max_header_bytes: Option<usize>,
+    /// maximum number of headers in a request, if not hyper's default
+    pub max_header_count: Option<usize>,
+    /// options for the server's TCP sockets
+    pub socket_options: ConfigSocketOptions,
 }
 
 /// A thin wrapper around a Hyper Server object that exposes some interfaces that
@@ -92,6 +423,8 @@ pub struct HttpServerStarter<C: ServerContext> {
     app_state: Arc<DropshotState<C>>,
     local_addr: SocketAddr,
     wrapped: WrappedHttpServerStarter<C>,
+    additional_listeners: Vec<AdditionalListener>,
+    admin: Option<Box<dyn AdminStarter>>,
 }
 
 impl<C: ServerContext> HttpServerStarter<C> {
@@ -100,15 +433,148 @@ impl<C: ServerContext> HttpServerStarter<C> {
         api: ApiDescription<C>,
         private: C,
         log: &Logger,
+    ) -> Result<HttpServerStarter<C>, GenericError> {
+        HttpServerStarter::new_internal(config, api, private, log, None)
+    }
+
+    /// Like [`HttpServerStarter::new()`], but the server accepts connections
+    /// on `listener`, which the caller has already bound, rather than binding
+    /// to `config.bind_address` (or using `config.unix_socket` or
+    /// `config.listen_fd`).  This supports schemes for deploying a new
+    /// version of a server without downtime, in which the old version hands
+    /// its listening socket to the new one.
+    pub fn new_with_tcp_listener(
+        config: &ConfigDropshot,
+        api: ApiDescription<C>,
+        private: C,
+        log: &Logger,
+        listener: std::net::TcpListener,
+    ) -> Result<HttpServerStarter<C>, GenericError> {
+        HttpServerStarter::new_internal(
+            config,
+            api,
+            private,
+            log,
+            Some(listener),
+        )
+    }
+
+    fn new_internal(
+        config: &ConfigDropshot,
+        api: ApiDescription<C>,
+        private: C,
+        log: &Logger,
+        listener: Option<std::net::TcpListener>,
     ) -> Result<HttpServerStarter<C>, GenericError> {
         let server_config = ServerConfig {
             // We start aggressively to ensure test coverage.
             request_body_max_bytes: config.request_body_max_bytes,
             page_max_nitems: NonZeroU32::new(10000).unwrap(),
             page_default_nitems: NonZeroU32::new(100).unwrap(),
+            schema_validation: config.schema_validation,
+            strict_query_params: config.strict_query_params,
+            multipart_part_max_bytes: config.multipart_part_max_bytes,
+            request_body_decompressed_max_bytes: config
+                .request_body_decompressed_max_bytes,
+            handler_task_mode: config.handler_task_mode,
+            redact_server_errors: config.redact_server_errors,
+            method_not_allowed_as_not_found: config
+                .method_not_allowed_as_not_found,
+            method_override: config
+                .method_override
+                .as_ref()
+                .map(|method_override| {
+                    method_override
+                        .methods
+                        .iter()
+                        .map(|method| {
+                            http::Method::from_bytes(
+                                method.to_uppercase().as_bytes(),
+                            )
+                            .map_err(|_| {
+                                GenericError::from(format!(
+                                    "invalid method for \
+                                     method_override: \"{}\"",
+                                    method
+                                ))
+                            })
+                        })
+                        .collect::<Result<Vec<_>, _>>()
+                })
+                .transpose()?,
+            drain_timeout: config.drain_timeout_ms.map(Duration::from_millis),
+            keep_alive_timeout: config
+                .keep_alive_timeout_ms
+                .map(Duration::from_millis),
+            idle_timeout: config.idle_timeout_ms.map(Duration::from_millis),
+            max_requests_per_connection: config.max_requests_per_connection,
+            header_read_timeout: config
+                .header_read_timeout_ms
+                .map(Duration::from_millis),
+            max_header_bytes: config.max_header_bytes,
+            max_header_count: config.max_header_count,
+            socket_options: config.socket_options.clone(),
         };
 
-        let starter = match config.tls {
+        #[cfg(not(feature = "http3"))]
+        if config.http3.is_some() {
+            return Err(GenericError::from(
+                "HTTP/3 support requires the \"http3\" feature of dropshot",
+            ));
+        }
+        if config.max_requests_per_connection == Some(0) {
+            return Err(GenericError::from(
+                "max_requests_per_connection must be at least 1",
+            ));
+        }
+        if config.max_header_bytes.map_or(false, |max| max < MIN_HEADER_BYTES) {
+            return Err(GenericError::from(format!(
+                "max_header_bytes must be at least {}",
+                MIN_HEADER_BYTES
+            )));
+        }
+        if let Some(reuseport) = &config.reuseport {
+            if reuseport.acceptors == Some(0) {
+                return Err(GenericError::from(
+                    "reuseport requires at least one acceptor",
+                ));
+            }
+            if listener.is_some()
+                || config.listen_fd.is_some()
+                || config.unix_socket.is_some()
+            {
+                return Err(GenericError::from(
+                    "reuseport requires the server to bind its own TCP \
+                     listener",
+                ));
+            }
+        }
+        if config.unix_socket.is_some() && config.listen_fd.is_some() {
+            return Err(GenericError::from(
+                "unix_socket and listen_fd cannot both be configured",
+            ));
+        }
+        if config.unix_socket.is_some() && config.tls.is_some() {
+            return Err(GenericError::from(
+                "TLS is not supported on Unix domain sockets",
+            ));
+        }
+        if config.http3.is_some() && config.tls.is_none() {
+            return Err(GenericError::from(
+                "serving HTTP/3 requires TLS to be configured",
+            ));
+        }
+
+        #[cfg(not(feature = "compression"))]
+        if config.compression.is_some() {
+            return Err(GenericError::from(
+                "response compression requires the \"compression\" feature \
+                 of dropshot",
+            ));
+        }
+
+        let mut starter = match config.tls {
+            #[cfg(feature = "tls")]
             Some(_) => {
                 let (starter, app_state, local_addr) =
                     InnerHttpsServerStarter::new(
@@ -117,13 +583,22 @@ impl<C: ServerContext> HttpServerStarter<C> {
                         api,
                         private,
                         log,
+                        listener,
                     )?;
                 HttpServerStarter {
                     app_state,
                     local_addr,
                     wrapped: WrappedHttpServerStarter::Https(starter),
+                    additional_listeners: Vec::new(),
+                    admin: None,
                 }
             }
+            #[cfg(not(feature = "tls"))]
+            Some(_) => {
+                return Err(GenericError::from(
+                    "TLS support requires the \"tls\" feature of dropshot",
+                ));
+            }
             None => {
                 let (starter, app_state, local_addr) =
                     InnerHttpServerStarter::new(
@@ -132,16 +607,28 @@ impl<C: ServerContext> HttpServerStarter<C> {
                         api,
                         private,
                         log,
+                        listener,
                     )?;
                 HttpServerStarter {
                     app_state,
                     local_addr,
                     wrapped: WrappedHttpServerStarter::Http(starter),
+                    additional_listeners: Vec::new(),
+                    admin: None,
                 }
             }
         };
 
-        for (path, method, _) in &starter.app_state.router {
+        starter.additional_listeners = config
+            .additional_listeners
+            .iter()
+            .map(|listener| {
+                AdditionalListener::bind(listener, &config.socket_options)
+            })
+            .collect::<Result<_, _>>()?;
+        starter.bind_reuseport_acceptors()?;
+
+        for (path, method, _) in &*starter.app_state.router() {
             debug!(starter.app_state.log, "registered endpoint";
                 "method" => &method,
                 "path" => &path
@@ -151,20 +638,113 @@ impl<C: ServerContext> HttpServerStarter<C> {
         Ok(starter)
     }
 
+    /// If the server has several acceptors (see `ConfigDropshot::reuseport`),
+    /// binds the sockets for all but the first (the server's main listener)
+    /// and sets each one up to count the connections that it accepts.
+    fn bind_reuseport_acceptors(&mut self) -> std::io::Result<()> {
+        let acceptors = &self.app_state.acceptors;
+        if acceptors.is_empty() {
+            return Ok(());
+        }
+        match &mut self.wrapped {
+            WrappedHttpServerStarter::Http(http) => {
+                http.accepted = Some(acceptors.counter(0));
+            }
+            #[cfg(feature = "tls")]
+            WrappedHttpServerStarter::Https(https) => {
+                https.accepted = Some(acceptors.counter(0));
+            }
+        }
+        for index in 1..acceptors.len() {
+            // The main listener's address has the port that the kernel chose,
+            // if `bind_address` didn't specify one.
+            let listener = bind_reuseport(
+                &self.local_addr,
+                &self.app_state.config.socket_options,
+            )?;
+            self.additional_listeners.push(AdditionalListener {
+                listener,
+                local_addr: self.local_addr,
+                accepted: Some(acceptors.counter(index)),
+                #[cfg(feature = "tls")]
+                tls_acceptor: self.app_state.tls_acceptor.clone(),
+            });
+        }
+        Ok(())
+    }
+
+    /// Binds a second listener, configured by `config`, that serves `api` when
+    /// this server is started.  This is intended for operator-only endpoints
+    /// (health checks, statistics, runtime reconfiguration, and so on) that
+    /// should not be reachable through the public listener.  The admin
+    /// listener has its own TLS configuration and its own server-wide hooks
+    /// (those configured on `api`); its handlers can inspect and control this
+    /// server through [`AdminContext`].  See [`crate::admin`] for details.
+    ///
+    /// The admin listener is started and closed along with this server.
+    pub fn admin_listener(
+        mut self,
+        config: &ConfigDropshot,
+        api: ApiDescription<AdminContext<C>>,
+        log: &Logger,
+    ) -> Result<HttpServerStarter<C>, GenericError> {
+        let private = AdminContext::new(Arc::clone(&self.app_state));
+        let log = log.new(o!("listener" => "admin"));
+        let starter = HttpServerStarter::new(config, api, private, &log)?;
+        self.admin = Some(Box::new(starter));
+        Ok(self)
+    }
+
+    /// Sets the mapper used to inspect, and possibly rewrite, every error
+    /// that the server sends.  See [`ErrorMapper`].
+    pub fn error_mapper(self, error_mapper: Arc<dyn ErrorMapper<C>>) -> Self {
+        *self.app_state.error_mapper.write().unwrap() = Some(error_mapper);
+        self
+    }
+
     pub fn start(self) -> HttpServer<C> {
         let (tx, rx) = tokio::sync::oneshot::channel::<()>();
         let log_close = self.app_state.log.new(o!());
-        let join_handle = match self.wrapped {
-            WrappedHttpServerStarter::Http(http) => http.start(rx, log_close),
-            WrappedHttpServerStarter::Https(https) => {
-                https.start(rx, log_close)
-            }
+        let additional_local_addrs = self
+            .additional_listeners
+            .iter()
+            .map(|listener| listener.local_addr)
+            .collect::<Vec<_>>();
+        let (main_close, main_close_signal) = tokio::sync::oneshot::channel();
+        let mut listeners = vec![(
+            main_close,
+            match self.wrapped {
+                WrappedHttpServerStarter::Http(http) => {
+                    http.serve(main_close_signal, log_close.clone())
+                }
+                #[cfg(feature = "tls")]
+                WrappedHttpServerStarter::Https(https) => {
+                    https.serve(main_close_signal, log_close.clone())
+                }
+            },
+        )];
+        for listener in self.additional_listeners {
+            let (close, close_signal) = tokio::sync::oneshot::channel();
+            listeners.push((
+                close,
+                listener.serve(
+                    Arc::clone(&self.app_state),
+                    close_signal,
+                    log_close.clone(),
+                ),
+            ));
         }
-        .map(|r| {
-            r.map_err(|e| format!("waiting for server: {e}"))?
-                .map_err(|e| format!("server stopped: {e}"))
-        });
+        let join_handle =
+            tokio::spawn(serve_listeners(rx, listeners)).map(|r| {
+                r.map_err(|e| format!("waiting for server: {e}"))?
+                    .map_err(|e| format!("server stopped: {e}"))
+            });
         info!(self.app_state.log, "listening");
+        for local_addr in &additional_local_addrs {
+            info!(self.app_state.log, "listening";
+                "additional_local_addr" => %local_addr,
+            );
+        }
 
         #[cfg(feature = "usdt-probes")]
         let probe_registration = match usdt::register_probes() {
@@ -197,39 +777,206 @@ impl<C: ServerContext> HttpServerStarter<C> {
             probe_registration,
             app_state: self.app_state,
             local_addr: self.local_addr,
+            additional_local_addrs,
             closer: CloseHandle { close_channel: Some(tx) },
             join_future: join_handle.boxed().shared(),
+            admin: self.admin.map(|admin| admin.start()),
+        }
+    }
+}
+
+impl<C: ServerContext> AdminStarter for HttpServerStarter<C> {
+    fn start(self: Box<Self>) -> Box<dyn AdminServer> {
+        Box::new(HttpServerStarter::start(*self))
+    }
+}
+
+/// A future that runs one of a server's listeners until it's closed
+type ServeFuture = BoxFuture<'static, Result<ShutdownReport, GenericError>>;
+
+/// Runs each of a server's listeners (along with the sender with which it's
+/// closed) until `close_signal` fires.
+async fn serve_listeners(
+    close_signal: tokio::sync::oneshot::Receiver<()>,
+    listeners: Vec<(tokio::sync::oneshot::Sender<()>, ServeFuture)>,
+) -> Result<ShutdownReport, GenericError> {
+    let (closers, listeners): (Vec<_>, Vec<_>) = listeners.into_iter().unzip();
+    // If the server is dropped without being closed, dropping these senders
+    // passes that along, too.
+    let forward_close = async move {
+        if close_signal.await.is_ok() {
+            for closer in closers {
+                let _ = closer.send(());
+            }
+        }
+        Ok::<(), GenericError>(())
+    };
+    // Each listener runs on its own task, so that their accept loops can run
+    // in parallel.  Dropping the tasks (if one of them fails) aborts the rest.
+    let mut tasks = tokio::task::JoinSet::new();
+    for listener in listeners {
+        tasks.spawn(listener);
+    }
+    let join_all = async move {
+        let mut reports = Vec::new();
+        while let Some(result) = tasks.join_next().await {
+            reports.push(result??);
+        }
+        Ok::<_, GenericError>(reports)
+    };
+    let ((), reports) = tokio::try_join!(forward_close, join_all)?;
+    // Each counts all of the server's in-flight requests, whichever listener
+    // they arrived on.
+    Ok(ShutdownReport {
+        aborted_requests: reports
+            .iter()
+            .map(|report| report.aborted_requests)
+            .max()
+            .unwrap_or(0),
+    })
+}
+
+/// One of a server's additional listeners (see
+/// `ConfigDropshot::additional_listeners`)
+struct AdditionalListener {
+    listener: TcpListener,
+    local_addr: SocketAddr,
+    /// counts the connections accepted, if this is one of several sockets
+    /// bound with `SO_REUSEPORT`
+    accepted: Option<Arc<AtomicU64>>,
+    #[cfg(feature = "tls")]
+    tls_acceptor: Option<Arc<Mutex<TlsAcceptor>>>,
+}
+
+impl AdditionalListener {
+    fn bind(
+        config: &ConfigListener,
+        socket_options: &ConfigSocketOptions,
+    ) -> Result<AdditionalListener, GenericError> {
+        #[cfg(not(feature = "tls"))]
+        if config.tls.is_some() {
+            return Err(GenericError::from(
+                "TLS support requires the \"tls\" feature of dropshot",
+            ));
+        }
+        #[cfg(feature = "tls")]
+        let tls_acceptor = match &config.tls {
+            Some(tls) => Some(Arc::new(Mutex::new(TlsAcceptor::from(
+                Arc::new(rustls::ServerConfig::try_from(tls)?),
+            )))),
+            None => None,
+        };
+        let listener = bind_listener(&config.bind_address, socket_options)?;
+        let local_addr = listener.local_addr()?;
+        Ok(AdditionalListener {
+            listener,
+            local_addr,
+            accepted: None,
+            #[cfg(feature = "tls")]
+            tls_acceptor,
+        })
+    }
+
+    /// Returns a future that serves connections on this listener with the
+    /// server's state.
+    fn serve<C: ServerContext>(
+        self,
+        server: Arc<DropshotState<C>>,
+        close_signal: tokio::sync::oneshot::Receiver<()>,
+        log_close: Logger,
+    ) -> ServeFuture {
+        let log = server.log.new(o!("local_addr" => self.local_addr));
+        #[cfg(feature = "tls")]
+        if let Some(tls_acceptor) = self.tls_acceptor {
+            let connections = Box::pin(count_accepted(
+                tls_connections(
+                    log,
+                    tls_acceptor,
+                    self.listener,
+                    server.config.socket_options.clone(),
+                ),
+                self.accepted,
+            ));
+            return serve_connections(
+                server,
+                connections,
+                close_signal,
+                log_close,
+            )
+            .boxed();
         }
+        let connections = Box::pin(count_accepted(
+            tcp_connections(
+                log,
+                self.listener,
+                server.config.socket_options.clone(),
+            ),
+            self.accepted,
+        ));
+        serve_connections(server, connections, close_signal, log_close).boxed()
     }
 }
 
 enum WrappedHttpServerStarter<C: ServerContext> {
     Http(InnerHttpServerStarter<C>),
+    #[cfg(feature = "tls")]
     Https(InnerHttpsServerStarter<C>),
 }
 
-struct InnerHttpServerStarter<C: ServerContext>(
-    Server<AddrIncoming, ServerConnectionHandler<C>>,
-);
+struct InnerHttpServerStarter<C: ServerContext> {
+    app_state: Arc<DropshotState<C>>,
+    listener: ServerListener,
+    /// counts the connections accepted, if the listener is one of several
+    /// sockets bound with `SO_REUSEPORT`
+    accepted: Option<Arc<AtomicU64>>,
+}
 
 type InnerHttpServerStarterNewReturn<C> =
     (InnerHttpServerStarter<C>, Arc<DropshotState<C>>, SocketAddr);
 
 impl<C: ServerContext> InnerHttpServerStarter<C> {
-    /// Begins execution of the underlying Http server.
-    fn start(
+    /// Returns a future that runs the underlying Http server.
+    fn serve(
         self,
         close_signal: tokio::sync::oneshot::Receiver<()>,
         log_close: Logger,
-    ) -> tokio::task::JoinHandle<Result<(), hyper::Error>> {
-        let graceful = self.0.with_graceful_shutdown(async move {
-            close_signal.await.expect(
-                "dropshot server shutting down without invoking close()",
-            );
-            info!(log_close, "received request to begin graceful shutdown");
-        });
-
-        tokio::spawn(async { graceful.await })
+    ) -> ServeFuture {
+        let log = self.app_state.log.clone();
+        match self.listener {
+            ServerListener::Tcp(listener) => {
+                let connections = Box::pin(count_accepted(
+                    tcp_connections(
+                        log,
+                        listener,
+                        self.app_state.config.socket_options.clone(),
+                    ),
+                    self.accepted,
+                ));
+                serve_connections(
+                    self.app_state,
+                    connections,
+                    close_signal,
+                    log_close,
+                )
+                .boxed()
+            }
+            #[cfg(unix)]
+            ServerListener::Unix(listener, socket_file) => {
+                let connections = Box::pin(unix_connections(log, listener));
+                let serve = serve_connections(
+                    self.app_state,
+                    connections,
+                    close_signal,
+                    log_close,
+                );
+                async move {
+                    let result = serve.await;
+                    drop(socket_file);
+                    result
+                }
+                .boxed()
+            }
+        }
     }
 
     /// Set up an HTTP server bound on the specified address that runs registered
@@ -244,236 +991,499 @@ impl<C: ServerContext> InnerHttpServerStarter<C> {
         api: ApiDescription<C>,
         private: C,
         log: &Logger,
-    ) -> Result<InnerHttpServerStarterNewReturn<C>, hyper::Error> {
-        let incoming = AddrIncoming::bind(&config.bind_address)?;
-        let local_addr = incoming.local_addr();
+        listener: Option<std::net::TcpListener>,
+    ) -> Result<InnerHttpServerStarterNewReturn<C>, GenericError> {
+        let listener = ServerListener::bind(config, listener)?;
+        let local_addr = listener.local_addr()?;
 
         // TODO-cleanup too many Arcs?
+        let idempotency_store = api.idempotency_store.clone();
+        let tenant_resolver = api.tenant_resolver.clone();
+        let trusted_proxies = TrustedProxies::new(&config.trusted_proxies)?;
+        let settings = ServerSettingsHandle::new(ServerSettings::new(config));
+        let error_response_customizer = api.error_response_customizer.clone();
+        let error_format = api.error_format;
+        let continue_check = api.continue_check.clone();
+        let content_decoders = ContentDecoders::new(&api.content_decoders);
+        let routes = RwLock::new(Arc::new(Routes::new(api)));
         let app_state = Arc::new(DropshotState {
             private,
             config: server_config,
-            router: api.into_router(),
-            log: log.new(o!("local_addr" => local_addr)),
+            routes,
+            log: settings
+                .filter_logger(log.new(o!("local_addr" => local_addr))),
             local_addr,
+            #[cfg(feature = "tls")]
             tls_acceptor: None,
+            #[cfg(feature = "tls")]
+            tls_config: std::sync::Mutex::new(None),
+            request_log_sampler: RequestLogSampler::new(
+                &config.request_log_sampling,
+            ),
+            connection_throttle: Throttle::new(
+                config.connection_bandwidth_limit,
+            ),
+            log_redactor: LogRedactor::new(&config.log_redaction),
+            draining: AtomicBool::new(false),
+            accepting: watch::channel(true).0,
+            in_flight_requests: AtomicUsize::new(0),
+            load_limits: LoadLimits::new(&config.load_limits),
+            acceptors: Acceptors::new(config.reuseport.as_ref()),
+            alt_svc: None,
+            maintenance: MaintenanceState::default(),
+            idempotency_store,
+            coalescer: RequestCoalescer::default(),
+            slow_request_detector: SlowRequestDetector::new(
+                &config.slow_request_logging,
+            ),
+            tenant_resolver,
+            trusted_proxies,
+            settings,
+            error_response_customizer,
+            error_format,
+            error_mapper: RwLock::new(None),
+            strict_http: StrictHttp::new(config.strict_http.as_ref()),
+            continue_check,
+            content_decoders,
+            #[cfg(feature = "compression")]
+            compressor: config
+                .compression
+                .as_ref()
+                .map(ResponseCompressor::new),
         });
 
-        let make_service = ServerConnectionHandler::new(app_state.clone());
-        let builder = hyper::Server::builder(incoming);
-        let server = builder.serve(make_service);
-        Ok((InnerHttpServerStarter(server), app_state, local_addr))
+        let starter = InnerHttpServerStarter {
+            app_state: Arc::clone(&app_state),
+            listener,
+            accepted: None,
+        };
+        Ok((starter, app_state, local_addr))
     }
 }
 
-/// Wrapper for TlsStream<TcpStream> that also carries the remote SocketAddr
-#[derive(Debug)]
-struct TlsConn {
-    stream: TlsStream<TcpStream>,
-    remote_addr: SocketAddr,
+/// Returns the TCP listener on which a server accepts connections: the one
+/// that the caller supplied, if any, or else the one that `config` calls for.
+fn tcp_listener(
+    config: &ConfigDropshot,
+    listener: Option<std::net::TcpListener>,
+) -> std::io::Result<TcpListener> {
+    let listener = match (listener, config.listen_fd) {
+        (Some(listener), _) => listener,
+        (None, Some(index)) => take_listen_fd(index)?,
+        (None, None) if config.reuseport.is_some() => {
+            return bind_reuseport(&config.bind_address, &config.socket_options)
+        }
+        (None, None) => {
+            return bind_listener(&config.bind_address, &config.socket_options)
+        }
+    };
+    configure_listener(SockRef::from(&listener), &config.socket_options)?;
+    listener.set_nonblocking(true)?;
+    TcpListener::from_std(listener)
+}
+
+/// Binds a TCP listener to `address` with the given socket options.
+fn bind_listener(
+    address: &SocketAddr,
+    options: &ConfigSocketOptions,
+) -> std::io::Result<TcpListener> {
+    // This avoids invoking an async function (`TcpListener::bind()`), to match
+    // the interface provided by `HttpServerStarter::new`.
+    listen(listener_socket(address, options)?, address)
+}
+
+/// A socket on which an HTTP server accepts connections
+enum ServerListener {
+    Tcp(TcpListener),
+    /// a Unix domain socket (see `ConfigDropshot::unix_socket`), along with
+    /// its socket file
+    #[cfg(unix)]
+    Unix(tokio::net::UnixListener, UnixSocketFile),
 }
 
-impl TlsConn {
-    fn new(stream: TlsStream<TcpStream>, remote_addr: SocketAddr) -> TlsConn {
-        TlsConn { stream, remote_addr }
+impl ServerListener {
+    /// Returns `listener`, if the caller supplied one, or else binds the
+    /// listener that `config` calls for.
+    fn bind(
+        config: &ConfigDropshot,
+        listener: Option<std::net::TcpListener>,
+    ) -> std::io::Result<ServerListener> {
+        match &config.unix_socket {
+            Some(_) if listener.is_some() => {
+                Ok(ServerListener::Tcp(tcp_listener(config, listener)?))
+            }
+            None => Ok(ServerListener::Tcp(tcp_listener(config, listener)?)),
+            #[cfg(unix)]
+            Some(unix_socket) => {
+                let (listener, socket_file) = bind_unix_listener(unix_socket)?;
+                Ok(ServerListener::Unix(listener, socket_file))
+            }
+            #[cfg(not(unix))]
+            Some(_) => Err(std::io::Error::new(
+                std::io::ErrorKind::Other,
+                "Unix domain sockets are not supported on this platform",
+            )),
+        }
     }
 
-    fn remote_addr(&self) -> SocketAddr {
-        self.remote_addr
+    fn local_addr(&self) -> std::io::Result<SocketAddr> {
+        match self {
+            ServerListener::Tcp(listener) => listener.local_addr(),
+            #[cfg(unix)]
+            ServerListener::Unix(..) => Ok(unix_socket_addr()),
+        }
     }
 }
 
-/// Forward AsyncRead to the underlying stream
-impl tokio::io::AsyncRead for TlsConn {
-    fn poll_read(
-        mut self: Pin<&mut Self>,
-        ctx: &mut core::task::Context,
-        buf: &mut ReadBuf,
-    ) -> Poll<std::io::Result<()>> {
-        let pinned = Pin::new(&mut self.stream);
-        pinned.poll_read(ctx, buf)
-    }
+/// Returns the placeholder that stands in for the addresses of a Unix domain
+/// socket, which has no IP address.
+#[cfg(unix)]
+fn unix_socket_addr() -> SocketAddr {
+    SocketAddr::from(([0, 0, 0, 0], 0))
 }
 
-/// Forward AsyncWrite to the underlying stream
-impl tokio::io::AsyncWrite for TlsConn {
-    fn poll_write(
-        mut self: Pin<&mut Self>,
-        ctx: &mut core::task::Context,
-        data: &[u8],
-    ) -> Poll<std::io::Result<usize>> {
-        let pinned = Pin::new(&mut self.stream);
-        pinned.poll_write(ctx, data)
-    }
+/// Removes a Unix domain socket's file when dropped, so that it doesn't keep
+/// another server from binding to the same path later.
+#[cfg(unix)]
+struct UnixSocketFile(std::path::PathBuf);
 
-    fn poll_flush(
-        mut self: Pin<&mut Self>,
-        ctx: &mut core::task::Context,
-    ) -> Poll<std::io::Result<()>> {
-        let pinned = Pin::new(&mut self.stream);
-        pinned.poll_flush(ctx)
+#[cfg(unix)]
+impl Drop for UnixSocketFile {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.0);
     }
+}
 
-    fn poll_shutdown(
-        mut self: Pin<&mut Self>,
-        ctx: &mut core::task::Context,
-    ) -> Poll<std::io::Result<()>> {
-        let pinned = Pin::new(&mut self.stream);
-        pinned.poll_shutdown(ctx)
+/// Binds a Unix domain socket as `config` describes.
+#[cfg(unix)]
+fn bind_unix_listener(
+    config: &ConfigUnixSocket,
+) -> std::io::Result<(tokio::net::UnixListener, UnixSocketFile)> {
+    use std::os::unix::fs::PermissionsExt;
+
+    let listener = std::os::unix::net::UnixListener::bind(&config.path)?;
+    // From here on, the socket file is removed if anything goes wrong.
+    let socket_file = UnixSocketFile(config.path.clone());
+    if let Some(mode) = config.mode {
+        std::fs::set_permissions(
+            &config.path,
+            std::fs::Permissions::from_mode(mode),
+        )?;
     }
+    if config.uid.is_some() || config.gid.is_some() {
+        chown(&config.path, config.uid, config.gid)?;
+    }
+    listener.set_nonblocking(true)?;
+    Ok((tokio::net::UnixListener::from_std(listener)?, socket_file))
 }
 
-/// This is our bridge between tokio-rustls and hyper. It implements
-/// `hyper::server::accept::Accept` interface, producing TLS-over-TCP
-/// connections.
-///
-/// Internally, it creates a stream that produces fully negotiated TLS
-/// connections as they come in from a TCP listen socket.  This stream allows
-/// for multiple TLS connections to be negotiated concurrently with new
-/// connections being accepted.
-struct HttpsAcceptor {
-    stream: Box<dyn Stream<Item = std::io::Result<TlsConn>> + Send + Unpin>,
+/// Changes the owner and/or group of the file at `path`, leaving whichever of
+/// them is `None` unchanged.
+#[cfg(unix)]
+fn chown(
+    path: &std::path::Path,
+    uid: Option<u32>,
+    gid: Option<u32>,
+) -> std::io::Result<()> {
+    use std::os::unix::ffi::OsStrExt;
+
+    let path = std::ffi::CString::new(path.as_os_str().as_bytes())?;
+    // chown(2) leaves the owner or group unchanged if it's given as -1.
+    let uid = uid.map_or(libc::uid_t::MAX, |uid| uid as libc::uid_t);
+    let gid = gid.map_or(libc::gid_t::MAX, |gid| gid as libc::gid_t);
+    // Safety: `path` is a NUL-terminated string that outlives the call.
+    if unsafe { libc::chown(path.as_ptr(), uid, gid) } != 0 {
+        return Err(std::io::Error::last_os_error());
+    }
+    Ok(())
 }
 
-impl HttpsAcceptor {
-    pub fn new(
-        log: slog::Logger,
-        tls_acceptor: Arc<Mutex<TlsAcceptor>>,
-        tcp_listener: TcpListener,
-    ) -> HttpsAcceptor {
-        HttpsAcceptor {
-            stream: Box::new(Box::pin(Self::new_stream(
-                log,
-                tls_acceptor,
-                tcp_listener,
-            ))),
+/// Returns a stream of the connections accepted by `listener`, configured with
+/// `options`, along with their remote addresses.
+fn tcp_connections(
+    log: Logger,
+    listener: TcpListener,
+    options: ConfigSocketOptions,
+) -> impl Stream<Item = std::io::Result<(TcpStream, SocketAddr)>> {
+    stream! {
+        loop {
+            match listener.accept().await {
+                Ok((stream, addr)) => {
+                    if let Err(e) = configure_connection(&stream, &options) {
+                        warn!(log, "failed to set socket options: {}", e;
+                            "remote_addr" => %addr,
+                        );
+                    }
+                    yield Ok((stream, addr));
+                }
+                // These only affect the connection being accepted.
+                Err(e) if is_connection_error(&e) => continue,
+                // Other errors (like running out of file descriptors) are
+                // likely to be transient, so rather than stopping the server,
+                // wait a bit before trying again.
+                Err(e) => {
+                    warn!(log, "accept error: {}", e);
+                    tokio::time::sleep(Duration::from_secs(1)).await;
+                }
+            }
         }
     }
+}
 
-    fn new_stream(
-        log: slog::Logger,
-        tls_acceptor: Arc<Mutex<TlsAcceptor>>,
-        tcp_listener: TcpListener,
-    ) -> impl Stream<Item = std::io::Result<TlsConn>> {
-        stream! {
-            let mut tls_negotiations = futures::stream::FuturesUnordered::new();
-            loop {
-                tokio::select! {
-                    Some(negotiation) = tls_negotiations.next(), if
-                            !tls_negotiations.is_empty() => {
-
-                        match negotiation {
-                            Ok(conn) => yield Ok(conn),
-                            Err(e) => {
-                                // If TLS negotiation fails, log the cause but
-                                // don't forward it along. Yielding an error
-                                // from here will terminate the server.
-                                // These failures may be a fatal TLS alert
-                                // message, or a client disconnection during
-                                // negotiation, or other issues.
-                                // TODO: We may want to export a counter for
-                                // different error types, since this may contain
-                                // useful things like "your certificate is
-                                // invalid"
-                                warn!(log, "tls accept err: {}", e);
-                            },
-                        }
-                    },
-                    accept_result = tcp_listener.accept() => {
-                        let (socket, addr) = match accept_result {
-                            Ok(v) => v,
-                            Err(e) => {
-                                match e.kind() {
-                                    std::io::ErrorKind::ConnectionAborted => {
-                                        continue;
-                                    },
-                                    // The other errors that can be returned
-                                    // under POSIX are all programming errors or
-                                    // resource exhaustion. For now, handle
-                                    // these by no longer accepting new
-                                    // connections.
-                                    // TODO-robustness: Consider handling these
-                                    // more gracefully.
-                                    _ => {
-                                        yield Err(e);
-                                        break;
-                                    }
-                                }
-                            }
-                        };
-
-                        let tls_negotiation = tls_acceptor
-                            .lock()
-                            .await
-                            .accept(socket)
-                            .map_ok(move |stream| TlsConn::new(stream, addr));
-                        tls_negotiations.push(tls_negotiation);
-                    },
-                    else => break,
+/// Returns a stream of the connections accepted by the Unix domain socket
+/// `listener`, each along with the placeholder for its remote address.
+#[cfg(unix)]
+fn unix_connections(
+    log: Logger,
+    listener: tokio::net::UnixListener,
+) -> impl Stream<Item = std::io::Result<(tokio::net::UnixStream, SocketAddr)>> {
+    stream! {
+        loop {
+            match listener.accept().await {
+                Ok((stream, _)) => yield Ok((stream, unix_socket_addr())),
+                Err(e) if is_connection_error(&e) => continue,
+                // As with TCP listeners, wait a bit after other errors.
+                Err(e) => {
+                    warn!(log, "accept error: {}", e);
+                    tokio::time::sleep(Duration::from_secs(1)).await;
                 }
             }
         }
     }
 }
 
-impl hyper::server::accept::Accept for HttpsAcceptor {
-    type Conn = TlsConn;
-    type Error = std::io::Error;
+fn is_connection_error(e: &std::io::Error) -> bool {
+    matches!(
+        e.kind(),
+        std::io::ErrorKind::ConnectionRefused
+            | std::io::ErrorKind::ConnectionAborted
+            | std::io::ErrorKind::ConnectionReset
+    )
+}
 
-    fn poll_accept(
-        mut self: Pin<&mut Self>,
-        ctx: &mut core::task::Context,
-    ) -> core::task::Poll<Option<Result<Self::Conn, Self::Error>>> {
-        let pinned = Pin::new(&mut self.stream);
-        pinned.poll_next(ctx)
+/// Smallest allowed value of `ConfigDropshot::max_header_bytes`, which is
+/// hyper's minimum size for the buffer into which HTTP/1 requests are read
+const MIN_HEADER_BYTES: usize = 8192;
+
+/// Returns a builder for serving connections according to `config` and the
+/// server's current `settings`.
+fn connection_builder(
+    config: &ServerConfig,
+    settings: &ServerSettings,
+) -> auto::Builder<TokioExecutor> {
+    let mut builder = auto::Builder::new(TokioExecutor::new());
+    if let Some(timeout) = settings.header_read_timeout {
+        builder.http1().timer(TokioTimer::new()).header_read_timeout(timeout);
     }
+    if let Some(max) = config.max_header_bytes {
+        builder.http1().max_buf_size(max);
+        builder
+            .http2()
+            .max_header_list_size(u32::try_from(max).unwrap_or(u32::MAX));
+    }
+    builder
 }
 
-struct InnerHttpsServerStarter<C: ServerContext>(
-    Server<HttpsAcceptor, ServerConnectionHandler<C>>,
-);
-
-/// Create a TLS configuration from the Dropshot config structure.
-// Eventually we may want to change the APIs to allow users to pass
-// a rustls::ServerConfig themselves
-impl TryFrom<&ConfigTls> for rustls::ServerConfig {
-    type Error = std::io::Error;
-
-    fn try_from(config: &ConfigTls) -> std::io::Result<Self> {
-        let certs = load_certs(&config)?;
-        let private_key = load_private_key(&config)?;
-        let mut cfg = rustls::ServerConfig::builder()
-            // TODO: We may want to expose protocol configuration in our
-            // config
-            .with_safe_default_cipher_suites()
-            .with_safe_default_kx_groups()
-            .with_safe_default_protocol_versions()
-            .unwrap()
-            .with_client_cert_verifier(rustls::server::NoClientAuth::new())
-            .with_single_cert(certs, private_key)
-            .expect("bad certificate/key");
-        cfg.alpn_protocols = vec![b"h2".to_vec(), b"http/1.1".to_vec()];
-        Ok(cfg)
+/// Serves each connection produced by `connections` on its own task until
+/// `close_signal` fires, then waits for requests that are already in progress
+/// to complete (for up to the server's drain timeout, if it has one).
+async fn serve_connections<C, S, I>(
+    server: Arc<DropshotState<C>>,
+    mut connections: S,
+    close_signal: tokio::sync::oneshot::Receiver<()>,
+    log_close: Logger,
+) -> Result<ShutdownReport, GenericError>
+where
+    C: ServerContext,
+    S: Stream<Item = std::io::Result<(I, SocketAddr)>> + Unpin,
+    I: AcceptedConnection + AsyncRead + AsyncWrite + Send + Unpin + 'static,
+{
+    let graceful = GracefulShutdown::new();
+    let mut tasks = tokio::task::JoinSet::new();
+    let mut accepting = server.accepting.subscribe();
+    tokio::pin!(close_signal);
+
+    loop {
+        // While accepting is paused, new connections wait in the listener's
+        // backlog.
+        let accept = *accepting.borrow_and_update();
+        tokio::select! {
+            result = &mut close_signal => {
+                result.expect(
+                    "dropshot server shutting down without invoking close()",
+                );
+                info!(log_close, "received request to begin graceful shutdown");
+                break;
+            }
+            // Reap the tasks of connections that have been closed.
+            Some(_) = tasks.join_next() => {}
+            // Re-check whether to accept once the server is paused or resumed.
+            _ = accepting.changed() => {}
+            conn = connections.next(), if accept => {
+                let (stream, remote_addr) = match conn {
+                    Some(conn) => conn?,
+                    None => break,
+                };
+                // Each connection is served according to the settings when
+                // it's accepted.
+                let settings = server.settings.get();
+                let open_connection = match server
+                    .load_limits
+                    .accept_connection(settings.max_connections)
+                {
+                    Some(open_connection) => open_connection,
+                    None => {
+                        warn!(server.log, "shedding connection: too many open";
+                            "remote_addr" => %remote_addr,
+                        );
+                        let shed_server = Arc::clone(&server);
+                        let service = hyper::service::service_fn(
+                            move |request: Request<Incoming>| {
+                                futures::future::ready(Ok::<_, GenericError>(
+                                    shed_connection_response(
+                                        &shed_server,
+                                        &request,
+                                    ),
+                                ))
+                            },
+                        );
+                        // Connections beyond the server's limit are served
+                        // (only) a response that sheds them, after which
+                        // they're closed.
+                        let mut shed_builder =
+                            connection_builder(&server.config, &settings);
+                        shed_builder.http1().keep_alive(false);
+                        let conn = shed_builder
+                            .serve_connection(TokioIo::new(stream), service)
+                            .into_owned();
+                        let conn = graceful.watch(conn);
+                        tasks.spawn(async move {
+                            let _ = conn.await;
+                        });
+                        continue;
+                    }
+                };
+                let connection = Arc::new(stream.connection_info(remote_addr));
+                let lifetime = ConnectionLifetime {
+                    keep_alive_timeout: settings.keep_alive_timeout,
+                    idle_timeout: settings.idle_timeout,
+                    max_requests: server.config.max_requests_per_connection,
+                };
+                let (stream, requests) =
+                    keep_alive::wrap(stream, lifetime);
+                let (stream, early_hints) =
+                    early_hints::wrap(server.strict_http.wrap(stream));
+                let handler = http_connection_handle(
+                    Arc::clone(&server),
+                    connection,
+                    early_hints,
+                    requests,
+                );
+                let conn = connection_builder(&server.config, &settings)
+                    .serve_connection_with_upgrades(TokioIo::new(stream), handler)
+                    .into_owned();
+                let conn = graceful.watch(conn);
+                let log = server.log.clone();
+                tasks.spawn(async move {
+                    let _open_connection = open_connection;
+                    if let Err(e) = conn.await {
+                        debug!(log, "connection error";
+                            "remote_addr" => %remote_addr,
+                            "error" => %e,
+                        );
+                    }
+                });
+            }
+        }
     }
+
+    // Stop accepting connections before waiting for the open ones to finish.
+    drop(connections);
+    let drained = match server.settings.get().drain_timeout {
+        None => {
+            graceful.shutdown().await;
+            true
+        }
+        Some(timeout) => {
+            tokio::time::timeout(timeout, graceful.shutdown()).await.is_ok()
+        }
+    };
+    let mut report = ShutdownReport::default();
+    if !drained {
+        report.aborted_requests =
+            server.in_flight_requests.load(Ordering::SeqCst);
+        warn!(log_close, "closing connections at drain deadline";
+            "aborted_requests" => report.aborted_requests,
+        );
+        tasks.shutdown().await;
+    }
+    Ok(report)
+}
+
+/// Describes how a server's shutdown went (see
+/// [`HttpServer::close_with_report()`])
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct ShutdownReport {
+    /// number of requests that were still in progress when the drain timeout
+    /// (`ConfigDropshot::drain_timeout_ms`) elapsed, and whose connections
+    /// were closed without a response
+    pub aborted_requests: usize,
 }
 
+#[cfg(feature = "tls")]
+struct InnerHttpsServerStarter<C: ServerContext> {
+    app_state: Arc<DropshotState<C>>,
+    listener: TcpListener,
+    tls_acceptor: Arc<Mutex<TlsAcceptor>>,
+    /// counts the connections accepted, if the listener is one of several
+    /// sockets bound with `SO_REUSEPORT`
+    accepted: Option<Arc<AtomicU64>>,
+    tls_reload_interval: Option<Duration>,
+    #[cfg(feature = "http3")]
+    http3: Option<Http3Listener>,
+}
+
+#[cfg(feature = "tls")]
 type InnerHttpsServerStarterNewReturn<C> =
     (InnerHttpsServerStarter<C>, Arc<DropshotState<C>>, SocketAddr);
 
+#[cfg(feature = "tls")]
 impl<C: ServerContext> InnerHttpsServerStarter<C> {
-    /// Begins execution of the underlying Http server.
-    fn start(
+    /// Returns a future that runs the underlying Http server.
+    fn serve(
         self,
         close_signal: tokio::sync::oneshot::Receiver<()>,
         log_close: Logger,
-    ) -> tokio::task::JoinHandle<Result<(), hyper::Error>> {
-        let graceful = self.0.with_graceful_shutdown(async move {
-            close_signal.await.expect(
-                "dropshot server shutting down without invoking close()",
-            );
-            info!(log_close, "received request to begin graceful shutdown");
-        });
-
-        tokio::spawn(async { graceful.await })
+    ) -> ServeFuture {
+        let log = self.app_state.log.clone();
+        if let Some(interval) = self.tls_reload_interval {
+            tokio::spawn(watch_tls_files(
+                Arc::downgrade(&self.app_state),
+                interval,
+            ));
+        }
+        let connections = Box::pin(count_accepted(
+            tls_connections(
+                log,
+                self.tls_acceptor,
+                self.listener,
+                self.app_state.config.socket_options.clone(),
+            ),
+            self.accepted,
+        ));
+        #[cfg(feature = "http3")]
+        if let Some(http3) = self.http3 {
+            return serve_with_http3(
+                self.app_state,
+                connections,
+                http3,
+                close_signal,
+                log_close,
+            )
+            .boxed();
+        }
+        serve_connections(self.app_state, connections, close_signal, log_close)
+            .boxed()
     }
 
     fn new(
@@ -482,6 +1492,7 @@ impl<C: ServerContext> InnerHttpsServerStarter<C> {
         api: ApiDescription<C>,
         private: C,
         log: &Logger,
+        listener: Option<std::net::TcpListener>,
     ) -> Result<InnerHttpsServerStarterNewReturn<C>, GenericError> {
         let acceptor = Arc::new(Mutex::new(TlsAcceptor::from(Arc::new(
             // Unwrap is safe here because we cannot enter this code path
@@ -489,65 +1500,179 @@ impl<C: ServerContext> InnerHttpsServerStarter<C> {
             rustls::ServerConfig::try_from(config.tls.as_ref().unwrap())?,
         ))));
 
-        let tcp = {
-            let listener = std::net::TcpListener::bind(&config.bind_address)?;
-            listener.set_nonblocking(true)?;
-            // We use `from_std` instead of just calling `bind` here directly
-            // to avoid invoking an async function, to match the interface
-            // provided by `HttpServerStarter::new`.
-            TcpListener::from_std(listener)?
-        };
-
-        let local_addr = tcp.local_addr()?;
+        let listener = tcp_listener(config, listener)?;
+        let local_addr = listener.local_addr()?;
         let logger = log.new(o!("local_addr" => local_addr));
-        let https_acceptor =
-            HttpsAcceptor::new(logger.clone(), acceptor.clone(), tcp);
 
+        #[cfg(feature = "http3")]
+        let (http3, alt_svc) = match &config.http3 {
+            Some(http3_config) => {
+                let http3 = Http3Listener::bind(
+                    config.tls.as_ref().unwrap(),
+                    http3_config.bind_address.unwrap_or(local_addr),
+                )?;
+                let alt_svc =
+                    http3.alt_svc(http3_config.alt_svc_max_age_secs)?;
+                (Some(http3), Some(alt_svc))
+            }
+            None => (None, None),
+        };
+        #[cfg(not(feature = "http3"))]
+        let alt_svc = None;
+
+        let idempotency_store = api.idempotency_store.clone();
+        let tenant_resolver = api.tenant_resolver.clone();
+        let trusted_proxies = TrustedProxies::new(&config.trusted_proxies)?;
+        let settings = ServerSettingsHandle::new(ServerSettings::new(config));
+        let error_response_customizer = api.error_response_customizer.clone();
+        let error_format = api.error_format;
+        let continue_check = api.continue_check.clone();
+        let content_decoders = ContentDecoders::new(&api.content_decoders);
+        let routes = RwLock::new(Arc::new(Routes::new(api)));
         let app_state = Arc::new(DropshotState {
             private,
             config: server_config,
-            router: api.into_router(),
-            log: logger,
+            routes,
+            log: settings.filter_logger(logger),
             local_addr,
-            tls_acceptor: Some(acceptor),
+            tls_acceptor: Some(Arc::clone(&acceptor)),
+            tls_config: std::sync::Mutex::new(config.tls.clone()),
+            request_log_sampler: RequestLogSampler::new(
+                &config.request_log_sampling,
+            ),
+            connection_throttle: Throttle::new(
+                config.connection_bandwidth_limit,
+            ),
+            log_redactor: LogRedactor::new(&config.log_redaction),
+            draining: AtomicBool::new(false),
+            accepting: watch::channel(true).0,
+            in_flight_requests: AtomicUsize::new(0),
+            load_limits: LoadLimits::new(&config.load_limits),
+            acceptors: Acceptors::new(config.reuseport.as_ref()),
+            alt_svc,
+            maintenance: MaintenanceState::default(),
+            idempotency_store,
+            coalescer: RequestCoalescer::default(),
+            slow_request_detector: SlowRequestDetector::new(
+                &config.slow_request_logging,
+            ),
+            tenant_resolver,
+            trusted_proxies,
+            settings,
+            error_response_customizer,
+            error_format,
+            error_mapper: RwLock::new(None),
+            strict_http: StrictHttp::new(config.strict_http.as_ref()),
+            continue_check,
+            content_decoders,
+            #[cfg(feature = "compression")]
+            compressor: config
+                .compression
+                .as_ref()
+                .map(ResponseCompressor::new),
         });
 
-        let make_service = ServerConnectionHandler::new(Arc::clone(&app_state));
-        let server = Server::builder(https_acceptor).serve(make_service);
-
-        Ok((InnerHttpsServerStarter(server), app_state, local_addr))
+        let starter = InnerHttpsServerStarter {
+            app_state: Arc::clone(&app_state),
+            listener,
+            tls_acceptor: acceptor,
+            accepted: None,
+            tls_reload_interval: config
+                .tls_reload_interval_ms
+                .map(Duration::from_millis),
+            #[cfg(feature = "http3")]
+            http3,
+        };
+        Ok((starter, app_state, local_addr))
     }
 }
 
-impl<C: ServerContext> Service<&TlsConn> for ServerConnectionHandler<C> {
-    type Response = ServerRequestHandler<C>;
-    type Error = GenericError;
-    type Future = BoxFuture<'static, Result<Self::Response, Self::Error>>;
-
-    fn poll_ready(
-        &mut self,
-        _ctx: &mut Context<'_>,
-    ) -> Poll<Result<(), Self::Error>> {
-        Poll::Ready(Ok(()))
-    }
+/// Serves connections from both the TCP listener (`connections`) and `http3`
+/// until `close_signal` fires, then drains both (see `serve_connections()`).
+#[cfg(feature = "http3")]
+async fn serve_with_http3<C, S, I>(
+    server: Arc<DropshotState<C>>,
+    connections: S,
+    http3: Http3Listener,
+    close_signal: tokio::sync::oneshot::Receiver<()>,
+    log_close: Logger,
+) -> Result<ShutdownReport, GenericError>
+where
+    C: ServerContext,
+    S: Stream<Item = std::io::Result<(I, SocketAddr)>> + Unpin,
+    I: AcceptedConnection + AsyncRead + AsyncWrite + Send + Unpin + 'static,
+{
+    let (tcp_close, tcp_close_signal) = tokio::sync::oneshot::channel();
+    let (http3_close, http3_close_signal) = tokio::sync::oneshot::channel();
+    // If the server is dropped without being closed, dropping these senders
+    // passes that along, too.
+    let forward_close = async move {
+        if close_signal.await.is_ok() {
+            let _ = tcp_close.send(());
+            let _ = http3_close.send(());
+        }
+        Ok::<(), GenericError>(())
+    };
+    let ((), tcp, http3) = tokio::try_join!(
+        forward_close,
+        serve_connections(
+            Arc::clone(&server),
+            connections,
+            tcp_close_signal,
+            log_close,
+        ),
+        http3.serve(Arc::clone(&server), http3_close_signal),
+    )?;
+    // Both count all of the server's in-flight requests, whichever listener
+    // they arrived on.
+    Ok(ShutdownReport {
+        aborted_requests: tcp.aborted_requests.max(http3.aborted_requests),
+    })
+}
 
-    fn call(&mut self, conn: &TlsConn) -> Self::Future {
-        let server = Arc::clone(&self.server);
-        let remote_addr = conn.remote_addr();
-        Box::pin(http_connection_handle(server, remote_addr))
+/// Reloads the server's TLS certificates whenever the files named by its TLS
+/// configuration change, checking every `interval` until the server shuts down
+/// (see `ConfigDropshot::tls_reload_interval_ms`).
+#[cfg(feature = "tls")]
+async fn watch_tls_files<C: ServerContext>(
+    server: std::sync::Weak<DropshotState<C>>,
+    interval: Duration,
+) {
+    let mut times = None;
+    loop {
+        let server = match server.upgrade() {
+            Some(server) if !server.is_draining() => server,
+            _ => return,
+        };
+        let config = server.tls_config.lock().unwrap().clone();
+        let new_times = config.as_ref().map(tls_file_times);
+        if times.is_some() && new_times != times {
+            match server.reload_tls().await {
+                Ok(()) => info!(server.log, "reloaded TLS certificates"),
+                Err(error) => warn!(server.log,
+                    "failed to reload TLS certificates";
+                    "error" => error,
+                ),
+            }
+        }
+        // If the reload failed (e.g., because only some of the files had
+        // been replaced), the next change will trigger another.
+        times = new_times;
+        drop(server);
+        tokio::time::sleep(interval).await;
     }
 }
 
 type SharedBoxFuture<T> = Shared<Pin<Box<dyn Future<Output = T> + Send>>>;
 
 /// Future returned by [`Server::wait_for_shutdown()`].
-pub struct ShutdownWaitFuture(SharedBoxFuture<Result<(), String>>);
+pub struct ShutdownWaitFuture(SharedBoxFuture<Result<ShutdownReport, String>>);
 
 impl Future for ShutdownWaitFuture {
     type Output = Result<(), String>;
 
     fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
-        Pin::new(&mut self.get_mut().0).poll(cx)
+        Pin::new(&mut self.get_mut().0).poll(cx).map_ok(|_| ())
     }
 }
 
@@ -565,8 +1690,10 @@ pub struct HttpServer<C: ServerContext> {
     probe_registration: ProbeRegistration,
     app_state: Arc<DropshotState<C>>,
     local_addr: SocketAddr,
+    additional_local_addrs: Vec<SocketAddr>,
     closer: CloseHandle,
-    join_future: SharedBoxFuture<Result<(), String>>,
+    join_future: SharedBoxFuture<Result<ShutdownReport, String>>,
+    admin: Option<Box<dyn AdminServer>>,
 }
 
 // Handle used to trigger the shutdown of an [HttpServer].
@@ -579,6 +1706,19 @@ impl<C: ServerContext> HttpServer<C> {
         self.local_addr
     }
 
+    /// Returns the addresses of the server's additional listeners (see
+    /// `ConfigDropshot::additional_listeners`), in the order in which they
+    /// were configured.
+    pub fn additional_local_addrs(&self) -> &[SocketAddr] {
+        &self.additional_local_addrs
+    }
+
+    /// Returns the address of the admin listener, if one was configured with
+    /// [`HttpServerStarter::admin_listener()`].
+    pub fn admin_local_addr(&self) -> Option<SocketAddr> {
+        self.admin.as_ref().map(|admin| admin.local_addr())
+    }
+
     pub fn app_private(&self) -> &C {
         &self.app_state.private
     }
@@ -587,18 +1727,155 @@ impl<C: ServerContext> HttpServer<C> {
         self.app_state.using_tls()
     }
 
-    /// Update TLS certificates for a running HTTPS server.
+    /// Update TLS certificates for a running HTTPS server.  Connections that
+    /// have already been established are unaffected.  If the new certificates
+    /// can't be loaded, the server keeps using the ones it has.
+    #[cfg(feature = "tls")]
     pub async fn refresh_tls(&self, config: &ConfigTls) -> Result<(), String> {
-        let acceptor = &self
-            .app_state
-            .tls_acceptor
-            .as_ref()
-            .ok_or_else(|| "Not configured for TLS".to_string())?;
+        self.app_state.refresh_tls(config).await
+    }
 
-        *acceptor.lock().await = TlsAcceptor::from(Arc::new(
-            rustls::ServerConfig::try_from(config).unwrap(),
-        ));
-        Ok(())
+    /// Reloads the TLS certificates of a running HTTPS server from the files
+    /// named by its TLS configuration (the one it was started with, or the
+    /// one most recently passed to [`HttpServer::refresh_tls()`]), e.g., when
+    /// the process receives `SIGHUP`.  As with `refresh_tls()`, established
+    /// connections are unaffected, and if the certificates can't be loaded,
+    /// the server keeps using the ones it has.  See also
+    /// `ConfigDropshot::tls_reload_interval_ms`.
+    #[cfg(feature = "tls")]
+    pub async fn reload_tls(&self) -> Result<(), String> {
+        self.app_state.reload_tls().await
+    }
+
+    /// Override the level used for request-scoped log entries for the endpoint
+    /// registered with the given method and path (e.g., `"/projects/{id}"`).
+    /// This affects both the entries Dropshot emits for each request and those
+    /// emitted by the handler through `RequestContext::log`.  A `level` of
+    /// `None` removes any override, including one specified with the
+    /// endpoint's `log_level`.
+    ///
+    /// The override replaces the server's level for these entries, so it can
+    /// make them more verbose (e.g., `Debug` for one endpoint of a server
+    /// logging at `Info`) as long as the server's logger filters by level with
+    /// a [`RequestLevelFilter`](crate::RequestLevelFilter), as those created
+    /// by [`ConfigLogging::to_logger()`](crate::ConfigLogging::to_logger) do.
+    pub fn set_endpoint_log_level(
+        &self,
+        method: &http::Method,
+        path: &str,
+        level: Option<ConfigLoggingLevel>,
+    ) -> Result<(), String> {
+        self.app_state.set_endpoint_log_level(method, path, level)
+    }
+
+    /// Returns the router with which the server is currently routing
+    /// requests.  Its endpoints can be listed with
+    /// [`HttpRouter::endpoints()`].
+    pub fn router(&self) -> Arc<HttpRouter<C>> {
+        self.app_state.router()
+    }
+
+    /// Replaces the endpoints that the server serves with those of `api`
+    /// (e.g., to add or remove routes provided by plugins).  The change is
+    /// atomic: each request is routed either with the old endpoints or with
+    /// the new ones, and requests that are already in progress complete
+    /// normally.
+    ///
+    /// The state of each endpoint comes from `api` as well, including its
+    /// hooks, log level, and bandwidth limit, so changes made with
+    /// [`HttpServer::set_endpoint_log_level()`] and
+    /// [`HttpServer::set_endpoint_bandwidth_limit()`] are discarded.  The
+    /// server-wide settings of `api` (e.g., its tenant resolver, idempotency
+    /// store, and error format) are ignored: the ones that the server was
+    /// started with still apply.
+    pub fn replace_api(&self, api: ApiDescription<C>) {
+        self.app_state.replace_api(api)
+    }
+
+    /// Stops accepting new connections on the server's listeners until
+    /// [`HttpServer::resume_accepting()`] is called.  Connections that are
+    /// already open continue to be served, so requests in progress complete.
+    /// Clients that connect in the meantime wait in the listeners' backlogs
+    /// (and may time out or be refused once a backlog fills).
+    pub fn pause_accepting(&self) {
+        self.app_state.set_accepting(false);
+    }
+
+    /// Resumes accepting new connections after
+    /// [`HttpServer::pause_accepting()`].
+    pub fn resume_accepting(&self) {
+        self.app_state.set_accepting(true);
+    }
+
+    /// Returns whether the server is accepting new connections (i.e., it's
+    /// not paused with [`HttpServer::pause_accepting()`]).
+    pub fn is_accepting(&self) -> bool {
+        *self.app_state.accepting.borrow()
+    }
+
+    /// Puts the server into maintenance mode (or takes it out of maintenance
+    /// mode, if `mode` is `None`).  While in maintenance mode, requests to
+    /// endpoints that aren't exempt fail with a 503 ("Service Unavailable")
+    /// response as described by `mode`.  Requests already in progress are not
+    /// affected.
+    pub fn set_maintenance_mode(&self, mode: Option<MaintenanceMode>) {
+        self.app_state.set_maintenance_mode(mode)
+    }
+
+    /// Returns the current maintenance mode, if the server is in maintenance
+    /// mode.
+    pub fn maintenance_mode(&self) -> Option<MaintenanceMode> {
+        self.app_state.maintenance.get()
+    }
+
+    /// Returns the number of requests that have been rejected by strict HTTP
+    /// parsing (see `ConfigDropshot::strict_http`), by reason.
+    pub fn strict_http_rejections(&self) -> StrictHttpRejections {
+        self.app_state.strict_http.rejections()
+    }
+
+    /// Returns a handle with which the server's settings (e.g., its request
+    /// body size limit, timeouts, log level, and load limits) can be read and
+    /// changed while it runs.
+    pub fn settings(&self) -> ServerSettingsHandle {
+        self.app_state.settings.clone()
+    }
+
+    /// Returns statistics for each of the sockets that the server accepts
+    /// connections on when it's configured with `ConfigDropshot::reuseport`.
+    /// This is empty otherwise.
+    pub fn acceptor_stats(&self) -> Vec<AcceptorStats> {
+        self.app_state.acceptors.stats()
+    }
+
+    /// Limits the rate (in bytes per second) at which response bodies are
+    /// sent on each connection, or removes the limit if `bytes_per_sec` is
+    /// `None`.  Each connection is limited separately.  The new limit applies
+    /// to existing connections, but not to responses that were already being
+    /// sent without any limit.
+    pub fn set_connection_bandwidth_limit(&self, bytes_per_sec: Option<u64>) {
+        self.app_state.connection_throttle.set_rate(bytes_per_sec);
+    }
+
+    /// Returns the per-connection response bandwidth limit, in bytes per
+    /// second.
+    pub fn connection_bandwidth_limit(&self) -> Option<u64> {
+        self.app_state.connection_throttle.rate()
+    }
+
+    /// Limits the combined rate (in bytes per second) at which the response
+    /// bodies of the endpoint registered with the given method and path are
+    /// sent, or removes the limit if `bytes_per_sec` is `None`.  This
+    /// overrides the endpoint's `bandwidth_limit`.  As with
+    /// [`HttpServer::set_connection_bandwidth_limit()`], responses that were
+    /// already being sent without any limit are not affected.
+    pub fn set_endpoint_bandwidth_limit(
+        &self,
+        method: &http::Method,
+        path: &str,
+        bytes_per_sec: Option<u64>,
+    ) -> Result<(), String> {
+        self.app_state.set_endpoint_bandwidth_limit(method, path, bytes_per_sec)
     }
 
     /// Return the result of registering the server's DTrace USDT probes.
@@ -619,14 +1896,55 @@ impl<C: ServerContext> HttpServer<C> {
     }
 
     /// Signals the currently running server to stop and waits for it to exit.
-    pub async fn close(mut self) -> Result<(), String> {
+    ///
+    /// The readiness endpoint (see [`crate::health`]) fails from the moment
+    /// this is called.  The admin listener, if there is one, is closed once
+    /// this server has shut down, so it remains available while the server
+    /// drains.
+    pub async fn close(self) -> Result<(), String> {
+        self.close_with_report().await.map(|_| ())
+    }
+
+    /// Shuts the server down like [`HttpServer::close()`], and reports how
+    /// many requests were aborted.
+    ///
+    /// The server stops accepting connections, and responses sent on HTTP/1
+    /// connections from then on carry `Connection: close`, so that clients
+    /// don't send more requests on them.  The server then waits for the
+    /// requests in progress to complete.  If `ConfigDropshot::drain_timeout_ms`
+    /// is set, the connections of requests that are still in progress once it
+    /// has elapsed are closed, and those requests are counted in the report.
+    /// (The handlers of such requests still run to completion if the server's
+    /// `handler_task_mode` is `Detached`, but their responses are discarded.)
+    pub async fn close_with_report(mut self) -> Result<ShutdownReport, String> {
+        self.app_state.draining.store(true, Ordering::SeqCst);
         self.closer
             .close_channel
             .take()
             .expect("cannot close twice")
             .send(())
             .expect("failed to send close signal");
-        self.join_future.await
+        let result = (&mut self.join_future).await;
+        match self.admin.take() {
+            Some(admin) => {
+                let admin_result = admin
+                    .close()
+                    .await
+                    .map_err(|e| format!("admin listener: {e}"));
+                result.and_then(|report| admin_result.map(|()| report))
+            }
+            None => result,
+        }
+    }
+}
+
+impl<C: ServerContext> AdminServer for HttpServer<C> {
+    fn local_addr(&self) -> SocketAddr {
+        self.local_addr
+    }
+
+    fn close(self: Box<Self>) -> BoxFuture<'static, Result<(), String>> {
+        HttpServer::close(*self).boxed()
     }
 }
 
@@ -648,7 +1966,7 @@ impl<C: ServerContext> Future for HttpServer<C> {
     fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
         let server = Pin::into_inner(self);
         let join_future = Pin::new(&mut server.join_future);
-        join_future.poll(cx)
+        join_future.poll(cx).map_ok(|_| ())
     }
 }
 
@@ -662,35 +1980,93 @@ impl<C: ServerContext> FusedFuture for HttpServer<C> {
 /// This is invoked by Hyper when a new connection is accepted.  This function
 /// must return a Hyper Service object that will handle requests for this
 /// connection.
-async fn http_connection_handle<C: ServerContext>(
+fn http_connection_handle<C: ServerContext>(
     server: Arc<DropshotState<C>>,
-    remote_addr: SocketAddr,
-) -> Result<ServerRequestHandler<C>, GenericError> {
-    info!(server.log, "accepted connection"; "remote_addr" => %remote_addr);
-    Ok(ServerRequestHandler::new(server, remote_addr))
+    connection: Arc<ConnectionInfo>,
+    early_hints: EarlyHintsSender,
+    requests: ConnectionRequests,
+) -> ServerRequestHandler<C> {
+    let connection_id = generate_connection_id();
+    info!(server.log, "accepted connection";
+        "remote_addr" => %connection.remote_addr(),
+        "local_addr" => %connection.local_addr(),
+        "tls" => connection.is_tls(),
+        "conn_id" => &connection_id,
+    );
+    let throttle = server.connection_throttle.with_new_bucket();
+    ServerRequestHandler::new(
+        server,
+        connection,
+        connection_id,
+        throttle,
+        early_hints,
+        requests,
+    )
+}
+
+/// Returns the response to each request on a connection accepted beyond the
+/// server's limit (see `ConfigLoadLimits::max_connections`), which also asks
+/// the client to close the connection.
+fn shed_connection_response<C: ServerContext>(
+    server: &DropshotState<C>,
+    request: &Request<Incoming>,
+) -> Response<Body> {
+    let mut response = server.load_limits.overloaded_response(
+        server.error_format,
+        &generate_request_id(),
+        request.uri().path(),
+    );
+    response.headers_mut().insert(
+        http::header::CONNECTION,
+        http::HeaderValue::from_static("close"),
+    );
+    response
 }
 
 /// Initial entry point for handling a new request to the HTTP server.  This is
 /// invoked by Hyper when a new request is received.  This function returns a
 /// Result that either represents a valid HTTP response or an error (which will
 /// also get turned into an HTTP response).
-async fn http_request_handle_wrap<C: ServerContext>(
+pub(crate) async fn http_request_handle_wrap<C: ServerContext>(
     server: Arc<DropshotState<C>>,
-    remote_addr: SocketAddr,
+    connection: Arc<ConnectionInfo>,
+    connection_id: String,
+    connection_throttle: Throttle,
     request: Request<Body>,
 ) -> Result<Response<Body>, GenericError> {
     // This extra level of indirection makes error handling much more
     // straightforward, since the request handling code can simply return early
     // with an error and we'll treat it like an error from any of the endpoints
     // themselves.
+    let timings = Arc::new(RequestTimings::new(Instant::now()));
+    let in_flight = InFlightRequest::new(Arc::clone(&server));
+    let version = request.version();
+    #[cfg(feature = "compression")]
+    let method = request.method().clone();
+    #[cfg(feature = "compression")]
+    let coding = server
+        .compressor
+        .as_ref()
+        .and_then(|compressor| compressor.negotiate(request.headers()));
     let request_id = generate_request_id();
-    let request_log = server.log.new(o!(
+    let remote_addr = connection.remote_addr();
+    let client = server.trusted_proxies.client(&connection, request.headers());
+    let mut request_log = server.log.new(o!(
         "remote_addr" => remote_addr,
+        "conn_id" => connection_id,
         "req_id" => request_id.clone(),
         "method" => request.method().as_str().to_string(),
-        "uri" => format!("{}", request.uri()),
+        "uri" => server.log_redactor.redact_uri(request.uri()),
     ));
-    trace!(request_log, "incoming request");
+    if client.is_forwarded() {
+        request_log = request_log.new(o!(
+            "client_addr" => client.addr().to_string(),
+            "client_scheme" => client.scheme().to_string(),
+        ));
+    }
+    trace!(request_log, "incoming request";
+        "headers" => ?server.log_redactor.redact_headers(request.headers()),
+    );
     #[cfg(feature = "usdt-probes")]
     probes::request__start!(|| {
         let uri = request.uri();
@@ -709,19 +2085,68 @@ async fn http_request_handle_wrap<C: ServerContext>(
     #[cfg(feature = "usdt-probes")]
     let local_addr = server.local_addr;
 
+    // Likewise, hang onto the server state itself so that we can decide
+    // whether to log the completed request.
+    let server_ref = Arc::clone(&server);
+    let mut handled = HandledRequest::<C>::default();
+    // Error responses identify the request by its path.
+    let request_path = request.uri().path().to_string();
+
     let maybe_response = http_request_handle(
         server,
         request,
+        connection,
+        client,
         &request_id,
-        request_log.new(o!()),
+        &timings,
+        &mut request_log,
+        &mut handled,
     )
     .await;
 
+    let error_mapper = server_ref.error_mapper.read().unwrap().clone();
+    let maybe_response = match (maybe_response, error_mapper) {
+        (Err(error), Some(error_mapper)) => {
+            Err(error_mapper.map_error(error, handled.request_context.as_ref()))
+        }
+        (maybe_response, _) => maybe_response,
+    };
+
     let response = match maybe_response {
         Err(error) => {
+            let error = if server_ref.config.redact_server_errors
+                && error.status_code.is_server_error()
+            {
+                error!(request_log, "redacted server error";
+                    "error_message_internal" => &error.internal_message,
+                    "error_message_external" => &error.external_message,
+                    "error_code" => ?error.error_code,
+                );
+                redact_server_error(error, &request_id)
+            } else {
+                error
+            };
             let message_external = error.external_message.clone();
             let message_internal = error.internal_message.clone();
-            let r = error.into_response(&request_id);
+            let generated_error = handled.generated_error.or_else(|| {
+                timings
+                    .stopped_in_extraction()
+                    .then(|| GeneratedErrorKind::ExtractorFailure)
+            });
+            let mut r = error_response(
+                server_ref.error_response_customizer.as_deref(),
+                generated_error,
+                error,
+                server_ref.error_format,
+                &request_id,
+                &request_path,
+            );
+            if let Some(allow) = handled.allow.take() {
+                r.headers_mut().insert(
+                    http::header::ALLOW,
+                    http::HeaderValue::from_str(&allow).unwrap(),
+                );
+            }
 
             #[cfg(feature = "usdt-probes")]
             probes::request__done!(|| {
@@ -735,20 +2160,30 @@ async fn http_request_handle_wrap<C: ServerContext>(
             });
 
             // TODO-debug: add request and response headers here
-            info!(request_log, "request completed";
-                "response_code" => r.status().as_str().to_string(),
-                "error_message_internal" => message_internal,
-                "error_message_external" => message_external,
-            );
+            if server_ref
+                .request_log_sampler
+                .should_log(handled.route.as_deref(), r.status())
+            {
+                info!(request_log, "request completed";
+                    "response_code" => r.status().as_str().to_string(),
+                    "error_message_internal" => message_internal,
+                    "error_message_external" => message_external,
+                );
+            }
 
             r
         }
 
         Ok(response) => {
             // TODO-debug: add request and response headers here
-            info!(request_log, "request completed";
-                "response_code" => response.status().as_str().to_string()
-            );
+            if server_ref
+                .request_log_sampler
+                .should_log(handled.route.as_deref(), response.status())
+            {
+                info!(request_log, "request completed";
+                    "response_code" => response.status().as_str().to_string()
+                );
+            }
 
             #[cfg(feature = "usdt-probes")]
             probes::request__done!(|| {
@@ -765,14 +2200,185 @@ async fn http_request_handle_wrap<C: ServerContext>(
         }
     };
 
+    #[cfg(feature = "compression")]
+    let response = match &server_ref.compressor {
+        Some(compressor) if handled.compression => {
+            compressor.compress(&method, response, coding)
+        }
+        _ => response,
+    };
+
+    let throttles = handled
+        .throttle
+        .take()
+        .into_iter()
+        .chain(std::iter::once(connection_throttle))
+        .filter(|throttle| throttle.is_limited())
+        .collect::<Vec<_>>();
+    let response = if throttles.is_empty() {
+        response
+    } else {
+        map_response_body(response, |body, _| throttle_body(body, throttles))
+    };
+
+    let threshold = handled
+        .route
+        .as_deref()
+        .and_then(|route| server_ref.slow_request_detector.threshold(route));
+    let mut response = match threshold {
+        None => response,
+        Some(threshold) => map_response_body(response, |body, status| {
+            TimedBody::wrap(body, request_log, timings, threshold, status)
+        }),
+    };
+
+    // Tell clients of the TCP listener that they can switch to HTTP/3.
+    if let Some(alt_svc) = &server_ref.alt_svc {
+        if version < http::Version::HTTP_3 {
+            response
+                .headers_mut()
+                .insert(http::header::ALT_SVC, alt_svc.clone());
+        }
+    }
+
+    // Ask clients not to send more requests on a connection that's about to
+    // be closed.  (HTTP/2 connections are closed with a GOAWAY frame
+    // instead.)
+    if server_ref.is_draining() && version < http::Version::HTTP_2 {
+        response.headers_mut().insert(
+            http::header::CONNECTION,
+            http::HeaderValue::from_static("close"),
+        );
+    }
+
+    drop(in_flight);
     Ok(response)
 }
 
+/// Counts a request as in progress (see `DropshotState::in_flight_requests`)
+/// until it's dropped
+struct InFlightRequest<C: ServerContext> {
+    server: Arc<DropshotState<C>>,
+}
+
+impl<C: ServerContext> InFlightRequest<C> {
+    fn new(server: Arc<DropshotState<C>>) -> Self {
+        server.in_flight_requests.fetch_add(1, Ordering::SeqCst);
+        InFlightRequest { server }
+    }
+}
+
+impl<C: ServerContext> Drop for InFlightRequest<C> {
+    fn drop(&mut self) {
+        self.server.in_flight_requests.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+/// Replaces the body of `response` with the one returned by `wrap` (which is
+/// given the original body and the response's status code).
+fn map_response_body<F>(response: Response<Body>, wrap: F) -> Response<Body>
+where
+    F: FnOnce(Body, StatusCode) -> Body,
+{
+    let (mut parts, body) = response.into_parts();
+    // Wrapping the body hides its length from hyper, so preserve it.
+    if let Some(len) = HttpBody::size_hint(&body).exact() {
+        if !parts.headers.contains_key(http::header::CONTENT_LENGTH)
+            && !parts.status.is_informational()
+            && parts.status != StatusCode::NO_CONTENT
+            && parts.status != StatusCode::NOT_MODIFIED
+        {
+            parts.headers.insert(http::header::CONTENT_LENGTH, len.into());
+        }
+    }
+    let body = wrap(body, parts.status);
+    Response::from_parts(parts, body)
+}
+
+/// Header with which a `POST` request can name the method it should be handled
+/// as (see `ConfigDropshot::method_override`)
+const METHOD_OVERRIDE_HEADER: &str = "x-http-method-override";
+
+/// Replaces the method of a `POST` request with the one named by its
+/// `X-HTTP-Method-Override` header, if it has one, provided that it's one of
+/// `methods`.
+fn override_method(
+    methods: &[http::Method],
+    request: &mut Request<Body>,
+    request_log: &mut Logger,
+) -> Result<(), HttpError> {
+    if request.method() != http::Method::POST {
+        return Ok(());
+    }
+    let value = match request.headers().get(METHOD_OVERRIDE_HEADER) {
+        Some(value) => value,
+        None => return Ok(()),
+    };
+    let method = value
+        .to_str()
+        .ok()
+        .and_then(|value| {
+            http::Method::from_bytes(value.trim().to_uppercase().as_bytes())
+                .ok()
+        })
+        .filter(|method| methods.contains(method))
+        .ok_or_else(|| {
+            HttpError::for_bad_request(
+                None,
+                format!(
+                    "method may not be overridden to {:?}",
+                    String::from_utf8_lossy(value.as_bytes())
+                ),
+            )
+        })?;
+    *request_log =
+        request_log.new(o!("method_override" => method.as_str().to_string()));
+    info!(request_log, "method overridden");
+    *request.method_mut() = method;
+    Ok(())
+}
+
+/// What's learned about a request while handling it that's needed once it's
+/// been handled
+struct HandledRequest<C: ServerContext> {
+    /// path template of the route that the request matched
+    route: Option<String>,
+    /// kind of the error that Dropshot generated for the request (as opposed
+    /// to one returned by its handler), if any
+    generated_error: Option<GeneratedErrorKind>,
+    /// whether the response may be compressed
+    compression: bool,
+    /// context of the request, kept for the server's error mapper (if it has
+    /// one) once the request has been routed
+    request_context: Option<RequestContext<C>>,
+    /// value of the `Allow` header for a `405 Method Not Allowed` response
+    allow: Option<String>,
+    /// bandwidth limit of the endpoint that the request was routed to
+    throttle: Option<Throttle>,
+}
+
+impl<C: ServerContext> Default for HandledRequest<C> {
+    fn default() -> Self {
+        HandledRequest {
+            route: None,
+            generated_error: None,
+            compression: false,
+            request_context: None,
+            allow: None,
+            throttle: None,
+        }
+    }
+}
+
 async fn http_request_handle<C: ServerContext>(
     server: Arc<DropshotState<C>>,
-    request: Request<Body>,
+    mut request: Request<Body>,
+    connection: Arc<ConnectionInfo>,
+    client: EffectiveClient,
     request_id: &str,
-    request_log: Logger,
+    timings: &Arc<RequestTimings>,
+    request_log: &mut Logger,
+    handled: &mut HandledRequest<C>,
 ) -> Result<Response<Body>, HttpError> {
     // TODO-hardening: is it correct to (and do we correctly) read the entire
     // request body even if we decide it's too large and are going to send a 400
@@ -780,20 +2386,232 @@ async fn http_request_handle<C: ServerContext>(
     // TODO-hardening: add a request read timeout as well so that we don't allow
     // this to take forever.
     // TODO-correctness: Do we need to dump the body on errors?
+    let settings = server.settings.get();
+    if server.load_limits.shed_request(
+        server.in_flight_requests.load(Ordering::SeqCst),
+        settings.max_in_flight_requests,
+    ) {
+        warn!(request_log, "shedding request: too many requests in flight");
+        return Ok(server.load_limits.overloaded_response(
+            server.error_format,
+            request_id,
+            request.uri().path(),
+        ));
+    }
+    if let Some(max) = server.config.max_header_count {
+        if request.headers().len() > max {
+            return Err(HttpError::for_client_error(
+                None,
+                StatusCode::REQUEST_HEADER_FIELDS_TOO_LARGE,
+                format!("request has more than {} headers", max),
+            ));
+        }
+    }
+    if let Some(methods) = &server.config.method_override {
+        override_method(methods, &mut request, request_log)?;
+    }
+    let tenant = match &server.tenant_resolver {
+        Some(resolver) => resolver.resolve(&request)?,
+        None => None,
+    };
+    if let Some(tenant) = &tenant {
+        *request_log = request_log.new(o!("tenant" => tenant.tenant.clone()));
+    }
     let method = request.method();
     let uri = request.uri();
+    let route_path = tenant
+        .as_ref()
+        .and_then(|t| t.route_path.clone())
+        .unwrap_or_else(|| uri.path().to_string());
+    let tenant = tenant.map(|t| t.tenant);
+    let routes = server.routes();
     let lookup_result =
-        server.router.lookup_route(&method, uri.path().into())?;
+        match routes.router.lookup_route(&method, route_path.as_str().into()) {
+            Ok(lookup_result) => lookup_result,
+            Err(error) => {
+                let error =
+                    if error.status_code != StatusCode::METHOD_NOT_ALLOWED {
+                        error
+                    } else if server.config.method_not_allowed_as_not_found {
+                        HttpError::for_not_found(
+                            None,
+                            String::from("no handler for method"),
+                        )
+                    } else {
+                        handled.allow = routes
+                            .router
+                            .lookup_allowed_methods(route_path.as_str().into());
+                        error
+                    };
+                handled.generated_error =
+                    GeneratedErrorKind::for_routing_error(&error);
+                return Err(error);
+            }
+        };
+    let is_head = *method == http::Method::HEAD;
+    handled.route = Some(lookup_result.path.to_string());
+    handled.compression = lookup_result.compression;
+    handled.throttle =
+        routes.endpoint_throttles.get(method, lookup_result.path).cloned();
+    *request_log = routes.endpoint_log_levels.filter_logger(
+        method,
+        lookup_result.path,
+        request_log.clone(),
+    );
+    if let Some(response) = server.maintenance.response_for(
+        lookup_result.path,
+        server.error_format,
+        request_id,
+        uri.path(),
+    ) {
+        return Ok(response);
+    }
+    let idempotency_key = match lookup_result.idempotency {
+        Some(mode) => idempotency_key(
+            mode,
+            method,
+            uri.path(),
+            request.headers(),
+            tenant.as_deref(),
+        )?,
+        None => None,
+    };
+    let coalesce_key = lookup_result.coalesce.map(|headers| {
+        CoalesceKey::new(uri, request.headers(), headers, tenant.as_deref())
+    });
+    // If hyper drops this future because the client has gone away, the guard
+    // lets the handler know.
+    let (disconnect_guard, disconnect) = DisconnectGuard::new();
     let rqctx = RequestContext {
         server: Arc::clone(&server),
         request: RequestInfo::from(&request),
         path_variables: lookup_result.variables,
         body_content_type: lookup_result.body_content_type,
+        additional_body_content_types: lookup_result
+            .additional_body_content_types,
         request_id: request_id.to_string(),
-        log: request_log,
+        log: request_log.new(o!()),
+        connection,
+        client,
+        timings: Arc::clone(timings),
+        extensions: Default::default(),
+        tenant,
+        disconnect,
+        early_hints: request.extensions_mut().remove::<EarlyHints>(),
+        request_body_max_bytes: lookup_result
+            .request_body_max_bytes
+            .unwrap_or(settings.request_body_max_bytes),
+        schema_validators: lookup_result
+            .endpoint
+            .map(|endpoint| Arc::clone(&endpoint.schema_validators))
+            .unwrap_or_default(),
     };
-    let mut response =
-        lookup_result.handler.handle_request(rqctx, request).await?;
+    if server.error_mapper.read().unwrap().is_some() {
+        handled.request_context = Some(rqctx.detached());
+    }
+    if let Some(check) = &server.continue_check {
+        if expects_continue(request.headers()) {
+            check.check(&rqctx).await?;
+        }
+    }
+    // The handler consumes the request context, so the hooks that run after
+    // it see a detached copy.
+    let hooked = match lookup_result.endpoint {
+        Some(endpoint) => {
+            let hooks = routes.hooks.for_endpoint(endpoint);
+            for hook in &hooks {
+                hook.before(&rqctx, endpoint).await?;
+            }
+            (!hooks.is_empty()).then(|| (endpoint, hooks, rqctx.detached()))
+        }
+        None => None,
+    };
+    // An idempotency key is bound to the body of the request that first used
+    // it, so the body is read before the handler runs in order to compare it
+    // with that one.
+    let idempotent = match (idempotency_key, &server.idempotency_store) {
+        (Some(key), Some(store)) => {
+            let body = http_read_body(
+                request.body_mut(),
+                rqctx.request_body_max_bytes(),
+            )
+            .await?;
+            let digest = request_digest(&body);
+            *request.body_mut() = Body::from(body);
+            Some((Arc::clone(store), key, digest))
+        }
+        _ => None,
+    };
+    let handler = lookup_result.handler;
+    let timeout = lookup_result.timeout;
+    let run_handler =
+        move || async move { handler.handle_request(rqctx, request).await };
+    let run = {
+        let server = Arc::clone(&server);
+        let run = async move {
+            match (coalesce_key, idempotent) {
+                (Some(key), _) => server.coalescer.run(key, run_handler).await,
+                (None, Some((store, key, digest))) => {
+                    run_idempotent(store, &key, &digest, run_handler()).await
+                }
+                (None, None) => run_handler().await,
+            }
+        };
+        // A handler that runs past its endpoint's timeout is dropped, which
+        // cancels it wherever it's waiting.
+        async move {
+            match timeout {
+                None => run.await,
+                Some(timeout) => tokio::time::timeout(timeout, run)
+                    .await
+                    .unwrap_or_else(|_| {
+                        Err(HttpError::for_unavail(
+                            None,
+                            format!("handler timed out after {:?}", timeout),
+                        ))
+                    }),
+            }
+        }
+    };
+    let mut result = match server.config.handler_task_mode {
+        HandlerTaskMode::CancelOnDisconnect => run.await,
+        // Run the handler in its own task so that it isn't stopped if hyper
+        // drops this future.
+        HandlerTaskMode::Detached => match tokio::spawn(run).await {
+            Ok(result) => result,
+            Err(error) => match error.try_into_panic() {
+                Ok(panic) => std::panic::resume_unwind(panic),
+                Err(error) => {
+                    return Err(HttpError::for_internal_error(format!(
+                        "handler task failed: {}",
+                        error
+                    )))
+                }
+            },
+        },
+    };
+    if let Some((endpoint, hooks, rqctx)) = hooked {
+        for hook in &hooks {
+            result = hook.after(&rqctx, endpoint, result).await;
+        }
+    }
+    // Whether the handler succeeded or failed, the client is still there to
+    // receive its response.
+    disconnect_guard.disarm();
+    let mut response = result?;
+    if is_head {
+        // The response to a HEAD request has no body, but its headers describe
+        // the body that a GET request would have gotten.  A streaming body is
+        // dropped without being read, which stops whatever was producing it.
+        let (mut parts, body) = response.into_parts();
+        if let Some(length) = body.size_hint().exact() {
+            parts
+                .headers
+                .entry(http::header::CONTENT_LENGTH)
+                .or_insert_with(|| length.into());
+        }
+        response = Response::from_parts(parts, Body::empty());
+    }
     response.headers_mut().insert(
         HEADER_REQUEST_ID,
         http::header::HeaderValue::from_str(&request_id).unwrap(),
@@ -810,55 +2628,12 @@ fn generate_request_id() -> String {
     format!("{}", Uuid::new_v4())
 }
 
-/// ServerConnectionHandler is a Hyper Service implementation that forwards
-/// incoming connections to `http_connection_handle()`, providing the server
-/// state object as an additional argument.  We could use `make_service_fn` here
-/// using a closure to capture the state object, but the resulting code is a bit
-/// simpler without it.
-pub struct ServerConnectionHandler<C: ServerContext> {
-    /// backend state that will be made available to the connection handler
-    server: Arc<DropshotState<C>>,
-}
-
-impl<C: ServerContext> ServerConnectionHandler<C> {
-    /// Create an ServerConnectionHandler with the given state object that
-    /// will be made available to the handler.
-    fn new(server: Arc<DropshotState<C>>) -> Self {
-        ServerConnectionHandler { server }
-    }
-}
-
-impl<T: ServerContext> Service<&AddrStream> for ServerConnectionHandler<T> {
-    // Recall that a Service in this context is just something that takes a
-    // request (which could be anything) and produces a response (which could be
-    // anything).  This being a connection handler, the request type is an
-    // AddrStream (which wraps a TCP connection) and the response type is
-    // another Service: one that accepts HTTP requests and produces HTTP
-    // responses.
-    type Response = ServerRequestHandler<T>;
-    type Error = GenericError;
-    type Future = BoxFuture<'static, Result<Self::Response, Self::Error>>;
-
-    fn poll_ready(
-        &mut self,
-        _cx: &mut Context<'_>,
-    ) -> Poll<Result<(), Self::Error>> {
-        // TODO is this right?
-        Poll::Ready(Ok(()))
-    }
-
-    fn call(&mut self, conn: &AddrStream) -> Self::Future {
-        // We're given a borrowed reference to the AddrStream, but our interface
-        // is async (which is good, so that we can support time-consuming
-        // operations as part of receiving requests).  To avoid having to ensure
-        // that conn's lifetime exceeds that of this async operation, we simply
-        // copy the only useful information out of the conn: the SocketAddr.  We
-        // may want to create our own connection type to encapsulate the socket
-        // address and any other per-connection state that we want to keep.
-        let server = Arc::clone(&self.server);
-        let remote_addr = conn.remote_addr();
-        Box::pin(http_connection_handle(server, remote_addr))
-    }
+// Connection ids identify the connection on which a request arrived.  They're
+// included in the log entries for each request as well as those for any
+// connection that's later upgraded (e.g., to a websocket) so that events on a
+// long-lived connection can be correlated with the requests that created it.
+pub(crate) fn generate_connection_id() -> String {
+    format!("{}", Uuid::new_v4())
 }
 
 /// ServerRequestHandler is a Hyper Service implementation that forwards
@@ -869,66 +2644,90 @@ impl<T: ServerContext> Service<&AddrStream> for ServerConnectionHandler<T> {
 pub struct ServerRequestHandler<C: ServerContext> {
     /// backend state that will be made available to the request handler
     server: Arc<DropshotState<C>>,
-    remote_addr: SocketAddr,
+    /// the connection on which requests arrive
+    connection: Arc<ConnectionInfo>,
+    /// unique id assigned to this connection
+    connection_id: String,
+    /// limits the bandwidth of responses sent on this connection
+    throttle: Throttle,
+    /// sends early hints on this connection
+    early_hints: EarlyHintsSender,
+    /// tracks requests on this connection, to close it when it has served
+    /// enough of them or been idle too long
+    requests: ConnectionRequests,
 }
 
 impl<C: ServerContext> ServerRequestHandler<C> {
     /// Create a ServerRequestHandler object with the given state object that
     /// will be provided to the handler function.
-    fn new(server: Arc<DropshotState<C>>, remote_addr: SocketAddr) -> Self {
-        ServerRequestHandler { server, remote_addr }
+    fn new(
+        server: Arc<DropshotState<C>>,
+        connection: Arc<ConnectionInfo>,
+        connection_id: String,
+        throttle: Throttle,
+        early_hints: EarlyHintsSender,
+        requests: ConnectionRequests,
+    ) -> Self {
+        ServerRequestHandler {
+            server,
+            connection,
+            connection_id,
+            throttle,
+            early_hints,
+            requests,
+        }
     }
 }
 
-impl<C: ServerContext> Service<Request<Body>> for ServerRequestHandler<C> {
+impl<C: ServerContext> Service<Request<Incoming>> for ServerRequestHandler<C> {
     type Response = Response<Body>;
     type Error = GenericError;
     type Future = BoxFuture<'static, Result<Self::Response, Self::Error>>;
 
-    fn poll_ready(
-        &mut self,
-        _cx: &mut Context<'_>,
-    ) -> Poll<Result<(), Self::Error>> {
-        // TODO is this right?
-        Poll::Ready(Ok(()))
-    }
-
-    fn call(&mut self, req: Request<Body>) -> Self::Future {
-        Box::pin(http_request_handle_wrap(
+    fn call(&self, mut req: Request<Incoming>) -> Self::Future {
+        // Informational responses other than `100 Continue` were introduced
+        // in HTTP/1.1, and hints can't be sent on HTTP/2 connections.
+        let early_hints = self.early_hints.begin();
+        let early_hints =
+            (req.version() == http::Version::HTTP_11).then(|| {
+                req.extensions_mut().insert(early_hints.clone());
+                early_hints
+            });
+        let request = self.requests.enabled().then(|| self.requests.begin());
+        // Only HTTP/1 has a way to ask the client to close the connection.
+        let close = request.as_ref().map_or(false, |request| request.is_last())
+            && req.version() <= http::Version::HTTP_11;
+        let requests = self.requests.clone();
+        let response = http_request_handle_wrap(
             Arc::clone(&self.server),
-            self.remote_addr,
-            req,
-        ))
-    }
-}
-
-fn io_error(err: String) -> std::io::Error {
-    std::io::Error::new(std::io::ErrorKind::Other, err)
-}
-
-// Load public certificate from config.
-fn load_certs(config: &ConfigTls) -> std::io::Result<Vec<rustls::Certificate>> {
-    let mut reader = config.cert_reader()?;
-
-    // Load and return certificate.
-    rustls_pemfile::certs(&mut reader)
-        .map_err(|err| io_error(format!("failed to load certificate: {err}")))
-        .map(|mut chain| chain.drain(..).map(rustls::Certificate).collect())
-}
-
-// Load private key from config.
-fn load_private_key(config: &ConfigTls) -> std::io::Result<rustls::PrivateKey> {
-    let mut reader = config.key_reader()?;
-
-    // Load and return a single private key.
-    let keys =
-        rustls_pemfile::pkcs8_private_keys(&mut reader).map_err(|err| {
-            io_error(format!("failed to load private key: {err}"))
-        })?;
-    if keys.len() != 1 {
-        return Err(io_error("expected a single private key".into()));
+            Arc::clone(&self.connection),
+            self.connection_id.clone(),
+            self.throttle.clone(),
+            req.map(Body::wrap),
+        );
+        Box::pin(async move {
+            let result = response.await;
+            if let Some(early_hints) = early_hints {
+                early_hints.finish();
+            }
+            let mut response = result?;
+            if response.status() == StatusCode::SWITCHING_PROTOCOLS {
+                requests.upgraded();
+            } else if close {
+                response.headers_mut().insert(
+                    http::header::CONNECTION,
+                    http::HeaderValue::from_static("close"),
+                );
+            }
+            // The request is in progress until its response has been sent.
+            Ok(match request {
+                Some(request) => {
+                    response.map(|body| request.hold_until_sent(body))
+                }
+                None => response,
+            })
+        })
     }
-    Ok(rustls::PrivateKey(keys[0].clone()))
 }
 
 #[cfg(test)]
@@ -0,0 +1,223 @@
+// Copyright 2023 Oxide Computer Company
+//! Server settings that can be changed while the server runs
+
+use crate::config::ConfigDropshot;
+use crate::logging::is_overridden;
+use crate::ConfigLoggingLevel;
+
+use slog::Drain;
+use slog::Level;
+use slog::Logger;
+use slog::OwnedKVList;
+use slog::Record;
+use std::sync::atomic::AtomicUsize;
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
+use std::sync::RwLock;
+use std::time::Duration;
+
+/// The settings of a running server that can be changed without restarting
+/// it (see [`ServerSettingsHandle`]).  Each is initially as configured in
+/// [`ConfigDropshot`], except for `log_level`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ServerSettings {
+    /// maximum allowed size of a request body, for endpoints that don't have
+    /// their own limit (see `ConfigDropshot::request_body_max_bytes`)
+    pub request_body_max_bytes: usize,
+    /// how long a connection may stay open without a request in progress, if
+    /// not indefinitely (see `ConfigDropshot::keep_alive_timeout_ms`)
+    pub keep_alive_timeout: Option<Duration>,
+    /// how long a connection may go without any reads or writes, if not
+    /// indefinitely (see `ConfigDropshot::idle_timeout_ms`)
+    pub idle_timeout: Option<Duration>,
+    /// how long a client may take to send a request's head, if not
+    /// indefinitely (see `ConfigDropshot::header_read_timeout_ms`)
+    pub header_read_timeout: Option<Duration>,
+    /// how long to wait for requests in progress to complete when shutting
+    /// down, if not indefinitely (see `ConfigDropshot::drain_timeout_ms`)
+    pub drain_timeout: Option<Duration>,
+    /// If present, the server's log entries less severe than this level are
+    /// discarded.  This can't make logging more verbose than the server's
+    /// logger allows.  It doesn't apply to requests for endpoints with their
+    /// own log level (see `HttpServer::set_endpoint_log_level`).
+    pub log_level: Option<ConfigLoggingLevel>,
+    /// maximum number of connections open at once, beyond which connections
+    /// are shed (see `ConfigLoadLimits::max_connections`)
+    pub max_connections: Option<usize>,
+    /// maximum number of requests in flight at once, beyond which requests are
+    /// shed (see `ConfigLoadLimits::max_in_flight_requests`)
+    pub max_in_flight_requests: Option<usize>,
+}
+
+impl ServerSettings {
+    pub(crate) fn new(config: &ConfigDropshot) -> ServerSettings {
+        ServerSettings {
+            request_body_max_bytes: config.request_body_max_bytes,
+            keep_alive_timeout: config
+                .keep_alive_timeout_ms
+                .map(Duration::from_millis),
+            idle_timeout: config.idle_timeout_ms.map(Duration::from_millis),
+            header_read_timeout: config
+                .header_read_timeout_ms
+                .map(Duration::from_millis),
+            drain_timeout: config.drain_timeout_ms.map(Duration::from_millis),
+            log_level: None,
+            max_connections: config.load_limits.max_connections,
+            max_in_flight_requests: config.load_limits.max_in_flight_requests,
+        }
+    }
+}
+
+#[derive(Debug)]
+struct Shared {
+    settings: RwLock<ServerSettings>,
+    /// `settings.log_level` as a `slog::Level` (or 0 if it's not set), so that
+    /// it can be checked for every log entry without taking the lock
+    log_level: AtomicUsize,
+}
+
+/// Handle with which the settings of a running server are read and changed,
+/// from [`HttpServer::settings()`](crate::HttpServer::settings) or
+/// [`AdminContext::settings()`](crate::AdminContext::settings).
+///
+/// Changes take effect for new work: a new body size limit applies to requests
+/// that arrive afterward, new timeouts to connections accepted afterward (the
+/// drain timeout to a shutdown that begins afterward), and new load limits to
+/// the next connection or request that's counted against them.
+///
+/// ```
+/// # fn f(server: &dropshot::HttpServer<()>) {
+/// // Reject larger bodies and fewer concurrent requests during an incident.
+/// let settings = server.settings().update(|settings| {
+///     settings.request_body_max_bytes = 4096;
+///     settings.max_in_flight_requests = Some(100);
+/// });
+/// assert_eq!(settings.request_body_max_bytes, 4096);
+/// # }
+/// ```
+#[derive(Clone, Debug)]
+pub struct ServerSettingsHandle {
+    shared: Arc<Shared>,
+}
+
+impl ServerSettingsHandle {
+    pub(crate) fn new(settings: ServerSettings) -> ServerSettingsHandle {
+        let log_level = level_value(settings.log_level.as_ref());
+        ServerSettingsHandle {
+            shared: Arc::new(Shared {
+                settings: RwLock::new(settings),
+                log_level: AtomicUsize::new(log_level),
+            }),
+        }
+    }
+
+    /// Returns the server's current settings.
+    pub fn get(&self) -> ServerSettings {
+        self.shared.settings.read().unwrap().clone()
+    }
+
+    /// Replaces the server's settings with `settings`.
+    pub fn set(&self, settings: ServerSettings) {
+        self.update(|current| *current = settings);
+    }
+
+    /// Changes the server's settings with `f`, returning the new settings.
+    pub fn update<F>(&self, f: F) -> ServerSettings
+    where
+        F: FnOnce(&mut ServerSettings),
+    {
+        let mut settings = self.shared.settings.write().unwrap();
+        f(&mut settings);
+        self.shared
+            .log_level
+            .store(level_value(settings.log_level.as_ref()), Ordering::SeqCst);
+        settings.clone()
+    }
+
+    /// Returns a logger that emits whatever `log` would, filtered by the
+    /// current `log_level`.
+    pub(crate) fn filter_logger(&self, log: Logger) -> Logger {
+        Logger::root(
+            LevelFilter { drain: log, shared: Arc::clone(&self.shared) },
+            slog::o!(),
+        )
+    }
+}
+
+fn level_value(level: Option<&ConfigLoggingLevel>) -> usize {
+    level.map_or(0, |level| Level::from(level).as_usize())
+}
+
+/// Drain that discards entries less severe than a server's current
+/// `log_level`, except those from loggers with a per-endpoint level override
+/// (which is applied by the server logger's own filter instead)
+struct LevelFilter {
+    drain: Logger,
+    shared: Arc<Shared>,
+}
+
+impl LevelFilter {
+    fn allows(&self, level: Level) -> bool {
+        match self.shared.log_level.load(Ordering::Relaxed) {
+            0 => true,
+            min => level.as_usize() <= min,
+        }
+    }
+}
+
+impl Drain for LevelFilter {
+    type Ok = ();
+    type Err = slog::Never;
+
+    fn log(
+        &self,
+        record: &Record<'_>,
+        values: &OwnedKVList,
+    ) -> Result<(), slog::Never> {
+        if self.allows(record.level()) || is_overridden() {
+            self.drain.log(record, values)
+        } else {
+            Ok(())
+        }
+    }
+
+    // This can't know about overrides, so it only reflects the server's own
+    // level.
+    fn is_enabled(&self, level: Level) -> bool {
+        self.allows(level) && self.drain.is_enabled(level)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::ServerSettings;
+    use super::ServerSettingsHandle;
+    use crate::config::ConfigDropshot;
+    use crate::ConfigLoggingLevel;
+    use slog::Drain;
+
+    #[test]
+    fn test_settings() {
+        let handle =
+            ServerSettingsHandle::new(ServerSettings::new(&Default::default()));
+        assert_eq!(
+            handle.get(),
+            ServerSettings::new(&ConfigDropshot::default())
+        );
+        let log =
+            handle.filter_logger(slog::Logger::root(slog::Discard, slog::o!()));
+        assert!(log.is_info_enabled());
+
+        let settings = handle.update(|settings| {
+            settings.request_body_max_bytes = 1;
+            settings.log_level = Some(ConfigLoggingLevel::Warn);
+        });
+        assert_eq!(settings.request_body_max_bytes, 1);
+        assert_eq!(handle.get(), settings);
+        assert!(!log.is_info_enabled());
+        assert!(log.is_warning_enabled());
+
+        handle.set(ServerSettings { log_level: None, ..settings });
+        assert!(log.is_debug_enabled());
+    }
+}
@@ -0,0 +1,248 @@
+// Copyright 2023 Oxide Computer Company
+//! Detection and reporting of slow requests
+
+use crate::config::ConfigSlowRequestLogging;
+use crate::route_pattern::RoutePattern;
+use crate::Body;
+use crate::BoxError;
+use bytes::Bytes;
+use http_body::Body as HttpBody;
+use http_body::Frame;
+use http_body::SizeHint;
+use slog::Logger;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::sync::Mutex;
+use std::task::Context;
+use std::task::Poll;
+use std::time::Duration;
+use std::time::Instant;
+
+/// Decides which requests are slow enough to report, based on the server's
+/// configured per-route thresholds
+#[derive(Debug)]
+pub(crate) struct SlowRequestDetector {
+    /// route pattern and threshold of each rule
+    rules: Vec<(RoutePattern, Duration)>,
+}
+
+impl SlowRequestDetector {
+    pub fn new(config: &[ConfigSlowRequestLogging]) -> SlowRequestDetector {
+        SlowRequestDetector {
+            rules: config
+                .iter()
+                .map(|rule| {
+                    (
+                        RoutePattern::new(&rule.path),
+                        Duration::from_millis(rule.threshold_ms),
+                    )
+                })
+                .collect(),
+        }
+    }
+
+    /// Returns the latency threshold for requests to `route` (the path
+    /// template of the matched endpoint), if any.  The first matching rule
+    /// applies.
+    pub fn threshold(&self, route: &str) -> Option<Duration> {
+        self.rules
+            .iter()
+            .find(|(pattern, _)| pattern.matches(route))
+            .map(|(_, threshold)| *threshold)
+    }
+}
+
+/// Phases of handling a request, in the order in which they happen
+#[derive(Clone, Copy, Debug)]
+pub(crate) enum RequestPhase {
+    /// extracting the handler's arguments from the request
+    Extraction,
+    /// running the handler function
+    Handler,
+    /// converting the handler's return value into a response
+    Serialization,
+    /// sending the response (which ends when the body has been written)
+    Write,
+}
+
+/// Records when a request entered each phase of its handling
+#[derive(Debug)]
+pub(crate) struct RequestTimings {
+    received: Instant,
+    marks: Mutex<[Option<Instant>; 4]>,
+}
+
+impl RequestTimings {
+    pub fn new(received: Instant) -> RequestTimings {
+        RequestTimings { received, marks: Mutex::new([None; 4]) }
+    }
+
+    /// Records that the request has entered phase `phase`.
+    pub fn mark(&self, phase: RequestPhase) {
+        self.marks.lock().unwrap()[phase as usize] = Some(Instant::now());
+    }
+
+    /// Returns true if the request began extracting its arguments but never
+    /// made it to the handler (i.e., extraction failed).
+    pub fn stopped_in_extraction(&self) -> bool {
+        let marks = self.marks.lock().unwrap();
+        marks[RequestPhase::Extraction as usize].is_some()
+            && marks[RequestPhase::Handler as usize].is_none()
+    }
+
+    /// Returns how long the request spent in each phase (starting with the
+    /// time between receiving the request and extracting its arguments, which
+    /// we call "queueing"), given that it finished at `done`.  A phase that
+    /// the request never entered (e.g., because extraction failed, or because
+    /// the response was shared with another request) took no time.
+    fn breakdown(&self, done: Instant) -> [Duration; 5] {
+        let marks = self.marks.lock().unwrap();
+        let all = [
+            Some(self.received),
+            marks[0],
+            marks[1],
+            marks[2],
+            marks[3],
+            Some(done),
+        ];
+        let mut durations = [Duration::ZERO; 5];
+        for (i, duration) in durations.iter_mut().enumerate() {
+            if let Some(start) = all[i] {
+                let end = all[i + 1..].iter().find_map(|m| *m).unwrap();
+                *duration = end.saturating_duration_since(start);
+            }
+        }
+        durations
+    }
+}
+
+/// Response body that logs a warning with the request's timing breakdown when
+/// it's been completely sent (or dropped) if the request as a whole took
+/// longer than `threshold`
+pub(crate) struct TimedBody {
+    body: Body,
+    log: Logger,
+    timings: Arc<RequestTimings>,
+    threshold: Duration,
+    status: u16,
+}
+
+impl TimedBody {
+    pub fn wrap(
+        body: Body,
+        log: Logger,
+        timings: Arc<RequestTimings>,
+        threshold: Duration,
+        status: http::StatusCode,
+    ) -> Body {
+        timings.mark(RequestPhase::Write);
+        Body::wrap(TimedBody {
+            body,
+            log,
+            timings,
+            threshold,
+            status: status.as_u16(),
+        })
+    }
+}
+
+impl HttpBody for TimedBody {
+    type Data = Bytes;
+    type Error = BoxError;
+
+    fn poll_frame(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Frame<Bytes>, BoxError>>> {
+        HttpBody::poll_frame(Pin::new(&mut self.body), cx)
+    }
+
+    fn is_end_stream(&self) -> bool {
+        HttpBody::is_end_stream(&self.body)
+    }
+
+    fn size_hint(&self) -> SizeHint {
+        HttpBody::size_hint(&self.body)
+    }
+}
+
+impl Drop for TimedBody {
+    fn drop(&mut self) {
+        let done = Instant::now();
+        let total = done.saturating_duration_since(self.timings.received);
+        if total <= self.threshold {
+            return;
+        }
+
+        let [queueing, extraction, handler, serialization, write] =
+            self.timings.breakdown(done);
+        warn!(self.log, "slow request";
+            "response_code" => self.status,
+            "threshold_us" => self.threshold.as_micros() as u64,
+            "total_us" => total.as_micros() as u64,
+            "queueing_us" => queueing.as_micros() as u64,
+            "extraction_us" => extraction.as_micros() as u64,
+            "handler_us" => handler.as_micros() as u64,
+            "serialization_us" => serialization.as_micros() as u64,
+            "write_us" => write.as_micros() as u64,
+        );
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::RequestPhase;
+    use super::RequestTimings;
+    use super::SlowRequestDetector;
+    use crate::config::ConfigSlowRequestLogging;
+    use std::time::Duration;
+    use std::time::Instant;
+
+    #[test]
+    fn test_threshold() {
+        let detector = SlowRequestDetector::new(&[
+            ConfigSlowRequestLogging {
+                path: String::from("/reports/*"),
+                threshold_ms: 5000,
+            },
+            ConfigSlowRequestLogging {
+                path: String::from("/projects/{id}"),
+                threshold_ms: 100,
+            },
+        ]);
+        assert_eq!(
+            detector.threshold("/reports/{kind}"),
+            Some(Duration::from_secs(5))
+        );
+        assert_eq!(
+            detector.threshold("/projects/{id}"),
+            Some(Duration::from_millis(100))
+        );
+        assert_eq!(detector.threshold("/projects"), None);
+    }
+
+    #[test]
+    fn test_breakdown() {
+        let received = Instant::now();
+        let timings = RequestTimings::new(received);
+        timings.marks.lock().unwrap()[RequestPhase::Extraction as usize] =
+            Some(received + Duration::from_millis(1));
+        timings.marks.lock().unwrap()[RequestPhase::Handler as usize] =
+            Some(received + Duration::from_millis(3));
+        // Serialization never happened (e.g., the handler failed).
+        timings.marks.lock().unwrap()[RequestPhase::Write as usize] =
+            Some(received + Duration::from_millis(10));
+
+        let done = received + Duration::from_millis(15);
+        assert_eq!(
+            timings.breakdown(done),
+            [
+                Duration::from_millis(1),
+                Duration::from_millis(2),
+                Duration::from_millis(7),
+                Duration::ZERO,
+                Duration::from_millis(5),
+            ]
+        );
+    }
+}
@@ -0,0 +1,161 @@
+// Copyright 2023 Oxide Computer Company
+//! Support for the systemd socket activation protocol (see
+//! `ConfigDropshot::listen_fd`)
+//!
+//! A service manager that implements the protocol binds the service's
+//! sockets itself and passes them to the service as file descriptors 3, 4, and
+//! so on, setting `LISTEN_FDS` to their number and `LISTEN_PID` to the process
+//! ID of the service (so that child processes don't mistake the variables for
+//! their own).  See sd_listen_fds(3).
+
+#[cfg(unix)]
+use std::convert::TryFrom;
+use std::io;
+#[cfg(unix)]
+use std::sync::Mutex;
+
+/// file descriptor of the first socket passed to the service
+#[cfg(unix)]
+const LISTEN_FDS_START: i32 = 3;
+
+/// indexes of the passed sockets that have been taken, each of which may only
+/// be taken (and owned) once
+#[cfg(unix)]
+static TAKEN_FDS: Mutex<Vec<usize>> = Mutex::new(Vec::new());
+
+/// Takes ownership of the listening TCP socket passed to this process at
+/// `index` (starting at 0) by the socket activation protocol.
+#[cfg(unix)]
+pub(crate) fn take_listen_fd(
+    index: usize,
+) -> io::Result<std::net::TcpListener> {
+    use std::os::unix::io::FromRawFd;
+
+    let fd = listen_fd(
+        index,
+        std::env::var("LISTEN_PID").ok().as_deref(),
+        std::env::var("LISTEN_FDS").ok().as_deref(),
+        std::process::id(),
+    )?;
+    let mut taken = TAKEN_FDS.lock().unwrap();
+    if taken.contains(&index) {
+        return Err(io::Error::new(
+            io::ErrorKind::AddrInUse,
+            format!("socket activation: socket {} is already in use", index),
+        ));
+    }
+
+    // As sd_listen_fds(3) does, keep child processes from inheriting the
+    // socket.
+    // Safety: these calls only manipulate the flags of `fd`, which is valid
+    // for the lifetime of the process per the protocol.
+    let flags = unsafe { libc::fcntl(fd, libc::F_GETFD) };
+    if flags < 0
+        || unsafe { libc::fcntl(fd, libc::F_SETFD, flags | libc::FD_CLOEXEC) }
+            < 0
+    {
+        return Err(io::Error::last_os_error());
+    }
+
+    // Safety: the protocol hands ownership of the socket to this process, and
+    // `TAKEN_FDS` ensures that only one listener owns it.
+    let listener = unsafe { std::net::TcpListener::from_raw_fd(fd) };
+    // This fails if the socket isn't a TCP (IPv4 or IPv6) socket.
+    if let Err(e) = listener.local_addr() {
+        // Don't close a socket that isn't ours to use.
+        std::mem::forget(listener);
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("socket activation: socket {} is not TCP: {}", index, e),
+        ));
+    }
+    taken.push(index);
+    Ok(listener)
+}
+
+#[cfg(not(unix))]
+pub(crate) fn take_listen_fd(
+    _index: usize,
+) -> io::Result<std::net::TcpListener> {
+    Err(io::Error::new(
+        io::ErrorKind::Other,
+        "socket activation is not supported on this platform",
+    ))
+}
+
+/// Returns the file descriptor of the socket passed at `index`, given the
+/// values of `LISTEN_PID` and `LISTEN_FDS` and this process's ID.
+#[cfg(unix)]
+fn listen_fd(
+    index: usize,
+    listen_pid: Option<&str>,
+    listen_fds: Option<&str>,
+    pid: u32,
+) -> io::Result<i32> {
+    let error = |message: String| {
+        io::Error::new(
+            io::ErrorKind::NotFound,
+            format!("socket activation: {}", message),
+        )
+    };
+    let listen_pid = listen_pid
+        .ok_or_else(|| error(String::from("LISTEN_PID is not set")))?;
+    if listen_pid.parse::<u32>().ok() != Some(pid) {
+        return Err(error(format!(
+            "LISTEN_PID ({}) is not this process ({})",
+            listen_pid, pid
+        )));
+    }
+    let listen_fds = listen_fds
+        .ok_or_else(|| error(String::from("LISTEN_FDS is not set")))?;
+    let nfds = listen_fds.parse::<usize>().map_err(|_| {
+        error(format!("LISTEN_FDS is not a number: {:?}", listen_fds))
+    })?;
+    if index >= nfds {
+        return Err(error(format!(
+            "socket {} was requested, but only {} were passed",
+            index, nfds
+        )));
+    }
+    i32::try_from(index)
+        .ok()
+        .and_then(|index| LISTEN_FDS_START.checked_add(index))
+        .ok_or_else(|| error(format!("socket {} is out of range", index)))
+}
+
+#[cfg(all(test, unix))]
+mod test {
+    use super::listen_fd;
+
+    #[test]
+    fn test_listen_fd() {
+        assert_eq!(listen_fd(0, Some("100"), Some("1"), 100).unwrap(), 3);
+        assert_eq!(listen_fd(2, Some("100"), Some("3"), 100).unwrap(), 5);
+
+        let error = |index, listen_pid, listen_fds| {
+            listen_fd(index, listen_pid, listen_fds, 100)
+                .unwrap_err()
+                .to_string()
+        };
+        assert_eq!(
+            error(0, None, Some("1")),
+            "socket activation: LISTEN_PID is not set"
+        );
+        assert_eq!(
+            error(0, Some("101"), Some("1")),
+            "socket activation: LISTEN_PID (101) is not this process (100)"
+        );
+        assert_eq!(
+            error(0, Some("100"), None),
+            "socket activation: LISTEN_FDS is not set"
+        );
+        assert_eq!(
+            error(0, Some("100"), Some("one")),
+            "socket activation: LISTEN_FDS is not a number: \"one\""
+        );
+        assert_eq!(
+            error(1, Some("100"), Some("1")),
+            "socket activation: socket 1 was requested, but only 1 were passed"
+        );
+    }
+}
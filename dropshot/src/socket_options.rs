@@ -0,0 +1,114 @@
+// Copyright 2023 Oxide Computer Company
+//! Options for a server's TCP sockets (see `ConfigDropshot::socket_options`)
+
+use crate::config::ConfigSocketOptions;
+
+use socket2::Domain;
+use socket2::Protocol;
+use socket2::SockRef;
+use socket2::Socket;
+use socket2::TcpKeepalive;
+use socket2::Type;
+use std::io;
+use std::net::SocketAddr;
+use std::time::Duration;
+use tokio::net::TcpListener;
+use tokio::net::TcpStream;
+
+/// Returns a new, unbound socket for listening on `address`, with the options
+/// in `options` that must be set before it's bound.
+pub(crate) fn listener_socket(
+    address: &SocketAddr,
+    options: &ConfigSocketOptions,
+) -> io::Result<Socket> {
+    let socket = Socket::new(
+        Domain::for_address(*address),
+        Type::STREAM,
+        Some(Protocol::TCP),
+    )?;
+    // Like `std::net::TcpListener::bind()`, allow binding the address while
+    // connections from a previous listener linger in TIME_WAIT.
+    #[cfg(unix)]
+    socket.set_reuse_address(true)?;
+    if let (SocketAddr::V6(_), Some(ipv6_only)) = (address, options.ipv6_only) {
+        socket.set_only_v6(ipv6_only)?;
+    }
+    configure_listener(SockRef::from(&socket), options)?;
+    Ok(socket)
+}
+
+/// Binds `socket` to `address` and starts listening on it.
+pub(crate) fn listen(
+    socket: Socket,
+    address: &SocketAddr,
+) -> io::Result<TcpListener> {
+    socket.bind(&(*address).into())?;
+    socket.listen(1024)?;
+    socket.set_nonblocking(true)?;
+    TcpListener::from_std(socket.into())
+}
+
+/// Sets the options in `options` that apply to a listening socket (and are
+/// inherited by its connections).
+pub(crate) fn configure_listener(
+    socket: SockRef<'_>,
+    options: &ConfigSocketOptions,
+) -> io::Result<()> {
+    if let Some(size) = options.recv_buffer_size {
+        socket.set_recv_buffer_size(size)?;
+    }
+    if let Some(size) = options.send_buffer_size {
+        socket.set_send_buffer_size(size)?;
+    }
+    Ok(())
+}
+
+/// Sets the options in `options` that apply to an accepted connection.
+pub(crate) fn configure_connection(
+    stream: &TcpStream,
+    options: &ConfigSocketOptions,
+) -> io::Result<()> {
+    if options.tcp_nodelay {
+        stream.set_nodelay(true)?;
+    }
+    if let Some(secs) = options.tcp_keepalive_secs {
+        let keepalive =
+            TcpKeepalive::new().with_time(Duration::from_secs(secs));
+        SockRef::from(stream).set_tcp_keepalive(&keepalive)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::configure_connection;
+    use super::listen;
+    use super::listener_socket;
+    use crate::config::ConfigSocketOptions;
+    use socket2::SockRef;
+
+    #[tokio::test]
+    async fn test_socket_options() {
+        let options = ConfigSocketOptions {
+            tcp_nodelay: true,
+            tcp_keepalive_secs: Some(60),
+            recv_buffer_size: Some(65536),
+            send_buffer_size: Some(65536),
+            ipv6_only: Some(true),
+        };
+        let address = "127.0.0.1:0".parse().unwrap();
+        let listener =
+            listen(listener_socket(&address, &options).unwrap(), &address)
+                .unwrap();
+        // Operating systems may round buffer sizes up (Linux doubles them).
+        assert!(SockRef::from(&listener).recv_buffer_size().unwrap() >= 65536);
+        assert!(SockRef::from(&listener).send_buffer_size().unwrap() >= 65536);
+
+        let address = listener.local_addr().unwrap();
+        let _client = tokio::net::TcpStream::connect(address).await.unwrap();
+        let (stream, _) = listener.accept().await.unwrap();
+        configure_connection(&stream, &options).unwrap();
+        assert!(stream.nodelay().unwrap());
+        assert!(SockRef::from(&stream).keepalive().unwrap());
+    }
+}
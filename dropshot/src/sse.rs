@@ -0,0 +1,264 @@
+// Copyright 2023 Oxide Computer Company
+
+//! Server-sent events (`text/event-stream`) responses
+
+use crate::api_description::ApiEndpointResponse;
+use crate::api_description::ApiSchemaGenerator;
+use crate::body::BoxError;
+use crate::handler::HttpHandlerResult;
+use crate::handler::HttpResponse;
+use crate::http_util::CONTENT_TYPE_EVENT_STREAM;
+use crate::schema_util::make_subschema_for;
+use crate::Body;
+
+use async_stream::stream;
+use bytes::Bytes;
+use futures::stream::BoxStream;
+use futures::Stream;
+use futures::StreamExt;
+use http::Response;
+use http::StatusCode;
+use schemars::JsonSchema;
+use serde::Serialize;
+use std::time::Duration;
+use sync_wrapper::SyncWrapper;
+
+/// How often a comment is sent on an otherwise idle stream, by default
+const DEFAULT_KEEP_ALIVE: Duration = Duration::from_secs(15);
+
+/// One event of an [`HttpResponseSse`] stream.  Its data is serialized as JSON
+/// (on a single `data` line); the other fields are optional.
+#[derive(Clone, Debug)]
+pub struct SseEvent<T> {
+    data: T,
+    id: Option<String>,
+    event: Option<String>,
+    retry: Option<Duration>,
+}
+
+impl<T> SseEvent<T> {
+    /// Returns an event with the given data.
+    pub fn new(data: T) -> SseEvent<T> {
+        SseEvent { data, id: None, event: None, retry: None }
+    }
+
+    /// Sets the event's id, which the client sends back in the
+    /// `Last-Event-ID` header if it reconnects.  The id may not contain line
+    /// breaks or NULs.
+    pub fn id(mut self, id: impl Into<String>) -> SseEvent<T> {
+        self.id = Some(id.into());
+        self
+    }
+
+    /// Sets the event's type (its `event` field), which the client uses to
+    /// dispatch it.  The type may not contain line breaks.
+    pub fn event(mut self, event: impl Into<String>) -> SseEvent<T> {
+        self.event = Some(event.into());
+        self
+    }
+
+    /// Sets how long the client should wait before reconnecting if the
+    /// connection is lost.
+    pub fn retry(mut self, retry: Duration) -> SseEvent<T> {
+        self.retry = Some(retry);
+        self
+    }
+}
+
+impl<T: Serialize> SseEvent<T> {
+    /// Encodes the event in the `text/event-stream` format.
+    fn encode(&self) -> Result<Bytes, String> {
+        let mut encoded = String::new();
+        if let Some(id) = &self.id {
+            if id.contains('\0') {
+                return Err(format!(
+                    "\"id\" field of event contains a NUL: {:?}",
+                    id
+                ));
+            }
+            encode_field(&mut encoded, "id", id)?;
+        }
+        if let Some(event) = &self.event {
+            encode_field(&mut encoded, "event", event)?;
+        }
+        if let Some(retry) = self.retry {
+            let retry = retry.as_millis().to_string();
+            encode_field(&mut encoded, "retry", &retry)?;
+        }
+        // JSON escapes line breaks within strings, so the data always fits on
+        // one line.
+        let data = serde_json::to_string(&self.data)
+            .map_err(|e| format!("failed to serialize event data: {}", e))?;
+        encode_field(&mut encoded, "data", &data)?;
+        encoded.push('\n');
+        Ok(Bytes::from(encoded))
+    }
+}
+
+fn encode_field(
+    encoded: &mut String,
+    name: &str,
+    value: &str,
+) -> Result<(), String> {
+    if value.contains(['\r', '\n']) {
+        return Err(format!(
+            "{:?} field of event contains a line break: {:?}",
+            name, value
+        ));
+    }
+    encoded.push_str(name);
+    encoded.push_str(": ");
+    encoded.push_str(value);
+    encoded.push('\n');
+    Ok(())
+}
+
+/// `HttpResponseSse<T>` is a 200 response that streams server-sent events
+/// (`text/event-stream`), each carrying data of type `T`, to the client.  The
+/// stream ends the response when it ends.
+///
+/// While no events are being sent, a comment is sent every 15 seconds (see
+/// [`HttpResponseSse::keep_alive()`]) so that proxies don't close the idle
+/// connection.  The response asks caches and proxies not to store or buffer
+/// the stream.  In the OpenAPI document, the response is described as a
+/// `text/event-stream` whose content is the schema of `T`.
+///
+/// An event that can't be encoded (e.g., because its id contains a line break
+/// or its data can't be serialized) ends the response abruptly, since there's
+/// no way to report an error once it has begun.
+///
+/// ```
+/// use dropshot::endpoint;
+/// use dropshot::HttpError;
+/// use dropshot::HttpResponseSse;
+/// use dropshot::RequestContext;
+/// use dropshot::SseEvent;
+/// use futures::StreamExt;
+///
+/// #[endpoint {
+///     method = GET,
+///     path = "/ticks",
+/// }]
+/// async fn ticks_get(
+///     _rqctx: RequestContext<()>,
+/// ) -> Result<HttpResponseSse<u64>, HttpError> {
+///     let events = futures::stream::iter(0..10)
+///         .map(|i| SseEvent::new(i).id(i.to_string()).event("tick"));
+///     Ok(HttpResponseSse::new(events))
+/// }
+/// ```
+pub struct HttpResponseSse<T> {
+    events: SyncWrapper<BoxStream<'static, SseEvent<T>>>,
+    keep_alive: Option<Duration>,
+}
+
+impl<T: Serialize + Send + 'static> HttpResponseSse<T> {
+    /// Returns a response that sends each event of `events`.
+    pub fn new<S>(events: S) -> HttpResponseSse<T>
+    where
+        S: Stream<Item = SseEvent<T>> + Send + 'static,
+    {
+        HttpResponseSse {
+            events: SyncWrapper::new(events.boxed()),
+            keep_alive: Some(DEFAULT_KEEP_ALIVE),
+        }
+    }
+
+    /// Sets how long the stream may be idle before a comment is sent to keep
+    /// the connection alive, or disables keep-alive comments if `None`.
+    pub fn keep_alive(mut self, interval: Option<Duration>) -> Self {
+        self.keep_alive = interval;
+        self
+    }
+}
+
+/// Returns the encoded stream of `events`, with a comment whenever the stream
+/// has been idle for `keep_alive`.
+fn encode_events<T: Serialize + Send + 'static>(
+    mut events: BoxStream<'static, SseEvent<T>>,
+    keep_alive: Option<Duration>,
+) -> impl Stream<Item = Result<Bytes, BoxError>> + Send + 'static {
+    stream! {
+        loop {
+            let next = match keep_alive {
+                Some(interval) => {
+                    match tokio::time::timeout(interval, events.next()).await {
+                        Ok(next) => next,
+                        Err(_) => {
+                            yield Ok(Bytes::from_static(b":\n\n"));
+                            continue;
+                        }
+                    }
+                }
+                None => events.next().await,
+            };
+            match next.map(|event| event.encode()) {
+                Some(Ok(encoded)) => yield Ok(encoded),
+                Some(Err(message)) => {
+                    yield Err(BoxError::from(message));
+                    break;
+                }
+                None => break,
+            }
+        }
+    }
+}
+
+impl<T> HttpResponse for HttpResponseSse<T>
+where
+    T: JsonSchema + Serialize + Send + 'static,
+{
+    fn to_result(self) -> HttpHandlerResult {
+        let events = encode_events(self.events.into_inner(), self.keep_alive);
+        Ok(Response::builder()
+            .status(StatusCode::OK)
+            .header(http::header::CONTENT_TYPE, CONTENT_TYPE_EVENT_STREAM)
+            .header(http::header::CACHE_CONTROL, "no-cache")
+            // Asks reverse proxies (e.g., nginx) to pass the events along as
+            // they're sent rather than buffering the response.
+            .header("x-accel-buffering", "no")
+            .body(Body::wrap_stream(events))?)
+    }
+
+    fn response_metadata() -> ApiEndpointResponse {
+        ApiEndpointResponse {
+            schema: Some(ApiSchemaGenerator::Gen {
+                name: T::schema_name,
+                schema: make_subschema_for::<T>,
+            }),
+            content_type: Some(CONTENT_TYPE_EVENT_STREAM.to_string()),
+            success: Some(StatusCode::OK),
+            description: Some(String::from("stream of server-sent events")),
+            ..Default::default()
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::SseEvent;
+    use std::time::Duration;
+
+    #[test]
+    fn test_sse_encode() {
+        assert_eq!(SseEvent::new(3).encode().unwrap(), "data: 3\n\n");
+        assert_eq!(
+            SseEvent::new("a\nb")
+                .id("7")
+                .event("update")
+                .retry(Duration::from_secs(2))
+                .encode()
+                .unwrap(),
+            "id: 7\nevent: update\nretry: 2000\ndata: \"a\\nb\"\n\n"
+        );
+
+        assert_eq!(
+            SseEvent::new(()).event("a\r\nb").encode().unwrap_err(),
+            "\"event\" field of event contains a line break: \"a\\r\\nb\""
+        );
+        assert_eq!(
+            SseEvent::new(()).id("a\0").encode().unwrap_err(),
+            "\"id\" field of event contains a NUL: \"a\\0\""
+        );
+    }
+}
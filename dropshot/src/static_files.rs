@@ -0,0 +1,322 @@
+// Copyright 2023 Oxide Computer Company
+//! Serving files from a directory
+//!
+//! With the `static-files` feature enabled,
+//! [`ApiDescription::register_static_files()`] registers a `GET` endpoint that
+//! serves the files under a directory on disk at every path under a given
+//! prefix.  For example, with the prefix `"/assets"`, a request for
+//! `/assets/css/site.css` is served from `css/site.css` under the directory.
+//!
+//! * The `Content-Type` of each file is guessed from its extension, falling
+//!   back to `application/octet-stream`.
+//! * Responses carry `Last-Modified` and (weak) `ETag` headers derived from the
+//!   file's modification time and size, and conditional requests
+//!   (`If-None-Match` or `If-Modified-Since`) for an unchanged file get a 304
+//!   ("Not Modified") response without a body.  These headers are evaluated
+//!   as they are by [`Preconditions`](crate::Preconditions), so `If-Match`
+//!   (which the weak entity tags never satisfy) gets a 412 ("Precondition
+//!   Failed") response.
+//! * A request for a directory is served the first of its index files (see
+//!   [`StaticFiles::index_file()`]) that exists.  Without index files,
+//!   directories are not served.
+//! * With [`StaticFiles::precompressed()`], a file with a pre-compressed
+//!   sibling (e.g., `app.js.br` or `app.js.gz` next to `app.js`) is served
+//!   from that sibling, with the corresponding `Content-Encoding`, to clients
+//!   whose `Accept-Encoding` allows it.  These responses carry
+//!   `Vary: Accept-Encoding`, and each variant has its own `ETag`.
+//! * Nothing outside of the directory is ever served: the router rejects paths
+//!   with `.` or `..` segments, and a file reached through a symbolic link
+//!   must still be within the directory.
+//!
+//! Files (and directories without an index file) that don't exist get a 404
+//! ("Not Found") response.
+//!
+//! ```
+//! use dropshot::static_files::StaticFiles;
+//! use dropshot::ApiDescription;
+//!
+//! let mut api = ApiDescription::<()>::new();
+//! let files = StaticFiles::new("/srv/www").index_file("index.html");
+//! api.register_static_files("/assets", files).unwrap();
+//! ```
+//!
+//! This endpoint is not included in the OpenAPI document.
+
+use crate::api_description::ApiDescription;
+use crate::api_description::ApiEndpoint;
+use crate::conditional::http_date;
+use crate::conditional::precondition_failed;
+use crate::conditional::EntityTag;
+use crate::conditional::Precondition;
+use crate::config::CompressionCoding;
+use crate::error::HttpError;
+use crate::extractor::Path;
+use crate::extractor::Preconditions;
+use crate::handler::RequestContext;
+use crate::http_util::negotiate_content_coding;
+use crate::http_util::CONTENT_TYPE_JSON;
+use crate::http_util::CONTENT_TYPE_OCTET_STREAM;
+use crate::server::ServerContext;
+use crate::Body;
+use crate::SafePath;
+
+use async_stream::try_stream;
+use bytes::BytesMut;
+use camino::Utf8PathBuf;
+use chrono::DateTime;
+use chrono::Utc;
+use http::header;
+use http::HeaderMap;
+use http::Method;
+use http::StatusCode;
+use hyper::Response;
+use schemars::JsonSchema;
+use serde::Deserialize;
+use std::fs::Metadata;
+use std::io;
+use std::path::Path;
+use std::path::PathBuf;
+use std::sync::Arc;
+use tokio::io::AsyncReadExt;
+
+/// Size of the chunks in which files are read and sent
+const CHUNK_SIZE: usize = 64 * 1024;
+
+/// Directory served by the endpoint registered with
+/// [`ApiDescription::register_static_files()`]
+#[derive(Clone, Debug)]
+pub struct StaticFiles {
+    root: Utf8PathBuf,
+    index_files: Vec<String>,
+    precompressed: bool,
+}
+
+impl StaticFiles {
+    /// Returns a configuration that serves the files under `root`.  The
+    /// directory is only accessed when requests arrive, so it need not exist
+    /// yet.
+    pub fn new<P: Into<Utf8PathBuf>>(root: P) -> Self {
+        StaticFiles {
+            root: root.into(),
+            index_files: Vec::new(),
+            precompressed: false,
+        }
+    }
+
+    /// Adds a file name (e.g., `index.html`) to look for when a directory is
+    /// requested.  Index files are tried in the order they were added.
+    pub fn index_file(mut self, name: &str) -> Self {
+        self.index_files.push(name.to_string());
+        self
+    }
+
+    /// Sets whether to serve pre-compressed siblings of files (`.br`, `.zst`,
+    /// and `.gz`, for the `br`, `zstd`, and `gzip` codings) to clients that
+    /// accept them.  The client's preferences (its `q` values) decide among
+    /// the siblings that exist, with ties going to the first in that list.
+    pub fn precompressed(mut self, precompressed: bool) -> Self {
+        self.precompressed = precompressed;
+        self
+    }
+}
+
+/// Suffixes of the pre-compressed siblings of a file, with the coding of each,
+/// in order of preference
+const PRECOMPRESSED_SUFFIXES: [(&str, CompressionCoding); 3] = [
+    ("br", CompressionCoding::Br),
+    ("zst", CompressionCoding::Zstd),
+    ("gz", CompressionCoding::Gzip),
+];
+
+/// The segments of the request path under the endpoint's prefix
+#[derive(Deserialize, JsonSchema)]
+struct FilePath {
+    path: SafePath,
+}
+
+impl<Context: ServerContext> ApiDescription<Context> {
+    /// Registers an endpoint that serves the files configured by `files` at
+    /// every path under `path` (e.g., `"/assets"`, or `"/"` for all paths that
+    /// no other endpoint handles).  See the [`crate::static_files`]
+    /// module-level documentation for details.
+    pub fn register_static_files(
+        &mut self,
+        path: &str,
+        files: StaticFiles,
+    ) -> Result<(), String> {
+        let files = Arc::new(files);
+        let path = format!("{}/{{path:.*}}", path.trim_end_matches('/'));
+        self.register(
+            ApiEndpoint::new(
+                "static_files".to_string(),
+                move |rqctx: RequestContext<Context>,
+                      path: Path<FilePath>,
+                      preconditions: Preconditions| {
+                    let files = Arc::clone(&files);
+                    async move {
+                        let path = path.into_inner().path;
+                        let headers = rqctx.request.headers();
+                        serve_file(&files, &path, headers, &preconditions).await
+                    }
+                },
+                Method::GET,
+                CONTENT_TYPE_JSON,
+                &path,
+            )
+            .visible(false),
+        )
+    }
+}
+
+async fn serve_file(
+    files: &StaticFiles,
+    path: &SafePath,
+    headers: &HeaderMap,
+    preconditions: &Preconditions,
+) -> Result<Response<Body>, HttpError> {
+    let root = tokio::fs::canonicalize(&files.root).await.map_err(|e| {
+        HttpError::for_internal_error(format!(
+            "failed to resolve static file directory {:?}: {}",
+            files.root, e
+        ))
+    })?;
+    let not_found = || {
+        HttpError::for_not_found(
+            None,
+            format!("no file at {:?}", path.as_str()),
+        )
+    };
+
+    // Resolving symbolic links (and then checking that we're still under the
+    // root) keeps links from exposing files outside of the directory.
+    let mut file_path = tokio::fs::canonicalize(root.join(path.as_path()))
+        .await
+        .map_err(|_| not_found())?;
+    if !file_path.starts_with(&root) {
+        return Err(not_found());
+    }
+    let mut metadata =
+        tokio::fs::metadata(&file_path).await.map_err(io_error)?;
+    if metadata.is_dir() {
+        // Index files may be links, too.
+        let mut index = None;
+        for name in &files.index_files {
+            index = resolve_file(&root, &file_path.join(name)).await;
+            if index.is_some() {
+                break;
+            }
+        }
+        (file_path, metadata) = index.ok_or_else(not_found)?;
+    }
+    if !metadata.is_file() {
+        return Err(not_found());
+    }
+
+    let content_type = mime_guess::from_path(&file_path)
+        .first_raw()
+        .unwrap_or(CONTENT_TYPE_OCTET_STREAM);
+    let mut content_encoding = None;
+    if files.precompressed {
+        if let Some((path, m, coding)) =
+            precompressed_sibling(&root, &file_path, headers).await
+        {
+            (file_path, metadata) = (path, m);
+            content_encoding = Some(coding.name());
+        }
+    }
+
+    let modified = metadata.modified().ok().map(DateTime::<Utc>::from);
+    let etag = entity_tag(metadata.len(), modified);
+    let mut builder =
+        Response::builder().header(header::ETAG, etag.to_string());
+    if let Some(modified) = modified {
+        builder = builder.header(header::LAST_MODIFIED, http_date(modified));
+    }
+    if files.precompressed {
+        builder = builder.header(header::VARY, "accept-encoding");
+    }
+    match preconditions.evaluate(Some(&etag), modified) {
+        Precondition::Proceed => (),
+        Precondition::NotModified => {
+            return Ok(builder
+                .status(StatusCode::NOT_MODIFIED)
+                .body(Body::empty())?);
+        }
+        Precondition::Failed => return Err(precondition_failed()),
+    }
+
+    if let Some(content_encoding) = content_encoding {
+        builder = builder.header(header::CONTENT_ENCODING, content_encoding);
+    }
+    let file = tokio::fs::File::open(&file_path).await.map_err(io_error)?;
+    Ok(builder
+        .status(StatusCode::OK)
+        .header(header::CONTENT_TYPE, content_type)
+        .header(header::CONTENT_LENGTH, metadata.len())
+        .body(file_body(file))?)
+}
+
+/// Returns the path, metadata, and coding of the pre-compressed sibling of the
+/// file at `file_path` that the client prefers, if there is one.
+async fn precompressed_sibling(
+    root: &Path,
+    file_path: &Path,
+    headers: &HeaderMap,
+) -> Option<(PathBuf, Metadata, CompressionCoding)> {
+    let name = file_path.file_name()?;
+    let mut siblings = Vec::new();
+    for (suffix, coding) in PRECOMPRESSED_SUFFIXES {
+        let mut sibling_name = name.to_os_string();
+        sibling_name.push(".");
+        sibling_name.push(suffix);
+        if let Some(found) =
+            resolve_file(root, &file_path.with_file_name(sibling_name)).await
+        {
+            siblings.push((found, coding));
+        }
+    }
+
+    let codings = siblings.iter().map(|(_, c)| *c).collect::<Vec<_>>();
+    let coding = negotiate_content_coding(headers, &codings)?;
+    let ((path, metadata), _) =
+        siblings.into_iter().find(|(_, c)| *c == coding)?;
+    Some((path, metadata, coding))
+}
+
+/// Returns the resolved path and metadata of the regular file at `path`, if
+/// there is one and it's within `root` once symbolic links are resolved.
+async fn resolve_file(root: &Path, path: &Path) -> Option<(PathBuf, Metadata)> {
+    let path = tokio::fs::canonicalize(path).await.ok()?;
+    if !path.starts_with(root) {
+        return None;
+    }
+    let metadata = tokio::fs::metadata(&path).await.ok()?;
+    metadata.is_file().then_some((path, metadata))
+}
+
+fn io_error(error: io::Error) -> HttpError {
+    HttpError::for_internal_error(format!("failed to read file: {}", error))
+}
+
+/// Returns a body that streams the contents of `file`.
+fn file_body(mut file: tokio::fs::File) -> Body {
+    let chunks = try_stream! {
+        loop {
+            let mut chunk = BytesMut::with_capacity(CHUNK_SIZE);
+            if file.read_buf(&mut chunk).await? == 0 {
+                break;
+            }
+            yield chunk.freeze();
+        }
+    };
+    Body::wrap_stream::<_, _, io::Error>(chunks)
+}
+
+/// Returns a weak entity tag for a file of size `len` last modified at
+/// `modified`.  (It's weak because files with the same size and modification
+/// time may still differ.)
+fn entity_tag(len: u64, modified: Option<DateTime<Utc>>) -> EntityTag {
+    let (secs, nanos) = modified
+        .map_or((0, 0), |m| (m.timestamp(), m.timestamp_subsec_nanos()));
+    EntityTag::weak(format!("{:x}-{:x}.{:x}", len, secs, nanos))
+}
@@ -0,0 +1,523 @@
+// Copyright 2023 Oxide Computer Company
+//! Strict parsing of HTTP/1 requests
+//!
+//! hyper's request parser is lenient in a few ways that matter when a server
+//! sits behind proxies that may frame requests differently (e.g., it ignores
+//! `Content-Length` when `Transfer-Encoding` is also present).  When strict
+//! parsing is enabled, the bytes received on each connection are inspected
+//! before hyper sees them.  When a request violates one of the configured
+//! checks, hyper is handed `REJECTION` in place of the offending byte,
+//! followed by the end of the stream, so that hyper rejects the request itself
+//! (with a 400 if it hasn't begun reading its body) and closes the connection.
+
+use crate::config::ConfigStrictHttp;
+
+use std::io;
+use std::pin::Pin;
+use std::sync::atomic::AtomicU64;
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
+use std::task::Context;
+use std::task::Poll;
+use tokio::io::AsyncRead;
+use tokio::io::AsyncWrite;
+use tokio::io::ReadBuf;
+
+/// Longest request line or header line that's inspected.  Requests with longer
+/// lines aren't inspected any further.
+const MAX_LINE_LEN: usize = 8192;
+
+/// Bytes that replace the rest of the stream once a request is rejected.  The
+/// NUL byte can't appear anywhere in a well-formed request head, and the blank
+/// line that follows it makes hyper parse the head right away rather than
+/// waiting for more of it.
+const REJECTION: &[u8] = b"\0\r\n\r\n";
+
+/// Counts of requests rejected by strict HTTP parsing (see
+/// [`ConfigStrictHttp`]), by reason
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct StrictHttpRejections {
+    /// requests with both `Content-Length` and `Transfer-Encoding` headers
+    pub content_length_with_transfer_encoding: u64,
+    /// requests whose headers use obsolete line folding
+    pub obs_fold: u64,
+    /// requests whose head contains a CR that isn't followed by LF
+    pub bare_cr: u64,
+    /// requests whose chunk extensions exceed the configured size
+    pub chunk_extensions_too_long: u64,
+}
+
+/// Ways in which a request can fail strict parsing
+#[derive(Clone, Copy, Debug)]
+enum Violation {
+    ContentLengthWithTransferEncoding,
+    ObsFold,
+    BareCr,
+    ChunkExtensionsTooLong,
+}
+
+/// Strict parsing configuration and rejection counts for a server
+#[derive(Debug, Default)]
+pub(crate) struct StrictHttp {
+    config: Option<ConfigStrictHttp>,
+    rejections: Arc<[AtomicU64; 4]>,
+}
+
+impl StrictHttp {
+    pub fn new(config: Option<&ConfigStrictHttp>) -> StrictHttp {
+        StrictHttp { config: config.cloned(), rejections: Default::default() }
+    }
+
+    pub fn rejections(&self) -> StrictHttpRejections {
+        let count =
+            |v: Violation| self.rejections[v as usize].load(Ordering::Relaxed);
+        StrictHttpRejections {
+            content_length_with_transfer_encoding: count(
+                Violation::ContentLengthWithTransferEncoding,
+            ),
+            obs_fold: count(Violation::ObsFold),
+            bare_cr: count(Violation::BareCr),
+            chunk_extensions_too_long: count(Violation::ChunkExtensionsTooLong),
+        }
+    }
+
+    /// Wraps the stream for a new connection so that the requests received on
+    /// it are inspected (if strict parsing is enabled).
+    pub fn wrap<I>(&self, stream: I) -> StrictHttpStream<I> {
+        let inspector = self.config.as_ref().map(|config| Inspector {
+            config: config.clone(),
+            rejections: Arc::clone(&self.rejections),
+            state: State::Head(Head::default()),
+            extension_bytes: 0,
+        });
+        StrictHttpStream { inner: stream, inspector }
+    }
+}
+
+/// Connection stream whose incoming requests are inspected before they're
+/// read by hyper
+pub(crate) struct StrictHttpStream<I> {
+    inner: I,
+    inspector: Option<Inspector>,
+}
+
+impl<I: AsyncRead + Unpin> AsyncRead for StrictHttpStream<I> {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        let inspector = match &mut this.inspector {
+            None => return Pin::new(&mut this.inner).poll_read(cx, buf),
+            Some(inspector) => inspector,
+        };
+        if let State::Rejected(unsent) = &mut inspector.state {
+            let n = unsent.len().min(buf.remaining());
+            buf.put_slice(&unsent[..n]);
+            *unsent = &unsent[n..];
+            return Poll::Ready(Ok(()));
+        }
+        let before = buf.filled().len();
+        match Pin::new(&mut this.inner).poll_read(cx, buf) {
+            Poll::Ready(Ok(())) => (),
+            other => return other,
+        }
+        if let Some(offset) = inspector.inspect(&buf.filled()[before..]) {
+            // At least the offending byte's space is available, so this
+            // always returns some bytes (rather than signaling the end of the
+            // stream) even if the offending byte was the first one read.
+            buf.set_filled(before + offset);
+            let n = REJECTION.len().min(buf.remaining());
+            buf.put_slice(&REJECTION[..n]);
+            inspector.state = State::Rejected(&REJECTION[n..]);
+        }
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl<I: AsyncWrite + Unpin> AsyncWrite for StrictHttpStream<I> {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        Pin::new(&mut self.get_mut().inner).poll_write(cx, buf)
+    }
+
+    fn poll_write_vectored(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        bufs: &[io::IoSlice<'_>],
+    ) -> Poll<io::Result<usize>> {
+        Pin::new(&mut self.get_mut().inner).poll_write_vectored(cx, bufs)
+    }
+
+    fn is_write_vectored(&self) -> bool {
+        self.inner.is_write_vectored()
+    }
+
+    fn poll_flush(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.get_mut().inner).poll_flush(cx)
+    }
+
+    fn poll_shutdown(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.get_mut().inner).poll_shutdown(cx)
+    }
+}
+
+/// Tracks the framing of the requests received on a connection
+struct Inspector {
+    config: ConfigStrictHttp,
+    rejections: Arc<[AtomicU64; 4]>,
+    state: State,
+    /// total size of the chunk extensions in the current request body
+    extension_bytes: usize,
+}
+
+enum State {
+    /// reading a request's head
+    Head(Head),
+    /// skipping the given number of remaining bytes of a request body
+    Body(u64),
+    /// reading the line that begins a chunk of a chunked request body
+    ChunkSize { size: u64, in_extension: bool },
+    /// skipping the given number of remaining bytes of a chunk's data
+    ChunkData(u64),
+    /// skipping the line ending that follows a chunk's data
+    ChunkDataEnd,
+    /// reading the trailer section of a chunked request body, given the
+    /// length of the current line
+    Trailers(usize),
+    /// no longer inspecting the connection (e.g., because it's being upgraded
+    /// or is using HTTP/2)
+    Passthrough,
+    /// a request was rejected, so nothing more is read from the connection;
+    /// the rest of `REJECTION` remains to be returned in its place
+    Rejected(&'static [u8]),
+}
+
+#[derive(Default)]
+struct Head {
+    /// contents of the current line (up to `MAX_LINE_LEN` bytes, without CRs)
+    line: Vec<u8>,
+    /// length of the current line (without CRs)
+    line_len: usize,
+    /// number of lines read so far (not counting empty lines preceding the
+    /// request line)
+    nlines: usize,
+    /// whether the last byte read was a CR
+    last_cr: bool,
+    content_length: Option<u64>,
+    transfer_encoding: bool,
+    chunked: bool,
+    /// whether the request asks to upgrade the connection
+    upgrade: bool,
+    /// whether the head couldn't be interpreted well enough to follow the
+    /// request's framing
+    opaque: bool,
+}
+
+impl Inspector {
+    /// Inspects the next bytes received on the connection, returning the
+    /// offset of the first byte that makes the request invalid (if any).
+    fn inspect(&mut self, data: &[u8]) -> Option<usize> {
+        let mut i = 0;
+        while i < data.len() {
+            let remaining = (data.len() - i) as u64;
+            match &mut self.state {
+                State::Passthrough | State::Rejected(_) => break,
+                State::Body(n) => {
+                    let skip = (*n).min(remaining);
+                    *n -= skip;
+                    i += skip as usize;
+                    if *n == 0 {
+                        self.state = State::Head(Head::default());
+                    }
+                }
+                State::ChunkData(n) => {
+                    let skip = (*n).min(remaining);
+                    *n -= skip;
+                    i += skip as usize;
+                    if *n == 0 {
+                        self.state = State::ChunkDataEnd;
+                    }
+                }
+                _ => {
+                    if let Err(violation) = self.byte(data[i]) {
+                        self.rejections[violation as usize]
+                            .fetch_add(1, Ordering::Relaxed);
+                        return Some(i);
+                    }
+                    i += 1;
+                }
+            }
+        }
+        None
+    }
+
+    fn byte(&mut self, b: u8) -> Result<(), Violation> {
+        match &mut self.state {
+            State::Head(head) => {
+                if head.last_cr && b != b'\n' && self.config.reject_bare_cr {
+                    return Err(Violation::BareCr);
+                }
+                head.last_cr = b == b'\r';
+                if b == b'\n' {
+                    let head = std::mem::take(head);
+                    return self.end_line(head);
+                }
+                if head.line_len == 0
+                    && head.nlines > 0
+                    && (b == b' ' || b == b'\t')
+                    && self.config.reject_obs_fold
+                {
+                    return Err(Violation::ObsFold);
+                }
+                if b != b'\r' {
+                    if head.line_len < MAX_LINE_LEN {
+                        head.line.push(b);
+                    }
+                    head.line_len += 1;
+                }
+            }
+            State::ChunkSize { size, in_extension } => {
+                if b == b'\n' {
+                    self.state = if *size == 0 {
+                        State::Trailers(0)
+                    } else {
+                        State::ChunkData(*size)
+                    };
+                } else if *in_extension {
+                    if b != b'\r' {
+                        self.extension_bytes += 1;
+                        if let Some(max) =
+                            self.config.chunk_extensions_max_bytes
+                        {
+                            if self.extension_bytes > max {
+                                return Err(Violation::ChunkExtensionsTooLong);
+                            }
+                        }
+                    }
+                } else if b == b';' {
+                    *in_extension = true;
+                } else if let Some(digit) = (b as char).to_digit(16) {
+                    match size
+                        .checked_mul(16)
+                        .and_then(|s| s.checked_add(u64::from(digit)))
+                    {
+                        Some(s) => *size = s,
+                        None => self.state = State::Passthrough,
+                    }
+                }
+            }
+            State::ChunkDataEnd => {
+                if b == b'\n' {
+                    self.state =
+                        State::ChunkSize { size: 0, in_extension: false };
+                }
+            }
+            State::Trailers(line_len) => {
+                if b == b'\n' {
+                    if *line_len == 0 {
+                        self.state = State::Head(Head::default());
+                    } else {
+                        *line_len = 0;
+                    }
+                } else if b != b'\r' {
+                    *line_len += 1;
+                }
+            }
+            State::Body(_)
+            | State::ChunkData(_)
+            | State::Passthrough
+            | State::Rejected(_) => (),
+        }
+        Ok(())
+    }
+
+    /// Handles the end of a line of a request's head.
+    fn end_line(&mut self, mut head: Head) -> Result<(), Violation> {
+        if head.line_len == 0 {
+            // Empty lines preceding the request line are ignored.
+            if head.nlines > 0 {
+                return self.end_head(head);
+            }
+            self.state = State::Head(head);
+            return Ok(());
+        } else if head.line_len > MAX_LINE_LEN {
+            head.opaque = true;
+        } else if head.nlines == 0 {
+            if head.line.starts_with(b"PRI * HTTP/2.0") {
+                self.state = State::Passthrough;
+                return Ok(());
+            }
+            head.upgrade = head.line.starts_with(b"CONNECT ");
+        } else {
+            let line = std::mem::take(&mut head.line);
+            let (name, value) = match line.iter().position(|b| *b == b':') {
+                Some(colon) => (&line[..colon], trim(&line[colon + 1..])),
+                None => (&line[..], &b""[..]),
+            };
+            if name.eq_ignore_ascii_case(b"content-length") {
+                if head.content_length.is_none() {
+                    match std::str::from_utf8(value)
+                        .ok()
+                        .and_then(|v| v.parse().ok())
+                    {
+                        Some(len) => head.content_length = Some(len),
+                        None => head.opaque = true,
+                    }
+                }
+            } else if name.eq_ignore_ascii_case(b"transfer-encoding") {
+                head.transfer_encoding = true;
+                head.chunked = value
+                    .rsplit(|b| *b == b',')
+                    .next()
+                    .map(trim)
+                    .map_or(false, |coding| {
+                        coding.eq_ignore_ascii_case(b"chunked")
+                    });
+            } else if name.eq_ignore_ascii_case(b"upgrade") {
+                head.upgrade = true;
+            }
+            head.line = line;
+        }
+        head.line.clear();
+        head.line_len = 0;
+        head.nlines += 1;
+        self.state = State::Head(head);
+        Ok(())
+    }
+
+    /// Handles the end of a request's head, setting up to follow its body.
+    fn end_head(&mut self, head: Head) -> Result<(), Violation> {
+        if head.transfer_encoding
+            && head.content_length.is_some()
+            && self.config.reject_content_length_with_transfer_encoding
+        {
+            return Err(Violation::ContentLengthWithTransferEncoding);
+        }
+        self.state = if head.upgrade || head.opaque {
+            State::Passthrough
+        } else if head.transfer_encoding {
+            if head.chunked {
+                self.extension_bytes = 0;
+                State::ChunkSize { size: 0, in_extension: false }
+            } else {
+                State::Passthrough
+            }
+        } else {
+            match head.content_length {
+                Some(len) if len > 0 => State::Body(len),
+                _ => State::Head(Head::default()),
+            }
+        };
+        Ok(())
+    }
+}
+
+/// Returns `bytes` without leading or trailing whitespace.
+fn trim(bytes: &[u8]) -> &[u8] {
+    let start = bytes.iter().position(|b| !b.is_ascii_whitespace());
+    let end = bytes.iter().rposition(|b| !b.is_ascii_whitespace());
+    match (start, end) {
+        (Some(start), Some(end)) => &bytes[start..=end],
+        _ => &[],
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::Inspector;
+    use super::State;
+    use super::StrictHttp;
+    use crate::config::ConfigStrictHttp;
+
+    fn new_inspector() -> Inspector {
+        StrictHttp::new(Some(&ConfigStrictHttp::default()))
+            .wrap(())
+            .inspector
+            .unwrap()
+    }
+
+    #[test]
+    fn test_well_formed_requests() {
+        let mut inspector = new_inspector();
+        let requests = b"GET / HTTP/1.1\r\nHost: a\r\n\r\n\
+            POST /x HTTP/1.1\r\nContent-Length: 5\r\n\r\nhello\
+            POST /y HTTP/1.1\r\nTransfer-Encoding: chunked\r\n\r\n\
+            5;name=value\r\nhello\r\n0\r\nTrailer: x\r\n\r\n\
+            GET / HTTP/1.1\r\n\r\n";
+        // Feeding the requests a byte at a time must give the same result as
+        // feeding them all at once.
+        for chunk in requests.chunks(1) {
+            assert_eq!(inspector.inspect(chunk), None);
+        }
+        assert!(matches!(inspector.state, State::Head(_)));
+        let mut inspector = new_inspector();
+        assert_eq!(inspector.inspect(requests), None);
+        assert!(matches!(inspector.state, State::Head(_)));
+    }
+
+    #[test]
+    fn test_violations() {
+        let head = b"POST / HTTP/1.1\r\nContent-Length: 3\r\n\
+            Transfer-Encoding: chunked\r\n\r\n";
+        let mut inspector = new_inspector();
+        assert_eq!(inspector.inspect(head), Some(head.len() - 1));
+
+        let mut inspector = new_inspector();
+        assert_eq!(
+            inspector.inspect(b"GET / HTTP/1.1\r\nX-A: b\r\n c\r\n\r\n"),
+            Some(24)
+        );
+
+        let mut inspector = new_inspector();
+        assert_eq!(
+            inspector.inspect(b"GET / HTTP/1.1\r\nX-A: b\rc\r\n"),
+            Some(23)
+        );
+
+        let mut inspector = new_inspector();
+        let body = format!("1;{}\r\na\r\n0\r\n\r\n", "x".repeat(2000));
+        let head = b"POST / HTTP/1.1\r\nTransfer-Encoding: chunked\r\n\r\n";
+        assert_eq!(inspector.inspect(head), None);
+        assert_eq!(inspector.inspect(body.as_bytes()), Some(1026));
+
+        // Checks that are disabled aren't applied.
+        let config = ConfigStrictHttp {
+            reject_content_length_with_transfer_encoding: false,
+            chunk_extensions_max_bytes: None,
+            ..Default::default()
+        };
+        let mut inspector =
+            StrictHttp::new(Some(&config)).wrap(()).inspector.unwrap();
+        let request = b"POST / HTTP/1.1\r\nContent-Length: 3\r\n\
+            Transfer-Encoding: chunked\r\n\r\n1;xxxxxxxx\r\na\r\n0\r\n\r\n";
+        assert_eq!(inspector.inspect(request), None);
+        assert!(matches!(inspector.state, State::Head(_)));
+    }
+
+    #[test]
+    fn test_passthrough() {
+        let mut inspector = new_inspector();
+        let request = b"GET /ws HTTP/1.1\r\nUpgrade: websocket\r\n\
+            Connection: upgrade\r\n\r\n\x81\x05hello\r\x00";
+        assert_eq!(inspector.inspect(request), None);
+        assert!(matches!(inspector.state, State::Passthrough));
+
+        let mut inspector = new_inspector();
+        assert_eq!(
+            inspector.inspect(b"PRI * HTTP/2.0\r\n\r\nSM\r\n\r\n"),
+            None
+        );
+        assert!(matches!(inspector.state, State::Passthrough));
+    }
+}
@@ -0,0 +1,171 @@
+// Copyright 2023 Oxide Computer Company
+//! Resolution of the tenant on whose behalf a request is made
+
+use crate::error::HttpError;
+use crate::Body;
+
+use hyper::Request;
+use std::fmt::Debug;
+
+/// Tenant identified by a [`TenantResolver`]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct ResolvedTenant {
+    /// identifier of the tenant
+    pub tenant: String,
+    /// If present, the request is routed using this path instead of the
+    /// request's own path (e.g., with the tenant's path prefix removed)
+    pub route_path: Option<String>,
+}
+
+impl ResolvedTenant {
+    pub fn new<T: ToString>(tenant: T) -> ResolvedTenant {
+        ResolvedTenant { tenant: tenant.to_string(), route_path: None }
+    }
+}
+
+/// Derives the tenant on whose behalf a request is made before the request is
+/// routed.  A server is configured with a resolver using
+/// [`ApiDescription::tenant_resolver()`](crate::ApiDescription::tenant_resolver).
+///
+/// The resolved tenant is available to handlers via
+/// [`RequestContext::tenant()`](crate::RequestContext::tenant), is included as
+/// `tenant` in the request's log entries, and scopes idempotency keys and
+/// request coalescing so that requests from different tenants never share
+/// results.
+///
+/// [`TenantSource`] implements the common cases.
+pub trait TenantResolver: Debug + Send + Sync {
+    /// Returns the tenant for `request`, or `None` if it doesn't identify
+    /// one.  Returning an error fails the request without routing it.
+    fn resolve(
+        &self,
+        request: &Request<Body>,
+    ) -> Result<Option<ResolvedTenant>, HttpError>;
+}
+
+/// Built-in ways of identifying a request's tenant
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum TenantSource {
+    /// The tenant is the part of the request's host name that precedes
+    /// `.suffix` (e.g., `"acme"` for `acme.example.com` with suffix
+    /// `"example.com"`).  Requests for other hosts have no tenant.
+    Host { suffix: String },
+    /// The tenant is the value of the given request header.  Requests without
+    /// the header have no tenant.
+    Header(String),
+    /// The tenant is the first segment of the request path (e.g., `"acme"` for
+    /// `/acme/projects`), and the request is routed using the rest of the path
+    /// (`/projects`).  Endpoints are therefore registered without the prefix.
+    PathPrefix,
+}
+
+impl TenantResolver for TenantSource {
+    fn resolve(
+        &self,
+        request: &Request<Body>,
+    ) -> Result<Option<ResolvedTenant>, HttpError> {
+        Ok(match self {
+            TenantSource::Host { suffix } => {
+                request_host(request).and_then(|host| {
+                    host.strip_suffix(suffix.as_str())?
+                        .strip_suffix('.')
+                        .filter(|tenant| !tenant.is_empty())
+                        .map(ResolvedTenant::new)
+                })
+            }
+            TenantSource::Header(name) => request
+                .headers()
+                .get(name.as_str())
+                .and_then(|value| value.to_str().ok())
+                .filter(|tenant| !tenant.is_empty())
+                .map(ResolvedTenant::new),
+            TenantSource::PathPrefix => {
+                let path = request.uri().path().trim_start_matches('/');
+                let (tenant, rest) = match path.find('/') {
+                    Some(i) => (&path[..i], &path[i..]),
+                    None => (path, "/"),
+                };
+                if tenant.is_empty() {
+                    None
+                } else {
+                    Some(ResolvedTenant {
+                        tenant: tenant.to_string(),
+                        route_path: Some(rest.to_string()),
+                    })
+                }
+            }
+        })
+    }
+}
+
+/// Returns the host name (without the port) to which the request was sent.
+fn request_host(request: &Request<Body>) -> Option<&str> {
+    let host = request
+        .uri()
+        .host()
+        .or_else(|| request.headers().get(http::header::HOST)?.to_str().ok())?;
+    // Strip the port, taking care not to mangle bracketed IPv6 literals.
+    Some(match host.rfind(':') {
+        Some(i) if !host[i..].contains(']') => &host[..i],
+        _ => host,
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::ResolvedTenant;
+    use super::TenantResolver;
+    use super::TenantSource;
+    use crate::Body;
+    use hyper::Request;
+
+    fn request(uri: &str, host: &str) -> Request<Body> {
+        Request::builder()
+            .uri(uri)
+            .header(http::header::HOST, host)
+            .header("x-tenant", "globex")
+            .body(Body::empty())
+            .unwrap()
+    }
+
+    #[test]
+    fn test_tenant_sources() {
+        let by_host =
+            TenantSource::Host { suffix: String::from("example.com") };
+        assert_eq!(
+            by_host.resolve(&request("/", "acme.example.com:8080")).unwrap(),
+            Some(ResolvedTenant::new("acme"))
+        );
+        assert_eq!(
+            by_host.resolve(&request("/", "example.com")).unwrap(),
+            None
+        );
+        assert_eq!(
+            by_host.resolve(&request("/", "acme.example.org")).unwrap(),
+            None
+        );
+
+        let by_header = TenantSource::Header(String::from("x-tenant"));
+        assert_eq!(
+            by_header.resolve(&request("/", "localhost")).unwrap(),
+            Some(ResolvedTenant::new("globex"))
+        );
+
+        let by_path = TenantSource::PathPrefix;
+        assert_eq!(
+            by_path.resolve(&request("/acme/projects/1", "localhost")).unwrap(),
+            Some(ResolvedTenant {
+                tenant: String::from("acme"),
+                route_path: Some(String::from("/projects/1")),
+            })
+        );
+        assert_eq!(
+            by_path.resolve(&request("/acme", "localhost")).unwrap(),
+            Some(ResolvedTenant {
+                tenant: String::from("acme"),
+                route_path: Some(String::from("/")),
+            })
+        );
+        assert_eq!(by_path.resolve(&request("/", "localhost")).unwrap(), None);
+    }
+}
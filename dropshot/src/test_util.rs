@@ -6,14 +6,13 @@ use camino::Utf8PathBuf;
 use chrono::DateTime;
 use chrono::Utc;
 use http::method::Method;
-use hyper::body::to_bytes;
-use hyper::client::HttpConnector;
-use hyper::Body;
-use hyper::Client;
 use hyper::Request;
 use hyper::Response;
 use hyper::StatusCode;
 use hyper::Uri;
+use hyper_util::client::legacy::connect::HttpConnector;
+use hyper_util::client::legacy::Client;
+use hyper_util::rt::TokioExecutor;
 use serde::de::DeserializeOwned;
 use serde::Deserialize;
 use serde::Serialize;
@@ -34,6 +33,8 @@ use crate::http_util::CONTENT_TYPE_URL_ENCODED;
 use crate::logging::ConfigLogging;
 use crate::pagination::ResultsPage;
 use crate::server::{HttpServer, HttpServerStarter, ServerContext};
+use crate::Body;
+use crate::BoxError;
 
 enum AllowedValue<'a> {
     Any,
@@ -56,11 +57,22 @@ pub const TEST_HEADER_2: &str = "x-dropshot-test-header-2";
 
 // List of allowed HTTP headers in responses.
 // Used to make sure we don't leak headers unexpectedly.
-const ALLOWED_HEADERS: [AllowedHeader<'static>; 8] = [
+const ALLOWED_HEADERS: [AllowedHeader<'static>; 19] = [
+    AllowedHeader::new("allow"),
+    AllowedHeader::new("cache-control"),
+    AllowedHeader::new("content-disposition"),
+    AllowedHeader::new("content-encoding"),
     AllowedHeader::new("content-length"),
     AllowedHeader::new("content-type"),
     AllowedHeader::new("date"),
+    AllowedHeader::new("etag"),
+    AllowedHeader::new("idempotent-replayed"),
+    AllowedHeader::new("last-modified"),
     AllowedHeader::new("location"),
+    AllowedHeader::new("retry-after"),
+    AllowedHeader::new("trailer"),
+    AllowedHeader::new("vary"),
+    AllowedHeader::new("x-accel-buffering"),
     AllowedHeader::new("x-request-id"),
     AllowedHeader {
         name: "transfer-encoding",
@@ -76,7 +88,7 @@ pub struct ClientTestContext {
     /// actual bind address of the HTTP server under test
     pub bind_address: SocketAddr,
     /// HTTP client, used for making requests against the test server
-    pub client: Client<HttpConnector>,
+    pub client: Client<HttpConnector, Body>,
     /// logger for the test suite HTTP client
     pub client_log: Logger,
 }
@@ -86,7 +98,7 @@ impl ClientTestContext {
     pub fn new(server_addr: SocketAddr, log: Logger) -> ClientTestContext {
         ClientTestContext {
             bind_address: server_addr,
-            client: Client::new(),
+            client: Client::builder(TokioExecutor::new()).build_http(),
             client_log: log,
         }
     }
@@ -246,7 +258,8 @@ impl ClientTestContext {
             .client
             .request(request)
             .await
-            .expect("failed to make request to server");
+            .expect("failed to make request to server")
+            .map(Body::wrap);
 
         // Check that we got the expected response code.
         let status = response.status();
@@ -563,6 +576,11 @@ pub async fn read_string(response: &mut Response<Body>) -> String {
         .expect("response contained non-UTF-8 bytes")
 }
 
+/// Reads the rest of `body` into memory, leaving it empty.
+async fn to_bytes(body: &mut Body) -> Result<bytes::Bytes, BoxError> {
+    std::mem::take(body).to_bytes().await
+}
+
 /// Fetches a single resource from the API.
 pub async fn object_get<T: DeserializeOwned>(
     client: &ClientTestContext,
@@ -0,0 +1,359 @@
+// Copyright 2023 Oxide Computer Company
+//! Support for serving HTTPS
+
+use crate::config::ClientAuthMode;
+use crate::config::ConfigSocketOptions;
+use crate::config::ConfigTls;
+use crate::config::ConfigTlsClientAuth;
+use crate::config::ConfigTlsSniCertificate;
+use crate::connection::AcceptedConnection;
+use crate::connection::ConnectionInfo;
+use crate::connection::PeerCertificate;
+use crate::socket_options::configure_connection;
+
+use async_stream::stream;
+use futures::future::TryFutureExt;
+use futures::lock::Mutex;
+use futures::stream::{Stream, StreamExt};
+use rustls::sign::CertifiedKey;
+use std::collections::HashMap;
+use std::convert::TryFrom;
+use std::net::IpAddr;
+use std::net::SocketAddr;
+use std::path::Path;
+use std::sync::Arc;
+use std::time::SystemTime;
+use tokio::net::{TcpListener, TcpStream};
+use tokio_rustls::{server::TlsStream, TlsAcceptor};
+
+/// This is our bridge between tokio-rustls and hyper.  It creates a stream
+/// that produces fully negotiated TLS connections (along with their remote
+/// addresses) as they come in from a TCP listen socket.  This stream allows
+/// for multiple TLS connections to be negotiated concurrently with new
+/// connections being accepted.
+pub(crate) fn tls_connections(
+    log: slog::Logger,
+    tls_acceptor: Arc<Mutex<TlsAcceptor>>,
+    tcp_listener: TcpListener,
+    socket_options: ConfigSocketOptions,
+) -> impl Stream<Item = std::io::Result<(TlsStream<TcpStream>, SocketAddr)>> {
+    stream! {
+        let mut tls_negotiations = futures::stream::FuturesUnordered::new();
+        loop {
+            tokio::select! {
+                Some(negotiation) = tls_negotiations.next(), if
+                        !tls_negotiations.is_empty() => {
+
+                    match negotiation {
+                        Ok(conn) => yield Ok(conn),
+                        Err(e) => {
+                            // If TLS negotiation fails, log the cause but
+                            // don't forward it along. Yielding an error
+                            // from here will terminate the server.
+                            // These failures may be a fatal TLS alert
+                            // message, or a client disconnection during
+                            // negotiation, or other issues.
+                            // TODO: We may want to export a counter for
+                            // different error types, since this may contain
+                            // useful things like "your certificate is
+                            // invalid"
+                            warn!(log, "tls accept err: {}", e);
+                        },
+                    }
+                },
+                accept_result = tcp_listener.accept() => {
+                    let (socket, addr) = match accept_result {
+                        Ok(v) => v,
+                        Err(e) => {
+                            match e.kind() {
+                                std::io::ErrorKind::ConnectionAborted => {
+                                    continue;
+                                },
+                                // The other errors that can be returned
+                                // under POSIX are all programming errors or
+                                // resource exhaustion. For now, handle
+                                // these by no longer accepting new
+                                // connections.
+                                // TODO-robustness: Consider handling these
+                                // more gracefully.
+                                _ => {
+                                    yield Err(e);
+                                    break;
+                                }
+                            }
+                        }
+                    };
+                    if let Err(e) =
+                        configure_connection(&socket, &socket_options)
+                    {
+                        warn!(log, "failed to set socket options: {}", e;
+                            "remote_addr" => %addr,
+                        );
+                    }
+
+                    let tls_negotiation = tls_acceptor
+                        .lock()
+                        .await
+                        .accept(socket)
+                        .map_ok(move |stream| (stream, addr));
+                    tls_negotiations.push(tls_negotiation);
+                },
+                else => break,
+            }
+        }
+    }
+}
+
+impl AcceptedConnection for TlsStream<TcpStream> {
+    fn connection_info(&self, remote_addr: SocketAddr) -> ConnectionInfo {
+        let (socket, session) = self.get_ref();
+        let local_addr = socket.local_addr().unwrap_or(remote_addr);
+        let alpn_protocol = session.alpn_protocol().map(|p| p.to_vec());
+        // The first certificate is the client's own; any others are
+        // intermediates.
+        let peer_certificate = session
+            .peer_certificates()
+            .and_then(|certs| certs.first())
+            .and_then(|cert| peer_certificate(&cert.0));
+        ConnectionInfo::new(remote_addr, local_addr, true, alpn_protocol)
+            .with_peer_certificate(peer_certificate)
+    }
+}
+
+/// Describes the (already verified) client certificate whose DER encoding is
+/// `der`.  Returns `None` if the certificate can't be parsed.
+fn peer_certificate(der: &[u8]) -> Option<PeerCertificate> {
+    let (_, cert) = x509_parser::parse_x509_certificate(der).ok()?;
+    let subject = cert.subject().to_string();
+    let subject_alt_names = match cert.subject_alternative_name() {
+        Ok(Some(extension)) => extension
+            .value
+            .general_names
+            .iter()
+            .filter_map(|name| {
+                use x509_parser::extensions::GeneralName;
+                match name {
+                    GeneralName::DNSName(name)
+                    | GeneralName::RFC822Name(name)
+                    | GeneralName::URI(name) => Some(name.to_string()),
+                    GeneralName::IPAddress(bytes) => ip_addr(bytes),
+                    _ => None,
+                }
+            })
+            .collect(),
+        _ => Vec::new(),
+    };
+    let digest = ring::digest::digest(&ring::digest::SHA256, der);
+    let fingerprint_sha256 =
+        digest.as_ref().iter().map(|b| format!("{:02x}", b)).collect();
+    Some(PeerCertificate::new(
+        der.to_vec(),
+        subject,
+        subject_alt_names,
+        fingerprint_sha256,
+    ))
+}
+
+/// Formats the IP address in a subject alternative name, which is encoded as
+/// its 4 or 16 bytes.
+fn ip_addr(bytes: &[u8]) -> Option<String> {
+    let addr = match bytes.len() {
+        4 => IpAddr::from(<[u8; 4]>::try_from(bytes).ok()?),
+        16 => IpAddr::from(<[u8; 16]>::try_from(bytes).ok()?),
+        _ => return None,
+    };
+    Some(addr.to_string())
+}
+
+/// Create a TLS configuration from the Dropshot config structure.
+// Eventually we may want to change the APIs to allow users to pass
+// a rustls::ServerConfig themselves
+impl TryFrom<&ConfigTls> for rustls::ServerConfig {
+    type Error = std::io::Error;
+
+    fn try_from(config: &ConfigTls) -> std::io::Result<Self> {
+        let client_cert_verifier = match config.client_auth() {
+            None => rustls::server::NoClientAuth::new(),
+            Some(client_auth) => {
+                let roots = load_client_ca_certs(client_auth)?;
+                match client_auth.mode() {
+                    ClientAuthMode::Require => {
+                        rustls::server::AllowAnyAuthenticatedClient::new(roots)
+                    }
+                    ClientAuthMode::Request => {
+                        rustls::server::AllowAnyAnonymousOrAuthenticatedClient::new(
+                            roots,
+                        )
+                    }
+                }
+            }
+        };
+        let builder = rustls::ServerConfig::builder()
+            // TODO: We may want to expose protocol configuration in our
+            // config
+            .with_safe_default_cipher_suites()
+            .with_safe_default_kx_groups()
+            .with_safe_default_protocol_versions()
+            .unwrap()
+            .with_client_cert_verifier(client_cert_verifier);
+        let mut cfg = match config {
+            ConfigTls::Sni { certificates, .. } => builder.with_cert_resolver(
+                Arc::new(SniCertResolver::new(certificates)?),
+            ),
+            ConfigTls::AsFile { .. } | ConfigTls::AsBytes { .. } => {
+                let certs = load_certs(&mut config.cert_reader()?)?;
+                let private_key = load_private_key(&mut config.key_reader()?)?;
+                builder.with_single_cert(certs, private_key).map_err(|err| {
+                    io_error(format!("bad certificate/key: {err}"))
+                })?
+            }
+        };
+        cfg.alpn_protocols = vec![b"h2".to_vec(), b"http/1.1".to_vec()];
+        Ok(cfg)
+    }
+}
+
+/// Returns the modification times of the files from which `config` is loaded
+/// (`None` for any that can't be read), so that changes to them can be
+/// noticed.
+pub(crate) fn tls_file_times(config: &ConfigTls) -> Vec<Option<SystemTime>> {
+    let mut files: Vec<&Path> = match config {
+        ConfigTls::AsFile { cert_file, key_file, .. } => {
+            vec![cert_file.as_path(), key_file.as_path()]
+        }
+        ConfigTls::AsBytes { .. } => Vec::new(),
+        ConfigTls::Sni { certificates, .. } => certificates
+            .iter()
+            .flat_map(|c| [c.cert_file.as_path(), c.key_file.as_path()])
+            .collect(),
+    };
+    if let Some(ConfigTlsClientAuth::AsFile { ca_file, .. }) =
+        config.client_auth()
+    {
+        files.push(ca_file.as_path());
+    }
+    files
+        .into_iter()
+        .map(|file| std::fs::metadata(file).and_then(|m| m.modified()).ok())
+        .collect()
+}
+
+fn io_error(err: String) -> std::io::Error {
+    std::io::Error::new(std::io::ErrorKind::Other, err)
+}
+
+/// Chooses the certificate chain for each connection by the hostname that the
+/// client names with SNI (see `ConfigTls::Sni`)
+struct SniCertResolver {
+    /// chains by (lowercase) hostname, including wildcard names
+    by_hostname: HashMap<String, Arc<CertifiedKey>>,
+    /// chain for clients that don't name a configured hostname
+    default: Arc<CertifiedKey>,
+}
+
+impl SniCertResolver {
+    fn new(
+        certificates: &[ConfigTlsSniCertificate],
+    ) -> std::io::Result<SniCertResolver> {
+        let mut by_hostname = HashMap::new();
+        let mut default = None;
+        for certificate in certificates {
+            let certs = load_certs(&mut certificate.cert_reader()?)?;
+            let private_key = load_private_key(&mut certificate.key_reader()?)?;
+            let signing_key = rustls::sign::any_supported_type(&private_key)
+                .map_err(|_| {
+                    io_error(format!(
+                        "unsupported private key type in {}",
+                        certificate.key_file.display()
+                    ))
+                })?;
+            let key = Arc::new(CertifiedKey::new(certs, signing_key));
+            for hostname in &certificate.hostnames {
+                let hostname = hostname.to_ascii_lowercase();
+                if by_hostname
+                    .insert(hostname.clone(), Arc::clone(&key))
+                    .is_some()
+                {
+                    return Err(io_error(format!(
+                        "hostname {:?} has more than one certificate",
+                        hostname
+                    )));
+                }
+            }
+            default.get_or_insert(key);
+        }
+        let default = default.ok_or_else(|| {
+            io_error("expected at least one certificate".into())
+        })?;
+        Ok(SniCertResolver { by_hostname, default })
+    }
+
+    /// Returns the chain for connections to `server_name`.
+    fn lookup(&self, server_name: Option<&str>) -> Arc<CertifiedKey> {
+        server_name
+            .and_then(|name| {
+                let name = name.to_ascii_lowercase();
+                self.by_hostname.get(&name).or_else(|| {
+                    let (_, parent) = name.split_once('.')?;
+                    self.by_hostname.get(&format!("*.{}", parent))
+                })
+            })
+            .unwrap_or(&self.default)
+            .clone()
+    }
+}
+
+impl rustls::server::ResolvesServerCert for SniCertResolver {
+    fn resolve(
+        &self,
+        client_hello: rustls::server::ClientHello,
+    ) -> Option<Arc<CertifiedKey>> {
+        Some(self.lookup(client_hello.server_name()))
+    }
+}
+
+// Load public certificate from config.
+pub(crate) fn load_certs(
+    reader: &mut dyn std::io::BufRead,
+) -> std::io::Result<Vec<rustls::Certificate>> {
+    // Load and return certificate.
+    rustls_pemfile::certs(reader)
+        .map_err(|err| io_error(format!("failed to load certificate: {err}")))
+        .map(|mut chain| chain.drain(..).map(rustls::Certificate).collect())
+}
+
+// Load private key from config.
+pub(crate) fn load_private_key(
+    reader: &mut dyn std::io::BufRead,
+) -> std::io::Result<rustls::PrivateKey> {
+    // Load and return a single private key.
+    let keys = rustls_pemfile::pkcs8_private_keys(reader).map_err(|err| {
+        io_error(format!("failed to load private key: {err}"))
+    })?;
+    if keys.len() != 1 {
+        return Err(io_error("expected a single private key".into()));
+    }
+    Ok(rustls::PrivateKey(keys[0].clone()))
+}
+
+// Load the CA certificates against which client certificates are verified.
+fn load_client_ca_certs(
+    client_auth: &ConfigTlsClientAuth,
+) -> std::io::Result<rustls::RootCertStore> {
+    let mut reader = client_auth.ca_reader()?;
+    let certs = rustls_pemfile::certs(&mut reader).map_err(|err| {
+        io_error(format!("failed to load client CA certificates: {err}"))
+    })?;
+    if certs.is_empty() {
+        return Err(io_error(
+            "expected at least one client CA certificate".into(),
+        ));
+    }
+    let mut roots = rustls::RootCertStore::empty();
+    for cert in certs {
+        roots.add(&rustls::Certificate(cert)).map_err(|err| {
+            io_error(format!("invalid client CA certificate: {err}"))
+        })?;
+    }
+    Ok(roots)
+}
@@ -190,8 +190,10 @@ impl SerializeStruct for MapSerializeStruct {
         T: Serialize,
     {
         let mut serializer = StringSerializer;
-        let value = value.serialize(&mut serializer)?;
-        self.output.insert(key.to_string(), value);
+        // Fields whose value is `None` are left out of the map.
+        if let Some(value) = value.serialize(&mut serializer)? {
+            self.output.insert(key.to_string(), value);
+        }
         Ok(())
     }
 
@@ -200,12 +202,13 @@ impl SerializeStruct for MapSerializeStruct {
     }
 }
 
-/// A trivial `Serializer` used to extract a `String`. One could imagine
-/// extending this to convert other scalars into strings, but for now we'll just
-/// work with strings.
+/// A trivial `Serializer` used to extract a `String` from a scalar: a string,
+/// a boolean, a number, a character, or a unit variant of an enum (which is
+/// serialized as its name).  Options and newtype structs are serialized as
+/// their contents; `None` produces no string at all.
 struct StringSerializer;
 impl<'a> Serializer for &'a mut StringSerializer {
-    type Ok = String;
+    type Ok = Option<String>;
     type Error = MapError;
 
     type SerializeSeq = Impossible<Self::Ok, Self::Error>;
@@ -217,32 +220,94 @@ impl<'a> Serializer for &'a mut StringSerializer {
     type SerializeStructVariant = Impossible<Self::Ok, Self::Error>;
 
     fn serialize_str(self, v: &str) -> Result<Self::Ok, Self::Error> {
-        Ok(v.to_string())
+        Ok(Some(v.to_string()))
+    }
+
+    fn serialize_bool(self, v: bool) -> Result<Self::Ok, Self::Error> {
+        Ok(Some(v.to_string()))
+    }
+
+    fn serialize_i8(self, v: i8) -> Result<Self::Ok, Self::Error> {
+        Ok(Some(v.to_string()))
+    }
+
+    fn serialize_i16(self, v: i16) -> Result<Self::Ok, Self::Error> {
+        Ok(Some(v.to_string()))
+    }
+
+    fn serialize_i32(self, v: i32) -> Result<Self::Ok, Self::Error> {
+        Ok(Some(v.to_string()))
+    }
+
+    fn serialize_i64(self, v: i64) -> Result<Self::Ok, Self::Error> {
+        Ok(Some(v.to_string()))
+    }
+
+    fn serialize_u8(self, v: u8) -> Result<Self::Ok, Self::Error> {
+        Ok(Some(v.to_string()))
+    }
+
+    fn serialize_u16(self, v: u16) -> Result<Self::Ok, Self::Error> {
+        Ok(Some(v.to_string()))
+    }
+
+    fn serialize_u32(self, v: u32) -> Result<Self::Ok, Self::Error> {
+        Ok(Some(v.to_string()))
+    }
+
+    fn serialize_u64(self, v: u64) -> Result<Self::Ok, Self::Error> {
+        Ok(Some(v.to_string()))
+    }
+
+    fn serialize_f32(self, v: f32) -> Result<Self::Ok, Self::Error> {
+        Ok(Some(v.to_string()))
+    }
+
+    fn serialize_f64(self, v: f64) -> Result<Self::Ok, Self::Error> {
+        Ok(Some(v.to_string()))
+    }
+
+    fn serialize_char(self, v: char) -> Result<Self::Ok, Self::Error> {
+        Ok(Some(v.to_string()))
+    }
+
+    fn serialize_none(self) -> Result<Self::Ok, Self::Error> {
+        Ok(None)
+    }
+
+    fn serialize_some<T: ?Sized>(
+        self,
+        value: &T,
+    ) -> Result<Self::Ok, Self::Error>
+    where
+        T: Serialize,
+    {
+        value.serialize(self)
+    }
+
+    fn serialize_unit_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        variant: &'static str,
+    ) -> Result<Self::Ok, Self::Error> {
+        Ok(Some(variant.to_string()))
+    }
+
+    fn serialize_newtype_struct<T: ?Sized>(
+        self,
+        _name: &'static str,
+        value: &T,
+    ) -> Result<Self::Ok, Self::Error>
+    where
+        T: Serialize,
+    {
+        value.serialize(self)
     }
 
-    ser_err!(serialize_bool, _v: bool);
-    ser_err!(serialize_i8, _v: i8);
-    ser_err!(serialize_i16, _v: i16);
-    ser_err!(serialize_i32, _v: i32);
-    ser_err!(serialize_i64, _v: i64);
-    ser_err!(serialize_u8, _v: u8);
-    ser_err!(serialize_u16, _v: u16);
-    ser_err!(serialize_u32, _v: u32);
-    ser_err!(serialize_u64, _v: u64);
-    ser_err!(serialize_f32, _v: f32);
-    ser_err!(serialize_f64, _v: f64);
-    ser_err!(serialize_char, _v: char);
     ser_err!(serialize_bytes, _v: &[u8]);
-    ser_err!(serialize_none);
     ser_err!(serialize_unit);
-    ser_t_err!(serialize_some, _value: &T);
     ser_err!(serialize_unit_struct, _name: &'static str);
-    ser_err!(
-        serialize_unit_variant,
-        _name: &'static str,
-        _variant_index: u32,
-        _variant: &'static str,
-    );
     ser_t_err!(
         serialize_newtype_variant,
         _name: &'static str,
@@ -250,7 +315,6 @@ impl<'a> Serializer for &'a mut StringSerializer {
         _variant: &'static str,
         _value: &T,
     );
-    ser_t_err!(serialize_newtype_struct, _name: &'static str, _value: &T);
 
     fn serialize_seq(
         self,
@@ -349,18 +413,64 @@ mod test {
     }
 
     #[test]
-    fn test_to_map_num() {
+    fn test_to_map_scalars() {
         #[derive(Serialize)]
-        struct Bad {
+        enum Mode {
+            Fast,
+        }
+
+        #[derive(Serialize)]
+        struct Wrapped(u32);
+
+        #[derive(Serialize)]
+        struct Scalars {
+            a: u32,
+            b: bool,
+            c: f64,
+            d: Option<String>,
+            e: Option<i64>,
+            f: Mode,
+            g: Wrapped,
+        }
+
+        let scalars = Scalars {
+            a: 0xb,
+            b: true,
+            c: 1.5,
+            d: None,
+            e: Some(-3),
+            f: Mode::Fast,
+            g: Wrapped(7),
+        };
+
+        let map = to_map(&scalars).unwrap();
+
+        assert_eq!(map.get("a"), Some(&"11".to_string()));
+        assert_eq!(map.get("b"), Some(&"true".to_string()));
+        assert_eq!(map.get("c"), Some(&"1.5".to_string()));
+        assert_eq!(map.get("d"), None);
+        assert_eq!(map.get("e"), Some(&"-3".to_string()));
+        assert_eq!(map.get("f"), Some(&"Fast".to_string()));
+        assert_eq!(map.get("g"), Some(&"7".to_string()));
+    }
+
+    #[test]
+    fn test_to_map_nested() {
+        #[derive(Serialize)]
+        struct Inner {
             a: String,
-            b: u32,
         }
 
-        let bad = Bad { a: "a".to_string(), b: 0xb };
+        #[derive(Serialize)]
+        struct Bad {
+            a: Inner,
+        }
+
+        let bad = Bad { a: Inner { a: "a".to_string() } };
 
         assert_eq!(
             to_map(&bad),
-            Err(MapError("invalid type: u32".to_string()))
+            Err(MapError("cannot serialize a struct".to_string()))
         );
     }
 
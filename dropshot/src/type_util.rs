@@ -27,6 +27,106 @@ pub fn type_is_scalar(
     })
 }
 
+/// Returns true iff the input schema is a scalar (see `type_is_scalar()`) or an
+/// array of scalars.
+pub fn type_is_scalar_or_array(
+    name: &String,
+    schema: &Schema,
+    dependencies: &IndexMap<String, Schema>,
+) -> Result<(), String> {
+    if type_is_scalar(name, schema, dependencies).is_ok() {
+        return Ok(());
+    }
+
+    let item_schema = match type_resolve(schema, dependencies) {
+        Schema::Object(SchemaObject {
+            instance_type: Some(SingleOrVec::Single(instance_type)),
+            subschemas: None,
+            array: Some(array_validation),
+            object: None,
+            reference: None,
+            ..
+        }) if instance_type.as_ref() == &InstanceType::Array => {
+            match &array_validation.items {
+                Some(SingleOrVec::Single(item_schema)) => Some(item_schema),
+                _ => None,
+            }
+        }
+        _ => None,
+    };
+    match item_schema {
+        Some(item_schema)
+            if type_is_scalar(name, item_schema, dependencies).is_ok() =>
+        {
+            Ok(())
+        }
+        _ => Err(format!(
+            "the parameter '{}' must have a scalar type or be an array of \
+             scalars",
+            name
+        )),
+    }
+}
+
+/// Returns true iff the input schema is an object (see
+/// `type_object_properties()`) whose properties are all scalars or arrays of
+/// scalars.
+pub fn type_is_object_of_scalars(
+    name: &String,
+    schema: &Schema,
+    dependencies: &IndexMap<String, Schema>,
+) -> Result<(), String> {
+    match type_object_properties(schema, dependencies) {
+        Some(properties)
+            if properties.values().all(|property| {
+                type_is_scalar_or_array(name, property, dependencies).is_ok()
+            }) =>
+        {
+            Ok(())
+        }
+        _ => Err(format!(
+            "the parameter '{}' must be an object whose properties have \
+             scalar types or are arrays of scalars",
+            name
+        )),
+    }
+}
+
+/// Returns the properties of the object that the input schema describes, if it
+/// describes one (or an optional one).
+pub fn type_object_properties<'a>(
+    schema: &'a Schema,
+    dependencies: &'a IndexMap<String, Schema>,
+) -> Option<&'a schemars::Map<String, Schema>> {
+    match type_resolve(schema, dependencies) {
+        Schema::Object(SchemaObject { object: Some(object), .. }) => {
+            Some(&object.properties)
+        }
+        Schema::Object(SchemaObject {
+            instance_type: None,
+            subschemas: Some(subschemas),
+            ..
+        }) => match subschemas.as_ref() {
+            SubschemaValidation {
+                all_of: Some(subs),
+                any_of: None,
+                one_of: None,
+                ..
+            }
+            | SubschemaValidation {
+                all_of: None,
+                any_of: Some(subs),
+                one_of: None,
+                ..
+            } if subs.len() == 1 => {
+                type_object_properties(&subs[0], dependencies)
+            }
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
 /// Returns true iff the input schema is a string.
 pub fn type_is_string(
     name: &String,
@@ -7,6 +7,8 @@
 //! which will be spawned to handle the incoming connection.
 
 use crate::api_description::ExtensionMode;
+use crate::api_description::WEBSOCKET_PARAM_SENTINEL;
+use crate::Body;
 use crate::{
     ApiEndpointBodyContentType, ExclusiveExtractor, ExtractorMetadata,
     HttpError, RequestContext, ServerContext,
@@ -17,7 +19,7 @@ use http::header;
 use http::Response;
 use http::StatusCode;
 use hyper::upgrade::OnUpgrade;
-use hyper::Body;
+use hyper_util::rt::TokioIo;
 use schemars::JsonSchema;
 use serde_json::json;
 use sha1::{Digest, Sha1};
@@ -48,15 +50,57 @@ pub type WebsocketEndpointResult = Result<Response<Body>, HttpError>;
 /// handler function. [`WebsocketConnection::into_inner`] can be used to
 /// access the raw upgraded connection, for passing to any implementation
 /// of the websockets protocol.
-pub struct WebsocketConnection(WebsocketConnectionRaw);
+pub struct WebsocketConnection {
+    raw: WebsocketConnectionRaw,
+    log: Logger,
+    request_id: String,
+}
 
 /// A type that implements [tokio::io::AsyncRead] + [tokio::io::AsyncWrite].
-pub type WebsocketConnectionRaw = hyper::upgrade::Upgraded;
+pub type WebsocketConnectionRaw = TokioIo<hyper::upgrade::Upgraded>;
 
 impl WebsocketConnection {
     /// Consumes `self` and returns the held raw connection.
     pub fn into_inner(self) -> WebsocketConnectionRaw {
-        self.0
+        self.raw
+    }
+
+    /// Returns a logger for events on this connection.  Its entries carry the
+    /// id of the request that was upgraded (`req_id`) and the id of the
+    /// underlying connection (`conn_id`), so that they can be correlated with
+    /// the entries for that request.
+    ///
+    /// Since [`WebsocketConnection::into_inner`] consumes the connection,
+    /// clone this first if you need it afterwards.  For per-message entries,
+    /// create a child logger with whatever identifies the message:
+    ///
+    /// ```
+    /// # use dropshot::WebsocketConnection;
+    /// # use futures::StreamExt;
+    /// # use slog::{debug, o};
+    /// # use tokio_tungstenite::tungstenite::protocol::Role;
+    /// # use tokio_tungstenite::WebSocketStream;
+    /// # async fn handle(conn: WebsocketConnection) {
+    /// let log = conn.log().clone();
+    /// let mut ws_stream = WebSocketStream::from_raw_socket(
+    ///     conn.into_inner(), Role::Server, None
+    /// ).await;
+    /// let mut nmessages = 0;
+    /// while let Some(msg) = ws_stream.next().await {
+    ///     nmessages += 1;
+    ///     let msg_log = log.new(o!("msg_seq" => nmessages));
+    ///     debug!(msg_log, "received message"; "ok" => msg.is_ok());
+    /// }
+    /// # }
+    /// ```
+    pub fn log(&self) -> &Logger {
+        &self.log
+    }
+
+    /// Returns the id of the request that was upgraded to create this
+    /// connection.
+    pub fn request_id(&self) -> &str {
+        &self.request_id
     }
 }
 
@@ -66,6 +110,7 @@ struct WebsocketUpgradeInner {
     accept_key: String,
     route: String,
     ws_log: Logger,
+    request_id: String,
 }
 
 // Originally copied from tungstenite-0.17.3 (rather than taking a whole
@@ -87,7 +132,7 @@ fn derive_accept_key(request_key: &[u8]) -> String {
 impl ExclusiveExtractor for WebsocketUpgrade {
     async fn from_request<Context: ServerContext>(
         rqctx: &RequestContext<Context>,
-        request: hyper::Request<hyper::Body>,
+        request: hyper::Request<crate::Body>,
     ) -> Result<Self, HttpError> {
         if !request
             .headers()
@@ -147,9 +192,9 @@ impl ExclusiveExtractor for WebsocketUpgrade {
 
         let route = request.uri().to_string();
         let upgrade_fut = hyper::upgrade::on(request);
-        // note: this is just used in our wrapper in `handle`; if a user wants
-        // to slog in their future, they can obtain it from rqctx the same way
-        // they do in any other endpoint & let it get `move`d into the closure
+        // note: this is used in our wrapper in `handle` and passed along to
+        // the handler as `WebsocketConnection::log()`.  It inherits the request
+        // and connection ids from the request's logger.
         let ws_log = rqctx.log.new(o!(
             "upgrade" => "websocket".to_string(),
         ));
@@ -159,6 +204,7 @@ impl ExclusiveExtractor for WebsocketUpgrade {
             accept_key,
             ws_log,
             route,
+            request_id: rqctx.request_id.clone(),
         })))
     }
 
@@ -224,13 +270,23 @@ impl WebsocketUpgrade {
                 upgrade_fut,
                 accept_key,
                 ws_log,
+                request_id,
                 ..
             }) => {
                 tokio::spawn(async move {
                     match upgrade_fut.await {
                         Ok(upgrade) => {
-                            match handler(WebsocketConnection(upgrade)).await {
-                                Ok(x) => Ok(x),
+                            debug!(ws_log, "upgraded connection");
+                            let conn = WebsocketConnection {
+                                raw: TokioIo::new(upgrade),
+                                log: ws_log.clone(),
+                                request_id,
+                            };
+                            match handler(conn).await {
+                                Ok(x) => {
+                                    debug!(ws_log, "handler completed");
+                                    Ok(x)
+                                }
                                 Err(e) => {
                                     error!(
                                         ws_log,
@@ -272,10 +328,6 @@ impl Drop for WebsocketUpgrade {
     }
 }
 
-// To indicate websocket usage by the endpoint to code generators (i.e. Progenitor)
-pub(crate) const WEBSOCKET_EXTENSION: &str = "x-dropshot-websocket";
-pub(crate) const WEBSOCKET_PARAM_SENTINEL: &str = "x-dropshot-websocket-param";
-
 impl JsonSchema for WebsocketUpgrade {
     fn schema_name() -> String {
         "WebsocketUpgrade".to_string()
@@ -294,18 +346,31 @@ impl JsonSchema for WebsocketUpgrade {
 
 #[cfg(test)]
 mod tests {
-    use crate::router::HttpRouter;
-    use crate::server::{DropshotState, ServerConfig};
+    use crate::bandwidth::Throttle;
+    use crate::connection::ConnectionInfo;
+    use crate::disconnect::DisconnectGuard;
+    use crate::forwarded::EffectiveClient;
+    use crate::load_limits::LoadLimits;
+    use crate::request_log::LogRedactor;
+    use crate::request_log::RequestLogSampler;
+    use crate::server::{DropshotState, Routes, ServerConfig};
+    use crate::settings::{ServerSettings, ServerSettingsHandle};
+    use crate::slow_request::RequestTimings;
+    use crate::slow_request::SlowRequestDetector;
+    use crate::Body;
     use crate::{
-        ExclusiveExtractor, HttpError, RequestContext, RequestInfo,
-        WebsocketUpgrade,
+        ApiDescription, ExclusiveExtractor, HttpError, RequestContext,
+        RequestInfo, WebsocketUpgrade,
     };
     use http::Request;
-    use hyper::Body;
     use std::net::{IpAddr, Ipv6Addr, SocketAddr};
     use std::num::NonZeroU32;
+    use std::sync::atomic::AtomicBool;
+    use std::sync::atomic::AtomicUsize;
     use std::sync::Arc;
+    use std::sync::RwLock;
     use std::time::Duration;
+    use std::time::Instant;
 
     async fn ws_upg_from_mock_rqctx() -> Result<WebsocketUpgrade, HttpError> {
         let log = slog::Logger::root(slog::Discard, slog::o!()).new(slog::o!());
@@ -316,6 +381,12 @@ mod tests {
             .header(http::header::SEC_WEBSOCKET_KEY, "aGFjayB0aGUgcGxhbmV0IQ==")
             .body(Body::empty())
             .unwrap();
+        let connection = Arc::new(ConnectionInfo::new(
+            SocketAddr::new(IpAddr::V6(Ipv6Addr::LOCALHOST), 8080),
+            SocketAddr::new(IpAddr::V6(Ipv6Addr::LOCALHOST), 8000),
+            false,
+            None,
+        ));
         let rqctx = RequestContext {
             server: Arc::new(DropshotState {
                 private: (),
@@ -323,20 +394,75 @@ mod tests {
                     request_body_max_bytes: 0,
                     page_max_nitems: NonZeroU32::new(1).unwrap(),
                     page_default_nitems: NonZeroU32::new(1).unwrap(),
+                    schema_validation: false,
+                    strict_query_params: false,
+                    multipart_part_max_bytes: None,
+                    request_body_decompressed_max_bytes: None,
+                    handler_task_mode: Default::default(),
+                    redact_server_errors: false,
+                    method_not_allowed_as_not_found: false,
+                    method_override: None,
+                    drain_timeout: None,
+                    keep_alive_timeout: None,
+                    idle_timeout: None,
+                    max_requests_per_connection: None,
+                    header_read_timeout: None,
+                    max_header_bytes: None,
+                    max_header_count: None,
+                    socket_options: Default::default(),
                 },
-                router: HttpRouter::new(),
+                routes: RwLock::new(Arc::new(Routes::new(
+                    ApiDescription::new(),
+                ))),
                 log: log.clone(),
                 local_addr: SocketAddr::new(
                     IpAddr::V6(Ipv6Addr::LOCALHOST),
                     8080,
                 ),
                 tls_acceptor: None,
+                tls_config: std::sync::Mutex::new(None),
+                request_log_sampler: RequestLogSampler::new(&[]),
+                connection_throttle: Throttle::new(None),
+                log_redactor: LogRedactor::new(&Default::default()),
+                draining: AtomicBool::new(false),
+                accepting: tokio::sync::watch::channel(true).0,
+                in_flight_requests: AtomicUsize::new(0),
+                load_limits: LoadLimits::new(&Default::default()),
+                acceptors: Default::default(),
+                alt_svc: None,
+                maintenance: Default::default(),
+                idempotency_store: None,
+                coalescer: Default::default(),
+                slow_request_detector: SlowRequestDetector::new(&[]),
+                tenant_resolver: None,
+                trusted_proxies: Default::default(),
+                settings: ServerSettingsHandle::new(ServerSettings::new(
+                    &Default::default(),
+                )),
+                error_response_customizer: None,
+                error_format: Default::default(),
+                error_mapper: Default::default(),
+                strict_http: Default::default(),
+                continue_check: None,
+                content_decoders: Default::default(),
+                #[cfg(feature = "compression")]
+                compressor: None,
             }),
             request: RequestInfo::from(&request),
             path_variables: Default::default(),
             body_content_type: Default::default(),
+            additional_body_content_types: Default::default(),
             request_id: "".to_string(),
             log: log.clone(),
+            client: EffectiveClient::for_connection(&connection),
+            connection,
+            timings: Arc::new(RequestTimings::new(Instant::now())),
+            extensions: Default::default(),
+            tenant: None,
+            disconnect: DisconnectGuard::new().1,
+            early_hints: None,
+            request_body_max_bytes: 0,
+            schema_validators: Default::default(),
         };
         let fut = WebsocketUpgrade::from_request(&rqctx, request);
         tokio::time::timeout(Duration::from_secs(1), fut)
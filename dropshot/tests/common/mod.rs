@@ -8,9 +8,32 @@ use dropshot::ConfigDropshot;
 use dropshot::ConfigLogging;
 use dropshot::ConfigLoggingIfExists;
 use dropshot::ConfigLoggingLevel;
+use hyper::Uri;
+use hyper_util::client::legacy::connect::Connected;
+use hyper_util::client::legacy::connect::Connection;
+use hyper_util::client::legacy::connect::HttpConnector;
+use hyper_util::rt::TokioIo;
 use slog::o;
+use std::convert::TryFrom;
+use std::future::Future;
 use std::io::Write;
+use std::net::SocketAddr;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::Context;
+use std::task::Poll;
+use std::time::Duration;
 use tempfile::NamedTempFile;
+use tokio::io::AsyncRead;
+use tokio::io::AsyncReadExt;
+use tokio::io::AsyncWrite;
+use tokio::io::AsyncWriteExt;
+use tokio::io::ReadBuf;
+use tokio::net::TcpStream;
+use tokio_rustls::client::TlsStream;
+use tokio_rustls::TlsConnector;
+
+pub mod reading;
 
 pub fn test_setup(
     test_name: &str,
@@ -24,10 +47,18 @@ pub fn test_setup(
     // multiple concurrent tests, so any fixed port could result in spurious
     // failures due to port conflicts.
     let config_dropshot: ConfigDropshot = Default::default();
+    test_setup_with_config(test_name, api, &config_dropshot)
+}
 
+/// Like [`test_setup()`], but with the given server configuration.
+pub fn test_setup_with_config(
+    test_name: &str,
+    api: ApiDescription<usize>,
+    config: &ConfigDropshot,
+) -> TestContext<usize> {
     let logctx = create_log_context(test_name);
     let log = logctx.log.new(o!());
-    TestContext::new(api, 0_usize, &config_dropshot, Some(logctx), log)
+    TestContext::new(api, 0_usize, config, Some(logctx), log)
 }
 
 pub fn create_log_context(test_name: &str) -> LogContext {
@@ -39,6 +70,114 @@ pub fn create_log_context(test_name: &str) -> LogContext {
     LogContext::new(test_name, &log_config)
 }
 
+/// Sends `request` on a new connection and returns everything the server sends
+/// back before closing the connection, which it must do within a few seconds.
+/// This is for testing requests that an HTTP client wouldn't send.
+pub async fn exchange(addr: SocketAddr, request: &[u8]) -> String {
+    let mut stream = TcpStream::connect(addr).await.unwrap();
+    stream.write_all(request).await.unwrap();
+    let mut response = Vec::new();
+    // A server that rejects a request without reading all of it may reset the
+    // connection after responding, which is fine.
+    let _ = tokio::time::timeout(
+        Duration::from_secs(10),
+        stream.read_to_end(&mut response),
+    )
+    .await
+    .expect("server did not close the connection");
+    String::from_utf8_lossy(&response).into_owned()
+}
+
+type BoxError = Box<dyn std::error::Error + Send + Sync>;
+
+/// Connector for making HTTPS requests with a hyper client (for tests of
+/// dropshot's TLS support).  Only "https" URLs are supported.
+#[derive(Clone)]
+pub struct HttpsConnector {
+    http: HttpConnector,
+    tls: TlsConnector,
+}
+
+impl HttpsConnector {
+    pub fn new(tls_config: rustls::ClientConfig) -> Self {
+        let mut http = HttpConnector::new();
+        http.enforce_http(false);
+        HttpsConnector { http, tls: TlsConnector::from(Arc::new(tls_config)) }
+    }
+}
+
+impl tower_service::Service<Uri> for HttpsConnector {
+    type Response = TokioIo<HttpsStream>;
+    type Error = BoxError;
+    type Future =
+        Pin<Box<dyn Future<Output = Result<Self::Response, BoxError>> + Send>>;
+
+    fn poll_ready(
+        &mut self,
+        cx: &mut Context<'_>,
+    ) -> Poll<Result<(), BoxError>> {
+        self.http.poll_ready(cx).map_err(Into::into)
+    }
+
+    fn call(&mut self, uri: Uri) -> Self::Future {
+        let tcp = self.http.call(uri.clone());
+        let tls = self.tls.clone();
+        Box::pin(async move {
+            if uri.scheme_str() != Some("https") {
+                return Err(BoxError::from("unsupported scheme"));
+            }
+            let host = uri.host().ok_or("missing host")?;
+            let server_name = rustls::ServerName::try_from(host)?;
+            let tcp = tcp.await?.into_inner();
+            let stream = tls.connect(server_name, tcp).await?;
+            Ok(TokioIo::new(HttpsStream(stream)))
+        })
+    }
+}
+
+/// Connection made by an [`HttpsConnector`]
+pub struct HttpsStream(TlsStream<TcpStream>);
+
+impl Connection for HttpsStream {
+    fn connected(&self) -> Connected {
+        self.0.get_ref().0.connected()
+    }
+}
+
+impl AsyncRead for HttpsStream {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<std::io::Result<()>> {
+        Pin::new(&mut self.0).poll_read(cx, buf)
+    }
+}
+
+impl AsyncWrite for HttpsStream {
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<std::io::Result<usize>> {
+        Pin::new(&mut self.0).poll_write(cx, buf)
+    }
+
+    fn poll_flush(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<std::io::Result<()>> {
+        Pin::new(&mut self.0).poll_flush(cx)
+    }
+
+    fn poll_shutdown(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<std::io::Result<()>> {
+        Pin::new(&mut self.0).poll_shutdown(cx)
+    }
+}
+
 pub struct TestCertificateChain {
     root_cert: rustls::Certificate,
     intermediate_cert: rustls::Certificate,
@@ -0,0 +1,111 @@
+// Copyright 2023 Oxide Computer Company
+//! A fixture for the tests of the request body formats: an endpoint that
+//! accepts a [`Reading`] in the given content types and echoes it back as JSON
+
+use dropshot::test_util::read_json;
+use dropshot::test_util::ClientTestContext;
+use dropshot::ApiDescription;
+use dropshot::ApiEndpoint;
+use dropshot::Body;
+use dropshot::HttpError;
+use dropshot::HttpResponseOk;
+use dropshot::RequestContext;
+use dropshot::TypedBody;
+use http::Method;
+use http::StatusCode;
+use hyper::Request;
+use schemars::JsonSchema;
+use serde::Deserialize;
+use serde::Serialize;
+
+// The fields are in alphabetical order so that encoded maps have their keys in
+// the same order regardless of how they're sorted.
+#[derive(Debug, Deserialize, JsonSchema, PartialEq, Serialize)]
+pub struct Reading {
+    pub sensor: String,
+    pub values: Vec<i32>,
+}
+
+impl Reading {
+    /// Returns the reading that the tests' bodies describe:
+    /// `{"sensor": "t1", "values": [-3, 300]}`
+    pub fn example() -> Reading {
+        Reading { sensor: String::from("t1"), values: vec![-3, 300] }
+    }
+}
+
+async fn reading_put(
+    _rqctx: RequestContext<usize>,
+    body: TypedBody<Reading>,
+) -> Result<HttpResponseOk<Reading>, HttpError> {
+    Ok(HttpResponseOk(body.into_inner()))
+}
+
+/// Returns an API whose `PUT /reading` endpoint accepts bodies of the first of
+/// `content_types` and of any of the others.
+pub fn api(content_types: &[&str]) -> ApiDescription<usize> {
+    let mut api = ApiDescription::new();
+    api.register(endpoint(content_types)).unwrap();
+    api
+}
+
+/// Returns the `PUT /reading` endpoint described in [`api()`].
+pub fn endpoint(content_types: &[&str]) -> ApiEndpoint<usize> {
+    let (content_type, additional) = content_types.split_first().unwrap();
+    ApiEndpoint::new(
+        String::from("reading_put"),
+        reading_put,
+        Method::PUT,
+        content_type,
+        "/reading",
+    )
+    .additional_content_types(additional)
+}
+
+/// Returns a request for `PUT /reading` with the given body.
+pub fn request<B: Into<Vec<u8>>>(
+    client: &ClientTestContext,
+    content_type: &str,
+    body: B,
+) -> Request<Body> {
+    Request::builder()
+        .method(Method::PUT)
+        .uri(client.url("/reading"))
+        .header(http::header::CONTENT_TYPE, content_type)
+        .body(Body::from(body.into()))
+        .unwrap()
+}
+
+/// Sends `body` to `PUT /reading` and returns the reading that the server
+/// parsed from it.
+pub async fn put_reading<B: Into<Vec<u8>>>(
+    client: &ClientTestContext,
+    content_type: &str,
+    body: B,
+) -> Reading {
+    let mut response = client
+        .make_request_with_request(
+            request(client, content_type, body),
+            StatusCode::OK,
+        )
+        .await
+        .unwrap();
+    read_json(&mut response).await
+}
+
+/// Sends `body` to `PUT /reading`, which must reject it with a 400 ("Bad
+/// Request") error, and returns the error's message.
+pub async fn put_reading_error<B: Into<Vec<u8>>>(
+    client: &ClientTestContext,
+    content_type: &str,
+    body: B,
+) -> String {
+    client
+        .make_request_with_request(
+            request(client, content_type, body),
+            StatusCode::BAD_REQUEST,
+        )
+        .await
+        .unwrap_err()
+        .message
+}
@@ -0,0 +1,119 @@
+// Copyright 2023 Oxide Computer Company
+
+//! Test cases for content negotiation with the `Accept` extractor.
+
+use dropshot::endpoint;
+use dropshot::Accept;
+use dropshot::ApiDescription;
+use dropshot::Body;
+use dropshot::HttpError;
+use dropshot::HttpResponseOk;
+use dropshot::Negotiated;
+use dropshot::RequestContext;
+use dropshot::CONTENT_TYPE_CBOR;
+use http::Method;
+use http::StatusCode;
+use http_body_util::BodyExt;
+use hyper::Request;
+
+pub mod common;
+
+#[endpoint {
+    method = GET,
+    path = "/values",
+}]
+async fn values_get(
+    _rqctx: RequestContext<usize>,
+    accept: Accept,
+) -> Result<HttpResponseOk<Negotiated<Vec<i32>>>, HttpError> {
+    Ok(HttpResponseOk(Negotiated::new(&accept, vec![-3, 300])?))
+}
+
+fn api() -> ApiDescription<usize> {
+    let mut api = ApiDescription::new();
+    api.register(values_get).unwrap();
+    api
+}
+
+fn request(
+    client: &dropshot::test_util::ClientTestContext,
+    accept: Option<&str>,
+) -> Request<Body> {
+    let mut builder =
+        Request::builder().method(Method::GET).uri(client.url("/values"));
+    if let Some(accept) = accept {
+        builder = builder.header(http::header::ACCEPT, accept);
+    }
+    builder.body(Body::empty()).unwrap()
+}
+
+#[tokio::test]
+async fn test_accept() {
+    let testctx = common::test_setup("accept", api());
+    let client = &testctx.client_testctx;
+
+    for (accept, content_type, body) in [
+        (None, "application/json", &b"[-3,300]"[..]),
+        (Some("*/*"), "application/json", &b"[-3,300]"[..]),
+        (
+            Some("application/json;q=0.5, application/*"),
+            CONTENT_TYPE_CBOR,
+            &[0x82, 0x22, 0x19, 0x01, 0x2c][..],
+        ),
+    ] {
+        let mut response = client
+            .make_request_with_request(request(client, accept), StatusCode::OK)
+            .await
+            .unwrap();
+        let headers = response.headers();
+        assert_eq!(
+            headers.get(http::header::CONTENT_TYPE).unwrap(),
+            content_type
+        );
+        assert_eq!(headers.get(http::header::VARY).unwrap(), "Accept");
+        let actual = response.body_mut().collect().await.unwrap().to_bytes();
+        assert_eq!(actual.as_ref(), body);
+    }
+
+    let error = client
+        .make_request_with_request(
+            request(client, Some("text/html, application/json;q=0")),
+            StatusCode::NOT_ACCEPTABLE,
+        )
+        .await
+        .unwrap_err();
+    assert_eq!(
+        error.message,
+        "none of the available media types is acceptable: application/json, \
+         application/cbor"
+    );
+
+    let error = client
+        .make_request_with_request(
+            request(client, Some("text/html;q=high")),
+            StatusCode::BAD_REQUEST,
+        )
+        .await
+        .unwrap_err();
+    assert_eq!(
+        error.message,
+        "invalid Accept header: invalid quality \"high\""
+    );
+
+    testctx.teardown().await;
+}
+
+#[test]
+fn test_accept_openapi() {
+    // Both representations are described, and the `Accept` header isn't
+    // documented as a parameter.
+    let spec = api().openapi("test", "1.0").json().unwrap();
+    let operation = &spec["paths"]["/values"]["get"];
+    assert!(operation.get("parameters").is_none());
+    let content = &operation["responses"]["200"]["content"];
+    assert_eq!(
+        content["application/json"]["schema"],
+        content[CONTENT_TYPE_CBOR]["schema"]
+    );
+    assert_eq!(content["application/json"]["schema"]["type"], "array");
+}
@@ -0,0 +1,146 @@
+// Copyright 2023 Oxide Computer Company
+
+//! Test cases for the separate admin listener.
+
+use dropshot::endpoint;
+use dropshot::test_util::ClientTestContext;
+use dropshot::AdminContext;
+use dropshot::ApiDescription;
+use dropshot::ConfigDropshot;
+use dropshot::HttpError;
+use dropshot::HttpResponseOk;
+use dropshot::HttpResponseUpdatedNoContent;
+use dropshot::HttpServerStarter;
+use dropshot::MaintenanceMode;
+use dropshot::RequestContext;
+use http::Method;
+use http::StatusCode;
+use slog::o;
+
+pub mod common;
+
+#[endpoint {
+    method = GET,
+    path = "/public",
+}]
+async fn public_get(
+    rqctx: RequestContext<usize>,
+) -> Result<HttpResponseOk<usize>, HttpError> {
+    Ok(HttpResponseOk(*rqctx.context()))
+}
+
+#[endpoint {
+    method = GET,
+    path = "/routes",
+}]
+async fn admin_routes(
+    rqctx: RequestContext<AdminContext<usize>>,
+) -> Result<HttpResponseOk<Vec<String>>, HttpError> {
+    let routes = rqctx
+        .context()
+        .routes()
+        .into_iter()
+        .map(|(method, path)| format!("{} {}", method, path))
+        .collect();
+    Ok(HttpResponseOk(routes))
+}
+
+#[endpoint {
+    method = PUT,
+    path = "/maintenance",
+}]
+async fn admin_maintenance_put(
+    rqctx: RequestContext<AdminContext<usize>>,
+) -> Result<HttpResponseUpdatedNoContent, HttpError> {
+    rqctx.context().set_maintenance_mode(Some(MaintenanceMode::default()));
+    Ok(HttpResponseUpdatedNoContent())
+}
+
+#[endpoint {
+    method = GET,
+    path = "/private",
+}]
+async fn admin_private_get(
+    rqctx: RequestContext<AdminContext<usize>>,
+) -> Result<HttpResponseOk<usize>, HttpError> {
+    Ok(HttpResponseOk(*rqctx.context().app_private()))
+}
+
+#[tokio::test]
+async fn test_admin_listener() {
+    let logctx = common::create_log_context("admin_listener");
+    let log = logctx.log.new(o!());
+
+    let mut api = ApiDescription::new();
+    api.register(public_get).unwrap();
+    let mut admin_api = ApiDescription::new();
+    admin_api.register(admin_routes).unwrap();
+    admin_api.register(admin_maintenance_put).unwrap();
+    admin_api.register(admin_private_get).unwrap();
+
+    let config = ConfigDropshot::default();
+    let server = HttpServerStarter::new(&config, api, 7_usize, &log)
+        .unwrap()
+        .admin_listener(&config, admin_api, &log)
+        .unwrap()
+        .start();
+    let admin_addr = server.admin_local_addr().unwrap();
+    assert_ne!(admin_addr, server.local_addr());
+
+    let client = ClientTestContext::new(server.local_addr(), log.new(o!()));
+    let admin_client = ClientTestContext::new(admin_addr, log.new(o!()));
+
+    // Each listener serves only its own API.
+    client
+        .make_request_no_body(Method::GET, "/public", StatusCode::OK)
+        .await
+        .unwrap();
+    client
+        .make_request_error(Method::GET, "/routes", StatusCode::NOT_FOUND)
+        .await;
+    admin_client
+        .make_request_error(Method::GET, "/public", StatusCode::NOT_FOUND)
+        .await;
+
+    // Admin handlers can see the public server's routes and context.
+    let mut response = admin_client
+        .make_request_no_body(Method::GET, "/routes", StatusCode::OK)
+        .await
+        .unwrap();
+    let routes: Vec<String> =
+        dropshot::test_util::read_json(&mut response).await;
+    assert_eq!(routes, vec![String::from("GET /public")]);
+    let mut response = admin_client
+        .make_request_no_body(Method::GET, "/private", StatusCode::OK)
+        .await
+        .unwrap();
+    assert_eq!(dropshot::test_util::read_json::<usize>(&mut response).await, 7);
+
+    // ... and can control the public server without affecting themselves.
+    admin_client
+        .make_request_no_body(
+            Method::PUT,
+            "/maintenance",
+            StatusCode::NO_CONTENT,
+        )
+        .await
+        .unwrap();
+    assert!(server.maintenance_mode().is_some());
+    client
+        .make_request_error(
+            Method::GET,
+            "/public",
+            StatusCode::SERVICE_UNAVAILABLE,
+        )
+        .await;
+    admin_client
+        .make_request_no_body(Method::GET, "/routes", StatusCode::OK)
+        .await
+        .unwrap();
+
+    // Closing the server closes the admin listener too.
+    server.close().await.unwrap();
+    assert!(tokio::net::TcpStream::connect(admin_addr).await.is_err());
+
+    logctx.cleanup_successful();
+}
@@ -0,0 +1,106 @@
+// Copyright 2023 Oxide Computer Company
+
+//! Test cases for `HttpResponseAttachment`.
+
+use dropshot::endpoint;
+use dropshot::ApiDescription;
+use dropshot::HttpError;
+use dropshot::HttpResponseAttachment;
+use dropshot::RequestContext;
+use http::Method;
+use http::StatusCode;
+use http_body_util::BodyExt;
+use std::io::Write;
+
+pub mod common;
+
+const CONTENTS: &str = "id,name\n1,sprocket\n2,widget\n";
+
+#[endpoint {
+    method = GET,
+    path = "/report.csv",
+}]
+async fn report_get(
+    _rqctx: RequestContext<usize>,
+) -> Result<HttpResponseAttachment, HttpError> {
+    let mut file = tempfile::tempfile().unwrap();
+    file.write_all(CONTENTS.as_bytes()).unwrap();
+    let mut file = tokio::fs::File::from_std(file);
+    tokio::io::AsyncSeekExt::rewind(&mut file).await.unwrap();
+    HttpResponseAttachment::from_file(file, "report.csv", "text/csv")
+        .await
+        .map_err(|e| HttpError::for_internal_error(e.to_string()))
+}
+
+#[endpoint {
+    method = GET,
+    path = "/resume",
+}]
+async fn resume_get(
+    _rqctx: RequestContext<usize>,
+) -> Result<HttpResponseAttachment, HttpError> {
+    Ok(HttpResponseAttachment::new(
+        CONTENTS.as_bytes(),
+        "résumé €.txt",
+        "text/plain; charset=utf-8",
+    ))
+}
+
+fn api() -> ApiDescription<usize> {
+    let mut api = ApiDescription::new();
+    api.register(report_get).unwrap();
+    api.register(resume_get).unwrap();
+    api
+}
+
+#[test]
+fn test_attachment_openapi() {
+    let spec = api().openapi("test", "1.0").json().unwrap();
+    let response = &spec["paths"]["/report.csv"]["get"]["responses"]["200"];
+    assert_eq!(response["description"], "file download");
+    let schema = &response["content"]["*/*"]["schema"];
+    assert_eq!(schema["type"], "string");
+    assert_eq!(schema["format"], "binary");
+    let header = &response["headers"]["Content-Disposition"];
+    assert_eq!(header["required"], true);
+    assert_eq!(header["schema"]["type"], "string");
+}
+
+#[tokio::test]
+async fn test_attachment() {
+    let testctx = common::test_setup("attachment", api());
+    let client = &testctx.client_testctx;
+
+    let mut response = client
+        .make_request_no_body(Method::GET, "/report.csv", StatusCode::OK)
+        .await
+        .unwrap();
+    let headers = response.headers();
+    assert_eq!(headers.get(http::header::CONTENT_TYPE).unwrap(), "text/csv");
+    assert_eq!(
+        headers.get(http::header::CONTENT_DISPOSITION).unwrap(),
+        "attachment; filename=\"report.csv\""
+    );
+    assert_eq!(
+        headers.get(http::header::CONTENT_LENGTH).unwrap(),
+        CONTENTS.len().to_string().as_str()
+    );
+    let body = response.body_mut().collect().await.unwrap().to_bytes();
+    assert_eq!(body, CONTENTS);
+
+    let mut response = client
+        .make_request_no_body(Method::GET, "/resume", StatusCode::OK)
+        .await
+        .unwrap();
+    let headers = response.headers();
+    assert_eq!(
+        headers.get(http::header::CONTENT_DISPOSITION).unwrap(),
+        "attachment; filename=\"r_sum_ _.txt\"; \
+         filename*=UTF-8''r%C3%A9sum%C3%A9%20%E2%82%AC.txt"
+    );
+    assert!(headers.get(http::header::CONTENT_LENGTH).is_none());
+    let body = response.body_mut().collect().await.unwrap().to_bytes();
+    assert_eq!(body, CONTENTS);
+
+    testctx.teardown().await;
+}
@@ -0,0 +1,80 @@
+// Copyright 2023 Oxide Computer Company
+
+//! Test cases for response bandwidth limits.
+
+use dropshot::endpoint;
+use dropshot::test_util::read_string;
+use dropshot::ApiDescription;
+use dropshot::Body;
+use dropshot::HttpError;
+use dropshot::RequestContext;
+use http::Method;
+use http::StatusCode;
+use hyper::Response;
+use std::time::Duration;
+use std::time::Instant;
+
+pub mod common;
+
+const DOWNLOAD_BYTES: usize = 96 * 1024;
+
+#[endpoint {
+    method = GET,
+    path = "/download",
+    bandwidth_limit = 32768,
+}]
+async fn download(
+    _rqctx: RequestContext<usize>,
+) -> Result<Response<Body>, HttpError> {
+    Ok(Response::builder()
+        .status(StatusCode::OK)
+        .body(Body::from("x".repeat(DOWNLOAD_BYTES)))?)
+}
+
+#[tokio::test]
+async fn test_endpoint_bandwidth_limit() {
+    let mut api = ApiDescription::new();
+    api.register(download).unwrap();
+    let testctx = common::test_setup("endpoint_bandwidth_limit", api);
+    let client = &testctx.client_testctx;
+
+    let timed_download = || async {
+        let start = Instant::now();
+        let mut response = client
+            .make_request_no_body(Method::GET, "/download", StatusCode::OK)
+            .await
+            .unwrap();
+        assert_eq!(
+            response.headers().get(http::header::CONTENT_LENGTH).unwrap(),
+            &DOWNLOAD_BYTES.to_string()
+        );
+        assert_eq!(read_string(&mut response).await.len(), DOWNLOAD_BYTES);
+        start.elapsed()
+    };
+
+    // The first 32 KiB are sent immediately, and the remaining 64 KiB take
+    // two seconds.
+    assert!(timed_download().await >= Duration::from_millis(1800));
+
+    // Lifting the limit takes effect for the next response.
+    testctx
+        .server
+        .set_endpoint_bandwidth_limit(&Method::GET, "/download", None)
+        .unwrap();
+    assert!(timed_download().await < Duration::from_millis(1000));
+
+    assert_eq!(
+        testctx
+            .server
+            .set_endpoint_bandwidth_limit(&Method::PUT, "/download", None)
+            .unwrap_err(),
+        "no endpoint registered for PUT /download"
+    );
+
+    // A limit on each connection applies to every endpoint.
+    testctx.server.set_connection_bandwidth_limit(Some(32768));
+    assert_eq!(testctx.server.connection_bandwidth_limit(), Some(32768));
+    assert!(timed_download().await >= Duration::from_millis(1800));
+
+    testctx.teardown().await;
+}
@@ -0,0 +1,70 @@
+// Copyright 2023 Oxide Computer Company
+
+//! Test cases for endpoints that accept request bodies of several content
+//! types.
+
+use dropshot::ApiDescription;
+use dropshot::CONTENT_TYPE_JSON;
+use dropshot::CONTENT_TYPE_URL_ENCODED;
+
+pub mod common;
+
+use common::reading::put_reading;
+use common::reading::put_reading_error;
+use common::reading::Reading;
+
+fn api() -> ApiDescription<usize> {
+    common::reading::api(&[CONTENT_TYPE_JSON, CONTENT_TYPE_URL_ENCODED])
+}
+
+#[tokio::test]
+async fn test_body_content_types() {
+    let testctx = common::test_setup("body_content_types", api());
+    let client = &testctx.client_testctx;
+
+    for (content_type, body) in [
+        (CONTENT_TYPE_JSON, r#"{"sensor": "t1", "values": [-3, 300]}"#),
+        (CONTENT_TYPE_URL_ENCODED, "sensor=t1&values[0]=-3&values[1]=300"),
+    ] {
+        assert_eq!(
+            put_reading(client, content_type, body).await,
+            Reading::example()
+        );
+    }
+
+    // The body is parsed according to its declared type.
+    let message = put_reading_error(
+        client,
+        CONTENT_TYPE_URL_ENCODED,
+        r#"{"sensor": "t1"}"#,
+    )
+    .await;
+    assert!(
+        message.starts_with("unable to parse URL-encoded body: "),
+        "{}",
+        message
+    );
+
+    assert_eq!(
+        put_reading_error(client, "application/cbor", "").await,
+        "expected content type \"application/json\" or \
+         \"application/x-www-form-urlencoded\", got \"application/cbor\""
+    );
+
+    testctx.teardown().await;
+}
+
+#[test]
+fn test_body_content_types_openapi() {
+    let spec = api().openapi("test", "1.0").json().unwrap();
+    let content = &spec["paths"]["/reading"]["put"]["requestBody"]["content"];
+    let content = content.as_object().unwrap();
+    assert_eq!(
+        content.keys().collect::<Vec<_>>(),
+        vec![CONTENT_TYPE_JSON, CONTENT_TYPE_URL_ENCODED]
+    );
+    assert_eq!(
+        content[CONTENT_TYPE_JSON]["schema"],
+        content[CONTENT_TYPE_URL_ENCODED]["schema"]
+    );
+}
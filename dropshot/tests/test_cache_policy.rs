@@ -0,0 +1,68 @@
+// Copyright 2023 Oxide Computer Company
+
+//! Test cases for `CachePolicy` and `HttpResponseCached`.
+
+use dropshot::endpoint;
+use dropshot::ApiDescription;
+use dropshot::CachePolicy;
+use dropshot::HttpError;
+use dropshot::HttpResponseCached;
+use dropshot::HttpResponseOk;
+use dropshot::RequestContext;
+use http::Method;
+use http::StatusCode;
+use std::time::Duration;
+
+pub mod common;
+
+#[endpoint {
+    method = GET,
+    path = "/widget",
+}]
+async fn widget_get(
+    _rqctx: RequestContext<usize>,
+) -> Result<HttpResponseCached<HttpResponseOk<String>>, HttpError> {
+    let policy = CachePolicy::new()
+        .public()
+        .max_age(Duration::from_secs(60))
+        .s_maxage(Duration::from_secs(300))
+        .stale_while_revalidate(Duration::from_secs(30));
+    Ok(HttpResponseCached::new(
+        HttpResponseOk(String::from("sprocket")),
+        policy,
+    ))
+}
+
+fn api() -> ApiDescription<usize> {
+    let mut api = ApiDescription::new();
+    api.register(widget_get).unwrap();
+    api
+}
+
+#[test]
+fn test_cache_policy_openapi() {
+    let spec = api().openapi("test", "1.0").json().unwrap();
+    let response = &spec["paths"]["/widget"]["get"]["responses"]["200"];
+    assert_eq!(response["description"], "successful operation");
+    let header = &response["headers"]["Cache-Control"];
+    assert_eq!(header["description"], "caching policy for the response");
+    assert_eq!(header["required"], true);
+    assert_eq!(header["schema"]["type"], "string");
+}
+
+#[tokio::test]
+async fn test_cache_policy() {
+    let testctx = common::test_setup("cache_policy", api());
+    let client = &testctx.client_testctx;
+
+    let response = client
+        .make_request_no_body(Method::GET, "/widget", StatusCode::OK)
+        .await
+        .unwrap();
+    assert_eq!(
+        response.headers().get(http::header::CACHE_CONTROL).unwrap(),
+        "public, max-age=60, s-maxage=300, stale-while-revalidate=30"
+    );
+
+    testctx.teardown().await;
+}
@@ -0,0 +1,132 @@
+// Copyright 2023 Oxide Computer Company
+
+//! Test cases for CBOR request and response bodies.
+
+use dropshot::endpoint;
+use dropshot::ApiDescription;
+use dropshot::Cbor;
+use dropshot::HttpError;
+use dropshot::HttpResponseOk;
+use dropshot::RequestContext;
+use dropshot::TypedBody;
+use dropshot::CONTENT_TYPE_CBOR;
+use http::Method;
+use http::StatusCode;
+use http_body_util::BodyExt;
+use hyper::Request;
+
+pub mod common;
+
+use common::reading::put_reading;
+use common::reading::put_reading_error;
+use common::reading::Reading;
+
+#[endpoint {
+    method = PUT,
+    path = "/reading/reversed",
+    content_type = "application/cbor",
+}]
+async fn reading_reversed_put(
+    _rqctx: RequestContext<usize>,
+    body: TypedBody<Reading>,
+) -> Result<HttpResponseOk<Cbor<Reading>>, HttpError> {
+    let mut reading = body.into_inner();
+    reading.values.reverse();
+    Ok(HttpResponseOk(Cbor(reading)))
+}
+
+fn api() -> ApiDescription<usize> {
+    let mut api = common::reading::api(&[CONTENT_TYPE_CBOR]);
+    api.register(reading_reversed_put).unwrap();
+    api
+}
+
+/// `{"sensor": "t1", "values": [-3, 300]}`
+const READING: &[u8] = &[
+    0xa2, // map of 2
+    0x66, b's', b'e', b'n', b's', b'o', b'r', // "sensor"
+    0x62, b't', b'1', // "t1"
+    0x66, b'v', b'a', b'l', b'u', b'e', b's', // "values"
+    0x82, 0x22, 0x19, 0x01, 0x2c, // [-3, 300]
+];
+
+/// `{"sensor": "t1", "values": [300, -3]}`
+const REVERSED: &[u8] = &[
+    0xa2, // map of 2
+    0x66, b's', b'e', b'n', b's', b'o', b'r', // "sensor"
+    0x62, b't', b'1', // "t1"
+    0x66, b'v', b'a', b'l', b'u', b'e', b's', // "values"
+    0x82, 0x19, 0x01, 0x2c, 0x22, // [300, -3]
+];
+
+#[tokio::test]
+async fn test_cbor_bodies() {
+    let testctx = common::test_setup("cbor_bodies", api());
+    let client = &testctx.client_testctx;
+
+    assert_eq!(
+        put_reading(client, CONTENT_TYPE_CBOR, READING).await,
+        Reading::example()
+    );
+
+    // A "self-described CBOR" tag doesn't change the meaning of the data.
+    let tagged = [&[0xd9u8, 0xd9, 0xf7][..], READING].concat();
+    assert_eq!(
+        put_reading(client, CONTENT_TYPE_CBOR, tagged).await,
+        Reading::example()
+    );
+
+    assert_eq!(
+        put_reading_error(client, CONTENT_TYPE_CBOR, &READING[..8]).await,
+        "unable to parse CBOR body: unexpected end of data"
+    );
+    let trailing = [READING, &[0x00u8][..]].concat();
+    assert_eq!(
+        put_reading_error(client, CONTENT_TYPE_CBOR, trailing).await,
+        format!(
+            "unable to parse CBOR body: trailing data at offset {}",
+            READING.len()
+        )
+    );
+
+    testctx.teardown().await;
+}
+
+#[tokio::test]
+async fn test_cbor_responses() {
+    let testctx = common::test_setup("cbor_responses", api());
+    let client = &testctx.client_testctx;
+
+    let request = Request::builder()
+        .method(Method::PUT)
+        .uri(client.url("/reading/reversed"))
+        .header(http::header::CONTENT_TYPE, CONTENT_TYPE_CBOR)
+        .body(READING.to_vec().into())
+        .unwrap();
+    let mut response = client
+        .make_request_with_request(request, StatusCode::OK)
+        .await
+        .unwrap();
+    assert_eq!(
+        response.headers().get(http::header::CONTENT_TYPE).unwrap(),
+        CONTENT_TYPE_CBOR
+    );
+    let body = response.body_mut().collect().await.unwrap().to_bytes();
+    assert_eq!(body.as_ref(), REVERSED);
+
+    testctx.teardown().await;
+}
+
+#[test]
+fn test_cbor_openapi() {
+    let spec = api().openapi("test", "1.0").json().unwrap();
+    let operation = &spec["paths"]["/reading"]["put"];
+    let request_content = &operation["requestBody"]["content"];
+    assert!(request_content.get(CONTENT_TYPE_CBOR).is_some());
+    assert!(request_content.get("application/json").is_none());
+
+    let operation = &spec["paths"]["/reading/reversed"]["put"];
+    let response_content = &operation["responses"]["200"]["content"];
+    assert!(response_content.get(CONTENT_TYPE_CBOR).is_some());
+    assert!(response_content.get("application/json").is_none());
+}
@@ -0,0 +1,92 @@
+// Copyright 2023 Oxide Computer Company
+
+//! Test cases for request bodies in character sets other than UTF-8.
+
+use dropshot::endpoint;
+use dropshot::test_util::read_json;
+use dropshot::ApiDescription;
+use dropshot::Body;
+use dropshot::HttpError;
+use dropshot::HttpResponseOk;
+use dropshot::RequestContext;
+use dropshot::TypedBody;
+use http::Method;
+use http::StatusCode;
+use hyper::Request;
+use schemars::JsonSchema;
+use serde::Deserialize;
+use serde::Serialize;
+
+pub mod common;
+
+#[derive(Debug, Deserialize, JsonSchema, PartialEq, Serialize)]
+struct Place {
+    name: String,
+}
+
+#[endpoint {
+    method = PUT,
+    path = "/place",
+}]
+async fn place_put(
+    _rqctx: RequestContext<usize>,
+    body: TypedBody<Place>,
+) -> Result<HttpResponseOk<Place>, HttpError> {
+    Ok(HttpResponseOk(body.into_inner()))
+}
+
+fn api() -> ApiDescription<usize> {
+    let mut api = ApiDescription::new();
+    api.register(place_put).unwrap();
+    api
+}
+
+fn request(
+    client: &dropshot::test_util::ClientTestContext,
+    content_type: &str,
+    body: &'static [u8],
+) -> Request<Body> {
+    Request::builder()
+        .method(Method::PUT)
+        .uri(client.url("/place"))
+        .header(http::header::CONTENT_TYPE, content_type)
+        .body(Body::from(body))
+        .unwrap()
+}
+
+#[tokio::test]
+async fn test_charset() {
+    let testctx = common::test_setup("charset", api());
+    let client = &testctx.client_testctx;
+    let expected = Place { name: String::from("Zürich") };
+
+    for (content_type, body) in [
+        ("application/json", "{\"name\": \"Zürich\"}".as_bytes()),
+        (
+            "application/json; charset=utf-8",
+            "{\"name\": \"Zürich\"}".as_bytes(),
+        ),
+        ("application/json; charset=ISO-8859-1", b"{\"name\": \"Z\xfcrich\"}"),
+    ] {
+        let mut response = client
+            .make_request_with_request(
+                request(client, content_type, body),
+                StatusCode::OK,
+            )
+            .await
+            .unwrap();
+        let place: Place = read_json(&mut response).await;
+        assert_eq!(place, expected);
+    }
+
+    let error = client
+        .make_request_with_request(
+            request(client, "application/json; charset=utf-16", b"{}"),
+            StatusCode::UNSUPPORTED_MEDIA_TYPE,
+        )
+        .await
+        .unwrap_err();
+    assert_eq!(error.message, "unsupported charset \"utf-16\"");
+
+    testctx.teardown().await;
+}
@@ -0,0 +1,105 @@
+// Copyright 2023 Oxide Computer Company
+
+//! Test cases for coalescing of concurrent identical GET requests.
+
+use dropshot::endpoint;
+use dropshot::test_util::read_json;
+use dropshot::ApiDescription;
+use dropshot::Body;
+use dropshot::HttpError;
+use dropshot::HttpResponseOk;
+use dropshot::RequestContext;
+use http::Method;
+use http::StatusCode;
+use hyper::Request;
+use std::sync::atomic::AtomicUsize;
+use std::sync::atomic::Ordering;
+use std::time::Duration;
+
+pub mod common;
+
+static NCALLS: AtomicUsize = AtomicUsize::new(0);
+
+#[endpoint {
+    method = GET,
+    path = "/report",
+    coalesce = [ "accept-language" ],
+}]
+async fn report_get(
+    _rqctx: RequestContext<usize>,
+) -> Result<HttpResponseOk<usize>, HttpError> {
+    let ncalls = NCALLS.fetch_add(1, Ordering::SeqCst);
+    tokio::time::sleep(Duration::from_millis(500)).await;
+    Ok(HttpResponseOk(ncalls))
+}
+
+#[endpoint {
+    method = POST,
+    path = "/report",
+    coalesce = [],
+}]
+async fn report_post(
+    _rqctx: RequestContext<usize>,
+) -> Result<HttpResponseOk<usize>, HttpError> {
+    Ok(HttpResponseOk(0))
+}
+
+#[test]
+fn test_coalesce_get_only() {
+    let mut api = ApiDescription::<usize>::new();
+    assert_eq!(
+        api.register(report_post).unwrap_err(),
+        "endpoint report_post coalesces requests, but only GET requests may \
+         be coalesced"
+    );
+}
+
+#[tokio::test]
+async fn test_coalesce_requests() {
+    let mut api = ApiDescription::new();
+    api.register(report_get).unwrap();
+    let testctx = common::test_setup("coalesce_requests", api);
+    let client = &testctx.client_testctx;
+
+    let get = |language: &str| {
+        let request = Request::builder()
+            .method(Method::GET)
+            .uri(client.url("/report"))
+            .header("accept-language", language)
+            .body(Body::empty())
+            .unwrap();
+        async move {
+            let mut response = client
+                .make_request_with_request(request, StatusCode::OK)
+                .await
+                .unwrap();
+            let request_id = response
+                .headers()
+                .get("x-request-id")
+                .unwrap()
+                .to_str()
+                .unwrap()
+                .to_string();
+            let ncalls: usize = read_json(&mut response).await;
+            (ncalls, request_id)
+        }
+    };
+
+    // Three concurrent identical requests share one invocation of the handler,
+    // but each gets its own request id.  A request with a different value for
+    // a header in the key gets its own invocation.
+    let (r1, r2, r3, r4) =
+        tokio::join!(get("en"), get("en"), get("en"), get("fr"));
+    assert_eq!(r1.0, r2.0);
+    assert_eq!(r1.0, r3.0);
+    assert_ne!(r1.0, r4.0);
+    assert_ne!(r1.1, r2.1);
+    assert_ne!(r2.1, r3.1);
+    assert_eq!(NCALLS.load(Ordering::SeqCst), 2);
+
+    // Once those have completed, the next request runs the handler again.
+    let (ncalls, _) = get("en").await;
+    assert_eq!(ncalls, 2);
+
+    testctx.teardown().await;
+}
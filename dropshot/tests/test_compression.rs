@@ -0,0 +1,175 @@
+// Copyright 2023 Oxide Computer Company
+
+//! Test cases for compressed response bodies.
+
+#![cfg(feature = "compression")]
+
+use async_compression::tokio::bufread::BrotliDecoder;
+use async_compression::tokio::bufread::GzipDecoder;
+use async_compression::tokio::bufread::ZstdDecoder;
+use dropshot::endpoint;
+use dropshot::test_util::TestContext;
+use dropshot::ApiDescription;
+use dropshot::Body;
+use dropshot::ConfigCompression;
+use dropshot::ConfigDropshot;
+use dropshot::HttpError;
+use dropshot::HttpResponseOk;
+use dropshot::RequestContext;
+use http::Method;
+use http::Response;
+use http::StatusCode;
+use http_body_util::BodyExt;
+use hyper::Request;
+use tokio::io::AsyncRead;
+use tokio::io::AsyncReadExt;
+
+pub mod common;
+
+fn big_text() -> String {
+    "all work and no play makes jack a dull boy ".repeat(100)
+}
+
+#[endpoint {
+    method = GET,
+    path = "/big",
+}]
+async fn big_get(
+    _rqctx: RequestContext<usize>,
+) -> Result<HttpResponseOk<String>, HttpError> {
+    Ok(HttpResponseOk(big_text()))
+}
+
+#[endpoint {
+    method = GET,
+    path = "/small",
+}]
+async fn small_get(
+    _rqctx: RequestContext<usize>,
+) -> Result<HttpResponseOk<String>, HttpError> {
+    Ok(HttpResponseOk(String::from("tiny")))
+}
+
+#[endpoint {
+    method = GET,
+    path = "/uncompressed",
+    compression = false,
+}]
+async fn uncompressed_get(
+    _rqctx: RequestContext<usize>,
+) -> Result<HttpResponseOk<String>, HttpError> {
+    Ok(HttpResponseOk(big_text()))
+}
+
+fn setup(test_name: &str) -> TestContext<usize> {
+    let mut api = ApiDescription::new();
+    api.register(big_get).unwrap();
+    api.register(small_get).unwrap();
+    api.register(uncompressed_get).unwrap();
+    let config = ConfigDropshot {
+        compression: Some(ConfigCompression::default()),
+        ..Default::default()
+    };
+    common::test_setup_with_config(test_name, api, &config)
+}
+
+async fn get(
+    testctx: &TestContext<usize>,
+    path: &str,
+    accept_encoding: Option<&str>,
+) -> Response<Body> {
+    let mut request = Request::builder()
+        .method(Method::GET)
+        .uri(testctx.client_testctx.url(path));
+    if let Some(accept_encoding) = accept_encoding {
+        request =
+            request.header(http::header::ACCEPT_ENCODING, accept_encoding);
+    }
+    testctx
+        .client_testctx
+        .make_request_with_request(
+            request.body(Body::empty()).unwrap(),
+            StatusCode::OK,
+        )
+        .await
+        .unwrap()
+}
+
+async fn read_body(response: &mut Response<Body>) -> Vec<u8> {
+    response.body_mut().collect().await.unwrap().to_bytes().to_vec()
+}
+
+async fn decode<R: AsyncRead + Unpin>(mut decoder: R) -> String {
+    let mut decoded = String::new();
+    decoder.read_to_string(&mut decoded).await.unwrap();
+    decoded
+}
+
+#[tokio::test]
+async fn test_compressed_responses() {
+    let testctx = setup("compressed_responses");
+    let expected = serde_json::to_string(&big_text()).unwrap();
+
+    for coding in ["gzip", "br", "zstd"] {
+        let mut response = get(&testctx, "/big", Some(coding)).await;
+        let headers = response.headers();
+        assert_eq!(
+            headers.get(http::header::CONTENT_ENCODING).unwrap(),
+            coding
+        );
+        assert_eq!(headers.get(http::header::VARY).unwrap(), "accept-encoding");
+        assert!(headers.get(http::header::CONTENT_LENGTH).is_none());
+        let body = read_body(&mut response).await;
+        assert!(body.len() < expected.len());
+        let decoded = match coding {
+            "gzip" => decode(GzipDecoder::new(body.as_slice())).await,
+            "br" => decode(BrotliDecoder::new(body.as_slice())).await,
+            _ => decode(ZstdDecoder::new(body.as_slice())).await,
+        };
+        assert_eq!(decoded, expected);
+    }
+
+    // The client's preferences win, and the server's break ties.
+    let response = get(&testctx, "/big", Some("gzip, br;q=0.5")).await;
+    assert_eq!(
+        response.headers().get(http::header::CONTENT_ENCODING).unwrap(),
+        "gzip"
+    );
+    let response = get(&testctx, "/big", Some("gzip, br")).await;
+    assert_eq!(
+        response.headers().get(http::header::CONTENT_ENCODING).unwrap(),
+        "br"
+    );
+
+    testctx.teardown().await;
+}
+
+#[tokio::test]
+async fn test_uncompressed_responses() {
+    let testctx = setup("uncompressed_responses");
+    let expected = serde_json::to_string(&big_text()).unwrap();
+
+    // Clients that don't ask for compression don't get it, but the response
+    // still varies with the header.
+    for accept_encoding in [None, Some("identity"), Some("gzip;q=0")] {
+        let mut response = get(&testctx, "/big", accept_encoding).await;
+        let headers = response.headers();
+        assert!(headers.get(http::header::CONTENT_ENCODING).is_none());
+        assert_eq!(headers.get(http::header::VARY).unwrap(), "accept-encoding");
+        assert_eq!(read_body(&mut response).await, expected.as_bytes());
+    }
+
+    // Small bodies aren't compressed.
+    let mut response = get(&testctx, "/small", Some("gzip")).await;
+    assert!(response.headers().get(http::header::CONTENT_ENCODING).is_none());
+    assert_eq!(read_body(&mut response).await, b"\"tiny\"");
+
+    // Endpoints can opt out.
+    let mut response = get(&testctx, "/uncompressed", Some("gzip")).await;
+    let headers = response.headers();
+    assert!(headers.get(http::header::CONTENT_ENCODING).is_none());
+    assert!(headers.get(http::header::VARY).is_none());
+    assert_eq!(read_body(&mut response).await, expected.as_bytes());
+
+    testctx.teardown().await;
+}
@@ -2,8 +2,11 @@
 //! Tests for configuration file.
 
 use dropshot::test_util::read_config;
-use dropshot::{ConfigDropshot, ConfigTls};
+use dropshot::{Body, ConfigDropshot, ConfigTls};
 use dropshot::{HttpServer, HttpServerStarter};
+use hyper_util::client::legacy::connect::{Connect, HttpConnector};
+use hyper_util::client::legacy::Client;
+use hyper_util::rt::TokioExecutor;
 use slog::o;
 use slog::Logger;
 use std::str::FromStr;
@@ -11,6 +14,7 @@ use tempfile::NamedTempFile;
 
 pub mod common;
 use common::create_log_context;
+use common::HttpsConnector;
 
 // Bad values for "bind_address"
 
@@ -149,6 +153,7 @@ fn make_config(
         ),
         request_body_max_bytes: 1024,
         tls,
+        ..Default::default()
     }
 }
 
@@ -156,9 +161,9 @@ fn make_config(
 // test logic
 trait TestConfigBindServer<C>
 where
-    C: hyper::client::connect::Connect + Clone + Send + Sync + 'static,
+    C: Connect + Clone + Send + Sync + 'static,
 {
-    fn make_client(&self) -> hyper::Client<C>;
+    fn make_client(&self) -> Client<C, Body>;
     fn make_server(&self, bind_port: u16) -> HttpServer<i32>;
     fn make_uri(&self, bind_port: u16) -> hyper::Uri;
 
@@ -169,7 +174,7 @@ where
 // it binds to ports as expected.
 async fn test_config_bind_server<C, T>(test_config: T, bind_port: u16)
 where
-    C: hyper::client::connect::Connect + Clone + Send + Sync + 'static,
+    C: Connect + Clone + Send + Sync + 'static,
     T: TestConfigBindServer<C>,
 {
     let client = test_config.make_client();
@@ -222,13 +227,9 @@ async fn test_config_bind_address_http() {
     struct ConfigBindServerHttp {
         log: slog::Logger,
     }
-    impl TestConfigBindServer<hyper::client::connect::HttpConnector>
-        for ConfigBindServerHttp
-    {
-        fn make_client(
-            &self,
-        ) -> hyper::Client<hyper::client::connect::HttpConnector> {
-            hyper::Client::new()
+    impl TestConfigBindServer<HttpConnector> for ConfigBindServerHttp {
+        fn make_client(&self) -> Client<HttpConnector, Body> {
+            Client::builder(TokioExecutor::new()).build_http()
         }
 
         fn make_uri(&self, bind_port: u16) -> hyper::Uri {
@@ -262,16 +263,8 @@ async fn test_config_bind_address_https() {
         key_file: NamedTempFile,
     }
 
-    impl
-        TestConfigBindServer<
-            hyper_rustls::HttpsConnector<hyper::client::connect::HttpConnector>,
-        > for ConfigBindServerHttps
-    {
-        fn make_client(
-            &self,
-        ) -> hyper::Client<
-            hyper_rustls::HttpsConnector<hyper::client::connect::HttpConnector>,
-        > {
+    impl TestConfigBindServer<HttpsConnector> for ConfigBindServerHttps {
+        fn make_client(&self) -> Client<HttpsConnector, Body> {
             // Configure TLS to trust the self-signed cert
             let mut root_store = rustls::RootCertStore { roots: vec![] };
             root_store
@@ -282,12 +275,8 @@ async fn test_config_bind_address_https() {
                 .with_safe_defaults()
                 .with_root_certificates(root_store)
                 .with_no_client_auth();
-            let https_connector = hyper_rustls::HttpsConnectorBuilder::new()
-                .with_tls_config(tls_config)
-                .https_only()
-                .enable_http1()
-                .build();
-            hyper::Client::builder().build(https_connector)
+            Client::builder(TokioExecutor::new())
+                .build(HttpsConnector::new(tls_config))
         }
 
         fn make_uri(&self, bind_port: u16) -> hyper::Uri {
@@ -298,6 +287,7 @@ async fn test_config_bind_address_https() {
             let tls = Some(ConfigTls::AsFile {
                 cert_file: self.cert_file.path().to_path_buf(),
                 key_file: self.key_file.path().to_path_buf(),
+                client_auth: None,
             });
             let config = make_config("127.0.0.1", bind_port, tls);
             make_server(&config, &self.log).start()
@@ -333,16 +323,8 @@ async fn test_config_bind_address_https_buffer() {
         serialized_key: Vec<u8>,
     }
 
-    impl
-        TestConfigBindServer<
-            hyper_rustls::HttpsConnector<hyper::client::connect::HttpConnector>,
-        > for ConfigBindServerHttps
-    {
-        fn make_client(
-            &self,
-        ) -> hyper::Client<
-            hyper_rustls::HttpsConnector<hyper::client::connect::HttpConnector>,
-        > {
+    impl TestConfigBindServer<HttpsConnector> for ConfigBindServerHttps {
+        fn make_client(&self) -> Client<HttpsConnector, Body> {
             // Configure TLS to trust the self-signed cert
             let mut root_store = rustls::RootCertStore { roots: vec![] };
             root_store
@@ -353,12 +335,8 @@ async fn test_config_bind_address_https_buffer() {
                 .with_safe_defaults()
                 .with_root_certificates(root_store)
                 .with_no_client_auth();
-            let https_connector = hyper_rustls::HttpsConnectorBuilder::new()
-                .with_tls_config(tls_config)
-                .https_only()
-                .enable_http1()
-                .build();
-            hyper::Client::builder().build(https_connector)
+            Client::builder(TokioExecutor::new())
+                .build(HttpsConnector::new(tls_config))
         }
 
         fn make_uri(&self, bind_port: u16) -> hyper::Uri {
@@ -369,6 +347,7 @@ async fn test_config_bind_address_https_buffer() {
             let tls = Some(ConfigTls::AsBytes {
                 certs: self.serialized_certs.clone(),
                 key: self.serialized_key.clone(),
+                client_auth: None,
             });
             let config = make_config("127.0.0.1", bind_port, tls);
             make_server(&config, &self.log).start()
@@ -396,3 +375,111 @@ async fn test_config_bind_address_https_buffer() {
 
     logctx.cleanup_successful();
 }
+
+#[tokio::test]
+async fn test_config_tcp_listener() {
+    let logctx = create_log_context("config_tcp_listener");
+    let log = logctx.log.new(o!());
+
+    // A server given a listener accepts connections on it rather than binding
+    // to its configured address.
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    let config = make_config("127.0.0.1", 0, None);
+    let server = HttpServerStarter::new_with_tcp_listener(
+        &config,
+        dropshot::ApiDescription::new(),
+        0,
+        &log,
+        listener,
+    )
+    .unwrap()
+    .start();
+    assert_eq!(server.local_addr(), addr);
+    let client = Client::builder(TokioExecutor::new()).build_http::<Body>();
+    let uri = format!("http://{}/", addr).parse().unwrap();
+    client.get(uri).await.unwrap();
+    server.close().await.unwrap();
+
+    // Socket activation fails if the process wasn't passed any sockets.
+    let config = ConfigDropshot { listen_fd: Some(0), ..Default::default() };
+    let error = HttpServerStarter::new(
+        &config,
+        dropshot::ApiDescription::<i32>::new(),
+        0,
+        &log,
+    )
+    .map(|_| ())
+    .unwrap_err();
+    assert!(error.to_string().starts_with("socket activation: "));
+
+    logctx.cleanup_successful();
+}
+
+#[cfg(all(unix, not(target_os = "illumos"), not(target_os = "solaris")))]
+#[tokio::test]
+async fn test_config_reuseport() {
+    let logctx = create_log_context("config_reuseport");
+    let log = logctx.log.new(o!());
+
+    let mut config = make_config("127.0.0.1", 0, None);
+    config.reuseport = Some(dropshot::ConfigReusePort { acceptors: Some(3) });
+    let server = make_server(&config, &log).start();
+    let uri: hyper::Uri =
+        format!("http://{}/", server.local_addr()).parse().unwrap();
+
+    // Each client makes its own connection, which one of the acceptors
+    // accepts.
+    for _ in 0..10 {
+        let client = Client::builder(TokioExecutor::new()).build_http::<Body>();
+        client.get(uri.clone()).await.unwrap();
+    }
+    let stats = server.acceptor_stats();
+    assert_eq!(stats.len(), 3);
+    assert_eq!(
+        stats.iter().map(|stats| stats.accepted_connections).sum::<u64>(),
+        10
+    );
+    server.close().await.unwrap();
+
+    config.reuseport = Some(dropshot::ConfigReusePort { acceptors: Some(0) });
+    let error = HttpServerStarter::new(
+        &config,
+        dropshot::ApiDescription::<i32>::new(),
+        0,
+        &log,
+    )
+    .map(|_| ())
+    .unwrap_err();
+    assert_eq!(error.to_string(), "reuseport requires at least one acceptor");
+
+    logctx.cleanup_successful();
+}
+
+#[tokio::test]
+async fn test_config_socket_options() {
+    let logctx = create_log_context("config_socket_options");
+    let log = logctx.log.new(o!());
+
+    let config = read_config::<ConfigDropshot>(
+        "socket_options",
+        r#"
+            bind_address = "127.0.0.1:0"
+
+            [socket_options]
+            tcp_nodelay = true
+            tcp_keepalive_secs = 60
+            recv_buffer_size = 65536
+            send_buffer_size = 65536
+            ipv6_only = true
+        "#,
+    )
+    .unwrap();
+    let server = make_server(&config, &log).start();
+    let client = Client::builder(TokioExecutor::new()).build_http::<Body>();
+    let uri = format!("http://{}/", server.local_addr()).parse().unwrap();
+    client.get(uri).await.unwrap();
+    server.close().await.unwrap();
+
+    logctx.cleanup_successful();
+}
@@ -0,0 +1,70 @@
+// Copyright 2023 Oxide Computer Company
+
+//! Test cases for the connection information available to handlers.
+
+use dropshot::endpoint;
+use dropshot::test_util::read_json;
+use dropshot::ApiDescription;
+use dropshot::HttpError;
+use dropshot::HttpResponseOk;
+use dropshot::RequestContext;
+use http::Method;
+use http::StatusCode;
+use schemars::JsonSchema;
+use serde::Deserialize;
+use serde::Serialize;
+use std::net::SocketAddr;
+
+pub mod common;
+
+#[derive(Debug, Deserialize, JsonSchema, Serialize)]
+struct Connection {
+    remote_addr: SocketAddr,
+    local_addr: SocketAddr,
+    tls: bool,
+    alpn_protocol: Option<String>,
+}
+
+#[endpoint {
+    method = GET,
+    path = "/connection",
+}]
+async fn connection_get(
+    rqctx: RequestContext<usize>,
+) -> Result<HttpResponseOk<Connection>, HttpError> {
+    let connection = rqctx.connection();
+    Ok(HttpResponseOk(Connection {
+        remote_addr: connection.remote_addr(),
+        local_addr: connection.local_addr(),
+        tls: connection.is_tls(),
+        alpn_protocol: connection
+            .alpn_protocol()
+            .map(|p| String::from_utf8_lossy(p).into_owned()),
+    }))
+}
+
+fn api() -> ApiDescription<usize> {
+    let mut api = ApiDescription::new();
+    api.register(connection_get).unwrap();
+    api
+}
+
+#[tokio::test]
+async fn test_connection_info() {
+    let testctx = common::test_setup("connection_info", api());
+    let client = &testctx.client_testctx;
+    let server_addr = testctx.server.local_addr();
+
+    let mut response = client
+        .make_request_no_body(Method::GET, "/connection", StatusCode::OK)
+        .await
+        .unwrap();
+    let connection: Connection = read_json(&mut response).await;
+    assert_eq!(connection.local_addr, server_addr);
+    assert_eq!(connection.remote_addr.ip(), server_addr.ip());
+    assert_ne!(connection.remote_addr.port(), server_addr.port());
+    assert!(!connection.tls);
+    assert_eq!(connection.alpn_protocol, None);
+
+    testctx.teardown().await;
+}
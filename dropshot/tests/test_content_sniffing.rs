@@ -0,0 +1,77 @@
+// Copyright 2023 Oxide Computer Company
+
+//! Test cases for verifying the type of uploaded content.
+
+use dropshot::endpoint;
+use dropshot::test_util::read_json;
+use dropshot::ApiDescription;
+use dropshot::Body;
+use dropshot::HttpError;
+use dropshot::HttpResponseOk;
+use dropshot::RequestContext;
+use dropshot::UntypedBody;
+use http::Method;
+use http::StatusCode;
+use hyper::Request;
+
+pub mod common;
+
+#[endpoint {
+    method = PUT,
+    path = "/avatar",
+}]
+async fn avatar_put(
+    _rqctx: RequestContext<usize>,
+    body: UntypedBody,
+) -> Result<HttpResponseOk<String>, HttpError> {
+    let media_type = body.verify_content_type(&["image/png", "image/jpeg"])?;
+    Ok(HttpResponseOk(media_type))
+}
+
+#[tokio::test]
+async fn test_content_sniffing() {
+    let mut api = ApiDescription::new();
+    api.register(avatar_put).unwrap();
+    let testctx = common::test_setup("content_sniffing", api);
+    let client = &testctx.client_testctx;
+
+    let upload = |content_type: &str, body: &'static [u8]| {
+        Request::builder()
+            .method(Method::PUT)
+            .uri(client.url("/avatar"))
+            .header(http::header::CONTENT_TYPE, content_type)
+            .body(Body::from(body))
+            .unwrap()
+    };
+
+    let mut response = client
+        .make_request_with_request(
+            upload("image/png", b"\x89PNG\r\n\x1a\n\x00\x00\x00\rIHDR"),
+            StatusCode::OK,
+        )
+        .await
+        .unwrap();
+    let media_type: String = read_json(&mut response).await;
+    assert_eq!(media_type, "image/png");
+
+    let error = client
+        .make_request_with_request(
+            upload("image/png", b"<html><script>alert(1)</script></html>"),
+            StatusCode::UNSUPPORTED_MEDIA_TYPE,
+        )
+        .await
+        .unwrap_err();
+    assert_eq!(error.error_code.as_deref(), Some("UnsupportedMediaType"));
+    assert_eq!(error.message, "body does not match content type \"image/png\"");
+
+    let error = client
+        .make_request_with_request(
+            upload("image/gif", b"GIF89a"),
+            StatusCode::UNSUPPORTED_MEDIA_TYPE,
+        )
+        .await
+        .unwrap_err();
+    assert_eq!(error.message, "content type \"image/gif\" is not allowed");
+
+    testctx.teardown().await;
+}
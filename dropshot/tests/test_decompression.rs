@@ -0,0 +1,308 @@
+// Copyright 2023 Oxide Computer Company
+
+//! Test cases for compressed request bodies.
+
+use dropshot::endpoint;
+use dropshot::test_util::read_json;
+use dropshot::test_util::TestContext;
+use dropshot::ApiDescription;
+use dropshot::Body;
+use dropshot::ConfigDropshot;
+use dropshot::ContentDecoder;
+use dropshot::HttpError;
+use dropshot::HttpResponseOk;
+use dropshot::RequestContext;
+use dropshot::TypedBody;
+use http::Method;
+use http::StatusCode;
+use hyper::Request;
+use schemars::JsonSchema;
+use serde::Deserialize;
+use serde::Serialize;
+use std::io::Write;
+use std::sync::Arc;
+
+pub mod common;
+
+#[derive(Debug, Deserialize, JsonSchema, PartialEq, Serialize)]
+struct Thing {
+    name: String,
+    count: u32,
+}
+
+#[endpoint {
+    method = PUT,
+    path = "/thing",
+}]
+async fn thing_put(
+    _rqctx: RequestContext<usize>,
+    body: TypedBody<Thing>,
+) -> Result<HttpResponseOk<Thing>, HttpError> {
+    Ok(HttpResponseOk(body.into_inner()))
+}
+
+/// `{"name":"dropshot","count":3}` compressed with gzip
+const THING_GZIP: &[u8] = &[
+    0x1f, 0x8b, 0x08, 0x00, 0x00, 0x00, 0x00, 0x00, 0x02, 0x03, 0xab, 0x56,
+    0xca, 0x4b, 0xcc, 0x4d, 0x55, 0xb2, 0x52, 0x4a, 0x29, 0xca, 0x2f, 0x28,
+    0xce, 0xc8, 0x2f, 0x51, 0xd2, 0x51, 0x4a, 0xce, 0x2f, 0xcd, 0x2b, 0x51,
+    0xb2, 0x32, 0xae, 0x05, 0x00, 0x97, 0xb4, 0x36, 0x59, 0x1d, 0x00, 0x00,
+    0x00,
+];
+/// `{"name":"dropshot","count":3}` compressed with zlib ("deflate")
+const THING_DEFLATE: &[u8] = &[
+    0x78, 0x9c, 0xab, 0x56, 0xca, 0x4b, 0xcc, 0x4d, 0x55, 0xb2, 0x52, 0x4a,
+    0x29, 0xca, 0x2f, 0x28, 0xce, 0xc8, 0x2f, 0x51, 0xd2, 0x51, 0x4a, 0xce,
+    0x2f, 0xcd, 0x2b, 0x51, 0xb2, 0x32, 0xae, 0x05, 0x00, 0x94, 0xd2, 0x09,
+    0xd5,
+];
+/// A thing whose name is 2,000 "a"s, compressed with gzip
+const BIG_THING_GZIP: &[u8] = &[
+    0x1f, 0x8b, 0x08, 0x00, 0x00, 0x00, 0x00, 0x00, 0x02, 0x03, 0xab, 0x56,
+    0xca, 0x4b, 0xcc, 0x4d, 0x55, 0xb2, 0x52, 0x4a, 0x1c, 0x05, 0xa3, 0x60,
+    0x14, 0x8c, 0x82, 0x51, 0x30, 0x0a, 0x46, 0xc1, 0x90, 0x07, 0x4a, 0x3a,
+    0x4a, 0xc9, 0xf9, 0xa5, 0x79, 0x25, 0x4a, 0x56, 0x86, 0xb5, 0x00, 0x44,
+    0xd1, 0x8f, 0x11, 0xe5, 0x07, 0x00, 0x00,
+];
+
+const THING_JSON: &[u8] = br#"{"name":"dropshot","count":3}"#;
+
+/// Compresses `data` with the named coding.
+fn compress(coding: &str, data: &[u8]) -> Vec<u8> {
+    match coding {
+        "gzip" => {
+            let mut encoder = flate2::write::GzEncoder::new(
+                Vec::new(),
+                flate2::Compression::default(),
+            );
+            encoder.write_all(data).unwrap();
+            encoder.finish().unwrap()
+        }
+        "deflate" => {
+            let mut encoder = flate2::write::ZlibEncoder::new(
+                Vec::new(),
+                flate2::Compression::default(),
+            );
+            encoder.write_all(data).unwrap();
+            encoder.finish().unwrap()
+        }
+        "br" => {
+            let mut encoder =
+                brotli::CompressorWriter::new(Vec::new(), 4096, 5, 22);
+            encoder.write_all(data).unwrap();
+            encoder.into_inner()
+        }
+        _ => panic!("unexpected coding: {}", coding),
+    }
+}
+
+/// Decoder for a made-up coding that reverses the body
+#[derive(Debug)]
+struct ReverseDecoder;
+
+impl ContentDecoder for ReverseDecoder {
+    fn decode(
+        &self,
+        encoded: &[u8],
+        _max_bytes: usize,
+    ) -> Result<Vec<u8>, HttpError> {
+        Ok(encoded.iter().rev().copied().collect())
+    }
+}
+
+fn setup(
+    test_name: &str,
+    decompressed_max_bytes: Option<usize>,
+) -> TestContext<usize> {
+    let mut api = ApiDescription::new()
+        .content_decoder("x-reverse", Arc::new(ReverseDecoder));
+    api.register(thing_put).unwrap();
+    let config = ConfigDropshot {
+        request_body_decompressed_max_bytes: decompressed_max_bytes,
+        ..Default::default()
+    };
+    common::test_setup_with_config(test_name, api, &config)
+}
+
+fn request(
+    testctx: &TestContext<usize>,
+    encoding: &str,
+    body: Vec<u8>,
+) -> Request<Body> {
+    Request::builder()
+        .method(Method::PUT)
+        .uri(testctx.client_testctx.url("/thing"))
+        .header(http::header::CONTENT_TYPE, "application/json")
+        .header(http::header::CONTENT_ENCODING, encoding)
+        .body(Body::from(body))
+        .unwrap()
+}
+
+async fn put_thing(
+    testctx: &TestContext<usize>,
+    encoding: &str,
+    body: Vec<u8>,
+) -> Thing {
+    let mut response = testctx
+        .client_testctx
+        .make_request_with_request(
+            request(testctx, encoding, body),
+            StatusCode::OK,
+        )
+        .await
+        .unwrap();
+    read_json(&mut response).await
+}
+
+async fn put_thing_error(
+    testctx: &TestContext<usize>,
+    encoding: &str,
+    body: Vec<u8>,
+    status: StatusCode,
+) -> String {
+    testctx
+        .client_testctx
+        .make_request_with_request(request(testctx, encoding, body), status)
+        .await
+        .unwrap_err()
+        .message
+}
+
+#[tokio::test]
+async fn test_compressed_bodies() {
+    let testctx = setup("compressed_bodies", None);
+    let expected = Thing { name: String::from("dropshot"), count: 3 };
+
+    assert_eq!(
+        put_thing(&testctx, "gzip", THING_GZIP.to_vec()).await,
+        expected
+    );
+    assert_eq!(
+        put_thing(&testctx, "deflate", THING_DEFLATE.to_vec()).await,
+        expected
+    );
+    for coding in ["gzip", "x-gzip", "deflate", "br"] {
+        let coding_name = if coding == "x-gzip" { "gzip" } else { coding };
+        assert_eq!(
+            put_thing(&testctx, coding, compress(coding_name, THING_JSON))
+                .await,
+            expected
+        );
+    }
+    assert_eq!(
+        put_thing(&testctx, "identity", THING_JSON.to_vec()).await,
+        expected
+    );
+
+    // Some clients send a raw DEFLATE stream rather than a zlib stream.
+    let mut raw = flate2::write::DeflateEncoder::new(
+        Vec::new(),
+        flate2::Compression::default(),
+    );
+    raw.write_all(THING_JSON).unwrap();
+    assert_eq!(
+        put_thing(&testctx, "deflate", raw.finish().unwrap()).await,
+        expected
+    );
+
+    // A gzip file may consist of several members.
+    let mut members = compress("gzip", &THING_JSON[..10]);
+    members.extend(compress("gzip", &THING_JSON[10..]));
+    assert_eq!(put_thing(&testctx, "gzip", members).await, expected);
+
+    // Codings are undone in the reverse of the order in which they were
+    // applied.
+    let mut reversed = THING_GZIP.to_vec();
+    reversed.reverse();
+    assert_eq!(
+        put_thing(&testctx, "gzip, x-reverse", reversed).await,
+        expected
+    );
+
+    assert_eq!(
+        put_thing_error(
+            &testctx,
+            "zstd",
+            THING_GZIP.to_vec(),
+            StatusCode::UNSUPPORTED_MEDIA_TYPE
+        )
+        .await,
+        "unsupported content encoding \"zstd\""
+    );
+    let message = put_thing_error(
+        &testctx,
+        "gzip",
+        THING_DEFLATE.to_vec(),
+        StatusCode::BAD_REQUEST,
+    )
+    .await;
+    assert!(
+        message.starts_with("unable to decompress request body: "),
+        "{}",
+        message
+    );
+
+    testctx.teardown().await;
+}
+
+#[tokio::test]
+async fn test_bad_compressed_bodies() {
+    let testctx = setup("bad_compressed_bodies", None);
+
+    for coding in ["gzip", "deflate", "br"] {
+        let encoded = compress(coding, THING_JSON);
+        let truncated = encoded[..encoded.len() / 2].to_vec();
+        let corrupt = match coding {
+            // The checksums at the end of gzip and zlib streams catch
+            // corruption of the data.
+            "gzip" | "deflate" => {
+                let mut corrupt = encoded.clone();
+                let last = corrupt.len() - 5;
+                corrupt[last] ^= 0xff;
+                corrupt
+            }
+            // Brotli streams have no checksum, but do have an end.
+            _ => [encoded.as_slice(), b"garbage"].concat(),
+        };
+        for body in [truncated, corrupt, b"garbage".to_vec()] {
+            let message = put_thing_error(
+                &testctx,
+                coding,
+                body,
+                StatusCode::BAD_REQUEST,
+            )
+            .await;
+            assert!(
+                message.starts_with("unable to decompress request body: "),
+                "{}: {}",
+                coding,
+                message
+            );
+        }
+    }
+
+    testctx.teardown().await;
+}
+
+#[tokio::test]
+async fn test_decompressed_limit() {
+    // By default, the decompressed body is held to the same limit as the
+    // compressed one.
+    let testctx = setup("decompressed_limit_default", None);
+    assert_eq!(
+        put_thing_error(
+            &testctx,
+            "gzip",
+            BIG_THING_GZIP.to_vec(),
+            StatusCode::BAD_REQUEST
+        )
+        .await,
+        "decompressed request body exceeded maximum size of 1024 bytes"
+    );
+    testctx.teardown().await;
+
+    let testctx = setup("decompressed_limit_configured", Some(4096));
+    let thing = put_thing(&testctx, "gzip", BIG_THING_GZIP.to_vec()).await;
+    assert_eq!(thing.name.len(), 2000);
+    testctx.teardown().await;
+}
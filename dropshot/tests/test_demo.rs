@@ -24,6 +24,7 @@ use dropshot::test_util::read_string;
 use dropshot::test_util::TEST_HEADER_1;
 use dropshot::test_util::TEST_HEADER_2;
 use dropshot::ApiDescription;
+use dropshot::Body;
 use dropshot::HttpError;
 use dropshot::HttpResponseDeleted;
 use dropshot::HttpResponseFound;
@@ -44,7 +45,6 @@ use dropshot::CONTENT_TYPE_JSON;
 use futures::stream::StreamExt;
 use futures::SinkExt;
 use http::StatusCode;
-use hyper::Body;
 use hyper::Method;
 use hyper::Response;
 use schemars::JsonSchema;
@@ -1045,7 +1045,7 @@ async fn demo_handler_raw_request(
     let (parts, body) = request.into_parts();
     // This is not generally a good pattern because it allows untrusted
     // consumers to use up all memory.  This is just a narrow test.
-    let whole_body = hyper::body::to_bytes(body).await.unwrap();
+    let whole_body = body.to_bytes().await.unwrap();
     Ok(HttpResponseOk(DemoRaw {
         nbytes: whole_body.len(),
         method: parts.method.to_string(),
@@ -0,0 +1,175 @@
+// Copyright 2023 Oxide Computer Company
+
+//! Test cases for handling clients that disconnect before the response is
+//! sent.
+
+use dropshot::endpoint;
+use dropshot::test_util::TestContext;
+use dropshot::ApiDescription;
+use dropshot::ConfigDropshot;
+use dropshot::HandlerTaskMode;
+use dropshot::HttpError;
+use dropshot::HttpResponseOk;
+use dropshot::RequestContext;
+use http::Method;
+use http::StatusCode;
+use std::net::SocketAddr;
+use std::sync::atomic::AtomicBool;
+use std::sync::atomic::Ordering;
+use std::time::Duration;
+use tokio::io::AsyncWriteExt;
+
+pub mod common;
+
+static NOTICED_DISCONNECT: AtomicBool = AtomicBool::new(false);
+static HANDLER_DROPPED: AtomicBool = AtomicBool::new(false);
+static HANDLER_FINISHED: AtomicBool = AtomicBool::new(false);
+static FAILURE_DISCONNECT: AtomicBool = AtomicBool::new(false);
+
+#[endpoint {
+    method = GET,
+    path = "/watchful",
+}]
+async fn watchful(
+    rqctx: RequestContext<usize>,
+) -> Result<HttpResponseOk<()>, HttpError> {
+    tokio::select! {
+        _ = rqctx.cancelled() => {
+            NOTICED_DISCONNECT.store(rqctx.is_disconnected(), Ordering::SeqCst);
+        }
+        _ = tokio::time::sleep(Duration::from_secs(30)) => {}
+    }
+    Ok(HttpResponseOk(()))
+}
+
+#[endpoint {
+    method = GET,
+    path = "/fast",
+}]
+async fn fast(
+    rqctx: RequestContext<usize>,
+) -> Result<HttpResponseOk<bool>, HttpError> {
+    Ok(HttpResponseOk(rqctx.is_disconnected()))
+}
+
+#[endpoint {
+    method = GET,
+    path = "/failing",
+}]
+async fn failing(
+    rqctx: RequestContext<usize>,
+) -> Result<HttpResponseOk<()>, HttpError> {
+    // Keep watching the request after the handler returns its error, which
+    // the client is still connected to receive.
+    tokio::spawn(async move {
+        tokio::select! {
+            _ = rqctx.cancelled() => {
+                FAILURE_DISCONNECT.store(true, Ordering::SeqCst);
+            }
+            _ = tokio::time::sleep(Duration::from_secs(2)) => {}
+        }
+    });
+    Err(HttpError::for_bad_request(None, String::from("nope")))
+}
+
+struct DropFlag;
+
+impl Drop for DropFlag {
+    fn drop(&mut self) {
+        HANDLER_DROPPED.store(true, Ordering::SeqCst);
+    }
+}
+
+#[endpoint {
+    method = GET,
+    path = "/oblivious",
+}]
+async fn oblivious(
+    _rqctx: RequestContext<usize>,
+) -> Result<HttpResponseOk<()>, HttpError> {
+    let _flag = DropFlag;
+    tokio::time::sleep(Duration::from_secs(1)).await;
+    HANDLER_FINISHED.store(true, Ordering::SeqCst);
+    Ok(HttpResponseOk(()))
+}
+
+fn setup(
+    test_name: &str,
+    handler_task_mode: HandlerTaskMode,
+) -> TestContext<usize> {
+    let mut api = ApiDescription::new();
+    api.register(watchful).unwrap();
+    api.register(fast).unwrap();
+    api.register(oblivious).unwrap();
+    api.register(failing).unwrap();
+    let config = ConfigDropshot { handler_task_mode, ..Default::default() };
+    common::test_setup_with_config(test_name, api, &config)
+}
+
+/// Sends a request for `path` and then disconnects without waiting for the
+/// response.
+async fn request_and_disconnect(addr: SocketAddr, path: &str) {
+    let mut stream = tokio::net::TcpStream::connect(addr).await.unwrap();
+    let request = format!("GET {} HTTP/1.1\r\nHost: {}\r\n\r\n", path, addr);
+    stream.write_all(request.as_bytes()).await.unwrap();
+    tokio::time::sleep(Duration::from_millis(200)).await;
+    drop(stream);
+}
+
+async fn wait_for(flag: &AtomicBool) -> bool {
+    for _ in 0..50 {
+        if flag.load(Ordering::SeqCst) {
+            return true;
+        }
+        tokio::time::sleep(Duration::from_millis(100)).await;
+    }
+    false
+}
+
+#[tokio::test]
+async fn test_disconnect_detected() {
+    let testctx = setup("disconnect_detected", HandlerTaskMode::Detached);
+    let client = &testctx.client_testctx;
+
+    let mut response = client
+        .make_request_no_body(Method::GET, "/fast", StatusCode::OK)
+        .await
+        .unwrap();
+    let disconnected: bool =
+        dropshot::test_util::read_json(&mut response).await;
+    assert!(!disconnected);
+
+    request_and_disconnect(client.bind_address, "/watchful").await;
+    assert!(wait_for(&NOTICED_DISCONNECT).await);
+
+    testctx.teardown().await;
+}
+
+#[tokio::test]
+async fn test_cancel_on_disconnect() {
+    let testctx =
+        setup("cancel_on_disconnect", HandlerTaskMode::CancelOnDisconnect);
+    let client = &testctx.client_testctx;
+
+    request_and_disconnect(client.bind_address, "/oblivious").await;
+    assert!(wait_for(&HANDLER_DROPPED).await);
+    tokio::time::sleep(Duration::from_secs(1)).await;
+    assert!(!HANDLER_FINISHED.load(Ordering::SeqCst));
+
+    testctx.teardown().await;
+}
+
+#[tokio::test]
+async fn test_disconnect_not_on_error() {
+    let testctx = setup("disconnect_not_on_error", HandlerTaskMode::Detached);
+    let client = &testctx.client_testctx;
+
+    let error = client
+        .make_request_error(Method::GET, "/failing", StatusCode::BAD_REQUEST)
+        .await;
+    assert_eq!(error.message, "nope");
+    tokio::time::sleep(Duration::from_secs(1)).await;
+    assert!(!FAILURE_DISCONNECT.load(Ordering::SeqCst));
+
+    testctx.teardown().await;
+}
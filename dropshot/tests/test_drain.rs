@@ -0,0 +1,134 @@
+// Copyright 2023 Oxide Computer Company
+
+//! Test cases for draining requests when a server shuts down.
+
+use dropshot::endpoint;
+use dropshot::test_util::ClientTestContext;
+use dropshot::ApiDescription;
+use dropshot::Body;
+use dropshot::ConfigDropshot;
+use dropshot::HttpError;
+use dropshot::HttpResponseOk;
+use dropshot::HttpServerStarter;
+use dropshot::RequestContext;
+use dropshot::ShutdownReport;
+use http::Method;
+use http::StatusCode;
+use hyper::Request;
+use slog::o;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::Notify;
+
+pub mod common;
+
+#[derive(Default)]
+struct DrainContext {
+    /// notified when a handler has started
+    started: Notify,
+    /// notified to let the slow handler finish
+    release: Notify,
+}
+
+#[endpoint {
+    method = GET,
+    path = "/slow",
+}]
+async fn slow_get(
+    rqctx: RequestContext<Arc<DrainContext>>,
+) -> Result<HttpResponseOk<u32>, HttpError> {
+    let context = rqctx.context();
+    context.started.notify_one();
+    context.release.notified().await;
+    Ok(HttpResponseOk(1))
+}
+
+#[endpoint {
+    method = GET,
+    path = "/hang",
+}]
+async fn hang_get(
+    rqctx: RequestContext<Arc<DrainContext>>,
+) -> Result<HttpResponseOk<u32>, HttpError> {
+    rqctx.context().started.notify_one();
+    tokio::time::sleep(Duration::from_secs(3600)).await;
+    Ok(HttpResponseOk(2))
+}
+
+fn get(client: &ClientTestContext, path: &str) -> Request<Body> {
+    Request::builder()
+        .method(Method::GET)
+        .uri(client.url(path))
+        .body(Body::empty())
+        .unwrap()
+}
+
+fn api() -> ApiDescription<Arc<DrainContext>> {
+    let mut api = ApiDescription::new();
+    api.register(slow_get).unwrap();
+    api.register(hang_get).unwrap();
+    api
+}
+
+#[tokio::test]
+async fn test_drain() {
+    let logctx = common::create_log_context("drain");
+    let log = logctx.log.new(o!());
+    let context = Arc::new(DrainContext::default());
+    let config = ConfigDropshot::default();
+    let server =
+        HttpServerStarter::new(&config, api(), Arc::clone(&context), &log)
+            .unwrap()
+            .start();
+    let client = ClientTestContext::new(server.local_addr(), log.new(o!()));
+
+    // A request that's in progress when the server begins shutting down
+    // completes, and its response asks the client to close the connection.
+    let (response, report) =
+        tokio::join!(client.client.request(get(&client, "/slow")), async {
+            context.started.notified().await;
+            let close = server.close_with_report();
+            let ((), report) = tokio::join!(
+                async {
+                    tokio::time::sleep(Duration::from_millis(100)).await;
+                    context.release.notify_one();
+                },
+                close
+            );
+            report
+        });
+    let response = response.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(
+        response.headers().get(http::header::CONNECTION).unwrap(),
+        "close"
+    );
+    assert_eq!(report.unwrap(), ShutdownReport { aborted_requests: 0 });
+
+    logctx.cleanup_successful();
+}
+
+#[tokio::test]
+async fn test_drain_timeout() {
+    let logctx = common::create_log_context("drain_timeout");
+    let log = logctx.log.new(o!());
+    let context = Arc::new(DrainContext::default());
+    let config =
+        ConfigDropshot { drain_timeout_ms: Some(100), ..Default::default() };
+    let server =
+        HttpServerStarter::new(&config, api(), Arc::clone(&context), &log)
+            .unwrap()
+            .start();
+    let client = ClientTestContext::new(server.local_addr(), log.new(o!()));
+
+    // A request that's still in progress at the deadline is aborted.
+    let (result, report) =
+        tokio::join!(client.client.request(get(&client, "/hang")), async {
+            context.started.notified().await;
+            server.close_with_report().await
+        });
+    assert!(result.is_err());
+    assert_eq!(report.unwrap(), ShutdownReport { aborted_requests: 1 });
+
+    logctx.cleanup_successful();
+}
@@ -0,0 +1,69 @@
+// Copyright 2023 Oxide Computer Company
+
+//! Test cases for `103 Early Hints` responses.
+
+use dropshot::endpoint;
+use dropshot::test_util::TestContext;
+use dropshot::ApiDescription;
+use dropshot::HttpError;
+use dropshot::HttpResponseOk;
+use dropshot::RequestContext;
+use http::header;
+use http::HeaderMap;
+use std::time::Duration;
+
+pub mod common;
+
+#[endpoint {
+    method = GET,
+    path = "/page",
+}]
+async fn page(
+    rqctx: RequestContext<usize>,
+) -> Result<HttpResponseOk<bool>, HttpError> {
+    let mut hints = HeaderMap::new();
+    hints.insert(
+        header::LINK,
+        "</style.css>; rel=preload; as=style".parse().unwrap(),
+    );
+    let sent = rqctx.send_early_hints(&hints);
+    // Give the hints a chance to be written before the response is ready.
+    tokio::time::sleep(Duration::from_millis(100)).await;
+    Ok(HttpResponseOk(sent))
+}
+
+fn test_setup(test_name: &str) -> TestContext<usize> {
+    let mut api = ApiDescription::new();
+    api.register(page).unwrap();
+    common::test_setup(test_name, api)
+}
+
+#[tokio::test]
+async fn test_early_hints() {
+    let testctx = test_setup("early_hints");
+    let addr = testctx.server.local_addr();
+
+    // The hints precede the final response, on each of the requests on a
+    // connection.
+    let response = common::exchange(
+        addr,
+        b"GET /page HTTP/1.1\r\nHost: a\r\n\r\n\
+        GET /page HTTP/1.1\r\nHost: a\r\nConnection: close\r\n\r\n",
+    )
+    .await;
+    let hints = "HTTP/1.1 103 Early Hints\r\n\
+        link: </style.css>; rel=preload; as=style\r\n\r\n\
+        HTTP/1.1 200 OK\r\n";
+    assert!(response.starts_with(hints), "{}", response);
+    assert_eq!(response.matches(hints).count(), 2, "{}", response);
+    assert!(response.ends_with("true"), "{}", response);
+
+    // HTTP/1.0 clients don't get hints.
+    let response =
+        common::exchange(addr, b"GET /page HTTP/1.0\r\nHost: a\r\n\r\n").await;
+    assert!(response.starts_with("HTTP/1.0 200 OK\r\n"), "{}", response);
+    assert!(!response.contains("103"), "{}", response);
+    assert!(response.ends_with("false"), "{}", response);
+
+    testctx.teardown().await;
+}
@@ -0,0 +1,106 @@
+// Copyright 2023 Oxide Computer Company
+
+//! Test cases for changing an endpoint's log level while the server runs.
+
+use dropshot::endpoint;
+use dropshot::test_util::TestContext;
+use dropshot::ApiDescription;
+use dropshot::ConfigLoggingLevel;
+use dropshot::HttpError;
+use dropshot::HttpResponseOk;
+use dropshot::RequestContext;
+use dropshot::RequestLevelFilter;
+use http::Method;
+use http::StatusCode;
+use slog::debug;
+use std::sync::Arc;
+use std::sync::Mutex;
+
+/// Log drain that records the level and message of each entry
+#[derive(Clone, Default)]
+struct RecordingDrain(Arc<Mutex<Vec<(slog::Level, String)>>>);
+
+impl RecordingDrain {
+    fn count(&self, level: slog::Level, msg: &str) -> usize {
+        self.0
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|(l, m)| *l == level && m == msg)
+            .count()
+    }
+}
+
+impl slog::Drain for RecordingDrain {
+    type Ok = ();
+    type Err = slog::Never;
+
+    fn log(
+        &self,
+        record: &slog::Record,
+        _values: &slog::OwnedKVList,
+    ) -> Result<(), slog::Never> {
+        self.0.lock().unwrap().push((record.level(), record.msg().to_string()));
+        Ok(())
+    }
+}
+
+#[endpoint {
+    method = GET,
+    path = "/chatty",
+}]
+async fn chatty_get(
+    rqctx: RequestContext<usize>,
+) -> Result<HttpResponseOk<()>, HttpError> {
+    debug!(rqctx.log, "handler detail");
+    Ok(HttpResponseOk(()))
+}
+
+#[tokio::test]
+async fn test_endpoint_log_level_at_runtime() {
+    let mut api = ApiDescription::new();
+    api.register(chatty_get).unwrap();
+    // As with loggers created by `ConfigLogging::to_logger()`, the server's
+    // logger discards debug entries unless an endpoint says otherwise.
+    let drain = RecordingDrain::default();
+    let log = slog::Logger::root(
+        slog::Drain::fuse(RequestLevelFilter::new(
+            drain.clone(),
+            slog::Level::Info,
+        )),
+        slog::o!(),
+    );
+    let testctx =
+        TestContext::new(api, 0_usize, &Default::default(), None, log);
+    let client = &testctx.client_testctx;
+    let server = &testctx.server;
+
+    client
+        .make_request_no_body(Method::GET, "/chatty", StatusCode::OK)
+        .await
+        .unwrap();
+    assert_eq!(drain.count(slog::Level::Debug, "handler detail"), 0);
+
+    server
+        .set_endpoint_log_level(
+            &Method::GET,
+            "/chatty",
+            Some(ConfigLoggingLevel::Debug),
+        )
+        .unwrap();
+    client
+        .make_request_no_body(Method::GET, "/chatty", StatusCode::OK)
+        .await
+        .unwrap();
+    assert_eq!(drain.count(slog::Level::Debug, "handler detail"), 1);
+
+    // Removing the override restores the server's level.
+    server.set_endpoint_log_level(&Method::GET, "/chatty", None).unwrap();
+    client
+        .make_request_no_body(Method::GET, "/chatty", StatusCode::OK)
+        .await
+        .unwrap();
+    assert_eq!(drain.count(slog::Level::Debug, "handler detail"), 1);
+
+    testctx.teardown().await;
+}
@@ -0,0 +1,362 @@
+// Copyright 2023 Oxide Computer Company
+
+//! Test cases for customizing the error responses that Dropshot generates and
+//! for the format of error responses.
+
+use dropshot::endpoint;
+use dropshot::test_util::object_get;
+use dropshot::test_util::read_string;
+use dropshot::test_util::ClientTestContext;
+use dropshot::test_util::TestContext;
+use dropshot::ApiDescription;
+use dropshot::Body;
+use dropshot::ConfigDropshot;
+use dropshot::CustomErrorBody;
+use dropshot::ErrorFormat;
+use dropshot::ErrorMapper;
+use dropshot::ErrorResponseCustomizer;
+use dropshot::GeneratedErrorKind;
+use dropshot::HttpError;
+use dropshot::HttpResponseOk;
+use dropshot::HttpServerStarter;
+use dropshot::ProblemDetails;
+use dropshot::Query;
+use dropshot::RequestContext;
+use dropshot::HEADER_REQUEST_ID;
+use http::Method;
+use http::StatusCode;
+use hyper::Request;
+use hyper::Response;
+use schemars::JsonSchema;
+use serde::Deserialize;
+use slog::o;
+use std::sync::Arc;
+
+pub mod common;
+
+#[derive(Deserialize, JsonSchema)]
+struct CountQuery {
+    count: u32,
+}
+
+#[endpoint {
+    method = GET,
+    path = "/count",
+}]
+async fn count_get(
+    _rqctx: RequestContext<usize>,
+    query: Query<CountQuery>,
+) -> Result<HttpResponseOk<u32>, HttpError> {
+    let count = query.into_inner().count;
+    if count == 0 {
+        return Err(HttpError::for_bad_request(
+            None,
+            String::from("count must be positive"),
+        ));
+    }
+    Ok(HttpResponseOk(count))
+}
+
+#[endpoint {
+    method = GET,
+    path = "/fail",
+}]
+async fn fail_get(
+    _rqctx: RequestContext<usize>,
+) -> Result<HttpResponseOk<u32>, HttpError> {
+    Err(HttpError {
+        status_code: StatusCode::SERVICE_UNAVAILABLE,
+        error_code: Some(String::from("DatabaseDown")),
+        external_message: String::from("database at 10.1.2.3 is unreachable"),
+        internal_message: String::from("connection refused"),
+    })
+}
+
+/// Replaces generated errors with a plain-text body
+#[derive(Debug)]
+struct TextErrors;
+
+impl ErrorResponseCustomizer for TextErrors {
+    fn customize(
+        &self,
+        kind: GeneratedErrorKind,
+        error: &HttpError,
+        request_id: &str,
+    ) -> Option<CustomErrorBody> {
+        let label = match kind {
+            GeneratedErrorKind::NotFound => "not-found",
+            GeneratedErrorKind::MethodNotAllowed => "method-not-allowed",
+            GeneratedErrorKind::ExtractorFailure => "bad-arguments",
+        };
+        Some(CustomErrorBody::new(
+            "text/plain",
+            format!("{} {} {}", label, error.status_code.as_u16(), request_id),
+        ))
+    }
+}
+
+fn api() -> ApiDescription<usize> {
+    let mut api =
+        ApiDescription::new().error_response_customizer(Arc::new(TextErrors));
+    api.register(count_get).unwrap();
+    api
+}
+
+async fn request(
+    client: &dropshot::test_util::ClientTestContext,
+    method: Method,
+    path: &str,
+) -> Response<Body> {
+    let request = Request::builder()
+        .method(method)
+        .uri(client.url(path))
+        .body(Body::empty())
+        .unwrap();
+    client.client.request(request).await.unwrap().map(Body::wrap)
+}
+
+async fn check_custom(mut response: Response<Body>, expected_status: u16) {
+    assert_eq!(response.status().as_u16(), expected_status);
+    assert_eq!(
+        response.headers().get(http::header::CONTENT_TYPE).unwrap(),
+        "text/plain"
+    );
+    let request_id = response
+        .headers()
+        .get(HEADER_REQUEST_ID)
+        .unwrap()
+        .to_str()
+        .unwrap()
+        .to_string();
+    let body = read_string(&mut response).await;
+    let label = match expected_status {
+        404 => "not-found",
+        405 => "method-not-allowed",
+        _ => "bad-arguments",
+    };
+    assert_eq!(body, format!("{} {} {}", label, expected_status, request_id));
+}
+
+#[tokio::test]
+async fn test_custom_generated_errors() {
+    let testctx = common::test_setup("custom_generated_errors", api());
+    let client = &testctx.client_testctx;
+
+    check_custom(request(client, Method::GET, "/nothing").await, 404).await;
+    check_custom(request(client, Method::DELETE, "/count").await, 405).await;
+    check_custom(request(client, Method::GET, "/count?count=many").await, 400)
+        .await;
+
+    // Errors returned by the handler itself keep the default body.
+    let error = client
+        .make_request_error(
+            Method::GET,
+            "/count?count=0",
+            StatusCode::BAD_REQUEST,
+        )
+        .await;
+    assert_eq!(error.message, "count must be positive");
+
+    testctx.teardown().await;
+}
+
+#[tokio::test]
+async fn test_default_generated_errors() {
+    let mut api = ApiDescription::new();
+    api.register(count_get).unwrap();
+    let testctx = common::test_setup("default_generated_errors", api);
+    let client = &testctx.client_testctx;
+
+    let error = client
+        .make_request_error(Method::GET, "/nothing", StatusCode::NOT_FOUND)
+        .await;
+    assert_eq!(error.message, "Not Found");
+
+    testctx.teardown().await;
+}
+
+fn problem_api() -> ApiDescription<usize> {
+    let mut api = ApiDescription::new().error_format(ErrorFormat::ProblemJson);
+    api.register(count_get).unwrap();
+    api
+}
+
+async fn check_problem(
+    mut response: Response<Body>,
+    expected_status: u16,
+) -> ProblemDetails {
+    assert_eq!(response.status().as_u16(), expected_status);
+    assert_eq!(
+        response.headers().get(http::header::CONTENT_TYPE).unwrap(),
+        "application/problem+json"
+    );
+    let request_id = response
+        .headers()
+        .get(HEADER_REQUEST_ID)
+        .unwrap()
+        .to_str()
+        .unwrap()
+        .to_string();
+    let body = read_string(&mut response).await;
+    let problem: ProblemDetails = serde_json::from_str(&body).unwrap();
+    assert_eq!(problem.problem_type, "about:blank");
+    assert_eq!(problem.status, expected_status);
+    assert_eq!(problem.request_id, request_id);
+    assert!(problem.extensions.is_empty());
+    problem
+}
+
+#[tokio::test]
+async fn test_problem_json_errors() {
+    let testctx = common::test_setup("problem_json_errors", problem_api());
+    let client = &testctx.client_testctx;
+
+    // Errors returned by the handler
+    let problem = check_problem(
+        request(client, Method::GET, "/count?count=0").await,
+        400,
+    )
+    .await;
+    assert_eq!(problem.title, "Bad Request");
+    assert_eq!(problem.detail, "count must be positive");
+    assert_eq!(problem.instance, "/count");
+    assert_eq!(problem.error_code, None);
+
+    // Errors generated by Dropshot
+    let problem =
+        check_problem(request(client, Method::GET, "/nothing").await, 404)
+            .await;
+    assert_eq!(problem.title, "Not Found");
+    assert_eq!(problem.detail, "Not Found");
+    assert_eq!(problem.instance, "/nothing");
+
+    // Successful responses are unaffected.
+    let count: u32 = object_get(client, "/count?count=3").await;
+    assert_eq!(count, 3);
+
+    testctx.teardown().await;
+}
+
+#[test]
+fn test_problem_json_openapi() {
+    let spec = problem_api().openapi("test", "1.0").json().unwrap();
+    let error = &spec["components"]["responses"]["Error"]["content"];
+    assert!(error.get("application/json").is_none());
+    assert_eq!(
+        error["application/problem+json"]["schema"]["$ref"],
+        "#/components/schemas/ProblemDetails"
+    );
+    let schema = &spec["components"]["schemas"]["ProblemDetails"];
+    for field in ["type", "title", "status", "detail", "instance"] {
+        assert!(schema["properties"].get(field).is_some(), "{}", field);
+    }
+}
+
+/// Adds a support URL (and, for routed requests, the request's path) to the
+/// messages of all errors
+#[derive(Debug)]
+struct SupportUrl;
+
+impl ErrorMapper<usize> for SupportUrl {
+    fn map_error(
+        &self,
+        mut error: HttpError,
+        rqctx: Option<&RequestContext<usize>>,
+    ) -> HttpError {
+        let path = rqctx.map_or("-", |rqctx| rqctx.request.uri().path());
+        error.external_message = format!(
+            "{} ({}; see https://example.com/support)",
+            error.external_message, path
+        );
+        error
+    }
+}
+
+#[tokio::test]
+async fn test_error_mapper() {
+    let logctx = common::create_log_context("error_mapper");
+    let log = logctx.log.new(o!());
+    let mut api = ApiDescription::new();
+    api.register(count_get).unwrap();
+    let server =
+        HttpServerStarter::new(&ConfigDropshot::default(), api, 0_usize, &log)
+            .unwrap()
+            .error_mapper(Arc::new(SupportUrl))
+            .start();
+    let client = ClientTestContext::new(server.local_addr(), log.new(o!()));
+
+    // Errors returned by the handler
+    let error = client
+        .make_request_error(
+            Method::GET,
+            "/count?count=0",
+            StatusCode::BAD_REQUEST,
+        )
+        .await;
+    assert_eq!(
+        error.message,
+        "count must be positive (/count; see https://example.com/support)"
+    );
+
+    // Errors generated by Dropshot, before and after routing
+    let error = client
+        .make_request_error(Method::GET, "/nothing", StatusCode::NOT_FOUND)
+        .await;
+    assert_eq!(error.message, "Not Found (-; see https://example.com/support)");
+    let error = client
+        .make_request_error(
+            Method::GET,
+            "/count?count=-1",
+            StatusCode::BAD_REQUEST,
+        )
+        .await;
+    assert!(error
+        .message
+        .ends_with("(/count; see https://example.com/support)"));
+
+    // Successful responses are unaffected.
+    let count: u32 = object_get(&client, "/count?count=3").await;
+    assert_eq!(count, 3);
+
+    server.close().await.unwrap();
+    logctx.cleanup_successful();
+}
+
+#[tokio::test]
+async fn test_redact_server_errors() {
+    let config =
+        ConfigDropshot { redact_server_errors: true, ..Default::default() };
+    let logctx = common::create_log_context("redact_server_errors");
+    let log = logctx.log.new(o!());
+    let mut api = ApiDescription::new();
+    api.register(count_get).unwrap();
+    api.register(fail_get).unwrap();
+    let testctx = TestContext::new(api, 0_usize, &config, Some(logctx), log);
+    let client = &testctx.client_testctx;
+
+    // Server errors say only what kind of error it was and which request.
+    let error = client
+        .make_request_error(
+            Method::GET,
+            "/fail",
+            StatusCode::SERVICE_UNAVAILABLE,
+        )
+        .await;
+    assert_eq!(
+        error.message,
+        format!("Service Unavailable (request id: {})", error.request_id)
+    );
+    assert_eq!(error.error_code, None);
+
+    // Client errors are unaffected.
+    let error = client
+        .make_request_error(
+            Method::GET,
+            "/count?count=0",
+            StatusCode::BAD_REQUEST,
+        )
+        .await;
+    assert_eq!(error.message, "count must be positive");
+
+    testctx.teardown().await;
+}
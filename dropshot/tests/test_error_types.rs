@@ -0,0 +1,158 @@
+// Copyright 2023 Oxide Computer Company
+
+//! Test cases for handlers that return their own error types.
+
+use dropshot::endpoint;
+use dropshot::ApiDescription;
+use dropshot::HttpError;
+use dropshot::HttpResponseError;
+use dropshot::HttpResponseOk;
+use dropshot::Path;
+use dropshot::RequestContext;
+use http::Method;
+use http::StatusCode;
+use schemars::JsonSchema;
+use serde::Deserialize;
+
+pub mod common;
+
+enum WidgetError {
+    NotFound(String),
+    Locked,
+}
+
+impl From<WidgetError> for HttpError {
+    fn from(error: WidgetError) -> HttpError {
+        match error {
+            WidgetError::NotFound(name) => HttpError::for_not_found(
+                None,
+                format!("no widget named {:?}", name),
+            ),
+            WidgetError::Locked => HttpError::for_client_error(
+                Some(String::from("Locked")),
+                StatusCode::CONFLICT,
+                String::from("widget is locked"),
+            ),
+        }
+    }
+}
+
+impl HttpResponseError for WidgetError {
+    fn error_responses() -> Vec<(StatusCode, String)> {
+        vec![
+            (StatusCode::NOT_FOUND, String::from("no such widget")),
+            (StatusCode::CONFLICT, String::from("widget is locked")),
+        ]
+    }
+}
+
+/// An application error that is documented by its schema.
+#[derive(JsonSchema)]
+enum GadgetError {
+    Missing { name: String },
+}
+
+impl From<GadgetError> for HttpError {
+    fn from(error: GadgetError) -> HttpError {
+        match error {
+            GadgetError::Missing { name } => HttpError::for_not_found(
+                None,
+                format!("no gadget named {:?}", name),
+            ),
+        }
+    }
+}
+
+#[derive(Deserialize, JsonSchema)]
+struct WidgetPath {
+    name: String,
+}
+
+#[endpoint {
+    method = GET,
+    path = "/widgets/{name}",
+}]
+async fn widget_get(
+    _rqctx: RequestContext<usize>,
+    path: Path<WidgetPath>,
+) -> Result<HttpResponseOk<String>, WidgetError> {
+    match path.into_inner().name.as_str() {
+        "sprocket" => Ok(HttpResponseOk(String::from("sprocket"))),
+        "cog" => Err(WidgetError::Locked),
+        name => Err(WidgetError::NotFound(name.to_string())),
+    }
+}
+
+#[endpoint {
+    method = GET,
+    path = "/gadgets/{name}",
+}]
+async fn gadget_get(
+    _rqctx: RequestContext<usize>,
+    path: Path<WidgetPath>,
+) -> Result<HttpResponseOk<String>, GadgetError> {
+    Err(GadgetError::Missing { name: path.into_inner().name })
+}
+
+fn api() -> ApiDescription<usize> {
+    let mut api = ApiDescription::new();
+    api.register(widget_get).unwrap();
+    api.register(gadget_get).unwrap();
+    api
+}
+
+#[test]
+fn test_error_types_openapi() {
+    let spec = api().openapi("test", "1.0").json().unwrap();
+    let responses = &spec["paths"]["/widgets/{name}"]["get"]["responses"];
+    for (code, description) in
+        [("404", "no such widget"), ("409", "widget is locked")]
+    {
+        assert_eq!(responses[code]["description"], description);
+        assert_eq!(
+            responses[code]["content"]["application/json"]["schema"]["$ref"],
+            "#/components/schemas/Error"
+        );
+    }
+    assert_eq!(responses["4XX"]["$ref"], "#/components/responses/Error");
+    assert_eq!(responses["5XX"]["$ref"], "#/components/responses/Error");
+
+    // An error type with a schema describes the generic error responses.
+    let responses = &spec["paths"]["/gadgets/{name}"]["get"]["responses"];
+    for code in ["4XX", "5XX"] {
+        assert_eq!(
+            responses[code]["content"]["application/json"]["schema"]["$ref"],
+            "#/components/schemas/GadgetError"
+        );
+    }
+    assert!(spec["components"]["schemas"]["GadgetError"].is_object());
+}
+
+#[tokio::test]
+async fn test_error_types() {
+    let testctx = common::test_setup("error_types", api());
+    let client = &testctx.client_testctx;
+
+    client
+        .make_request_no_body(Method::GET, "/widgets/sprocket", StatusCode::OK)
+        .await
+        .unwrap();
+
+    let error = client
+        .make_request_error(Method::GET, "/widgets/cog", StatusCode::CONFLICT)
+        .await;
+    assert_eq!(error.error_code.as_deref(), Some("Locked"));
+    assert_eq!(error.message, "widget is locked");
+
+    let error = client
+        .make_request_error(Method::GET, "/widgets/gear", StatusCode::NOT_FOUND)
+        .await;
+    assert_eq!(error.message, "Not Found");
+
+    let error = client
+        .make_request_error(Method::GET, "/gadgets/gear", StatusCode::NOT_FOUND)
+        .await;
+    assert_eq!(error.message, "Not Found");
+
+    testctx.teardown().await;
+}
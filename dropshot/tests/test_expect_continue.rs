@@ -0,0 +1,156 @@
+// Copyright 2023 Oxide Computer Company
+
+//! Test cases for requests that ask to continue (`Expect: 100-continue`).
+
+use async_trait::async_trait;
+use dropshot::endpoint;
+use dropshot::ApiDescription;
+use dropshot::ContinueCheck;
+use dropshot::HttpError;
+use dropshot::HttpResponseOk;
+use dropshot::RequestContext;
+use dropshot::UntypedBody;
+use http::StatusCode;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use tokio::io::AsyncBufReadExt;
+use tokio::io::AsyncReadExt;
+use tokio::io::AsyncWriteExt;
+use tokio::io::BufReader;
+use tokio::net::TcpStream;
+
+pub mod common;
+
+#[endpoint {
+    method = PUT,
+    path = "/small",
+    request_body_max_bytes = 16,
+}]
+async fn small_put(
+    _rqctx: RequestContext<usize>,
+    body: UntypedBody,
+) -> Result<HttpResponseOk<usize>, HttpError> {
+    Ok(HttpResponseOk(body.as_bytes().len()))
+}
+
+#[endpoint {
+    method = PUT,
+    path = "/large",
+}]
+async fn large_put(
+    _rqctx: RequestContext<usize>,
+    body: UntypedBody,
+) -> Result<HttpResponseOk<usize>, HttpError> {
+    Ok(HttpResponseOk(body.as_bytes().len()))
+}
+
+/// Lets requests continue only if they carry the right credentials
+#[derive(Debug)]
+struct RequireToken;
+
+#[async_trait]
+impl ContinueCheck<usize> for RequireToken {
+    async fn check(
+        &self,
+        rqctx: &RequestContext<usize>,
+    ) -> Result<(), HttpError> {
+        match rqctx.request.headers().get(http::header::AUTHORIZATION) {
+            Some(value) if value == "Bearer letmein" => Ok(()),
+            _ => Err(HttpError::for_client_error(
+                None,
+                StatusCode::UNAUTHORIZED,
+                String::from("credentials required"),
+            )),
+        }
+    }
+}
+
+/// Sends the head of a request that asks to continue and returns the status
+/// line of the server's first response.  If that's `100 Continue`, the body is
+/// sent and the status line of the final response is returned along with it.
+async fn put(
+    addr: SocketAddr,
+    path: &str,
+    body_len: usize,
+    authorization: &str,
+) -> (String, Option<String>) {
+    let stream = TcpStream::connect(addr).await.unwrap();
+    let mut stream = BufReader::new(stream);
+    let head = format!(
+        "PUT {} HTTP/1.1\r\nHost: test\r\nContent-Length: {}\r\n\
+        Authorization: {}\r\nExpect: 100-continue\r\n\r\n",
+        path, body_len, authorization,
+    );
+    stream.get_mut().write_all(head.as_bytes()).await.unwrap();
+
+    let first = read_response(&mut stream).await;
+    if first != "HTTP/1.1 100 Continue" {
+        return (first, None);
+    }
+    stream.get_mut().write_all(&vec![b'x'; body_len]).await.unwrap();
+    let last = read_response(&mut stream).await;
+    (first, Some(last))
+}
+
+/// Reads a response (skipping its body), returning its status line.
+async fn read_response(stream: &mut BufReader<TcpStream>) -> String {
+    let mut status_line = String::new();
+    stream.read_line(&mut status_line).await.unwrap();
+    let mut body_len = 0;
+    loop {
+        let mut line = String::new();
+        stream.read_line(&mut line).await.unwrap();
+        if line == "\r\n" {
+            break;
+        }
+        let line = line.to_ascii_lowercase();
+        if let Some(len) = line.strip_prefix("content-length:") {
+            body_len = len.trim().parse().unwrap();
+        }
+    }
+    stream.read_exact(&mut vec![0; body_len]).await.unwrap();
+    status_line.trim_end().to_string()
+}
+
+#[tokio::test]
+async fn test_expect_continue() {
+    let mut api = ApiDescription::new().continue_check(Arc::new(RequireToken));
+    api.register(small_put).unwrap();
+    api.register(large_put).unwrap();
+    let testctx = common::test_setup("expect_continue", api);
+    let addr = testctx.server.local_addr();
+    let token = "Bearer letmein";
+
+    // Requests within the limit continue and succeed.
+    assert_eq!(
+        put(addr, "/small", 16, token).await,
+        (
+            String::from("HTTP/1.1 100 Continue"),
+            Some(String::from("HTTP/1.1 200 OK"))
+        )
+    );
+    assert_eq!(
+        put(addr, "/large", 1024, token).await.1.as_deref(),
+        Some("HTTP/1.1 200 OK")
+    );
+
+    // Requests whose declared length exceeds the endpoint's limit (or the
+    // server's, for endpoints without their own) are rejected before the
+    // body is sent.
+    assert_eq!(
+        put(addr, "/small", 17, token).await,
+        (String::from("HTTP/1.1 413 Payload Too Large"), None)
+    );
+    assert_eq!(
+        put(addr, "/large", 1025, token).await,
+        (String::from("HTTP/1.1 413 Payload Too Large"), None)
+    );
+
+    // So are requests that fail the server's check.
+    assert_eq!(
+        put(addr, "/small", 16, "Bearer guess").await,
+        (String::from("HTTP/1.1 401 Unauthorized"), None)
+    );
+
+    testctx.teardown().await;
+}
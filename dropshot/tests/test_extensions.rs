@@ -0,0 +1,140 @@
+// Copyright 2023 Oxide Computer Company
+
+//! Test cases for values attached to requests and the `Extension` extractor.
+
+use async_trait::async_trait;
+use dropshot::endpoint;
+use dropshot::test_util::read_json;
+use dropshot::ApiDescription;
+use dropshot::Body;
+use dropshot::ContinueCheck;
+use dropshot::Extension;
+use dropshot::HttpError;
+use dropshot::HttpResponseOk;
+use dropshot::RequestContext;
+use http::Method;
+use http::Request;
+use http::StatusCode;
+use std::sync::Arc;
+
+pub mod common;
+
+/// The authenticated user on whose behalf a request is made
+#[derive(Clone, Debug)]
+struct Principal(String);
+
+/// Attaches the `Principal` named by the request's `Authorization` header
+#[derive(Debug)]
+struct Authenticate;
+
+#[async_trait]
+impl ContinueCheck<usize> for Authenticate {
+    async fn check(
+        &self,
+        rqctx: &RequestContext<usize>,
+    ) -> Result<(), HttpError> {
+        let user = rqctx
+            .request
+            .headers()
+            .get(http::header::AUTHORIZATION)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.strip_prefix("User "));
+        if let Some(user) = user {
+            rqctx.extensions().insert(Principal(user.to_string()));
+        }
+        Ok(())
+    }
+}
+
+#[endpoint {
+    method = GET,
+    path = "/whoami",
+}]
+async fn whoami_get(
+    _rqctx: RequestContext<usize>,
+    principal: Extension<Principal>,
+) -> Result<HttpResponseOk<String>, HttpError> {
+    Ok(HttpResponseOk(principal.into_inner().0))
+}
+
+#[endpoint {
+    method = GET,
+    path = "/whoami-maybe",
+}]
+async fn whoami_maybe_get(
+    rqctx: RequestContext<usize>,
+) -> Result<HttpResponseOk<Option<String>>, HttpError> {
+    let principal = rqctx.extensions().get::<Principal>();
+    Ok(HttpResponseOk(principal.map(|p| p.0)))
+}
+
+fn api() -> ApiDescription<usize> {
+    let mut api = ApiDescription::new().continue_check(Arc::new(Authenticate));
+    api.register(whoami_get).unwrap();
+    api.register(whoami_maybe_get).unwrap();
+    api
+}
+
+fn request(
+    client: &dropshot::test_util::ClientTestContext,
+    path: &str,
+    user: Option<&str>,
+) -> Request<Body> {
+    let mut builder = Request::builder()
+        .method(Method::GET)
+        .uri(client.url(path))
+        .header(http::header::EXPECT, "100-continue");
+    if let Some(user) = user {
+        builder = builder
+            .header(http::header::AUTHORIZATION, format!("User {}", user));
+    }
+    builder.body(Body::empty()).unwrap()
+}
+
+#[tokio::test]
+async fn test_extensions() {
+    let testctx = common::test_setup("extensions", api());
+    let client = &testctx.client_testctx;
+
+    let mut response = client
+        .make_request_with_request(
+            request(client, "/whoami", Some("alice")),
+            StatusCode::OK,
+        )
+        .await
+        .unwrap();
+    let user: String = read_json(&mut response).await;
+    assert_eq!(user, "alice");
+
+    let mut response = client
+        .make_request_with_request(
+            request(client, "/whoami-maybe", Some("bob")),
+            StatusCode::OK,
+        )
+        .await
+        .unwrap();
+    let user: Option<String> = read_json(&mut response).await;
+    assert_eq!(user.as_deref(), Some("bob"));
+
+    let mut response = client
+        .make_request_with_request(
+            request(client, "/whoami-maybe", None),
+            StatusCode::OK,
+        )
+        .await
+        .unwrap();
+    let user: Option<String> = read_json(&mut response).await;
+    assert_eq!(user, None);
+
+    // An extractor for a value that isn't there is a server bug.
+    let error = client
+        .make_request_with_request(
+            request(client, "/whoami", None),
+            StatusCode::INTERNAL_SERVER_ERROR,
+        )
+        .await
+        .unwrap_err();
+    assert_eq!(error.message, "Internal Server Error");
+
+    testctx.teardown().await;
+}
@@ -0,0 +1,88 @@
+// Copyright 2023 Oxide Computer Company
+
+//! Test cases for using extracted values through `Deref` and `AsRef`.
+
+use dropshot::endpoint;
+use dropshot::test_util::read_json;
+use dropshot::ApiDescription;
+use dropshot::HttpError;
+use dropshot::HttpResponseOk;
+use dropshot::Path;
+use dropshot::Query;
+use dropshot::RequestContext;
+use dropshot::TypedBody;
+use http::Method;
+use http::StatusCode;
+use schemars::JsonSchema;
+use serde::Deserialize;
+use serde::Serialize;
+
+pub mod common;
+
+#[derive(Deserialize, JsonSchema)]
+struct Sensor {
+    sensor: String,
+}
+
+#[derive(Deserialize, JsonSchema)]
+struct Scale {
+    scale: i32,
+}
+
+#[derive(Debug, Deserialize, JsonSchema, PartialEq, Serialize)]
+struct Reading {
+    sensor: String,
+    values: Vec<i32>,
+}
+
+fn sensor_name(sensor: impl AsRef<Sensor>) -> String {
+    sensor.as_ref().sensor.clone()
+}
+
+#[endpoint {
+    method = PUT,
+    path = "/readings/{sensor}",
+}]
+async fn reading_put(
+    _rqctx: RequestContext<usize>,
+    path: Path<Sensor>,
+    query: Query<Scale>,
+    mut body: TypedBody<Vec<i32>>,
+) -> Result<HttpResponseOk<Reading>, HttpError> {
+    for value in body.iter_mut() {
+        *value *= query.scale;
+    }
+    Ok(HttpResponseOk(Reading {
+        sensor: sensor_name(path),
+        values: body.into_inner(),
+    }))
+}
+
+fn api() -> ApiDescription<usize> {
+    let mut api = ApiDescription::new();
+    api.register(reading_put).unwrap();
+    api
+}
+
+#[tokio::test]
+async fn test_extractor_deref() {
+    let testctx = common::test_setup("extractor_deref", api());
+    let client = &testctx.client_testctx;
+
+    let mut response = client
+        .make_request(
+            Method::PUT,
+            "/readings/t1?scale=2",
+            Some(vec![-3, 300]),
+            StatusCode::OK,
+        )
+        .await
+        .unwrap();
+    let reading: Reading = read_json(&mut response).await;
+    assert_eq!(
+        reading,
+        Reading { sensor: String::from("t1"), values: vec![-6, 600] }
+    );
+
+    testctx.teardown().await;
+}
@@ -0,0 +1,103 @@
+// Copyright 2023 Oxide Computer Company
+
+//! Test cases for the handler of requests that match no route.
+
+use async_trait::async_trait;
+use dropshot::endpoint;
+use dropshot::test_util::read_string;
+use dropshot::ApiDescription;
+use dropshot::Body;
+use dropshot::FallbackHandler;
+use dropshot::HttpError;
+use dropshot::HttpResponseOk;
+use dropshot::RequestContext;
+use http::Method;
+use http::StatusCode;
+use hyper::Request;
+use hyper::Response;
+use std::sync::Arc;
+
+pub mod common;
+
+#[endpoint {
+    method = GET,
+    path = "/api/widgets",
+}]
+async fn widgets_get(
+    _rqctx: RequestContext<usize>,
+) -> Result<HttpResponseOk<Vec<String>>, HttpError> {
+    Ok(HttpResponseOk(vec![]))
+}
+
+/// Serves an index page for unmatched `GET` requests outside of "/api"
+#[derive(Debug)]
+struct IndexFallback;
+
+#[async_trait]
+impl FallbackHandler<usize> for IndexFallback {
+    async fn handle(
+        &self,
+        _rqctx: RequestContext<usize>,
+        request: Request<Body>,
+    ) -> Result<Response<Body>, HttpError> {
+        let path = request.uri().path();
+        if request.method() != Method::GET || path.starts_with("/api/") {
+            return Err(HttpError::for_not_found(
+                None,
+                format!("nothing at {}", path),
+            ));
+        }
+        Ok(Response::builder()
+            .status(StatusCode::OK)
+            .header(http::header::CONTENT_TYPE, "text/html")
+            .body(Body::from(format!("index for {}", path)))?)
+    }
+}
+
+fn api() -> ApiDescription<usize> {
+    let mut api =
+        ApiDescription::new().fallback_handler(Arc::new(IndexFallback));
+    api.register(widgets_get).unwrap();
+    api
+}
+
+#[tokio::test]
+async fn test_fallback() {
+    let testctx = common::test_setup("fallback", api());
+    let client = &testctx.client_testctx;
+
+    // Requests that match a route are unaffected.
+    let mut response = client
+        .make_request_no_body(Method::GET, "/api/widgets", StatusCode::OK)
+        .await
+        .unwrap();
+    assert_eq!(read_string(&mut response).await, "[]");
+
+    // Requests that match no route go to the fallback handler.
+    let mut response = client
+        .make_request_no_body(Method::GET, "/projects/p1", StatusCode::OK)
+        .await
+        .unwrap();
+    assert_eq!(
+        response.headers().get(http::header::CONTENT_TYPE).unwrap(),
+        "text/html"
+    );
+    assert_eq!(read_string(&mut response).await, "index for /projects/p1");
+
+    let error = client
+        .make_request_error(Method::GET, "/api/gadgets", StatusCode::NOT_FOUND)
+        .await;
+    assert_eq!(error.message, "Not Found");
+
+    // Requests for a route without a handler for their method still fail with
+    // a 405.
+    client
+        .make_request_error(
+            Method::PUT,
+            "/api/widgets",
+            StatusCode::METHOD_NOT_ALLOWED,
+        )
+        .await;
+
+    testctx.teardown().await;
+}
@@ -0,0 +1,126 @@
+// Copyright 2023 Oxide Computer Company
+
+//! Test cases for identifying clients behind trusted proxies.
+
+use dropshot::endpoint;
+use dropshot::test_util::read_json;
+use dropshot::test_util::ClientTestContext;
+use dropshot::test_util::TestContext;
+use dropshot::ApiDescription;
+use dropshot::Body;
+use dropshot::ConfigDropshot;
+use dropshot::HttpError;
+use dropshot::HttpResponseOk;
+use dropshot::HttpServerStarter;
+use dropshot::RequestContext;
+use http::Method;
+use http::StatusCode;
+use hyper::Request;
+use slog::o;
+
+pub mod common;
+
+#[endpoint {
+    method = GET,
+    path = "/whoami",
+}]
+async fn whoami(
+    rqctx: RequestContext<usize>,
+) -> Result<HttpResponseOk<(String, String, bool)>, HttpError> {
+    let client = rqctx.client();
+    Ok(HttpResponseOk((
+        client.addr().to_string(),
+        client.scheme().to_string(),
+        client.is_forwarded(),
+    )))
+}
+
+fn test_setup(test_name: &str, trusted_proxies: &[&str]) -> TestContext<usize> {
+    let mut api = ApiDescription::new();
+    api.register(whoami).unwrap();
+    let config = ConfigDropshot {
+        trusted_proxies: trusted_proxies
+            .iter()
+            .map(|p| p.to_string())
+            .collect(),
+        ..Default::default()
+    };
+    common::test_setup_with_config(test_name, api, &config)
+}
+
+async fn whoami_with(
+    client: &ClientTestContext,
+    headers: &[(&str, &str)],
+) -> (String, String, bool) {
+    let mut request =
+        Request::builder().method(Method::GET).uri(client.url("/whoami"));
+    for (name, value) in headers {
+        request = request.header(*name, *value);
+    }
+    let mut response = client
+        .make_request_with_request(
+            request.body(Body::empty()).unwrap(),
+            StatusCode::OK,
+        )
+        .await
+        .unwrap();
+    read_json(&mut response).await
+}
+
+#[tokio::test]
+async fn test_trusted_proxy() {
+    let testctx = test_setup("trusted_proxy", &["127.0.0.0/8", "::1"]);
+    let client = &testctx.client_testctx;
+
+    let whoami = whoami_with(client, &[]).await;
+    assert_eq!(whoami, ("127.0.0.1".to_string(), "http".to_string(), false));
+
+    let whoami = whoami_with(
+        client,
+        &[
+            ("x-forwarded-for", "198.51.100.7, 127.0.0.2"),
+            ("x-forwarded-proto", "https"),
+        ],
+    )
+    .await;
+    assert_eq!(whoami, ("198.51.100.7".to_string(), "https".to_string(), true));
+
+    let whoami = whoami_with(
+        client,
+        &[("forwarded", "for=\"[2001:db8::1]:443\";proto=https")],
+    )
+    .await;
+    assert_eq!(whoami, ("2001:db8::1".to_string(), "https".to_string(), true));
+
+    testctx.teardown().await;
+}
+
+#[tokio::test]
+async fn test_untrusted_proxy() {
+    let testctx = test_setup("untrusted_proxy", &["10.0.0.0/8"]);
+    let client = &testctx.client_testctx;
+
+    // Anyone else's forwarding headers are ignored.
+    let whoami = whoami_with(
+        client,
+        &[("x-forwarded-for", "198.51.100.7"), ("x-forwarded-proto", "https")],
+    )
+    .await;
+    assert_eq!(whoami, ("127.0.0.1".to_string(), "http".to_string(), false));
+
+    // Invalid addresses are rejected when the server is created.
+    let config = ConfigDropshot {
+        trusted_proxies: vec!["proxy.example.com".to_string()],
+        ..Default::default()
+    };
+    let log = testctx.log.new(o!());
+    let error = HttpServerStarter::new(&config, ApiDescription::new(), 0, &log)
+        .map(|_| ())
+        .unwrap_err();
+    assert_eq!(
+        error.to_string(),
+        "invalid address for trusted_proxies: \"proxy.example.com\""
+    );
+
+    testctx.teardown().await;
+}
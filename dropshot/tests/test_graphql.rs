@@ -0,0 +1,142 @@
+// Copyright 2023 Oxide Computer Company
+
+//! Test cases for serving a GraphQL schema.
+
+#![cfg(feature = "graphql")]
+
+use async_graphql::EmptyMutation;
+use async_graphql::Object;
+use async_graphql::Schema;
+use async_graphql::Subscription;
+use dropshot::test_util::read_json;
+use dropshot::ApiDescription;
+use futures::SinkExt;
+use futures::Stream;
+use futures::StreamExt;
+use http::Method;
+use http::StatusCode;
+use serde_json::json;
+use tokio_tungstenite::tungstenite::client::IntoClientRequest;
+use tokio_tungstenite::tungstenite::Message;
+
+pub mod common;
+
+struct Query;
+
+#[Object]
+impl Query {
+    async fn answer(&self) -> u32 {
+        42
+    }
+}
+
+struct Countdown;
+
+#[Subscription]
+impl Countdown {
+    async fn countdown(&self) -> impl Stream<Item = u32> {
+        futures::stream::iter(vec![2, 1, 0])
+    }
+}
+
+fn api() -> ApiDescription<usize> {
+    let schema = Schema::new(Query, EmptyMutation, Countdown);
+    let mut api = ApiDescription::new();
+    api.register_graphql("/graphql", schema).unwrap();
+    api
+}
+
+#[tokio::test]
+async fn test_graphql_queries() {
+    let testctx = common::test_setup("graphql_queries", api());
+    let client = &testctx.client_testctx;
+
+    let mut response = client
+        .make_request(
+            Method::POST,
+            "/graphql",
+            Some(json!({ "query": "{ answer }" })),
+            StatusCode::OK,
+        )
+        .await
+        .unwrap();
+    let body: serde_json::Value = read_json(&mut response).await;
+    assert_eq!(body, json!({ "data": { "answer": 42 } }));
+
+    let mut response = client
+        .make_request_no_body(
+            Method::GET,
+            "/graphql?query=%7B%20answer%20%7D",
+            StatusCode::OK,
+        )
+        .await
+        .unwrap();
+    let body: serde_json::Value = read_json(&mut response).await;
+    assert_eq!(body, json!({ "data": { "answer": 42 } }));
+
+    testctx.teardown().await;
+}
+
+#[tokio::test]
+async fn test_graphql_subscription() {
+    let testctx = common::test_setup("graphql_subscription", api());
+
+    let url =
+        format!("ws://{}/graphql/ws", testctx.client_testctx.bind_address);
+    let mut request = url.into_client_request().unwrap();
+    request.headers_mut().insert(
+        "Sec-WebSocket-Protocol",
+        "graphql-transport-ws".parse().unwrap(),
+    );
+    let (mut ws, response) =
+        tokio_tungstenite::connect_async(request).await.unwrap();
+    assert_eq!(
+        response.headers()["Sec-WebSocket-Protocol"],
+        "graphql-transport-ws"
+    );
+
+    ws.send(Message::Text(json!({ "type": "connection_init" }).to_string()))
+        .await
+        .unwrap();
+    let ack = next_json(&mut ws).await;
+    assert_eq!(ack["type"], "connection_ack");
+
+    ws.send(Message::Text(
+        json!({
+            "id": "1",
+            "type": "subscribe",
+            "payload": { "query": "subscription { countdown }" },
+        })
+        .to_string(),
+    ))
+    .await
+    .unwrap();
+    for n in [2, 1, 0] {
+        assert_eq!(
+            next_json(&mut ws).await,
+            json!({
+                "id": "1",
+                "type": "next",
+                "payload": { "data": { "countdown": n } },
+            })
+        );
+    }
+    assert_eq!(
+        next_json(&mut ws).await,
+        json!({ "id": "1", "type": "complete" })
+    );
+
+    ws.close(None).await.unwrap();
+    testctx.teardown().await;
+}
+
+/// Reads the next text message from `ws` and parses it as JSON.
+async fn next_json<S>(ws: &mut S) -> serde_json::Value
+where
+    S: Stream<Item = tokio_tungstenite::tungstenite::Result<Message>> + Unpin,
+{
+    match ws.next().await.unwrap().unwrap() {
+        Message::Text(text) => serde_json::from_str(&text).unwrap(),
+        other => panic!("unexpected message: {:?}", other),
+    }
+}
@@ -0,0 +1,209 @@
+// Copyright 2023 Oxide Computer Company
+
+//! Test cases for the Header extractor.
+
+use dropshot::endpoint;
+use dropshot::test_util::read_json;
+use dropshot::ApiDescription;
+use dropshot::Body;
+use dropshot::Header;
+use dropshot::HttpError;
+use dropshot::HttpResponseOk;
+use dropshot::RequestContext;
+use http::Method;
+use http::StatusCode;
+use hyper::Request;
+use schemars::JsonSchema;
+use serde::Deserialize;
+use serde::Serialize;
+
+pub mod common;
+
+#[derive(Debug, Deserialize, JsonSchema, PartialEq, Serialize)]
+struct ClientHeaders {
+    /// Identifies the client's request
+    #[serde(rename = "x-request-id")]
+    request_id: String,
+    #[serde(rename = "x-retry-count")]
+    retry_count: Option<u32>,
+}
+
+#[endpoint {
+    method = GET,
+    path = "/headers",
+}]
+async fn headers_get(
+    _rqctx: RequestContext<usize>,
+    headers: Header<ClientHeaders>,
+) -> Result<HttpResponseOk<ClientHeaders>, HttpError> {
+    Ok(HttpResponseOk(headers.into_inner()))
+}
+
+/// Header names are derived from the names of fields without `rename`.
+#[derive(Debug, Deserialize, JsonSchema, PartialEq, Serialize)]
+struct TraceHeaders {
+    /// Identifies the trace that the request belongs to
+    x_trace_id: String,
+    traceparent: Option<String>,
+}
+
+#[endpoint {
+    method = GET,
+    path = "/trace",
+}]
+async fn trace_get(
+    _rqctx: RequestContext<usize>,
+    headers: Header<TraceHeaders>,
+) -> Result<HttpResponseOk<TraceHeaders>, HttpError> {
+    Ok(HttpResponseOk(headers.into_inner()))
+}
+
+fn api() -> ApiDescription<usize> {
+    let mut api = ApiDescription::new();
+    api.register(headers_get).unwrap();
+    api.register(trace_get).unwrap();
+    api
+}
+
+fn request(
+    client: &dropshot::test_util::ClientTestContext,
+    headers: &[(&'static str, &'static str)],
+) -> Request<Body> {
+    request_to(client, "/headers", headers)
+}
+
+fn request_to(
+    client: &dropshot::test_util::ClientTestContext,
+    path: &str,
+    headers: &[(&'static str, &'static str)],
+) -> Request<Body> {
+    let mut builder =
+        Request::builder().method(Method::GET).uri(client.url(path));
+    for (name, value) in headers {
+        builder = builder.header(*name, *value);
+    }
+    builder.body(Body::empty()).unwrap()
+}
+
+#[tokio::test]
+async fn test_header() {
+    let testctx = common::test_setup("header", api());
+    let client = &testctx.client_testctx;
+
+    // Names are matched case-insensitively.
+    let mut response = client
+        .make_request_with_request(
+            request(client, &[("X-Request-Id", "abc"), ("x-retry-count", "2")]),
+            StatusCode::OK,
+        )
+        .await
+        .unwrap();
+    let headers: ClientHeaders = read_json(&mut response).await;
+    assert_eq!(
+        headers,
+        ClientHeaders { request_id: String::from("abc"), retry_count: Some(2) }
+    );
+
+    let mut response = client
+        .make_request_with_request(
+            request(client, &[("x-request-id", "abc")]),
+            StatusCode::OK,
+        )
+        .await
+        .unwrap();
+    let headers: ClientHeaders = read_json(&mut response).await;
+    assert_eq!(
+        headers,
+        ClientHeaders { request_id: String::from("abc"), retry_count: None }
+    );
+
+    // Errors name the offending header.
+    let error = client
+        .make_request_with_request(
+            request(client, &[("x-retry-count", "2")]),
+            StatusCode::BAD_REQUEST,
+        )
+        .await
+        .unwrap_err();
+    assert_eq!(error.message, "missing required header \"x-request-id\"");
+
+    let error = client
+        .make_request_with_request(
+            request(
+                client,
+                &[("x-request-id", "abc"), ("x-retry-count", "many")],
+            ),
+            StatusCode::BAD_REQUEST,
+        )
+        .await
+        .unwrap_err();
+    assert_eq!(
+        error.message,
+        "invalid value for header \"x-retry-count\": unable to parse 'many' \
+         as u32"
+    );
+
+    // Fields that aren't renamed are headers named in kebab-case.
+    let mut response = client
+        .make_request_with_request(
+            request_to(
+                client,
+                "/trace",
+                &[("X-Trace-Id", "t1"), ("traceparent", "00-abc-01")],
+            ),
+            StatusCode::OK,
+        )
+        .await
+        .unwrap();
+    let headers: TraceHeaders = read_json(&mut response).await;
+    assert_eq!(
+        headers,
+        TraceHeaders {
+            x_trace_id: String::from("t1"),
+            traceparent: Some(String::from("00-abc-01")),
+        }
+    );
+
+    let error = client
+        .make_request_with_request(
+            request_to(client, "/trace", &[("x_trace_id", "t1")]),
+            StatusCode::BAD_REQUEST,
+        )
+        .await
+        .unwrap_err();
+    assert_eq!(error.message, "missing required header \"x-trace-id\"");
+
+    testctx.teardown().await;
+}
+
+#[test]
+fn test_header_openapi() {
+    let spec = api().openapi("test", "1.0").json().unwrap();
+    let parameters = spec["paths"]["/headers"]["get"]["parameters"]
+        .as_array()
+        .unwrap()
+        .clone();
+    assert_eq!(parameters.len(), 2);
+    assert_eq!(parameters[0]["in"], "header");
+    assert_eq!(parameters[0]["name"], "x-request-id");
+    assert_eq!(parameters[0]["required"], true);
+    assert_eq!(parameters[0]["description"], "Identifies the client's request");
+    assert_eq!(parameters[1]["in"], "header");
+    assert_eq!(parameters[1]["name"], "x-retry-count");
+    assert!(parameters[1].get("required").is_none());
+
+    let parameters = spec["paths"]["/trace"]["get"]["parameters"]
+        .as_array()
+        .unwrap()
+        .clone();
+    assert_eq!(parameters.len(), 2);
+    assert_eq!(parameters[0]["in"], "header");
+    assert_eq!(parameters[0]["name"], "traceparent");
+    assert_eq!(parameters[1]["in"], "header");
+    assert_eq!(parameters[1]["name"], "x-trace-id");
+    assert_eq!(parameters[1]["required"], true);
+    assert_eq!(
+        parameters[1]["description"],
+        "Identifies the trace that the request belongs to"
+    );
+}
@@ -0,0 +1,115 @@
+// Copyright 2023 Oxide Computer Company
+
+//! Test cases for the limits on reading request heads.
+
+use dropshot::endpoint;
+use dropshot::test_util::TestContext;
+use dropshot::ApiDescription;
+use dropshot::ConfigDropshot;
+use dropshot::HttpError;
+use dropshot::HttpResponseOk;
+use dropshot::HttpServerStarter;
+use dropshot::RequestContext;
+use slog::o;
+
+pub mod common;
+
+#[endpoint {
+    method = GET,
+    path = "/",
+}]
+async fn index(
+    _rqctx: RequestContext<usize>,
+) -> Result<HttpResponseOk<u32>, HttpError> {
+    Ok(HttpResponseOk(1))
+}
+
+fn test_setup(test_name: &str, config: &ConfigDropshot) -> TestContext<usize> {
+    let mut api = ApiDescription::new();
+    api.register(index).unwrap();
+    common::test_setup_with_config(test_name, api, config)
+}
+
+#[tokio::test]
+async fn test_header_read_timeout() {
+    let config = ConfigDropshot {
+        header_read_timeout_ms: Some(100),
+        ..Default::default()
+    };
+    let testctx = test_setup("header_read_timeout", &config);
+    let addr = testctx.server.local_addr();
+
+    // A client that stops sending partway through the head is disconnected.
+    let response =
+        common::exchange(addr, b"GET / HTTP/1.1\r\nHost: a\r\n").await;
+    assert!(!response.contains("200 OK"), "{}", response);
+
+    let response = common::exchange(
+        addr,
+        b"GET / HTTP/1.1\r\nHost: a\r\nConnection: close\r\n\r\n",
+    )
+    .await;
+    assert!(response.starts_with("HTTP/1.1 200 OK"), "{}", response);
+
+    testctx.teardown().await;
+}
+
+#[tokio::test]
+async fn test_max_header_bytes() {
+    let config =
+        ConfigDropshot { max_header_bytes: Some(8192), ..Default::default() };
+    let testctx = test_setup("max_header_bytes", &config);
+    let addr = testctx.server.local_addr();
+
+    let request = format!(
+        "GET / HTTP/1.1\r\nHost: a\r\nX-Large: {}\r\n\r\n",
+        "x".repeat(10000)
+    );
+    let response = common::exchange(addr, request.as_bytes()).await;
+    assert!(
+        response.starts_with("HTTP/1.1 431 Request Header Fields Too Large"),
+        "{}",
+        response
+    );
+
+    // The limit can't be smaller than hyper's smallest buffer.
+    let config =
+        ConfigDropshot { max_header_bytes: Some(1024), ..Default::default() };
+    let log = testctx.log.new(o!());
+    let error = HttpServerStarter::new(&config, ApiDescription::new(), 0, &log)
+        .map(|_| ())
+        .unwrap_err();
+    assert_eq!(error.to_string(), "max_header_bytes must be at least 8192");
+
+    testctx.teardown().await;
+}
+
+#[tokio::test]
+async fn test_max_header_count() {
+    let config =
+        ConfigDropshot { max_header_count: Some(3), ..Default::default() };
+    let testctx = test_setup("max_header_count", &config);
+    let addr = testctx.server.local_addr();
+
+    let response = common::exchange(
+        addr,
+        b"GET / HTTP/1.1\r\nHost: a\r\nA: 1\r\nConnection: close\r\n\r\n",
+    )
+    .await;
+    assert!(response.starts_with("HTTP/1.1 200 OK"), "{}", response);
+
+    let response = common::exchange(
+        addr,
+        b"GET / HTTP/1.1\r\nHost: a\r\nA: 1\r\nA: 2\r\n\
+        Connection: close\r\n\r\n",
+    )
+    .await;
+    assert!(
+        response.starts_with("HTTP/1.1 431 Request Header Fields Too Large"),
+        "{}",
+        response
+    );
+    assert!(response.contains("request has more than 3 headers"));
+
+    testctx.teardown().await;
+}
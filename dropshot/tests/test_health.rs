@@ -0,0 +1,80 @@
+// Copyright 2023 Oxide Computer Company
+
+//! Test cases for the built-in liveness and readiness endpoints.
+
+use dropshot::health::HealthChecks;
+use dropshot::health::HealthReport;
+use dropshot::health::HealthStatus;
+use dropshot::test_util::read_json;
+use dropshot::ApiDescription;
+use dropshot::Body;
+use http::Method;
+use http::StatusCode;
+use std::sync::atomic::AtomicBool;
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
+use std::time::Duration;
+
+pub mod common;
+
+#[tokio::test]
+async fn test_health_endpoints() {
+    let ready = Arc::new(AtomicBool::new(false));
+    let ready_check = Arc::clone(&ready);
+    let checks = HealthChecks::new()
+        .liveness_check("alive", Duration::from_secs(10), || async { Ok(()) })
+        .readiness_check("warmed-up", Duration::from_secs(10), move || {
+            let ready = ready_check.load(Ordering::SeqCst);
+            async move {
+                if ready {
+                    Ok(())
+                } else {
+                    Err(String::from("still warming up"))
+                }
+            }
+        });
+    let mut api = ApiDescription::new();
+    api.register_health_endpoints(checks).unwrap();
+
+    // The health endpoints are not part of the API's OpenAPI document.
+    let spec = api.openapi("test", "1.0").json().unwrap();
+    assert!(spec["paths"].as_object().unwrap().is_empty());
+
+    let testctx = common::test_setup("health_endpoints", api);
+    let client = &testctx.client_testctx;
+
+    let mut response = client
+        .make_request_no_body(Method::GET, "/healthz", StatusCode::OK)
+        .await
+        .unwrap();
+    let report: HealthReport = read_json(&mut response).await;
+    assert_eq!(report.status, HealthStatus::Pass);
+    assert_eq!(report.checks.len(), 1);
+    assert_eq!(report.checks[0].name, "alive");
+
+    // A failing check fails the readiness endpoint with a 503 whose body
+    // describes the failure.  (The test client only accepts error bodies in
+    // the usual format, so use a plain hyper client here.)
+    let mut response = client
+        .client
+        .get(client.url("/readyz"))
+        .await
+        .expect("failed to make request")
+        .map(Body::wrap);
+    assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+    let report: HealthReport = read_json(&mut response).await;
+    assert_eq!(report.status, HealthStatus::Fail);
+    assert_eq!(report.checks[0].name, "warmed-up");
+    assert_eq!(report.checks[0].status, HealthStatus::Fail);
+    assert_eq!(report.checks[0].message.as_deref(), Some("still warming up"));
+
+    ready.store(true, Ordering::SeqCst);
+    let mut response = client
+        .make_request_no_body(Method::GET, "/readyz", StatusCode::OK)
+        .await
+        .unwrap();
+    let report: HealthReport = read_json(&mut response).await;
+    assert_eq!(report.status, HealthStatus::Pass);
+
+    testctx.teardown().await;
+}
@@ -0,0 +1,146 @@
+// Copyright 2023 Oxide Computer Company
+
+//! Test cases for hooks attached to routes and tags.
+
+use async_trait::async_trait;
+use dropshot::endpoint;
+use dropshot::test_util::object_get;
+use dropshot::ApiDescription;
+use dropshot::ApiEndpoint;
+use dropshot::Body;
+use dropshot::HttpError;
+use dropshot::HttpResponseOk;
+use dropshot::RequestContext;
+use dropshot::RequestHook;
+use http::Method;
+use http::StatusCode;
+use hyper::Response;
+use std::sync::atomic::AtomicUsize;
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
+
+pub mod common;
+
+#[endpoint {
+    method = GET,
+    path = "/widgets",
+}]
+async fn widgets_get(
+    _rqctx: RequestContext<usize>,
+) -> Result<HttpResponseOk<Vec<String>>, HttpError> {
+    Ok(HttpResponseOk(vec![]))
+}
+
+#[endpoint {
+    method = GET,
+    path = "/admin/users",
+    tags = ["admin"],
+}]
+async fn users_get(
+    _rqctx: RequestContext<usize>,
+) -> Result<HttpResponseOk<Vec<String>>, HttpError> {
+    Ok(HttpResponseOk(vec![String::from("root")]))
+}
+
+#[endpoint {
+    method = GET,
+    path = "/admin/fail",
+    tags = ["admin"],
+}]
+async fn fail_get(
+    _rqctx: RequestContext<usize>,
+) -> Result<HttpResponseOk<Vec<String>>, HttpError> {
+    Err(HttpError::for_bad_request(None, String::from("no good")))
+}
+
+/// Rejects requests that don't ask for administrator access, and marks the
+/// messages of errors from the endpoints it's attached to
+#[derive(Debug)]
+struct RequireAdmin;
+
+#[async_trait]
+impl RequestHook<usize> for RequireAdmin {
+    async fn before(
+        &self,
+        rqctx: &RequestContext<usize>,
+        _endpoint: &ApiEndpoint<usize>,
+    ) -> Result<(), HttpError> {
+        match rqctx.request.uri().query() {
+            Some("admin=true") => Ok(()),
+            _ => Err(HttpError::for_client_error(
+                None,
+                StatusCode::FORBIDDEN,
+                String::from("administrators only"),
+            )),
+        }
+    }
+
+    async fn after(
+        &self,
+        _rqctx: &RequestContext<usize>,
+        endpoint: &ApiEndpoint<usize>,
+        result: Result<Response<Body>, HttpError>,
+    ) -> Result<Response<Body>, HttpError> {
+        result.map_err(|mut error| {
+            error.external_message = format!(
+                "{} ({})",
+                error.external_message, endpoint.operation_id
+            );
+            error
+        })
+    }
+}
+
+/// Counts the requests that reach the endpoints it's attached to
+#[derive(Debug, Default)]
+struct Counter(AtomicUsize);
+
+#[async_trait]
+impl RequestHook<usize> for Counter {
+    async fn before(
+        &self,
+        _rqctx: &RequestContext<usize>,
+        _endpoint: &ApiEndpoint<usize>,
+    ) -> Result<(), HttpError> {
+        self.0.fetch_add(1, Ordering::SeqCst);
+        Ok(())
+    }
+}
+
+#[tokio::test]
+async fn test_hooks() {
+    let counter = Arc::new(Counter::default());
+    let mut api = ApiDescription::new()
+        .tag_hook("admin", Arc::new(RequireAdmin))
+        .route_hook(Method::GET, "/widgets", counter.clone());
+    api.register(widgets_get).unwrap();
+    api.register(users_get).unwrap();
+    api.register(fail_get).unwrap();
+    let testctx = common::test_setup("hooks", api);
+    let client = &testctx.client_testctx;
+
+    // Route hooks apply to just their route.
+    let widgets: Vec<String> = object_get(client, "/widgets").await;
+    assert!(widgets.is_empty());
+    assert_eq!(counter.0.load(Ordering::SeqCst), 1);
+
+    // Tag hooks apply to all endpoints with the tag.
+    let error = client
+        .make_request_error(Method::GET, "/admin/users", StatusCode::FORBIDDEN)
+        .await;
+    assert_eq!(error.message, "administrators only");
+    let users: Vec<String> =
+        object_get(client, "/admin/users?admin=true").await;
+    assert_eq!(users, vec!["root"]);
+    let error = client
+        .make_request_error(
+            Method::GET,
+            "/admin/fail?admin=true",
+            StatusCode::BAD_REQUEST,
+        )
+        .await;
+    assert_eq!(error.message, "no good (fail_get)");
+    assert_eq!(counter.0.load(Ordering::SeqCst), 1);
+
+    testctx.teardown().await;
+}
@@ -0,0 +1,119 @@
+// Copyright 2023 Oxide Computer Company
+
+//! Test cases for serving HTTP/3.
+
+#![cfg(feature = "http3")]
+
+use bytes::BufMut;
+use dropshot::endpoint;
+use dropshot::ApiDescription;
+use dropshot::Body;
+use dropshot::ConfigDropshot;
+use dropshot::ConfigHttp3;
+use dropshot::ConfigTls;
+use dropshot::HttpError;
+use dropshot::HttpResponseOk;
+use dropshot::HttpServerStarter;
+use dropshot::RequestContext;
+use http::StatusCode;
+use hyper_util::client::legacy::Client;
+use hyper_util::rt::TokioExecutor;
+use slog::o;
+use std::sync::Arc;
+
+pub mod common;
+use common::HttpsConnector;
+
+#[endpoint {
+    method = GET,
+    path = "/protocol",
+}]
+async fn protocol_get(
+    rqctx: RequestContext<()>,
+) -> Result<HttpResponseOk<String>, HttpError> {
+    let alpn = rqctx.connection().alpn_protocol().unwrap_or_default();
+    Ok(HttpResponseOk(String::from_utf8_lossy(alpn).into_owned()))
+}
+
+#[tokio::test]
+async fn test_http3() {
+    let logctx = common::create_log_context("http3");
+    let log = logctx.log.new(o!());
+    let (certs, key) = common::generate_tls_key();
+    let (cert_file, key_file) = common::tls_key_to_file(&certs, &key);
+    let root = certs.last().unwrap().clone();
+
+    let config = ConfigDropshot {
+        tls: Some(ConfigTls::AsFile {
+            cert_file: cert_file.path().to_path_buf(),
+            key_file: key_file.path().to_path_buf(),
+            client_auth: None,
+        }),
+        http3: Some(ConfigHttp3::default()),
+        ..Default::default()
+    };
+    let mut api = ApiDescription::new();
+    api.register(protocol_get).unwrap();
+    let server =
+        HttpServerStarter::new(&config, api, (), &log).unwrap().start();
+    let addr = server.local_addr();
+    let uri = format!("https://localhost:{}/protocol", addr.port());
+
+    // Responses on the TCP listener advertise the HTTP/3 endpoint, which is on
+    // the same port.
+    let mut roots = rustls::RootCertStore::empty();
+    roots.add(&root).unwrap();
+    let tls_config = rustls::ClientConfig::builder()
+        .with_safe_defaults()
+        .with_root_certificates(roots)
+        .with_no_client_auth();
+    let client: Client<HttpsConnector, Body> =
+        Client::builder(TokioExecutor::new())
+            .build(HttpsConnector::new(tls_config));
+    let request = http::Request::get(&uri).body(Body::empty()).unwrap();
+    let response = client.request(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(
+        response.headers().get(http::header::ALT_SVC).unwrap(),
+        format!("h3=\":{}\"; ma=86400", addr.port()).as_str()
+    );
+
+    // The same API is served over HTTP/3.
+    let mut roots = quic_rustls::RootCertStore::empty();
+    roots.add(&quic_rustls::Certificate(root.0)).unwrap();
+    let mut tls_config = quic_rustls::ClientConfig::builder()
+        .with_safe_defaults()
+        .with_root_certificates(roots)
+        .with_no_client_auth();
+    tls_config.alpn_protocols = vec![b"h3".to_vec()];
+    let mut endpoint =
+        quinn::Endpoint::client("127.0.0.1:0".parse().unwrap()).unwrap();
+    endpoint.set_default_client_config(quinn::ClientConfig::new(Arc::new(
+        tls_config,
+    )));
+    let conn = endpoint.connect(addr, "localhost").unwrap().await.unwrap();
+    let (mut driver, mut send_request) =
+        h3::client::new(h3_quinn::Connection::new(conn)).await.unwrap();
+    let driver = tokio::spawn(async move {
+        let _ = futures::future::poll_fn(|cx| driver.poll_close(cx)).await;
+    });
+
+    let request = http::Request::get(&uri).body(()).unwrap();
+    let mut stream = send_request.send_request(request).await.unwrap();
+    stream.finish().await.unwrap();
+    let response = stream.recv_response().await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    assert!(response.headers().get(http::header::ALT_SVC).is_none());
+    let mut body = Vec::new();
+    while let Some(chunk) = stream.recv_data().await.unwrap() {
+        body.put(chunk);
+    }
+    let protocol: String = serde_json::from_slice(&body).unwrap();
+    assert_eq!(protocol, "h3");
+
+    drop(send_request);
+    endpoint.close(0u32.into(), b"done");
+    driver.abort();
+    server.close().await.unwrap();
+    logctx.cleanup_successful();
+}
@@ -0,0 +1,158 @@
+// Copyright 2023 Oxide Computer Company
+
+//! Test cases for idempotency keys.
+
+use dropshot::endpoint;
+use dropshot::test_util::read_json;
+use dropshot::ApiDescription;
+use dropshot::Body;
+use dropshot::HttpError;
+use dropshot::HttpResponseCreated;
+use dropshot::InMemoryIdempotencyStore;
+use dropshot::RequestContext;
+use http::Method;
+use http::StatusCode;
+use hyper::Request;
+use std::sync::atomic::AtomicBool;
+use std::sync::atomic::AtomicUsize;
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
+use std::time::Duration;
+
+pub mod common;
+
+static NPAYMENTS: AtomicUsize = AtomicUsize::new(0);
+
+#[endpoint {
+    method = POST,
+    path = "/payments",
+    idempotency = "required",
+}]
+async fn payment_create(
+    _rqctx: RequestContext<usize>,
+) -> Result<HttpResponseCreated<usize>, HttpError> {
+    Ok(HttpResponseCreated(NPAYMENTS.fetch_add(1, Ordering::SeqCst)))
+}
+
+static HUNG: AtomicBool = AtomicBool::new(false);
+
+/// Hangs past its timeout the first time it's called.
+#[endpoint {
+    method = POST,
+    path = "/refunds",
+    idempotency = "required",
+    timeout_secs = 1,
+}]
+async fn refund_create(
+    _rqctx: RequestContext<usize>,
+) -> Result<HttpResponseCreated<()>, HttpError> {
+    if !HUNG.swap(true, Ordering::SeqCst) {
+        tokio::time::sleep(Duration::from_secs(3600)).await;
+    }
+    Ok(HttpResponseCreated(()))
+}
+
+#[test]
+fn test_idempotency_requires_store() {
+    let mut api = ApiDescription::<usize>::new();
+    assert_eq!(
+        api.register(payment_create).unwrap_err(),
+        "endpoint payment_create accepts idempotency keys, but no \
+         idempotency store has been configured"
+    );
+}
+
+#[tokio::test]
+async fn test_idempotency_keys() {
+    let store = InMemoryIdempotencyStore::new(Duration::from_secs(60));
+    let mut api = ApiDescription::new().idempotency_store(Arc::new(store));
+    api.register(payment_create).unwrap();
+    let testctx = common::test_setup("idempotency_keys", api);
+    let client = &testctx.client_testctx;
+
+    let error = client
+        .make_request_error(Method::POST, "/payments", StatusCode::BAD_REQUEST)
+        .await;
+    assert_eq!(error.error_code.as_deref(), Some("IdempotencyKeyRequired"));
+
+    let post_with_key = |key: &str| {
+        Request::builder()
+            .method(Method::POST)
+            .uri(client.url("/payments"))
+            .header("idempotency-key", key)
+            .body(Body::from("{}"))
+            .unwrap()
+    };
+
+    let mut response = client
+        .make_request_with_request(post_with_key("k1"), StatusCode::CREATED)
+        .await
+        .unwrap();
+    assert!(response.headers().get("idempotent-replayed").is_none());
+    let first: usize = read_json(&mut response).await;
+
+    // Retrying with the same key replays the first response.
+    let mut response = client
+        .make_request_with_request(post_with_key("k1"), StatusCode::CREATED)
+        .await
+        .unwrap();
+    assert_eq!(response.headers().get("idempotent-replayed").unwrap(), "true");
+    let replayed: usize = read_json(&mut response).await;
+    assert_eq!(first, replayed);
+
+    // A different key executes the request again.
+    let mut response = client
+        .make_request_with_request(post_with_key("k2"), StatusCode::CREATED)
+        .await
+        .unwrap();
+    let second: usize = read_json(&mut response).await;
+    assert_eq!(second, first + 1);
+
+    // Reusing a key with a different body is an error.
+    let request = Request::builder()
+        .method(Method::POST)
+        .uri(client.url("/payments"))
+        .header("idempotency-key", "k1")
+        .body(Body::from("{\"amount\":2}"))
+        .unwrap();
+    let error = client
+        .make_request_with_request(request, StatusCode::UNPROCESSABLE_ENTITY)
+        .await
+        .unwrap_err();
+    assert_eq!(error.error_code.as_deref(), Some("IdempotencyKeyMismatch"));
+
+    testctx.teardown().await;
+}
+
+#[tokio::test]
+async fn test_idempotency_timeout() {
+    let store = InMemoryIdempotencyStore::new(Duration::from_secs(60));
+    let mut api = ApiDescription::new().idempotency_store(Arc::new(store));
+    api.register(refund_create).unwrap();
+    let testctx = common::test_setup("idempotency_timeout", api);
+    let client = &testctx.client_testctx;
+
+    let post = || {
+        Request::builder()
+            .method(Method::POST)
+            .uri(client.url("/refunds"))
+            .header("idempotency-key", "k1")
+            .body(Body::empty())
+            .unwrap()
+    };
+    client
+        .make_request_with_request(post(), StatusCode::SERVICE_UNAVAILABLE)
+        .await
+        .unwrap_err();
+
+    // The request that timed out released its key (in the background), so a
+    // retry executes the handler rather than failing with a conflict.
+    tokio::time::sleep(Duration::from_millis(100)).await;
+    let response = client
+        .make_request_with_request(post(), StatusCode::CREATED)
+        .await
+        .unwrap();
+    assert!(response.headers().get("idempotent-replayed").is_none());
+
+    testctx.teardown().await;
+}
@@ -0,0 +1,159 @@
+// Copyright 2023 Oxide Computer Company
+
+//! Test cases for streamed JSON array and NDJSON responses.
+
+use dropshot::endpoint;
+use dropshot::ApiDescription;
+use dropshot::HttpError;
+use dropshot::HttpResponseNdjson;
+use dropshot::HttpResponseOk;
+use dropshot::JsonArrayStream;
+use dropshot::NdjsonStream;
+use dropshot::Path;
+use dropshot::RequestContext;
+use dropshot::CONTENT_TYPE_JSON;
+use dropshot::CONTENT_TYPE_NDJSON;
+use http::Method;
+use http::StatusCode;
+use http_body_util::BodyExt;
+use schemars::JsonSchema;
+use serde::Deserialize;
+use serde::Serialize;
+
+pub mod common;
+
+#[derive(JsonSchema, Serialize)]
+struct Item {
+    id: u32,
+}
+
+#[derive(Deserialize, JsonSchema)]
+struct Count {
+    count: u32,
+}
+
+fn items(count: u32) -> impl futures::Stream<Item = Item> + Send + 'static {
+    futures::stream::iter((1..=count).map(|id| Item { id }))
+}
+
+#[endpoint {
+    method = GET,
+    path = "/array/{count}",
+}]
+async fn array_get(
+    _rqctx: RequestContext<usize>,
+    path: Path<Count>,
+) -> Result<HttpResponseOk<JsonArrayStream<Item>>, HttpError> {
+    Ok(HttpResponseOk(JsonArrayStream::new(items(path.into_inner().count))))
+}
+
+#[endpoint {
+    method = GET,
+    path = "/ndjson/{count}",
+}]
+async fn ndjson_get(
+    _rqctx: RequestContext<usize>,
+    path: Path<Count>,
+) -> Result<HttpResponseOk<NdjsonStream<Item>>, HttpError> {
+    Ok(HttpResponseOk(NdjsonStream::new(items(path.into_inner().count))))
+}
+
+#[endpoint {
+    method = GET,
+    path = "/lines/{count}",
+}]
+async fn lines_get(
+    _rqctx: RequestContext<usize>,
+    path: Path<Count>,
+) -> Result<HttpResponseNdjson<Item>, HttpError> {
+    let count = path.into_inner().count;
+    // The stream fails after producing `count` items.
+    let lines = futures::stream::iter((1..=count + 1).map(move |id| {
+        if id <= count {
+            Ok(Item { id })
+        } else {
+            Err(HttpError::for_internal_error(String::from("out of items")))
+        }
+    }));
+    Ok(HttpResponseNdjson::new(lines))
+}
+
+fn api() -> ApiDescription<usize> {
+    let mut api = ApiDescription::new();
+    api.register(array_get).unwrap();
+    api.register(ndjson_get).unwrap();
+    api.register(lines_get).unwrap();
+    api
+}
+
+#[tokio::test]
+async fn test_json_stream() {
+    let testctx = common::test_setup("json_stream", api());
+    let client = &testctx.client_testctx;
+
+    for (path, content_type, expected) in [
+        ("/array/0", CONTENT_TYPE_JSON, "[]"),
+        ("/array/3", CONTENT_TYPE_JSON, "[{\"id\":1},{\"id\":2},{\"id\":3}]"),
+        ("/ndjson/0", CONTENT_TYPE_NDJSON, ""),
+        ("/ndjson/2", CONTENT_TYPE_NDJSON, "{\"id\":1}\n{\"id\":2}\n"),
+    ] {
+        let mut response = client
+            .make_request_no_body(Method::GET, path, StatusCode::OK)
+            .await
+            .unwrap();
+        assert_eq!(
+            response.headers().get(http::header::CONTENT_TYPE).unwrap(),
+            content_type
+        );
+        let body = response.body_mut().collect().await.unwrap().to_bytes();
+        assert_eq!(body, expected, "{}", path);
+    }
+
+    testctx.teardown().await;
+}
+
+#[tokio::test]
+async fn test_ndjson_response() {
+    let testctx = common::test_setup("ndjson_response", api());
+    let client = &testctx.client_testctx;
+
+    let mut response = client
+        .make_request_no_body(Method::GET, "/lines/2", StatusCode::OK)
+        .await
+        .unwrap();
+    let headers = response.headers();
+    assert_eq!(
+        headers.get(http::header::CONTENT_TYPE).unwrap(),
+        CONTENT_TYPE_NDJSON
+    );
+    assert_eq!(headers.get("x-accel-buffering").unwrap(), "no");
+
+    // The lines sent before the stream failed arrive before the response
+    // ends abruptly.
+    let mut received = Vec::new();
+    while let Some(frame) = response.body_mut().frame().await {
+        match frame {
+            Ok(frame) => received.extend(frame.into_data().unwrap()),
+            Err(_) => break,
+        }
+    }
+    assert_eq!(received, b"{\"id\":1}\n{\"id\":2}\n");
+
+    testctx.teardown().await;
+}
+
+#[test]
+fn test_json_stream_openapi() {
+    let spec = api().openapi("test", "1.0").json().unwrap();
+    let paths = &spec["paths"];
+    let schema = &paths["/array/{count}"]["get"]["responses"]["200"]["content"]
+        [CONTENT_TYPE_JSON]["schema"];
+    assert_eq!(schema["type"], "array");
+    assert_eq!(schema["items"]["$ref"], "#/components/schemas/Item");
+    let schema = &paths["/ndjson/{count}"]["get"]["responses"]["200"]
+        ["content"][CONTENT_TYPE_NDJSON]["schema"];
+    assert_eq!(schema["$ref"], "#/components/schemas/Item");
+    let schema = &paths["/lines/{count}"]["get"]["responses"]["200"]["content"]
+        [CONTENT_TYPE_NDJSON]["schema"];
+    assert_eq!(schema["$ref"], "#/components/schemas/Item");
+}
@@ -0,0 +1,128 @@
+// Copyright 2023 Oxide Computer Company
+
+//! Test cases for the limits on how long connections stay open.
+
+use dropshot::endpoint;
+use dropshot::test_util::TestContext;
+use dropshot::ApiDescription;
+use dropshot::ConfigDropshot;
+use dropshot::HttpError;
+use dropshot::HttpResponseOk;
+use dropshot::HttpServerStarter;
+use dropshot::RequestContext;
+use slog::o;
+use std::time::Duration;
+
+pub mod common;
+
+#[endpoint {
+    method = GET,
+    path = "/fast",
+}]
+async fn fast_get(
+    _rqctx: RequestContext<usize>,
+) -> Result<HttpResponseOk<u32>, HttpError> {
+    Ok(HttpResponseOk(1))
+}
+
+#[endpoint {
+    method = GET,
+    path = "/slow",
+}]
+async fn slow_get(
+    _rqctx: RequestContext<usize>,
+) -> Result<HttpResponseOk<u32>, HttpError> {
+    tokio::time::sleep(Duration::from_millis(500)).await;
+    Ok(HttpResponseOk(2))
+}
+
+fn test_setup(test_name: &str, config: &ConfigDropshot) -> TestContext<usize> {
+    let mut api = ApiDescription::new();
+    api.register(fast_get).unwrap();
+    api.register(slow_get).unwrap();
+    common::test_setup_with_config(test_name, api, config)
+}
+
+#[tokio::test]
+async fn test_keep_alive_timeout() {
+    let config = ConfigDropshot {
+        keep_alive_timeout_ms: Some(100),
+        ..Default::default()
+    };
+    let testctx = test_setup("keep_alive_timeout", &config);
+    let addr = testctx.server.local_addr();
+
+    // The connection is closed once it's been idle for long enough, but not
+    // while a request that takes longer than that is in progress.
+    let response = common::exchange(
+        addr,
+        b"GET /fast HTTP/1.1\r\nHost: a\r\n\r\n\
+        GET /slow HTTP/1.1\r\nHost: a\r\n\r\n",
+    )
+    .await;
+    assert_eq!(response.matches("HTTP/1.1 200 OK").count(), 2, "{}", response);
+    assert!(response.ends_with('2'), "{}", response);
+
+    testctx.teardown().await;
+}
+
+#[tokio::test]
+async fn test_max_requests_per_connection() {
+    let config = ConfigDropshot {
+        max_requests_per_connection: Some(2),
+        ..Default::default()
+    };
+    let testctx = test_setup("max_requests_per_connection", &config);
+    let addr = testctx.server.local_addr();
+
+    // The response to the last request asks the client to close the
+    // connection, and requests after it go unanswered.
+    let response = common::exchange(
+        addr,
+        b"GET /fast HTTP/1.1\r\nHost: a\r\n\r\n\
+        GET /fast HTTP/1.1\r\nHost: a\r\n\r\n\
+        GET /fast HTTP/1.1\r\nHost: a\r\n\r\n",
+    )
+    .await;
+    assert_eq!(response.matches("HTTP/1.1 200 OK").count(), 2, "{}", response);
+    assert_eq!(response.matches("connection: close").count(), 1);
+
+    // A server can't be configured to serve no requests at all.
+    let config = ConfigDropshot {
+        max_requests_per_connection: Some(0),
+        ..Default::default()
+    };
+    let log = testctx.log.new(o!());
+    let error = HttpServerStarter::new(&config, ApiDescription::new(), 0, &log)
+        .map(|_| ())
+        .unwrap_err();
+    assert_eq!(
+        error.to_string(),
+        "max_requests_per_connection must be at least 1"
+    );
+
+    testctx.teardown().await;
+}
+
+#[tokio::test]
+async fn test_idle_timeout() {
+    let config =
+        ConfigDropshot { idle_timeout_ms: Some(100), ..Default::default() };
+    let testctx = test_setup("idle_timeout", &config);
+    let addr = testctx.server.local_addr();
+
+    // A client that stops sending partway through a request is disconnected.
+    let response =
+        common::exchange(addr, b"GET /fast HTTP/1.1\r\nHost: a\r\n").await;
+    assert!(!response.contains("200 OK"), "{}", response);
+
+    // Clients that keep the connection busy aren't.
+    let response = common::exchange(
+        addr,
+        b"GET /fast HTTP/1.1\r\nHost: a\r\nConnection: close\r\n\r\n",
+    )
+    .await;
+    assert!(response.starts_with("HTTP/1.1 200 OK"), "{}", response);
+
+    testctx.teardown().await;
+}
@@ -0,0 +1,149 @@
+// Copyright 2023 Oxide Computer Company
+
+//! Test cases for shedding connections and requests beyond a server's limits.
+
+use dropshot::endpoint;
+use dropshot::test_util::ClientTestContext;
+use dropshot::ApiDescription;
+use dropshot::Body;
+use dropshot::ConfigDropshot;
+use dropshot::ConfigLoadLimits;
+use dropshot::HttpError;
+use dropshot::HttpResponseOk;
+use dropshot::HttpServerStarter;
+use dropshot::RequestContext;
+use http::Method;
+use http::StatusCode;
+use hyper::Request;
+use slog::o;
+use std::sync::Arc;
+use tokio::sync::Notify;
+
+pub mod common;
+
+#[derive(Default)]
+struct LoadContext {
+    /// notified when the slow handler has started
+    started: Notify,
+    /// notified to let the slow handler finish
+    release: Notify,
+}
+
+#[endpoint {
+    method = GET,
+    path = "/slow",
+}]
+async fn slow_get(
+    rqctx: RequestContext<Arc<LoadContext>>,
+) -> Result<HttpResponseOk<u32>, HttpError> {
+    let context = rqctx.context();
+    context.started.notify_one();
+    context.release.notified().await;
+    Ok(HttpResponseOk(1))
+}
+
+#[endpoint {
+    method = GET,
+    path = "/fast",
+}]
+async fn fast_get(
+    _rqctx: RequestContext<Arc<LoadContext>>,
+) -> Result<HttpResponseOk<u32>, HttpError> {
+    Ok(HttpResponseOk(2))
+}
+
+fn get(client: &ClientTestContext, path: &str) -> Request<Body> {
+    Request::builder()
+        .method(Method::GET)
+        .uri(client.url(path))
+        .body(Body::empty())
+        .unwrap()
+}
+
+fn api() -> ApiDescription<Arc<LoadContext>> {
+    let mut api = ApiDescription::new();
+    api.register(slow_get).unwrap();
+    api.register(fast_get).unwrap();
+    api
+}
+
+#[tokio::test]
+async fn test_max_in_flight_requests() {
+    let logctx = common::create_log_context("max_in_flight_requests");
+    let log = logctx.log.new(o!());
+    let context = Arc::new(LoadContext::default());
+    let config = ConfigDropshot {
+        load_limits: ConfigLoadLimits {
+            max_in_flight_requests: Some(1),
+            retry_after_secs: 3,
+            ..Default::default()
+        },
+        ..Default::default()
+    };
+    let server =
+        HttpServerStarter::new(&config, api(), Arc::clone(&context), &log)
+            .unwrap()
+            .start();
+    let client = ClientTestContext::new(server.local_addr(), log.new(o!()));
+
+    // While one request is in progress, another is shed.
+    let (slow, fast) =
+        tokio::join!(client.client.request(get(&client, "/slow")), async {
+            context.started.notified().await;
+            let fast = client.client.request(get(&client, "/fast")).await;
+            context.release.notify_one();
+            fast
+        });
+    assert_eq!(slow.unwrap().status(), StatusCode::OK);
+    let fast = fast.unwrap();
+    assert_eq!(fast.status(), StatusCode::SERVICE_UNAVAILABLE);
+    assert_eq!(fast.headers().get(http::header::RETRY_AFTER).unwrap(), "3");
+
+    // Once it's done, requests are handled again.
+    let fast = client.client.request(get(&client, "/fast")).await.unwrap();
+    assert_eq!(fast.status(), StatusCode::OK);
+
+    server.close().await.unwrap();
+    logctx.cleanup_successful();
+}
+
+#[tokio::test]
+async fn test_max_connections() {
+    let logctx = common::create_log_context("max_connections");
+    let log = logctx.log.new(o!());
+    let context = Arc::new(LoadContext::default());
+    let config = ConfigDropshot {
+        load_limits: ConfigLoadLimits {
+            max_connections: Some(1),
+            ..Default::default()
+        },
+        ..Default::default()
+    };
+    let server =
+        HttpServerStarter::new(&config, api(), Arc::clone(&context), &log)
+            .unwrap()
+            .start();
+    let client = ClientTestContext::new(server.local_addr(), log.new(o!()));
+
+    // While one connection is busy with a request, the client's pool opens
+    // another for the second request, which is shed and closed.
+    let (slow, fast) =
+        tokio::join!(client.client.request(get(&client, "/slow")), async {
+            context.started.notified().await;
+            let fast = client.client.request(get(&client, "/fast")).await;
+            context.release.notify_one();
+            fast
+        });
+    assert_eq!(slow.unwrap().status(), StatusCode::OK);
+    let fast = fast.unwrap();
+    assert_eq!(fast.status(), StatusCode::SERVICE_UNAVAILABLE);
+    assert_eq!(fast.headers().get(http::header::RETRY_AFTER).unwrap(), "1");
+    assert_eq!(fast.headers().get(http::header::CONNECTION).unwrap(), "close");
+
+    // The first connection is still open and can be reused.
+    let fast = client.client.request(get(&client, "/fast")).await.unwrap();
+    assert_eq!(fast.status(), StatusCode::OK);
+
+    server.close().await.unwrap();
+    logctx.cleanup_successful();
+}
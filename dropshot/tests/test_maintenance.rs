@@ -0,0 +1,110 @@
+// Copyright 2023 Oxide Computer Company
+
+//! Test cases for maintenance mode.
+
+use dropshot::endpoint;
+use dropshot::health::HealthChecks;
+use dropshot::ApiDescription;
+use dropshot::Body;
+use dropshot::HttpError;
+use dropshot::HttpResponseUpdatedNoContent;
+use dropshot::MaintenanceMode;
+use dropshot::RequestContext;
+use http::Method;
+use http::StatusCode;
+use std::time::Duration;
+
+pub mod common;
+
+#[endpoint {
+    method = GET,
+    path = "/projects",
+}]
+async fn projects_get(
+    _rqctx: RequestContext<usize>,
+) -> Result<HttpResponseUpdatedNoContent, HttpError> {
+    Ok(HttpResponseUpdatedNoContent())
+}
+
+#[endpoint {
+    method = GET,
+    path = "/admin/status",
+}]
+async fn admin_status(
+    _rqctx: RequestContext<usize>,
+) -> Result<HttpResponseUpdatedNoContent, HttpError> {
+    Ok(HttpResponseUpdatedNoContent())
+}
+
+#[tokio::test]
+async fn test_maintenance_mode() {
+    let mut api = ApiDescription::new();
+    api.register(projects_get).unwrap();
+    api.register(admin_status).unwrap();
+    api.register_health_endpoints(HealthChecks::new()).unwrap();
+    let testctx = common::test_setup("maintenance_mode", api);
+    let client = &testctx.client_testctx;
+    let server = &testctx.server;
+
+    assert!(server.maintenance_mode().is_none());
+    client
+        .make_request_no_body(Method::GET, "/projects", StatusCode::NO_CONTENT)
+        .await
+        .unwrap();
+
+    server.set_maintenance_mode(Some(
+        MaintenanceMode::new()
+            .message("back soon")
+            .retry_after(Duration::from_secs(30))
+            .exempt_path("/admin/*"),
+    ));
+    assert!(server.maintenance_mode().is_some());
+
+    let request = hyper::Request::builder()
+        .method(Method::GET)
+        .uri(client.url("/projects"))
+        .body(Body::empty())
+        .unwrap();
+    let response = client.client.request(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+    assert_eq!(
+        response.headers().get(http::header::RETRY_AFTER).unwrap(),
+        "30"
+    );
+
+    let error = client
+        .make_request_error(
+            Method::GET,
+            "/projects",
+            StatusCode::SERVICE_UNAVAILABLE,
+        )
+        .await;
+    assert_eq!(error.error_code.as_deref(), Some("Maintenance"));
+    assert_eq!(error.message, "back soon");
+
+    // Exempt endpoints and the health endpoints keep working.
+    client
+        .make_request_no_body(
+            Method::GET,
+            "/admin/status",
+            StatusCode::NO_CONTENT,
+        )
+        .await
+        .unwrap();
+    client
+        .make_request_no_body(Method::GET, "/healthz", StatusCode::OK)
+        .await
+        .unwrap();
+    client
+        .make_request_no_body(Method::GET, "/readyz", StatusCode::OK)
+        .await
+        .unwrap();
+
+    server.set_maintenance_mode(None);
+    client
+        .make_request_no_body(Method::GET, "/projects", StatusCode::NO_CONTENT)
+        .await
+        .unwrap();
+
+    testctx.teardown().await;
+}
@@ -0,0 +1,249 @@
+// Copyright 2023 Oxide Computer Company
+
+//! Test cases for the responses to requests whose methods have no handlers.
+
+use dropshot::endpoint;
+use dropshot::test_util::read_string;
+use dropshot::test_util::TestContext;
+use dropshot::ApiDescription;
+use dropshot::Body;
+use dropshot::ConfigDropshot;
+use dropshot::ConfigMethodOverride;
+use dropshot::HttpError;
+use dropshot::HttpResponseDeleted;
+use dropshot::HttpResponseOk;
+use dropshot::HttpResponseUpdatedNoContent;
+use dropshot::Path;
+use dropshot::RequestContext;
+use http::Method;
+use http::StatusCode;
+use hyper::Request;
+use schemars::JsonSchema;
+use serde::Deserialize;
+use slog::o;
+
+pub mod common;
+
+#[derive(Deserialize, JsonSchema)]
+struct WidgetPath {
+    name: String,
+}
+
+#[endpoint {
+    method = GET,
+    path = "/widgets/{name}",
+}]
+async fn widget_get(
+    _rqctx: RequestContext<usize>,
+    path: Path<WidgetPath>,
+) -> Result<HttpResponseOk<String>, HttpError> {
+    Ok(HttpResponseOk(path.into_inner().name))
+}
+
+#[endpoint {
+    method = DELETE,
+    path = "/widgets/{name}",
+}]
+async fn widget_delete(
+    _rqctx: RequestContext<usize>,
+    _path: Path<WidgetPath>,
+) -> Result<HttpResponseDeleted, HttpError> {
+    Ok(HttpResponseDeleted())
+}
+
+#[endpoint {
+    method = GET,
+    path = "/gadgets",
+}]
+async fn gadgets_get(
+    _rqctx: RequestContext<usize>,
+) -> Result<HttpResponseOk<Vec<String>>, HttpError> {
+    Ok(HttpResponseOk(vec![]))
+}
+
+/// An explicit OPTIONS handler, which takes precedence over Dropshot's own
+#[endpoint {
+    method = OPTIONS,
+    path = "/gadgets",
+}]
+async fn gadgets_options(
+    _rqctx: RequestContext<usize>,
+) -> Result<HttpResponseUpdatedNoContent, HttpError> {
+    Ok(HttpResponseUpdatedNoContent())
+}
+
+fn api() -> ApiDescription<usize> {
+    let mut api = ApiDescription::new();
+    api.register(widget_get).unwrap();
+    api.register(widget_delete).unwrap();
+    api.register(gadgets_get).unwrap();
+    api.register(gadgets_options).unwrap();
+    api
+}
+
+#[tokio::test]
+async fn test_options() {
+    let testctx = common::test_setup("options", api());
+    let client = &testctx.client_testctx;
+
+    let response = client
+        .make_request_no_body(
+            Method::OPTIONS,
+            "/widgets/sprocket",
+            StatusCode::NO_CONTENT,
+        )
+        .await
+        .unwrap();
+    assert_eq!(
+        response.headers().get(http::header::ALLOW).unwrap(),
+        "DELETE, GET, HEAD, OPTIONS"
+    );
+
+    let response = client
+        .make_request_no_body(
+            Method::OPTIONS,
+            "/gadgets",
+            StatusCode::NO_CONTENT,
+        )
+        .await
+        .unwrap();
+    assert!(response.headers().get(http::header::ALLOW).is_none());
+
+    client
+        .make_request_error(Method::OPTIONS, "/nothing", StatusCode::NOT_FOUND)
+        .await;
+
+    testctx.teardown().await;
+}
+
+#[tokio::test]
+async fn test_method_not_allowed() {
+    let testctx = common::test_setup("method_not_allowed", api());
+    let client = &testctx.client_testctx;
+
+    let request = Request::builder()
+        .method(Method::PUT)
+        .uri(client.url("/widgets/sprocket"))
+        .body(Body::empty())
+        .unwrap();
+    let response = client.client.request(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::METHOD_NOT_ALLOWED);
+    assert_eq!(
+        response.headers().get(http::header::ALLOW).unwrap(),
+        "DELETE, GET, HEAD, OPTIONS"
+    );
+
+    let error = client
+        .make_request_error(
+            Method::PUT,
+            "/widgets/sprocket",
+            StatusCode::METHOD_NOT_ALLOWED,
+        )
+        .await;
+    assert_eq!(
+        error.message,
+        "Method Not Allowed (allowed methods: DELETE, GET, HEAD, OPTIONS)"
+    );
+
+    testctx.teardown().await;
+}
+
+#[tokio::test]
+async fn test_method_not_allowed_as_not_found() {
+    let config = ConfigDropshot {
+        method_not_allowed_as_not_found: true,
+        ..Default::default()
+    };
+    let logctx = common::create_log_context("method_not_allowed_as_not_found");
+    let log = logctx.log.new(o!());
+    let testctx = TestContext::new(api(), 0_usize, &config, Some(logctx), log);
+    let client = &testctx.client_testctx;
+
+    let error = client
+        .make_request_error(
+            Method::PUT,
+            "/widgets/sprocket",
+            StatusCode::NOT_FOUND,
+        )
+        .await;
+    assert_eq!(error.message, "Not Found");
+
+    testctx.teardown().await;
+}
+
+#[tokio::test]
+async fn test_head() {
+    let testctx = common::test_setup("head", api());
+    let client = &testctx.client_testctx;
+
+    // HEAD requests are served by the GET handler, without the body.
+    let mut response = client
+        .make_request_no_body(Method::HEAD, "/widgets/sprocket", StatusCode::OK)
+        .await
+        .unwrap();
+    assert_eq!(
+        response.headers().get(http::header::CONTENT_LENGTH).unwrap(),
+        "\"sprocket\"".len().to_string().as_str()
+    );
+    assert_eq!(read_string(&mut response).await, "");
+
+    testctx.teardown().await;
+}
+
+#[tokio::test]
+async fn test_method_override() {
+    let config = ConfigDropshot {
+        method_override: Some(ConfigMethodOverride {
+            methods: vec![String::from("delete")],
+        }),
+        ..Default::default()
+    };
+    let logctx = common::create_log_context("method_override");
+    let log = logctx.log.new(o!());
+    let testctx = TestContext::new(api(), 0_usize, &config, Some(logctx), log);
+    let client = &testctx.client_testctx;
+
+    let request_with_override = |method: Method, method_override: &str| {
+        Request::builder()
+            .method(method)
+            .uri(client.url("/widgets/sprocket"))
+            .header("X-HTTP-Method-Override", method_override)
+            .body(Body::empty())
+            .unwrap()
+    };
+
+    // A POST request is handled as the method named by the header.
+    let response = client
+        .client
+        .request(request_with_override(Method::POST, "DELETE"))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::NO_CONTENT);
+
+    // Only the configured methods may be requested.
+    let response = client
+        .client
+        .request(request_with_override(Method::POST, "PUT"))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+
+    // The header is ignored on requests with other methods.
+    let response = client
+        .client
+        .request(request_with_override(Method::GET, "DELETE"))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+
+    // Without the header, a POST request is just a POST request.
+    client
+        .make_request_error(
+            Method::POST,
+            "/widgets/sprocket",
+            StatusCode::METHOD_NOT_ALLOWED,
+        )
+        .await;
+
+    testctx.teardown().await;
+}
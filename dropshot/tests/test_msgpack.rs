@@ -0,0 +1,70 @@
+// Copyright 2023 Oxide Computer Company
+
+//! Test cases for MessagePack request bodies.
+
+use dropshot::CONTENT_TYPE_MSGPACK;
+
+pub mod common;
+use common::reading::api;
+use common::reading::put_reading;
+use common::reading::put_reading_error;
+use common::reading::Reading;
+
+/// `{"sensor": "t1", "values": [-3, 300]}`
+const READING: &[u8] = &[
+    0x82, // map of 2
+    0xa6, b's', b'e', b'n', b's', b'o', b'r', // "sensor"
+    0xa2, b't', b'1', // "t1"
+    0xa6, b'v', b'a', b'l', b'u', b'e', b's', // "values"
+    0x92, 0xfd, 0xcd, 0x01, 0x2c, // [-3, 300]
+];
+
+#[tokio::test]
+async fn test_msgpack_body() {
+    let testctx =
+        common::test_setup("msgpack_body", api(&[CONTENT_TYPE_MSGPACK]));
+    let client = &testctx.client_testctx;
+
+    for content_type in [CONTENT_TYPE_MSGPACK, "application/x-msgpack"] {
+        assert_eq!(
+            put_reading(client, content_type, READING).await,
+            Reading::example()
+        );
+    }
+
+    // Structs may also be encoded as arrays of their fields' values, which is
+    // how rmp-serde encodes them by default.
+    let compact = rmp_serde::to_vec(&Reading::example()).unwrap();
+    assert_eq!(compact[0], 0x92);
+    assert_eq!(
+        put_reading(client, CONTENT_TYPE_MSGPACK, compact).await,
+        Reading::example()
+    );
+
+    // Malformed bodies (including those with anything after the value) and
+    // bodies of other types are rejected.
+    let trailing = [READING, &[0xc0]].concat();
+    for body in [&READING[..10], &trailing[..]] {
+        let message =
+            put_reading_error(client, CONTENT_TYPE_MSGPACK, body).await;
+        assert!(
+            message.starts_with("unable to parse MessagePack body: "),
+            "{}",
+            message
+        );
+    }
+    assert_eq!(
+        put_reading_error(client, "application/json", "{}").await,
+        "expected content type \"application/msgpack\", got \"application/json\""
+    );
+
+    testctx.teardown().await;
+}
+
+#[test]
+fn test_msgpack_openapi() {
+    let spec =
+        api(&[CONTENT_TYPE_MSGPACK]).openapi("test", "1.0").json().unwrap();
+    let content = &spec["paths"]["/reading"]["put"]["requestBody"]["content"];
+    assert!(content.get(CONTENT_TYPE_MSGPACK).is_some(), "{}", content);
+}
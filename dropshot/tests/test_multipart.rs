@@ -0,0 +1,258 @@
+// Copyright 2023 Oxide Computer Company
+
+//! Test cases for streaming multipart/form-data request bodies.
+
+use dropshot::endpoint;
+use dropshot::test_util::read_json;
+use dropshot::test_util::TestContext;
+use dropshot::ApiDescription;
+use dropshot::Body;
+use dropshot::ConfigDropshot;
+use dropshot::HttpError;
+use dropshot::HttpResponseOk;
+use dropshot::MultipartBody;
+use dropshot::RequestContext;
+use futures::TryStreamExt;
+use http::Method;
+use http::StatusCode;
+use hyper::Request;
+use schemars::JsonSchema;
+use serde::Deserialize;
+use serde::Serialize;
+
+pub mod common;
+
+#[derive(Debug, Deserialize, JsonSchema, PartialEq, Serialize)]
+struct PartSummary {
+    name: Option<String>,
+    file_name: Option<String>,
+    content_type: Option<String>,
+    nchunks: usize,
+    nbytes: usize,
+}
+
+#[endpoint {
+    method = POST,
+    path = "/upload",
+}]
+async fn upload(
+    _rqctx: RequestContext<usize>,
+    mut body: MultipartBody,
+) -> Result<HttpResponseOk<Vec<PartSummary>>, HttpError> {
+    let mut summaries = Vec::new();
+    while let Some(mut part) = body.next_part().await? {
+        let mut summary = PartSummary {
+            name: part.name().map(String::from),
+            file_name: part.file_name().map(String::from),
+            content_type: part.content_type().map(String::from),
+            nchunks: 0,
+            nbytes: 0,
+        };
+        while let Some(chunk) = part.chunk().await? {
+            summary.nchunks += 1;
+            summary.nbytes += chunk.len();
+        }
+        summaries.push(summary);
+    }
+    Ok(HttpResponseOk(summaries))
+}
+
+#[endpoint {
+    method = POST,
+    path = "/upload-stream",
+}]
+async fn upload_stream(
+    _rqctx: RequestContext<usize>,
+    body: MultipartBody,
+) -> Result<HttpResponseOk<Vec<usize>>, HttpError> {
+    let sizes =
+        body.into_stream()
+            .and_then(|part| {
+                part.try_fold(0, |total, chunk| async move {
+                    Ok(total + chunk.len())
+                })
+            })
+            .try_collect()
+            .await?;
+    Ok(HttpResponseOk(sizes))
+}
+
+#[derive(Debug, Deserialize, JsonSchema, Serialize)]
+struct BufferedParts {
+    description: String,
+    file_bytes: usize,
+}
+
+#[endpoint {
+    method = POST,
+    path = "/upload-buffered",
+}]
+async fn upload_buffered(
+    _rqctx: RequestContext<usize>,
+    mut body: MultipartBody,
+) -> Result<HttpResponseOk<BufferedParts>, HttpError> {
+    let description = body.next_part().await?.unwrap().text().await?;
+    let file_bytes = body.next_part().await?.unwrap().bytes().await?.len();
+    Ok(HttpResponseOk(BufferedParts { description, file_bytes }))
+}
+
+fn setup(test_name: &str) -> TestContext<usize> {
+    let mut api = ApiDescription::new();
+    api.register(upload).unwrap();
+    api.register(upload_stream).unwrap();
+    api.register(upload_buffered).unwrap();
+    let config = ConfigDropshot {
+        request_body_max_bytes: 1024,
+        multipart_part_max_bytes: Some(600),
+        ..Default::default()
+    };
+    common::test_setup_with_config(test_name, api, &config)
+}
+
+const BOUNDARY: &str = "dropshot-test-boundary";
+
+fn multipart_body(file_size: usize) -> String {
+    format!(
+        "--{b}\r\n\
+         Content-Disposition: form-data; name=\"description\"\r\n\
+         \r\n\
+         a picture of a cat\r\n\
+         --{b}\r\n\
+         Content-Disposition: form-data; name=\"file\"; filename=\"cat.txt\"\r\n\
+         Content-Type: text/plain\r\n\
+         \r\n\
+         {file}\r\n\
+         --{b}--\r\n",
+        b = BOUNDARY,
+        file = "x".repeat(file_size),
+    )
+}
+
+fn multipart_request(
+    testctx: &TestContext<usize>,
+    path: &str,
+    body: String,
+) -> Request<Body> {
+    Request::builder()
+        .method(Method::POST)
+        .uri(testctx.client_testctx.url(path))
+        .header(
+            http::header::CONTENT_TYPE,
+            format!("multipart/form-data; boundary={}", BOUNDARY),
+        )
+        .body(Body::from(body))
+        .unwrap()
+}
+
+#[tokio::test]
+async fn test_multipart_parts() {
+    let testctx = setup("multipart_parts");
+    let client = &testctx.client_testctx;
+
+    let mut response = client
+        .make_request_with_request(
+            multipart_request(&testctx, "/upload", multipart_body(500)),
+            StatusCode::OK,
+        )
+        .await
+        .unwrap();
+    let summaries: Vec<PartSummary> = read_json(&mut response).await;
+    assert_eq!(summaries.len(), 2);
+    assert_eq!(summaries[0].name.as_deref(), Some("description"));
+    assert_eq!(summaries[0].file_name, None);
+    assert_eq!(summaries[0].content_type, None);
+    assert_eq!(summaries[0].nbytes, "a picture of a cat".len());
+    assert_eq!(summaries[1].name.as_deref(), Some("file"));
+    assert_eq!(summaries[1].file_name.as_deref(), Some("cat.txt"));
+    assert_eq!(summaries[1].content_type.as_deref(), Some("text/plain"));
+    assert_eq!(summaries[1].nbytes, 500);
+    assert!(summaries[1].nchunks >= 1);
+
+    let mut response = client
+        .make_request_with_request(
+            multipart_request(&testctx, "/upload-stream", multipart_body(500)),
+            StatusCode::OK,
+        )
+        .await
+        .unwrap();
+    let sizes: Vec<usize> = read_json(&mut response).await;
+    assert_eq!(sizes, vec!["a picture of a cat".len(), 500]);
+
+    let mut response = client
+        .make_request_with_request(
+            multipart_request(
+                &testctx,
+                "/upload-buffered",
+                multipart_body(500),
+            ),
+            StatusCode::OK,
+        )
+        .await
+        .unwrap();
+    let parts: BufferedParts = read_json(&mut response).await;
+    assert_eq!(parts.description, "a picture of a cat");
+    assert_eq!(parts.file_bytes, 500);
+
+    testctx.teardown().await;
+}
+
+#[tokio::test]
+async fn test_multipart_limits() {
+    let testctx = setup("multipart_limits");
+    let client = &testctx.client_testctx;
+
+    let error = client
+        .make_request_with_request(
+            multipart_request(&testctx, "/upload", multipart_body(700)),
+            StatusCode::BAD_REQUEST,
+        )
+        .await
+        .unwrap_err();
+    assert_eq!(
+        error.message,
+        "part \"file\" exceeded maximum size of 600 bytes"
+    );
+
+    let body = format!(
+        "{}--{}\r\n\
+         Content-Disposition: form-data; name=\"extra\"\r\n\
+         \r\n\
+         {}\r\n\
+         --{}--\r\n",
+        multipart_body(500).trim_end_matches(&format!("--{}--\r\n", BOUNDARY)),
+        BOUNDARY,
+        "y".repeat(500),
+        BOUNDARY,
+    );
+    let error = client
+        .make_request_with_request(
+            multipart_request(&testctx, "/upload", body),
+            StatusCode::BAD_REQUEST,
+        )
+        .await
+        .unwrap_err();
+    assert_eq!(
+        error.message,
+        "request body exceeded maximum size of 1024 bytes"
+    );
+
+    let error = client
+        .make_request_with_request(
+            Request::builder()
+                .method(Method::POST)
+                .uri(client.url("/upload"))
+                .header(http::header::CONTENT_TYPE, "application/json")
+                .body(Body::from("{}"))
+                .unwrap(),
+            StatusCode::BAD_REQUEST,
+        )
+        .await
+        .unwrap_err();
+    assert_eq!(
+        error.message,
+        "expected content type \"multipart/form-data\" with a boundary, got \
+         \"application/json\""
+    );
+
+    testctx.teardown().await;
+}
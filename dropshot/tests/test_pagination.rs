@@ -10,6 +10,7 @@ use dropshot::test_util::objects_list_page;
 use dropshot::test_util::ClientTestContext;
 use dropshot::test_util::LogContext;
 use dropshot::ApiDescription;
+use dropshot::Body;
 use dropshot::ConfigLogging;
 use dropshot::ConfigLoggingIfExists;
 use dropshot::ConfigLoggingLevel;
@@ -24,9 +25,9 @@ use dropshot::ResultsPage;
 use dropshot::WhichPage;
 use http::Method;
 use http::StatusCode;
-use hyper::Body;
-use hyper::Client;
 use hyper::Request;
+use hyper_util::client::legacy::Client;
+use hyper_util::rt::TokioExecutor;
 use schemars::JsonSchema;
 use serde::de::DeserializeOwned;
 use serde::Deserialize;
@@ -836,7 +837,7 @@ async fn start_example(path: &str, port: u16) -> ExampleContext {
     let server_addr = SocketAddr::from((Ipv4Addr::LOCALHOST, port));
     let client = ClientTestContext::new(server_addr, logctx.log.new(o!()));
     let url = client.url("/");
-    let raw_client = Client::new();
+    let raw_client = Client::builder(TokioExecutor::new()).build_http();
     let rv = ExampleContext { child, client, logctx: Some(logctx) };
 
     while start.elapsed().as_secs() < 10 {
@@ -0,0 +1,152 @@
+// Copyright 2023 Oxide Computer Company
+
+//! Test cases for path parameter structs that derive `PathParams`.
+
+use dropshot::endpoint;
+use dropshot::test_util::read_json;
+use dropshot::ApiDescription;
+use dropshot::HttpError;
+use dropshot::HttpResponseOk;
+use dropshot::Path;
+use dropshot::PathParams;
+use dropshot::RequestContext;
+use http::Method;
+use http::StatusCode;
+use schemars::JsonSchema;
+use serde::Deserialize;
+use serde::Serialize;
+use std::net::Ipv4Addr;
+
+pub mod common;
+
+#[derive(JsonSchema, PathParams)]
+struct HostPath {
+    #[serde(rename = "type")]
+    kind: String,
+    addr: Ipv4Addr,
+    port: u16,
+    rest: Vec<String>,
+}
+
+#[derive(Debug, Deserialize, PartialEq, Serialize)]
+struct Host {
+    kind: String,
+    addr: Ipv4Addr,
+    port: u16,
+    rest: Vec<String>,
+}
+
+#[endpoint {
+    method = GET,
+    path = "/hosts/{type}/{addr}/{port}/{rest:.*}",
+    unpublished = true,
+}]
+async fn host_get(
+    _rqctx: RequestContext<usize>,
+    path: Path<HostPath>,
+) -> Result<HttpResponseOk<Host>, HttpError> {
+    let HostPath { kind, addr, port, rest } = path.into_inner();
+    Ok(HttpResponseOk(Host { kind, addr, port, rest }))
+}
+
+fn api() -> ApiDescription<usize> {
+    let mut api = ApiDescription::new();
+    api.register(host_get).unwrap();
+    api
+}
+
+#[tokio::test]
+async fn test_path_params_derive() {
+    let testctx = common::test_setup("path_params_derive", api());
+    let client = &testctx.client_testctx;
+
+    let mut response = client
+        .make_request_no_body(
+            Method::GET,
+            "/hosts/web/10.0.0.1/8080/a/b",
+            StatusCode::OK,
+        )
+        .await
+        .unwrap();
+    let host: Host = read_json(&mut response).await;
+    assert_eq!(
+        host,
+        Host {
+            kind: String::from("web"),
+            addr: Ipv4Addr::new(10, 0, 0, 1),
+            port: 8080,
+            rest: vec![String::from("a"), String::from("b")],
+        }
+    );
+
+    // Errors name the offending parameter.
+    let error = client
+        .make_request_no_body(
+            Method::GET,
+            "/hosts/web/10.0.0.1/http",
+            StatusCode::BAD_REQUEST,
+        )
+        .await
+        .unwrap_err();
+    assert_eq!(
+        error.message,
+        "bad parameter in URL path: invalid value \"http\" for parameter \
+         \"port\": invalid digit found in string"
+    );
+
+    let error = client
+        .make_request_no_body(
+            Method::GET,
+            "/hosts/web/localhost/80",
+            StatusCode::BAD_REQUEST,
+        )
+        .await
+        .unwrap_err();
+    assert!(
+        error.message.starts_with(
+            "bad parameter in URL path: invalid value \"localhost\" for \
+             parameter \"addr\": "
+        ),
+        "{}",
+        error.message
+    );
+
+    testctx.teardown().await;
+}
+
+#[test]
+fn test_path_params_derive_openapi() {
+    // The parameters are described by the `JsonSchema` implementation, which
+    // uses the same (renamed) names.
+    let mut api = ApiDescription::<()>::new();
+
+    #[derive(JsonSchema, PathParams)]
+    #[allow(dead_code)]
+    struct ItemPath {
+        #[serde(rename = "type")]
+        kind: String,
+        id: u64,
+    }
+
+    #[endpoint {
+        method = GET,
+        path = "/items/{type}/{id}",
+    }]
+    async fn item_get(
+        _rqctx: RequestContext<()>,
+        _path: Path<ItemPath>,
+    ) -> Result<HttpResponseOk<()>, HttpError> {
+        Ok(HttpResponseOk(()))
+    }
+
+    api.register(item_get).unwrap();
+    let spec = api.openapi("test", "1.0").json().unwrap();
+    let mut names = spec["paths"]["/items/{type}/{id}"]["get"]["parameters"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|parameter| parameter["name"].as_str().unwrap().to_string())
+        .collect::<Vec<_>>();
+    names.sort();
+    assert_eq!(names, vec!["id", "type"]);
+}
@@ -0,0 +1,103 @@
+// Copyright 2023 Oxide Computer Company
+
+//! Test cases for path variables constrained by patterns.
+
+use dropshot::endpoint;
+use dropshot::test_util::read_json;
+use dropshot::ApiDescription;
+use dropshot::HttpError;
+use dropshot::HttpResponseOk;
+use dropshot::Path;
+use dropshot::RequestContext;
+use http::Method;
+use http::StatusCode;
+use schemars::JsonSchema;
+use serde::Deserialize;
+use serde::Serialize;
+
+pub mod common;
+
+#[derive(Debug, Deserialize, JsonSchema, PartialEq, Serialize)]
+struct ItemPath {
+    project: String,
+    item: String,
+}
+
+#[endpoint {
+    method = GET,
+    path = "/projects/{project:uuid}/items/{item:[a-z][a-z0-9-]*}",
+}]
+async fn item_get(
+    _rqctx: RequestContext<usize>,
+    path: Path<ItemPath>,
+) -> Result<HttpResponseOk<ItemPath>, HttpError> {
+    Ok(HttpResponseOk(path.into_inner()))
+}
+
+fn api() -> ApiDescription<usize> {
+    let mut api = ApiDescription::new();
+    api.register(item_get).unwrap();
+    api
+}
+
+const PROJECT: &str = "3d4b2b0e-9d5c-4f1a-8c6e-2b7f1a0c9e8d";
+
+#[tokio::test]
+async fn test_path_patterns() {
+    let testctx = common::test_setup("path_patterns", api());
+    let client = &testctx.client_testctx;
+
+    let mut response = client
+        .make_request_no_body(
+            Method::GET,
+            &format!("/projects/{}/items/item-1", PROJECT),
+            StatusCode::OK,
+        )
+        .await
+        .unwrap();
+    let path: ItemPath = read_json(&mut response).await;
+    assert_eq!(
+        path,
+        ItemPath {
+            project: String::from(PROJECT),
+            item: String::from("item-1")
+        }
+    );
+
+    // Segments that don't match their variable's pattern don't match the
+    // route.
+    for uri in [
+        format!("/projects/{}/items/item-1", "default"),
+        format!("/projects/{}/items/{}", PROJECT, "1-item"),
+        format!("/projects/{}/items/{}", PROJECT, "Item"),
+    ] {
+        let error = client
+            .make_request_no_body(Method::GET, &uri, StatusCode::NOT_FOUND)
+            .await
+            .unwrap_err();
+        assert_eq!(error.message, "Not Found");
+    }
+
+    testctx.teardown().await;
+}
+
+#[test]
+fn test_path_patterns_openapi() {
+    let spec = api().openapi("test", "1.0").json().unwrap();
+    let operation = &spec["paths"]["/projects/{project}/items/{item}"]["get"];
+    let parameter = |name: &str| {
+        operation["parameters"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .find(|parameter| parameter["name"] == name)
+            .unwrap()
+            .clone()
+    };
+    let project = parameter("project");
+    assert_eq!(project["schema"]["format"], "uuid");
+    assert!(project["schema"].get("pattern").is_none());
+    let item = parameter("item");
+    assert_eq!(item["schema"]["pattern"], "^(?:[a-z][a-z0-9-]*)$");
+    assert!(item["schema"].get("format").is_none());
+}
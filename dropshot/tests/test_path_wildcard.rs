@@ -0,0 +1,138 @@
+// Copyright 2023 Oxide Computer Company
+
+//! Test cases for path variables that match the rest of the path.
+
+use dropshot::endpoint;
+use dropshot::test_util::read_json;
+use dropshot::ApiDescription;
+use dropshot::HttpError;
+use dropshot::HttpResponseOk;
+use dropshot::Path;
+use dropshot::RequestContext;
+use http::Method;
+use http::StatusCode;
+use schemars::JsonSchema;
+use serde::Deserialize;
+use serde::Serialize;
+
+pub mod common;
+
+#[derive(Debug, Deserialize, JsonSchema, PartialEq, Serialize)]
+struct SegmentsPath {
+    path: Vec<String>,
+}
+
+#[endpoint {
+    method = GET,
+    path = "/segments/{path:.*}",
+    unpublished = true,
+}]
+async fn segments_get(
+    _rqctx: RequestContext<usize>,
+    path: Path<SegmentsPath>,
+) -> Result<HttpResponseOk<SegmentsPath>, HttpError> {
+    Ok(HttpResponseOk(path.into_inner()))
+}
+
+#[derive(Debug, Deserialize, JsonSchema, PartialEq, Serialize)]
+struct RawPath {
+    path: String,
+}
+
+#[endpoint {
+    method = GET,
+    path = "/raw/{path:.*}",
+    unpublished = true,
+}]
+async fn raw_get(
+    _rqctx: RequestContext<usize>,
+    path: Path<RawPath>,
+) -> Result<HttpResponseOk<RawPath>, HttpError> {
+    Ok(HttpResponseOk(path.into_inner()))
+}
+
+fn api() -> ApiDescription<usize> {
+    let mut api = ApiDescription::new();
+    api.register(segments_get).unwrap();
+    api.register(raw_get).unwrap();
+    api
+}
+
+#[tokio::test]
+async fn test_path_wildcard() {
+    let testctx = common::test_setup("path_wildcard", api());
+    let client = &testctx.client_testctx;
+
+    let mut response = client
+        .make_request_no_body(
+            Method::GET,
+            "/segments/docs//a%20b/c%3Fd",
+            StatusCode::OK,
+        )
+        .await
+        .unwrap();
+    let path: SegmentsPath = read_json(&mut response).await;
+    assert_eq!(path.path, vec!["docs", "a b", "c?d"]);
+
+    let mut response = client
+        .make_request_no_body(Method::GET, "/segments", StatusCode::OK)
+        .await
+        .unwrap();
+    let path: SegmentsPath = read_json(&mut response).await;
+    assert!(path.path.is_empty());
+
+    let mut response = client
+        .make_request_no_body(
+            Method::GET,
+            "/raw/docs//a%20b/c%3Fd",
+            StatusCode::OK,
+        )
+        .await
+        .unwrap();
+    let path: RawPath = read_json(&mut response).await;
+    assert_eq!(path.path, "docs/a%20b/c%3Fd");
+
+    // Segments that would mean something else once joined back together are
+    // rejected.
+    for uri in ["/segments/a/%2e%2e/b", "/segments/a%2Fb", "/raw/a%00"] {
+        let error = client
+            .make_request_no_body(Method::GET, uri, StatusCode::BAD_REQUEST)
+            .await
+            .unwrap_err();
+        assert!(
+            error.message.ends_with("is not permitted here"),
+            "{}",
+            error.message
+        );
+    }
+
+    testctx.teardown().await;
+}
+
+#[derive(Deserialize, JsonSchema)]
+#[allow(dead_code)]
+struct NumbersPath {
+    path: Vec<u32>,
+}
+
+#[endpoint {
+    method = GET,
+    path = "/numbers/{path:.*}",
+    unpublished = true,
+}]
+async fn numbers_get(
+    _rqctx: RequestContext<()>,
+    _path: Path<NumbersPath>,
+) -> Result<HttpResponseOk<()>, HttpError> {
+    Ok(HttpResponseOk(()))
+}
+
+#[test]
+fn test_path_wildcard_bad_type() {
+    let mut api = ApiDescription::new();
+    let error = api.register(numbers_get).unwrap_err();
+    assert_eq!(
+        error,
+        "the parameter 'path' must be an array of strings or a string"
+    );
+}
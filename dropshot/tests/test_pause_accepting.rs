@@ -0,0 +1,79 @@
+// Copyright 2023 Oxide Computer Company
+
+//! Test cases for pausing and resuming the acceptance of new connections.
+
+use dropshot::endpoint;
+use dropshot::test_util::TestContext;
+use dropshot::ApiDescription;
+use dropshot::HttpError;
+use dropshot::HttpResponseOk;
+use dropshot::RequestContext;
+use slog::o;
+use std::time::Duration;
+use tokio::io::AsyncReadExt;
+use tokio::io::AsyncWriteExt;
+use tokio::net::TcpStream;
+
+pub mod common;
+
+#[endpoint {
+    method = GET,
+    path = "/",
+}]
+async fn index(
+    _rqctx: RequestContext<usize>,
+) -> Result<HttpResponseOk<u32>, HttpError> {
+    Ok(HttpResponseOk(1))
+}
+
+const REQUEST: &[u8] =
+    b"GET / HTTP/1.1\r\nHost: a\r\nConnection: close\r\n\r\n";
+
+/// Reads everything the server sends on `stream` until it closes it.
+async fn read_response(mut stream: TcpStream) -> String {
+    let mut response = Vec::new();
+    stream.read_to_end(&mut response).await.unwrap();
+    String::from_utf8_lossy(&response).into_owned()
+}
+
+#[tokio::test]
+async fn test_pause_accepting() {
+    let mut api = ApiDescription::new();
+    api.register(index).unwrap();
+    let logctx = common::create_log_context("pause_accepting");
+    let log = logctx.log.new(o!());
+    let testctx =
+        TestContext::new(api, 0_usize, &Default::default(), Some(logctx), log);
+    let server = &testctx.server;
+    let addr = server.local_addr();
+    assert!(server.is_accepting());
+
+    // A connection that's already open keeps being served while accepting is
+    // paused.
+    let mut open = TcpStream::connect(addr).await.unwrap();
+    open.write_all(b"GET / HTTP/1.1\r\nHost: a\r\n\r\n").await.unwrap();
+    let mut buf = [0; 1024];
+    assert!(open.read(&mut buf).await.unwrap() > 0);
+
+    server.pause_accepting();
+    assert!(!server.is_accepting());
+    open.write_all(REQUEST).await.unwrap();
+    assert!(read_response(open).await.contains("200 OK"));
+
+    // A new connection waits in the listener's backlog without being served.
+    let mut waiting = TcpStream::connect(addr).await.unwrap();
+    waiting.write_all(REQUEST).await.unwrap();
+    let mut response = tokio::spawn(read_response(waiting));
+    assert!(tokio::time::timeout(Duration::from_millis(500), &mut response)
+        .await
+        .is_err());
+
+    // Once accepting resumes, it's served.
+    server.resume_accepting();
+    assert!(server.is_accepting());
+    let response =
+        tokio::time::timeout(Duration::from_secs(10), response).await.unwrap();
+    assert!(response.unwrap().starts_with("HTTP/1.1 200 OK"));
+
+    testctx.teardown().await;
+}
@@ -0,0 +1,235 @@
+// Copyright 2023 Oxide Computer Company
+
+//! Test cases for the `Preconditions` extractor and `HttpResponseConditional`.
+
+use chrono::TimeZone;
+use chrono::Utc;
+use dropshot::endpoint;
+use dropshot::ApiDescription;
+use dropshot::Body;
+use dropshot::EntityTag;
+use dropshot::HttpError;
+use dropshot::HttpResponseConditional;
+use dropshot::HttpResponseOk;
+use dropshot::HttpResponseUpdatedNoContent;
+use dropshot::Preconditions;
+use dropshot::RequestContext;
+use http::Method;
+use http::StatusCode;
+use http_body_util::BodyExt;
+use hyper::Request;
+
+pub mod common;
+
+const WIDGET_ETAG: &str = "v1";
+
+#[endpoint {
+    method = PUT,
+    path = "/widget",
+}]
+async fn widget_put(
+    _rqctx: RequestContext<usize>,
+    preconditions: Preconditions,
+) -> Result<HttpResponseUpdatedNoContent, HttpError> {
+    preconditions.require()?;
+    preconditions.check(Some(WIDGET_ETAG), None)?;
+    Ok(HttpResponseUpdatedNoContent())
+}
+
+#[endpoint {
+    method = GET,
+    path = "/widget",
+}]
+async fn widget_get(
+    _rqctx: RequestContext<usize>,
+    preconditions: Preconditions,
+) -> Result<HttpResponseConditional<HttpResponseOk<String>>, HttpError> {
+    let last_modified = Utc.with_ymd_and_hms(2015, 10, 21, 7, 28, 0).unwrap();
+    Ok(preconditions.respond(
+        Some(EntityTag::strong(WIDGET_ETAG)),
+        Some(last_modified),
+        || HttpResponseOk(String::from("sprocket")),
+    ))
+}
+
+fn api() -> ApiDescription<usize> {
+    let mut api = ApiDescription::new();
+    api.register(widget_put).unwrap();
+    api.register(widget_get).unwrap();
+    api
+}
+
+#[test]
+fn test_preconditions_openapi() {
+    let spec = api().openapi("test", "1.0").json().unwrap();
+    let parameters = spec["paths"]["/widget"]["put"]["parameters"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|p| {
+            (
+                p["in"].as_str().unwrap(),
+                p["name"].as_str().unwrap(),
+                p["required"].as_bool().unwrap_or(false),
+            )
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(
+        parameters,
+        vec![
+            ("header", "If-Match", false),
+            ("header", "If-None-Match", false),
+            ("header", "If-Modified-Since", false),
+            ("header", "If-Unmodified-Since", false)
+        ]
+    );
+
+    let responses = &spec["paths"]["/widget"]["get"]["responses"];
+    assert!(responses["200"]["headers"]["ETag"].is_object());
+    assert!(responses["200"]["headers"]["Last-Modified"].is_object());
+    assert_eq!(responses["304"]["description"], "resource not modified");
+    assert!(responses["304"]["content"].is_null());
+    assert!(responses["304"]["headers"]["ETag"].is_object());
+    assert_eq!(responses["412"]["description"], "precondition failed");
+    assert_eq!(
+        responses["412"]["content"]["application/json"]["schema"]["$ref"],
+        "#/components/schemas/Error"
+    );
+}
+
+#[tokio::test]
+async fn test_preconditions() {
+    let testctx = common::test_setup("preconditions", api());
+    let client = &testctx.client_testctx;
+
+    let error = client
+        .make_request_error(
+            Method::PUT,
+            "/widget",
+            StatusCode::PRECONDITION_REQUIRED,
+        )
+        .await;
+    assert_eq!(error.error_code.as_deref(), Some("PreconditionRequired"));
+
+    let put_if_match = |value: &str| {
+        Request::builder()
+            .method(Method::PUT)
+            .uri(client.url("/widget"))
+            .header(http::header::IF_MATCH, value)
+            .body(Body::empty())
+            .unwrap()
+    };
+
+    let error = client
+        .make_request_with_request(
+            put_if_match(r#""v0""#),
+            StatusCode::PRECONDITION_FAILED,
+        )
+        .await
+        .unwrap_err();
+    assert_eq!(error.error_code.as_deref(), Some("PreconditionFailed"));
+
+    client
+        .make_request_with_request(
+            put_if_match(r#""v0", "v1""#),
+            StatusCode::NO_CONTENT,
+        )
+        .await
+        .unwrap();
+    client
+        .make_request_with_request(put_if_match("*"), StatusCode::NO_CONTENT)
+        .await
+        .unwrap();
+
+    let error = client
+        .make_request_with_request(put_if_match("v1"), StatusCode::BAD_REQUEST)
+        .await
+        .unwrap_err();
+    assert_eq!(error.message, "invalid entity tag in If-Match header: v1");
+
+    testctx.teardown().await;
+}
+
+#[tokio::test]
+async fn test_conditional_get() {
+    let testctx = common::test_setup("conditional_get", api());
+    let client = &testctx.client_testctx;
+
+    let get_with = |name: http::header::HeaderName, value: &str| {
+        Request::builder()
+            .method(Method::GET)
+            .uri(client.url("/widget"))
+            .header(name, value)
+            .body(Body::empty())
+            .unwrap()
+    };
+
+    let mut response = client
+        .make_request_no_body(Method::GET, "/widget", StatusCode::OK)
+        .await
+        .unwrap();
+    let headers = response.headers();
+    assert_eq!(headers.get(http::header::ETAG).unwrap(), "\"v1\"");
+    assert_eq!(
+        headers.get(http::header::LAST_MODIFIED).unwrap(),
+        "Wed, 21 Oct 2015 07:28:00 GMT"
+    );
+    let body = response.body_mut().collect().await.unwrap().to_bytes();
+    assert_eq!(body.as_ref(), b"\"sprocket\"");
+
+    // If-None-Match uses the weak comparison function.
+    for value in [r#""v0", "v1""#, r#"W/"v1""#, "*"] {
+        let mut response = client
+            .make_request_with_request(
+                get_with(http::header::IF_NONE_MATCH, value),
+                StatusCode::NOT_MODIFIED,
+            )
+            .await
+            .unwrap();
+        assert_eq!(
+            response.headers().get(http::header::ETAG).unwrap(),
+            "\"v1\""
+        );
+        let body = response.body_mut().collect().await.unwrap().to_bytes();
+        assert!(body.is_empty());
+    }
+    client
+        .make_request_with_request(
+            get_with(http::header::IF_NONE_MATCH, r#""v0""#),
+            StatusCode::OK,
+        )
+        .await
+        .unwrap();
+
+    client
+        .make_request_with_request(
+            get_with(
+                http::header::IF_MODIFIED_SINCE,
+                "Wed, 21 Oct 2015 07:28:00 GMT",
+            ),
+            StatusCode::NOT_MODIFIED,
+        )
+        .await
+        .unwrap();
+    client
+        .make_request_with_request(
+            get_with(
+                http::header::IF_MODIFIED_SINCE,
+                "Tue, 20 Oct 2015 07:28:00 GMT",
+            ),
+            StatusCode::OK,
+        )
+        .await
+        .unwrap();
+
+    let error = client
+        .make_request_with_request(
+            get_with(http::header::IF_MATCH, r#""v0""#),
+            StatusCode::PRECONDITION_FAILED,
+        )
+        .await
+        .unwrap_err();
+    assert_eq!(error.error_code.as_deref(), Some("PreconditionFailed"));
+
+    testctx.teardown().await;
+}
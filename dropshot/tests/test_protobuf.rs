@@ -0,0 +1,107 @@
+// Copyright 2023 Oxide Computer Company
+
+//! Test cases for Protobuf request and response bodies.
+
+#![cfg(feature = "protobuf")]
+
+use dropshot::endpoint;
+use dropshot::ApiDescription;
+use dropshot::HttpError;
+use dropshot::HttpResponseOk;
+use dropshot::Protobuf;
+use dropshot::ProtobufBody;
+use dropshot::RequestContext;
+use dropshot::CONTENT_TYPE_PROTOBUF;
+use http::StatusCode;
+use http_body_util::BodyExt;
+use prost::Message;
+
+pub mod common;
+
+use common::reading::put_reading_error;
+use common::reading::request;
+
+#[derive(Clone, PartialEq, prost::Message)]
+struct Reading {
+    #[prost(string, tag = "1")]
+    sensor: String,
+    #[prost(sint32, repeated, tag = "2")]
+    values: Vec<i32>,
+}
+
+impl prost::Name for Reading {
+    const NAME: &'static str = "Reading";
+    const PACKAGE: &'static str = "telemetry";
+}
+
+#[endpoint {
+    method = PUT,
+    path = "/reading",
+}]
+async fn reading_put(
+    _rqctx: RequestContext<usize>,
+    body: ProtobufBody<Reading>,
+) -> Result<HttpResponseOk<Protobuf<Reading>>, HttpError> {
+    let mut reading = body.into_inner();
+    reading.values.reverse();
+    Ok(HttpResponseOk(Protobuf(reading)))
+}
+
+fn api() -> ApiDescription<usize> {
+    let mut api = ApiDescription::new();
+    api.register(reading_put).unwrap();
+    api
+}
+
+#[tokio::test]
+async fn test_protobuf_bodies() {
+    let testctx = common::test_setup("protobuf_bodies", api());
+    let client = &testctx.client_testctx;
+
+    let reading = Reading { sensor: String::from("t1"), values: vec![-3, 300] };
+    let mut response = client
+        .make_request_with_request(
+            request(client, CONTENT_TYPE_PROTOBUF, reading.encode_to_vec()),
+            StatusCode::OK,
+        )
+        .await
+        .unwrap();
+    assert_eq!(
+        response.headers().get(http::header::CONTENT_TYPE).unwrap(),
+        CONTENT_TYPE_PROTOBUF
+    );
+    let body = response.body_mut().collect().await.unwrap().to_bytes();
+    assert_eq!(
+        Reading::decode(body).unwrap(),
+        Reading { sensor: String::from("t1"), values: vec![300, -3] }
+    );
+
+    // A truncated message can't be decoded.
+    let encoded = reading.encode_to_vec();
+    let message =
+        put_reading_error(client, CONTENT_TYPE_PROTOBUF, &encoded[..3]).await;
+    assert!(message.starts_with("unable to parse Protobuf body: "));
+
+    assert_eq!(
+        put_reading_error(client, "application/json", "{}").await,
+        "expected content type \"application/x-protobuf\", got \
+         \"application/json\""
+    );
+
+    testctx.teardown().await;
+}
+
+#[test]
+fn test_protobuf_openapi() {
+    let spec = api().openapi("test", "1.0").json().unwrap();
+    let operation = &spec["paths"]["/reading"]["put"];
+    for content in [
+        &operation["requestBody"]["content"],
+        &operation["responses"]["200"]["content"],
+    ] {
+        let schema = &content[CONTENT_TYPE_PROTOBUF]["schema"];
+        assert_eq!(schema["type"], "string");
+        assert_eq!(schema["format"], "binary");
+        assert_eq!(schema["x-protobuf-message"], "telemetry.Reading");
+    }
+}
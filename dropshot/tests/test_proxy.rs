@@ -0,0 +1,235 @@
+// Copyright 2023 Oxide Computer Company
+
+//! Test cases for forwarding requests with `dropshot::proxy`.
+
+use dropshot::endpoint;
+use dropshot::proxy::Proxy;
+use dropshot::test_util::read_json;
+use dropshot::test_util::TestContext;
+use dropshot::ApiDescription;
+use dropshot::Body;
+use dropshot::HttpError;
+use dropshot::HttpResponseOk;
+use dropshot::Path;
+use dropshot::RawRequest;
+use dropshot::RequestContext;
+use dropshot::UntypedBody;
+use http::Method;
+use http::StatusCode;
+use hyper::Response;
+use hyper_util::client::legacy::Client;
+use hyper_util::rt::TokioExecutor;
+use schemars::JsonSchema;
+use serde::Deserialize;
+use serde::Serialize;
+use slog::o;
+
+pub mod common;
+
+#[derive(Debug, Deserialize, JsonSchema, Serialize)]
+struct EchoResult {
+    uri: String,
+    forwarded_for: Option<String>,
+    forwarded_host: Option<String>,
+    forwarded_proto: Option<String>,
+    hop_by_hop: Option<String>,
+    body: String,
+}
+
+#[endpoint {
+    method = POST,
+    path = "/echo",
+}]
+async fn upstream_echo(
+    rqctx: RequestContext<usize>,
+    body: UntypedBody,
+) -> Result<HttpResponseOk<EchoResult>, HttpError> {
+    let headers = rqctx.request.headers();
+    let header =
+        |name: &str| headers.get(name).map(|v| v.to_str().unwrap().to_string());
+    Ok(HttpResponseOk(EchoResult {
+        uri: rqctx.request.uri().to_string(),
+        forwarded_for: header("x-forwarded-for"),
+        forwarded_host: header("x-forwarded-host"),
+        forwarded_proto: header("x-forwarded-proto"),
+        hop_by_hop: header("x-hop-by-hop"),
+        body: body.as_str()?.to_string(),
+    }))
+}
+
+#[derive(Deserialize, JsonSchema)]
+struct AllPath {
+    path: Vec<String>,
+}
+
+#[endpoint {
+    method = POST,
+    path = "/proxy/{path:.*}",
+    unpublished = true,
+}]
+async fn proxy_forward(
+    rqctx: RequestContext<Proxy>,
+    path: Path<AllPath>,
+    raw_request: RawRequest,
+) -> Result<Response<Body>, HttpError> {
+    let path = path.into_inner().path.join("/");
+    rqctx.context().forward(&rqctx, raw_request.into_inner(), &path).await
+}
+
+fn proxy_setup(test_name: &str, proxy: Proxy) -> TestContext<Proxy> {
+    let mut api = ApiDescription::new();
+    api.register(proxy_forward).unwrap();
+    let logctx = common::create_log_context(test_name);
+    let log = logctx.log.new(o!());
+    TestContext::new(api, proxy, &Default::default(), Some(logctx), log)
+}
+
+#[tokio::test]
+async fn test_proxy_forward() {
+    let mut api = ApiDescription::new();
+    api.register(upstream_echo).unwrap();
+    let upstream = common::test_setup("proxy_forward_upstream", api);
+
+    let proxy =
+        Proxy::new(&format!("http://{}", upstream.client_testctx.bind_address))
+            .unwrap();
+    let testctx = proxy_setup("proxy_forward", proxy);
+    let client = &testctx.client_testctx;
+
+    let request = hyper::Request::builder()
+        .method(Method::POST)
+        .uri(client.url("/proxy/echo?a=b"))
+        .header("x-forwarded-for", "192.0.2.1")
+        .header("x-forwarded-for", "198.51.100.2")
+        .header("connection", "x-hop-by-hop")
+        .header("x-hop-by-hop", "1")
+        .body(Body::from("hello upstream"))
+        .unwrap();
+    let mut response = client
+        .make_request_with_request(request, StatusCode::OK)
+        .await
+        .unwrap();
+    let result: EchoResult = read_json(&mut response).await;
+
+    assert_eq!(result.uri, "/echo?a=b");
+    assert_eq!(
+        result.forwarded_for.unwrap(),
+        "192.0.2.1, 198.51.100.2, 127.0.0.1"
+    );
+    assert_eq!(result.forwarded_host.unwrap(), client.bind_address.to_string());
+    assert_eq!(result.forwarded_proto.unwrap(), "http");
+    assert_eq!(result.hop_by_hop, None);
+    assert_eq!(result.body, "hello upstream");
+
+    // Errors from the upstream server are passed through as-is.  (The body
+    // therefore has the upstream server's request id rather than ours, so we
+    // can't use `make_request_error()`.)
+    let request = hyper::Request::builder()
+        .method(Method::POST)
+        .uri(client.url("/proxy/nonexistent"))
+        .body(Body::empty())
+        .unwrap();
+    let mut response =
+        client.client.request(request).await.unwrap().map(Body::wrap);
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    let error: dropshot::HttpErrorResponseBody = read_json(&mut response).await;
+    assert_eq!(error.message, "Not Found");
+
+    testctx.teardown().await;
+    upstream.teardown().await;
+}
+
+#[tokio::test]
+async fn test_proxy_http2() {
+    let mut api = ApiDescription::new();
+    api.register(upstream_echo).unwrap();
+    let upstream = common::test_setup("proxy_http2_upstream", api);
+
+    let proxy =
+        Proxy::new(&format!("http://{}", upstream.client_testctx.bind_address))
+            .unwrap();
+    let testctx = proxy_setup("proxy_http2", proxy);
+    let client = &testctx.client_testctx;
+
+    // The request arrives at the proxy over HTTP/2, but is forwarded to the
+    // upstream server over HTTP/1.1.
+    let h2_client = Client::builder(TokioExecutor::new())
+        .http2_only(true)
+        .build_http::<Body>();
+    let request = hyper::Request::builder()
+        .method(Method::POST)
+        .uri(client.url("/proxy/echo"))
+        .body(Body::from("hello upstream"))
+        .unwrap();
+    let response = h2_client.request(request).await.unwrap();
+    assert_eq!(response.version(), http::Version::HTTP_2);
+    assert_eq!(response.status(), StatusCode::OK);
+    let mut response = response.map(Body::wrap);
+    let result: EchoResult = read_json(&mut response).await;
+    assert_eq!(result.uri, "/echo");
+    assert_eq!(result.body, "hello upstream");
+
+    testctx.teardown().await;
+    upstream.teardown().await;
+}
+
+#[tokio::test]
+async fn test_proxy_path_escaping() {
+    let mut api = ApiDescription::new();
+    api.register(upstream_echo).unwrap();
+    let upstream = common::test_setup("proxy_path_escaping_upstream", api);
+
+    let proxy = Proxy::new(&format!(
+        "http://{}/base",
+        upstream.client_testctx.bind_address
+    ))
+    .unwrap();
+    let testctx = proxy_setup("proxy_path_escaping", proxy);
+    let client = &testctx.client_testctx;
+
+    // An encoded "?" stays part of the path rather than starting a query
+    // string, so this is "/base/echo%3Fa=b" upstream, which doesn't exist.
+    let request = hyper::Request::builder()
+        .method(Method::POST)
+        .uri(client.url("/proxy/echo%3Fa=b"))
+        .body(Body::empty())
+        .unwrap();
+    let response = client.client.request(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+
+    // Encoded ".." segments can't climb out of "/base" to reach "/echo".
+    let error = client
+        .make_request_error(
+            Method::POST,
+            "/proxy/%2E%2E/echo",
+            StatusCode::BAD_REQUEST,
+        )
+        .await;
+    assert!(error.message.contains("not permitted"), "{}", error.message);
+
+    testctx.teardown().await;
+    upstream.teardown().await;
+}
+
+#[tokio::test]
+async fn test_proxy_upstream_down() {
+    // Find a port with nothing listening on it.
+    let unused_addr = {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        listener.local_addr().unwrap()
+    };
+    let proxy = Proxy::new(&format!("http://{}", unused_addr)).unwrap();
+    let testctx = proxy_setup("proxy_upstream_down", proxy);
+
+    let error = testctx
+        .client_testctx
+        .make_request_error(
+            Method::POST,
+            "/proxy/echo",
+            StatusCode::BAD_GATEWAY,
+        )
+        .await;
+    assert_eq!(error.message, "Bad Gateway");
+
+    testctx.teardown().await;
+}
@@ -0,0 +1,123 @@
+// Copyright 2023 Oxide Computer Company
+
+//! Test cases for query parameters in the `deepObject` style.
+
+use dropshot::endpoint;
+use dropshot::test_util::read_json;
+use dropshot::ApiDescription;
+use dropshot::HttpError;
+use dropshot::HttpResponseOk;
+use dropshot::Query;
+use dropshot::RequestContext;
+use http::Method;
+use http::StatusCode;
+use schemars::JsonSchema;
+use serde::Deserialize;
+use serde::Serialize;
+
+pub mod common;
+
+#[derive(Debug, Deserialize, JsonSchema, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+enum State {
+    Running,
+    Stopped,
+}
+
+#[derive(Debug, Deserialize, JsonSchema, PartialEq, Serialize)]
+struct Filter {
+    name: Option<String>,
+    state: Option<State>,
+    min_cpus: Option<u16>,
+}
+
+#[derive(Debug, Deserialize, JsonSchema, PartialEq, Serialize)]
+struct ListParams {
+    filter: Option<Filter>,
+    limit: Option<u32>,
+}
+
+#[endpoint {
+    method = GET,
+    path = "/instances",
+}]
+async fn instances_list(
+    _rqctx: RequestContext<usize>,
+    query: Query<ListParams>,
+) -> Result<HttpResponseOk<ListParams>, HttpError> {
+    Ok(HttpResponseOk(query.into_inner()))
+}
+
+fn api() -> ApiDescription<usize> {
+    let mut api = ApiDescription::new();
+    api.register(instances_list).unwrap();
+    api
+}
+
+#[tokio::test]
+async fn test_query_deep_object() {
+    let testctx = common::test_setup("query_deep_object", api());
+    let client = &testctx.client_testctx;
+
+    let running = ListParams {
+        filter: Some(Filter {
+            name: Some(String::from("foo")),
+            state: Some(State::Running),
+            min_cpus: Some(2),
+        }),
+        limit: Some(10),
+    };
+    for (uri, expected) in [
+        (
+            "/instances?filter[name]=foo&filter[state]=running\
+             &filter[min_cpus]=2&limit=10",
+            &running,
+        ),
+        (
+            "/instances?limit=10&filter%5Bname%5D=foo\
+             &filter%5Bstate%5D=running&filter%5Bmin_cpus%5D=2",
+            &running,
+        ),
+        ("/instances", &ListParams { filter: None, limit: None }),
+    ] {
+        let mut response = client
+            .make_request_no_body(Method::GET, uri, StatusCode::OK)
+            .await
+            .unwrap();
+        let params: ListParams = read_json(&mut response).await;
+        assert_eq!(&params, expected);
+    }
+
+    let error = client
+        .make_request_no_body(
+            Method::GET,
+            "/instances?filter[state]=paused",
+            StatusCode::BAD_REQUEST,
+        )
+        .await
+        .unwrap_err();
+    assert!(
+        error.message.starts_with(
+            "unable to parse query string: unknown variant `paused`"
+        ),
+        "{}",
+        error.message
+    );
+
+    testctx.teardown().await;
+}
+
+#[test]
+fn test_query_deep_object_openapi() {
+    let spec = api().openapi("test", "1.0").json().unwrap();
+    let parameters = &spec["paths"]["/instances"]["get"]["parameters"];
+    let filter = &parameters[0];
+    assert_eq!(filter["name"], "filter");
+    assert_eq!(filter["in"], "query");
+    assert_eq!(filter["style"], "deepObject");
+    assert_eq!(filter["explode"], true);
+    let limit = &parameters[1];
+    assert_eq!(limit["name"], "limit");
+    assert!(limit.get("style").is_none());
+    assert!(limit.get("explode").is_none());
+}
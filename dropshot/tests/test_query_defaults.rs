@@ -0,0 +1,87 @@
+// Copyright 2023 Oxide Computer Company
+
+//! Test cases for the defaults of query parameters in the OpenAPI spec.
+
+use dropshot::endpoint;
+use dropshot::ApiDescription;
+use dropshot::HttpError;
+use dropshot::HttpResponseOk;
+use dropshot::Query;
+use dropshot::RequestContext;
+use schemars::JsonSchema;
+use serde::Deserialize;
+use serde::Serialize;
+use serde_json::json;
+
+#[derive(Deserialize, JsonSchema, Serialize)]
+#[serde(rename_all = "lowercase")]
+enum Order {
+    Ascending,
+    Descending,
+}
+
+#[derive(Deserialize, JsonSchema)]
+#[allow(dead_code)]
+struct ListParams {
+    /// Whether to include details
+    #[serde(default)]
+    verbose: bool,
+    /// Maximum number of items to return
+    #[serde(default = "default_limit")]
+    limit: u32,
+    /// Order in which to return items
+    #[serde(default = "default_order")]
+    order: Order,
+    name: Option<String>,
+}
+
+fn default_limit() -> u32 {
+    100
+}
+
+fn default_order() -> Order {
+    Order::Descending
+}
+
+#[endpoint {
+    method = GET,
+    path = "/items",
+}]
+async fn items_list(
+    _rqctx: RequestContext<()>,
+    _query: Query<ListParams>,
+) -> Result<HttpResponseOk<()>, HttpError> {
+    Ok(HttpResponseOk(()))
+}
+
+#[test]
+fn test_query_defaults() {
+    let mut api = ApiDescription::new();
+    api.register(items_list).unwrap();
+    let spec = api.openapi("test", "1.0").json().unwrap();
+    let parameters = spec["paths"]["/items"]["get"]["parameters"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|p| (p["name"].as_str().unwrap(), p))
+        .collect::<std::collections::BTreeMap<_, _>>();
+
+    let limit = parameters["limit"];
+    assert_eq!(limit["description"], "Maximum number of items to return");
+    assert_eq!(limit["schema"]["default"], 100);
+    assert_eq!(limit["schema"]["type"], "integer");
+
+    // A default accompanies a reference with `allOf`.
+    let order = parameters["order"];
+    assert_eq!(order["description"], "Order in which to return items");
+    assert_eq!(
+        order["schema"],
+        json!({
+            "default": "descending",
+            "allOf": [{ "$ref": "#/components/schemas/Order" }],
+        })
+    );
+
+    assert_eq!(parameters["verbose"]["schema"]["default"], false);
+    assert!(parameters["name"]["schema"].get("default").is_none());
+}
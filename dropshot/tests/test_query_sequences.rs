@@ -0,0 +1,92 @@
+// Copyright 2023 Oxide Computer Company
+
+//! Test cases for query parameters that are given more than once.
+
+use dropshot::endpoint;
+use dropshot::test_util::read_json;
+use dropshot::ApiDescription;
+use dropshot::HttpError;
+use dropshot::HttpResponseOk;
+use dropshot::Query;
+use dropshot::RequestContext;
+use http::Method;
+use http::StatusCode;
+use schemars::JsonSchema;
+use serde::Deserialize;
+use serde::Serialize;
+
+pub mod common;
+
+#[derive(Debug, Deserialize, JsonSchema, PartialEq, Serialize)]
+struct Lookup {
+    #[serde(default)]
+    id: Vec<u32>,
+    verbose: Option<bool>,
+}
+
+#[endpoint {
+    method = GET,
+    path = "/lookup",
+}]
+async fn lookup(
+    _rqctx: RequestContext<usize>,
+    query: Query<Lookup>,
+) -> Result<HttpResponseOk<Lookup>, HttpError> {
+    Ok(HttpResponseOk(query.into_inner()))
+}
+
+fn api() -> ApiDescription<usize> {
+    let mut api = ApiDescription::new();
+    api.register(lookup).unwrap();
+    api
+}
+
+#[tokio::test]
+async fn test_query_sequences() {
+    let testctx = common::test_setup("query_sequences", api());
+    let client = &testctx.client_testctx;
+
+    for (uri, expected) in [
+        (
+            "/lookup?id=3&verbose=true&id=1&id=2",
+            Lookup { id: vec![3, 1, 2], verbose: Some(true) },
+        ),
+        ("/lookup?id=7", Lookup { id: vec![7], verbose: None }),
+        ("/lookup", Lookup { id: vec![], verbose: None }),
+    ] {
+        let mut response = client
+            .make_request_no_body(Method::GET, uri, StatusCode::OK)
+            .await
+            .unwrap();
+        let lookup: Lookup = read_json(&mut response).await;
+        assert_eq!(lookup, expected);
+    }
+
+    let error = client
+        .make_request_no_body(
+            Method::GET,
+            "/lookup?id=1&id=two",
+            StatusCode::BAD_REQUEST,
+        )
+        .await
+        .unwrap_err();
+    assert_eq!(
+        error.message,
+        "unable to parse query string: invalid digit found in string"
+    );
+
+    testctx.teardown().await;
+}
+
+#[test]
+fn test_query_sequences_openapi() {
+    let spec = api().openapi("test", "1.0").json().unwrap();
+    let parameter = &spec["paths"]["/lookup"]["get"]["parameters"][0];
+    assert_eq!(parameter["name"], "id");
+    assert_eq!(parameter["in"], "query");
+    // "form" is the default style, so it's not serialized.
+    assert!(parameter.get("style").is_none());
+    assert_eq!(parameter["explode"], true);
+    assert_eq!(parameter["schema"]["type"], "array");
+    assert_eq!(parameter["schema"]["items"]["type"], "integer");
+}
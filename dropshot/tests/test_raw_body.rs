@@ -0,0 +1,135 @@
+// Copyright 2023 Oxide Computer Company
+
+//! Test cases for the RawBody extractor, including request trailers.
+
+use dropshot::endpoint;
+use dropshot::test_util::read_json;
+use dropshot::ApiDescription;
+use dropshot::Body;
+use dropshot::HttpError;
+use dropshot::HttpResponseOk;
+use dropshot::RawBody;
+use dropshot::RequestContext;
+use http::HeaderMap;
+use http::Method;
+use http::StatusCode;
+use http_body::Frame;
+use hyper::Request;
+use schemars::JsonSchema;
+use serde::Deserialize;
+use serde::Serialize;
+
+pub mod common;
+
+#[derive(Debug, Deserialize, JsonSchema, PartialEq, Serialize)]
+struct Upload {
+    nbytes: usize,
+    checksum: Option<String>,
+}
+
+#[endpoint {
+    method = PUT,
+    path = "/upload",
+}]
+async fn upload(
+    _rqctx: RequestContext<usize>,
+    mut body: RawBody,
+) -> Result<HttpResponseOk<Upload>, HttpError> {
+    let mut nbytes = 0;
+    while let Some(data) = body.data().await {
+        nbytes += data?.len();
+    }
+    let checksum = body.trailers().await?.and_then(|trailers| {
+        trailers
+            .get("x-checksum")
+            .and_then(|value| value.to_str().ok())
+            .map(String::from)
+    });
+    Ok(HttpResponseOk(Upload { nbytes, checksum }))
+}
+
+fn api() -> ApiDescription<usize> {
+    let mut api = ApiDescription::new();
+    api.register(upload).unwrap();
+    api
+}
+
+/// Makes a (chunked) request whose body is `chunks`, followed by `trailers`.
+fn request(
+    client: &dropshot::test_util::ClientTestContext,
+    chunks: &[&str],
+    trailers: Option<HeaderMap>,
+) -> Request<Body> {
+    let frames = chunks
+        .iter()
+        .map(|chunk| {
+            Frame::data(bytes::Bytes::copy_from_slice(chunk.as_bytes()))
+        })
+        .chain(trailers.map(Frame::trailers))
+        .map(Ok::<_, std::io::Error>)
+        .collect::<Vec<_>>();
+    Request::builder()
+        .method(Method::PUT)
+        .uri(client.url("/upload"))
+        .header(http::header::TRAILER, "x-checksum")
+        .body(Body::wrap(http_body_util::StreamBody::new(
+            futures::stream::iter(frames),
+        )))
+        .unwrap()
+}
+
+#[tokio::test]
+async fn test_raw_body_trailers() {
+    let testctx = common::test_setup("raw_body_trailers", api());
+    let client = &testctx.client_testctx;
+
+    let mut trailers = HeaderMap::new();
+    trailers.insert("x-checksum", "abc123".parse().unwrap());
+    let mut response = client
+        .make_request_with_request(
+            request(client, &["hello, ", "world"], Some(trailers)),
+            StatusCode::OK,
+        )
+        .await
+        .unwrap();
+    let upload: Upload = read_json(&mut response).await;
+    assert_eq!(
+        upload,
+        Upload { nbytes: 12, checksum: Some(String::from("abc123")) }
+    );
+
+    // Without trailers, there's no checksum.
+    let mut response = client
+        .make_request_with_request(
+            request(client, &["hello"], None),
+            StatusCode::OK,
+        )
+        .await
+        .unwrap();
+    let upload: Upload = read_json(&mut response).await;
+    assert_eq!(upload, Upload { nbytes: 5, checksum: None });
+
+    testctx.teardown().await;
+}
+
+#[tokio::test]
+async fn test_raw_body_limit() {
+    let testctx = common::test_setup("raw_body_limit", api());
+    let client = &testctx.client_testctx;
+
+    // The default limit is 1024 bytes.
+    let chunk = "x".repeat(600);
+    let error = client
+        .make_request_with_request(
+            request(client, &[&chunk, &chunk], None),
+            StatusCode::BAD_REQUEST,
+        )
+        .await
+        .unwrap_err();
+    assert_eq!(
+        error.message,
+        "request body exceeded maximum size of 1024 bytes"
+    );
+
+    testctx.teardown().await;
+}
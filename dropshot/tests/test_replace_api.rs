@@ -0,0 +1,116 @@
+// Copyright 2023 Oxide Computer Company
+
+//! Test cases for replacing the API of a running server.
+
+use dropshot::endpoint;
+use dropshot::test_util::object_get;
+use dropshot::test_util::TestContext;
+use dropshot::ApiDescription;
+use dropshot::ConfigDropshot;
+use dropshot::HttpError;
+use dropshot::HttpResponseOk;
+use dropshot::RequestContext;
+use http::Method;
+use http::StatusCode;
+use slog::o;
+use tokio::sync::Notify;
+
+pub mod common;
+
+#[derive(Default)]
+struct PluginContext {
+    /// notified when the slow handler has started
+    started: Notify,
+    /// notified to let the slow handler finish
+    release: Notify,
+}
+
+#[endpoint {
+    method = GET,
+    path = "/core",
+}]
+async fn core_get(
+    _rqctx: RequestContext<PluginContext>,
+) -> Result<HttpResponseOk<String>, HttpError> {
+    Ok(HttpResponseOk(String::from("core")))
+}
+
+#[endpoint {
+    method = GET,
+    path = "/plugins/slow",
+}]
+async fn plugin_slow_get(
+    rqctx: RequestContext<PluginContext>,
+) -> Result<HttpResponseOk<String>, HttpError> {
+    let context = rqctx.context();
+    context.started.notify_one();
+    context.release.notified().await;
+    Ok(HttpResponseOk(String::from("slow")))
+}
+
+#[endpoint {
+    method = GET,
+    path = "/plugins/fast",
+}]
+async fn plugin_fast_get(
+    _rqctx: RequestContext<PluginContext>,
+) -> Result<HttpResponseOk<String>, HttpError> {
+    Ok(HttpResponseOk(String::from("fast")))
+}
+
+fn api(plugins: &[&str]) -> ApiDescription<PluginContext> {
+    let mut api = ApiDescription::new();
+    api.register(core_get).unwrap();
+    for plugin in plugins {
+        match *plugin {
+            "slow" => api.register(plugin_slow_get).unwrap(),
+            "fast" => api.register(plugin_fast_get).unwrap(),
+            _ => panic!("unknown plugin: {}", plugin),
+        }
+    }
+    api
+}
+
+#[tokio::test]
+async fn test_replace_api() {
+    let config = ConfigDropshot::default();
+    let logctx = common::create_log_context("replace_api");
+    let log = logctx.log.new(o!());
+    let testctx = TestContext::new(
+        api(&["slow"]),
+        PluginContext::default(),
+        &config,
+        Some(logctx),
+        log,
+    );
+    let client = &testctx.client_testctx;
+    let server = &testctx.server;
+
+    client
+        .make_request_error(Method::GET, "/plugins/fast", StatusCode::NOT_FOUND)
+        .await;
+
+    // Replace the API while a request to an endpoint that's being removed is
+    // in progress.  That request still completes.
+    let (slow, ()) =
+        tokio::join!(object_get::<String>(client, "/plugins/slow"), async {
+            server.app_private().started.notified().await;
+            server.replace_api(api(&["fast"]));
+            server.app_private().release.notify_one();
+        });
+    assert_eq!(slow, "slow");
+
+    let routes = server
+        .router()
+        .endpoints()
+        .map(|endpoint| endpoint.path.clone())
+        .collect::<Vec<_>>();
+    assert_eq!(routes, ["/core", "/plugins/fast"]);
+    assert_eq!(object_get::<String>(client, "/core").await, "core");
+    assert_eq!(object_get::<String>(client, "/plugins/fast").await, "fast");
+    client
+        .make_request_error(Method::GET, "/plugins/slow", StatusCode::NOT_FOUND)
+        .await;
+
+    testctx.teardown().await;
+}
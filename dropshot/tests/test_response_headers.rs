@@ -0,0 +1,98 @@
+// Copyright 2023 Oxide Computer Company
+
+//! Test cases for the named headers of `HttpResponseHeaders`.
+
+use dropshot::endpoint;
+use dropshot::ApiDescription;
+use dropshot::HttpError;
+use dropshot::HttpResponseHeaders;
+use dropshot::HttpResponseOk;
+use dropshot::RequestContext;
+use http::Method;
+use http::StatusCode;
+use schemars::JsonSchema;
+use serde::Serialize;
+
+pub mod common;
+
+#[derive(Serialize, JsonSchema)]
+#[serde(rename_all = "lowercase")]
+enum Mode {
+    Fast,
+}
+
+#[derive(Serialize, JsonSchema)]
+struct WidgetHeaders {
+    /// how the widget was built
+    x_dropshot_test_header_1: Mode,
+    /// number of widgets remaining
+    x_dropshot_test_header_2: u32,
+    /// seconds until more widgets are available
+    retry_after: Option<u64>,
+}
+
+#[endpoint {
+    method = GET,
+    path = "/widget",
+}]
+async fn widget_get(
+    _rqctx: RequestContext<usize>,
+) -> Result<HttpResponseHeaders<HttpResponseOk<String>, WidgetHeaders>, HttpError>
+{
+    Ok(HttpResponseHeaders::new(
+        HttpResponseOk(String::from("sprocket")),
+        WidgetHeaders {
+            x_dropshot_test_header_1: Mode::Fast,
+            x_dropshot_test_header_2: 41,
+            retry_after: None,
+        },
+    ))
+}
+
+fn api() -> ApiDescription<usize> {
+    let mut api = ApiDescription::new();
+    api.register(widget_get).unwrap();
+    api
+}
+
+#[test]
+fn test_response_headers_openapi() {
+    let spec = api().openapi("test", "1.0").json().unwrap();
+    let headers =
+        &spec["paths"]["/widget"]["get"]["responses"]["200"]["headers"];
+
+    let header = &headers["x-dropshot-test-header-1"];
+    assert_eq!(header["description"], "how the widget was built");
+    assert_eq!(header["required"], true);
+    assert_eq!(header["schema"]["$ref"], "#/components/schemas/Mode");
+
+    let header = &headers["x-dropshot-test-header-2"];
+    assert_eq!(header["description"], "number of widgets remaining");
+    assert_eq!(header["required"], true);
+    assert_eq!(header["schema"]["type"], "integer");
+
+    let header = &headers["retry-after"];
+    assert_eq!(
+        header["description"],
+        "seconds until more widgets are available"
+    );
+    assert!(!header["required"].as_bool().unwrap_or(false));
+    assert_eq!(header["schema"]["type"], "integer");
+}
+
+#[tokio::test]
+async fn test_response_headers() {
+    let testctx = common::test_setup("response_headers", api());
+    let client = &testctx.client_testctx;
+
+    let response = client
+        .make_request_no_body(Method::GET, "/widget", StatusCode::OK)
+        .await
+        .unwrap();
+    let headers = response.headers();
+    assert_eq!(headers.get("x-dropshot-test-header-1").unwrap(), "fast");
+    assert_eq!(headers.get("x-dropshot-test-header-2").unwrap(), "41");
+    assert!(headers.get(http::header::RETRY_AFTER).is_none());
+
+    testctx.teardown().await;
+}
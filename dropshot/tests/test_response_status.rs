@@ -0,0 +1,94 @@
+// Copyright 2023 Oxide Computer Company
+
+//! Test cases for `HttpResponseStatus`.
+
+use dropshot::endpoint;
+use dropshot::ApiDescription;
+use dropshot::HttpError;
+use dropshot::HttpResponseStatus;
+use dropshot::Path;
+use dropshot::RequestContext;
+use dropshot::ResponseStatusSet;
+use http::Method;
+use http::StatusCode;
+use http_body_util::BodyExt;
+use schemars::JsonSchema;
+use serde::Deserialize;
+
+pub mod common;
+
+struct WidgetStatuses;
+impl ResponseStatusSet for WidgetStatuses {
+    const STATUSES: &'static [(StatusCode, &'static str)] = &[
+        (StatusCode::OK, "widget is ready"),
+        (StatusCode::ACCEPTED, "widget is being built"),
+    ];
+}
+
+#[derive(Deserialize, JsonSchema)]
+struct StatusPath {
+    status: u16,
+}
+
+#[endpoint {
+    method = GET,
+    path = "/widget/{status}",
+}]
+async fn widget_get(
+    _rqctx: RequestContext<usize>,
+    path: Path<StatusPath>,
+) -> Result<HttpResponseStatus<WidgetStatuses, String>, HttpError> {
+    let status = StatusCode::from_u16(path.into_inner().status)
+        .map_err(|e| HttpError::for_bad_request(None, e.to_string()))?;
+    HttpResponseStatus::new(status, String::from("sprocket"))
+}
+
+fn api() -> ApiDescription<usize> {
+    let mut api = ApiDescription::new();
+    api.register(widget_get).unwrap();
+    api
+}
+
+#[test]
+fn test_response_status_openapi() {
+    let spec = api().openapi("test", "1.0").json().unwrap();
+    let responses = &spec["paths"]["/widget/{status}"]["get"]["responses"];
+    for (code, description) in
+        [("200", "widget is ready"), ("202", "widget is being built")]
+    {
+        assert_eq!(responses[code]["description"], description);
+        assert_eq!(
+            responses[code]["content"]["application/json"]["schema"]["type"],
+            "string"
+        );
+    }
+    assert_eq!(responses["4XX"]["$ref"], "#/components/responses/Error");
+}
+
+#[tokio::test]
+async fn test_response_status() {
+    let testctx = common::test_setup("response_status", api());
+    let client = &testctx.client_testctx;
+
+    for (path, status) in
+        [("/widget/200", StatusCode::OK), ("/widget/202", StatusCode::ACCEPTED)]
+    {
+        let mut response = client
+            .make_request_no_body(Method::GET, path, status)
+            .await
+            .unwrap();
+        let body = response.body_mut().collect().await.unwrap().to_bytes();
+        assert_eq!(body.as_ref(), b"\"sprocket\"");
+    }
+
+    // Statuses outside the declared set are a server error.
+    client
+        .make_request_error(
+            Method::GET,
+            "/widget/201",
+            StatusCode::INTERNAL_SERVER_ERROR,
+        )
+        .await;
+
+    testctx.teardown().await;
+}
@@ -0,0 +1,90 @@
+// Copyright 2023 Oxide Computer Company
+
+//! Test cases for SafePath, the type for wildcard paths to files.
+
+use dropshot::endpoint;
+use dropshot::test_util::read_json;
+use dropshot::ApiDescription;
+use dropshot::HttpError;
+use dropshot::HttpResponseOk;
+use dropshot::Path;
+use dropshot::RequestContext;
+use dropshot::SafePath;
+use http::Method;
+use http::StatusCode;
+use schemars::JsonSchema;
+use serde::Deserialize;
+
+pub mod common;
+
+#[derive(Deserialize, JsonSchema)]
+struct FilePath {
+    path: SafePath,
+}
+
+#[endpoint {
+    method = GET,
+    path = "/files/{path:.*}",
+    unpublished = true,
+}]
+async fn file_get(
+    _rqctx: RequestContext<usize>,
+    path: Path<FilePath>,
+) -> Result<HttpResponseOk<String>, HttpError> {
+    Ok(HttpResponseOk(path.path.under("/srv/files").into_string()))
+}
+
+fn api() -> ApiDescription<usize> {
+    let mut api = ApiDescription::new();
+    api.register(file_get).unwrap();
+    api
+}
+
+#[tokio::test]
+async fn test_safe_path() {
+    let testctx = common::test_setup("safe_path", api());
+    let client = &testctx.client_testctx;
+
+    for (uri, expected) in [
+        ("/files/docs/a%20b.txt", "/srv/files/docs/a b.txt"),
+        ("/files//docs//", "/srv/files/docs"),
+        ("/files", "/srv/files"),
+    ] {
+        let mut response = client
+            .make_request_no_body(Method::GET, uri, StatusCode::OK)
+            .await
+            .unwrap();
+        let path: String = read_json(&mut response).await;
+        assert_eq!(path, expected);
+    }
+
+    // Segments that could escape the root are rejected, whether the router or
+    // SafePath catches them.
+    for uri in [
+        "/files/docs/%2e%2e/%2e%2e/etc/passwd",
+        "/files/docs%2F..%2F..",
+        "/files/..%5C..%5Cetc",
+        "/files/a%00b",
+    ] {
+        client
+            .make_request_no_body(Method::GET, uri, StatusCode::BAD_REQUEST)
+            .await
+            .unwrap_err();
+    }
+
+    let error = client
+        .make_request_no_body(
+            Method::GET,
+            "/files/..%5C..%5Cetc",
+            StatusCode::BAD_REQUEST,
+        )
+        .await
+        .unwrap_err();
+    assert_eq!(
+        error.message,
+        "bad parameter in URL path: path segment \"..\\\\..\\\\etc\" is not \
+         permitted"
+    );
+
+    testctx.teardown().await;
+}
@@ -0,0 +1,230 @@
+// Copyright 2023 Oxide Computer Company
+
+//! Test cases for validating requests against their JSON schemas.
+
+use dropshot::endpoint;
+use dropshot::test_util::TestContext;
+use dropshot::ApiDescription;
+use dropshot::ConfigDropshot;
+use dropshot::EmptyScanParams;
+use dropshot::HttpError;
+use dropshot::HttpResponseOk;
+use dropshot::HttpResponseUpdatedNoContent;
+use dropshot::PaginationParams;
+use dropshot::Path;
+use dropshot::Query;
+use dropshot::RegisterError;
+use dropshot::RequestContext;
+use dropshot::ResultsPage;
+use dropshot::TypedBody;
+use http::Method;
+use http::StatusCode;
+use schemars::JsonSchema;
+use serde::Deserialize;
+use serde::Serialize;
+
+pub mod common;
+
+#[derive(Deserialize, JsonSchema)]
+struct UserPath {
+    #[schemars(regex(pattern = r"^[a-z][a-z0-9-]*$"))]
+    username: String,
+}
+
+#[derive(Debug, Deserialize, JsonSchema, Serialize)]
+struct UserUpdate {
+    #[schemars(length(min = 1, max = 32))]
+    display_name: String,
+    #[schemars(email)]
+    email: String,
+    #[schemars(range(min = 13, max = 150))]
+    age: Option<u32>,
+}
+
+#[endpoint {
+    method = PUT,
+    path = "/users/{username}",
+}]
+async fn user_put(
+    _rqctx: RequestContext<usize>,
+    _path: Path<UserPath>,
+    _body: TypedBody<UserUpdate>,
+) -> Result<HttpResponseUpdatedNoContent, HttpError> {
+    Ok(HttpResponseUpdatedNoContent())
+}
+
+#[derive(Deserialize, JsonSchema)]
+struct UserSearch {
+    #[schemars(range(min = 1, max = 100))]
+    limit: u32,
+}
+
+#[endpoint {
+    method = GET,
+    path = "/users",
+}]
+async fn users_search(
+    _rqctx: RequestContext<usize>,
+    query: Query<UserSearch>,
+) -> Result<HttpResponseOk<u32>, HttpError> {
+    Ok(HttpResponseOk(query.into_inner().limit))
+}
+
+#[endpoint {
+    method = GET,
+    path = "/groups",
+}]
+async fn groups_list(
+    _rqctx: RequestContext<usize>,
+    _query: Query<PaginationParams<EmptyScanParams, String>>,
+) -> Result<HttpResponseOk<ResultsPage<String>>, HttpError> {
+    Ok(HttpResponseOk(ResultsPage { items: vec![], next_page: None }))
+}
+
+fn setup(test_name: &str, schema_validation: bool) -> TestContext<usize> {
+    let mut api = ApiDescription::new();
+    api.register(user_put).unwrap();
+    api.register(users_search).unwrap();
+    api.register(groups_list).unwrap();
+    let config = ConfigDropshot { schema_validation, ..Default::default() };
+    common::test_setup_with_config(test_name, api, &config)
+}
+
+fn user(display_name: &str, email: &str, age: Option<u32>) -> UserUpdate {
+    UserUpdate {
+        display_name: display_name.to_string(),
+        email: email.to_string(),
+        age,
+    }
+}
+
+#[tokio::test]
+async fn test_schema_validation() {
+    let testctx = setup("schema_validation", true);
+    let client = &testctx.client_testctx;
+
+    client
+        .make_request(
+            Method::PUT,
+            "/users/ada",
+            Some(user("Ada", "ada@example.com", Some(36))),
+            StatusCode::NO_CONTENT,
+        )
+        .await
+        .unwrap();
+
+    // Every violation in the body is reported.
+    let error = client
+        .make_request(
+            Method::PUT,
+            "/users/ada",
+            Some(user("", "ada", Some(12))),
+            StatusCode::BAD_REQUEST,
+        )
+        .await
+        .unwrap_err();
+    assert_eq!(error.error_code.as_deref(), Some("ValidationFailed"));
+    assert_eq!(
+        error.message,
+        "invalid body: /age: must be at least 13; /display_name: must be at \
+         least 1 characters long; /email: must be a valid \"email\""
+    );
+
+    let error = client
+        .make_request(
+            Method::PUT,
+            "/users/Ada",
+            Some(user("Ada", "ada@example.com", None)),
+            StatusCode::BAD_REQUEST,
+        )
+        .await
+        .unwrap_err();
+    assert_eq!(
+        error.message,
+        "invalid path parameters: /username: must match the pattern \
+         \"^[a-z][a-z0-9-]*$\""
+    );
+
+    client
+        .make_request_no_body(Method::GET, "/users?limit=100", StatusCode::OK)
+        .await
+        .unwrap();
+    let error = client
+        .make_request_error(
+            Method::GET,
+            "/users?limit=101",
+            StatusCode::BAD_REQUEST,
+        )
+        .await;
+    assert_eq!(
+        error.message,
+        "invalid query parameters: /limit: must be at most 100"
+    );
+
+    // The flattened schema of pagination parameters doesn't get in the way.
+    client
+        .make_request_no_body(Method::GET, "/groups?limit=5", StatusCode::OK)
+        .await
+        .unwrap();
+
+    testctx.teardown().await;
+}
+
+#[tokio::test]
+async fn test_schema_validation_disabled() {
+    let testctx = setup("schema_validation_disabled", false);
+    let client = &testctx.client_testctx;
+
+    client
+        .make_request(
+            Method::PUT,
+            "/users/Ada",
+            Some(user("", "ada", Some(12))),
+            StatusCode::NO_CONTENT,
+        )
+        .await
+        .unwrap();
+    client
+        .make_request_no_body(Method::GET, "/users?limit=101", StatusCode::OK)
+        .await
+        .unwrap();
+
+    testctx.teardown().await;
+}
+
+#[derive(Deserialize, JsonSchema)]
+struct LookaheadPath {
+    // The `regex` crate doesn't support lookahead.
+    #[schemars(regex(pattern = r"^(?!admin$)[a-z]+$"))]
+    username: String,
+}
+
+#[endpoint {
+    method = GET,
+    path = "/members/{username}",
+}]
+async fn member_get(
+    _rqctx: RequestContext<usize>,
+    _path: Path<LookaheadPath>,
+) -> Result<HttpResponseUpdatedNoContent, HttpError> {
+    Ok(HttpResponseUpdatedNoContent())
+}
+
+#[test]
+fn test_schema_validation_bad_pattern() {
+    let mut api = ApiDescription::<usize>::new();
+    match api.try_register(member_get).unwrap_err() {
+        RegisterError::Invalid { operation_id, message } => {
+            assert_eq!(operation_id, "member_get");
+            assert!(
+                message.starts_with(
+                    "invalid schema for LookaheadPath: invalid pattern \
+                     \"^(?!admin$)[a-z]+$\": "
+                ),
+                "{}",
+                message
+            );
+        }
+        other => panic!("unexpected error: {:?}", other),
+    }
+}
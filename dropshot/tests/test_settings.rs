@@ -0,0 +1,89 @@
+// Copyright 2023 Oxide Computer Company
+
+//! Test cases for changing a server's settings while it runs.
+
+use dropshot::endpoint;
+use dropshot::test_util::TestContext;
+use dropshot::ApiDescription;
+use dropshot::ConfigDropshot;
+use dropshot::ConfigLoggingLevel;
+use dropshot::HttpError;
+use dropshot::HttpResponseOk;
+use dropshot::RequestContext;
+use dropshot::UntypedBody;
+use http::Method;
+use http::StatusCode;
+
+pub mod common;
+
+#[endpoint {
+    method = PUT,
+    path = "/echo",
+}]
+async fn echo(
+    _rqctx: RequestContext<usize>,
+    body: UntypedBody,
+) -> Result<HttpResponseOk<usize>, HttpError> {
+    Ok(HttpResponseOk(body.as_bytes().len()))
+}
+
+fn test_setup(test_name: &str) -> TestContext<usize> {
+    let mut api = ApiDescription::new();
+    api.register(echo).unwrap();
+    let config =
+        ConfigDropshot { request_body_max_bytes: 1024, ..Default::default() };
+    common::test_setup_with_config(test_name, api, &config)
+}
+
+#[tokio::test]
+async fn test_settings() {
+    let testctx = test_setup("settings");
+    let client = &testctx.client_testctx;
+    let settings = testctx.server.settings();
+    assert_eq!(settings.get().request_body_max_bytes, 1024);
+    assert_eq!(settings.get().max_in_flight_requests, None);
+
+    let body = "x".repeat(2000);
+    let error = client
+        .make_request(
+            Method::PUT,
+            "/echo",
+            Some(body.clone()),
+            StatusCode::BAD_REQUEST,
+        )
+        .await
+        .unwrap_err();
+    assert_eq!(
+        error.message,
+        "request body exceeded maximum size of 1024 bytes"
+    );
+
+    // A larger limit applies to the next request.
+    settings.update(|settings| {
+        settings.request_body_max_bytes = 4096;
+        settings.log_level = Some(ConfigLoggingLevel::Warn);
+    });
+    client
+        .make_request(Method::PUT, "/echo", Some(body.clone()), StatusCode::OK)
+        .await
+        .unwrap();
+
+    // So does a limit on requests in flight.
+    settings.update(|settings| settings.max_in_flight_requests = Some(0));
+    client
+        .make_request(
+            Method::PUT,
+            "/echo",
+            Some(body.clone()),
+            StatusCode::SERVICE_UNAVAILABLE,
+        )
+        .await
+        .unwrap_err();
+    settings.update(|settings| settings.max_in_flight_requests = None);
+    client
+        .make_request(Method::PUT, "/echo", Some(body), StatusCode::OK)
+        .await
+        .unwrap();
+
+    testctx.teardown().await;
+}
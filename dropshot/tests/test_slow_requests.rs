@@ -0,0 +1,132 @@
+// Copyright 2023 Oxide Computer Company
+
+//! Test cases for slow request reporting.
+
+use dropshot::endpoint;
+use dropshot::test_util::TestContext;
+use dropshot::ApiDescription;
+use dropshot::ConfigDropshot;
+use dropshot::ConfigSlowRequestLogging;
+use dropshot::HttpError;
+use dropshot::HttpResponseOk;
+use dropshot::RequestContext;
+use http::Method;
+use http::StatusCode;
+use std::sync::Arc;
+use std::sync::Mutex;
+use std::time::Duration;
+
+/// Log drain that records the message and keys of each "slow request" entry
+#[derive(Clone, Default)]
+struct SlowRequestDrain(Arc<Mutex<Vec<Vec<String>>>>);
+
+impl slog::Drain for SlowRequestDrain {
+    type Ok = ();
+    type Err = slog::Never;
+
+    fn log(
+        &self,
+        record: &slog::Record,
+        _values: &slog::OwnedKVList,
+    ) -> Result<(), slog::Never> {
+        if record.msg().to_string() == "slow request" {
+            let mut keys = KeyCollector(Vec::new());
+            slog::KV::serialize(&record.kv(), record, &mut keys).unwrap();
+            self.0.lock().unwrap().push(keys.0);
+        }
+        Ok(())
+    }
+}
+
+struct KeyCollector(Vec<String>);
+
+impl slog::Serializer for KeyCollector {
+    fn emit_arguments(
+        &mut self,
+        key: slog::Key,
+        _val: &std::fmt::Arguments,
+    ) -> slog::Result {
+        self.0.push(key.to_string());
+        Ok(())
+    }
+}
+
+#[endpoint {
+    method = GET,
+    path = "/slow",
+}]
+async fn slow_get(
+    _rqctx: RequestContext<usize>,
+) -> Result<HttpResponseOk<()>, HttpError> {
+    tokio::time::sleep(Duration::from_millis(200)).await;
+    Ok(HttpResponseOk(()))
+}
+
+#[endpoint {
+    method = GET,
+    path = "/fast",
+}]
+async fn fast_get(
+    _rqctx: RequestContext<usize>,
+) -> Result<HttpResponseOk<()>, HttpError> {
+    Ok(HttpResponseOk(()))
+}
+
+#[tokio::test]
+async fn test_slow_requests() {
+    let mut api = ApiDescription::new();
+    api.register(slow_get).unwrap();
+    api.register(fast_get).unwrap();
+    let config = ConfigDropshot {
+        slow_request_logging: vec![
+            ConfigSlowRequestLogging {
+                path: String::from("/slow"),
+                threshold_ms: 100,
+            },
+            ConfigSlowRequestLogging {
+                path: String::from("*"),
+                threshold_ms: 10_000,
+            },
+        ],
+        ..Default::default()
+    };
+    let drain = SlowRequestDrain::default();
+    let log = slog::Logger::root(slog::Drain::fuse(drain.clone()), slog::o!());
+    let testctx = TestContext::new(api, 0_usize, &config, None, log);
+    let client = &testctx.client_testctx;
+
+    client
+        .make_request_no_body(Method::GET, "/fast", StatusCode::OK)
+        .await
+        .unwrap();
+    client
+        .make_request_no_body(Method::GET, "/slow", StatusCode::OK)
+        .await
+        .unwrap();
+
+    // The entry is emitted once the server has finished sending the response,
+    // which may be slightly after the client has received it.
+    let mut nwaits = 0;
+    while drain.0.lock().unwrap().is_empty() {
+        assert!(nwaits < 50, "slow request was not reported");
+        nwaits += 1;
+        tokio::time::sleep(Duration::from_millis(100)).await;
+    }
+
+    let entries = drain.0.lock().unwrap().clone();
+    assert_eq!(entries.len(), 1);
+    for key in [
+        "response_code",
+        "threshold_us",
+        "total_us",
+        "queueing_us",
+        "extraction_us",
+        "handler_us",
+        "serialization_us",
+        "write_us",
+    ] {
+        assert!(entries[0].iter().any(|k| k == key), "missing key {}", key);
+    }
+
+    testctx.teardown().await;
+}
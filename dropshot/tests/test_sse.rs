@@ -0,0 +1,112 @@
+// Copyright 2023 Oxide Computer Company
+
+//! Test cases for server-sent events responses.
+
+use dropshot::endpoint;
+use dropshot::ApiDescription;
+use dropshot::HttpError;
+use dropshot::HttpResponseSse;
+use dropshot::RequestContext;
+use dropshot::SseEvent;
+use dropshot::CONTENT_TYPE_EVENT_STREAM;
+use futures::StreamExt;
+use http::Method;
+use http::StatusCode;
+use http_body_util::BodyExt;
+use schemars::JsonSchema;
+use serde::Serialize;
+use std::time::Duration;
+
+pub mod common;
+
+#[derive(JsonSchema, Serialize)]
+struct Progress {
+    done: u32,
+    message: String,
+}
+
+#[endpoint {
+    method = GET,
+    path = "/progress",
+}]
+async fn progress_get(
+    _rqctx: RequestContext<usize>,
+) -> Result<HttpResponseSse<Progress>, HttpError> {
+    let events = futures::stream::iter(1..=2).map(|done| {
+        SseEvent::new(Progress { done, message: format!("step\n{}", done) })
+            .id(done.to_string())
+            .event("progress")
+    });
+    Ok(HttpResponseSse::new(events))
+}
+
+#[endpoint {
+    method = GET,
+    path = "/slow",
+}]
+async fn slow_get(
+    _rqctx: RequestContext<usize>,
+) -> Result<HttpResponseSse<u32>, HttpError> {
+    let events = futures::stream::once(async {
+        tokio::time::sleep(Duration::from_millis(250)).await;
+        SseEvent::new(1).retry(Duration::from_secs(3))
+    });
+    Ok(HttpResponseSse::new(events).keep_alive(Some(Duration::from_millis(50))))
+}
+
+fn api() -> ApiDescription<usize> {
+    let mut api = ApiDescription::new();
+    api.register(progress_get).unwrap();
+    api.register(slow_get).unwrap();
+    api
+}
+
+#[tokio::test]
+async fn test_sse() {
+    let testctx = common::test_setup("sse", api());
+    let client = &testctx.client_testctx;
+
+    let mut response = client
+        .make_request_no_body(Method::GET, "/progress", StatusCode::OK)
+        .await
+        .unwrap();
+    let headers = response.headers();
+    assert_eq!(
+        headers.get(http::header::CONTENT_TYPE).unwrap(),
+        CONTENT_TYPE_EVENT_STREAM
+    );
+    assert_eq!(headers.get(http::header::CACHE_CONTROL).unwrap(), "no-cache");
+    assert_eq!(headers.get("x-accel-buffering").unwrap(), "no");
+    let body = response.body_mut().collect().await.unwrap().to_bytes();
+    assert_eq!(
+        body,
+        "id: 1\nevent: progress\n\
+         data: {\"done\":1,\"message\":\"step\\n1\"}\n\n\
+         id: 2\nevent: progress\n\
+         data: {\"done\":2,\"message\":\"step\\n2\"}\n\n"
+    );
+
+    // An idle stream gets keep-alive comments.
+    let mut response = client
+        .make_request_no_body(Method::GET, "/slow", StatusCode::OK)
+        .await
+        .unwrap();
+    let body = response.body_mut().collect().await.unwrap().to_bytes();
+    let body = std::str::from_utf8(&body).unwrap();
+    let comments = body.strip_suffix("retry: 3000\ndata: 1\n\n").unwrap();
+    assert!(!comments.is_empty(), "{:?}", body);
+    assert!(comments.split(":\n\n").all(str::is_empty), "{:?}", body);
+
+    testctx.teardown().await;
+}
+
+#[test]
+fn test_sse_openapi() {
+    let spec = api().openapi("test", "1.0").json().unwrap();
+    let content =
+        &spec["paths"]["/progress"]["get"]["responses"]["200"]["content"];
+    assert_eq!(
+        content[CONTENT_TYPE_EVENT_STREAM]["schema"]["$ref"],
+        "#/components/schemas/Progress"
+    );
+}
@@ -0,0 +1,226 @@
+// Copyright 2023 Oxide Computer Company
+
+//! Test cases for serving static files.
+
+#![cfg(feature = "static-files")]
+
+use dropshot::static_files::StaticFiles;
+use dropshot::ApiDescription;
+use dropshot::Body;
+use http::Method;
+use http::StatusCode;
+use http_body_util::BodyExt;
+use hyper::Request;
+use std::collections::BTreeMap;
+use std::collections::BTreeSet;
+
+pub mod common;
+
+#[tokio::test]
+async fn test_static_files() {
+    let dir = tempfile::tempdir().unwrap();
+    let root = dir.path().join("www");
+    std::fs::create_dir_all(root.join("css")).unwrap();
+    std::fs::create_dir_all(root.join("leak")).unwrap();
+    std::fs::write(root.join("index.html"), "<h1>hello</h1>").unwrap();
+    std::fs::write(root.join("css/site.css"), "h1 { color: red }").unwrap();
+    std::fs::write(root.join("data"), [0u8, 1, 2]).unwrap();
+    std::fs::write(dir.path().join("secret.txt"), "secret").unwrap();
+    #[cfg(unix)]
+    std::os::unix::fs::symlink(
+        dir.path().join("secret.txt"),
+        root.join("secret.txt"),
+    )
+    .unwrap();
+    #[cfg(unix)]
+    std::os::unix::fs::symlink(
+        dir.path().join("secret.txt"),
+        root.join("leak/index.html"),
+    )
+    .unwrap();
+
+    let mut api = ApiDescription::<usize>::new();
+    let files = StaticFiles::new(root.to_str().unwrap().to_string())
+        .index_file("index.htm")
+        .index_file("index.html");
+    api.register_static_files("/assets", files).unwrap();
+    let testctx = common::test_setup("static_files", api);
+    let client = &testctx.client_testctx;
+
+    for (path, content_type, expected) in [
+        ("/assets", "text/html", &b"<h1>hello</h1>"[..]),
+        ("/assets/css/site.css", "text/css", &b"h1 { color: red }"[..]),
+        ("/assets/data", "application/octet-stream", &[0, 1, 2][..]),
+    ] {
+        let mut response = client
+            .make_request_no_body(Method::GET, path, StatusCode::OK)
+            .await
+            .unwrap();
+        let headers = response.headers();
+        assert_eq!(
+            headers.get(http::header::CONTENT_TYPE).unwrap(),
+            content_type
+        );
+        assert!(headers.contains_key(http::header::ETAG));
+        assert!(headers.contains_key(http::header::LAST_MODIFIED));
+        let body = response.body_mut().collect().await.unwrap().to_bytes();
+        assert_eq!(body.as_ref(), expected, "{}", path);
+    }
+
+    // A conditional request for an unchanged file gets a 304.
+    let response = client
+        .make_request_no_body(
+            Method::GET,
+            "/assets/css/site.css",
+            StatusCode::OK,
+        )
+        .await
+        .unwrap();
+    let headers = response.headers();
+    let etag = headers.get(http::header::ETAG).unwrap().clone();
+    let last_modified =
+        headers.get(http::header::LAST_MODIFIED).unwrap().to_str().unwrap();
+    // The obsolete date formats that clients may still send
+    let time = chrono::DateTime::parse_from_rfc2822(last_modified).unwrap();
+    let rfc850 = time.format("%A, %d-%b-%y %H:%M:%S GMT").to_string();
+    let asctime = time.format("%a %b %e %H:%M:%S %Y").to_string();
+    for (name, value) in [
+        (http::header::IF_NONE_MATCH, etag.to_str().unwrap()),
+        (
+            http::header::IF_NONE_MATCH,
+            format!("\"a,b\", {}", etag.to_str().unwrap()).as_str(),
+        ),
+        (http::header::IF_MODIFIED_SINCE, last_modified),
+        (http::header::IF_MODIFIED_SINCE, rfc850.as_str()),
+        (http::header::IF_MODIFIED_SINCE, asctime.as_str()),
+    ] {
+        let request = Request::builder()
+            .method(Method::GET)
+            .uri(client.url("/assets/css/site.css"))
+            .header(name, value)
+            .body(Body::empty())
+            .unwrap();
+        let mut response = client
+            .make_request_with_request(request, StatusCode::NOT_MODIFIED)
+            .await
+            .unwrap();
+        assert_eq!(response.headers().get(http::header::ETAG).unwrap(), etag);
+        let body = response.body_mut().collect().await.unwrap().to_bytes();
+        assert!(body.is_empty());
+    }
+
+    // The entity tags are weak, so they never satisfy If-Match.
+    let request = Request::builder()
+        .method(Method::GET)
+        .uri(client.url("/assets/css/site.css"))
+        .header(http::header::IF_MATCH, etag.clone())
+        .body(Body::empty())
+        .unwrap();
+    client
+        .make_request_with_request(request, StatusCode::PRECONDITION_FAILED)
+        .await
+        .unwrap_err();
+
+    // Missing files, directories without an index file, and links out of the
+    // directory (including index files) are not found.
+    for path in [
+        "/assets/missing.txt",
+        "/assets/css",
+        "/assets/secret.txt",
+        "/assets/leak",
+    ] {
+        let error = client
+            .make_request_no_body(Method::GET, path, StatusCode::NOT_FOUND)
+            .await
+            .unwrap_err();
+        assert_eq!(error.message, "Not Found");
+    }
+
+    testctx.teardown().await;
+}
+
+#[tokio::test]
+async fn test_static_files_precompressed() {
+    let dir = tempfile::tempdir().unwrap();
+    let root = dir.path();
+    std::fs::write(root.join("site.css"), "identity").unwrap();
+    std::fs::write(root.join("site.css.gz"), "gzip bytes").unwrap();
+    std::fs::write(root.join("site.css.br"), "brotli bytes").unwrap();
+    std::fs::write(root.join("plain.css"), "plain").unwrap();
+
+    let mut api = ApiDescription::<usize>::new();
+    let files = StaticFiles::new(root.to_str().unwrap().to_string())
+        .precompressed(true);
+    api.register_static_files("/assets", files).unwrap();
+    let testctx = common::test_setup("static_files_precompressed", api);
+    let client = &testctx.client_testctx;
+
+    let get = |path: &'static str, accept_encoding: Option<&'static str>| {
+        let mut request =
+            Request::builder().method(Method::GET).uri(client.url(path));
+        if let Some(accept_encoding) = accept_encoding {
+            request =
+                request.header(http::header::ACCEPT_ENCODING, accept_encoding);
+        }
+        async move {
+            let mut response = client
+                .make_request_with_request(
+                    request.body(Body::empty()).unwrap(),
+                    StatusCode::OK,
+                )
+                .await
+                .unwrap();
+            let body = response.body_mut().collect().await.unwrap().to_bytes();
+            (response, String::from_utf8(body.to_vec()).unwrap())
+        }
+    };
+
+    let mut etags = BTreeMap::new();
+    for (accept_encoding, encoding, expected) in [
+        (None, None, "identity"),
+        (Some("gzip"), Some("gzip"), "gzip bytes"),
+        (Some("gzip, br"), Some("br"), "brotli bytes"),
+        (Some("gzip;q=1.0, br;q=0.5"), Some("gzip"), "gzip bytes"),
+        (Some("br;q=0, *"), Some("gzip"), "gzip bytes"),
+        (Some("zstd"), None, "identity"),
+    ] {
+        let (response, body) = get("/assets/site.css", accept_encoding).await;
+        let headers = response.headers();
+        assert_eq!(body, expected, "{:?}", accept_encoding);
+        assert_eq!(
+            headers
+                .get(http::header::CONTENT_ENCODING)
+                .map(|v| v.to_str().unwrap()),
+            encoding
+        );
+        assert_eq!(
+            headers.get(http::header::CONTENT_TYPE).unwrap(),
+            "text/css"
+        );
+        assert_eq!(headers.get(http::header::VARY).unwrap(), "accept-encoding");
+        assert_eq!(
+            headers.get(http::header::CONTENT_LENGTH).unwrap(),
+            &expected.len().to_string()
+        );
+        let etag = headers.get(http::header::ETAG).unwrap().to_str().unwrap();
+        let first_etag =
+            etags.entry(encoding).or_insert_with(|| etag.to_string());
+        assert_eq!(first_etag.as_str(), etag);
+    }
+
+    // Each variant has its own entity tag.
+    assert_eq!(etags.len(), 3);
+    assert_eq!(etags.values().collect::<BTreeSet<_>>().len(), 3);
+
+    // Files without pre-compressed siblings are served as they are, but the
+    // response still depends on Accept-Encoding.
+    let (response, body) = get("/assets/plain.css", Some("gzip, br")).await;
+    assert_eq!(body, "plain");
+    assert!(!response.headers().contains_key(http::header::CONTENT_ENCODING));
+    assert_eq!(
+        response.headers().get(http::header::VARY).unwrap(),
+        "accept-encoding"
+    );
+
+    testctx.teardown().await;
+}
@@ -2,10 +2,12 @@
 
 //! Test cases for streaming requests.
 
+use dropshot::Body;
 use dropshot::{endpoint, ApiDescription, HttpError, RequestContext};
+use futures::StreamExt;
 use http::{Method, Response, StatusCode};
-use hyper::{body::HttpBody, Body};
-use hyper_staticfile::FileBytesStream;
+use hyper_staticfile::util::FileBytesStream;
+use hyper_staticfile::vfs::TokioFileAccess;
 use tokio::io::{AsyncSeekExt, AsyncWriteExt};
 
 extern crate slog;
@@ -16,6 +18,7 @@ fn api() -> ApiDescription<usize> {
     let mut api = ApiDescription::new();
     api.register(api_streaming).unwrap();
     api.register(api_not_streaming).unwrap();
+    api.register(api_writer).unwrap();
     api
 }
 
@@ -46,10 +49,29 @@ async fn api_streaming(
     }
     file.seek(std::io::SeekFrom::Start(0)).await.unwrap();
 
-    let file_stream = FileBytesStream::new(file);
+    let file_stream = FileBytesStream::new(TokioFileAccess::new(file));
     Ok(Response::builder()
         .status(StatusCode::OK)
-        .body(file_stream.into_body())?)
+        .body(Body::wrap_stream(file_stream))?)
+}
+
+#[endpoint {
+    method = GET,
+    path = "/writer",
+}]
+async fn api_writer(
+    _rqctx: RequestContext<usize>,
+) -> Result<Response<Body>, HttpError> {
+    let (mut writer, body) = Body::channel();
+    tokio::spawn(async move {
+        let mut buf = [0; BUF_SIZE];
+        for i in 0..BUF_COUNT {
+            buf.fill((i & 255) as u8);
+            writer.write_all(&buf).await?;
+        }
+        writer.shutdown().await
+    });
+    Ok(Response::builder().status(StatusCode::OK).body(body)?)
 }
 
 #[endpoint {
@@ -96,7 +118,7 @@ async fn test_streaming_server_streaming_client() {
 
     let mut chunk_count = 0;
     let mut byte_count = 0;
-    while let Some(chunk) = response.body_mut().data().await {
+    while let Some(chunk) = response.body_mut().next().await {
         let chunk = chunk.expect("Should have received chunk without error");
         byte_count += chunk.len();
         chunk_count += 1;
@@ -128,7 +150,8 @@ async fn test_streaming_server_buffered_client() {
         .expect("Expected GET request to succeed");
     check_has_transfer_encoding(&response, Some("chunked"));
 
-    let body_bytes = hyper::body::to_bytes(response.body_mut())
+    let body_bytes = std::mem::take(response.body_mut())
+        .to_bytes()
         .await
         .expect("Error reading body");
     assert_eq!(
@@ -140,6 +163,34 @@ async fn test_streaming_server_buffered_client() {
     testctx.teardown().await;
 }
 
+#[tokio::test]
+async fn test_streaming_server_writer() {
+    let api = api();
+    let testctx = common::test_setup("streaming_server_writer", api);
+    let client = &testctx.client_testctx;
+
+    let mut response = client
+        .make_request_no_body(Method::GET, "/writer", StatusCode::OK)
+        .await
+        .expect("Expected GET request to succeed");
+    check_has_transfer_encoding(&response, Some("chunked"));
+
+    let body_bytes = std::mem::take(response.body_mut())
+        .to_bytes()
+        .await
+        .expect("Error reading body");
+    assert_eq!(
+        BUF_SIZE * BUF_COUNT,
+        body_bytes.len(),
+        "Mismatch of sent vs received byte count"
+    );
+    for (i, chunk) in body_bytes.chunks(BUF_SIZE).enumerate() {
+        assert!(chunk.iter().all(|b| *b == (i & 255) as u8));
+    }
+
+    testctx.teardown().await;
+}
+
 #[tokio::test]
 async fn test_non_streaming_servers_do_not_use_transfer_encoding() {
     let api = api();
@@ -0,0 +1,106 @@
+// Copyright 2023 Oxide Computer Company
+
+//! Test cases for strict HTTP parsing.
+
+use dropshot::endpoint;
+use dropshot::test_util::TestContext;
+use dropshot::ApiDescription;
+use dropshot::ConfigDropshot;
+use dropshot::ConfigStrictHttp;
+use dropshot::HttpError;
+use dropshot::HttpResponseOk;
+use dropshot::RequestContext;
+use dropshot::StrictHttpRejections;
+use dropshot::UntypedBody;
+
+pub mod common;
+
+#[endpoint {
+    method = POST,
+    path = "/echo",
+}]
+async fn echo(
+    _rqctx: RequestContext<usize>,
+    body: UntypedBody,
+) -> Result<HttpResponseOk<String>, HttpError> {
+    Ok(HttpResponseOk(body.as_str()?.to_string()))
+}
+
+fn test_setup(test_name: &str) -> TestContext<usize> {
+    let mut api = ApiDescription::new();
+    api.register(echo).unwrap();
+    let config = ConfigDropshot {
+        strict_http: Some(ConfigStrictHttp::default()),
+        ..Default::default()
+    };
+    common::test_setup_with_config(test_name, api, &config)
+}
+
+#[tokio::test]
+async fn test_strict_http() {
+    let testctx = test_setup("strict_http");
+    let addr = testctx.server.local_addr();
+
+    // Well-formed requests, including pipelined ones with chunked bodies, are
+    // unaffected.
+    let response = common::exchange(
+        addr,
+        b"POST /echo HTTP/1.1\r\nHost: a\r\nContent-Length: 5\r\n\r\nhello\
+        POST /echo HTTP/1.1\r\nHost: a\r\nTransfer-Encoding: chunked\r\n\r\n\
+        5;a=b\r\nworld\r\n0\r\n\r\n\
+        POST /echo HTTP/1.1\r\nHost: a\r\nContent-Length: 0\r\n\
+        Connection: close\r\n\r\n",
+    )
+    .await;
+    assert_eq!(response.matches("HTTP/1.1 200 OK").count(), 3, "{}", response);
+    assert!(response.contains("\"hello\""));
+    assert!(response.contains("\"world\""));
+    assert_eq!(
+        testctx.server.strict_http_rejections(),
+        StrictHttpRejections::default()
+    );
+
+    // The request is rejected without reaching the handler, and so is the
+    // request smuggled after it.
+    let response = common::exchange(
+        addr,
+        b"POST /echo HTTP/1.1\r\nHost: a\r\nContent-Length: 4\r\n\
+        Transfer-Encoding: chunked\r\n\r\n\
+        0\r\n\r\nPOST /echo HTTP/1.1\r\nHost: a\r\nContent-Length: 0\r\n\r\n",
+    )
+    .await;
+    assert!(response.starts_with("HTTP/1.1 400 Bad Request"), "{}", response);
+    assert!(!response.contains("200 OK"));
+
+    let response = common::exchange(
+        addr,
+        b"POST /echo HTTP/1.1\r\nHost: a\r\nX-Folded: a\r\n b\r\n\r\n",
+    )
+    .await;
+    assert!(response.starts_with("HTTP/1.1 400 Bad Request"), "{}", response);
+
+    let response =
+        common::exchange(addr, b"POST /echo HTTP/1.1\r\nHost: a\rX: b\r\n\r\n")
+            .await;
+    assert!(response.starts_with("HTTP/1.1 400 Bad Request"), "{}", response);
+
+    let request = format!(
+        "POST /echo HTTP/1.1\r\nHost: a\r\nTransfer-Encoding: chunked\r\n\r\n\
+        5;{}\r\nhello\r\n0\r\n\r\n",
+        "x".repeat(2000)
+    );
+    let response = common::exchange(addr, request.as_bytes()).await;
+    assert!(!response.contains("200 OK"), "{}", response);
+
+    assert_eq!(
+        testctx.server.strict_http_rejections(),
+        StrictHttpRejections {
+            content_length_with_transfer_encoding: 1,
+            obs_fold: 1,
+            bare_cr: 1,
+            chunk_extensions_too_long: 1,
+        }
+    );
+
+    testctx.teardown().await;
+}
@@ -0,0 +1,113 @@
+// Copyright 2023 Oxide Computer Company
+
+//! Test cases for rejecting undeclared query parameters.
+
+use dropshot::endpoint;
+use dropshot::test_util::TestContext;
+use dropshot::ApiDescription;
+use dropshot::ConfigDropshot;
+use dropshot::EmptyScanParams;
+use dropshot::HttpError;
+use dropshot::HttpResponseOk;
+use dropshot::PaginationParams;
+use dropshot::Query;
+use dropshot::RequestContext;
+use dropshot::ResultsPage;
+use http::Method;
+use http::StatusCode;
+use schemars::JsonSchema;
+use serde::Deserialize;
+
+pub mod common;
+
+#[derive(Deserialize, JsonSchema)]
+struct ProjectFilter {
+    owner: Option<String>,
+}
+
+#[endpoint {
+    method = GET,
+    path = "/projects",
+}]
+async fn projects_list(
+    _rqctx: RequestContext<usize>,
+    _query: Query<ProjectFilter>,
+) -> Result<HttpResponseOk<()>, HttpError> {
+    Ok(HttpResponseOk(()))
+}
+
+#[endpoint {
+    method = GET,
+    path = "/groups",
+}]
+async fn groups_list(
+    _rqctx: RequestContext<usize>,
+    _query: Query<PaginationParams<EmptyScanParams, String>>,
+) -> Result<HttpResponseOk<ResultsPage<String>>, HttpError> {
+    Ok(HttpResponseOk(ResultsPage { items: vec![], next_page: None }))
+}
+
+fn setup(test_name: &str, strict_query_params: bool) -> TestContext<usize> {
+    let mut api = ApiDescription::new();
+    api.register(projects_list).unwrap();
+    api.register(groups_list).unwrap();
+    let config = ConfigDropshot { strict_query_params, ..Default::default() };
+    common::test_setup_with_config(test_name, api, &config)
+}
+
+#[tokio::test]
+async fn test_strict_query_params() {
+    let testctx = setup("strict_query_params", true);
+    let client = &testctx.client_testctx;
+
+    client
+        .make_request_no_body(Method::GET, "/projects", StatusCode::OK)
+        .await
+        .unwrap();
+    client
+        .make_request_no_body(
+            Method::GET,
+            "/projects?owner=ada",
+            StatusCode::OK,
+        )
+        .await
+        .unwrap();
+    client
+        .make_request_no_body(Method::GET, "/groups?limit=5", StatusCode::OK)
+        .await
+        .unwrap();
+
+    let error = client
+        .make_request_error(
+            Method::GET,
+            "/projects?ownr=ada&sort=name&ownr=grace",
+            StatusCode::BAD_REQUEST,
+        )
+        .await;
+    assert_eq!(error.error_code.as_deref(), Some("UnknownQueryParameters"));
+    assert_eq!(error.message, "unknown query parameters: \"ownr\", \"sort\"");
+
+    let error = client
+        .make_request_error(
+            Method::GET,
+            "/groups?limt=5",
+            StatusCode::BAD_REQUEST,
+        )
+        .await;
+    assert_eq!(error.message, "unknown query parameters: \"limt\"");
+
+    testctx.teardown().await;
+}
+
+#[tokio::test]
+async fn test_lenient_query_params() {
+    let testctx = setup("lenient_query_params", false);
+    let client = &testctx.client_testctx;
+
+    client
+        .make_request_no_body(Method::GET, "/projects?ownr=ada", StatusCode::OK)
+        .await
+        .unwrap();
+
+    testctx.teardown().await;
+}
@@ -0,0 +1,125 @@
+// Copyright 2023 Oxide Computer Company
+
+//! Test cases for tenant resolution.
+
+use dropshot::endpoint;
+use dropshot::test_util::read_json;
+use dropshot::ApiDescription;
+use dropshot::Body;
+use dropshot::HttpError;
+use dropshot::HttpResponseOk;
+use dropshot::Path;
+use dropshot::RequestContext;
+use dropshot::TenantSource;
+use http::Method;
+use http::StatusCode;
+use hyper::Request;
+use schemars::JsonSchema;
+use serde::Deserialize;
+use std::sync::Arc;
+
+pub mod common;
+
+#[endpoint {
+    method = GET,
+    path = "/whoami",
+}]
+async fn whoami(
+    rqctx: RequestContext<usize>,
+) -> Result<HttpResponseOk<Option<String>>, HttpError> {
+    Ok(HttpResponseOk(rqctx.tenant().map(str::to_string)))
+}
+
+#[derive(Deserialize, JsonSchema)]
+struct ProjectPath {
+    project: String,
+}
+
+#[endpoint {
+    method = GET,
+    path = "/projects/{project}",
+}]
+async fn project_get(
+    rqctx: RequestContext<usize>,
+    path: Path<ProjectPath>,
+) -> Result<HttpResponseOk<(Option<String>, String)>, HttpError> {
+    Ok(HttpResponseOk((
+        rqctx.tenant().map(str::to_string),
+        path.into_inner().project,
+    )))
+}
+
+fn api(source: TenantSource) -> ApiDescription<usize> {
+    let mut api = ApiDescription::new().tenant_resolver(Arc::new(source));
+    api.register(whoami).unwrap();
+    api.register(project_get).unwrap();
+    api
+}
+
+#[tokio::test]
+async fn test_tenant_from_path_prefix() {
+    let testctx = common::test_setup(
+        "tenant_from_path_prefix",
+        api(TenantSource::PathPrefix),
+    );
+    let client = &testctx.client_testctx;
+
+    // The tenant's prefix is removed before the request is routed.
+    let mut response = client
+        .make_request_no_body(Method::GET, "/acme/whoami", StatusCode::OK)
+        .await
+        .unwrap();
+    let tenant: Option<String> = read_json(&mut response).await;
+    assert_eq!(tenant.as_deref(), Some("acme"));
+
+    let mut response = client
+        .make_request_no_body(
+            Method::GET,
+            "/globex/projects/rocket",
+            StatusCode::OK,
+        )
+        .await
+        .unwrap();
+    let (tenant, project): (Option<String>, String) =
+        read_json(&mut response).await;
+    assert_eq!(tenant.as_deref(), Some("globex"));
+    assert_eq!(project, "rocket");
+
+    // Without the prefix, the path does not match any route.
+    client
+        .make_request_error(Method::GET, "/whoami", StatusCode::NOT_FOUND)
+        .await;
+
+    testctx.teardown().await;
+}
+
+#[tokio::test]
+async fn test_tenant_from_header() {
+    let testctx = common::test_setup(
+        "tenant_from_header",
+        api(TenantSource::Header(String::from("x-tenant"))),
+    );
+    let client = &testctx.client_testctx;
+
+    let mut response = client
+        .make_request_no_body(Method::GET, "/whoami", StatusCode::OK)
+        .await
+        .unwrap();
+    let tenant: Option<String> = read_json(&mut response).await;
+    assert_eq!(tenant, None);
+
+    let request = Request::builder()
+        .method(Method::GET)
+        .uri(client.url("/whoami"))
+        .header("x-tenant", "initech")
+        .body(Body::empty())
+        .unwrap();
+    let mut response = client
+        .make_request_with_request(request, StatusCode::OK)
+        .await
+        .unwrap();
+    let tenant: Option<String> = read_json(&mut response).await;
+    assert_eq!(tenant.as_deref(), Some("initech"));
+
+    testctx.teardown().await;
+}
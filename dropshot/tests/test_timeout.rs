@@ -0,0 +1,65 @@
+// Copyright 2023 Oxide Computer Company
+
+//! Test cases for endpoints with timeouts.
+
+use dropshot::endpoint;
+use dropshot::test_util::object_get;
+use dropshot::ApiDescription;
+use dropshot::HttpError;
+use dropshot::HttpResponseOk;
+use dropshot::RequestContext;
+use http::Method;
+use http::StatusCode;
+use std::time::Duration;
+
+pub mod common;
+
+#[endpoint {
+    method = GET,
+    path = "/hang",
+    timeout_secs = 1,
+}]
+async fn hang_get(
+    _rqctx: RequestContext<usize>,
+) -> Result<HttpResponseOk<u32>, HttpError> {
+    tokio::time::sleep(Duration::from_secs(3600)).await;
+    Ok(HttpResponseOk(0))
+}
+
+#[endpoint {
+    method = GET,
+    path = "/quick",
+    timeout_secs = 30,
+}]
+async fn quick_get(
+    _rqctx: RequestContext<usize>,
+) -> Result<HttpResponseOk<u32>, HttpError> {
+    Ok(HttpResponseOk(3))
+}
+
+fn api() -> ApiDescription<usize> {
+    let mut api = ApiDescription::new();
+    api.register(hang_get).unwrap();
+    api.register(quick_get).unwrap();
+    api
+}
+
+#[tokio::test]
+async fn test_timeout() {
+    let testctx = common::test_setup("timeout", api());
+    let client = &testctx.client_testctx;
+
+    let value: u32 = object_get(client, "/quick").await;
+    assert_eq!(value, 3);
+
+    let error = client
+        .make_request_error(
+            Method::GET,
+            "/hang",
+            StatusCode::SERVICE_UNAVAILABLE,
+        )
+        .await;
+    assert_eq!(error.message, "Service Unavailable");
+
+    testctx.teardown().await;
+}
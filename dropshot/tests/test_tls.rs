@@ -2,7 +2,15 @@
 //! Test cases for TLS support. This validates various behaviors of our TLS mode,
 //! including certificate loading and supported modes.
 
-use dropshot::{ConfigDropshot, ConfigTls, HttpResponseOk, HttpServerStarter};
+use dropshot::{
+    Body, ClientAuthMode, ConfigDropshot, ConfigListener, ConfigTls,
+    ConfigTlsClientAuth, ConfigTlsSniCertificate, HttpResponseOk,
+    HttpServerStarter,
+};
+use http_body_util::BodyExt;
+use hyper_util::client::legacy::connect::{Connect, HttpConnector};
+use hyper_util::client::legacy::Client;
+use hyper_util::rt::TokioExecutor;
 use slog::{o, Logger};
 use std::convert::TryFrom;
 use std::path::Path;
@@ -12,6 +20,7 @@ use std::time::SystemTime;
 
 pub mod common;
 use common::create_log_context;
+use common::HttpsConnector;
 
 /// See rustls::client::ServerCertVerifier::verify_server_cert for argument
 /// meanings
@@ -48,19 +57,12 @@ fn make_https_client<
     T: rustls::client::ServerCertVerifier + Send + Sync + 'static,
 >(
     verifier: T,
-) -> hyper::Client<
-    hyper_rustls::HttpsConnector<hyper::client::connect::HttpConnector>,
-> {
+) -> Client<HttpsConnector, Body> {
     let tls_config = rustls::ClientConfig::builder()
         .with_safe_defaults()
         .with_custom_certificate_verifier(Arc::new(verifier))
         .with_no_client_auth();
-    let https_connector = hyper_rustls::HttpsConnectorBuilder::new()
-        .with_tls_config(tls_config)
-        .https_only()
-        .enable_http1()
-        .build();
-    hyper::Client::builder().build(https_connector)
+    Client::builder(TokioExecutor::new()).build(HttpsConnector::new(tls_config))
 }
 
 fn make_server(
@@ -74,7 +76,9 @@ fn make_server(
         tls: Some(ConfigTls::AsFile {
             cert_file: cert_file.to_path_buf(),
             key_file: key_file.to_path_buf(),
+            client_auth: None,
         }),
+        ..Default::default()
     };
     HttpServerStarter::new(&config, dropshot::ApiDescription::new(), 0, log)
         .unwrap()
@@ -105,7 +109,7 @@ async fn test_tls_certificate_loading() {
     let request = hyper::Request::builder()
         .method(http::method::Method::GET)
         .uri(&uri)
-        .body(hyper::Body::empty())
+        .body(Body::empty())
         .unwrap();
 
     let verifier_called = Arc::new(AtomicUsize::new(0));
@@ -159,14 +163,14 @@ async fn test_tls_only() {
     let https_request = hyper::Request::builder()
         .method(http::method::Method::GET)
         .uri(&https_uri)
-        .body(hyper::Body::empty())
+        .body(Body::empty())
         .unwrap();
     let http_uri: hyper::Uri =
         format!("http://localhost:{}/", port).parse().unwrap();
     let http_request = hyper::Request::builder()
         .method(http::method::Method::GET)
         .uri(&http_uri)
-        .body(hyper::Body::empty())
+        .body(Body::empty())
         .unwrap();
 
     let https_client = make_https_client(make_pki_verifier(&certs));
@@ -174,8 +178,12 @@ async fn test_tls_only() {
 
     // Send an HTTP request, it should fail due to incomplete message, since
     // the server and client are speaking different protocols
-    let http_client = hyper::Client::builder().build_http();
+    let http_client: Client<HttpConnector, Body> =
+        Client::builder(TokioExecutor::new()).build_http();
     let error = http_client.request(http_request).await.unwrap_err();
+    let error = std::error::Error::source(&error)
+        .and_then(|e| e.downcast_ref::<hyper::Error>())
+        .expect("expected hyper error");
     assert!(error.is_incomplete_message());
 
     // Make an HTTPS request again, to make sure the HTTP client didn't
@@ -183,7 +191,7 @@ async fn test_tls_only() {
     let https_request = hyper::Request::builder()
         .method(http::method::Method::GET)
         .uri(&https_uri)
-        .body(hyper::Body::empty())
+        .body(Body::empty())
         .unwrap();
     https_client.request(https_request).await.unwrap();
 
@@ -212,7 +220,7 @@ async fn test_tls_refresh_certificates() {
         hyper::Request::builder()
             .method(http::method::Method::GET)
             .uri(&https_uri)
-            .body(hyper::Body::empty())
+            .body(Body::empty())
             .unwrap()
     };
 
@@ -262,6 +270,7 @@ async fn test_tls_refresh_certificates() {
     let config = ConfigTls::AsFile {
         cert_file: cert_file.path().to_path_buf(),
         key_file: key_file.path().to_path_buf(),
+        client_auth: None,
     };
 
     // Refresh the server to use the new certificate chain.
@@ -282,6 +291,83 @@ async fn test_tls_refresh_certificates() {
     logctx.cleanup_successful();
 }
 
+/// Replaces the contents of the certificate and key files at `cert_file` and
+/// `key_file` with a new certificate chain, which is returned.
+fn replace_tls_files(
+    cert_file: &Path,
+    key_file: &Path,
+) -> Vec<rustls::Certificate> {
+    let (certs, key) = common::generate_tls_key();
+    let (cert_bytes, key_bytes) = common::tls_key_to_buffer(&certs, &key);
+    std::fs::write(cert_file, cert_bytes).unwrap();
+    std::fs::write(key_file, key_bytes).unwrap();
+    certs
+}
+
+#[tokio::test]
+async fn test_tls_reload() {
+    let logctx = create_log_context("test_tls_reload");
+    let log = logctx.log.new(o!());
+    let (certs, key) = common::generate_tls_key();
+    let (cert_file, key_file) = common::tls_key_to_file(&certs, &key);
+    let server = make_server(&log, cert_file.path(), key_file.path()).start();
+    let addr = server.local_addr();
+    assert_eq!(sni_certificate(addr, "localhost").await, certs[0]);
+
+    // Reloading picks up the new contents of the files.
+    let new_certs = replace_tls_files(cert_file.path(), key_file.path());
+    assert_eq!(sni_certificate(addr, "localhost").await, certs[0]);
+    server.reload_tls().await.unwrap();
+    assert_eq!(sni_certificate(addr, "localhost").await, new_certs[0]);
+
+    // If the files can't be loaded, the server keeps its certificates.
+    std::fs::write(key_file.path(), "not a key").unwrap();
+    server.reload_tls().await.unwrap_err();
+    assert_eq!(sni_certificate(addr, "localhost").await, new_certs[0]);
+
+    server.close().await.unwrap();
+    logctx.cleanup_successful();
+}
+
+#[tokio::test]
+async fn test_tls_reload_interval() {
+    let logctx = create_log_context("test_tls_reload_interval");
+    let log = logctx.log.new(o!());
+    let (certs, key) = common::generate_tls_key();
+    let (cert_file, key_file) = common::tls_key_to_file(&certs, &key);
+    let config = ConfigDropshot {
+        tls: Some(ConfigTls::AsFile {
+            cert_file: cert_file.path().to_path_buf(),
+            key_file: key_file.path().to_path_buf(),
+            client_auth: None,
+        }),
+        tls_reload_interval_ms: Some(10),
+        ..Default::default()
+    };
+    let server = HttpServerStarter::new(
+        &config,
+        dropshot::ApiDescription::new(),
+        0,
+        &log,
+    )
+    .unwrap()
+    .start();
+    let addr = server.local_addr();
+    assert_eq!(sni_certificate(addr, "localhost").await, certs[0]);
+
+    // The server notices that the files have changed and reloads them.
+    let new_certs = replace_tls_files(cert_file.path(), key_file.path());
+    let mut attempts = 0;
+    while sni_certificate(addr, "localhost").await != new_certs[0] {
+        attempts += 1;
+        assert!(attempts < 500, "server did not reload its certificates");
+        tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+    }
+
+    server.close().await.unwrap();
+    logctx.cleanup_successful();
+}
+
 #[tokio::test]
 async fn test_tls_aborted_negotiation() {
     let logctx = create_log_context("test_tls_aborted_negotiation");
@@ -347,7 +433,8 @@ async fn tls_check_handler(
     rqctx: dropshot::RequestContext<usize>,
     query: dropshot::Query<TlsCheckArgs>,
 ) -> Result<HttpResponseOk<()>, dropshot::HttpError> {
-    if rqctx.server.using_tls() != query.into_inner().tls {
+    let tls = query.into_inner().tls;
+    if rqctx.server.using_tls() != tls || rqctx.connection().is_tls() != tls {
         return Err(dropshot::HttpError::for_bad_request(
             None,
             "mismatch between expected and actual tls state".to_string(),
@@ -371,7 +458,9 @@ async fn test_server_is_https() {
         tls: Some(ConfigTls::AsFile {
             cert_file: cert_file.path().to_path_buf(),
             key_file: key_file.path().to_path_buf(),
+            client_auth: None,
         }),
+        ..Default::default()
     };
     let mut api = dropshot::ApiDescription::new();
     api.register(tls_check_handler).unwrap();
@@ -384,7 +473,7 @@ async fn test_server_is_https() {
     let https_request = hyper::Request::builder()
         .method(http::method::Method::GET)
         .uri(format!("https://localhost:{}/?tls=true", port))
-        .body(hyper::Body::empty())
+        .body(Body::empty())
         .unwrap();
     let res = https_client.request(https_request).await.unwrap();
     assert_eq!(res.status(), hyper::StatusCode::OK);
@@ -393,7 +482,7 @@ async fn test_server_is_https() {
     let https_request = hyper::Request::builder()
         .method(http::method::Method::GET)
         .uri(format!("https://localhost:{}/?tls=false", port))
-        .body(hyper::Body::empty())
+        .body(Body::empty())
         .unwrap();
     let res = https_client.request(https_request).await.unwrap();
     assert_eq!(res.status(), hyper::StatusCode::BAD_REQUEST);
@@ -403,6 +492,233 @@ async fn test_server_is_https() {
     logctx.cleanup_successful();
 }
 
+#[dropshot::endpoint {
+    method = GET,
+    path = "/peer",
+}]
+async fn peer_certificate_handler(
+    rqctx: dropshot::RequestContext<usize>,
+) -> Result<HttpResponseOk<Option<Vec<String>>>, dropshot::HttpError> {
+    let peer_certificate = rqctx.connection().peer_certificate();
+    if let Some(cert) = peer_certificate {
+        assert_eq!(cert.fingerprint_sha256().len(), 64);
+    }
+    Ok(HttpResponseOk(
+        peer_certificate.map(|cert| cert.subject_alt_names().to_vec()),
+    ))
+}
+
+/// Starts a server that authenticates clients whose certificates chain to the
+/// root certificate of `client_chain`.
+fn make_client_auth_server(
+    log: &Logger,
+    server_certs: &Vec<rustls::Certificate>,
+    server_key: &rustls::PrivateKey,
+    client_chain: &common::TestCertificateChain,
+    mode: ClientAuthMode,
+) -> dropshot::HttpServer<usize> {
+    let (certs, key) = common::tls_key_to_buffer(server_certs, server_key);
+    let client_root = client_chain.cert_chain().pop().unwrap();
+    let (ca_certs, _) = common::tls_key_to_buffer(
+        &vec![client_root],
+        &client_chain.end_cert_private_key(),
+    );
+    let config = ConfigDropshot {
+        tls: Some(ConfigTls::AsBytes {
+            certs,
+            key,
+            client_auth: Some(ConfigTlsClientAuth::AsBytes { ca_certs, mode }),
+        }),
+        ..Default::default()
+    };
+    let mut api = dropshot::ApiDescription::new();
+    api.register(peer_certificate_handler).unwrap();
+    HttpServerStarter::new(&config, api, 0, log).unwrap().start()
+}
+
+/// Makes an HTTPS client that presents the end-entity certificate of
+/// `client_chain` (if any).
+fn make_client_auth_client(
+    server_certs: &Vec<rustls::Certificate>,
+    client_chain: Option<&common::TestCertificateChain>,
+) -> Client<HttpsConnector, Body> {
+    let builder = rustls::ClientConfig::builder()
+        .with_safe_defaults()
+        .with_custom_certificate_verifier(Arc::new(make_pki_verifier(
+            server_certs,
+        )));
+    let tls_config = match client_chain {
+        Some(chain) => {
+            let mut certs = chain.cert_chain();
+            // Omit the root, which the server already has.
+            certs.pop();
+            builder
+                .with_single_cert(certs, chain.end_cert_private_key())
+                .unwrap()
+        }
+        None => builder.with_no_client_auth(),
+    };
+    Client::builder(TokioExecutor::new()).build(HttpsConnector::new(tls_config))
+}
+
+async fn get_peer_subject_alt_names(
+    client: &Client<HttpsConnector, Body>,
+    port: u16,
+) -> Result<Option<Vec<String>>, hyper_util::client::legacy::Error> {
+    let request = hyper::Request::builder()
+        .method(http::method::Method::GET)
+        .uri(format!("https://localhost:{}/peer", port))
+        .body(Body::empty())
+        .unwrap();
+    let response = client.request(request).await?;
+    assert_eq!(response.status(), hyper::StatusCode::OK);
+    let body = response.into_body().collect().await.unwrap().to_bytes();
+    Ok(serde_json::from_slice(&body).unwrap())
+}
+
+#[tokio::test]
+async fn test_client_auth_required() {
+    let logctx = create_log_context("test_client_auth_required");
+    let log = logctx.log.new(o!());
+    let (server_certs, server_key) = common::generate_tls_key();
+    let client_chain = common::TestCertificateChain::new();
+    let server = make_client_auth_server(
+        &log,
+        &server_certs,
+        &server_key,
+        &client_chain,
+        ClientAuthMode::Require,
+    );
+    let port = server.local_addr().port();
+
+    // A client with a certificate signed by the configured CA connects, and
+    // its certificate is available to the handler.
+    let client = make_client_auth_client(&server_certs, Some(&client_chain));
+    let names = get_peer_subject_alt_names(&client, port).await.unwrap();
+    assert_eq!(names, Some(vec![String::from("localhost")]));
+
+    // A client without a certificate can't connect.
+    let client = make_client_auth_client(&server_certs, None);
+    get_peer_subject_alt_names(&client, port).await.unwrap_err();
+
+    // Nor can one whose certificate is signed by another CA.
+    let other_chain = common::TestCertificateChain::new();
+    let client = make_client_auth_client(&server_certs, Some(&other_chain));
+    get_peer_subject_alt_names(&client, port).await.unwrap_err();
+
+    server.close().await.unwrap();
+    logctx.cleanup_successful();
+}
+
+#[tokio::test]
+async fn test_client_auth_requested() {
+    let logctx = create_log_context("test_client_auth_requested");
+    let log = logctx.log.new(o!());
+    let (server_certs, server_key) = common::generate_tls_key();
+    let client_chain = common::TestCertificateChain::new();
+    let server = make_client_auth_server(
+        &log,
+        &server_certs,
+        &server_key,
+        &client_chain,
+        ClientAuthMode::Request,
+    );
+    let port = server.local_addr().port();
+
+    let client = make_client_auth_client(&server_certs, Some(&client_chain));
+    let names = get_peer_subject_alt_names(&client, port).await.unwrap();
+    assert_eq!(names, Some(vec![String::from("localhost")]));
+
+    // A client without a certificate connects, but has no peer certificate.
+    let client = make_client_auth_client(&server_certs, None);
+    let names = get_peer_subject_alt_names(&client, port).await.unwrap();
+    assert_eq!(names, None);
+
+    server.close().await.unwrap();
+    logctx.cleanup_successful();
+}
+
+/// Returns the end-entity certificate that the server at `addr` presents to a
+/// client that names `server_name` with SNI.
+async fn sni_certificate(
+    addr: std::net::SocketAddr,
+    server_name: &str,
+) -> rustls::Certificate {
+    let tls_config = rustls::ClientConfig::builder()
+        .with_safe_defaults()
+        .with_custom_certificate_verifier(Arc::new(CertificateVerifier(
+            Box::new(
+                |_end_entity: &rustls::Certificate,
+                 _intermediates: &[rustls::Certificate],
+                 _server_name: &rustls::ServerName,
+                 _scts: &mut dyn Iterator<Item = &[u8]>,
+                 _ocsp_response: &[u8],
+                 _now: SystemTime| {
+                    Ok(rustls::client::ServerCertVerified::assertion())
+                },
+            ),
+        )))
+        .with_no_client_auth();
+    let connector = tokio_rustls::TlsConnector::from(Arc::new(tls_config));
+    let tcp = tokio::net::TcpStream::connect(addr).await.unwrap();
+    let server_name = rustls::ServerName::try_from(server_name).unwrap();
+    let stream = connector.connect(server_name, tcp).await.unwrap();
+    let (_, session) = stream.get_ref();
+    session.peer_certificates().unwrap()[0].clone()
+}
+
+#[tokio::test]
+async fn test_sni_certificates() {
+    let logctx = create_log_context("test_sni_certificates");
+    let log = logctx.log.new(o!());
+
+    let (net_certs, net_key) = common::generate_tls_key();
+    let (net_cert_file, net_key_file) =
+        common::tls_key_to_file(&net_certs, &net_key);
+    let (com_certs, com_key) = common::generate_tls_key();
+    let (com_cert_file, com_key_file) =
+        common::tls_key_to_file(&com_certs, &com_key);
+
+    let config = ConfigDropshot {
+        tls: Some(ConfigTls::Sni {
+            certificates: vec![
+                ConfigTlsSniCertificate {
+                    hostnames: vec![String::from("example.net")],
+                    cert_file: net_cert_file.path().to_path_buf(),
+                    key_file: net_key_file.path().to_path_buf(),
+                },
+                ConfigTlsSniCertificate {
+                    hostnames: vec![String::from("*.Example.com")],
+                    cert_file: com_cert_file.path().to_path_buf(),
+                    key_file: com_key_file.path().to_path_buf(),
+                },
+            ],
+            client_auth: None,
+        }),
+        ..Default::default()
+    };
+    let server = HttpServerStarter::new(
+        &config,
+        dropshot::ApiDescription::new(),
+        0,
+        &log,
+    )
+    .unwrap()
+    .start();
+    let addr = server.local_addr();
+
+    assert_eq!(sni_certificate(addr, "example.net").await, net_certs[0]);
+    assert_eq!(sni_certificate(addr, "api.example.com").await, com_certs[0]);
+    assert_eq!(sni_certificate(addr, "API.EXAMPLE.COM").await, com_certs[0]);
+    // Hostnames without a chain of their own get the first one.
+    assert_eq!(sni_certificate(addr, "example.com").await, net_certs[0]);
+    assert_eq!(sni_certificate(addr, "a.b.example.com").await, net_certs[0]);
+    assert_eq!(sni_certificate(addr, "localhost").await, net_certs[0]);
+
+    server.close().await.unwrap();
+    logctx.cleanup_successful();
+}
+
 #[tokio::test]
 async fn test_server_is_http() {
     let mut api = dropshot::ApiDescription::new();
@@ -434,3 +750,85 @@ async fn test_server_is_http() {
         .await
         .expect_err("expected failure");
 }
+
+#[dropshot::endpoint {
+    method = GET,
+    path = "/listener",
+}]
+async fn listener_handler(
+    rqctx: dropshot::RequestContext<usize>,
+) -> Result<HttpResponseOk<(u16, bool)>, dropshot::HttpError> {
+    let connection = rqctx.connection();
+    Ok(HttpResponseOk((connection.local_addr().port(), connection.is_tls())))
+}
+
+async fn get_listener<C: Connect + Clone + Send + Sync + 'static>(
+    client: &Client<C, Body>,
+    scheme: &str,
+    port: u16,
+) -> (u16, bool) {
+    let uri = format!("{}://localhost:{}/listener", scheme, port);
+    let request = hyper::Request::get(uri).body(Body::empty()).unwrap();
+    let mut response = client.request(request).await.unwrap();
+    assert_eq!(response.status(), hyper::StatusCode::OK);
+    let body = response.body_mut().collect().await.unwrap().to_bytes();
+    serde_json::from_slice(&body).unwrap()
+}
+
+#[tokio::test]
+async fn test_additional_listeners() {
+    let logctx = create_log_context("additional_listeners");
+    let log = logctx.log.new(o!());
+    let (certs, key) = common::generate_tls_key();
+    let (cert_file, key_file) = common::tls_key_to_file(&certs, &key);
+
+    // The main listener is plain HTTP, and there's one additional listener
+    // with TLS and one without.
+    let config = ConfigDropshot {
+        bind_address: "127.0.0.1:0".parse().unwrap(),
+        additional_listeners: vec![
+            ConfigListener {
+                bind_address: "127.0.0.1:0".parse().unwrap(),
+                tls: Some(ConfigTls::AsFile {
+                    cert_file: cert_file.path().to_path_buf(),
+                    key_file: key_file.path().to_path_buf(),
+                    client_auth: None,
+                }),
+            },
+            ConfigListener {
+                bind_address: "127.0.0.1:0".parse().unwrap(),
+                tls: None,
+            },
+        ],
+        ..Default::default()
+    };
+    let mut api = dropshot::ApiDescription::new();
+    api.register(listener_handler).unwrap();
+    let server = HttpServerStarter::new(&config, api, 0, &log).unwrap().start();
+    let main_port = server.local_addr().port();
+    let additional = server.additional_local_addrs().to_vec();
+    assert_eq!(additional.len(), 2);
+    assert!(!server.using_tls());
+
+    let http_client = Client::builder(TokioExecutor::new()).build_http();
+    let https_client = make_https_client(make_pki_verifier(&certs));
+    assert_eq!(
+        get_listener(&http_client, "http", main_port).await,
+        (main_port, false)
+    );
+    assert_eq!(
+        get_listener(&https_client, "https", additional[0].port()).await,
+        (additional[0].port(), true)
+    );
+    assert_eq!(
+        get_listener(&http_client, "http", additional[1].port()).await,
+        (additional[1].port(), false)
+    );
+
+    // Closing the server closes all of its listeners.
+    server.close().await.unwrap();
+    for addr in &additional {
+        assert!(tokio::net::TcpStream::connect(addr).await.is_err());
+    }
+    logctx.cleanup_successful();
+}
@@ -0,0 +1,118 @@
+// Copyright 2023 Oxide Computer Company
+
+//! Test cases for responses with trailers.
+
+use dropshot::endpoint;
+use dropshot::ApiDescription;
+use dropshot::Body;
+use dropshot::HttpError;
+use dropshot::RequestContext;
+use http::HeaderMap;
+use http::Method;
+use http::Response;
+use http::StatusCode;
+use http_body_util::BodyExt;
+use hyper::Request;
+
+pub mod common;
+
+const CHUNKS: [&str; 3] =
+    ["all work ", "and no play ", "makes jack a dull boy"];
+
+/// Returns a body made of `CHUNKS`, with a trailer carrying the sum of its
+/// bytes, which is computed as the chunks are sent.
+fn checksummed_body() -> Body {
+    let (checksum_tx, checksum_rx) = tokio::sync::oneshot::channel();
+    Body::wrap_stream(async_stream::stream! {
+        let mut checksum = 0u32;
+        for chunk in CHUNKS {
+            for byte in chunk.bytes() {
+                checksum = checksum.wrapping_add(u32::from(byte));
+            }
+            yield Ok::<_, std::io::Error>(chunk);
+        }
+        let _ = checksum_tx.send(checksum);
+    })
+    .with_trailers(async move {
+        let checksum = checksum_rx.await?;
+        let mut trailers = HeaderMap::new();
+        trailers.insert("x-checksum", checksum.into());
+        Ok::<_, tokio::sync::oneshot::error::RecvError>(trailers)
+    })
+}
+
+#[endpoint {
+    method = GET,
+    path = "/download",
+}]
+async fn download_get(
+    _rqctx: RequestContext<usize>,
+) -> Result<Response<Body>, HttpError> {
+    Ok(Response::builder()
+        .header(http::header::TRAILER, "x-checksum")
+        .body(checksummed_body())?)
+}
+
+#[endpoint {
+    method = GET,
+    path = "/download/throttled",
+    bandwidth_limit = 1048576,
+}]
+async fn download_throttled_get(
+    _rqctx: RequestContext<usize>,
+) -> Result<Response<Body>, HttpError> {
+    Ok(Response::builder()
+        .header(http::header::TRAILER, "x-checksum")
+        .body(checksummed_body())?)
+}
+
+fn api() -> ApiDescription<usize> {
+    let mut api = ApiDescription::new();
+    api.register(download_get).unwrap();
+    api.register(download_throttled_get).unwrap();
+    api
+}
+
+#[tokio::test]
+async fn test_trailers() {
+    let testctx = common::test_setup("trailers", api());
+    let client = &testctx.client_testctx;
+    let expected_checksum = CHUNKS
+        .iter()
+        .flat_map(|chunk| chunk.bytes())
+        .map(u32::from)
+        .sum::<u32>()
+        .to_string();
+
+    for path in ["/download", "/download/throttled"] {
+        let request = Request::builder()
+            .method(Method::GET)
+            .uri(client.url(path))
+            .header(http::header::TE, "trailers")
+            .body(Body::empty())
+            .unwrap();
+        let mut response = client
+            .make_request_with_request(request, StatusCode::OK)
+            .await
+            .unwrap();
+        let headers = response.headers();
+        assert_eq!(headers.get(http::header::TRAILER).unwrap(), "x-checksum");
+        assert!(headers.get(http::header::CONTENT_LENGTH).is_none());
+
+        let collected = response.body_mut().collect().await.unwrap();
+        let trailers = collected.trailers().unwrap().clone();
+        assert_eq!(collected.to_bytes(), CHUNKS.concat(), "{}", path);
+        assert_eq!(trailers["x-checksum"], expected_checksum.as_str());
+    }
+
+    // Clients that don't ask for trailers get the body without them.
+    let mut response = client
+        .make_request_no_body(Method::GET, "/download", StatusCode::OK)
+        .await
+        .unwrap();
+    let collected = response.body_mut().collect().await.unwrap();
+    assert!(collected.trailers().is_none());
+    assert_eq!(collected.to_bytes(), CHUNKS.concat());
+
+    testctx.teardown().await;
+}
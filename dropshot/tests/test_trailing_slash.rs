@@ -0,0 +1,80 @@
+// Copyright 2023 Oxide Computer Company
+
+//! Test cases for the routing of request paths with a trailing "/".
+
+use dropshot::endpoint;
+use dropshot::ApiDescription;
+use dropshot::HttpError;
+use dropshot::HttpResponseOk;
+use dropshot::RequestContext;
+use dropshot::TrailingSlash;
+use http::Method;
+use http::StatusCode;
+
+pub mod common;
+
+#[endpoint {
+    method = GET,
+    path = "/widgets",
+}]
+async fn widgets_get(
+    _rqctx: RequestContext<usize>,
+) -> Result<HttpResponseOk<Vec<String>>, HttpError> {
+    Ok(HttpResponseOk(vec![]))
+}
+
+fn api(trailing_slash: TrailingSlash) -> ApiDescription<usize> {
+    let mut api = ApiDescription::new().trailing_slash(trailing_slash);
+    api.register(widgets_get).unwrap();
+    api
+}
+
+#[tokio::test]
+async fn test_trailing_slash_redirect() {
+    let testctx = common::test_setup(
+        "trailing_slash_redirect",
+        api(TrailingSlash::Redirect),
+    );
+    let client = &testctx.client_testctx;
+
+    client
+        .make_request_no_body(Method::GET, "/widgets", StatusCode::OK)
+        .await
+        .unwrap();
+
+    for (path, location) in
+        [("/widgets/", "/widgets"), ("//widgets//?limit=3", "/widgets?limit=3")]
+    {
+        let response = client
+            .make_request_no_body(
+                Method::GET,
+                path,
+                StatusCode::PERMANENT_REDIRECT,
+            )
+            .await
+            .unwrap();
+        assert_eq!(
+            response.headers().get(http::header::LOCATION).unwrap(),
+            location
+        );
+    }
+
+    testctx.teardown().await;
+}
+
+#[tokio::test]
+async fn test_trailing_slash_strict() {
+    let testctx =
+        common::test_setup("trailing_slash_strict", api(TrailingSlash::Strict));
+    let client = &testctx.client_testctx;
+
+    client
+        .make_request_no_body(Method::GET, "/widgets", StatusCode::OK)
+        .await
+        .unwrap();
+    client
+        .make_request_error(Method::GET, "/widgets/", StatusCode::NOT_FOUND)
+        .await;
+
+    testctx.teardown().await;
+}
@@ -0,0 +1,138 @@
+// Copyright 2023 Oxide Computer Company
+
+//! Test cases for streaming newline-delimited JSON request bodies.
+
+use dropshot::endpoint;
+use dropshot::test_util::read_json;
+use dropshot::ApiDescription;
+use dropshot::Body;
+use dropshot::HttpError;
+use dropshot::HttpResponseOk;
+use dropshot::RequestContext;
+use dropshot::TypedStream;
+use dropshot::CONTENT_TYPE_NDJSON;
+use futures::TryStreamExt;
+use http::Method;
+use http::StatusCode;
+use hyper::Request;
+use schemars::JsonSchema;
+use serde::Deserialize;
+use serde::Serialize;
+
+pub mod common;
+
+#[derive(Debug, Deserialize, JsonSchema, PartialEq, Serialize)]
+struct Reading {
+    sensor: String,
+    value: i32,
+}
+
+#[endpoint {
+    method = POST,
+    path = "/readings",
+}]
+async fn readings_post(
+    _rqctx: RequestContext<usize>,
+    body: TypedStream<Reading>,
+) -> Result<HttpResponseOk<Vec<Reading>>, HttpError> {
+    Ok(HttpResponseOk(body.try_collect().await?))
+}
+
+fn api() -> ApiDescription<usize> {
+    let mut api = ApiDescription::new();
+    api.register(readings_post).unwrap();
+    api
+}
+
+/// Makes a request whose body is sent in the given chunks.
+fn request(
+    client: &dropshot::test_util::ClientTestContext,
+    content_type: &str,
+    chunks: &[&'static str],
+) -> Request<Body> {
+    let chunks: Vec<_> =
+        chunks.iter().map(|c| Ok::<_, std::io::Error>(*c)).collect();
+    Request::builder()
+        .method(Method::POST)
+        .uri(client.url("/readings"))
+        .header(http::header::CONTENT_TYPE, content_type)
+        .body(Body::wrap_stream(futures::stream::iter(chunks)))
+        .unwrap()
+}
+
+#[tokio::test]
+async fn test_typed_stream() {
+    let testctx = common::test_setup("typed_stream", api());
+    let client = &testctx.client_testctx;
+
+    // Records may be split across chunks, blank lines are skipped, and the
+    // last line needn't end with a newline.
+    let mut response = client
+        .make_request_with_request(
+            request(
+                client,
+                CONTENT_TYPE_NDJSON,
+                &[
+                    "{\"sensor\": \"t1\", \"value\": 1}\n{\"sensor\": \"t",
+                    "2\", \"value\": -2}\r\n\n",
+                    "{\"sensor\": \"t3\", \"value\": 3}",
+                ],
+            ),
+            StatusCode::OK,
+        )
+        .await
+        .unwrap();
+    let readings: Vec<Reading> = read_json(&mut response).await;
+    assert_eq!(
+        readings,
+        vec![
+            Reading { sensor: String::from("t1"), value: 1 },
+            Reading { sensor: String::from("t2"), value: -2 },
+            Reading { sensor: String::from("t3"), value: 3 },
+        ]
+    );
+
+    let error = client
+        .make_request_with_request(
+            request(
+                client,
+                CONTENT_TYPE_NDJSON,
+                &["{\"sensor\": \"t1\", \"value\": 1}\n\n{\"sensor\": \"t2\"}\n"],
+            ),
+            StatusCode::BAD_REQUEST,
+        )
+        .await
+        .unwrap_err();
+    assert!(
+        error.message.starts_with(
+            "unable to parse NDJSON body at line 3: missing field `value`"
+        ),
+        "{}",
+        error.message
+    );
+
+    let error = client
+        .make_request_with_request(
+            request(client, "application/json", &["{}"]),
+            StatusCode::BAD_REQUEST,
+        )
+        .await
+        .unwrap_err();
+    assert_eq!(
+        error.message,
+        "expected content type \"application/x-ndjson\", got \
+         \"application/json\""
+    );
+
+    testctx.teardown().await;
+}
+
+#[test]
+fn test_typed_stream_openapi() {
+    let spec = api().openapi("test", "1.0").json().unwrap();
+    let content = &spec["paths"]["/readings"]["post"]["requestBody"]["content"];
+    assert_eq!(
+        content[CONTENT_TYPE_NDJSON]["schema"]["$ref"],
+        "#/components/schemas/Reading"
+    );
+}
@@ -0,0 +1,140 @@
+// Copyright 2023 Oxide Computer Company
+
+//! Test cases for listening on a Unix domain socket.
+
+#![cfg(unix)]
+
+use dropshot::endpoint;
+use dropshot::ApiDescription;
+use dropshot::Body;
+use dropshot::ConfigDropshot;
+use dropshot::ConfigTls;
+use dropshot::ConfigUnixSocket;
+use dropshot::HttpError;
+use dropshot::HttpResponseOk;
+use dropshot::HttpServerStarter;
+use dropshot::RequestContext;
+use http::StatusCode;
+use http_body_util::BodyExt;
+use hyper::Request;
+use hyper_util::rt::TokioIo;
+use schemars::JsonSchema;
+use serde::Deserialize;
+use serde::Serialize;
+use slog::o;
+use std::os::unix::fs::PermissionsExt;
+use tokio::net::UnixStream;
+
+pub mod common;
+
+#[derive(Debug, Deserialize, JsonSchema, PartialEq, Serialize)]
+struct Peer {
+    uid: u32,
+    gid: u32,
+    pid: Option<i32>,
+}
+
+#[endpoint {
+    method = GET,
+    path = "/peer",
+}]
+async fn peer_get(
+    rqctx: RequestContext<()>,
+) -> Result<HttpResponseOk<Peer>, HttpError> {
+    let credentials = rqctx.connection().peer_credentials().unwrap();
+    Ok(HttpResponseOk(Peer {
+        uid: credentials.uid(),
+        gid: credentials.gid(),
+        pid: credentials.pid(),
+    }))
+}
+
+#[tokio::test]
+async fn test_unix_socket() {
+    let logctx = common::create_log_context("unix_socket");
+    let log = logctx.log.new(o!());
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("dropshot.sock");
+
+    let config = ConfigDropshot {
+        unix_socket: Some(ConfigUnixSocket {
+            path: path.clone(),
+            mode: Some(0o600),
+            uid: None,
+            gid: None,
+        }),
+        ..Default::default()
+    };
+    let mut api = ApiDescription::new();
+    api.register(peer_get).unwrap();
+    let server =
+        HttpServerStarter::new(&config, api, (), &log).unwrap().start();
+    let metadata = std::fs::metadata(&path).unwrap();
+    assert_eq!(metadata.permissions().mode() & 0o777, 0o600);
+
+    // Binding to a path that's in use fails.
+    assert!(HttpServerStarter::new(&config, ApiDescription::new(), (), &log)
+        .is_err());
+
+    let stream = UnixStream::connect(&path).await.unwrap();
+    let (mut sender, conn) =
+        hyper::client::conn::http1::handshake(TokioIo::new(stream))
+            .await
+            .unwrap();
+    let conn = tokio::spawn(conn);
+    let request = Request::get("/peer")
+        .header(http::header::HOST, "localhost")
+        .body(Body::empty())
+        .unwrap();
+    let mut response = sender.send_request(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    let body = response.body_mut().collect().await.unwrap().to_bytes();
+    let peer: Peer = serde_json::from_slice(&body).unwrap();
+    // Safety: these calls have no preconditions.
+    let (uid, gid) = unsafe { (libc::geteuid(), libc::getegid()) };
+    assert_eq!(peer.uid, uid);
+    assert_eq!(peer.gid, gid);
+    if let Some(pid) = peer.pid {
+        assert_eq!(pid as u32, std::process::id());
+    }
+
+    drop(sender);
+    conn.await.unwrap().unwrap();
+    server.close().await.unwrap();
+    // The socket file is removed when the server shuts down.
+    assert!(!path.exists());
+    logctx.cleanup_successful();
+}
+
+#[tokio::test]
+async fn test_unix_socket_tls() {
+    let logctx = common::create_log_context("unix_socket_tls");
+    let log = logctx.log.new(o!());
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("dropshot.sock");
+
+    let config = ConfigDropshot {
+        unix_socket: Some(ConfigUnixSocket {
+            path: path.clone(),
+            mode: None,
+            uid: None,
+            gid: None,
+        }),
+        tls: Some(ConfigTls::AsFile {
+            cert_file: dir.path().join("cert.pem"),
+            key_file: dir.path().join("key.pem"),
+            client_auth: None,
+        }),
+        ..Default::default()
+    };
+    let error =
+        HttpServerStarter::new(&config, ApiDescription::new(), (), &log)
+            .map(|_| ())
+            .unwrap_err();
+    assert_eq!(
+        error.to_string(),
+        "TLS is not supported on Unix domain sockets"
+    );
+    assert!(!path.exists());
+    logctx.cleanup_successful();
+}
@@ -0,0 +1,132 @@
+// Copyright 2023 Oxide Computer Company
+
+//! Test cases for URL-encoded request bodies that describe nested structures.
+
+use dropshot::endpoint;
+use dropshot::test_util::read_json;
+use dropshot::ApiDescription;
+use dropshot::HttpError;
+use dropshot::HttpResponseOk;
+use dropshot::RequestContext;
+use dropshot::TypedBody;
+use http::Method;
+use http::StatusCode;
+use schemars::JsonSchema;
+use serde::Deserialize;
+use serde::Serialize;
+
+pub mod common;
+
+#[derive(Debug, Deserialize, JsonSchema, PartialEq, Serialize)]
+struct Filter {
+    name: String,
+    min_age: Option<u8>,
+}
+
+#[derive(Debug, Deserialize, JsonSchema, PartialEq, Serialize)]
+struct Search {
+    filter: Filter,
+    #[serde(default)]
+    ids: Vec<u32>,
+}
+
+#[endpoint {
+    method = POST,
+    path = "/search",
+    content_type = "application/x-www-form-urlencoded",
+}]
+async fn search_post(
+    _rqctx: RequestContext<usize>,
+    body: TypedBody<Search>,
+) -> Result<HttpResponseOk<Search>, HttpError> {
+    Ok(HttpResponseOk(body.into_inner()))
+}
+
+fn api() -> ApiDescription<usize> {
+    let mut api = ApiDescription::new();
+    api.register(search_post).unwrap();
+    api
+}
+
+#[tokio::test]
+async fn test_urlencoded_nested() {
+    let testctx = common::test_setup("urlencoded_nested", api());
+    let client = &testctx.client_testctx;
+
+    // Brackets may be sent as they are or percent-encoded (as browsers do).
+    for body in [
+        "filter[name]=ada&filter[min_age]=30&ids[]=1&ids[]=2",
+        "filter%5Bname%5D=ada&filter%5Bmin_age%5D=30&ids%5B%5D=1&ids%5B%5D=2",
+        "ids[1]=2&ids[0]=1&filter[min_age]=30&filter[name]=ada",
+    ] {
+        let mut response = client
+            .make_request_with_body_url_encoded(
+                Method::POST,
+                "/search",
+                body.into(),
+                StatusCode::OK,
+            )
+            .await
+            .unwrap();
+        let search: Search = read_json(&mut response).await;
+        assert_eq!(
+            search,
+            Search {
+                filter: Filter { name: String::from("ada"), min_age: Some(30) },
+                ids: vec![1, 2],
+            }
+        );
+    }
+
+    // Empty values of optional fields, as forms send for blank inputs, are
+    // treated as missing.
+    let mut response = client
+        .make_request_with_body_url_encoded(
+            Method::POST,
+            "/search",
+            "filter[name]=ada&filter[min_age]=".into(),
+            StatusCode::OK,
+        )
+        .await
+        .unwrap();
+    let search: Search = read_json(&mut response).await;
+    assert_eq!(
+        search,
+        Search {
+            filter: Filter { name: String::from("ada"), min_age: None },
+            ids: vec![],
+        }
+    );
+
+    let error = client
+        .make_request_with_body_url_encoded(
+            Method::POST,
+            "/search",
+            "filter[name]=ada&ids[]=one".into(),
+            StatusCode::BAD_REQUEST,
+        )
+        .await
+        .unwrap_err();
+    assert_eq!(
+        error.message,
+        "unable to parse URL-encoded body: invalid digit found in string"
+    );
+
+    // A key without a value is an error, not an empty value.
+    let error = client
+        .make_request_with_body_url_encoded(
+            Method::POST,
+            "/search",
+            "filter[name]=ada&filter[min_age]".into(),
+            StatusCode::BAD_REQUEST,
+        )
+        .await
+        .unwrap_err();
+    assert_eq!(
+        error.message,
+        "unable to parse URL-encoded body: missing value for key \
+         \"filter[min_age]\""
+    );
+
+    testctx.teardown().await;
+}
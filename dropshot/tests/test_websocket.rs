@@ -0,0 +1,112 @@
+// Copyright 2023 Oxide Computer Company
+
+//! Test cases for logging on upgraded websocket connections.
+
+#![cfg(feature = "websocket")]
+
+use dropshot::channel;
+use dropshot::test_util::TestContext;
+use dropshot::ApiDescription;
+use dropshot::RequestContext;
+use dropshot::WebsocketChannelResult;
+use dropshot::WebsocketConnection;
+use dropshot::HEADER_REQUEST_ID;
+use futures::SinkExt;
+use futures::StreamExt;
+use std::collections::BTreeMap;
+use std::sync::Arc;
+use std::sync::Mutex;
+use tokio_tungstenite::tungstenite::protocol::Role;
+use tokio_tungstenite::tungstenite::Message;
+use tokio_tungstenite::WebSocketStream;
+
+/// Log drain that records the logger's key-value pairs for each entry with a
+/// given message
+#[derive(Clone)]
+struct ValuesDrain {
+    msg: &'static str,
+    entries: Arc<Mutex<Vec<BTreeMap<String, String>>>>,
+}
+
+impl slog::Drain for ValuesDrain {
+    type Ok = ();
+    type Err = slog::Never;
+
+    fn log(
+        &self,
+        record: &slog::Record,
+        values: &slog::OwnedKVList,
+    ) -> Result<(), slog::Never> {
+        if record.msg().to_string() == self.msg {
+            let mut collector = ValueCollector(BTreeMap::new());
+            slog::KV::serialize(values, record, &mut collector).unwrap();
+            self.entries.lock().unwrap().push(collector.0);
+        }
+        Ok(())
+    }
+}
+
+struct ValueCollector(BTreeMap<String, String>);
+
+impl slog::Serializer for ValueCollector {
+    fn emit_arguments(
+        &mut self,
+        key: slog::Key,
+        val: &std::fmt::Arguments,
+    ) -> slog::Result {
+        self.0.entry(key.to_string()).or_insert_with(|| val.to_string());
+        Ok(())
+    }
+}
+
+#[channel {
+    protocol = WEBSOCKETS,
+    path = "/ws",
+}]
+async fn ws_echo_request_id(
+    _rqctx: RequestContext<usize>,
+    conn: WebsocketConnection,
+) -> WebsocketChannelResult {
+    slog::info!(conn.log(), "websocket message");
+    let request_id = conn.request_id().to_string();
+    let mut ws_stream =
+        WebSocketStream::from_raw_socket(conn.into_inner(), Role::Server, None)
+            .await;
+    ws_stream.send(Message::Text(request_id)).await?;
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_websocket_log_correlation() {
+    let mut api = ApiDescription::new();
+    api.register(ws_echo_request_id).unwrap();
+    let drain =
+        ValuesDrain { msg: "websocket message", entries: Default::default() };
+    let log = slog::Logger::root(slog::Drain::fuse(drain.clone()), slog::o!());
+    let testctx =
+        TestContext::new(api, 0_usize, &Default::default(), None, log);
+
+    let url = format!("ws://{}/ws", testctx.client_testctx.bind_address);
+    let (mut ws, response) =
+        tokio_tungstenite::connect_async(url).await.unwrap();
+    let header_request_id = response
+        .headers()
+        .get(HEADER_REQUEST_ID)
+        .expect("upgrade response had no request id")
+        .to_str()
+        .unwrap()
+        .to_string();
+
+    // The connection's request id is that of the upgrade request.
+    let msg = ws.next().await.unwrap().unwrap();
+    assert_eq!(msg, Message::Text(header_request_id.clone()));
+
+    // The handler logged before sending its message, so the entry is there.
+    let entries = drain.entries.lock().unwrap().clone();
+    assert_eq!(entries.len(), 1);
+    assert_eq!(entries[0]["req_id"], header_request_id);
+    assert!(entries[0].contains_key("conn_id"), "{:?}", entries[0]);
+    assert_eq!(entries[0]["upgrade"], "websocket");
+
+    testctx.teardown().await;
+}
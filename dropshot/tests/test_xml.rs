@@ -0,0 +1,54 @@
+// Copyright 2023 Oxide Computer Company
+
+//! Test cases for XML request bodies.
+
+use dropshot::CONTENT_TYPE_XML;
+
+pub mod common;
+
+use common::reading::api;
+use common::reading::put_reading;
+use common::reading::put_reading_error;
+use common::reading::Reading;
+
+const READING: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<reading>
+  <sensor>t1</sensor>
+  <values>-3</values>
+  <values>300</values>
+</reading>"#;
+
+#[tokio::test]
+async fn test_xml_body() {
+    let testctx = common::test_setup("xml_body", api(&[CONTENT_TYPE_XML]));
+    let client = &testctx.client_testctx;
+
+    for content_type in [CONTENT_TYPE_XML, "text/xml; charset=utf-8"] {
+        assert_eq!(
+            put_reading(client, content_type, READING).await,
+            Reading::example()
+        );
+    }
+
+    // Malformed bodies and bodies of other types are rejected.
+    let message = put_reading_error(
+        client,
+        CONTENT_TYPE_XML,
+        "<reading><sensor>t1</reading>",
+    )
+    .await;
+    assert!(message.starts_with("unable to parse XML body: "), "{}", message);
+    assert_eq!(
+        put_reading_error(client, "application/json", "{}").await,
+        "expected content type \"application/xml\", got \"application/json\""
+    );
+
+    testctx.teardown().await;
+}
+
+#[test]
+fn test_xml_openapi() {
+    let spec = api(&[CONTENT_TYPE_XML]).openapi("test", "1.0").json().unwrap();
+    let content = &spec["paths"]["/reading"]["put"]["requestBody"]["content"];
+    assert!(content.get(CONTENT_TYPE_XML).is_some(), "{}", content);
+}
@@ -0,0 +1,57 @@
+// Copyright 2023 Oxide Computer Company
+
+//! Test cases for YAML request bodies.
+
+use dropshot::CONTENT_TYPE_JSON;
+use dropshot::CONTENT_TYPE_YAML;
+
+pub mod common;
+
+use common::reading::api;
+use common::reading::put_reading;
+use common::reading::put_reading_error;
+use common::reading::Reading;
+
+const READING: &str = "sensor: t1\nvalues:\n  - -3\n  - 300\n";
+
+#[tokio::test]
+async fn test_yaml_body() {
+    let testctx = common::test_setup("yaml_body", api(&[CONTENT_TYPE_YAML]));
+    let client = &testctx.client_testctx;
+
+    for content_type in [CONTENT_TYPE_YAML, "application/x-yaml", "text/yaml"] {
+        assert_eq!(
+            put_reading(client, content_type, READING).await,
+            Reading::example()
+        );
+    }
+
+    // Malformed bodies, bodies that don't match the type, and bodies of other
+    // types are rejected.
+    let message =
+        put_reading_error(client, CONTENT_TYPE_YAML, "sensor: [t1").await;
+    assert!(message.starts_with("unable to parse YAML body: "), "{}", message);
+    assert_eq!(
+        put_reading_error(client, CONTENT_TYPE_YAML, "sensor: t1\n").await,
+        "unable to parse YAML body: missing field `values`"
+    );
+    assert_eq!(
+        put_reading_error(client, "application/json", "{}").await,
+        "expected content type \"application/yaml\", got \"application/json\""
+    );
+
+    testctx.teardown().await;
+}
+
+#[test]
+fn test_yaml_openapi() {
+    let request_content = |content_type: &str| {
+        let spec = api(&[content_type]).openapi("test", "1.0").json().unwrap();
+        spec["paths"]["/reading"]["put"]["requestBody"]["content"][content_type]
+            .clone()
+    };
+    assert_eq!(
+        request_content(CONTENT_TYPE_YAML),
+        request_content(CONTENT_TYPE_JSON)
+    );
+}
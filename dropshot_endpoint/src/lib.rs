@@ -19,6 +19,7 @@ use syn::spanned::Spanned;
 
 use syn_parsing::ItemFnForSignature;
 
+mod path_params;
 mod syn_parsing;
 
 #[allow(non_snake_case)]
@@ -56,6 +57,14 @@ struct EndpointMetadata {
     #[serde(default)]
     deprecated: bool,
     content_type: Option<String>,
+    additional_content_types: Option<Vec<String>>,
+    log_level: Option<String>,
+    idempotency: Option<String>,
+    coalesce: Option<Vec<String>>,
+    bandwidth_limit: Option<u64>,
+    request_body_max_bytes: Option<usize>,
+    compression: Option<bool>,
+    timeout_secs: Option<u64>,
     _dropshot_crate: Option<String>,
 }
 
@@ -75,10 +84,20 @@ struct ChannelMetadata {
     unpublished: bool,
     #[serde(default)]
     deprecated: bool,
+    log_level: Option<String>,
     _dropshot_crate: Option<String>,
 }
 
 const DROPSHOT: &str = "dropshot";
+/// media types supported for request bodies
+const CONTENT_TYPES: &[&str] = &[
+    "application/json",
+    "application/x-www-form-urlencoded",
+    "application/msgpack",
+    "application/cbor",
+    "application/xml",
+    "application/yaml",
+];
 const USAGE: &str = "Endpoint handlers must have the following signature:
     async fn(
         rqctx: dropshot::RequestContext<MyContext>,
@@ -104,11 +123,30 @@ const USAGE: &str = "Endpoint handlers must have the following signature:
 ///     // Optional tags for the operation's description
 ///     tags = [ "all", "your", "OpenAPI", "tags" ],
 ///     // Specifies the media type used to encode the request body
-///     content_type = { "application/json" | "application/x-www-form-urlencoded" }
+///     content_type = { "application/json" | "application/x-www-form-urlencoded" | "application/msgpack" | "application/cbor" | "application/xml" | "application/yaml" }
+///     // Other media types accepted for the request body (from the same list)
+///     additional_content_types = [ "application/x-www-form-urlencoded" ],
 ///     // A value of `true` marks the operation as deprecated
 ///     deprecated = { true | false },
 ///     // A value of `true` causes the operation to be omitted from the API description
 ///     unpublished = { true | false },
+///     // Overrides the level of request-scoped log entries for this endpoint
+///     log_level = { "trace" | "debug" | "info" | "warn" | "error" | "critical" },
+///     // Whether requests may (or must) carry an `Idempotency-Key` header
+///     idempotency = { "optional" | "required" },
+///     // Coalesces concurrent GET requests with the same path, query string,
+///     // and values of these headers
+///     coalesce = [ "list", "of", "headers" ],
+///     // Limits the combined bandwidth (in bytes per second) of responses
+///     bandwidth_limit = 1048576,
+///     // Overrides the server's maximum request body size for this endpoint
+///     request_body_max_bytes = 10485760,
+///     // A value of `false` prevents the endpoint's responses from being
+///     // compressed
+///     compression = { true | false },
+///     // Cancels the handler and fails the request with a 503 if the handler
+///     // runs for longer than this many seconds
+///     timeout_secs = 30,
 /// }]
 /// ```
 ///
@@ -158,6 +196,34 @@ pub fn channel(
     do_output(do_channel(attr.into(), item.into()))
 }
 
+/// Derives `serde::Deserialize` for a struct of path parameters (for use with
+/// `dropshot::Path`) by parsing each field with its type's `FromStr`
+/// implementation.  Fields that can't be parsed from a single path segment
+/// (e.g., nested structs) fail to compile, and a value that doesn't parse is
+/// reported along with the name of its parameter.  A field for a wildcard
+/// parameter (e.g., `{rest:.*}`) may be a `Vec<T>` of such types.
+///
+/// As with `schemars::JsonSchema`, which the struct must also implement, a
+/// field's parameter may be renamed with `#[serde(rename = "...")]`.  No other
+/// serde attributes are supported.
+///
+/// ```ignore
+/// #[derive(dropshot::PathParams, schemars::JsonSchema)]
+/// struct InstancePath {
+///     project: String,
+///     #[serde(rename = "instance")]
+///     instance_id: u64,
+/// }
+/// ```
+#[proc_macro_derive(PathParams, attributes(serde))]
+pub fn derive_path_params(
+    item: proc_macro::TokenStream,
+) -> proc_macro::TokenStream {
+    path_params::do_derive_path_params(item.into())
+        .unwrap_or_else(|e| e.to_compile_error())
+        .into()
+}
+
 fn do_channel(
     attr: proc_macro2::TokenStream,
     item: proc_macro2::TokenStream,
@@ -168,6 +234,7 @@ fn do_channel(
         tags,
         unpublished,
         deprecated,
+        log_level,
         _dropshot_crate,
     } = from_tokenstream(&attr)?;
     match protocol {
@@ -242,6 +309,14 @@ fn do_channel(
                 unpublished,
                 deprecated,
                 content_type: Some("application/json".to_string()),
+                additional_content_types: None,
+                log_level,
+                idempotency: None,
+                coalesce: None,
+                bandwidth_limit: None,
+                request_body_max_bytes: None,
+                compression: None,
+                timeout_secs: None,
                 _dropshot_crate,
             };
             do_endpoint_inner(metadata, attr, new_item)
@@ -278,10 +353,12 @@ fn do_endpoint_inner(
     let path = metadata.path;
     let content_type =
         metadata.content_type.unwrap_or_else(|| "application/json".to_string());
-    if !matches!(
-        content_type.as_str(),
-        "application/json" | "application/x-www-form-urlencoded"
-    ) {
+    let additional_content_types =
+        metadata.additional_content_types.unwrap_or_default();
+    if std::iter::once(&content_type)
+        .chain(&additional_content_types)
+        .any(|content_type| !CONTENT_TYPES.contains(&content_type.as_str()))
+    {
         return Err(Error::new_spanned(
             &attr,
             "invalid content type for endpoint",
@@ -389,6 +466,93 @@ fn do_endpoint_inner(
 
     let dropshot = get_crate(metadata._dropshot_crate);
 
+    let log_level = match metadata.log_level.as_deref() {
+        None => quote! {},
+        Some(level) => {
+            let level_ident = match level {
+                "trace" => "Trace",
+                "debug" => "Debug",
+                "info" => "Info",
+                "warn" => "Warn",
+                "error" => "Error",
+                "critical" => "Critical",
+                _ => {
+                    return Err(Error::new_spanned(
+                        &attr,
+                        "invalid log level for endpoint",
+                    ));
+                }
+            };
+            let level_ident = format_ident!("{}", level_ident);
+            quote! {
+                .log_level(#dropshot::ConfigLoggingLevel::#level_ident)
+            }
+        }
+    };
+
+    let idempotency = match metadata.idempotency.as_deref() {
+        None => quote! {},
+        Some(mode) => {
+            let mode_ident = match mode {
+                "optional" => "Optional",
+                "required" => "Required",
+                _ => {
+                    return Err(Error::new_spanned(
+                        &attr,
+                        "invalid idempotency mode for endpoint",
+                    ));
+                }
+            };
+            let mode_ident = format_ident!("{}", mode_ident);
+            quote! {
+                .idempotency(#dropshot::IdempotencyMode::#mode_ident)
+            }
+        }
+    };
+
+    let coalesce = match &metadata.coalesce {
+        None => quote! {},
+        Some(headers) => quote! {
+            .coalesce(&[#(#headers),*])
+        },
+    };
+
+    let additional_content_types = if additional_content_types.is_empty() {
+        quote! {}
+    } else {
+        quote! {
+            .additional_content_types(&[#(#additional_content_types),*])
+        }
+    };
+
+    let bandwidth_limit = match metadata.bandwidth_limit {
+        None => quote! {},
+        Some(bytes_per_sec) => quote! {
+            .bandwidth_limit(#bytes_per_sec)
+        },
+    };
+
+    let request_body_max_bytes = match metadata.request_body_max_bytes {
+        None => quote! {},
+        Some(max_bytes) => quote! {
+            .request_body_max_bytes(#max_bytes)
+        },
+    };
+
+    let compression = match metadata.compression {
+        None => quote! {},
+        Some(compression) => quote! {
+            .compression(#compression)
+        },
+    };
+
+    let timeout = match metadata.timeout_secs {
+        None => quote! {},
+        Some(secs) => quote! {
+            .timeout(std::time::Duration::from_secs(#secs))
+        },
+    };
+
     let first_arg = match ast.sig.inputs.first() {
         Some(syn::FnArg::Typed(syn::PatType {
             attrs: _,
@@ -543,18 +707,11 @@ fn do_endpoint_inner(
                         <#ret_ty as ResultTrait>::T,
                     );
 
-                    // Verify that the error result is of type HttpError.
-                    trait TypeEq {
-                        type This: ?Sized;
-                    }
-
-                    impl<T: ?Sized> TypeEq for T {
-                        type This = Self;
-                    }
-
+                    // Verify that the error result implements the
+                    // HttpResponseError trait (as HttpError does).
                     fn validate_result_error_type<T>()
                     where
-                        T: ?Sized + TypeEq<This = #dropshot::HttpError>,
+                        T: ?Sized + #dropshot::HttpResponseError,
                     {
                     }
 
@@ -590,6 +747,15 @@ fn do_endpoint_inner(
             #(#tags)*
             #visible
             #deprecated
+            #log_level
+            #idempotency
+            #additional_content_types
+            #coalesce
+            #bandwidth_limit
+            #request_body_max_bytes
+            #compression
+            #timeout
+            .source_location(concat!(file!(), ":", line!()))
         }
     } else {
         quote! {
@@ -783,15 +949,9 @@ mod tests {
                 struct NeedHttpResponse(
                     <Result<HttpResponseOk<()>, HttpError> as ResultTrait>::T,
                 );
-                trait TypeEq {
-                    type This: ?Sized;
-                }
-                impl<T: ?Sized> TypeEq for T {
-                    type This = Self;
-                }
                 fn validate_result_error_type<T>()
                 where
-                    T: ?Sized + TypeEq<This = dropshot::HttpError>,
+                    T: ?Sized + dropshot::HttpResponseError,
                 {
                 }
                 validate_result_error_type::<
@@ -833,6 +993,7 @@ mod tests {
                         "application/json",
                         "/a/b/c",
                     )
+                    .source_location(concat!(file!(), ":", line!()))
                 }
             }
         };
@@ -875,15 +1036,9 @@ mod tests {
                 struct NeedHttpResponse(
                     <std::Result<dropshot::HttpResponseOk<()>, dropshot::HttpError> as ResultTrait>::T,
                 );
-                trait TypeEq {
-                    type This: ?Sized;
-                }
-                impl<T: ?Sized> TypeEq for T {
-                    type This = Self;
-                }
                 fn validate_result_error_type<T>()
                 where
-                    T: ?Sized + TypeEq<This = dropshot::HttpError>,
+                    T: ?Sized + dropshot::HttpResponseError,
                 {
                 }
                 validate_result_error_type::<
@@ -921,6 +1076,7 @@ mod tests {
                         "application/json",
                         "/a/b/c",
                     )
+                    .source_location(concat!(file!(), ":", line!()))
                 }
             }
         };
@@ -974,15 +1130,9 @@ mod tests {
                 struct NeedHttpResponse(
                     <Result<HttpResponseOk<()>, HttpError> as ResultTrait>::T,
                 );
-                trait TypeEq {
-                    type This: ?Sized;
-                }
-                impl<T: ?Sized> TypeEq for T {
-                    type This = Self;
-                }
                 fn validate_result_error_type<T>()
                 where
-                    T: ?Sized + TypeEq<This = dropshot::HttpError>,
+                    T: ?Sized + dropshot::HttpResponseError,
                 {
                 }
                 validate_result_error_type::<
@@ -1027,6 +1177,7 @@ mod tests {
                         "application/json",
                         "/a/b/c",
                     )
+                    .source_location(concat!(file!(), ":", line!()))
                 }
             }
         };
@@ -1080,15 +1231,9 @@ mod tests {
                 struct NeedHttpResponse(
                     <Result<HttpResponseOk<()>, HttpError> as ResultTrait>::T,
                 );
-                trait TypeEq {
-                    type This: ?Sized;
-                }
-                impl<T: ?Sized> TypeEq for T {
-                    type This = Self;
-                }
                 fn validate_result_error_type<T>()
                 where
-                    T: ?Sized + TypeEq<This = dropshot::HttpError>,
+                    T: ?Sized + dropshot::HttpResponseError,
                 {
                 }
                 validate_result_error_type::<
@@ -1133,6 +1278,7 @@ mod tests {
                         "application/json",
                         "/a/b/c",
                     )
+                    .source_location(concat!(file!(), ":", line!()))
                 }
             }
         };
@@ -1177,15 +1323,9 @@ mod tests {
                 struct NeedHttpResponse(
                     <Result<HttpResponseOk<()>, HttpError> as ResultTrait>::T,
                 );
-                trait TypeEq {
-                    type This: ?Sized;
-                }
-                impl<T: ?Sized> TypeEq for T {
-                    type This = Self;
-                }
                 fn validate_result_error_type<T>()
                 where
-                    T: ?Sized + TypeEq<This = dropshot::HttpError>,
+                    T: ?Sized + dropshot::HttpResponseError,
                 {
                 }
                 validate_result_error_type::<
@@ -1229,6 +1369,7 @@ mod tests {
                     )
                     .tag("stuff")
                     .tag("things")
+                    .source_location(concat!(file!(), ":", line!()))
                 }
             }
         };
@@ -1273,15 +1414,9 @@ mod tests {
                 struct NeedHttpResponse(
                     <Result<HttpResponseOk<()>, HttpError> as ResultTrait>::T,
                 );
-                trait TypeEq {
-                    type This: ?Sized;
-                }
-                impl<T: ?Sized> TypeEq for T {
-                    type This = Self;
-                }
                 fn validate_result_error_type<T>()
                 where
-                    T: ?Sized + TypeEq<This = dropshot::HttpError>,
+                    T: ?Sized + dropshot::HttpResponseError,
                 {
                 }
                 validate_result_error_type::<
@@ -1325,6 +1460,7 @@ mod tests {
                         "/a/b/c",
                     )
                     .summary("handle \"xyz\" requests")
+                    .source_location(concat!(file!(), ":", line!()))
                 }
             }
         };
@@ -1365,6 +1501,27 @@ mod tests {
         assert_eq!("expected a string, but found `/`", msg);
     }
 
+    #[test]
+    fn test_endpoint_bad_additional_content_type() {
+        let ret = do_endpoint(
+            quote! {
+                method = POST,
+                path = "/a/b/c",
+                additional_content_types = [ "text/plain" ]
+            },
+            quote! {
+                pub async fn handler_xyz(
+                    _rqctx: RequestContext<()>,
+                ) -> Result<HttpResponseOk<()>, HttpError> {
+                    Ok(())
+                }
+            },
+        );
+
+        let msg = format!("{}", ret.err().unwrap());
+        assert_eq!("invalid content type for endpoint", msg);
+    }
+
     #[test]
     fn test_endpoint_bad_metadata() {
         let ret = do_endpoint(
@@ -1381,6 +1538,40 @@ mod tests {
         assert_eq!("extraneous member `methud`", msg);
     }
 
+    #[test]
+    fn test_endpoint_bad_log_level() {
+        let ret = do_endpoint(
+            quote! {
+                method = GET,
+                path = "/a/b/c",
+                log_level = "verbose",
+            },
+            quote! {
+                async fn handler_xyz(_rqctx: RequestContext<()>) {}
+            },
+        );
+
+        let msg = format!("{}", ret.err().unwrap());
+        assert_eq!("invalid log level for endpoint", msg);
+    }
+
+    #[test]
+    fn test_endpoint_bad_idempotency() {
+        let ret = do_endpoint(
+            quote! {
+                method = POST,
+                path = "/a/b/c",
+                idempotency = "always",
+            },
+            quote! {
+                async fn handler_xyz(_rqctx: RequestContext<()>) {}
+            },
+        );
+
+        let msg = format!("{}", ret.err().unwrap());
+        assert_eq!("invalid idempotency mode for endpoint", msg);
+    }
+
     #[test]
     fn test_endpoint_not_async() {
         let (_, errors) = do_endpoint(
@@ -1478,15 +1669,9 @@ mod tests {
                 struct NeedHttpResponse(
                     <Result<HttpResponseOk<()>, HttpError> as ResultTrait>::T,
                 );
-                trait TypeEq {
-                    type This: ?Sized;
-                }
-                impl<T: ?Sized> TypeEq for T {
-                    type This = Self;
-                }
                 fn validate_result_error_type<T>()
                 where
-                    T: ?Sized + TypeEq<This = dropshot::HttpError>,
+                    T: ?Sized + dropshot::HttpResponseError,
                 {
                 }
                 validate_result_error_type::<
@@ -1528,6 +1713,7 @@ mod tests {
                         "application/x-www-form-urlencoded",
                         "/a/b/c",
                     )
+                    .source_location(concat!(file!(), ":", line!()))
                 }
             }
         };
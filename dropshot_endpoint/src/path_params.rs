@@ -0,0 +1,294 @@
+// Copyright 2023 Oxide Computer Company
+
+//! Implementation of `#[derive(PathParams)]`, which deserializes a struct from
+//! path parameters using the `FromStr` implementations of its fields' types.
+
+use proc_macro2::TokenStream;
+use quote::format_ident;
+use quote::quote;
+use quote::quote_spanned;
+use syn::spanned::Spanned;
+
+/// A field of the struct, as a path parameter.
+struct PathParamField<'a> {
+    ident: &'a syn::Ident,
+    /// name of the path parameter
+    name: String,
+    ty: &'a syn::Type,
+    /// the element type, for (wildcard) parameters of type `Vec<_>`
+    item_ty: Option<&'a syn::Type>,
+}
+
+pub(crate) fn do_derive_path_params(
+    item: TokenStream,
+) -> Result<TokenStream, syn::Error> {
+    let input: syn::DeriveInput = syn::parse2(item)?;
+
+    if !input.generics.params.is_empty() {
+        return Err(syn::Error::new_spanned(
+            &input.generics,
+            "PathParams cannot be derived for generic types",
+        ));
+    }
+    if let Some(attr) = input.attrs.iter().find(|a| a.path.is_ident("serde")) {
+        return Err(syn::Error::new_spanned(
+            attr,
+            "PathParams does not support container-level serde attributes",
+        ));
+    }
+    let fields =
+        match &input.data {
+            syn::Data::Struct(syn::DataStruct {
+                fields: syn::Fields::Named(fields),
+                ..
+            }) => &fields.named,
+            _ => return Err(syn::Error::new_spanned(
+                &input.ident,
+                "PathParams can only be derived for structs with named fields",
+            )),
+        };
+    let fields =
+        fields.iter().map(path_param_field).collect::<Result<Vec<_>, _>>()?;
+
+    let dropshot = crate::get_crate(None);
+    let ident = &input.ident;
+    let vars = (0..fields.len())
+        .map(|i| format_ident!("__field{}", i))
+        .collect::<Vec<_>>();
+    let idents = fields.iter().map(|f| f.ident);
+    let names = fields.iter().map(|f| &f.name).collect::<Vec<_>>();
+    let tys = fields.iter().map(|f| f.ty);
+    // Parsing happens through functions bounded by `FromStr`, so that fields
+    // of other types (e.g., nested structs) fail to compile.  The spans point
+    // those errors at the offending fields.
+    let parses = fields.iter().map(|f| {
+        let name = &f.name;
+        match f.item_ty {
+            Some(item_ty) => quote_spanned! {f.ty.span()=>
+                #dropshot::__path_params::parse_all::<#item_ty, __A::Error>(
+                    #name,
+                    __map.next_value()?,
+                )?
+            },
+            None => {
+                let ty = f.ty;
+                quote_spanned! {f.ty.span()=>
+                    #dropshot::__path_params::parse::<#ty, __A::Error>(
+                        #name,
+                        __map.next_value()?,
+                    )?
+                }
+            }
+        }
+    });
+
+    Ok(quote! {
+        const _: () = {
+            use #dropshot::__path_params::serde;
+
+            impl<'de> serde::Deserialize<'de> for #ident {
+                fn deserialize<__D>(
+                    __deserializer: __D,
+                ) -> ::std::result::Result<Self, __D::Error>
+                where
+                    __D: serde::Deserializer<'de>,
+                {
+                    struct __Visitor;
+
+                    impl<'de> serde::de::Visitor<'de> for __Visitor {
+                        type Value = #ident;
+
+                        fn expecting(
+                            &self,
+                            __formatter: &mut ::std::fmt::Formatter,
+                        ) -> ::std::fmt::Result {
+                            __formatter.write_str("path parameters")
+                        }
+
+                        fn visit_map<__A>(
+                            self,
+                            mut __map: __A,
+                        ) -> ::std::result::Result<#ident, __A::Error>
+                        where
+                            __A: serde::de::MapAccess<'de>,
+                        {
+                            #(
+                                let mut #vars: ::std::option::Option<#tys> =
+                                    ::std::option::Option::None;
+                            )*
+                            while let ::std::option::Option::Some(__key) =
+                                __map.next_key::<::std::string::String>()?
+                            {
+                                match __key.as_str() {
+                                    #(
+                                        #names => {
+                                            #vars = ::std::option::Option::Some(
+                                                #parses
+                                            );
+                                        }
+                                    )*
+                                    _ => {
+                                        __map.next_value::<
+                                            serde::de::IgnoredAny
+                                        >()?;
+                                    }
+                                }
+                            }
+                            ::std::result::Result::Ok(#ident {
+                                #(
+                                    #idents: #vars.ok_or_else(|| {
+                                        <__A::Error as serde::de::Error>
+                                            ::missing_field(#names)
+                                    })?,
+                                )*
+                            })
+                        }
+                    }
+
+                    __deserializer.deserialize_map(__Visitor)
+                }
+            }
+        };
+    })
+}
+
+/// Determines how a field is filled in from the path parameters: the name of
+/// its parameter (which, as for `JsonSchema`, may be changed with
+/// `#[serde(rename = "...")]`) and whether it receives all of the segments
+/// matched by a wildcard.
+fn path_param_field(field: &syn::Field) -> Result<PathParamField, syn::Error> {
+    let ident = field.ident.as_ref().expect("fields are named");
+    let mut name = ident.to_string().trim_start_matches("r#").to_string();
+
+    for attr in field.attrs.iter().filter(|a| a.path.is_ident("serde")) {
+        let unsupported = || {
+            syn::Error::new_spanned(
+                attr,
+                "PathParams supports only the `rename` serde attribute",
+            )
+        };
+        let list = match attr.parse_meta()? {
+            syn::Meta::List(list) => list,
+            _ => return Err(unsupported()),
+        };
+        for nested in &list.nested {
+            match nested {
+                syn::NestedMeta::Meta(syn::Meta::NameValue(
+                    syn::MetaNameValue {
+                        path, lit: syn::Lit::Str(rename), ..
+                    },
+                )) if path.is_ident("rename") => name = rename.value(),
+                _ => return Err(unsupported()),
+            }
+        }
+    }
+
+    Ok(PathParamField {
+        ident,
+        name,
+        ty: &field.ty,
+        item_ty: vec_item(&field.ty),
+    })
+}
+
+/// Returns `T` if `ty` is (syntactically) `Vec<T>`.
+fn vec_item(ty: &syn::Type) -> Option<&syn::Type> {
+    let segment = match ty {
+        syn::Type::Path(syn::TypePath { qself: None, path }) => {
+            path.segments.last()?
+        }
+        _ => return None,
+    };
+    if segment.ident != "Vec" {
+        return None;
+    }
+    match &segment.arguments {
+        syn::PathArguments::AngleBracketed(args) if args.args.len() == 1 => {
+            match args.args.first()? {
+                syn::GenericArgument::Type(item) => Some(item),
+                _ => None,
+            }
+        }
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::do_derive_path_params;
+    use quote::quote;
+
+    #[test]
+    fn test_path_params_basic() {
+        let output = do_derive_path_params(quote! {
+            struct MyPath {
+                project: String,
+                #[serde(rename = "type")]
+                kind: u32,
+                rest: Vec<String>,
+            }
+        })
+        .unwrap()
+        .to_string();
+        assert!(output.contains("\"project\""));
+        assert!(output.contains("\"type\""));
+        assert!(!output.contains("\"kind\""));
+        assert!(output.contains("parse_all"));
+        assert!(output.contains("missing_field"));
+    }
+
+    #[test]
+    fn test_path_params_not_struct() {
+        let error = do_derive_path_params(quote! {
+            enum MyPath {
+                A,
+            }
+        })
+        .unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "PathParams can only be derived for structs with named fields"
+        );
+    }
+
+    #[test]
+    fn test_path_params_generic() {
+        let error = do_derive_path_params(quote! {
+            struct MyPath<T> {
+                id: T,
+            }
+        })
+        .unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "PathParams cannot be derived for generic types"
+        );
+    }
+
+    #[test]
+    fn test_path_params_bad_serde_attr() {
+        let error = do_derive_path_params(quote! {
+            struct MyPath {
+                #[serde(default)]
+                id: String,
+            }
+        })
+        .unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "PathParams supports only the `rename` serde attribute"
+        );
+
+        let error = do_derive_path_params(quote! {
+            #[serde(rename_all = "camelCase")]
+            struct MyPath {
+                project_id: String,
+            }
+        })
+        .unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "PathParams does not support container-level serde attributes"
+        );
+    }
+}